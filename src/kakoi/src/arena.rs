@@ -2,28 +2,216 @@
 //!
 //! Everything we can interact with in Kakoi is backed by a [`Value`] that is
 //! stored in a single [`Arena`].
+//!
+//! An arena can be written out as JSON and read back in (see [`crate::json`],
+//! and [`export::to_json`]).
+//! Values are identified there by their [`Value::uuid`] rather than their
+//! [`ArenaKey`], and only those worth saving are written (see
+//! [`Arena::saved_values`]), leaving out the set bound to the
+//! [`CHANGED_REGISTER`], which only describes what changed while Kakoi was
+//! running. Images are kept in one of two ways. A [`Structure::Image`] holds
+//! decoded pixels, and is written encoded as a PNG, so reading in an arena
+//! with thousands of them decodes all of them up front. A
+//! [`Structure::ImageRef`] (made when importing with
+//! [`link_images`](crate::import::ImportPolicy::link_images)) only refers to
+//! an image file on disk, whose pixels are read while it is on screen, and is
+//! written as its path. Leaves are deduplicated by their [`ContentHash`],
+//! which is the same on every machine and in every version of Kakoi, so a leaf
+//! read back in is found again wherever it is already stored.
+//!
+//! JSON is written and read on request, rather than being a file Kakoi keeps
+//! an arena in as it runs, so nothing stops two instances of Kakoi from
+//! writing to the same path. A file Kakoi keeps an arena in should take an
+//! advisory lock on it when opened, asking before overriding a lock held by
+//! someone else, and should be saved by writing a temporary file next to the
+//! original and renaming it over the original, so that a crash partway through
+//! never leaves a half-written arena behind.
+//!
+//! Operations that change many values at once can be made all-or-nothing by
+//! performing them inside of a transaction (see [`Arena::begin`]): rolling it
+//! back puts every value as it was when it began, so a panic partway through
+//! doesn't have to leave containers and [inclusions](Value::inclusions)
+//! disagreeing with each other.
+//!
+//! Two arenas, like one exported as JSON earlier (see [`crate::json`]) and the
+//! arena as it is now, can be compared with [`diff`], which lists the values
+//! that were added and removed and the containers whose members changed.
+//! [`Arena::diff_view`] puts those changes into a value of their own, so that
+//! they can be looked through like anything else.
+//!
+//! An arena belongs to the thread running the event loop, and is only ever
+//! touched from there. It can be sent to another thread, but not shared with
+//! one: reading it can fill in memoized caches (like [`Arena::leaf_count`]),
+//! which live in [`RefCell`]s. Work done in the background (see
+//! [`crate::task`]) is handed whatever it needs up front, and its results are
+//! applied to the arena on the main thread once it finishes. Anything that
+//! needs to read the arena while it is being modified, like saving it in the
+//! background, will need the arena to change first: either into persistent
+//! structures that can be cheaply snapshotted, or into storage behind locks
+//! (with the caches made thread-safe too). Neither exists yet.
 
+use crate::blob::Blob;
+use crate::content_hash::{ContentHash, ContentHasher};
+use crate::import::format_byte_size;
+use crate::leaf::{CustomLeaf, CustomValue, Deserializer};
+use crate::spatial_tree::LayoutStrategy;
+use crate::string_class::{self, StringClass};
 use slotmap::{new_key_type, SlotMap};
 use std::{
+    cell::RefCell,
     collections::{hash_map::DefaultHasher, HashMap, HashSet},
     hash::{Hash, Hasher},
+    ops::{Deref, Index, IndexMut, Range},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
 };
+use uuid::Uuid;
+
+/// Getting everything in an arena out of Kakoi, for scripts and other tools.
+pub mod export {
+    pub use crate::json::to_json;
+}
 
 new_key_type! {
     /// Key for accessing [`Value`]s in an [`Arena`].
     pub struct ArenaKey;
 }
 
+/// The register that quickly-captured strings are collected in. See
+/// [`Arena::capture_string`].
+///
+/// Registers entered by the user are usually named after a single key (which
+/// may follow the [`LOCAL_REGISTER_SIGIL`]), so registers with longer names that
+/// aren't the name of any key are reserved for Kakoi's own use. They can still
+/// be reached by typing their names out (see [`crate::input_manager`]).
+pub const INBOX_REGISTER: &str = "inbox";
+
+/// The register that queries are saved in, so that they can be run again. See
+/// [`Arena::save_query`] and [`crate::query`].
+pub const QUERIES_REGISTER: &str = "queries";
+
+/// The register that starred values are collected in. See
+/// [`Arena::toggle_star`].
+pub const STARRED_REGISTER: &str = "starred";
+
+/// The register that tags are kept in. See [`Arena::tag`].
+pub const TAGS_REGISTER: &str = "tags";
+
+/// Returns the name of `tag`, which may be written with a leading `#`.
+pub fn tag_name(tag: &str) -> &str {
+    tag.trim().trim_start_matches('#')
+}
+
+/// The register that the most recently visited values are collected in, while
+/// usage is tracked. See [`Arena::record_visit`].
+pub const RECENT_REGISTER: &str = "recent";
+
+/// How many values the set bound to the [`RECENT_REGISTER`] holds at most.
+pub const RECENT_LIMIT: usize = 12;
+
+/// The register that recently changed values are collected in. See
+/// [`Arena::record_change`].
+pub const CHANGED_REGISTER: &str = "changed";
+
+/// The registers whose values Kakoi derives from how the arena was used, rather
+/// than from anything entered into it, so that they aren't saved along with it.
+/// See [`Arena::saved_values`].
+pub const DERIVED_REGISTERS: [&str; 2] = [RECENT_REGISTER, CHANGED_REGISTER];
+
+/// How long values stay in the set bound to the [`CHANGED_REGISTER`] after
+/// they change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeWindow {
+    /// Until this many changes have been recorded since.
+    Changes(u64),
+    /// For this long.
+    Time(Duration),
+}
+
+impl Default for ChangeWindow {
+    fn default() -> Self {
+        ChangeWindow::Time(Duration::from_secs(15 * 60))
+    }
+}
+
+impl ChangeWindow {
+    /// Parses a number of changes, like `20`, or a length of time in seconds,
+    /// minutes or hours, like `90s`, `15m` or `2h`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let seconds = match text.chars().last()? {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            _ => return text.parse().ok().map(ChangeWindow::Changes),
+        };
+        let amount: u64 = text[..text.len() - 1].parse().ok()?;
+        Some(ChangeWindow::Time(Duration::from_secs(amount * seconds)))
+    }
+}
+
+/// Where a reference to a value from outside of the arena, like a deep link
+/// (see [`crate::link`]), leads. See [`Arena::resolve_uuid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The value is still where it was.
+    Found(ArenaKey),
+    /// The value was merged into this one (see [`Arena::merge_into`]).
+    Merged(ArenaKey),
+    /// Nothing in the arena has the uuid, and nothing was merged away from
+    /// it.
+    Stale,
+}
+
+/// The number of slots that values can be pinned to. Slots are numbered from 1
+/// to `PIN_SLOTS`, so that each is reachable with a single number key. See
+/// [`Arena::pin`].
+pub const PIN_SLOTS: usize = 9;
+
+/// Returns the name of the register that the value pinned to `slot` is bound
+/// to. Like the [`INBOX_REGISTER`], it isn't the name of any key.
+pub fn pin_register(slot: usize) -> String {
+    format!("pin {}", slot)
+}
+
+/// Registers whose names start with this are local registers. See
+/// [`is_local_register`].
+pub const LOCAL_REGISTER_SIGIL: char = ',';
+
+/// Returns true if `register` is a local register.
+///
+/// Local registers are bound separately for every value: binding `,a` while
+/// looking at one set doesn't change what `,a` is bound to while looking at
+/// another. This lets the registers used while working inside of one structure
+/// be reused inside of another without clobbering each other. Which value's
+/// local registers are used is decided by the value bound to `.` at the time.
+pub fn is_local_register(register: &str) -> bool {
+    register.len() > LOCAL_REGISTER_SIGIL.len_utf8() && register.starts_with(LOCAL_REGISTER_SIGIL)
+}
+
+/// The local register that the earlier versions of a string or set are kept
+/// in, as a list with the oldest first. See [`Arena::record_version`].
+pub const VERSIONS_REGISTER: &str = ",versions";
+
+/// The most earlier versions kept of each value. Older versions are
+/// forgotten.
+pub const MAX_VERSIONS: usize = 50;
+
+/// The most members a set may have had for its earlier version to be kept.
+/// Each version of a set is a copy of it, keeping its members from being
+/// collected as garbage, so larger sets aren't versioned.
+pub const MAX_VERSIONED_MEMBERS: usize = 1000;
+
 /// Describes the way in which a containee [`Value`] is included inside of a
 /// [`Structure::List`].
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ListRoute {
     index: usize,
 }
 
 /// Describes the way in which a containee [`Value`] is included inside of a
 /// [`Structure::Map`].
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MapRoute {
     /// For [`Value`]s contained within the key of a map.
     Key,
@@ -34,7 +222,7 @@ pub enum MapRoute {
 
 /// Describes the way in which a containee [`Value`] is included inside of a
 /// container [`Value`].
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Route {
     /// For [`Value`]s contained within a [`Structure::Set`].
     Set,
@@ -64,10 +252,88 @@ pub enum Structure {
     Map(Box<HashMap<ArenaKey, ArenaKey>>),
     /// An image. Does not contain any other values.
     Image(Box<image::RgbaImage>),
+    /// An image file on disk, whose pixels are only read while it is on
+    /// screen (see [`crate::render::image`]). Does not contain any other
+    /// values.
+    ImageRef(Box<PathBuf>),
     /// A string. Does not contain any other values.
     String(Box<String>),
+    /// A finite number. Does not contain any other values. See
+    /// [`crate::number`].
+    Number(f64),
+    /// The contents of a file that is neither an image nor text. Does not
+    /// contain any other values. See [`crate::blob`].
+    Blob(Box<Blob>),
     Command(Box<Vec<ArenaKey>>),
+    /// A leaf of a type defined outside of Kakoi. Does not contain any other
+    /// values. See [`crate::leaf`].
+    Custom(Box<CustomValue>),
+}
+
+/// Which variant of [`Structure`] a value is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Set,
+    List,
+    Map,
+    Image,
+    ImageRef,
+    String,
+    Number,
+    Blob,
+    Command,
+    Custom,
+}
+
+impl Kind {
+    /// The name of a value of this kind, like `set`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Set => "set",
+            Kind::List => "list",
+            Kind::Map => "map",
+            Kind::Image => "image",
+            Kind::ImageRef => "linked image",
+            Kind::String => "string",
+            Kind::Number => "number",
+            Kind::Blob => "file",
+            Kind::Command => "command",
+            Kind::Custom => "custom value",
+        }
+    }
+
+    /// The name of values of this kind, in the plural, like `sets`.
+    pub fn plural(self) -> &'static str {
+        match self {
+            Kind::Set => "sets",
+            Kind::List => "lists",
+            Kind::Map => "maps",
+            Kind::Image => "images",
+            Kind::ImageRef => "linked images",
+            Kind::String => "strings",
+            Kind::Number => "numbers",
+            Kind::Blob => "files",
+            Kind::Command => "commands",
+            Kind::Custom => "custom values",
+        }
+    }
+}
 
+impl Structure {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Structure::Set(_) => Kind::Set,
+            Structure::List(_) => Kind::List,
+            Structure::Map(_) => Kind::Map,
+            Structure::Image(_) => Kind::Image,
+            Structure::ImageRef(_) => Kind::ImageRef,
+            Structure::String(_) => Kind::String,
+            Structure::Number(_) => Kind::Number,
+            Structure::Blob(_) => Kind::Blob,
+            Structure::Command(_) => Kind::Command,
+            Structure::Custom(_) => Kind::Custom,
+        }
+    }
 }
 
 /// Container that also tracks which [`Value`]s contain it.
@@ -90,51 +356,397 @@ pub struct Value {
     /// the string value's `inclusions` field must be modified to point to this
     /// value through [`Route::Set`].
     pub inclusions: HashSet<(ArenaKey, Route)>,
+    /// Identifier of this value that stays the same across sessions and
+    /// machines.
+    ///
+    /// [`ArenaKey`]s are only meaningful inside of the [`Arena`] that created
+    /// them, so anything that needs to refer to a value from outside of the
+    /// running process (saved files, exports, messages sent to other
+    /// instances of Kakoi) should use this instead. A fresh uuid is assigned
+    /// when the value is first inserted. Code that recreates a value from an
+    /// external source should restore its original uuid with
+    /// [`Arena::restore_uuid`] so that existing references don't dangle.
+    pub uuid: Uuid,
+    /// When this value was created, and who by, if metadata was being
+    /// recorded at the time. See [`Arena::record_metadata`].
+    pub metadata: Option<Box<Metadata>>,
 }
 
 /// Storage container for [`Value`]s.
 pub struct Arena {
     /// Underlying container implementation.
-    pub slot_map: SlotMap<ArenaKey, Value>,
+    pub slot_map: Values,
     /// Key that refers to a `Structure::Map` in the `slot_map` pairing
     /// registers with their values.
     pub register_map: ArenaKey,
-    /// Associates the hash of the unboxed data inside of a [`Structure`]
-    /// variant with the [`ArenaKey`] it is bound to in the `slot_map`. We use
-    /// this to determine if hashable values have already been inserted into the
-    /// [`Arena`].
+    /// Associates the [`ContentHash`] of the unboxed data inside of a
+    /// [`Structure`] variant with the [`ArenaKey`] it is bound to in the
+    /// `slot_map`. We use this to determine if hashable values have already
+    /// been inserted into the [`Arena`].
     ///
     /// This is necessary when we need to find the key of a value in the
     /// `slot_map`. For instance, many functions on [`Arena`] take strings that
     /// represent register names. Without this field, there would be no easy way
     /// to know if we had already inserted the register string into the
     /// `register_map`, and we would be unable to easily look up its value.
-    lookup_map: HashMap<u64, ArenaKey>,
+    lookup_map: HashMap<ContentHash, ArenaKey>,
+    /// Associates the [`Uuid`] of each [`Value`] in the `slot_map` with its
+    /// [`ArenaKey`].
+    uuid_map: HashMap<Uuid, ArenaKey>,
+    /// Associates the [`Uuid`] of each value that was merged into another
+    /// with the uuid of the value it was merged into, so that references to
+    /// it from outside of the arena lead to where it went. See
+    /// [`Arena::resolve_uuid`]. It is written out as JSON too (see
+    /// [`crate::json`]), so that links to merged values lead where they went
+    /// in arenas it is imported into.
+    merged_uuids: HashMap<Uuid, Uuid>,
+    /// Memoized results of [`Arena::leaf_count`]. This is cleared whenever a
+    /// container is modified.
+    ///
+    /// Leaf counts are needed while laying out values on screen, which only
+    /// has shared access to the arena, so this uses a [`RefCell`].
+    leaf_counts: RefCell<HashMap<ArenaKey, usize>>,
+    /// How far above (or below) its usual place in the drawing order each
+    /// value is drawn. Values without an entry have an offset of zero. See
+    /// [`Arena::z_offset`].
+    z_offsets: HashMap<ArenaKey, i32>,
+    /// How each value is arranged regardless of how the rest of the screen is
+    /// arranged. Values without an entry are arranged automatically. See
+    /// [`Arena::layout_override`].
+    layout_overrides: HashMap<ArenaKey, LayoutStrategy>,
+    /// Which member of each set arranged with [`LayoutStrategy::Zoomed`] is
+    /// enlarged. Sets without an entry have a position of zero. See
+    /// [`Arena::carousel_position`].
+    carousel_positions: HashMap<ArenaKey, f32>,
+    /// How far each container's members are turned from where they are
+    /// usually placed. Containers without an entry aren't turned. See
+    /// [`Arena::rotation`].
+    rotations: HashMap<ArenaKey, f32>,
+    /// Values that can't be modified, along with everything inside of them.
+    /// See [`Arena::is_locked`].
+    locked: HashSet<ArenaKey>,
+    /// Memoized results of [`Arena::string_class`]. Strings only change
+    /// through [`Arena::rewrite_strings`], which clears their entries.
+    string_classes: RefCell<HashMap<ArenaKey, StringClass>>,
+    /// Every string changed by [`Arena::rewrite_strings`], in the order they
+    /// were changed. See [`Arena::rewritten_strings`].
+    rewritten_strings: Vec<ArenaKey>,
+    /// Associates values with the `Structure::Map`s pairing their local
+    /// registers with their values. See [`is_local_register`].
+    local_register_maps: HashMap<ArenaKey, ArenaKey>,
+    /// The keys of every value of each [`Kind`], alongside how many values
+    /// there were when they were gathered. Values never change kind, and are
+    /// only removed by [`Arena::collect_garbage`] (which throws this away), so
+    /// this only needs rebuilding when values are added. See
+    /// [`Arena::keys_of_kind`].
+    kind_index: RefCell<(usize, HashMap<Kind, Vec<ArenaKey>>)>,
+    /// How often and how recently each value has been visited, or [`None`] if
+    /// usage isn't being tracked. Usage is only tracked when asked for (see
+    /// [`Arena::set_usage_tracking`]). It is kept apart from the values
+    /// themselves so that anything exporting values leaves it out unless it
+    /// asks for it with [`Arena::usage`].
+    usage: Option<HashMap<ArenaKey, Usage>>,
+    /// How many visits have been recorded since usage started being tracked.
+    /// See [`Usage::last_visited`].
+    visits_recorded: u64,
+    /// How the members of sets are arranged. See [`Arena::arrange`].
+    usage_order: Option<UsageOrder>,
+    /// When each value in the set bound to the [`CHANGED_REGISTER`] last
+    /// changed, both as how many changes had been recorded by then and as
+    /// an instant. See [`Arena::record_change`].
+    changes: HashMap<ArenaKey, (u64, Instant)>,
+    /// How many changes have been recorded.
+    changes_recorded: u64,
+    /// How long values stay in the set bound to the [`CHANGED_REGISTER`].
+    change_window: ChangeWindow,
+    /// Turns serialized custom leaves back into leaves, by the name of their
+    /// type. See [`Arena::register_leaf_type`].
+    leaf_types: HashMap<&'static str, Deserializer>,
+    /// The arena as it was when the open transaction began, if there is one.
+    /// See [`Arena::begin`].
+    savepoint: Option<Savepoint>,
+    /// The metadata new values are stamped with (once `created` is filled
+    /// in), or [`None`] if metadata isn't being recorded. See
+    /// [`Arena::record_metadata`].
+    recording: Option<Metadata>,
+}
+
+/// What [`Arena::rollback`] puts back besides the values themselves (see
+/// [`Journal`]): what indexes the values of an arena, as it was when
+/// [`Arena::begin`] was called. Anything that only describes how values are
+/// displayed or used, like rotations or [`Arena::record_change`], is left out.
+#[derive(Debug)]
+struct Savepoint {
+    lookup_map: HashMap<ContentHash, ArenaKey>,
+    uuid_map: HashMap<Uuid, ArenaKey>,
+    merged_uuids: HashMap<Uuid, Uuid>,
+    local_register_maps: HashMap<ArenaKey, ArenaKey>,
+    locked: HashSet<ArenaKey>,
+}
+
+/// The [`Value`]s of an [`Arena`]. Reading them goes straight through to the
+/// [`SlotMap`] underneath, but while a transaction is open, changing a value
+/// first copies it into a [`Journal`], so that [`Arena::begin`] doesn't have
+/// to copy anything up front.
+#[derive(Default)]
+pub struct Values {
+    slot_map: SlotMap<ArenaKey, Value>,
+    journal: Option<Journal>,
+    /// The values inserted since [`Arena::stamp_metadata`] was last called,
+    /// or [`None`] if metadata isn't being recorded.
+    unstamped: Option<Vec<ArenaKey>>,
+    /// The values inserted, or removed from something, since
+    /// [`Arena::prune_orphans_keeping`] last ran, alongside the orphans it
+    /// kept: the only values that can be orphans. [`None`] if it hasn't run
+    /// yet, or if inclusions were changed wholesale since, so that every
+    /// value has to be looked at.
+    orphan_candidates: Option<HashSet<ArenaKey>>,
+}
+
+/// What the values of an [`Arena`] were before the open transaction changed
+/// them. See [`Values`].
+#[derive(Debug, Default)]
+struct Journal {
+    /// The uuid, inclusions and structure of every value that existed when
+    /// the transaction began and has changed since, as they were before it
+    /// first changed. Images, blobs and custom leaves never change once
+    /// inserted, so their structures aren't copied.
+    originals: HashMap<ArenaKey, (Uuid, HashSet<(ArenaKey, Route)>, Option<Structure>)>,
+    /// Every value inserted since the transaction began.
+    inserted: HashSet<ArenaKey>,
+}
+
+impl Values {
+    /// Copies the value bound to `key` into the journal, unless no
+    /// transaction is open, or it was inserted or already copied since the
+    /// transaction began.
+    fn preserve(&mut self, key: ArenaKey) {
+        let (journal, value) = match (&mut self.journal, self.slot_map.get(key)) {
+            (Some(journal), Some(value)) => (journal, value),
+            _ => return,
+        };
+        if journal.inserted.contains(&key) {
+            return;
+        }
+        journal.originals.entry(key).or_insert_with(|| {
+            let structure = copy_changeable(&value.structure);
+            (value.uuid, value.inclusions.clone(), structure)
+        });
+    }
+
+    /// Returns true if the value bound to `key` was inserted since the open
+    /// transaction began, or false if it wasn't or no transaction is open.
+    fn inserted_since_begin(&self, key: ArenaKey) -> bool {
+        self.journal
+            .as_ref()
+            .map_or(false, |journal| journal.inserted.contains(&key))
+    }
+
+    pub fn insert(&mut self, value: Value) -> ArenaKey {
+        let key = self.slot_map.insert(value);
+        if let Some(journal) = &mut self.journal {
+            journal.inserted.insert(key);
+        }
+        if let Some(unstamped) = &mut self.unstamped {
+            unstamped.push(key);
+        }
+        self.may_be_orphaned(key);
+        key
+    }
+
+    /// Remembers that `key` may have become an orphan. See
+    /// [`Arena::orphans`].
+    fn may_be_orphaned(&mut self, key: ArenaKey) {
+        if let Some(candidates) = &mut self.orphan_candidates {
+            candidates.insert(key);
+        }
+    }
+
+    pub fn remove(&mut self, key: ArenaKey) -> Option<Value> {
+        self.preserve(key);
+        self.slot_map.remove(key)
+    }
+
+    pub fn get_mut(&mut self, key: ArenaKey) -> Option<&mut Value> {
+        self.preserve(key);
+        self.slot_map.get_mut(key)
+    }
+
+    pub fn get_disjoint_mut<const N: usize>(
+        &mut self,
+        keys: [ArenaKey; N],
+    ) -> Option<[&mut Value; N]> {
+        for &key in &keys {
+            self.preserve(key);
+        }
+        self.slot_map.get_disjoint_mut(keys)
+    }
+
+    /// Like [`SlotMap::iter_mut`]. While a transaction is open, this copies
+    /// every value that hasn't been copied yet.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (ArenaKey, &mut Value)> {
+        if self.journal.is_some() {
+            let keys: Vec<ArenaKey> = self.slot_map.keys().collect();
+            for key in keys {
+                self.preserve(key);
+            }
+        }
+        self.slot_map.iter_mut()
+    }
+
+    /// Like [`SlotMap::values_mut`]. While a transaction is open, this copies
+    /// every value that hasn't been copied yet.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut Value> {
+        self.iter_mut().map(|(_, value)| value)
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.slot_map.reserve(additional);
+    }
+}
+
+impl Deref for Values {
+    type Target = SlotMap<ArenaKey, Value>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.slot_map
+    }
+}
+
+impl Index<ArenaKey> for Values {
+    type Output = Value;
+
+    fn index(&self, key: ArenaKey) -> &Value {
+        &self.slot_map[key]
+    }
+}
+
+impl IndexMut<ArenaKey> for Values {
+    fn index_mut(&mut self, key: ArenaKey) -> &mut Value {
+        self.preserve(key);
+        &mut self.slot_map[key]
+    }
+}
+
+/// Copies `structure` for a [`Journal`], unless it is a leaf that never
+/// changes once inserted.
+fn copy_changeable(structure: &Structure) -> Option<Structure> {
+    match structure {
+        Structure::Set(set) => Some(Structure::Set(set.clone())),
+        Structure::List(list) => Some(Structure::List(list.clone())),
+        Structure::Map(map) => Some(Structure::Map(map.clone())),
+        Structure::String(string) => Some(Structure::String(string.clone())),
+        Structure::Number(number) => Some(Structure::Number(*number)),
+        Structure::Command(command) => Some(Structure::Command(command.clone())),
+        Structure::Image(_)
+        | Structure::ImageRef(_)
+        | Structure::Blob(_)
+        | Structure::Custom(_) => None,
+    }
+}
+
+/// Inserts a [`Structure`] into a [`SlotMap`] as a [`Value`] with no
+/// inclusions and a freshly-generated [`Uuid`], which is recorded in the
+/// `uuid_map`.
+fn insert_value(
+    slot_map: &mut Values,
+    uuid_map: &mut HashMap<Uuid, ArenaKey>,
+    structure: Structure,
+) -> ArenaKey {
+    let uuid = Uuid::new_v4();
+    let key = slot_map.insert(Value {
+        structure,
+        inclusions: HashSet::new(),
+        uuid,
+        metadata: None,
+    });
+    uuid_map.insert(uuid, key);
+    key
+}
+
+/// Returns the key of the value whose contents hash to `hash`, if one has
+/// been inserted and `same` agrees that its structure holds the same contents
+/// (see [`crate::content_hash`] for why hashes alone aren't trusted).
+fn lookup_hashed<F: Fn(&Structure) -> bool>(
+    slot_map: &SlotMap<ArenaKey, Value>,
+    lookup_map: &HashMap<ContentHash, ArenaKey>,
+    hash: ContentHash,
+    same: F,
+) -> Option<ArenaKey> {
+    let key = *lookup_map.get(&hash)?;
+    if same(&slot_map[key].structure) {
+        Some(key)
+    } else {
+        None
+    }
 }
 
 /// Inserts a [`String`] into a [`SlotMap`].
 ///
-/// If the string's hash already has an entry in the `lookup_map`, that key is
-/// returned and the `slot_map` is not modified.
+/// If the same string has already been inserted, its key is returned and the
+/// `slot_map` is not modified.
 fn insert_string<S: Into<String>>(
-    slot_map: &mut SlotMap<ArenaKey, Value>,
-    lookup_map: &mut HashMap<u64, ArenaKey>,
+    slot_map: &mut Values,
+    lookup_map: &mut HashMap<ContentHash, ArenaKey>,
+    uuid_map: &mut HashMap<Uuid, ArenaKey>,
     string: S,
 ) -> ArenaKey {
     let string = string.into();
-
-    let mut hasher = DefaultHasher::new();
-    string.hash(&mut hasher);
-    let hash = hasher.finish();
-    match lookup_map.get(&hash).copied() {
+    let hash = ContentHash::of_string(&string);
+    let same = |structure: &Structure| matches!(structure, Structure::String(s) if **s == string);
+    match lookup_hashed(slot_map, lookup_map, hash, same) {
         // if we previously inserted the string, use that key instead
         Some(key) => key,
         // otherwise, insert the string into the slot map and the lookup map
         None => {
-            let key = slot_map.insert(Value {
-                structure: Structure::String(Box::new(string)),
-                inclusions: HashSet::new(),
-            });
+            let key = insert_value(slot_map, uuid_map, Structure::String(Box::new(string)));
+            lookup_map.entry(hash).or_insert(key);
+            key
+        }
+    }
+}
+
+/// Inserts a number into a [`SlotMap`].
+///
+/// If the same number has already been inserted, its key is returned and the
+/// `slot_map` is not modified.
+fn insert_number(
+    slot_map: &mut Values,
+    lookup_map: &mut HashMap<ContentHash, ArenaKey>,
+    uuid_map: &mut HashMap<Uuid, ArenaKey>,
+    number: f64,
+) -> ArenaKey {
+    let hash = ContentHash::of_number(number);
+    let same = |structure: &Structure| matches!(structure, Structure::Number(n) if n.to_bits() == number.to_bits());
+    match lookup_hashed(slot_map, lookup_map, hash, same) {
+        Some(key) => key,
+        None => {
+            let key = insert_value(slot_map, uuid_map, Structure::Number(number));
+            lookup_map.entry(hash).or_insert(key);
+            key
+        }
+    }
+}
+
+/// Inserts a [`Blob`] into a [`SlotMap`].
+///
+/// If a blob with the same bytes has already been inserted, its key is
+/// returned and the `slot_map` is not modified, even if it was read from a
+/// file with a different name. `hash` must be the blob's
+/// [`ContentHash::of_blob`].
+fn insert_blob(
+    slot_map: &mut Values,
+    lookup_map: &mut HashMap<ContentHash, ArenaKey>,
+    uuid_map: &mut HashMap<Uuid, ArenaKey>,
+    blob: Blob,
+    hash: ContentHash,
+) -> ArenaKey {
+    let same =
+        |structure: &Structure| matches!(structure, Structure::Blob(b) if b.bytes == blob.bytes);
+    match lookup_hashed(slot_map, lookup_map, hash, same) {
+        Some(key) => key,
+        None => {
+            let key = insert_value(slot_map, uuid_map, Structure::Blob(Box::new(blob)));
             lookup_map.entry(hash).or_insert(key);
             key
         }
@@ -143,25 +755,83 @@ fn insert_string<S: Into<String>>(
 
 /// Inserts an [`image`](image::RgbaImage) into a [`SlotMap`].
 ///
-/// If the image's hash already has an entry in the `lookup_map`, that key is
-/// returned and the `slot_map` is not modified.
+/// If the same image has already been inserted, its key is returned and the
+/// `slot_map` is not modified.
 fn insert_image(
-    slot_map: &mut SlotMap<ArenaKey, Value>,
-    lookup_map: &mut HashMap<u64, ArenaKey>,
+    slot_map: &mut Values,
+    lookup_map: &mut HashMap<ContentHash, ArenaKey>,
+    uuid_map: &mut HashMap<Uuid, ArenaKey>,
     image: image::RgbaImage,
 ) -> ArenaKey {
-    let mut hasher = DefaultHasher::new();
-    image.hash(&mut hasher);
-    let hash = hasher.finish();
-    match lookup_map.get(&hash).copied() {
+    let hash = ContentHash::of_image(&image);
+    let same = |structure: &Structure| matches!(structure, Structure::Image(i) if **i == image);
+    match lookup_hashed(slot_map, lookup_map, hash, same) {
         // if we previously inserted the image, use that key instead
         Some(key) => key,
         // otherwise, insert the image into the slot map and the lookup map
         None => {
-            let key = slot_map.insert(Value {
-                structure: Structure::Image(Box::new(image)),
-                inclusions: HashSet::new(),
-            });
+            let key = insert_value(slot_map, uuid_map, Structure::Image(Box::new(image)));
+            lookup_map.entry(hash).or_insert(key);
+            key
+        }
+    }
+}
+
+/// Inserts a reference to the image file at `path` into a [`SlotMap`].
+///
+/// If the same path has already been inserted, its key is returned and the
+/// `slot_map` is not modified.
+fn insert_image_ref(
+    slot_map: &mut Values,
+    lookup_map: &mut HashMap<ContentHash, ArenaKey>,
+    uuid_map: &mut HashMap<Uuid, ArenaKey>,
+    path: PathBuf,
+) -> ArenaKey {
+    let hash = ContentHash::of_image_ref(&path);
+    let same = |structure: &Structure| matches!(structure, Structure::ImageRef(p) if **p == path);
+    match lookup_hashed(slot_map, lookup_map, hash, same) {
+        Some(key) => key,
+        None => {
+            let key = insert_value(slot_map, uuid_map, Structure::ImageRef(Box::new(path)));
+            lookup_map.entry(hash).or_insert(key);
+            key
+        }
+    }
+}
+
+/// Hashes the type and contents of a custom leaf (see
+/// [`CustomLeaf::hash_contents`]).
+fn custom_hash(leaf: &dyn CustomLeaf) -> ContentHash {
+    let mut hasher = ContentHasher::tagged("custom");
+    hasher.write(leaf.type_name().as_bytes());
+    hasher.write(&[0]);
+    leaf.hash_contents(&mut hasher);
+    hasher.finish_content()
+}
+
+/// Inserts a [`CustomLeaf`] into a [`SlotMap`], rendering its thumbnail.
+///
+/// If a leaf of the same type that hashes and serializes the same has already
+/// been inserted, its key is returned and the `slot_map` is not modified.
+fn insert_custom(
+    slot_map: &mut Values,
+    lookup_map: &mut HashMap<ContentHash, ArenaKey>,
+    uuid_map: &mut HashMap<Uuid, ArenaKey>,
+    leaf: Box<dyn CustomLeaf>,
+) -> ArenaKey {
+    let hash = custom_hash(&*leaf);
+    let same = |structure: &Structure| match structure {
+        Structure::Custom(custom) => {
+            custom.leaf.type_name() == leaf.type_name()
+                && custom.leaf.serialize() == leaf.serialize()
+        }
+        _ => false,
+    };
+    match lookup_hashed(slot_map, lookup_map, hash, same) {
+        Some(key) => key,
+        None => {
+            let structure = Structure::Custom(Box::new(CustomValue::new(leaf)));
+            let key = insert_value(slot_map, uuid_map, structure);
             lookup_map.entry(hash).or_insert(key);
             key
         }
@@ -174,12 +844,7 @@ fn insert_image(
 /// * `indicated`: the contained value
 /// * `indicator`: the container of `indicated`
 /// * `route`: the way in which `indicated` is contained within `indicator`
-fn add_inclusion(
-    slot_map: &mut SlotMap<ArenaKey, Value>,
-    indicated: ArenaKey,
-    indicator: ArenaKey,
-    route: Route,
-) {
+fn add_inclusion(slot_map: &mut Values, indicated: ArenaKey, indicator: ArenaKey, route: Route) {
     slot_map
         .get_mut(indicated)
         .unwrap()
@@ -193,28 +858,25 @@ fn add_inclusion(
 /// * `indicated`: the contained value
 /// * `indicator`: the container of `indicated`
 /// * `route`: the way in which `indicated` is contained within `indicator`
-fn remove_inclusion(
-    slot_map: &mut SlotMap<ArenaKey, Value>,
-    indicated: ArenaKey,
-    indicator: ArenaKey,
-    route: Route,
-) {
+fn remove_inclusion(slot_map: &mut Values, indicated: ArenaKey, indicator: ArenaKey, route: Route) {
     slot_map
         .get_mut(indicated)
         .unwrap()
         .inclusions
         .remove(&(indicator, route));
+    slot_map.may_be_orphaned(indicated);
 }
 
 /// Inserts a [`set`](HashSet) into a [`SlotMap`].
-fn insert_set(slot_map: &mut SlotMap<ArenaKey, Value>, set: HashSet<ArenaKey>) -> ArenaKey {
+fn insert_set(
+    slot_map: &mut Values,
+    uuid_map: &mut HashMap<Uuid, ArenaKey>,
+    set: HashSet<ArenaKey>,
+) -> ArenaKey {
     let indications = set.iter().copied().collect::<Vec<_>>();
 
     // insert the set into the slot map
-    let key = slot_map.insert(Value {
-        structure: Structure::Set(Box::new(set)),
-        inclusions: HashSet::new(),
-    });
+    let key = insert_value(slot_map, uuid_map, Structure::Set(Box::new(set)));
 
     // add the set's key to the inclusions of each value in the set
     for k in indications {
@@ -225,15 +887,15 @@ fn insert_set(slot_map: &mut SlotMap<ArenaKey, Value>, set: HashSet<ArenaKey>) -
 }
 
 /// Inserts a [`list`](Vec) into a [`SlotMap`].
-#[allow(unused)]
-fn insert_list(slot_map: &mut SlotMap<ArenaKey, Value>, list: Vec<ArenaKey>) -> ArenaKey {
+fn insert_list(
+    slot_map: &mut Values,
+    uuid_map: &mut HashMap<Uuid, ArenaKey>,
+    list: Vec<ArenaKey>,
+) -> ArenaKey {
     let indications = list.clone();
 
     // insert the list into the slot map
-    let key = slot_map.insert(Value {
-        structure: Structure::List(Box::new(list)),
-        inclusions: HashSet::new(),
-    });
+    let key = insert_value(slot_map, uuid_map, Structure::List(Box::new(list)));
 
     // add the list's key to the inclusions of each value in the list
     for (n, k) in indications.into_iter().enumerate() {
@@ -245,16 +907,14 @@ fn insert_list(slot_map: &mut SlotMap<ArenaKey, Value>, list: Vec<ArenaKey>) ->
 
 /// Inserts a [`map`](HashMap) into a [`SlotMap`].
 fn insert_map(
-    slot_map: &mut SlotMap<ArenaKey, Value>,
+    slot_map: &mut Values,
+    uuid_map: &mut HashMap<Uuid, ArenaKey>,
     map: HashMap<ArenaKey, ArenaKey>,
 ) -> ArenaKey {
     let indications = map.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>();
 
     // insert the map into the slot map
-    let key = slot_map.insert(Value {
-        structure: Structure::Map(Box::new(map)),
-        inclusions: HashSet::new(),
-    });
+    let key = insert_value(slot_map, uuid_map, Structure::Map(Box::new(map)));
 
     // add the map's key to the inclusions of each key and value in the map
     for (k, v) in indications {
@@ -278,7 +938,7 @@ fn insert_map(
 //     }
 // }
 
-fn list_push(slot_map: &mut SlotMap<ArenaKey, Value>, list: ArenaKey, value: ArenaKey) {
+fn list_push(slot_map: &mut Values, list: ArenaKey, value: ArenaKey) {
     let index = match &mut slot_map.get_mut(list).unwrap().structure {
         Structure::List(vec) => {
             let index = vec.len();
@@ -291,7 +951,7 @@ fn list_push(slot_map: &mut SlotMap<ArenaKey, Value>, list: ArenaKey, value: Are
     add_inclusion(slot_map, value, list, Route::List(ListRoute { index }));
 }
 
-fn list_pop(slot_map: &mut SlotMap<ArenaKey, Value>, list: ArenaKey) {
+fn list_pop(slot_map: &mut Values, list: ArenaKey) {
     let (index, value) = match &mut slot_map.get_mut(list).unwrap().structure {
         Structure::List(vec) => {
             let value = vec.pop();
@@ -307,7 +967,38 @@ fn list_pop(slot_map: &mut SlotMap<ArenaKey, Value>, list: ArenaKey) {
     });
 }
 
-fn set_insert(slot_map: &mut SlotMap<ArenaKey, Value>, set: ArenaKey, value: ArenaKey) {
+/// Applies `edit` to the elements of the list `list`, keeping the
+/// [`ListRoute`]s of its elements up to date. Only the elements at indices in
+/// `affected` (both before and after the edit) may change position, so only
+/// their inclusions are updated. Indices in `affected` past the end of the list
+/// are ignored.
+fn list_edit<F: FnOnce(&mut Vec<ArenaKey>)>(
+    slot_map: &mut Values,
+    list: ArenaKey,
+    affected: Range<usize>,
+    edit: F,
+) {
+    let elements = |slot_map: &SlotMap<ArenaKey, Value>| match &slot_map[list].structure {
+        Structure::List(vec) => affected
+            .clone()
+            .filter_map(|index| Some((index, *vec.get(index)?)))
+            .collect::<Vec<_>>(),
+        _ => panic!(),
+    };
+
+    for (index, value) in elements(slot_map) {
+        remove_inclusion(slot_map, value, list, Route::List(ListRoute { index }));
+    }
+    match &mut slot_map.get_mut(list).unwrap().structure {
+        Structure::List(vec) => edit(vec),
+        _ => panic!(),
+    }
+    for (index, value) in elements(slot_map) {
+        add_inclusion(slot_map, value, list, Route::List(ListRoute { index }));
+    }
+}
+
+fn set_insert(slot_map: &mut Values, set: ArenaKey, value: ArenaKey) {
     add_inclusion(slot_map, value, set, Route::Set);
     match &mut slot_map.get_mut(set).unwrap().structure {
         Structure::Set(hash_set) => {
@@ -317,7 +1008,7 @@ fn set_insert(slot_map: &mut SlotMap<ArenaKey, Value>, set: ArenaKey, value: Are
     }
 }
 
-fn set_remove(slot_map: &mut SlotMap<ArenaKey, Value>, set: ArenaKey, value: ArenaKey) {
+fn set_remove(slot_map: &mut Values, set: ArenaKey, value: ArenaKey) {
     remove_inclusion(slot_map, value, set, Route::Set);
     match &mut slot_map.get_mut(set).unwrap().structure {
         Structure::Set(hash_set) => {
@@ -327,7 +1018,7 @@ fn set_remove(slot_map: &mut SlotMap<ArenaKey, Value>, set: ArenaKey, value: Are
     }
 }
 
-fn set_union(slot_map: &mut SlotMap<ArenaKey, Value>, set_to_modify: ArenaKey, other: ArenaKey) {
+fn set_union(slot_map: &mut Values, set_to_modify: ArenaKey, other: ArenaKey) {
     // add `set_to_modify` to the inclusions of the indications of `other`
     let other_indications = match &slot_map.get(other).unwrap().structure {
         Structure::Set(hash_set) => hash_set.iter().copied().collect::<Vec<_>>(),
@@ -355,11 +1046,7 @@ fn set_union(slot_map: &mut SlotMap<ArenaKey, Value>, set_to_modify: ArenaKey, o
     }
 }
 
-fn set_difference(
-    slot_map: &mut SlotMap<ArenaKey, Value>,
-    set_to_modify: ArenaKey,
-    other: ArenaKey,
-) {
+fn set_difference(slot_map: &mut Values, set_to_modify: ArenaKey, other: ArenaKey) {
     // remove `set_to_modify` from the inclusions of the indications of `other`.
     let other_indications = match &slot_map.get(other).unwrap().structure {
         Structure::Set(hash_set) => hash_set.iter().copied().collect::<Vec<_>>(),
@@ -388,11 +1075,7 @@ fn set_difference(
 }
 
 // If there was an old value associated with `key`, remove `map` from its inclusions.
-fn map_remove_value_inclusion(
-    slot_map: &mut SlotMap<ArenaKey, Value>,
-    map: ArenaKey,
-    key: ArenaKey,
-) {
+fn map_remove_value_inclusion(slot_map: &mut Values, map: ArenaKey, key: ArenaKey) {
     match &slot_map.get(map).unwrap().structure {
         Structure::Map(hash_map) => {
             hash_map.get(&key).copied().map(|old_value| {
@@ -403,8 +1086,7 @@ fn map_remove_value_inclusion(
     }
 }
 
-#[allow(unused)]
-fn map_remove(slot_map: &mut SlotMap<ArenaKey, Value>, map: ArenaKey, key: ArenaKey) {
+fn map_remove(slot_map: &mut Values, map: ArenaKey, key: ArenaKey) {
     map_remove_value_inclusion(slot_map, map, key);
     remove_inclusion(slot_map, key, map, Route::Map(MapRoute::Key));
     match &mut slot_map.get_mut(map).unwrap().structure {
@@ -415,12 +1097,7 @@ fn map_remove(slot_map: &mut SlotMap<ArenaKey, Value>, map: ArenaKey, key: Arena
     }
 }
 
-fn map_insert(
-    slot_map: &mut SlotMap<ArenaKey, Value>,
-    map: ArenaKey,
-    key: ArenaKey,
-    value: ArenaKey,
-) {
+fn map_insert(slot_map: &mut Values, map: ArenaKey, key: ArenaKey, value: ArenaKey) {
     // If there was an old value associated with `key`, remove `map` from its inclusions.
     map_remove_value_inclusion(slot_map, map, key);
 
@@ -444,140 +1121,2945 @@ fn map_get(slot_map: &SlotMap<ArenaKey, Value>, map: ArenaKey, key: ArenaKey) ->
     }
 }
 
-impl Arena {
-    pub fn new() -> Self {
-        let mut slot_map = SlotMap::with_key();
-        let mut lookup_map = HashMap::new();
-        let selected_register = insert_string(&mut slot_map, &mut lookup_map, ".");
-        let empty_set = insert_set(&mut slot_map, HashSet::new());
-        let register_map = insert_map(
-            &mut slot_map,
-            vec![(selected_register, empty_set)].into_iter().collect(),
-        );
-        // let s1 = insert_string(&mut slot_map, &mut lookup_map, "set-insert");
-        // let s2 = insert_string(&mut slot_map, &mut lookup_map, "my-favorite-set");
-        // let s3 = insert_string(&mut slot_map, &mut lookup_map, "'Hello, world!'");
-        // let command = slot_map.insert(Value {
-        //     structure: Structure::Command(Box::new(vec![empty_set, empty_set, empty_set, empty_set])),
-        //     inclusions: HashSet::new(),
-        // });
-        // set_insert(&mut slot_map, empty_set, command);
-        Self {
-            slot_map,
-            register_map,
-            lookup_map,
+/// A disagreement between the structure of a container and the inclusions of
+/// the values it contains, as found by [`Arena::validate`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// `container` contains `member` through `route`, but this is missing
+    /// from the inclusions of `member`.
+    MissingInclusion {
+        member: ArenaKey,
+        container: ArenaKey,
+        route: Route,
+    },
+    /// The inclusions of `member` say that it is contained in `container`
+    /// through `route`, but it isn't.
+    StaleInclusion {
+        member: ArenaKey,
+        container: ArenaKey,
+        route: Route,
+    },
+    /// `container` contains `member` through `route`, but `member` is not in
+    /// the arena.
+    DanglingMember {
+        member: ArenaKey,
+        container: ArenaKey,
+        route: Route,
+    },
+}
+
+/// What [`Arena::merge`] does when a register is bound in both arenas, but to
+/// different values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Bind their value to a new register named after the original one, like
+    /// `a~1`, leaving our binding alone.
+    Rename,
+    /// Keep our binding.
+    Ours,
+    /// Replace our binding with theirs.
+    Theirs,
+}
+
+/// A register that was bound to different values in the two arenas given to
+/// [`Arena::merge`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct RegisterConflict {
+    pub register: String,
+    /// The value the register was bound to in the arena being merged into.
+    pub ours: ArenaKey,
+    /// The value the register was bound to in the arena being merged from,
+    /// after it was imported.
+    pub theirs: ArenaKey,
+    /// The register `theirs` was bound to instead, if the conflict was
+    /// resolved with [`ConflictPolicy::Rename`].
+    pub renamed: Option<String>,
+}
+
+/// What happened during a call to [`Arena::merge`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// The number of values that were copied into the arena.
+    pub imported: usize,
+    /// The number of values that were already in the arena, either because
+    /// they had the same [`Uuid`] or because they were strings or images with
+    /// the same contents.
+    pub reused: usize,
+    /// Every register that was bound differently in the two arenas.
+    pub conflicts: Vec<RegisterConflict>,
+}
+
+/// How often and how recently a value has been visited. See
+/// [`Arena::record_visit`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// How many times the value has been visited.
+    pub visits: u32,
+    /// When the value was last visited, counted in visits to any value since
+    /// usage started being tracked. Larger is more recent, and zero means
+    /// never.
+    pub last_visited: u64,
+}
+
+/// When a value was created, and who by. See [`Arena::record_metadata`].
+///
+/// Tags aren't part of a value's metadata. They are kept in the arena itself,
+/// so that they can be browsed like any other value (see [`Arena::tag`]).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// When the value was created, or [`None`] if it already existed when
+    /// metadata started being recorded.
+    pub created: Option<SystemTime>,
+    /// Who created the value, if anyone was named.
+    pub author: Option<String>,
+}
+
+/// An order in which the members of sets are arranged, based on their
+/// [`Usage`]. See [`Arena::arrange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageOrder {
+    /// The most recently visited members come first.
+    Recency,
+    /// The most often visited members come first.
+    Frequency,
+}
+
+impl UsageOrder {
+    /// Returns the order to switch to after `order`, cycling through both
+    /// orders and [`None`], which stands for not ordering members at all.
+    pub fn next(order: Option<Self>) -> Option<Self> {
+        match order {
+            None => Some(Self::Recency),
+            Some(Self::Recency) => Some(Self::Frequency),
+            Some(Self::Frequency) => None,
         }
     }
 
-    pub fn string(&mut self, string: &str) -> ArenaKey {
-        insert_string(&mut self.slot_map, &mut self.lookup_map, string)
+    pub fn describe(order: Option<Self>) -> &'static str {
+        match order {
+            None => "in no particular order",
+            Some(Self::Recency) => "most recently visited first",
+            Some(Self::Frequency) => "most often visited first",
+        }
     }
+}
 
-    pub fn image(&mut self, image: image::RgbaImage) -> ArenaKey {
-        insert_image(&mut self.slot_map, &mut self.lookup_map, image)
-    }
+/// An estimate of how much memory an arena takes up, as returned by
+/// [`Arena::memory_usage`]. Only memory that grows with the number of values is
+/// counted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The estimated number of bytes taken up by the values of each kind,
+    /// including their inclusions.
+    pub by_kind: HashMap<Kind, usize>,
+    /// The estimated number of bytes taken up by memoized results and by the
+    /// indexes used to find values.
+    pub caches: usize,
+}
 
-    pub fn register<S: Into<String>>(&mut self, register: S) -> Option<ArenaKey> {
-        let register = insert_string(&mut self.slot_map, &mut self.lookup_map, register.into());
-        map_get(&self.slot_map, self.register_map, register)
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.by_kind.values().sum::<usize>() + self.caches
     }
+}
 
-    pub fn bind_register<S: Into<String>>(&mut self, register: S, value: ArenaKey) {
-        let register = insert_string(&mut self.slot_map, &mut self.lookup_map, register.into());
-        map_insert(&mut self.slot_map, self.register_map, register, value);
-    }
+/// Which values [`Arena::prune_orphans_keeping`] treats as orphans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pruning {
+    /// Whether a value that is only kept by being pinned to a slot (see
+    /// [`Arena::pin`]) is kept. If not, it is unpinned and removed.
+    pub keep_pinned: bool,
+}
 
-    pub fn bind_register_to_empty_set<S: Into<String>>(&mut self, register: S) {
-        let register = insert_string(&mut self.slot_map, &mut self.lookup_map, register.into());
-        let set = insert_set(&mut self.slot_map, HashSet::new());
-        map_insert(&mut self.slot_map, self.register_map, register, set);
-    }
+/// What happened during a call to [`Arena::compact`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionReport {
+    pub before: MemoryUsage,
+    pub after: MemoryUsage,
+}
 
-    pub fn bind_register_to_string<S: Into<String>>(&mut self, register: S, string: S) {
-        let register = insert_string(&mut self.slot_map, &mut self.lookup_map, register.into());
-        let string = insert_string(&mut self.slot_map, &mut self.lookup_map, string.into());
-        map_insert(&mut self.slot_map, self.register_map, register, string);
+impl CompactionReport {
+    /// Describes how much memory was saved in total and for each kind of
+    /// value, like `12.0 MB → 9.5 MB (images 9.0 MB → 9.0 MB, sets ...)`.
+    pub fn describe(&self) -> String {
+        let mut kinds: Vec<_> = self.before.by_kind.keys().copied().collect();
+        kinds.sort_by_key(|kind| std::cmp::Reverse(self.before.by_kind[kind]));
+        let by_kind: Vec<String> = kinds
+            .into_iter()
+            .map(|kind| {
+                format!(
+                    "{} {} → {}",
+                    kind.plural(),
+                    format_byte_size(self.before.by_kind[&kind] as u64),
+                    format_byte_size(self.after.by_kind.get(&kind).copied().unwrap_or(0) as u64)
+                )
+            })
+            .collect();
+        format!(
+            "{} → {} ({})",
+            format_byte_size(self.before.total() as u64),
+            format_byte_size(self.after.total() as u64),
+            by_kind.join(", ")
+        )
     }
+}
 
-    pub fn bind_register_to_register_value<S: Into<String>>(
-        &mut self,
-        to_be_binded: S,
-        to_lookup: S,
-    ) {
-        let to_be_binded = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            to_be_binded.into(),
-        );
-        let to_lookup = insert_string(&mut self.slot_map, &mut self.lookup_map, to_lookup.into());
-        map_get(&self.slot_map, self.register_map, to_lookup).map(|k| {
-            map_insert(&mut self.slot_map, self.register_map, to_be_binded, k);
+/// How a value differs between two arenas, as found by [`diff`].
+///
+/// Values that are in both arenas are named by their [`Uuid`] in the newer
+/// one, and values that are only in the older one by their uuid there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Change {
+    /// The value is only in the newer arena.
+    Added(Uuid),
+    /// The value is only in the older arena.
+    Removed(Uuid),
+    /// The leaf is in both arenas, but its contents changed, like a string
+    /// that was rewritten (see [`Arena::rewrite_strings`]).
+    Rewritten(Uuid),
+    /// `member` was put inside of `container`, which is in both arenas.
+    Included { container: Uuid, member: Uuid },
+    /// `member` was taken out of `container`, which is in both arenas.
+    Excluded { container: Uuid, member: Uuid },
+    /// The list or command has the same elements in both arenas, but in a
+    /// different order.
+    Reordered(Uuid),
+}
+
+/// Lists how the values of `new` differ from those of `old`, sorted so that
+/// diffing the same arenas twice gives the same changes.
+///
+/// Values are matched up by [`Uuid`] first, like in [`Arena::merge`]. A leaf
+/// whose uuid isn't in `new` is matched with a leaf holding the same
+/// contents instead, since leaves are only ever stored once. Only the values
+/// worth saving are compared (see [`Arena::saved_values`]), so that neither
+/// garbage that hasn't been collected yet nor the [`DERIVED_REGISTERS`] show
+/// up as changes, and an arena read back from a file doesn't differ from the
+/// one that was saved. Values that were added or
+/// removed aren't also listed as having been included in, or excluded from,
+/// containers that were added or removed along with them.
+pub fn diff(old: &Arena, new: &Arena) -> Vec<Change> {
+    let (old_values, new_values) = (compared_values(old), compared_values(new));
+    // Associates the keys of `old` with the keys of the same values in `new`.
+    let mut counterparts: HashMap<ArenaKey, ArenaKey> = HashMap::new();
+    for &key in &old_values {
+        let value = &old.slot_map[key];
+        let same = |structure: &Structure| same_leaf(structure, &value.structure);
+        let counterpart = new.lookup_uuid(&value.uuid).or_else(|| {
+            lookup_hashed(&new.slot_map, &new.lookup_map, old.content_hash(key)?, same)
         });
+        if let Some(counterpart) = counterpart.filter(|key| new_values.contains(key)) {
+            counterparts.insert(key, counterpart);
+        }
     }
+    let matched: HashSet<ArenaKey> = counterparts.values().copied().collect();
+    let name = |key: ArenaKey| match counterparts.get(&key) {
+        Some(&counterpart) => new.slot_map[counterpart].uuid,
+        None => old.slot_map[key].uuid,
+    };
 
-    pub fn list_push<S: Into<String>>(
-        &mut self,
-        list_register: S,
-        value_register: S,
+    let mut changes: Vec<Change> = new_values
+        .iter()
+        .filter(|key| !matched.contains(key))
+        .map(|&key| Change::Added(new.slot_map[key].uuid))
+        .collect();
+    for &key in &old_values {
+        let counterpart = match counterparts.get(&key) {
+            Some(&counterpart) => counterpart,
+            None => {
+                changes.push(Change::Removed(old.slot_map[key].uuid));
+                continue;
+            }
+        };
+        let container = new.slot_map[counterpart].uuid;
+        let (before, after) = (
+            &old.slot_map[key].structure,
+            &new.slot_map[counterpart].structure,
+        );
+        if before.kind() != after.kind() || old.content_hash(key).is_some() {
+            if !same_leaf(before, after) {
+                changes.push(Change::Rewritten(container));
+            }
+            continue;
+        }
+        let ordered = matches!(before.kind(), Kind::List | Kind::Command);
+        let before: Vec<Uuid> = members(before).into_iter().map(name).collect();
+        let after: Vec<Uuid> = members(after)
+            .into_iter()
+            .map(|member| new.slot_map[member].uuid)
+            .collect();
+        let (before_set, after_set): (HashSet<Uuid>, HashSet<Uuid>) = (
+            before.iter().copied().collect(),
+            after.iter().copied().collect(),
+        );
+        changes.extend(
+            after_set
+                .difference(&before_set)
+                .map(|&member| Change::Included { container, member }),
+        );
+        changes.extend(
+            before_set
+                .difference(&after_set)
+                .map(|&member| Change::Excluded { container, member }),
+        );
+        if ordered && before_set == after_set && before != after {
+            changes.push(Change::Reordered(container));
+        }
+    }
+    changes.sort_unstable();
+    changes
+}
+
+/// Returns the values of `arena` that [`diff`] compares: those worth saving,
+/// apart from the maps holding registers, which are saved as what they pair
+/// rather than as values of their own.
+fn compared_values(arena: &Arena) -> HashSet<ArenaKey> {
+    let mut values = arena.saved_values();
+    for map in arena.register_maps() {
+        values.remove(&map);
+    }
+    values
+}
+
+/// Returns true if `a` and `b` are leaves holding the same contents.
+fn same_leaf(a: &Structure, b: &Structure) -> bool {
+    match (a, b) {
+        (Structure::String(a), Structure::String(b)) => a == b,
+        (Structure::Number(a), Structure::Number(b)) => a.to_bits() == b.to_bits(),
+        (Structure::Image(a), Structure::Image(b)) => a == b,
+        (Structure::ImageRef(a), Structure::ImageRef(b)) => a == b,
+        (Structure::Blob(a), Structure::Blob(b)) => a.bytes == b.bytes,
+        (Structure::Custom(a), Structure::Custom(b)) => {
+            a.leaf.type_name() == b.leaf.type_name() && a.leaf.serialize() == b.leaf.serialize()
+        }
+        _ => false,
+    }
+}
+
+/// Returns the values directly inside of `structure`, with the keys and
+/// values of maps taking turns.
+fn members(structure: &Structure) -> Vec<ArenaKey> {
+    match structure {
+        Structure::Set(set) => set.iter().copied().collect(),
+        Structure::List(list) | Structure::Command(list) => list.to_vec(),
+        Structure::Map(map) => map.iter().flat_map(|(&k, &v)| vec![k, v]).collect(),
+        Structure::String(_)
+        | Structure::Image(_)
+        | Structure::ImageRef(_)
+        | Structure::Number(_)
+        | Structure::Blob(_)
+        | Structure::Custom(_) => vec![],
+    }
+}
+
+/// Describes the value bound to `key` in `arena` in a few words, like
+/// `"milk"` or `set of 3 values`, for when it can't be displayed itself.
+fn describe_value(arena: &Arena, key: ArenaKey) -> String {
+    let structure = &arena.slot_map[key].structure;
+    match structure {
+        Structure::String(string) => format!("\"{}\"", string),
+        Structure::Number(number) => crate::number::format(*number),
+        Structure::Image(image) => format!("image {} × {}", image.width(), image.height()),
+        Structure::ImageRef(path) => path.display().to_string(),
+        Structure::Blob(blob) => blob.describe(),
+        Structure::Custom(custom) => custom.leaf.describe(),
+        Structure::Set(_) | Structure::List(_) | Structure::Map(_) | Structure::Command(_) => {
+            let count = members(structure).len();
+            let values = if count == 1 { "value" } else { "values" };
+            format!("{} of {} {}", structure.kind().name(), count, values)
+        }
+    }
+}
+
+/// Estimates the number of bytes taken up by `value`, including what its
+/// structure holds on the heap. Collections are counted by their capacity
+/// rather than their length, since that is what they have allocated.
+fn value_size(value: &Value) -> usize {
+    use std::mem::size_of;
+    let structure = match &value.structure {
+        Structure::Set(set) => set.capacity() * size_of::<ArenaKey>(),
+        Structure::List(list) | Structure::Command(list) => list.capacity() * size_of::<ArenaKey>(),
+        Structure::Map(map) => map.capacity() * 2 * size_of::<ArenaKey>(),
+        Structure::Image(image) => image.as_raw().capacity(),
+        Structure::ImageRef(path) => path.capacity(),
+        Structure::String(string) => string.capacity(),
+        Structure::Number(_) => 0,
+        Structure::Blob(blob) => {
+            blob.bytes.capacity() + blob.mime.capacity() + blob.name.capacity()
+        }
+        Structure::Custom(custom) => custom
+            .thumbnail
+            .as_ref()
+            .map_or(0, |thumbnail| thumbnail.as_raw().capacity()),
+    };
+    size_of::<Value>() + value.inclusions.capacity() * size_of::<(ArenaKey, Route)>() + structure
+}
+
+/// Releases memory that `structure` has allocated but isn't using.
+fn shrink_structure(structure: &mut Structure) {
+    match structure {
+        Structure::Set(set) => set.shrink_to_fit(),
+        Structure::List(list) | Structure::Command(list) => list.shrink_to_fit(),
+        Structure::Map(map) => map.shrink_to_fit(),
+        Structure::String(string) => string.shrink_to_fit(),
+        Structure::Blob(blob) => blob.bytes.shrink_to_fit(),
+        // Images are allocated at exactly the size of their pixels, and custom
+        // leaves manage their own memory.
+        Structure::ImageRef(path) => path.shrink_to_fit(),
+        Structure::Image(_) | Structure::Number(_) | Structure::Custom(_) => {}
+    }
+}
+
+/// Counts the leaves reachable from `key`: values that don't contain anything,
+/// like strings, images, and empty containers. Each leaf is only counted once,
+/// even if it can be reached in more than one way.
+fn reachable_leaves(slot_map: &SlotMap<ArenaKey, Value>, key: ArenaKey) -> usize {
+    let mut visited = HashSet::new();
+    let mut todo = vec![key];
+    let mut leaves = 0;
+    while let Some(key) = todo.pop() {
+        if !visited.insert(key) {
+            continue;
+        }
+        let before = todo.len();
+        match slot_map.get(key).map(|value| &value.structure) {
+            Some(Structure::Set(set)) => todo.extend(set.iter()),
+            Some(Structure::List(list)) => todo.extend(list.iter()),
+            Some(Structure::Command(command)) => todo.extend(command.iter()),
+            Some(Structure::Map(map)) => todo.extend(map.iter().flat_map(|(&k, &v)| vec![k, v])),
+            Some(Structure::String(_))
+            | Some(Structure::Image(_))
+            | Some(Structure::ImageRef(_))
+            | Some(Structure::Number(_))
+            | Some(Structure::Blob(_))
+            | Some(Structure::Custom(_))
+            | None => {}
+        }
+        if todo.len() == before {
+            leaves += 1;
+        }
+    }
+    leaves
+}
+
+/// The inclusions of a single [`Value`]. See [`Value::inclusions`].
+type Inclusions = HashSet<(ArenaKey, Route)>;
+
+/// Computes the inclusions that each value in `slot_map` ought to have by
+/// looking only at the structures of the containers in `slot_map`. Values that
+/// aren't contained anywhere are left out.
+///
+/// Members of containers that are not themselves in `slot_map` can't have any
+/// inclusions, so they are returned separately as
+/// [`Inconsistency::DanglingMember`]s.
+///
+/// [`Structure::Command`]s don't record their arguments as inclusions, so
+/// they are ignored.
+fn expected_inclusions(
+    slot_map: &SlotMap<ArenaKey, Value>,
+) -> (HashMap<ArenaKey, Inclusions>, Vec<Inconsistency>) {
+    let mut expected: HashMap<ArenaKey, Inclusions> = HashMap::new();
+    let mut dangling = Vec::new();
+    for (container, value) in slot_map.iter() {
+        let members: Vec<(ArenaKey, Route)> = match &value.structure {
+            Structure::Set(set) => set.iter().map(|&k| (k, Route::Set)).collect(),
+            Structure::List(list) => list
+                .iter()
+                .enumerate()
+                .map(|(index, &k)| (k, Route::List(ListRoute { index })))
+                .collect(),
+            Structure::Map(map) => map
+                .iter()
+                .flat_map(|(&k, &v)| {
+                    vec![
+                        (k, Route::Map(MapRoute::Key)),
+                        (v, Route::Map(MapRoute::ValueOf(k))),
+                    ]
+                })
+                .collect(),
+            Structure::Image(_)
+            | Structure::ImageRef(_)
+            | Structure::String(_)
+            | Structure::Number(_)
+            | Structure::Blob(_)
+            | Structure::Command(_)
+            | Structure::Custom(_) => vec![],
+        };
+        for (member, route) in members {
+            if slot_map.contains_key(member) {
+                expected
+                    .entry(member)
+                    .or_default()
+                    .insert((container, route));
+            } else {
+                dangling.push(Inconsistency::DanglingMember {
+                    member,
+                    container,
+                    route,
+                });
+            }
+        }
+    }
+    (expected, dangling)
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        let mut slot_map = Values::default();
+        let mut lookup_map = HashMap::new();
+        let mut uuid_map = HashMap::new();
+        let selected_register = insert_string(&mut slot_map, &mut lookup_map, &mut uuid_map, ".");
+        let empty_set = insert_set(&mut slot_map, &mut uuid_map, HashSet::new());
+        let register_map = insert_map(
+            &mut slot_map,
+            &mut uuid_map,
+            vec![(selected_register, empty_set)].into_iter().collect(),
+        );
+        // let s1 = insert_string(&mut slot_map, &mut lookup_map, "set-insert");
+        // let s2 = insert_string(&mut slot_map, &mut lookup_map, "my-favorite-set");
+        // let s3 = insert_string(&mut slot_map, &mut lookup_map, "'Hello, world!'");
+        // let command = slot_map.insert(Value {
+        //     structure: Structure::Command(Box::new(vec![empty_set, empty_set, empty_set, empty_set])),
+        //     inclusions: HashSet::new(),
+        // });
+        // set_insert(&mut slot_map, empty_set, command);
+        Self {
+            slot_map,
+            register_map,
+            lookup_map,
+            uuid_map,
+            merged_uuids: HashMap::new(),
+            leaf_counts: RefCell::new(HashMap::new()),
+            z_offsets: HashMap::new(),
+            layout_overrides: HashMap::new(),
+            carousel_positions: HashMap::new(),
+            rotations: HashMap::new(),
+            locked: HashSet::new(),
+            string_classes: RefCell::new(HashMap::new()),
+            rewritten_strings: Vec::new(),
+            local_register_maps: HashMap::new(),
+            kind_index: RefCell::new((0, HashMap::new())),
+            usage: None,
+            visits_recorded: 0,
+            usage_order: None,
+            changes: HashMap::new(),
+            changes_recorded: 0,
+            change_window: ChangeWindow::default(),
+            leaf_types: HashMap::new(),
+            savepoint: None,
+            recording: None,
+        }
+    }
+
+    /// Returns the [`Uuid`] of the value bound to `key`, if there is one.
+    pub fn uuid(&self, key: ArenaKey) -> Option<Uuid> {
+        self.slot_map.get(key).map(|value| value.uuid)
+    }
+
+    /// Returns the key of the value whose [`Uuid`] is `uuid`, if it exists in
+    /// this arena.
+    pub fn lookup_uuid(&self, uuid: &Uuid) -> Option<ArenaKey> {
+        self.uuid_map.get(uuid).copied()
+    }
+
+    /// Returns where a reference to the value whose [`Uuid`] is `uuid` from
+    /// outside of the arena leads. Unlike [`Arena::lookup_uuid`], references
+    /// to a value that was merged into another lead to the other value, since
+    /// the merged value is left contained in nothing.
+    pub fn resolve_uuid(&self, uuid: &Uuid) -> Resolution {
+        match self.merged_uuids.get(uuid) {
+            Some(into) => match self.lookup_uuid(into) {
+                Some(key) => Resolution::Merged(key),
+                None => Resolution::Stale,
+            },
+            None => match self.lookup_uuid(uuid) {
+                Some(key) => Resolution::Found(key),
+                None => Resolution::Stale,
+            },
+        }
+    }
+
+    /// Returns the [`Uuid`] of each value that was merged into another (see
+    /// [`Arena::merge_into`]), paired with the uuid of the value it was merged
+    /// into.
+    pub fn merged_uuids(&self) -> impl Iterator<Item = (Uuid, Uuid)> + '_ {
+        self.merged_uuids.iter().map(|(&from, &into)| (from, into))
+    }
+
+    /// Records that the value whose [`Uuid`] is `from` was merged into the one
+    /// whose uuid is `into`, like [`Arena::merge_into`] does, so that
+    /// [`Arena::resolve_uuid`] leads from one to the other. Does nothing if
+    /// `from` was already merged into something else here, or if `into`
+    /// leads back to `from`.
+    pub fn restore_merged_uuid(&mut self, from: Uuid, into: Uuid) {
+        let into = self.merged_uuids.get(&into).copied().unwrap_or(into);
+        if from == into || self.merged_uuids.contains_key(&from) {
+            return;
+        }
+        for merged_into in self.merged_uuids.values_mut() {
+            if *merged_into == from {
+                *merged_into = into;
+            }
+        }
+        self.merged_uuids.insert(from, into);
+    }
+
+    /// Replaces the [`Uuid`] of the value bound to `key` with `uuid`.
+    ///
+    /// This is meant to be used when recreating a value that was previously
+    /// written somewhere outside of this arena, so that references to its
+    /// original uuid keep working. Returns [`None`] if `key` is not in the
+    /// arena, or if `uuid` already belongs to a different value.
+    pub fn restore_uuid(&mut self, key: ArenaKey, uuid: Uuid) -> Option<()> {
+        match self.uuid_map.get(&uuid).copied() {
+            Some(existing) if existing != key => None,
+            _ => {
+                let value = self.slot_map.get_mut(key)?;
+                self.uuid_map.remove(&value.uuid);
+                value.uuid = uuid;
+                self.uuid_map.insert(uuid, key);
+                Some(())
+            }
+        }
+    }
+
+    /// Cross-checks the members of every container against the inclusions of
+    /// every value, returning each disagreement that was found. An empty
+    /// result means that the arena is consistent.
+    ///
+    /// The inclusions are maintained by hand by every function that modifies a
+    /// container, so this is mostly useful for catching bugs in those
+    /// functions. See [`Value::inclusions`].
+    pub fn validate(&self) -> Vec<Inconsistency> {
+        let (expected, mut inconsistencies) = expected_inclusions(&self.slot_map);
+        let empty = HashSet::new();
+        for (member, value) in self.slot_map.iter() {
+            let expected = expected.get(&member).unwrap_or(&empty);
+            for &(container, route) in expected.difference(&value.inclusions) {
+                inconsistencies.push(Inconsistency::MissingInclusion {
+                    member,
+                    container,
+                    route,
+                });
+            }
+            for &(container, route) in value.inclusions.difference(expected) {
+                inconsistencies.push(Inconsistency::StaleInclusion {
+                    member,
+                    container,
+                    route,
+                });
+            }
+        }
+        inconsistencies
+    }
+
+    /// Rebuilds the inclusions of every value from scratch, using only the
+    /// structures of the containers in the arena. Returns the inconsistencies
+    /// that were present beforehand (see [`Arena::validate`]).
+    ///
+    /// [`Inconsistency::DanglingMember`]s can't be fixed by rebuilding
+    /// inclusions, so they are left alone and will still be reported by later
+    /// calls to [`Arena::validate`].
+    pub fn repair(&mut self) -> Vec<Inconsistency> {
+        let inconsistencies = self.validate();
+        let (mut expected, _) = expected_inclusions(&self.slot_map);
+        for (key, value) in self.slot_map.iter_mut() {
+            value.inclusions = expected.remove(&key).unwrap_or_default();
+        }
+        self.slot_map.orphan_candidates = None;
+        inconsistencies
+    }
+
+    /// Estimates how much memory the values in this arena take up. See
+    /// [`Arena::compact`].
+    pub fn memory_usage(&self) -> MemoryUsage {
+        use std::mem::size_of;
+        let mut by_kind = HashMap::new();
+        for value in self.slot_map.values() {
+            *by_kind.entry(value.structure.kind()).or_insert(0) += value_size(value);
+        }
+        let key_size = size_of::<ArenaKey>();
+        let caches = self.lookup_map.capacity() * (size_of::<ContentHash>() + key_size)
+            + self.uuid_map.capacity() * (size_of::<Uuid>() + key_size)
+            + self.leaf_counts.borrow().capacity() * (key_size + size_of::<usize>())
+            + self.string_classes.borrow().capacity() * (key_size + size_of::<StringClass>())
+            + self
+                .kind_index
+                .borrow()
+                .1
+                .values()
+                .map(|keys| keys.capacity() * key_size)
+                .sum::<usize>()
+            + self.usage.as_ref().map_or(0, |usage| {
+                usage.capacity() * (key_size + size_of::<Usage>())
+            });
+        MemoryUsage { by_kind, caches }
+    }
+
+    /// Releases memory that has built up over a long session without changing
+    /// any value: containers that have shrunk give back the space they no
+    /// longer use, and memoized results (which are recomputed when they are
+    /// next needed) are thrown away. Returns estimates of how much memory was
+    /// used before and after.
+    ///
+    /// Values are only removed from an arena by [`Arena::collect_garbage`], so
+    /// they can't be reclaimed here. Neither can the pixels of a
+    /// [`Structure::Image`], which are kept decoded for as long as it is in
+    /// the arena: only a [`Structure::ImageRef`] leaves its pixels on disk
+    /// (see [the module-level documentation](crate::arena)), and turning one
+    /// into the other would change the value.
+    pub fn compact(&mut self) -> CompactionReport {
+        let before = self.memory_usage();
+        for value in self.slot_map.values_mut() {
+            shrink_structure(&mut value.structure);
+            value.inclusions.shrink_to_fit();
+        }
+        self.lookup_map.shrink_to_fit();
+        self.uuid_map.shrink_to_fit();
+        self.merged_uuids.shrink_to_fit();
+        self.z_offsets.shrink_to_fit();
+        self.layout_overrides.shrink_to_fit();
+        self.carousel_positions.shrink_to_fit();
+        self.rotations.shrink_to_fit();
+        self.rewritten_strings.shrink_to_fit();
+        self.locked.shrink_to_fit();
+        self.local_register_maps.shrink_to_fit();
+        if let Some(usage) = &mut self.usage {
+            usage.shrink_to_fit();
+        }
+        *self.leaf_counts.get_mut() = HashMap::new();
+        *self.string_classes.get_mut() = HashMap::new();
+        *self.kind_index.get_mut() = (0, HashMap::new());
+        CompactionReport {
+            before,
+            after: self.memory_usage(),
+        }
+    }
+
+    /// Removes every value that can't be reached from a register: values
+    /// replaced or removed from everything that contained them, strings
+    /// typed and then discarded, and so on. Returns how many were removed.
+    ///
+    /// Their keys are never given to another value, so indexing the arena
+    /// with one afterwards panics. Anything outside of the arena holding on
+    /// to keys should use [`Arena::collect_garbage_keeping`] instead. Their
+    /// uuids stop resolving too (see [`Arena::resolve_uuid`]), so links to
+    /// them become stale.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.collect_garbage_keeping(&[])
+    }
+
+    /// Like [`Arena::collect_garbage`], but also keeps every value that can
+    /// be reached from `keep`.
+    ///
+    /// While a transaction is open, every value that existed when it began is
+    /// kept too, so that it can still be rolled back.
+    pub fn collect_garbage_keeping(&mut self, keep: &[ArenaKey]) -> usize {
+        let mut todo: Vec<ArenaKey> = keep.to_vec();
+        todo.push(self.register_map);
+        if self.savepoint.is_some() {
+            let slot_map = &self.slot_map;
+            todo.extend(
+                slot_map
+                    .keys()
+                    .filter(|&key| !slot_map.inserted_since_begin(key)),
+            );
+        }
+        let reachable = self.reachable_from(todo);
+        let garbage: Vec<ArenaKey> = self
+            .slot_map
+            .keys()
+            .filter(|key| !reachable.contains(key))
+            .collect();
+        self.remove_values(&garbage);
+        garbage.len()
+    }
+
+    /// Returns how many times `key` is contained: once for each way each
+    /// container holds it (a list can hold it at several indices), and once
+    /// for each register bound to it. Returns [`None`] if `key` isn't in the
+    /// arena.
+    pub fn inclusion_count(&self, key: ArenaKey) -> Option<usize> {
+        Some(self.slot_map.get(key)?.inclusions.len())
+    }
+
+    /// Returns a hash of the contents of the value bound to `key`, which
+    /// changes whenever they do (barring collisions), or [`None`] if there is
+    /// no such value. The members of a set (or the entries of a map) hash the
+    /// same whatever order they are in.
+    pub fn fingerprint(&self, key: ArenaKey) -> Option<u64> {
+        fn hash_of<T: Hash>(value: T) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+        Some(match &self.slot_map.get(key)?.structure {
+            Structure::Set(members) => members.iter().map(hash_of).fold(0, u64::wrapping_add),
+            Structure::Map(entries) => entries.iter().map(hash_of).fold(0, u64::wrapping_add),
+            Structure::List(elements) | Structure::Command(elements) => hash_of(elements),
+            Structure::String(string) => hash_of(string),
+            Structure::Number(number) => number.to_bits(),
+            // Nothing else changes once it is inserted.
+            Structure::Image(_)
+            | Structure::ImageRef(_)
+            | Structure::Blob(_)
+            | Structure::Custom(_) => 0,
+        })
+    }
+
+    /// Returns true if nothing contains `key` and no register is bound to it,
+    /// apart from the pin slots named by `pins` when `pruning` doesn't keep
+    /// pinned values.
+    fn is_orphan(&self, key: ArenaKey, pins: &HashSet<ArenaKey>, pruning: Pruning) -> bool {
+        self.slot_map[key]
+            .inclusions
+            .iter()
+            .all(|(container, route)| match route {
+                Route::Map(MapRoute::ValueOf(name)) if *container == self.register_map => {
+                    !pruning.keep_pinned && pins.contains(name)
+                }
+                _ => false,
+            })
+    }
+
+    /// Returns every orphan: every value that has been removed from (or was
+    /// never inserted into) anything, and that no register is bound to. See
+    /// [`Arena::prune_orphans_keeping`].
+    ///
+    /// Once pruning has run, only the values inserted or removed from
+    /// something since are looked at, alongside the orphans it kept, since
+    /// nothing else can have become an orphan.
+    pub fn orphans(&self, pruning: Pruning) -> Vec<ArenaKey> {
+        let pins = self.pin_names();
+        let register_maps: HashSet<ArenaKey> = self.register_maps().collect();
+        let candidates: Vec<ArenaKey> = match &self.slot_map.orphan_candidates {
+            Some(candidates) => candidates
+                .iter()
+                .copied()
+                .filter(|&key| self.slot_map.contains_key(key))
+                .collect(),
+            None => self.slot_map.keys().collect(),
+        };
+        candidates
+            .into_iter()
+            .filter(|key| !register_maps.contains(key) && self.is_orphan(*key, &pins, pruning))
+            .collect()
+    }
+
+    /// Returns the keys of the names of the pin slots that have been used.
+    fn pin_names(&self) -> HashSet<ArenaKey> {
+        (1..=PIN_SLOTS)
+            .filter_map(|slot| self.lookup_string(&pin_register(slot)))
+            .collect()
+    }
+
+    /// Removes every [orphan](Arena::orphans) apart from those in `keep`,
+    /// then every value that was only contained by the orphans removed, and
+    /// so on. Returns how many values were removed.
+    ///
+    /// Unlike [`Arena::collect_garbage`], this only looks at what contains
+    /// each value, not at whether it can be reached from a register, so it
+    /// doesn't have to walk through the whole arena. Values that contain each
+    /// other are never orphans, even once nothing else leads to them; only
+    /// collecting garbage removes those. Nothing is removed while a
+    /// transaction is open, so that it can still be rolled back.
+    pub fn prune_orphans_keeping(&mut self, keep: &[ArenaKey], pruning: Pruning) -> usize {
+        if self.savepoint.is_some() {
+            return 0;
+        }
+        let pins = self.pin_names();
+        let mut keep: HashSet<ArenaKey> =
+            keep.iter().copied().chain(self.register_maps()).collect();
+        let mut pruned = HashSet::new();
+        // The orphans kept, which have to be looked at again next time.
+        let mut kept = HashSet::new();
+        let mut todo = self.orphans(pruning);
+        while let Some(key) = todo.pop() {
+            if keep.contains(&key) {
+                kept.insert(key);
+                continue;
+            }
+            if pruned.contains(&key) || !self.is_orphan(key, &pins, pruning) {
+                continue;
+            }
+            pruned.insert(key);
+            // Only pin slots can still be bound to it.
+            let names: Vec<ArenaKey> = self.slot_map[key]
+                .inclusions
+                .iter()
+                .filter_map(|(_, route)| match route {
+                    Route::Map(MapRoute::ValueOf(name)) => Some(*name),
+                    _ => None,
+                })
+                .collect();
+            for &name in &names {
+                map_remove(&mut self.slot_map, self.register_map, name);
+            }
+            todo.extend(names);
+            let members: Vec<ArenaKey> = match &self.slot_map[key].structure {
+                Structure::Set(set) => set.iter().copied().collect(),
+                Structure::List(list) | Structure::Command(list) => list.to_vec(),
+                Structure::Map(map) => map.iter().flat_map(|(&k, &v)| vec![k, v]).collect(),
+                Structure::String(_)
+                | Structure::Image(_)
+                | Structure::ImageRef(_)
+                | Structure::Number(_)
+                | Structure::Blob(_)
+                | Structure::Custom(_) => vec![],
+            };
+            for &member in &members {
+                self.slot_map[member]
+                    .inclusions
+                    .retain(|(container, _)| *container != key);
+            }
+            todo.extend(members);
+            if let Some(registers) = self.local_register_maps.remove(&key) {
+                keep.remove(&registers);
+                todo.push(registers);
+            }
+        }
+        if pruned.is_empty() {
+            self.slot_map.orphan_candidates = Some(kept);
+            // Removing nothing would still throw away the caches.
+            return 0;
+        }
+        let pruned: Vec<ArenaKey> = pruned.into_iter().collect();
+        self.remove_values(&pruned);
+        self.slot_map.orphan_candidates = Some(kept);
+        pruned.len()
+    }
+
+    /// Removes `garbage` from the arena, along with everything the arena
+    /// remembers about it. Values still in the arena may have been inside of
+    /// garbage, so their inclusions are updated too.
+    fn remove_values(&mut self, garbage: &[ArenaKey]) {
+        for &key in garbage {
+            let value = self.slot_map.remove(key).unwrap();
+            self.uuid_map.remove(&value.uuid);
+        }
+        let garbage: HashSet<ArenaKey> = garbage.iter().copied().collect();
+        let exists = |key: &ArenaKey| !garbage.contains(key);
+        for value in self.slot_map.values_mut() {
+            value.inclusions.retain(|(container, _)| exists(container));
+        }
+        self.slot_map.orphan_candidates = None;
+        self.lookup_map.retain(|_, key| exists(key));
+        self.z_offsets.retain(|key, _| exists(key));
+        self.layout_overrides.retain(|key, _| exists(key));
+        self.carousel_positions.retain(|key, _| exists(key));
+        self.rotations.retain(|key, _| exists(key));
+        self.locked.retain(|key| exists(key));
+        self.local_register_maps.retain(|key, _| exists(key));
+        self.changes.retain(|key, _| exists(key));
+        if let Some(usage) = &mut self.usage {
+            usage.retain(|key, _| exists(key));
+        }
+        self.leaf_counts.get_mut().clear();
+        self.string_classes.get_mut().retain(|key, _| exists(key));
+        *self.kind_index.get_mut() = (0, HashMap::new());
+        // `rewritten_strings` is left alone, since anything keeping track of
+        // how far through it it has read would lose its place.
+    }
+
+    /// Begins a transaction: every change made to the arena from now on can be
+    /// undone all at once with [`Arena::rollback`], or kept with
+    /// [`Arena::commit`]. Returns [`None`], changing nothing, if a
+    /// transaction is already open; transactions don't nest.
+    ///
+    /// Values are copied as they first change, rather than all at once when
+    /// the transaction begins (see [`Values`]), but the indexes that find
+    /// values by their contents and uuids are copied whole. It is meant for
+    /// operations that change many values at once, not for every change.
+    pub fn begin(&mut self) -> Option<()> {
+        if self.savepoint.is_some() {
+            return None;
+        }
+        self.slot_map.journal = Some(Journal::default());
+        self.savepoint = Some(Savepoint {
+            lookup_map: self.lookup_map.clone(),
+            uuid_map: self.uuid_map.clone(),
+            merged_uuids: self.merged_uuids.clone(),
+            local_register_maps: self.local_register_maps.clone(),
+            locked: self.locked.clone(),
+        });
+        Some(())
+    }
+
+    /// Returns true if a transaction is open. See [`Arena::begin`].
+    pub fn in_transaction(&self) -> bool {
+        self.savepoint.is_some()
+    }
+
+    /// Keeps every change made since [`Arena::begin`], ending the
+    /// transaction. Returns [`None`] if no transaction is open.
+    pub fn commit(&mut self) -> Option<()> {
+        self.slot_map.journal = None;
+        self.savepoint.take().map(|_| ())
+    }
+
+    /// Puts every value back the way it was when [`Arena::begin`] was called,
+    /// removing the values inserted since (along with everything the arena
+    /// remembers about them, like [`Arena::record_change`]), and ends the
+    /// transaction. Returns [`None`] if no transaction is open.
+    ///
+    /// Strings changed since are listed in [`Arena::rewritten_strings`] again,
+    /// so that the search index learns what they say now.
+    pub fn rollback(&mut self) -> Option<()> {
+        let savepoint = self.savepoint.take()?;
+        let journal = self.slot_map.journal.take()?;
+        let inserted: Vec<ArenaKey> = journal
+            .inserted
+            .into_iter()
+            .filter(|&key| self.slot_map.contains_key(key))
+            .collect();
+        self.remove_values(&inserted);
+        for (key, (uuid, inclusions, structure)) in journal.originals {
+            // Values that existed when the transaction began are never
+            // collected as garbage while it is open.
+            let value = match self.slot_map.get_mut(key) {
+                Some(value) => value,
+                None => continue,
+            };
+            value.uuid = uuid;
+            value.inclusions = inclusions;
+            if let Some(structure) = structure {
+                if let (Structure::String(old), Structure::String(new)) =
+                    (&structure, &value.structure)
+                {
+                    if old != new {
+                        self.rewritten_strings.push(key);
+                    }
+                }
+                value.structure = structure;
+            }
+        }
+        self.lookup_map = savepoint.lookup_map;
+        self.uuid_map = savepoint.uuid_map;
+        self.merged_uuids = savepoint.merged_uuids;
+        self.local_register_maps = savepoint.local_register_maps;
+        self.locked = savepoint.locked;
+        self.leaf_counts.get_mut().clear();
+        self.string_classes.get_mut().clear();
+        *self.kind_index.get_mut() = (0, HashMap::new());
+        Some(())
+    }
+
+    /// Returns the number of leaves (values that don't contain anything) that
+    /// can be reached from the value bound to `key`, counting the value itself
+    /// if it is a leaf. This is used as the weight of a value when laying it
+    /// out with [`LayoutStrategy::WeightedCircles`].
+    ///
+    /// Values that only contain themselves (directly or indirectly) have no
+    /// leaves, so this returns at least one to keep them visible.
+    ///
+    /// [`LayoutStrategy::WeightedCircles`]: crate::spatial_tree::LayoutStrategy::WeightedCircles
+    pub fn leaf_count(&self, key: ArenaKey) -> usize {
+        *self
+            .leaf_counts
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| reachable_leaves(&self.slot_map, key).max(1))
+    }
+
+    /// Returns how many levels above its usual place in the drawing order the
+    /// value bound to `key` is drawn. Values are normally drawn above the
+    /// values that contain them; a positive offset raises a value above
+    /// overlapping values that are nested more deeply, and a negative offset
+    /// lowers it. See [`crate::render::draw_order`].
+    pub fn z_offset(&self, key: ArenaKey) -> i32 {
+        self.z_offsets.get(&key).copied().unwrap_or(0)
+    }
+
+    /// Changes the z-offset of the value bound to `key`. See
+    /// [`Arena::z_offset`].
+    pub fn set_z_offset(&mut self, key: ArenaKey, z_offset: i32) {
+        if z_offset == 0 {
+            self.z_offsets.remove(&key);
+        } else {
+            self.z_offsets.insert(key, z_offset);
+        }
+    }
+
+    /// Returns how the container bound to `key` is arranged, if it is always
+    /// arranged the same way instead of following the layout of its layer
+    /// (see [`crate::spatial_tree::container_layout`]).
+    pub fn layout_override(&self, key: ArenaKey) -> Option<LayoutStrategy> {
+        self.layout_overrides.get(&key).copied()
+    }
+
+    /// Changes how the container bound to `key` is arranged. See
+    /// [`Arena::layout_override`].
+    pub fn set_layout_override(&mut self, key: ArenaKey, layout: Option<LayoutStrategy>) {
+        match layout {
+            Some(layout) => self.layout_overrides.insert(key, layout),
+            None => self.layout_overrides.remove(&key),
+        };
+    }
+
+    /// Returns which member of the set bound to `key` is enlarged when it is
+    /// arranged with [`LayoutStrategy::Zoomed`], as an index into its members
+    /// in the order they are laid out. Positions wrap around, so any position
+    /// is valid, and positions between two indices are part of the way
+    /// through turning from one member to the next.
+    pub fn carousel_position(&self, key: ArenaKey) -> f32 {
+        self.carousel_positions.get(&key).copied().unwrap_or(0.0)
+    }
+
+    /// Changes which member of the set bound to `key` is enlarged. See
+    /// [`Arena::carousel_position`].
+    pub fn set_carousel_position(&mut self, key: ArenaKey, position: f32) {
+        if position == 0.0 {
+            self.carousel_positions.remove(&key);
+        } else {
+            self.carousel_positions.insert(key, position);
+        }
+    }
+
+    /// Returns how far the members of the container bound to `key` are
+    /// turned, counterclockwise in radians, from where they are usually
+    /// placed. The first member is usually placed on the left, so a rotation
+    /// of minus a quarter turn places it at the top. Always between zero and
+    /// a full turn. Containers drawn as rectangles
+    /// ([`LayoutStrategy::Treemap`]) aren't turned.
+    pub fn rotation(&self, key: ArenaKey) -> f32 {
+        self.rotations.get(&key).copied().unwrap_or(0.0)
+    }
+
+    /// Changes how far the members of the container bound to `key` are
+    /// turned. See [`Arena::rotation`].
+    pub fn set_rotation(&mut self, key: ArenaKey, rotation: f32) {
+        let rotation = rotation.rem_euclid(2.0 * std::f32::consts::PI);
+        if rotation == 0.0 {
+            self.rotations.remove(&key);
+        } else {
+            self.rotations.insert(key, rotation);
+        }
+    }
+
+    /// Returns true if the value bound to `key` shouldn't be modified, because
+    /// it or a container it is (directly or indirectly) inside of was locked
+    /// with [`Arena::set_locked`].
+    ///
+    /// Being bound to a register doesn't count as being inside of a container,
+    /// so register maps are never followed.
+    ///
+    /// Nothing in the arena itself checks this. It is up to whoever is about
+    /// to modify a value to ask first.
+    pub fn is_locked(&self, key: ArenaKey) -> bool {
+        let register_maps: HashSet<ArenaKey> = self.register_maps().collect();
+        let mut visited = HashSet::new();
+        let mut pending = vec![key];
+        while let Some(key) = pending.pop() {
+            if self.locked.contains(&key) {
+                return true;
+            }
+            if let Some(value) = self.slot_map.get(key) {
+                for &(container, _) in &value.inclusions {
+                    if !register_maps.contains(&container) && visited.insert(container) {
+                        pending.push(container);
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Locks (or unlocks) the value bound to `key` and everything inside of
+    /// it. Unlocking a value that is inside of another locked value leaves it
+    /// locked. See [`Arena::is_locked`].
+    pub fn set_locked(&mut self, key: ArenaKey, locked: bool) {
+        if locked {
+            self.locked.insert(key);
+        } else {
+            self.locked.remove(&key);
+        }
+    }
+
+    /// Returns true if the value bound to `key` was itself locked, rather
+    /// than only being inside of something locked.
+    pub fn is_locked_directly(&self, key: ArenaKey) -> bool {
+        self.locked.contains(&key)
+    }
+
+    /// Copies every value of `other` into this arena and binds the registers
+    /// of `other` to their copies, returning a report of what happened.
+    ///
+    /// Values are matched up by [`Uuid`] first, so values that both arenas
+    /// got from the same place are only stored once. Values that are only
+    /// in `other` are copied along with their uuids. Strings and images are
+    /// also deduplicated by their contents, like everywhere else in the arena.
+    /// A container that is already in this arena is used as-is; changes made
+    /// to it in `other` are not merged.
+    ///
+    /// Registers that are only bound in `other` are bound here too. Registers
+    /// that are bound to different values in each arena are resolved with
+    /// `policy` and listed in [`MergeReport::conflicts`]. The `.` register
+    /// describes what is being looked at rather than what is stored, and the
+    /// [`CHANGED_REGISTER`] describes what changed in `other` rather than
+    /// here, so neither is ever merged.
+    ///
+    /// [Local registers](is_local_register) are never in conflict. They are
+    /// copied along with the value they belong to, unless that value already
+    /// has local registers in this arena, in which case those are kept.
+    pub fn merge(&mut self, other: &Arena, policy: ConflictPolicy) -> MergeReport {
+        let mut report = MergeReport::default();
+        // Associates the keys of `other` with the keys of their copies.
+        let mut keys: HashMap<ArenaKey, ArenaKey> = HashMap::new();
+        keys.insert(other.register_map, self.register_map);
+
+        // Copy every value, leaving containers empty for now, since they may
+        // contain values that haven't been copied yet (or even themselves).
+        let mut containers = Vec::new();
+        for (other_key, value) in other.slot_map.iter() {
+            if other_key == other.register_map {
+                continue;
+            }
+            if let Some(key) = self.lookup_uuid(&value.uuid) {
+                keys.insert(other_key, key);
+                report.reused += 1;
+                continue;
+            }
+            let len = self.slot_map.len();
+            let key = match &value.structure {
+                Structure::String(string) => self.string(string),
+                Structure::Image(image) => self.image((**image).clone()),
+                Structure::ImageRef(path) => self.image_ref((**path).clone()),
+                Structure::Number(number) => self.number(*number),
+                Structure::Blob(blob) => self.blob((**blob).clone()),
+                Structure::Custom(custom) => self.custom(custom.leaf.duplicate()),
+                Structure::Set(_) => {
+                    insert_set(&mut self.slot_map, &mut self.uuid_map, HashSet::new())
+                }
+                Structure::List(_) => insert_list(&mut self.slot_map, &mut self.uuid_map, vec![]),
+                Structure::Map(_) => {
+                    insert_map(&mut self.slot_map, &mut self.uuid_map, HashMap::new())
+                }
+                Structure::Command(_) => insert_value(
+                    &mut self.slot_map,
+                    &mut self.uuid_map,
+                    Structure::Command(Box::new(vec![])),
+                ),
+            };
+            keys.insert(other_key, key);
+            if self.slot_map.len() > len {
+                self.restore_uuid(key, value.uuid).unwrap();
+                self.set_z_offset(key, other.z_offset(other_key));
+                self.set_layout_override(key, other.layout_override(other_key));
+                self.set_locked(key, other.locked.contains(&other_key));
+                self.slot_map[key].metadata = value.metadata.clone();
+                containers.push(other_key);
+                report.imported += 1;
+            } else {
+                report.reused += 1;
+            }
+        }
+
+        // Fill in the copied containers. Members that aren't in `other` are
+        // dropped.
+        for other_key in containers {
+            let key = keys[&other_key];
+            match &other.slot_map[other_key].structure {
+                Structure::Set(set) => {
+                    for member in set.iter().filter_map(|member| keys.get(member)) {
+                        set_insert(&mut self.slot_map, key, *member);
+                    }
+                }
+                Structure::List(list) => {
+                    for member in list.iter().filter_map(|member| keys.get(member)) {
+                        list_push(&mut self.slot_map, key, *member);
+                    }
+                }
+                Structure::Map(map) => {
+                    for (k, v) in map.iter() {
+                        if let (Some(&k), Some(&v)) = (keys.get(k), keys.get(v)) {
+                            map_insert(&mut self.slot_map, key, k, v);
+                        }
+                    }
+                }
+                Structure::Command(command) => {
+                    let command = command
+                        .iter()
+                        .filter_map(|member| keys.get(member).copied())
+                        .collect();
+                    self.slot_map[key].structure = Structure::Command(Box::new(command));
+                }
+                Structure::String(_)
+                | Structure::Image(_)
+                | Structure::ImageRef(_)
+                | Structure::Number(_)
+                | Structure::Blob(_)
+                | Structure::Custom(_) => {}
+            }
+        }
+
+        let registers = match &other.slot_map[other.register_map].structure {
+            Structure::Map(map) => map.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>(),
+            _ => panic!(),
+        };
+        for (register, value) in registers {
+            let register = match other.slot_map.get(register).map(|v| &v.structure) {
+                Some(Structure::String(register))
+                    if register.as_str() != "." && register.as_str() != CHANGED_REGISTER =>
+                {
+                    register.to_string()
+                }
+                _ => continue,
+            };
+            let theirs = match keys.get(&value) {
+                Some(&theirs) => theirs,
+                None => continue,
+            };
+            match self.register(register.as_str()) {
+                None => self.bind_register(register, theirs),
+                Some(ours) if ours == theirs => {}
+                Some(ours) => {
+                    let renamed = match policy {
+                        ConflictPolicy::Ours => None,
+                        ConflictPolicy::Theirs => {
+                            self.bind_register(register.as_str(), theirs);
+                            None
+                        }
+                        ConflictPolicy::Rename => {
+                            let renamed = (1..)
+                                .map(|n| format!("{}~{}", register, n))
+                                .find(|renamed| self.register(renamed.as_str()).is_none())
+                                .unwrap();
+                            self.bind_register(renamed.as_str(), theirs);
+                            Some(renamed)
+                        }
+                    };
+                    report.conflicts.push(RegisterConflict {
+                        register,
+                        ours,
+                        theirs,
+                        renamed,
+                    });
+                }
+            }
+        }
+
+        for (other_owner, other_map) in other.local_register_maps.iter() {
+            if let (Some(&owner), Some(&map)) = (keys.get(other_owner), keys.get(other_map)) {
+                self.local_register_maps.entry(owner).or_insert(map);
+            }
+        }
+
+        self.leaf_counts.get_mut().clear();
+        report
+    }
+
+    /// Returns every value inside of the values in `todo`, directly or not,
+    /// including their local registers, along with those values themselves.
+    fn reachable_from(&self, mut todo: Vec<ArenaKey>) -> HashSet<ArenaKey> {
+        let mut reachable = HashSet::new();
+        while let Some(key) = todo.pop() {
+            let value = match self.slot_map.get(key) {
+                Some(value) if reachable.insert(key) => value,
+                _ => continue,
+            };
+            todo.extend(members(&value.structure));
+            todo.extend(self.local_register_maps.get(&key));
+        }
+        reachable
+    }
+
+    /// Returns every value worth saving: those that can be reached from a
+    /// register, apart from the [`DERIVED_REGISTERS`], along with the names
+    /// of those registers and the local registers of every such value.
+    pub fn saved_values(&self) -> HashSet<ArenaKey> {
+        let todo = match &self.slot_map[self.register_map].structure {
+            Structure::Map(map) => map
+                .iter()
+                .filter(|&(&name, _)| !self.is_derived_register(name))
+                .flat_map(|(&name, &value)| vec![name, value])
+                .collect(),
+            _ => vec![],
+        };
+        self.reachable_from(todo)
+    }
+
+    /// Returns true if `name` is the name of one of the [`DERIVED_REGISTERS`].
+    pub fn is_derived_register(&self, name: ArenaKey) -> bool {
+        matches!(
+            self.slot_map.get(name).map(|value| &value.structure),
+            Some(Structure::String(name)) if DERIVED_REGISTERS.contains(&name.as_str())
+        )
+    }
+
+    /// Inserts a map describing `changes`, as found by [`diff`] between `old`
+    /// and this arena, and returns it. Its keys are strings:
+    ///
+    /// * `added` is bound to a set of the values that were added, and
+    ///   `rewritten` and `reordered` to sets of the leaves that were rewritten
+    ///   and the lists that were reordered;
+    /// * `removed` is bound to a set of the values that were removed. Those
+    ///   that have already been collected as garbage are described by strings
+    ///   instead, since they aren't in this arena to be displayed;
+    /// * `changed` is bound to a map pairing each container whose members
+    ///   changed with a map from `included` and `excluded` to sets of the
+    ///   members put into it and taken out of it, described the same way.
+    ///
+    /// Keys that would be bound to something empty are left out.
+    pub fn diff_view(&mut self, old: &Arena, changes: &[Change]) -> ArenaKey {
+        // The key of the value named `uuid` by a change, or a string
+        // describing it if it was removed.
+        let resolve = |arena: &mut Arena, uuid: &Uuid| match arena.lookup_uuid(uuid) {
+            Some(key) => Some(key),
+            None => Some(arena.string(&describe_value(old, old.lookup_uuid(uuid)?))),
+        };
+        let mut sets: HashMap<&str, Vec<ArenaKey>> = HashMap::new();
+        let mut changed: Vec<(ArenaKey, Vec<ArenaKey>, Vec<ArenaKey>)> = vec![];
+        for change in changes {
+            let (name, uuid) = match change {
+                Change::Added(uuid) => ("added", uuid),
+                Change::Removed(uuid) => ("removed", uuid),
+                Change::Rewritten(uuid) => ("rewritten", uuid),
+                Change::Reordered(uuid) => ("reordered", uuid),
+                Change::Included { container, member } | Change::Excluded { container, member } => {
+                    let (container, member) =
+                        match (self.lookup_uuid(container), resolve(self, member)) {
+                            (Some(container), Some(member)) => (container, member),
+                            _ => continue,
+                        };
+                    let index = match changed.iter().position(|(key, ..)| *key == container) {
+                        Some(index) => index,
+                        None => {
+                            changed.push((container, vec![], vec![]));
+                            changed.len() - 1
+                        }
+                    };
+                    match change {
+                        Change::Included { .. } => changed[index].1.push(member),
+                        _ => changed[index].2.push(member),
+                    }
+                    continue;
+                }
+            };
+            if let Some(key) = resolve(self, uuid) {
+                sets.entry(name).or_default().push(key);
+            }
+        }
+
+        let mut entries = vec![];
+        for name in &["added", "removed", "rewritten", "reordered"] {
+            if let Some(members) = sets.remove(name) {
+                let set = self.set(members);
+                entries.push((self.string(name), set));
+            }
+        }
+        if !changed.is_empty() {
+            let mut containers = vec![];
+            for (container, included, excluded) in changed {
+                let mut sides = vec![];
+                for (name, members) in vec![("included", included), ("excluded", excluded)] {
+                    if !members.is_empty() {
+                        let set = self.set(members);
+                        sides.push((self.string(name), set));
+                    }
+                }
+                containers.push((container, self.map(sides)));
+            }
+            let map = self.map(containers);
+            entries.push((self.string("changed"), map));
+        }
+        self.map(entries)
+    }
+
+    /// Returns what kind of content the string bound to `key` holds (see
+    /// [`crate::string_class`]). Values that aren't strings are
+    /// [`StringClass::Plain`].
+    pub fn string_class(&self, key: ArenaKey) -> StringClass {
+        *self
+            .string_classes
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| match self.slot_map.get(key).map(|v| &v.structure) {
+                Some(Structure::String(string)) => string_class::classify(string),
+                _ => StringClass::Plain,
+            })
+    }
+
+    pub fn string(&mut self, string: &str) -> ArenaKey {
+        insert_string(
+            &mut self.slot_map,
+            &mut self.lookup_map,
+            &mut self.uuid_map,
+            string,
+        )
+    }
+
+    /// Inserts `number`, which should be finite (see [`crate::number`]).
+    pub fn number(&mut self, number: f64) -> ArenaKey {
+        insert_number(
+            &mut self.slot_map,
+            &mut self.lookup_map,
+            &mut self.uuid_map,
+            number,
+        )
+    }
+
+    /// Returns the number bound to `key`, if it is a number.
+    pub fn as_number(&self, key: ArenaKey) -> Option<f64> {
+        match self.slot_map.get(key)?.structure {
+            Structure::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    /// Inserts `blob`, or returns the blob already inserted with the same
+    /// contents (see [`crate::blob`]).
+    pub fn blob(&mut self, blob: Blob) -> ArenaKey {
+        let hash = ContentHash::of_blob(&blob.bytes);
+        self.blob_hashed(blob, hash)
+    }
+
+    /// Like [`Arena::blob`], but with the blob's [`ContentHash::of_blob`]
+    /// already worked out, so that large blobs can be hashed away from the
+    /// render thread, as they are when imported.
+    pub fn blob_hashed(&mut self, blob: Blob, hash: ContentHash) -> ArenaKey {
+        insert_blob(
+            &mut self.slot_map,
+            &mut self.lookup_map,
+            &mut self.uuid_map,
+            blob,
+            hash,
+        )
+    }
+
+    pub fn image(&mut self, image: image::RgbaImage) -> ArenaKey {
+        insert_image(
+            &mut self.slot_map,
+            &mut self.lookup_map,
+            &mut self.uuid_map,
+            image,
+        )
+    }
+
+    /// Inserts a reference to the image file at `path`, without reading it.
+    /// Its pixels are only read while it is on screen, so this is meant for
+    /// photos and other images too large to keep in memory by the hundred.
+    pub fn image_ref<P: Into<PathBuf>>(&mut self, path: P) -> ArenaKey {
+        insert_image_ref(
+            &mut self.slot_map,
+            &mut self.lookup_map,
+            &mut self.uuid_map,
+            path.into(),
+        )
+    }
+
+    /// Inserts a [`CustomLeaf`], rendering its thumbnail if it hasn't been
+    /// inserted before. See [`crate::leaf`].
+    pub fn custom(&mut self, leaf: Box<dyn CustomLeaf>) -> ArenaKey {
+        insert_custom(
+            &mut self.slot_map,
+            &mut self.lookup_map,
+            &mut self.uuid_map,
+            leaf,
+        )
+    }
+
+    /// Registers the [`Deserializer`] for custom leaves whose
+    /// [`CustomLeaf::type_name`] is `type_name`, replacing any registered
+    /// before.
+    pub fn register_leaf_type(&mut self, type_name: &'static str, deserialize: Deserializer) {
+        self.leaf_types.insert(type_name, deserialize);
+    }
+
+    /// Turns `bytes` (as returned by [`CustomLeaf::serialize`]) back into a
+    /// leaf of the type named `type_name` and inserts it. Returns [`None`] if
+    /// no such type has been registered, or if `bytes` don't describe a leaf.
+    pub fn deserialize_leaf(&mut self, type_name: &str, bytes: &[u8]) -> Option<ArenaKey> {
+        let leaf = self.leaf_types.get(type_name)?(bytes)?;
+        Some(self.custom(leaf))
+    }
+
+    /// Inserts every string in `strings`, returning their keys in the same
+    /// order, like calling [`Arena::string`] on each of them.
+    ///
+    /// This is meant for inserting many strings at once. Room for all of them
+    /// is made up front, instead of the arena growing (and rehashing) over and
+    /// over again as they come in.
+    pub fn insert_strings<I, S>(&mut self, strings: I) -> Vec<ArenaKey>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let strings = strings.into_iter();
+        let (additional, _) = strings.size_hint();
+        self.slot_map.reserve(additional);
+        self.lookup_map.reserve(additional);
+        self.uuid_map.reserve(additional);
+        strings
+            .map(|string| {
+                insert_string(
+                    &mut self.slot_map,
+                    &mut self.lookup_map,
+                    &mut self.uuid_map,
+                    string,
+                )
+            })
+            .collect()
+    }
+
+    /// Inserts every value in `values` into the set bound to `set`. Returns
+    /// [`None`], changing nothing, if `set` isn't a set or any of `values`
+    /// isn't in the arena.
+    ///
+    /// Unlike calling [`Arena::set_insert_value`] once per value, the set only
+    /// grows once and the cached [leaf counts](Arena::leaf_count) are only
+    /// thrown away once.
+    pub fn set_insert_many<I: IntoIterator<Item = ArenaKey>>(
+        &mut self,
+        set: ArenaKey,
+        values: I,
+    ) -> Option<()> {
+        let values = values.into_iter().collect::<Vec<_>>();
+        if !values
+            .iter()
+            .all(|&value| self.slot_map.contains_key(value))
+        {
+            return None;
+        }
+        match &mut self.slot_map.get_mut(set)?.structure {
+            Structure::Set(members) => members.extend(values.iter().copied()),
+            _ => return None,
+        }
+        for value in values {
+            add_inclusion(&mut self.slot_map, value, set, Route::Set);
+        }
+        self.leaf_counts.get_mut().clear();
+        Some(())
+    }
+
+    /// Creates a new set containing `members`.
+    pub fn set<I: IntoIterator<Item = ArenaKey>>(&mut self, members: I) -> ArenaKey {
+        insert_set(
+            &mut self.slot_map,
+            &mut self.uuid_map,
+            members.into_iter().collect(),
+        )
+    }
+
+    /// Creates a new list containing `elements`, in order.
+    pub fn list<I: IntoIterator<Item = ArenaKey>>(&mut self, elements: I) -> ArenaKey {
+        insert_list(
+            &mut self.slot_map,
+            &mut self.uuid_map,
+            elements.into_iter().collect(),
+        )
+    }
+
+    /// Creates a new map pairing the first value of each of `entries` with
+    /// the second.
+    pub fn map<I: IntoIterator<Item = (ArenaKey, ArenaKey)>>(&mut self, entries: I) -> ArenaKey {
+        insert_map(
+            &mut self.slot_map,
+            &mut self.uuid_map,
+            entries.into_iter().collect(),
+        )
+    }
+
+    /// Pairs `key` with `value` in `map`, replacing whatever it was paired
+    /// with before. Returns [`None`], changing nothing, if `map` isn't a map
+    /// or `key` or `value` don't exist.
+    pub fn map_insert(&mut self, map: ArenaKey, key: ArenaKey, value: ArenaKey) -> Option<()> {
+        match self.slot_map.get(map)?.structure {
+            Structure::Map(_)
+                if self.slot_map.contains_key(key) && self.slot_map.contains_key(value) =>
+            {
+                map_insert(&mut self.slot_map, map, key, value);
+                self.leaf_counts.get_mut().clear();
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    /// Removes `key` from `map`, along with whatever it was paired with.
+    /// Returns [`None`], changing nothing, if `map` isn't a map or `key`
+    /// isn't in it.
+    pub fn map_remove(&mut self, map: ArenaKey, key: ArenaKey) -> Option<()> {
+        self.entries(map)?.get(&key)?;
+        self.leaf_counts.get_mut().clear();
+        map_remove(&mut self.slot_map, map, key);
+        Some(())
+    }
+
+    /// Returns the entries of `map`, or [`None`] if it isn't a map.
+    pub fn entries(&self, map: ArenaKey) -> Option<&HashMap<ArenaKey, ArenaKey>> {
+        match &self.slot_map.get(map)?.structure {
+            Structure::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Creates a new command made of `elements`, in order. Like every
+    /// command, it isn't recorded in the inclusions of its elements.
+    pub fn command<I: IntoIterator<Item = ArenaKey>>(&mut self, elements: I) -> ArenaKey {
+        insert_value(
+            &mut self.slot_map,
+            &mut self.uuid_map,
+            Structure::Command(Box::new(elements.into_iter().collect())),
+        )
+    }
+
+    /// Returns the number of elements in `list`, or [`None`] if it isn't a
+    /// list.
+    fn list_len(&self, list: ArenaKey) -> Option<usize> {
+        match &self.slot_map.get(list)?.structure {
+            Structure::List(vec) => Some(vec.len()),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of members of `set`, or [`None`] if it isn't a set.
+    fn set_len(&self, set: ArenaKey) -> Option<usize> {
+        match &self.slot_map.get(set)?.structure {
+            Structure::Set(members) => Some(members.len()),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of `list`, in order, or [`None`] if it isn't a
+    /// list.
+    pub fn elements(&self, list: ArenaKey) -> Option<&[ArenaKey]> {
+        match &self.slot_map.get(list)?.structure {
+            Structure::List(vec) => Some(vec.as_slice()),
+            _ => None,
+        }
+    }
+
+    /// Makes `list` hold exactly `elements`, in order, returning the elements
+    /// it held before. Returns [`None`], changing nothing, if `list` isn't a
+    /// list or one of `elements` doesn't exist.
+    pub fn list_replace(
+        &mut self,
+        list: ArenaKey,
+        elements: Vec<ArenaKey>,
+    ) -> Option<Vec<ArenaKey>> {
+        let len = self.list_len(list)?;
+        if !elements.iter().all(|&key| self.slot_map.contains_key(key)) {
+            return None;
+        }
+        self.leaf_counts.get_mut().clear();
+        let affected = 0..len.max(elements.len());
+        let mut old = Vec::new();
+        list_edit(&mut self.slot_map, list, affected, |vec| {
+            old = std::mem::replace(vec, elements)
+        });
+        Some(old)
+    }
+
+    /// Inserts `value` into `list` at `index`, shifting the elements after it
+    /// towards the end. Returns [`None`], changing nothing, if `list` isn't a
+    /// list, `value` doesn't exist, or `index` is past the end of the list.
+    pub fn list_insert_at(&mut self, list: ArenaKey, index: usize, value: ArenaKey) -> Option<()> {
+        let len = self.list_len(list)?;
+        if index > len || !self.slot_map.contains_key(value) {
+            return None;
+        }
+        self.leaf_counts.get_mut().clear();
+        list_edit(&mut self.slot_map, list, index..len + 1, |vec| {
+            vec.insert(index, value)
+        });
+        Some(())
+    }
+
+    /// Removes and returns the element of `list` at `index`, shifting the
+    /// elements after it towards the start. Returns [`None`], changing
+    /// nothing, if `list` isn't a list or has no such element.
+    pub fn list_remove_at(&mut self, list: ArenaKey, index: usize) -> Option<ArenaKey> {
+        let len = self.list_len(list)?;
+        if index >= len {
+            return None;
+        }
+        self.leaf_counts.get_mut().clear();
+        let mut removed = None;
+        list_edit(&mut self.slot_map, list, index..len, |vec| {
+            removed = Some(vec.remove(index))
+        });
+        removed
+    }
+
+    /// Exchanges the elements of `list` at indices `a` and `b`. Returns
+    /// [`None`], changing nothing, if `list` isn't a list or either index is
+    /// out of bounds.
+    pub fn list_swap(&mut self, list: ArenaKey, a: usize, b: usize) -> Option<()> {
+        let len = self.list_len(list)?;
+        if a >= len || b >= len {
+            return None;
+        }
+        list_edit(&mut self.slot_map, list, a.min(b)..a.max(b) + 1, |vec| {
+            vec.swap(a, b)
+        });
+        Some(())
+    }
+
+    /// Moves the element of `list` at index `from` so that it ends up at index
+    /// `to`, shifting the elements in between by one. Returns [`None`],
+    /// changing nothing, if `list` isn't a list or either index is out of
+    /// bounds.
+    pub fn list_move(&mut self, list: ArenaKey, from: usize, to: usize) -> Option<()> {
+        let len = self.list_len(list)?;
+        if from >= len || to >= len {
+            return None;
+        }
+        list_edit(
+            &mut self.slot_map,
+            list,
+            from.min(to)..from.max(to) + 1,
+            |vec| {
+                let value = vec.remove(from);
+                vec.insert(to, value);
+            },
+        );
+        Some(())
+    }
+
+    /// Removes the element of `list` at `index` and inserts it into the set
+    /// bound to `to`. Returns [`None`], changing nothing, if `list` isn't a
+    /// list, has no such element, or `to` isn't a set.
+    pub fn list_move_to_set(&mut self, list: ArenaKey, index: usize, to: ArenaKey) -> Option<()> {
+        if index >= self.list_len(list)? {
+            return None;
+        }
+        match self.slot_map.get(to)?.structure {
+            Structure::Set(_) => {}
+            _ => return None,
+        }
+        let value = self.list_remove_at(list, index)?;
+        set_insert(&mut self.slot_map, to, value);
+        Some(())
+    }
+
+    /// Makes the set bound to `set` contain exactly the members of the set
+    /// bound to `other`. Returns [`None`] if either isn't a set.
+    pub fn set_replace(&mut self, set: ArenaKey, other: ArenaKey) -> Option<()> {
+        let members = |slot_map: &SlotMap<ArenaKey, Value>, key| match &slot_map.get(key)?.structure
+        {
+            Structure::Set(members) => Some(members.iter().copied().collect::<Vec<_>>()),
+            _ => None,
+        };
+        let old = members(&self.slot_map, set)?;
+        let new = members(&self.slot_map, other)?;
+        self.leaf_counts.get_mut().clear();
+        for member in old {
+            set_remove(&mut self.slot_map, set, member);
+        }
+        for member in new {
+            set_insert(&mut self.slot_map, set, member);
+        }
+        Some(())
+    }
+
+    /// Removes `value` from the set bound to `from` and inserts it into the set
+    /// bound to `to`. Returns [`None`], changing nothing, if either isn't a
+    /// set, they are the same set, or `value` isn't in `from`.
+    pub fn set_move(&mut self, value: ArenaKey, from: ArenaKey, to: ArenaKey) -> Option<()> {
+        match (
+            &self.slot_map.get(from)?.structure,
+            &self.slot_map.get(to)?.structure,
+        ) {
+            (Structure::Set(members), Structure::Set(_)) if from != to => {
+                if !members.contains(&value) {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+        self.leaf_counts.get_mut().clear();
+        set_remove(&mut self.slot_map, from, value);
+        set_insert(&mut self.slot_map, to, value);
+        Some(())
+    }
+
+    /// Removes each of `remove` from `set` and then inserts each of `insert`
+    /// into it. Returns [`None`], changing nothing, if `set` isn't a set.
+    pub fn set_change(
+        &mut self,
+        set: ArenaKey,
+        insert: &[ArenaKey],
+        remove: &[ArenaKey],
+    ) -> Option<()> {
+        match &self.slot_map.get(set)?.structure {
+            Structure::Set(_) => {}
+            _ => return None,
+        }
+        self.leaf_counts.get_mut().clear();
+        for &member in remove {
+            set_remove(&mut self.slot_map, set, member);
+        }
+        for &member in insert {
+            set_insert(&mut self.slot_map, set, member);
+        }
+        Some(())
+    }
+
+    /// Puts the value bound to `into` everywhere the value bound to `from` is
+    /// contained, leaving `from` contained in nothing. Sets containing both end
+    /// up containing `into` once. Returns [`None`], changing nothing, if they
+    /// are the same value, or if `from` is the key of a map, where `into`
+    /// could clash with a key the map already has.
+    ///
+    /// `from` is still in the arena afterwards, just not inside of anything,
+    /// until [`Arena::collect_garbage`] removes it.
+    pub fn merge_into(&mut self, from: ArenaKey, into: ArenaKey) -> Option<()> {
+        if from == into || !self.slot_map.contains_key(into) {
+            return None;
+        }
+        let inclusions: Vec<(ArenaKey, Route)> = self
+            .slot_map
+            .get(from)?
+            .inclusions
+            .iter()
+            .copied()
+            .collect();
+        if inclusions
+            .iter()
+            .any(|&(_, route)| route == Route::Map(MapRoute::Key))
+        {
+            return None;
+        }
+        self.leaf_counts.get_mut().clear();
+        for (container, route) in inclusions {
+            match route {
+                Route::Set => {
+                    set_remove(&mut self.slot_map, container, from);
+                    set_insert(&mut self.slot_map, container, into);
+                }
+                Route::List(ListRoute { index }) => {
+                    remove_inclusion(&mut self.slot_map, from, container, route);
+                    match &mut self.slot_map[container].structure {
+                        Structure::List(list) => list[index] = into,
+                        _ => panic!(),
+                    }
+                    add_inclusion(&mut self.slot_map, into, container, route);
+                }
+                Route::Map(MapRoute::ValueOf(key)) => {
+                    map_insert(&mut self.slot_map, container, key, into);
+                }
+                Route::Map(MapRoute::Key) => unreachable!(),
+            }
+        }
+        // Anything that was merged into `from` has now been merged into
+        // `into`, so that following references never takes more than one step.
+        let (from, into) = (self.slot_map[from].uuid, self.slot_map[into].uuid);
+        for merged_into in self.merged_uuids.values_mut() {
+            if *merged_into == from {
+                *merged_into = into;
+            }
+        }
+        self.merged_uuids.remove(&into);
+        self.merged_uuids.insert(from, into);
+        Some(())
+    }
+
+    /// Returns the key of every string changed by [`Arena::rewrite_strings`],
+    /// in the order they were changed, once for each time it was changed.
+    /// Strings don't change any other way, so anything keeping track of what
+    /// strings say only has to look at the ones it hasn't seen yet.
+    pub fn rewritten_strings(&self) -> &[ArenaKey] {
+        &self.rewritten_strings
+    }
+
+    /// Creates a new set containing the members of the set bound to `set`,
+    /// except that each member that is itself a set is replaced by its own
+    /// members. Returns [`None`] if `set` isn't a set.
+    pub fn set_flatten_once(&mut self, set: ArenaKey) -> Option<ArenaKey> {
+        let members = match &self.slot_map.get(set)?.structure {
+            Structure::Set(members) => members.iter().copied().collect::<Vec<_>>(),
+            _ => return None,
+        };
+        let mut flattened = HashSet::new();
+        for member in members {
+            match &self.slot_map[member].structure {
+                Structure::Set(inner) => flattened.extend(inner.iter().copied()),
+                _ => {
+                    flattened.insert(member);
+                }
+            }
+        }
+        Some(self.set(flattened))
+    }
+
+    /// Returns the map that `register` is bound in, alongside the key of the
+    /// string naming it.
+    ///
+    /// [Local registers](is_local_register) are bound in the local register
+    /// map of the value bound to `.`, and everything else is bound in the
+    /// `register_map`. If `.` has no local register map yet, one is created
+    /// when `create` is true, and [`None`] is returned otherwise.
+    fn resolve_register(&mut self, register: String, create: bool) -> Option<(ArenaKey, ArenaKey)> {
+        let map = if is_local_register(&register) {
+            let selected_register = insert_string(
+                &mut self.slot_map,
+                &mut self.lookup_map,
+                &mut self.uuid_map,
+                ".",
+            );
+            let owner = map_get(&self.slot_map, self.register_map, selected_register)?;
+            match self.local_register_maps.get(&owner) {
+                Some(&map) => map,
+                None if create => self.local_register_map(owner),
+                None => return None,
+            }
+        } else {
+            self.register_map
+        };
+        let register = insert_string(
+            &mut self.slot_map,
+            &mut self.lookup_map,
+            &mut self.uuid_map,
+            register,
+        );
+        Some((map, register))
+    }
+
+    /// Returns the names of the (non-local) registers bound to `key`, other
+    /// than `.`, in sorted order. These are used to label containers on
+    /// screen.
+    pub fn register_names(&self, key: ArenaKey) -> Vec<&str> {
+        let mut names = self
+            .slot_map
+            .get(key)
+            .into_iter()
+            .flat_map(|value| value.inclusions.iter())
+            .filter_map(|&(container, route)| match route {
+                Route::Map(MapRoute::ValueOf(register)) if container == self.register_map => {
+                    match &self.slot_map[register].structure {
+                        Structure::String(name) if name.as_str() != "." => Some(name.as_str()),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the names of every (non-local) register, in sorted order.
+    pub fn registers(&self) -> Vec<&str> {
+        let mut names = match &self.slot_map[self.register_map].structure {
+            Structure::Map(map) => map
+                .keys()
+                .filter_map(|&register| match &self.slot_map[register].structure {
+                    Structure::String(name) => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the maps pairing registers with their values: the
+    /// `register_map`, and the local register map of every value that has
+    /// one.
+    pub fn register_maps<'a>(&'a self) -> impl Iterator<Item = ArenaKey> + 'a {
+        std::iter::once(self.register_map).chain(self.local_register_maps.values().copied())
+    }
+
+    /// Returns the map holding the local registers of `owner`, if any have
+    /// been bound while it was bound to `.`.
+    pub fn local_registers(&self, owner: ArenaKey) -> Option<ArenaKey> {
+        self.local_register_maps.get(&owner).copied()
+    }
+
+    /// Returns the map holding the local registers of `owner`, creating it
+    /// if `owner` doesn't have one yet.
+    fn local_register_map(&mut self, owner: ArenaKey) -> ArenaKey {
+        match self.local_register_maps.get(&owner) {
+            Some(&map) => map,
+            None => {
+                let map = insert_map(&mut self.slot_map, &mut self.uuid_map, HashMap::new());
+                self.local_register_maps.insert(owner, map);
+                map
+            }
+        }
+    }
+
+    /// Binds the [local register](is_local_register) `register` of `owner` to
+    /// `value`, whatever is bound to `.`. Returns [`None`], changing nothing,
+    /// if `register` isn't local or `owner` or `value` don't exist.
+    pub fn bind_local_register(
+        &mut self,
+        owner: ArenaKey,
+        register: &str,
+        value: ArenaKey,
     ) -> Option<()> {
-        let list_register = insert_string(
+        if !is_local_register(register)
+            || !self.slot_map.contains_key(owner)
+            || !self.slot_map.contains_key(value)
+        {
+            return None;
+        }
+        let map = self.local_register_map(owner);
+        let register = insert_string(
+            &mut self.slot_map,
+            &mut self.lookup_map,
+            &mut self.uuid_map,
+            register,
+        );
+        map_insert(&mut self.slot_map, map, register, value);
+        Some(())
+    }
+
+    /// Returns the list of the earlier versions of `key`, oldest first, if
+    /// any have been recorded. See [`Arena::record_version`].
+    pub fn versions(&self, key: ArenaKey) -> Option<ArenaKey> {
+        let map = *self.local_register_maps.get(&key)?;
+        let register = self.lookup_string(VERSIONS_REGISTER)?;
+        map_get(&self.slot_map, map, register)
+    }
+
+    /// Remembers that `version` holds what the string or set bound to `key`
+    /// held before it last changed, as its latest earlier version.
+    ///
+    /// Versions are kept in a list bound to the [`VERSIONS_REGISTER`] among
+    /// the local registers of `key`, so that they can be reached from it (and
+    /// are kept and exported along with it). Only the latest [`MAX_VERSIONS`]
+    /// are kept. Returns [`None`] if `key` isn't a string or set,
+    /// `version` isn't of the same kind, or `version` is a set with more than
+    /// [`MAX_VERSIONED_MEMBERS`] members. Nothing changes if `version` is
+    /// already one of the versions of `key`.
+    pub fn record_version(&mut self, key: ArenaKey, version: ArenaKey) -> Option<()> {
+        let kind = self.slot_map.get(key)?.structure.kind();
+        let too_large = matches!(
+            &self.slot_map.get(version)?.structure,
+            Structure::Set(members) if members.len() > MAX_VERSIONED_MEMBERS
+        );
+        if !matches!(kind, Kind::String | Kind::Set)
+            || self.slot_map[version].structure.kind() != kind
+            || key == version
+            || too_large
+        {
+            return None;
+        }
+        let list = match self.versions(key) {
+            Some(list) => list,
+            None => {
+                let map = self.local_register_map(key);
+                let register = insert_string(
+                    &mut self.slot_map,
+                    &mut self.lookup_map,
+                    &mut self.uuid_map,
+                    VERSIONS_REGISTER,
+                );
+                let list = insert_list(&mut self.slot_map, &mut self.uuid_map, vec![]);
+                map_insert(&mut self.slot_map, map, register, list);
+                list
+            }
+        };
+        let len = self.list_len(list)?;
+        if self.elements(list)?.contains(&version) {
+            return Some(());
+        }
+        self.leaf_counts.get_mut().clear();
+        list_push(&mut self.slot_map, list, version);
+        let excess = (len + 1).saturating_sub(MAX_VERSIONS);
+        if excess > 0 {
+            list_edit(&mut self.slot_map, list, 0..len + 1, |vec| {
+                vec.drain(..excess);
+            });
+        }
+        Some(())
+    }
+
+    /// Returns true if `version` is one of the earlier versions of `key`, and
+    /// isn't kept anywhere else, so that its contents can be handed over.
+    fn is_only_a_version_of(&self, version: ArenaKey, key: ArenaKey) -> bool {
+        match (self.versions(key), self.slot_map.get(version)) {
+            (Some(list), Some(value)) => {
+                !value.inclusions.is_empty()
+                    && value
+                        .inclusions
+                        .iter()
+                        .all(|&(container, _)| container == list)
+            }
+            _ => false,
+        }
+    }
+
+    /// Puts back `version`, one of the earlier versions of the string or set
+    /// bound to `key` (see [`Arena::versions`]), by swapping their contents:
+    /// `version` then holds what it replaced, so that restoring it again
+    /// undoes the restore. Returns [`None`], changing nothing, if `version`
+    /// isn't one of them or is also kept somewhere else, since its contents
+    /// would change there too.
+    pub fn restore_version(&mut self, key: ArenaKey, version: ArenaKey) -> Option<()> {
+        if !self.is_only_a_version_of(version, key) {
+            return None;
+        }
+        match (
+            &self.slot_map[key].structure,
+            &self.slot_map[version].structure,
+        ) {
+            (Structure::String(_), Structure::String(text)) => {
+                let edit = vec![(key, text.to_string())];
+                if self.rewrite_strings(edit).is_empty() {
+                    None
+                } else {
+                    Some(())
+                }
+            }
+            (Structure::Set(ours), Structure::Set(theirs)) => {
+                let ours: Vec<ArenaKey> = ours.iter().copied().collect();
+                let theirs: Vec<ArenaKey> = theirs.iter().copied().collect();
+                self.leaf_counts.get_mut().clear();
+                for &member in &ours {
+                    set_remove(&mut self.slot_map, key, member);
+                }
+                for &member in &theirs {
+                    set_remove(&mut self.slot_map, version, member);
+                }
+                for &member in &theirs {
+                    set_insert(&mut self.slot_map, key, member);
+                }
+                for &member in &ours {
+                    set_insert(&mut self.slot_map, version, member);
+                }
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the value bound to `register`, resolving it with
+    /// [`Arena::resolve_register`].
+    fn lookup_register(&mut self, register: String) -> Option<ArenaKey> {
+        let (map, register) = self.resolve_register(register, false)?;
+        map_get(&self.slot_map, map, register)
+    }
+
+    /// Binds `register` to `value`, resolving it with
+    /// [`Arena::resolve_register`].
+    fn bind_resolved_register(&mut self, register: String, value: ArenaKey) {
+        // `.` is always bound, so this never fails.
+        let (map, register) = self.resolve_register(register, true).unwrap();
+        self.leaf_counts.get_mut().clear();
+        map_insert(&mut self.slot_map, map, register, value);
+    }
+
+    pub fn register<S: Into<String>>(&mut self, register: S) -> Option<ArenaKey> {
+        self.lookup_register(register.into())
+    }
+
+    pub fn bind_register<S: Into<String>>(&mut self, register: S, value: ArenaKey) {
+        self.bind_resolved_register(register.into(), value);
+    }
+
+    /// Leaves `register` bound to nothing, resolving it with
+    /// [`Arena::resolve_register`]. Returns [`None`] if it wasn't bound.
+    pub fn unbind_register<S: Into<String>>(&mut self, register: S) -> Option<()> {
+        let (map, register) = self.resolve_register(register.into(), false)?;
+        map_get(&self.slot_map, map, register)?;
+        self.leaf_counts.get_mut().clear();
+        map_remove(&mut self.slot_map, map, register);
+        Some(())
+    }
+
+    pub fn bind_register_to_empty_set<S: Into<String>>(&mut self, register: S) {
+        let set = insert_set(&mut self.slot_map, &mut self.uuid_map, HashSet::new());
+        self.bind_resolved_register(register.into(), set);
+    }
+
+    pub fn bind_register_to_empty_list<S: Into<String>>(&mut self, register: S) {
+        let list = insert_list(&mut self.slot_map, &mut self.uuid_map, vec![]);
+        self.bind_resolved_register(register.into(), list);
+    }
+
+    pub fn bind_register_to_string<S: Into<String>>(&mut self, register: S, string: S) {
+        let string = insert_string(
             &mut self.slot_map,
             &mut self.lookup_map,
-            list_register.into(),
+            &mut self.uuid_map,
+            string.into(),
         );
-        let value_register = insert_string(
+        self.bind_resolved_register(register.into(), string);
+    }
+
+    pub fn bind_register_to_register_value<S: Into<String>>(
+        &mut self,
+        to_be_binded: S,
+        to_lookup: S,
+    ) {
+        if let Some(k) = self.lookup_register(to_lookup.into()) {
+            self.bind_resolved_register(to_be_binded.into(), k);
+        }
+    }
+
+    /// Inserts the value bound to `value_register` at the end of the list
+    /// bound to `list_register`. Returns [`None`], changing nothing, if either
+    /// register is unbound or `list_register` isn't bound to a list.
+    pub fn list_push<S: Into<String>>(
+        &mut self,
+        list_register: S,
+        value_register: S,
+    ) -> Option<()> {
+        let list = self.lookup_register(list_register.into())?;
+        let value = self.lookup_register(value_register.into())?;
+        self.list_len(list)?;
+
+        self.leaf_counts.get_mut().clear();
+        list_push(&mut self.slot_map, list, value);
+
+        Some(())
+    }
+
+    /// Removes the last element of the list bound to `list_register`, if it
+    /// has one. Returns [`None`], changing nothing, if `list_register` is
+    /// unbound or isn't bound to a list.
+    pub fn list_pop<S: Into<String>>(&mut self, list_register: S) -> Option<()> {
+        let list = self.lookup_register(list_register.into())?;
+        self.list_len(list)?;
+
+        self.leaf_counts.get_mut().clear();
+        list_pop(&mut self.slot_map, list);
+
+        Some(())
+    }
+
+    /// Inserts `string` into the set bound to `set_register`. Returns
+    /// [`None`], changing nothing, if `set_register` is unbound or isn't bound
+    /// to a set.
+    pub fn set_insert_string<S: Into<String>>(&mut self, set_register: S, string: S) -> Option<()> {
+        let set = self.lookup_register(set_register.into())?;
+        self.set_len(set)?;
+        let string = insert_string(
             &mut self.slot_map,
             &mut self.lookup_map,
-            value_register.into(),
+            &mut self.uuid_map,
+            string.into(),
         );
 
-        let list = map_get(&self.slot_map, self.register_map, list_register)?;
-        let value = map_get(&self.slot_map, self.register_map, value_register)?;
+        self.leaf_counts.get_mut().clear();
+        set_insert(&mut self.slot_map, set, string);
+
+        Some(())
+    }
+
+    /// Inserts `number` into the set bound to `set_register`. See
+    /// [`crate::number`]. Returns [`None`], changing nothing, if
+    /// `set_register` is unbound or isn't bound to a set.
+    pub fn set_insert_number<S: Into<String>>(
+        &mut self,
+        set_register: S,
+        number: f64,
+    ) -> Option<()> {
+        let set = self.lookup_register(set_register.into())?;
+        self.set_len(set)?;
+        let number = self.number(number);
+        self.leaf_counts.get_mut().clear();
+        set_insert(&mut self.slot_map, set, number);
+        Some(())
+    }
+
+    /// Inserts `string` into the set bound to the [`INBOX_REGISTER`]. If the
+    /// inbox register isn't bound to a set, it is first bound to an empty one.
+    pub fn capture_string<S: Into<String>>(&mut self, string: S) {
+        self.collect_string(INBOX_REGISTER, string.into());
+    }
+
+    /// Inserts the text of a query into the set bound to the
+    /// [`QUERIES_REGISTER`], like [`Arena::capture_string`].
+    pub fn save_query<S: Into<String>>(&mut self, query: S) {
+        self.collect_string(QUERIES_REGISTER, query.into());
+    }
+
+    /// Pins `value` to `slot`, replacing whatever was pinned there before.
+    /// Pins are bound to registers (see [`pin_register`]), so they are kept
+    /// alongside everything else in the arena. Returns [`None`] if there is no
+    /// such slot.
+    pub fn pin(&mut self, slot: usize, value: ArenaKey) -> Option<()> {
+        if (1..=PIN_SLOTS).contains(&slot) {
+            self.bind_register(pin_register(slot), value);
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value pinned to `slot`, if there is one. Unlike looking
+    /// up its register, this doesn't insert the name of the slot if it has
+    /// never been used, so that it doesn't leave an [orphan](Arena::orphans)
+    /// behind.
+    pub fn pinned(&self, slot: usize) -> Option<ArenaKey> {
+        if (1..=PIN_SLOTS).contains(&slot) {
+            let name = self.lookup_string(&pin_register(slot))?;
+            map_get(&self.slot_map, self.register_map, name)
+        } else {
+            None
+        }
+    }
+
+    /// Returns every pinned value alongside the slot it is pinned to, in
+    /// order of their slots.
+    pub fn pins(&self) -> Vec<(usize, ArenaKey)> {
+        (1..=PIN_SLOTS)
+            .filter_map(|slot| Some((slot, self.pinned(slot)?)))
+            .collect()
+    }
+
+    /// Stars `value` if it isn't starred, and unstars it otherwise. Starred
+    /// values are the members of the set bound to the [`STARRED_REGISTER`],
+    /// which is bound to an empty set first if it isn't bound to a set.
+    /// Returns true if `value` is starred afterwards.
+    pub fn toggle_star(&mut self, value: ArenaKey) -> bool {
+        let starred = self.collection(STARRED_REGISTER);
+        let contained = self.set_contains(starred, value).unwrap();
+        self.leaf_counts.get_mut().clear();
+        if contained {
+            set_remove(&mut self.slot_map, starred, value);
+        } else {
+            set_insert(&mut self.slot_map, starred, value);
+        }
+        !contained
+    }
+
+    /// Returns true if `value` is starred. See [`Arena::toggle_star`].
+    pub fn is_starred(&self, value: ArenaKey) -> bool {
+        self.lookup_string(STARRED_REGISTER)
+            .and_then(|register| map_get(&self.slot_map, self.register_map, register))
+            .and_then(|starred| self.set_contains(starred, value))
+            .unwrap_or(false)
+    }
+
+    /// Tags `value` with `tag`. Tags are kept in the map bound to the
+    /// [`TAGS_REGISTER`], which pairs the name of each tag (see [`tag_name`])
+    /// with the set of values tagged with it. The register is bound to an
+    /// empty map first if it isn't bound to a map. Returns [`None`], changing
+    /// nothing, if `tag` has no name.
+    pub fn tag(&mut self, value: ArenaKey, tag: &str) -> Option<()> {
+        let name = tag_name(tag);
+        if name.is_empty() {
+            return None;
+        }
+        let map = match self.tag_map() {
+            Some((map, _)) => map,
+            None => {
+                let map = insert_map(&mut self.slot_map, &mut self.uuid_map, HashMap::new());
+                self.bind_register(TAGS_REGISTER, map);
+                map
+            }
+        };
+        let name = self.string(name);
+        let tagged = match map_get(&self.slot_map, map, name) {
+            Some(set) if matches!(self.slot_map[set].structure, Structure::Set(_)) => set,
+            _ => {
+                let set = insert_set(&mut self.slot_map, &mut self.uuid_map, HashSet::new());
+                map_insert(&mut self.slot_map, map, name, set);
+                set
+            }
+        };
+        self.leaf_counts.get_mut().clear();
+        set_insert(&mut self.slot_map, tagged, value);
+        Some(())
+    }
+
+    /// Removes `tag` from `value`, forgetting the tag altogether once nothing
+    /// is tagged with it. Returns [`None`], changing nothing, if `value`
+    /// isn't tagged with `tag`.
+    pub fn untag(&mut self, value: ArenaKey, tag: &str) -> Option<()> {
+        let name = self.lookup_string(tag_name(tag))?;
+        let (map, tags) = self.tag_map()?;
+        let tagged = *tags.get(&name)?;
+        if !self.set_contains(tagged, value)? {
+            return None;
+        }
+        self.leaf_counts.get_mut().clear();
+        set_remove(&mut self.slot_map, tagged, value);
+        let forgotten = match &self.slot_map[tagged].structure {
+            Structure::Set(members) => members.is_empty(),
+            _ => false,
+        };
+        if forgotten {
+            map_remove(&mut self.slot_map, map, name);
+        }
+        Some(())
+    }
+
+    /// Returns the names of the tags `value` is tagged with, in sorted order.
+    /// See [`Arena::tag`].
+    pub fn tags(&self, value: ArenaKey) -> Vec<&str> {
+        let mut names = match self.tag_map() {
+            Some((_, tags)) => tags
+                .iter()
+                .filter(|&(_, &tagged)| self.set_contains(tagged, value) == Some(true))
+                .filter_map(|(&name, _)| match &self.slot_map[name].structure {
+                    Structure::String(name) => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect(),
+            None => vec![],
+        };
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the set of values tagged with `tag`, if anything has been.
+    /// See [`Arena::tag`].
+    pub fn tag_set(&self, tag: &str) -> Option<ArenaKey> {
+        let name = self.lookup_string(tag_name(tag))?;
+        self.tag_map()?.1.get(&name).copied()
+    }
+
+    /// Returns every value tagged with `tag`. See [`Arena::tag`].
+    pub fn tagged(&self, tag: &str) -> Vec<ArenaKey> {
+        let tagged = self.tag_set(tag);
+        match tagged.map(|tagged| &self.slot_map[tagged].structure) {
+            Some(Structure::Set(members)) => members.iter().copied().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns the map bound to the [`TAGS_REGISTER`], if it is bound to a
+    /// map.
+    fn tag_map(&self) -> Option<(ArenaKey, &HashMap<ArenaKey, ArenaKey>)> {
+        let map = self
+            .lookup_string(TAGS_REGISTER)
+            .and_then(|register| map_get(&self.slot_map, self.register_map, register))?;
+        match &self.slot_map[map].structure {
+            Structure::Map(tags) => Some((map, tags)),
+            _ => None,
+        }
+    }
+
+    /// Starts stamping values created from now on with [`Metadata`] saying
+    /// when they were created, and that `author` created them. Values that
+    /// don't have metadata yet are stamped with metadata that doesn't say
+    /// either, so that they aren't mistaken for new ones.
+    ///
+    /// New values are stamped by [`Arena::stamp_metadata`], rather than as
+    /// they are inserted, so it should be called after anything that might
+    /// insert values.
+    pub fn record_metadata(&mut self, author: Option<String>) {
+        for value in self.slot_map.values_mut() {
+            if value.metadata.is_none() {
+                value.metadata = Some(Box::new(Metadata::default()));
+            }
+        }
+        self.recording = Some(Metadata {
+            created: None,
+            author,
+        });
+        self.slot_map.unstamped.get_or_insert_with(Vec::new);
+    }
+
+    /// Stamps every value inserted since this was last called that doesn't
+    /// have [`Metadata`] yet as having been created at `now` by the author
+    /// given to [`Arena::record_metadata`], returning how many were stamped.
+    /// Does nothing unless metadata is being recorded.
+    pub fn stamp_metadata(&mut self, now: SystemTime) -> usize {
+        let metadata = match &self.recording {
+            Some(recording) => Metadata {
+                created: Some(now),
+                ..recording.clone()
+            },
+            None => return 0,
+        };
+        let unstamped = match &mut self.slot_map.unstamped {
+            Some(unstamped) => std::mem::take(unstamped),
+            None => return 0,
+        };
+        let mut stamped = 0;
+        for key in unstamped {
+            match self.slot_map.get_mut(key) {
+                Some(value) if value.metadata.is_none() => {
+                    value.metadata = Some(Box::new(metadata.clone()));
+                    stamped += 1;
+                }
+                _ => {}
+            }
+        }
+        stamped
+    }
+
+    /// Keeps [`Arena::stamp_metadata`] from stamping `key`, like when it was
+    /// only inserted to tell the user something rather than by them.
+    pub fn leave_unstamped(&mut self, key: ArenaKey) {
+        if let Some(unstamped) = &mut self.slot_map.unstamped {
+            unstamped.retain(|&unstamped| unstamped != key);
+        }
+    }
+
+    /// Returns when the value bound to `key` was created, and who by, if that
+    /// was recorded.
+    pub fn metadata(&self, key: ArenaKey) -> Option<&Metadata> {
+        self.slot_map.get(key)?.metadata.as_deref()
+    }
+
+    /// Replaces the metadata of the value bound to `key`, like when it is
+    /// recreated from somewhere outside of this arena. Returns [`None`] if
+    /// `key` is not in the arena.
+    pub fn set_metadata(&mut self, key: ArenaKey, metadata: Option<Metadata>) -> Option<()> {
+        self.slot_map.get_mut(key)?.metadata = metadata.map(Box::new);
+        Some(())
+    }
+
+    /// Returns every value that was created by `author`, according to its
+    /// [`Metadata`].
+    pub fn authored_by(&self, author: &str) -> Vec<ArenaKey> {
+        self.slot_map
+            .iter()
+            .filter(|(_, value)| {
+                value
+                    .metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata.author.as_deref())
+                    == Some(author)
+            })
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Starts (or stops) tracking how often and how recently values are
+    /// visited. Stopping throws away everything tracked so far, but leaves the
+    /// set bound to the [`RECENT_REGISTER`] as it is.
+    pub fn set_usage_tracking(&mut self, track: bool) {
+        match (track, &self.usage) {
+            (true, None) => self.usage = Some(HashMap::new()),
+            (false, _) => {
+                self.usage = None;
+                self.visits_recorded = 0;
+            }
+            (true, Some(_)) => {}
+        }
+    }
+
+    pub fn is_tracking_usage(&self) -> bool {
+        self.usage.is_some()
+    }
+
+    /// Returns how many visits have been recorded since usage started being
+    /// tracked. See [`Usage::last_visited`].
+    pub fn visits_recorded(&self) -> u64 {
+        self.visits_recorded
+    }
+
+    /// Returns how often and how recently the value bound to `key` has been
+    /// visited. Values are never visited while usage isn't being tracked.
+    pub fn usage(&self, key: ArenaKey) -> Usage {
+        self.usage
+            .as_ref()
+            .and_then(|usage| usage.get(&key))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Records that the value bound to `key` was visited, if usage is being
+    /// tracked (see [`Arena::set_usage_tracking`]), and puts it in the set
+    /// bound to the [`RECENT_REGISTER`]. That set holds the [`RECENT_LIMIT`]
+    /// most recently visited values, so the least recently visited one is
+    /// taken out of it when it is full.
+    ///
+    /// Visits to the set of recent values itself aren't recorded, so that it
+    /// never contains itself.
+    pub fn record_visit(&mut self, key: ArenaKey) {
+        if self.usage.is_none() || self.register(RECENT_REGISTER) == Some(key) {
+            return;
+        }
+        self.visits_recorded += 1;
+        let visits_recorded = self.visits_recorded;
+        let usage = self.usage.as_mut().unwrap().entry(key).or_default();
+        usage.visits += 1;
+        usage.last_visited = visits_recorded;
+        let recent = self.collection(RECENT_REGISTER);
+        self.leaf_counts.get_mut().clear();
+        set_insert(&mut self.slot_map, recent, key);
+        let members = match &self.slot_map[recent].structure {
+            Structure::Set(members) => members.iter().copied().collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        if members.len() > RECENT_LIMIT {
+            let least_recent = members
+                .into_iter()
+                .min_by_key(|&member| self.usage(member).last_visited)
+                .unwrap();
+            set_remove(&mut self.slot_map, recent, least_recent);
+        }
+    }
+
+    /// Records that the value bound to `key` changed at `now`, putting it in
+    /// the set bound to the [`CHANGED_REGISTER`]. Values stay in that set for
+    /// as long as the [`ChangeWindow`] says, after which
+    /// [`Arena::evict_changes`] takes them out again.
+    ///
+    /// Changes to the set of changed values itself aren't recorded, so that it
+    /// never contains itself.
+    pub fn record_change(&mut self, key: ArenaKey, now: Instant) {
+        let changed = self.collection(CHANGED_REGISTER);
+        if key == changed {
+            return;
+        }
+        self.changes_recorded += 1;
+        self.changes.insert(key, (self.changes_recorded, now));
+        self.leaf_counts.get_mut().clear();
+        set_insert(&mut self.slot_map, changed, key);
+        self.evict_changes(now);
+    }
 
-        list_push(&mut self.slot_map, list, value);
+    /// Takes the values that changed too long ago (see [`ChangeWindow`]) out
+    /// of the set bound to the [`CHANGED_REGISTER`]. Returns true if any were
+    /// taken out.
+    pub fn evict_changes(&mut self, now: Instant) -> bool {
+        let (window, recorded) = (self.change_window, self.changes_recorded);
+        let expired: Vec<ArenaKey> = self
+            .changes
+            .iter()
+            .filter(|(_, &(number, at))| match window {
+                ChangeWindow::Changes(changes) => recorded - number >= changes,
+                ChangeWindow::Time(duration) => now.saturating_duration_since(at) >= duration,
+            })
+            .map(|(&key, _)| key)
+            .collect();
+        if expired.is_empty() {
+            return false;
+        }
+        let changed = self.collection(CHANGED_REGISTER);
+        self.leaf_counts.get_mut().clear();
+        for key in expired {
+            self.changes.remove(&key);
+            set_remove(&mut self.slot_map, changed, key);
+        }
+        true
+    }
 
-        Some(())
+    /// Returns when [`Arena::evict_changes`] will next have something to take
+    /// out, if values are kept for a length of time rather than a number of
+    /// changes.
+    pub fn next_change_eviction(&self) -> Option<Instant> {
+        match self.change_window {
+            ChangeWindow::Changes(_) => None,
+            ChangeWindow::Time(duration) => {
+                self.changes.values().map(|&(_, at)| at + duration).min()
+            }
+        }
     }
 
-    pub fn list_pop<S: Into<String>>(
-        &mut self,
-        list_register: S,
-    ) -> Option<()> {
-        let list_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            list_register.into(),
-        );
+    pub fn set_change_window(&mut self, window: ChangeWindow) {
+        self.change_window = window;
+    }
 
-        let list = map_get(&self.slot_map, self.register_map, list_register)?;
+    /// Returns how the members of sets are arranged. See [`Arena::arrange`].
+    pub fn usage_order(&self) -> Option<UsageOrder> {
+        self.usage_order
+    }
 
-        list_pop(&mut self.slot_map, list);
+    pub fn set_usage_order(&mut self, order: Option<UsageOrder>) {
+        self.usage_order = order;
+    }
 
-        Some(())
+    /// Returns `members` in the order they should be arranged in, according to
+    /// [`Arena::usage_order`]. Members that are equally recent (or frequent)
+    /// keep the order they were given in.
+    pub fn arrange(&self, members: &HashSet<ArenaKey>) -> Vec<ArenaKey> {
+        let mut arranged: Vec<ArenaKey> = members.iter().copied().collect();
+        match self.usage_order {
+            Some(UsageOrder::Recency) => {
+                arranged.sort_by_key(|&member| std::cmp::Reverse(self.usage(member).last_visited))
+            }
+            Some(UsageOrder::Frequency) => {
+                arranged.sort_by_key(|&member| std::cmp::Reverse(self.usage(member).visits))
+            }
+            None => {}
+        }
+        arranged
     }
 
-    pub fn set_insert_string<S: Into<String>>(&mut self, set_register: S, string: S) -> Option<()> {
-        let set_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            set_register.into(),
-        );
-        let string = insert_string(&mut self.slot_map, &mut self.lookup_map, string.into());
+    /// Returns the set bound to `register`, first binding `register` to an
+    /// empty set if it isn't bound to a set.
+    fn collection(&mut self, register: &str) -> ArenaKey {
+        match self.register(register) {
+            Some(set) if matches!(self.slot_map[set].structure, Structure::Set(_)) => set,
+            _ => {
+                self.bind_register_to_empty_set(register);
+                self.register(register).unwrap()
+            }
+        }
+    }
+
+    /// Inserts `string` into the set bound to `register`, first binding
+    /// `register` to an empty set if it isn't bound to a set.
+    fn collect_string(&mut self, register: &str, string: String) {
+        self.collection(register);
+        self.set_insert_string(register.to_string(), string)
+            .unwrap();
+    }
 
-        let set = map_get(&self.slot_map, self.register_map, set_register)?;
+    /// Returns every string that `predicate` returns true for, in no
+    /// particular order.
+    pub fn strings_where<P: Fn(&str) -> bool>(&self, predicate: P) -> Vec<ArenaKey> {
+        self.keys_of_kind(Kind::String)
+            .into_iter()
+            .filter(|&key| match &self.slot_map[key].structure {
+                Structure::String(string) => predicate(string),
+                _ => false,
+            })
+            .collect()
+    }
 
-        set_insert(&mut self.slot_map, set, string);
+    /// Creates a new set of every string that `predicate` returns true for.
+    /// [`crate::query`] can find other kinds of values, and containers by
+    /// what they contain.
+    pub fn find_strings<P: Fn(&str) -> bool>(&mut self, predicate: P) -> ArenaKey {
+        let strings = self.strings_where(predicate);
+        self.set(strings)
+    }
 
-        Some(())
+    /// Creates a new set of every string containing `text`, ignoring case.
+    pub fn find_substring(&mut self, text: &str) -> ArenaKey {
+        let text = text.to_lowercase();
+        self.find_strings(|string| string.to_lowercase().contains(&text))
     }
 
-    pub fn set_insert_value<S: Into<String>>(&mut self, set_register: S, value: ArenaKey) -> Option<()> {
-        let set_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            set_register.into(),
-        );
-        let set = map_get(&self.slot_map, self.register_map, set_register)?;
+    /// Creates a new set of every string starting with `prefix`, ignoring
+    /// case.
+    pub fn find_prefix(&mut self, prefix: &str) -> ArenaKey {
+        let prefix = prefix.to_lowercase();
+        self.find_strings(|string| string.to_lowercase().starts_with(&prefix))
+    }
+
+    /// Returns the key of the string `string`, if it has been inserted.
+    pub fn lookup_string(&self, string: &str) -> Option<ArenaKey> {
+        lookup_hashed(
+            &self.slot_map,
+            &self.lookup_map,
+            ContentHash::of_string(string),
+            |structure| matches!(structure, Structure::String(s) if s.as_str() == string),
+        )
+    }
+
+    /// Returns the key of the string or file whose contents are exactly
+    /// `bytes`, if one has been inserted. Strings are looked for first, so
+    /// this is the same as [`Arena::lookup_string`] for text.
+    pub fn key_of_content(&self, bytes: &[u8]) -> Option<ArenaKey> {
+        std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|string| self.lookup_string(string))
+            .or_else(|| {
+                lookup_hashed(
+                    &self.slot_map,
+                    &self.lookup_map,
+                    ContentHash::of_blob(bytes),
+                    |structure| matches!(structure, Structure::Blob(blob) if blob.bytes == bytes),
+                )
+            })
+    }
+
+    /// Returns the [`ContentHash`] of `key`, or [`None`] if it isn't a leaf
+    /// (containers are told apart by their [`Value::uuid`] instead).
+    pub fn content_hash(&self, key: ArenaKey) -> Option<ContentHash> {
+        match &self.slot_map.get(key)?.structure {
+            Structure::String(string) => Some(ContentHash::of_string(string)),
+            Structure::Number(number) => Some(ContentHash::of_number(*number)),
+            Structure::Blob(blob) => Some(ContentHash::of_blob(&blob.bytes)),
+            Structure::Image(image) => Some(ContentHash::of_image(image)),
+            Structure::ImageRef(path) => Some(ContentHash::of_image_ref(path)),
+            Structure::Custom(custom) => Some(custom_hash(&*custom.leaf)),
+            _ => None,
+        }
+    }
+
+    /// Changes the contents of strings in place, keeping their keys, uuids and
+    /// the values containing them the same. Each edit pairs the key of a string
+    /// with its new contents. Returns the previous contents of the strings
+    /// that were changed, which can be passed back in to undo the edits.
+    ///
+    /// An edit is skipped if its key isn't bound to a string, or if its new
+    /// contents are already bound to another value, since strings are only
+    /// ever stored once. Edits are retried as others free up the contents they
+    /// need, so edits like `a -> b` and `b -> c` are both applied no matter
+    /// which comes first, but edits swapping two strings are skipped.
+    ///
+    /// The exception is a string that is only kept as an earlier version of
+    /// the string being edited (see [`Arena::versions`]): it takes over the
+    /// previous contents instead, so that undoing an edit keeps what it
+    /// undid as a version.
+    pub fn rewrite_strings(&mut self, edits: Vec<(ArenaKey, String)>) -> Vec<(ArenaKey, String)> {
+        let hash = ContentHash::of_string;
+        let mut pending: Vec<_> = edits
+            .into_iter()
+            .filter(|(key, _)| {
+                matches!(
+                    self.slot_map.get(*key).map(|value| &value.structure),
+                    Some(Structure::String(_))
+                )
+            })
+            .collect();
+        let mut previous = Vec::new();
+        loop {
+            let before = pending.len();
+            pending.retain(|(key, string)| {
+                let new_hash = hash(string);
+                let taken = self
+                    .lookup_map
+                    .get(&new_hash)
+                    .copied()
+                    .filter(|other| other != key);
+                if let Some(other) = taken {
+                    let same = matches!(
+                        &self.slot_map[other].structure,
+                        Structure::String(text) if **text == *string
+                    );
+                    if !same || !self.is_only_a_version_of(other, *key) {
+                        return true;
+                    }
+                }
+                let old = match &mut self.slot_map[*key].structure {
+                    Structure::String(old) => std::mem::replace(&mut **old, string.clone()),
+                    _ => unreachable!(),
+                };
+                let old_hash = hash(&old);
+                match taken {
+                    Some(other) => {
+                        if let Structure::String(version) = &mut self.slot_map[other].structure {
+                            **version = old.clone();
+                        }
+                        self.lookup_map.insert(old_hash, other);
+                        self.string_classes.get_mut().remove(&other);
+                        self.rewritten_strings.push(other);
+                    }
+                    None if self.lookup_map.get(&old_hash) == Some(key) => {
+                        self.lookup_map.remove(&old_hash);
+                    }
+                    None => {}
+                }
+                self.lookup_map.insert(new_hash, *key);
+                self.string_classes.get_mut().remove(key);
+                self.rewritten_strings.push(*key);
+                previous.push((*key, old));
+                false
+            });
+            if pending.is_empty() || pending.len() == before {
+                return previous;
+            }
+        }
+    }
+
+    /// Returns the keys of every value of kind `kind`.
+    pub fn keys_of_kind(&self, kind: Kind) -> Vec<ArenaKey> {
+        let mut index = self.kind_index.borrow_mut();
+        let (indexed, keys) = &mut *index;
+        if *indexed != self.slot_map.len() {
+            keys.clear();
+            for (key, value) in self.slot_map.iter() {
+                keys.entry(value.structure.kind()).or_default().push(key);
+            }
+            *indexed = self.slot_map.len();
+        }
+        keys.get(&kind).cloned().unwrap_or_default()
+    }
+
+    pub fn set_insert_value<S: Into<String>>(
+        &mut self,
+        set_register: S,
+        value: ArenaKey,
+    ) -> Option<()> {
+        let set = self.lookup_register(set_register.into())?;
+        self.set_len(set)?;
+        self.leaf_counts.get_mut().clear();
         set_insert(&mut self.slot_map, set, value);
         Some(())
     }
@@ -587,20 +4069,11 @@ impl Arena {
         set_register: S,
         insertion_register: S,
     ) -> Option<()> {
-        let set_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            set_register.into(),
-        );
-        let insertion_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            insertion_register.into(),
-        );
-
-        let set = map_get(&self.slot_map, self.register_map, set_register)?;
-        let insertion = map_get(&self.slot_map, self.register_map, insertion_register)?;
+        let set = self.lookup_register(set_register.into())?;
+        let insertion = self.lookup_register(insertion_register.into())?;
+        self.set_len(set)?;
 
+        self.leaf_counts.get_mut().clear();
         set_insert(&mut self.slot_map, set, insertion);
 
         Some(())
@@ -611,44 +4084,62 @@ impl Arena {
         set_register: S,
         removal_register: S,
     ) -> Option<()> {
-        let set_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            set_register.into(),
-        );
-        let removal_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            removal_register.into(),
-        );
-
-        let set = map_get(&self.slot_map, self.register_map, set_register)?;
-        let removal = map_get(&self.slot_map, self.register_map, removal_register)?;
+        let set = self.lookup_register(set_register.into())?;
+        let removal = self.lookup_register(removal_register.into())?;
+        self.set_len(set)?;
 
+        self.leaf_counts.get_mut().clear();
         set_remove(&mut self.slot_map, set, removal);
 
         Some(())
     }
 
+    /// Returns true if `value` is a member of `set`, or [`None`] if `set` isn't
+    /// a set.
+    pub fn set_contains(&self, set: ArenaKey, value: ArenaKey) -> Option<bool> {
+        match &self.slot_map.get(set)?.structure {
+            Structure::Set(members) => Some(members.contains(&value)),
+            _ => None,
+        }
+    }
+
+    /// Removes the value bound to `member_register` from the set bound to
+    /// `set_register` if it is a member, and inserts it otherwise. Returns
+    /// whether it is a member afterwards, or [`None`] (changing nothing) if
+    /// either register is unbound or `set_register` isn't bound to a set.
+    pub fn set_toggle<S: Into<String>>(
+        &mut self,
+        set_register: S,
+        member_register: S,
+    ) -> Option<bool> {
+        let set = self.lookup_register(set_register.into())?;
+        let member = self.lookup_register(member_register.into())?;
+        let contained = self.set_contains(set, member)?;
+
+        self.leaf_counts.get_mut().clear();
+        if contained {
+            set_remove(&mut self.slot_map, set, member);
+        } else {
+            set_insert(&mut self.slot_map, set, member);
+        }
+
+        Some(!contained)
+    }
+
     pub fn set_union<S: Into<String>>(
         &mut self,
         set_modified_register: S,
         set_other_register: S,
     ) -> Option<()> {
-        let set_modified_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            set_modified_register.into(),
-        );
-        let set_other_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            set_other_register.into(),
-        );
-
-        let set_modified = map_get(&self.slot_map, self.register_map, set_modified_register)?;
-        let set_other = map_get(&self.slot_map, self.register_map, set_other_register)?;
+        let set_modified = self.lookup_register(set_modified_register.into())?;
+        let set_other = self.lookup_register(set_other_register.into())?;
+        self.set_len(set_modified)?;
+        self.set_len(set_other)?;
+        if set_modified == set_other {
+            return Some(());
+        }
 
+        self.leaf_counts.get_mut().clear();
         set_union(&mut self.slot_map, set_modified, set_other);
 
         Some(())
@@ -659,74 +4150,998 @@ impl Arena {
         set_modified_register: S,
         set_other_register: S,
     ) -> Option<()> {
-        let set_modified_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            set_modified_register.into(),
-        );
-        let set_other_register = insert_string(
-            &mut self.slot_map,
-            &mut self.lookup_map,
-            set_other_register.into(),
-        );
-
-        let set_modified = map_get(&self.slot_map, self.register_map, set_modified_register)?;
-        let set_other = map_get(&self.slot_map, self.register_map, set_other_register)?;
+        let set_modified = self.lookup_register(set_modified_register.into())?;
+        let set_other = self.lookup_register(set_other_register.into())?;
+        self.set_len(set_modified)?;
+        self.set_len(set_other)?;
+        if set_modified == set_other {
+            let members: Vec<ArenaKey> = match &self.slot_map[set_modified].structure {
+                Structure::Set(members) => members.iter().copied().collect(),
+                _ => unreachable!(),
+            };
+            self.leaf_counts.get_mut().clear();
+            for member in members {
+                set_remove(&mut self.slot_map, set_modified, member);
+            }
+            return Some(());
+        }
 
+        self.leaf_counts.get_mut().clear();
         set_difference(&mut self.slot_map, set_modified, set_other);
 
         Some(())
     }
 }
 
-// pub fn naming_example() -> (Self, ArenaKey) {
-//     let mut arena = Arena::new();
-
-//     let consonant_set = {
-//         let consonants = [
-//             "b", "c", "d", "f", "g", "h", "j", "k", "l", "m", "n", "p", "q", "r", "s", "t",
-//             "v", "w", "x", "y", "z",
-//         ]
-//         .iter()
-//         .map(|&s| arena.insert(Structure::String(s.into())).unwrap())
-//         .collect();
-//         arena.insert(Structure::Set(consonants)).unwrap()
-//     };
-
-//     let vowel_set = {
-//         let vowels = ["a", "e", "i", "o", "u"]
-//             .iter()
-//             .map(|&s| arena.insert(Structure::String(s.into())).unwrap())
-//             .collect();
-//         arena.insert(Structure::Set(vowels)).unwrap()
-//     };
-
-//     let kakoi_set = {
-//         let kakoi_example_1 = {
-//             let kakoi_example_1 =
-//                 include_bytes!("resources/images/Kakoi Example 1 [senseis.xmp.net].png");
-//             image::load_from_memory(kakoi_example_1)
-//                 .unwrap()
-//                 .into_rgba8()
-//         };
-//         let kakoi_example_2 = {
-//             let kakoi_example_2 =
-//                 include_bytes!("resources/images/Kakoi Example 2 [senseis.xmp.net].png");
-//             image::load_from_memory(kakoi_example_2)
-//                 .unwrap()
-//                 .into_rgba8()
-//         };
-//         let kakoi_example_3 = {
-//             let kakoi_example_3 =
-//                 include_bytes!("resources/images/Kakoi Example 1 [senseis.xmp.net] wide.png");
-//             image::load_from_memory(kakoi_example_3)
-//                 .unwrap()
-//                 .into_rgba8()
-//         };
-//         let kakoi = vec![kakoi_example_1, kakoi_example_2, kakoi_example_3]
-//             .drain(..)
-//             .map(|s| arena.insert(Structure::Image(s)).unwrap())
-//             .collect();
-//         arena.insert(Structure::Set(kakoi)).unwrap()
-//     };
-// }
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn uuid_lookup() {
+        let mut arena = Arena::new();
+        let hello = arena.string("hello");
+        let uuid = arena.uuid(hello).unwrap();
+        assert_eq!(Some(hello), arena.lookup_uuid(&uuid));
+        assert_eq!(hello, arena.string("hello"));
+        assert_eq!(Some(uuid), arena.uuid(hello));
+    }
+
+    #[test]
+    fn uuid_restore() {
+        let mut arena = Arena::new();
+        let hello = arena.string("hello");
+        let world = arena.string("world");
+        let old_uuid = arena.uuid(hello).unwrap();
+        let new_uuid = Uuid::new_v4();
+        assert!(arena.restore_uuid(hello, new_uuid).is_some());
+        assert_eq!(Some(hello), arena.lookup_uuid(&new_uuid));
+        assert_eq!(None, arena.lookup_uuid(&old_uuid));
+        // uuids may not be shared between values
+        assert!(arena.restore_uuid(world, new_uuid).is_none());
+    }
+
+    #[test]
+    fn rewrite_strings() {
+        let mut arena = Arena::new();
+        let a = arena.string("a");
+        let b = arena.string("b");
+        let c = arena.string("c");
+        let set = arena.set(vec![a, b]);
+        let previous = arena.rewrite_strings(vec![(a, "b".to_string()), (b, "x".to_string())]);
+        assert_eq!(2, previous.len());
+        assert_eq!(&[b, a], arena.rewritten_strings());
+        assert_eq!(Some(a), arena.lookup_string("b"));
+        assert_eq!(Some(b), arena.lookup_string("x"));
+        assert_eq!(None, arena.lookup_string("a"));
+        assert_eq!(Some(true), arena.set_contains(set, a));
+        // swapping contents, or taking another string's contents, is refused
+        let skipped = arena.rewrite_strings(vec![(a, "x".to_string()), (b, "b".to_string())]);
+        assert!(skipped.is_empty());
+        assert!(arena.rewrite_strings(vec![(a, "c".to_string())]).is_empty());
+        assert_eq!(Some(c), arena.lookup_string("c"));
+        // undoing restores the original contents
+        arena.rewrite_strings(previous);
+        assert_eq!(Some(a), arena.lookup_string("a"));
+        assert_eq!(Some(b), arena.lookup_string("b"));
+        assert_eq!(None, arena.lookup_string("x"));
+    }
+
+    #[test]
+    fn changes() {
+        let mut arena = Arena::new();
+        let (a, b, c) = (arena.string("a"), arena.string("b"), arena.string("c"));
+        let now = Instant::now();
+        arena.set_change_window(ChangeWindow::Changes(2));
+        for &key in &[a, b, c] {
+            arena.record_change(key, now);
+        }
+        let changed = arena.register(CHANGED_REGISTER).unwrap();
+        assert_eq!(Some(false), arena.set_contains(changed, a));
+        assert_eq!(Some(true), arena.set_contains(changed, c));
+        arena.record_change(changed, now);
+        assert_eq!(Some(false), arena.set_contains(changed, changed));
+
+        arena.set_change_window(ChangeWindow::Time(Duration::from_secs(60)));
+        assert_eq!(
+            Some(now + Duration::from_secs(60)),
+            arena.next_change_eviction()
+        );
+        assert!(!arena.evict_changes(now + Duration::from_secs(59)));
+        assert!(arena.evict_changes(now + Duration::from_secs(60)));
+        assert_eq!(Some(false), arena.set_contains(changed, c));
+        assert_eq!(None, arena.next_change_eviction());
+
+        assert_eq!(Some(ChangeWindow::Changes(20)), ChangeWindow::parse("20"));
+        assert_eq!(
+            Some(ChangeWindow::Time(Duration::from_secs(900))),
+            ChangeWindow::parse("15m")
+        );
+        assert_eq!(None, ChangeWindow::parse("soon"));
+    }
+
+    #[test]
+    fn merge_into() {
+        let mut arena = Arena::new();
+        let (colour, color) = (arena.string("colour"), arena.string("color"));
+        let both = arena.set(vec![colour, color]);
+        let list = arena.list(vec![colour, colour]);
+        arena.bind_register("c", colour);
+        assert!(arena.merge_into(colour, color).is_some());
+        assert!(arena.slot_map[colour].inclusions.is_empty());
+        assert_eq!(Some(true), arena.set_contains(both, color));
+        assert_eq!(Some(false), arena.set_contains(both, colour));
+        match &arena.slot_map[list].structure {
+            Structure::List(list) => assert_eq!(&vec![color, color], &**list),
+            _ => unreachable!(),
+        }
+        assert_eq!(Some(color), arena.register("c"));
+        let uuid = arena.uuid(colour).unwrap();
+        assert_eq!(Resolution::Merged(color), arena.resolve_uuid(&uuid));
+        // merging again follows along
+        let colr = arena.string("colr");
+        arena.set_insert_many(both, vec![colr]);
+        assert!(arena.merge_into(color, colr).is_some());
+        assert_eq!(Resolution::Merged(colr), arena.resolve_uuid(&uuid));
+        assert_eq!(Resolution::Stale, arena.resolve_uuid(&Uuid::nil()));
+        // register names can't be merged
+        let name = arena.string("c");
+        assert!(arena.merge_into(name, color).is_none());
+        assert!(arena.merge_into(color, color).is_none());
+    }
+
+    #[test]
+    fn leaf_count() {
+        let mut arena = Arena::new();
+        let set = arena.register(".").unwrap();
+        assert_eq!(1, arena.leaf_count(set));
+        arena.set_insert_string(".", "hello").unwrap();
+        arena.set_insert_string(".", "world").unwrap();
+        assert_eq!(2, arena.leaf_count(set));
+        // A set containing itself doesn't count twice.
+        arena.set_insert(".", ".").unwrap();
+        assert_eq!(2, arena.leaf_count(set));
+    }
+
+    #[test]
+    fn capture_string() {
+        let mut arena = Arena::new();
+        // Anything that isn't a set is replaced by a new inbox.
+        arena.bind_register_to_string(INBOX_REGISTER, "not a set");
+        arena.capture_string("hello");
+        arena.capture_string("world");
+        let inbox = arena.register(INBOX_REGISTER).unwrap();
+        let hello = arena.string("hello");
+        let world = arena.string("world");
+        match &arena.slot_map[inbox].structure {
+            Structure::Set(set) => assert_eq!(
+                vec![hello, world].into_iter().collect::<HashSet<_>>(),
+                **set
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn set_replace() {
+        let mut arena = Arena::new();
+        let (a, b, c) = (arena.string("a"), arena.string("b"), arena.string("c"));
+        let set = arena.set(vec![a, b]);
+        let other = arena.set(vec![b, c]);
+        arena.set_replace(set, other).unwrap();
+        match &arena.slot_map[set].structure {
+            Structure::Set(members) => {
+                assert_eq!(vec![b, c].into_iter().collect::<HashSet<_>>(), **members)
+            }
+            _ => panic!(),
+        }
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+        assert_eq!(None, arena.set_replace(set, a));
+    }
+
+    #[test]
+    fn locked_subtrees() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_empty_set("outer");
+        arena.bind_register_to_empty_set("inner");
+        arena.set_insert("outer", "inner").unwrap();
+        arena.set_insert_string("inner", "leaf").unwrap();
+        let outer = arena.register("outer").unwrap();
+        let inner = arena.register("inner").unwrap();
+        let leaf = arena.string("leaf");
+        assert!(!arena.is_locked(leaf));
+        arena.set_locked(outer, true);
+        assert!(arena.is_locked(outer));
+        assert!(arena.is_locked(inner));
+        assert!(arena.is_locked(leaf));
+        arena.set_locked(inner, false);
+        assert!(arena.is_locked(inner));
+        assert!(!arena.is_locked_directly(inner));
+        arena.set_locked(outer, false);
+        assert!(!arena.is_locked(leaf));
+        // Values aren't inside of the registers they are bound to.
+        arena.set_locked(arena.register_map, true);
+        assert!(!arena.is_locked(outer));
+    }
+
+    #[test]
+    fn bulk_inserts() {
+        let mut arena = Arena::new();
+        let keys = arena.insert_strings(vec!["a", "b", "a"]);
+        assert_eq!(3, keys.len());
+        assert_eq!(keys[0], keys[2]);
+        assert_eq!(arena.string("b"), keys[1]);
+
+        let set = arena.register(".").unwrap();
+        assert!(arena.set_insert_many(keys[0], keys.clone()).is_none());
+        arena.set_insert_many(set, keys).unwrap();
+        match &arena.slot_map[set].structure {
+            Structure::Set(members) => assert_eq!(2, members.len()),
+            _ => panic!(),
+        }
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn positional_list_edits() {
+        let mut arena = Arena::new();
+        let [a, b, c, d] = [
+            arena.string("a"),
+            arena.string("b"),
+            arena.string("c"),
+            arena.string("d"),
+        ];
+        let list = arena.list(vec![a, b, a]);
+        let elements = |arena: &Arena| match &arena.slot_map[list].structure {
+            Structure::List(vec) => vec.to_vec(),
+            _ => panic!(),
+        };
+
+        arena.list_insert_at(list, 1, c).unwrap();
+        assert_eq!(vec![a, c, b, a], elements(&arena));
+        arena.list_insert_at(list, 4, d).unwrap();
+        assert_eq!(vec![a, c, b, a, d], elements(&arena));
+        assert!(arena.list_insert_at(list, 6, d).is_none());
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+
+        arena.list_swap(list, 0, 4).unwrap();
+        assert_eq!(vec![d, c, b, a, a], elements(&arena));
+        arena.list_move(list, 1, 3).unwrap();
+        assert_eq!(vec![d, b, a, c, a], elements(&arena));
+        arena.list_move(list, 4, 0).unwrap();
+        assert_eq!(vec![a, d, b, a, c], elements(&arena));
+        assert!(arena.list_swap(list, 0, 5).is_none());
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+
+        assert_eq!(Some(d), arena.list_remove_at(list, 1));
+        assert_eq!(vec![a, b, a, c], elements(&arena));
+        assert_eq!(None, arena.list_remove_at(list, 4));
+        assert!(arena.list_remove_at(a, 0).is_none());
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+
+        let set = arena.set(vec![]);
+        assert!(arena.list_move_to_set(list, 0, b).is_none());
+        assert!(arena.list_move_to_set(list, 4, set).is_none());
+        arena.list_move_to_set(list, 1, set).unwrap();
+        assert_eq!(vec![a, a, c], elements(&arena));
+        assert_eq!(Some(true), arena.set_contains(set, b));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+
+        assert_eq!(Some(vec![a, a, c]), arena.list_replace(list, vec![c, d]));
+        assert_eq!(Some(&[c, d][..]), arena.elements(list));
+        assert_eq!(Some(vec![c, d]), arena.list_replace(list, vec![b, a, b]));
+        assert_eq!(vec![b, a, b], elements(&arena));
+        assert_eq!(None, arena.list_replace(set, vec![]));
+        assert_eq!(None, arena.elements(set));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn list_registers() {
+        let mut arena = Arena::new();
+        let a = arena.string("a");
+        arena.bind_register("a", a);
+        arena.bind_register_to_empty_list("l");
+        arena.list_push("l", "a").unwrap();
+        arena.list_push("l", "a").unwrap();
+        let list = arena.register("l").unwrap();
+        assert_eq!(Some(&[a, a][..]), arena.elements(list));
+        arena.list_pop("l").unwrap();
+        assert_eq!(Some(&[a][..]), arena.elements(list));
+        arena.bind_register_to_empty_set("s");
+        assert_eq!(None, arena.list_push("s", "a"));
+        assert_eq!(None, arena.list_pop("s"));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn arena_is_send() {
+        // See the threading model in the module-level documentation.
+        fn assert_send<T: Send>() {}
+        assert_send::<Arena>();
+    }
+
+    #[test]
+    fn set_toggle() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_string("a", "a");
+        let (set, a) = (arena.register(".").unwrap(), arena.register("a").unwrap());
+        assert_eq!(Some(false), arena.set_contains(set, a));
+        assert_eq!(Some(true), arena.set_toggle(".", "a"));
+        assert_eq!(Some(true), arena.set_contains(set, a));
+        assert_eq!(Some(false), arena.set_toggle(".", "a"));
+        assert_eq!(Some(false), arena.set_contains(set, a));
+        assert_eq!(None, arena.set_toggle("a", "."));
+        assert_eq!(None, arena.set_contains(a, set));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn set_operations_reject_non_sets() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_string("a", "a");
+        let a = arena.register("a").unwrap();
+        assert_eq!(None, arena.set_insert("a", "."));
+        assert_eq!(None, arena.set_remove("a", "."));
+        assert_eq!(None, arena.set_union("a", "."));
+        assert_eq!(None, arena.set_union(".", "a"));
+        assert_eq!(None, arena.set_insert_string("a", "b"));
+        assert_eq!(None, arena.set_insert_number("a", 1.0));
+        assert_eq!(Some(a), arena.register("a"));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn unbinding() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_string("a", "a");
+        assert_eq!(Some(()), arena.unbind_register("a"));
+        assert_eq!(None, arena.register("a"));
+        assert_eq!(None, arena.unbind_register("a"));
+        let a = arena.string("a");
+        let b = arena.string("b");
+        let map = arena.map(vec![(a, b)]);
+        assert_eq!(None, arena.map_remove(map, b));
+        assert_eq!(Some(()), arena.map_remove(map, a));
+        assert_eq!(Some(&HashMap::new()), arena.entries(map));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn stars() {
+        let mut arena = Arena::new();
+        let a = arena.string("a");
+        assert!(!arena.is_starred(a));
+        assert!(arena.toggle_star(a));
+        assert!(arena.is_starred(a));
+        assert!(!arena.toggle_star(a));
+        assert!(!arena.is_starred(a));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn tags() {
+        let mut arena = Arena::new();
+        let a = arena.string("a");
+        let b = arena.string("b");
+        assert_eq!(None, arena.tag(a, "#"));
+        arena.tag(a, "#todo").unwrap();
+        arena.tag(a, "idea").unwrap();
+        arena.tag(b, "todo").unwrap();
+        assert_eq!(vec!["idea", "todo"], arena.tags(a));
+        let mut todo = arena.tagged("#todo");
+        todo.sort();
+        let mut expected = vec![a, b];
+        expected.sort();
+        assert_eq!(expected, todo);
+
+        assert_eq!(None, arena.untag(b, "idea"));
+        arena.untag(a, "idea").unwrap();
+        assert_eq!(vec!["todo"], arena.tags(a));
+        // Tags are forgotten once nothing is tagged with them.
+        let tags = arena.register(TAGS_REGISTER).unwrap();
+        let idea = arena.string("idea");
+        assert_eq!(None, map_get(&arena.slot_map, tags, idea));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn metadata() {
+        let mut arena = Arena::new();
+        let old = arena.string("old");
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(60);
+        assert_eq!(0, arena.stamp_metadata(now));
+        assert_eq!(None, arena.metadata(old));
+
+        arena.record_metadata(Some("ada".into()));
+        assert_eq!(Some(&Metadata::default()), arena.metadata(old));
+        let new = arena.string("new");
+        let notice = arena.string("notice");
+        arena.leave_unstamped(notice);
+        assert_eq!(1, arena.stamp_metadata(now));
+        assert_eq!(0, arena.stamp_metadata(now));
+        assert_eq!(None, arena.metadata(notice));
+        let stamped = Metadata {
+            created: Some(now),
+            author: Some("ada".into()),
+        };
+        assert_eq!(Some(&stamped), arena.metadata(new));
+        assert_eq!(vec![new], arena.authored_by("ada"));
+    }
+
+    #[test]
+    fn usage() {
+        let mut arena = Arena::new();
+        let (a, b) = (arena.string("a"), arena.string("b"));
+        arena.record_visit(a);
+        assert_eq!(Usage::default(), arena.usage(a));
+        assert_eq!(None, arena.register(RECENT_REGISTER));
+        arena.set_usage_tracking(true);
+        arena.record_visit(a);
+        arena.record_visit(b);
+        arena.record_visit(a);
+        assert_eq!(2, arena.usage(a).visits);
+        assert!(arena.usage(a).last_visited > arena.usage(b).last_visited);
+        let members: HashSet<ArenaKey> = vec![a, b].into_iter().collect();
+        arena.set_usage_order(Some(UsageOrder::Recency));
+        assert_eq!(vec![a, b], arena.arrange(&members));
+        arena.record_visit(b);
+        assert_eq!(vec![b, a], arena.arrange(&members));
+        arena.set_usage_order(Some(UsageOrder::Frequency));
+        arena.record_visit(a);
+        assert_eq!(vec![a, b], arena.arrange(&members));
+        let recent = arena.register(RECENT_REGISTER).unwrap();
+        assert_eq!(Some(true), arena.set_contains(recent, b));
+        arena.record_visit(recent);
+        assert_eq!(Some(false), arena.set_contains(recent, recent));
+        for i in 0..RECENT_LIMIT {
+            let value = arena.string(&i.to_string());
+            arena.record_visit(value);
+        }
+        assert_eq!(Some(false), arena.set_contains(recent, b));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn pins() {
+        let mut arena = Arena::new();
+        let (a, b) = (arena.string("a"), arena.string("b"));
+        assert!(arena.pin(0, a).is_none());
+        assert!(arena.pin(PIN_SLOTS + 1, a).is_none());
+        arena.pin(3, a).unwrap();
+        arena.pin(1, b).unwrap();
+        arena.pin(3, b).unwrap();
+        assert_eq!(vec![(1, b), (3, b)], arena.pins());
+        assert_eq!(None, arena.pinned(2));
+        assert_eq!(vec!["pin 1", "pin 3"], arena.register_names(b));
+    }
+
+    #[test]
+    fn set_move() {
+        let mut arena = Arena::new();
+        let (a, b) = (arena.string("a"), arena.string("b"));
+        let from = arena.set(vec![a, b]);
+        let to = arena.set(vec![b]);
+        arena.set_move(a, from, to).unwrap();
+        let members = |arena: &Arena, set| match &arena.slot_map[set].structure {
+            Structure::Set(members) => members.iter().copied().collect::<HashSet<_>>(),
+            _ => panic!(),
+        };
+        assert_eq!(
+            vec![b].into_iter().collect::<HashSet<_>>(),
+            members(&arena, from)
+        );
+        assert_eq!(
+            vec![a, b].into_iter().collect::<HashSet<_>>(),
+            members(&arena, to)
+        );
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+        assert_eq!(None, arena.set_move(a, from, to));
+        assert_eq!(None, arena.set_move(a, to, to));
+        assert_eq!(None, arena.set_move(a, to, a));
+    }
+
+    #[test]
+    fn set_change() {
+        let mut arena = Arena::new();
+        let (a, b, c) = (arena.string("a"), arena.string("b"), arena.string("c"));
+        let set = arena.set(vec![a, b]);
+        arena.set_change(set, &[c], &[a]).unwrap();
+        match &arena.slot_map[set].structure {
+            Structure::Set(members) => assert_eq!(
+                vec![b, c].into_iter().collect::<HashSet<_>>(),
+                members.iter().copied().collect()
+            ),
+            _ => panic!(),
+        }
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+        assert_eq!(None, arena.set_change(a, &[b], &[]));
+    }
+
+    #[test]
+    fn set_flatten_once() {
+        let mut arena = Arena::new();
+        let (a, b, c) = (arena.string("a"), arena.string("b"), arena.string("c"));
+        let inner = arena.set(vec![b, c]);
+        let nested = arena.set(vec![a, inner]);
+        let outer = arena.set(vec![a, b, nested]);
+        let flattened = arena.set_flatten_once(outer).unwrap();
+        match &arena.slot_map[flattened].structure {
+            Structure::Set(members) => assert_eq!(
+                vec![a, b, inner].into_iter().collect::<HashSet<_>>(),
+                **members
+            ),
+            _ => panic!(),
+        }
+        assert_eq!(None, arena.set_flatten_once(a));
+    }
+
+    #[test]
+    fn local_registers() {
+        let mut arena = Arena::new();
+        let first = arena.register(".").unwrap();
+        let second = arena.string("second");
+        assert_eq!(None, arena.register(",a"));
+        arena.bind_register(",a", second);
+        arena.bind_register("a", first);
+        assert_eq!(Some(second), arena.register(",a"));
+        assert!(arena.local_registers(first).is_some());
+
+        arena.bind_register(".", second);
+        assert_eq!(None, arena.register(",a"));
+        arena.bind_register(",a", first);
+        assert_eq!(Some(first), arena.register(",a"));
+        assert_eq!(Some(first), arena.register("a"));
+
+        arena.bind_register(".", first);
+        assert_eq!(Some(second), arena.register(",a"));
+        // A lone sigil is an ordinary register.
+        assert!(!is_local_register(","));
+    }
+
+    #[test]
+    fn rotations() {
+        use std::f32::consts::PI;
+
+        let mut arena = Arena::new();
+        let set = arena.set(vec![]);
+        assert_eq!(0.0, arena.rotation(set));
+        arena.set_rotation(set, -PI / 2.0);
+        assert!((arena.rotation(set) - 1.5 * PI).abs() < 1e-5);
+        arena.set_rotation(set, 2.0 * PI);
+        assert_eq!(0.0, arena.rotation(set));
+        assert!(arena.rotations.is_empty());
+    }
+
+    #[test]
+    fn register_names() {
+        let mut arena = Arena::new();
+        let focus = arena.register(".").unwrap();
+        assert!(arena.register_names(focus).is_empty());
+        arena.bind_register("b", focus);
+        arena.bind_register("a", focus);
+        arena.bind_register(",c", focus);
+        assert_eq!(vec!["a", "b"], arena.register_names(focus));
+    }
+
+    #[test]
+    fn merge() {
+        let mut ours = Arena::new();
+        ours.set_insert_string(".", "hello").unwrap();
+        ours.bind_register_to_string("a", "ours");
+        ours.bind_register_to_string("b", "same");
+
+        let mut theirs = Arena::new();
+        theirs.bind_register_to_empty_set("s");
+        theirs.set_insert_string("s", "hello").unwrap();
+        theirs.set_insert("s", "s").unwrap();
+        theirs.bind_register_to_string("a", "theirs");
+        theirs.bind_register_to_string("b", "same");
+
+        let report = ours.merge(&theirs, ConflictPolicy::Rename);
+        assert_eq!(Vec::<Inconsistency>::new(), ours.validate());
+        let a = ours.string("ours");
+        let a_theirs = ours.string("theirs");
+        assert_eq!(
+            vec![RegisterConflict {
+                register: "a".into(),
+                ours: a,
+                theirs: a_theirs,
+                renamed: Some("a~1".into()),
+            }],
+            report.conflicts
+        );
+        assert_eq!(Some(a), ours.register("a"));
+        assert_eq!(Some(a_theirs), ours.register("a~1"));
+
+        // The imported set still contains itself, and shares "hello" with the
+        // set we already had.
+        let set = ours.register("s").unwrap();
+        let hello = ours.string("hello");
+        match &ours.slot_map[set].structure {
+            Structure::Set(members) => assert_eq!(
+                vec![hello, set].into_iter().collect::<HashSet<_>>(),
+                **members
+            ),
+            _ => panic!(),
+        }
+        assert_eq!(theirs.uuid(theirs.register("s").unwrap()), ours.uuid(set));
+
+        // Merging again finds everything by uuid.
+        let report = ours.merge(&theirs, ConflictPolicy::Theirs);
+        assert_eq!(0, report.imported);
+        assert_eq!(Some(a_theirs), ours.register("a"));
+    }
+
+    #[test]
+    fn diff() {
+        let mut old = Arena::new();
+        let milk = old.string("milk");
+        let eggs = old.string("eggs");
+        let butter = old.string("butter");
+        let list = old.list(vec![milk, eggs]);
+        let set = old.set(vec![milk, butter, list]);
+        old.bind_register("a", set);
+
+        let mut new = Arena::new();
+        new.merge(&old, ConflictPolicy::Theirs);
+        let copy = |key| new.lookup_uuid(&old.uuid(key).unwrap()).unwrap();
+        let (dot, milk, eggs, butter) = (
+            copy(old.register(".").unwrap()),
+            copy(milk),
+            copy(eggs),
+            copy(butter),
+        );
+        let (list, set) = (copy(list), copy(set));
+        new.bind_register(".", dot);
+        assert_eq!(Vec::<Change>::new(), super::diff(&old, &new));
+
+        let bread = new.string("bread");
+        new.set_insert_many(set, vec![bread]).unwrap();
+        set_remove(&mut new.slot_map, set, butter);
+        new.list_replace(list, vec![eggs, milk]).unwrap();
+        new.rewrite_strings(vec![(eggs, "spam".to_string())]);
+        let uuid = |key| new.uuid(key).unwrap();
+        let changes = super::diff(&old, &new);
+        assert_eq!(
+            vec![
+                Change::Added(uuid(bread)),
+                Change::Removed(uuid(butter)),
+                Change::Rewritten(uuid(eggs)),
+                Change::Included {
+                    container: uuid(set),
+                    member: uuid(bread)
+                },
+                Change::Excluded {
+                    container: uuid(set),
+                    member: uuid(butter)
+                },
+                Change::Reordered(uuid(list)),
+            ],
+            changes
+        );
+
+        // Values that are gone for good are described instead.
+        new.collect_garbage();
+        let view = new.diff_view(&old, &changes);
+        let entry = |arena: &mut Arena, map, name| {
+            let name = arena.string(name);
+            map_get(&arena.slot_map, map, name).unwrap()
+        };
+        let removed = entry(&mut new, view, "removed");
+        let description = new.string("\"butter\"");
+        assert_eq!(Some(true), new.set_contains(removed, description));
+        let changed = entry(&mut new, view, "changed");
+        let sides = map_get(&new.slot_map, changed, set).unwrap();
+        let included = entry(&mut new, sides, "included");
+        assert_eq!(Some(true), new.set_contains(included, bread));
+        assert_eq!(Vec::<Inconsistency>::new(), new.validate());
+    }
+
+    #[test]
+    fn versions() {
+        let mut arena = Arena::new();
+        let note = arena.string("draft");
+        arena.bind_register("a", note);
+        for text in &["second draft", "final"] {
+            let previous = arena.rewrite_strings(vec![(note, text.to_string())]);
+            let version = arena.string(&previous[0].1);
+            arena.record_version(note, version).unwrap();
+        }
+        let versions = arena.versions(note).unwrap();
+        let draft = arena.lookup_string("draft").unwrap();
+        let second = arena.lookup_string("second draft").unwrap();
+        assert_eq!(Some(&[draft, second][..]), arena.elements(versions));
+
+        // Restoring swaps contents, so the restored text stays a version.
+        arena.restore_version(note, draft).unwrap();
+        assert_eq!(Some(note), arena.lookup_string("draft"));
+        assert_eq!(Some(draft), arena.lookup_string("final"));
+
+        // Undoing the restore hands the text back to the version.
+        let previous = arena.rewrite_strings(vec![(note, "final".to_string())]);
+        assert_eq!(vec![(note, "draft".to_string())], previous);
+        assert_eq!(Some(note), arena.lookup_string("final"));
+        assert_eq!(Some(draft), arena.lookup_string("draft"));
+
+        // A version kept anywhere else can't be restored.
+        arena.bind_register("b", second);
+        assert_eq!(None, arena.restore_version(note, second));
+
+        let milk = arena.string("milk");
+        let eggs = arena.string("eggs");
+        let set = arena.set(vec![milk]);
+        let old = arena.set(vec![eggs]);
+        arena.record_version(set, old).unwrap();
+        arena.record_version(set, old).unwrap();
+        assert_eq!(
+            Some(1),
+            arena.versions(set).and_then(|list| arena.list_len(list))
+        );
+        arena.restore_version(set, old).unwrap();
+        assert_eq!(Some(true), arena.set_contains(set, eggs));
+        assert_eq!(Some(true), arena.set_contains(old, milk));
+        assert_eq!(Some(false), arena.set_contains(set, milk));
+        assert_eq!(None, arena.record_version(set, note));
+        let numbers = arena.insert_strings((0..=MAX_VERSIONED_MEMBERS).map(|n| n.to_string()));
+        let large = arena.set(numbers);
+        assert_eq!(None, arena.record_version(set, large));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn validate_repair() {
+        let mut arena = Arena::new();
+        arena.set_insert_string(".", "hello").unwrap();
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+
+        let hello = arena.string("hello");
+        let world = arena.string("world");
+        let set = arena.register(".").unwrap();
+        // Forget that `hello` is in the set, and pretend that `world` is.
+        arena.slot_map[hello].inclusions.clear();
+        arena.slot_map[world].inclusions.insert((set, Route::Set));
+
+        let inconsistencies = arena.validate();
+        assert_eq!(2, inconsistencies.len());
+        assert!(inconsistencies.contains(&Inconsistency::MissingInclusion {
+            member: hello,
+            container: set,
+            route: Route::Set,
+        }));
+        assert!(inconsistencies.contains(&Inconsistency::StaleInclusion {
+            member: world,
+            container: set,
+            route: Route::Set,
+        }));
+
+        assert_eq!(2, arena.repair().len());
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn compact() {
+        let mut arena = Arena::new();
+        let strings: Vec<_> = (0..100).map(|i| arena.string(&i.to_string())).collect();
+        let set = arena.set(strings.iter().copied());
+        arena.bind_register(".", set);
+        for &string in &strings[1..] {
+            arena.bind_register("a", string);
+            arena.set_remove(".", "a").unwrap();
+        }
+        arena.leaf_count(set);
+        let report = arena.compact();
+        assert!(report.after.by_kind[&Kind::Set] < report.before.by_kind[&Kind::Set]);
+        assert!(report.after.total() < report.before.total());
+        // Nothing changes but how much memory is used.
+        assert_eq!(1, arena.leaf_count(set));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn collect_garbage() {
+        let mut arena = Arena::new();
+        let (a, b, c) = (arena.string("a"), arena.string("b"), arena.string("c"));
+        let set = arena.set(vec![a, b]);
+        let garbage = arena.set(vec![b, c]);
+        let kept = arena.string("kept");
+        arena.bind_register(".", set);
+        arena.set_rotation(garbage, 1.0);
+        assert_eq!(2, arena.leaf_count(garbage));
+        assert!(arena.collect_garbage_keeping(&[kept]) >= 2);
+        for key in [a, b, set, kept].iter() {
+            assert!(arena.slot_map.contains_key(*key));
+        }
+        assert!(!arena.slot_map.contains_key(garbage));
+        assert!(!arena.slot_map.contains_key(c));
+        assert!(!arena.keys_of_kind(Kind::String).contains(&c));
+        assert_eq!(0.0, arena.rotation(garbage));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+        assert_eq!(0, arena.collect_garbage_keeping(&[kept]));
+        // Strings that were removed are made again when they are needed.
+        assert_ne!(c, arena.string("c"));
+        assert_eq!(b, arena.string("b"));
+    }
+
+    #[test]
+    fn find_strings() {
+        let mut arena = Arena::new();
+        let (apple, pineapple) = (arena.string("Apple"), arena.string("pineapple"));
+        arena.string("pear");
+        let members = |arena: &Arena, set: ArenaKey| match &arena.slot_map[set].structure {
+            Structure::Set(members) => members.iter().copied().collect::<HashSet<_>>(),
+            _ => panic!(),
+        };
+        let found = arena.find_substring("APPLE");
+        assert_eq!(
+            vec![apple, pineapple].into_iter().collect::<HashSet<_>>(),
+            members(&arena, found)
+        );
+        let found = arena.find_prefix("app");
+        assert_eq!(
+            std::iter::once(apple).collect::<HashSet<_>>(),
+            members(&arena, found)
+        );
+        let found = arena.find_strings(|string| string.len() > 100);
+        assert!(members(&arena, found).is_empty());
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn content_keys() {
+        let mut arena = Arena::new();
+        let text = arena.string("hello");
+        let file = arena.blob(Blob {
+            bytes: vec![0xff, 0xfe],
+            mime: crate::blob::UNKNOWN_MIME_TYPE.to_string(),
+            name: "hello.bin".to_string(),
+        });
+        assert_eq!(Some(text), arena.key_of_content(b"hello"));
+        assert_eq!(Some(file), arena.key_of_content(&[0xff, 0xfe]));
+        assert_eq!(None, arena.key_of_content(b"goodbye"));
+        assert_eq!(
+            Some(ContentHash::of_string("hello")),
+            arena.content_hash(text)
+        );
+        let set = arena.set(vec![text]);
+        assert_eq!(None, arena.content_hash(set));
+        // Even when two values hash the same, they are only treated as one if
+        // their contents are the same.
+        let hash = ContentHash::of_string("goodbye");
+        arena.lookup_map.insert(hash, text);
+        assert_eq!(None, arena.lookup_string("goodbye"));
+        assert_ne!(text, arena.string("goodbye"));
+    }
+
+    #[test]
+    fn image_refs() {
+        let mut arena = Arena::new();
+        let photo = arena.image_ref("photos/cat.jpg");
+        assert_eq!(photo, arena.image_ref(PathBuf::from("photos/cat.jpg")));
+        assert_ne!(photo, arena.image_ref("photos/dog.jpg"));
+        assert_ne!(photo, arena.string("photos/cat.jpg"));
+        assert_eq!(Kind::ImageRef, arena.slot_map[photo].structure.kind());
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn pruning() {
+        let mut arena = Arena::new();
+        let keep_pinned = Pruning { keep_pinned: true };
+        arena.bind_register_to_empty_set("a");
+        arena.bind_register_to_empty_set("b");
+        arena.set_insert_string("b", "eggs").unwrap();
+        arena.set_insert("a", "b").unwrap();
+        let (a, b) = (arena.register("a").unwrap(), arena.register("b").unwrap());
+        let eggs = arena.lookup_string("eggs").unwrap();
+        arena.set_insert_string("b", "milk").unwrap();
+        let milk = arena.lookup_string("milk").unwrap();
+        arena.pin(1, eggs).unwrap();
+        arena.bind_register_to_empty_set("b");
+        assert_eq!(Vec::<ArenaKey>::new(), arena.orphans(keep_pinned));
+        assert_eq!(Some(2), arena.inclusion_count(eggs));
+
+        set_remove(&mut arena.slot_map, a, b);
+        assert_eq!(vec![b], arena.orphans(keep_pinned));
+        assert_eq!(0, arena.prune_orphans_keeping(&[b], keep_pinned));
+        // Milk was only in b, but eggs are still pinned.
+        assert_eq!(2, arena.prune_orphans_keeping(&[], keep_pinned));
+        assert!(!arena.slot_map.contains_key(b) && !arena.slot_map.contains_key(milk));
+        assert_eq!(None, arena.lookup_string("milk"));
+        assert_eq!(Some(1), arena.inclusion_count(eggs));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+
+        // Without keeping pinned values, eggs are unpinned, and the name of
+        // the slot goes with them.
+        assert_eq!(
+            2,
+            arena.prune_orphans_keeping(&[], Pruning { keep_pinned: false })
+        );
+        assert!(!arena.slot_map.contains_key(eggs));
+        assert_eq!(None, arena.lookup_string(&pin_register(1)));
+        assert!(arena.pins().is_empty());
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn orphan_candidates() {
+        let mut arena = Arena::new();
+        let keep_pinned = Pruning { keep_pinned: true };
+        arena.bind_register_to_empty_set("a");
+        arena.set_insert_string("a", "eggs").unwrap();
+        let a = arena.register("a").unwrap();
+        let eggs = arena.lookup_string("eggs").unwrap();
+        arena.prune_orphans_keeping(&[], keep_pinned);
+        assert_eq!(
+            Some(&HashSet::new()),
+            arena.slot_map.orphan_candidates.as_ref()
+        );
+
+        let milk = arena.string("milk");
+        set_remove(&mut arena.slot_map, a, eggs);
+        let mut orphans = arena.orphans(keep_pinned);
+        orphans.sort();
+        let mut expected = vec![eggs, milk];
+        expected.sort();
+        assert_eq!(expected, orphans);
+        assert_eq!(1, arena.prune_orphans_keeping(&[milk], keep_pinned));
+        // Milk was kept, so it is looked at again next time.
+        assert_eq!(vec![milk], arena.orphans(keep_pinned));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+
+    #[test]
+    fn transactions() {
+        let mut arena = Arena::new();
+        let (a, b) = (arena.string("a"), arena.string("b"));
+        let set = arena.set(vec![a]);
+        arena.bind_register("s", set);
+        arena.begin().unwrap();
+        assert!(arena.begin().is_none());
+        assert!(arena.in_transaction());
+        let c = arena.string("c");
+        arena.set_insert_many(set, vec![b, c]).unwrap();
+        arena.set_change(set, &[], &[a]).unwrap();
+        arena.rewrite_strings(vec![(b, "bee".to_string())]);
+        arena.bind_register_to_empty_set("s");
+        assert_eq!(0, arena.collect_garbage());
+        arena.rollback().unwrap();
+        assert!(!arena.in_transaction());
+        assert!(!arena.slot_map.contains_key(c));
+        assert_eq!(Some(set), arena.register("s"));
+        assert_eq!(Some(true), arena.set_contains(set, a));
+        assert_eq!(Some(false), arena.set_contains(set, b));
+        assert_eq!(Some(b), arena.lookup_string("b"));
+        assert_eq!(None, arena.lookup_string("c"));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+
+        arena.begin().unwrap();
+        arena.set_insert_many(set, vec![b]).unwrap();
+        arena.commit().unwrap();
+        assert!(arena.rollback().is_none());
+        assert_eq!(Some(true), arena.set_contains(set, b));
+    }
+
+    #[test]
+    fn rolling_back_forgets_inserted_values() {
+        let mut arena = Arena::new();
+        let now = Instant::now();
+        arena.set_change_window(ChangeWindow::Changes(1));
+        let a = arena.string("a");
+        arena.begin().unwrap();
+        // Nothing is copied until it changes.
+        assert_eq!(0, arena.slot_map.journal.as_ref().unwrap().originals.len());
+        let b = arena.string("b");
+        arena.record_change(b, now);
+        arena.set_rotation(b, 1.0);
+        arena.rollback().unwrap();
+        assert!(!arena.slot_map.contains_key(b));
+        assert_eq!(0.0, arena.rotation(b));
+
+        // Recording another change evicts the change to `b`, which would
+        // panic if `b` were still remembered as having changed.
+        arena.record_change(a, now);
+        assert!(!arena.evict_changes(now));
+        assert_eq!(Vec::<Inconsistency>::new(), arena.validate());
+    }
+}
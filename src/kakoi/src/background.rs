@@ -0,0 +1,200 @@
+//! # Running in the background
+//!
+//! Jotting something down shouldn't mean finding Kakoi's window first.
+//! `kakoi --background` opens a window that starts out hidden and hides again
+//! when it is closed instead of exiting, so that everything in the arena stays
+//! where it was. Another `kakoi` brings it back with `--summon`:
+//!
+//! * `kakoi --summon show` shows the window.
+//! * `kakoi --summon capture` shows the window and starts capturing a string
+//!   into the inbox, as if `c` had been pressed (see
+//!   [`CompleteAction::CaptureString`]).
+//! * `kakoi --summon quit` closes it for good.
+//!
+//! winit has no way to register a global hotkey or put an icon in the system
+//! tray, so neither is done here. Binding `kakoi --summon capture` to a
+//! shortcut in the desktop's keyboard settings does the hotkey's job on every
+//! platform. Some window managers refuse to give a window focus when it asks
+//! for it, in which case a summoned window has to be clicked before typing.
+//!
+//! Summons are sent as a single line over a TCP connection to [`ADDRESS`],
+//! which only accepts connections from the same machine. Only one Kakoi can
+//! run in the background at a time, since only one can listen there. Anyone
+//! on the machine can connect, so every summons starts with a random token,
+//! which the Kakoi running in the background writes to a file only its user
+//! can read (see [`token_path`]) when it starts; summons without it are
+//! ignored.
+//!
+//! [`CompleteAction::CaptureString`]: crate::input_manager::CompleteAction::CaptureString
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Where a Kakoi running in the background listens for summons.
+pub const ADDRESS: &str = "127.0.0.1:47183";
+
+/// How long to wait on a connection before giving up on it.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What a Kakoi running in the background is asked to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Summons {
+    Show,
+    /// Shows the window, ready to capture a string into the inbox.
+    Capture,
+    Quit,
+}
+
+impl Summons {
+    pub const ALL: [Summons; 3] = [Summons::Show, Summons::Capture, Summons::Quit];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Summons::Show => "show",
+            Summons::Capture => "capture",
+            Summons::Quit => "quit",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|summons| summons.name() == name.trim())
+    }
+}
+
+/// Returns where the token summons have to start with is kept: in the
+/// `kakoi` directory of the user's configuration directory, or [`None`] if
+/// there isn't one.
+pub fn token_path() -> Option<PathBuf> {
+    let config = if cfg!(windows) {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+    } else {
+        match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(config) => PathBuf::from(config),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        }
+    };
+    Some(config.join("kakoi").join("summons-token"))
+}
+
+fn no_token_path() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no configuration directory")
+}
+
+/// Writes `token` to `path`, readable only by the current user.
+fn write_token(path: &Path, token: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(token.as_bytes())
+}
+
+/// Reads a single summons from `stream`, if it starts with `token`. Only the
+/// start of the line is read, so that a connection can't make the listener
+/// buffer forever.
+fn receive(stream: TcpStream, token: &str) -> Option<Summons> {
+    stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+    let mut line = String::new();
+    BufReader::new(stream).take(128).read_line(&mut line).ok()?;
+    let mut words = line.trim().splitn(2, ' ');
+    if words.next()? != token {
+        return None;
+    }
+    Summons::parse(words.next()?)
+}
+
+/// Starts listening for summons at `address` on a new thread, handing each
+/// one to `deliver` until it returns false, and writes the token they have to
+/// start with to `token_path`. Fails if something is already listening
+/// there, like another Kakoi running in the background.
+pub fn listen_at<F>(address: &str, token_path: &Path, mut deliver: F) -> io::Result<()>
+where
+    F: FnMut(Summons) -> bool + Send + 'static,
+{
+    let listener = TcpListener::bind(address)?;
+    let token = Uuid::new_v4().to_simple().to_string();
+    write_token(token_path, &token)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream.ok().and_then(|stream| receive(stream, &token)) {
+                Some(summons) if !deliver(summons) => break,
+                Some(_) => {}
+                None => log::warn!("ignored a summons that couldn't be understood"),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Like [`listen_at`], listening at [`ADDRESS`] with the token at
+/// [`token_path`].
+pub fn listen<F>(deliver: F) -> io::Result<()>
+where
+    F: FnMut(Summons) -> bool + Send + 'static,
+{
+    listen_at(ADDRESS, &token_path().ok_or_else(no_token_path)?, deliver)
+}
+
+/// Sends `summons` to whatever is listening at `address`, starting it with
+/// the token at `token_path`.
+pub fn summon_at(address: &str, token_path: &Path, summons: Summons) -> io::Result<()> {
+    let token = fs::read_to_string(token_path)?;
+    let address: SocketAddr = address
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "not an address"))?;
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    writeln!(stream, "{} {}", token.trim(), summons.name())
+}
+
+/// Sends `summons` to the Kakoi running in the background, failing if there
+/// isn't one.
+pub fn summon(summons: Summons) -> io::Result<()> {
+    summon_at(ADDRESS, &token_path().ok_or_else(no_token_path)?, summons)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn summoning() {
+        // Port 0 can't be summoned, so find a free port first.
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let token = std::env::temp_dir()
+            .join(format!("kakoi-summons-test-{}", std::process::id()))
+            .join("summons-token");
+        let (sender, receiver) = mpsc::channel();
+        listen_at(&address, &token, move |summons| {
+            sender.send(summons).unwrap();
+            summons != Summons::Quit
+        })
+        .unwrap();
+        assert!(listen_at(&address, &token, |_| true).is_err());
+        // Summons without the token are ignored.
+        let mut stream = TcpStream::connect(&address).unwrap();
+        writeln!(stream, "show").unwrap();
+        drop(stream);
+        summon_at(&address, &token, Summons::Capture).unwrap();
+        summon_at(&address, &token, Summons::Quit).unwrap();
+        fs::remove_dir_all(token.parent().unwrap()).unwrap();
+        assert_eq!(Ok(Summons::Capture), receiver.recv_timeout(TIMEOUT * 5));
+        assert_eq!(Ok(Summons::Quit), receiver.recv_timeout(TIMEOUT * 5));
+    }
+}
@@ -1,7 +1,30 @@
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use kakoi::arena::{Arena, ChangeWindow, Pruning};
+use kakoi::background::{self, Summons};
+use kakoi::blob::Blob;
+use kakoi::export::{self, OutlineFormat};
+use kakoi::html;
+use kakoi::import::{self, ImportPolicy, ImportedImage, DEFAULT_MAX_DIMENSION};
+use kakoi::input_manager::BindingMode;
+use kakoi::json;
+use kakoi::link::DeepLink;
+use kakoi::profiling::Profiler;
+use kakoi::render::capture::CaptureRequest;
+use kakoi::render::text::DEFAULT_PREVIEW_WORDS;
+use kakoi::settings::{self, Bundle, Part};
+use kakoi::task::Progress;
+use kakoi::text_import::{self, ImportedText};
+use kakoi::tutorial;
 
 fn main() {
-    let matches = App::new("kakoi")
+    // An arena written out as JSON (see `kakoi::json`) can only be merged back
+    // in from inside the window. Opening one at startup, and focusing a value
+    // inside of it, belong here, alongside a default file to restore the last
+    // session from. `open-url` already accepts links to values in files (see
+    // `kakoi::link`), but can only follow links to values in the arena it
+    // starts with.
+    let app = App::new("kakoi")
         .version("0.1.0")
         .arg(
             Arg::with_name("create-window")
@@ -9,9 +32,446 @@ fn main() {
                 .short("c")
                 .help("Opens a new window"),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("validate")
+                .long("validate")
+                .help("Checks for and repairs inconsistencies in the arena after every action"),
+        )
+        .arg(
+            Arg::with_name("scancode-bindings")
+                .long("scancode-bindings")
+                .help(
+                    "Binds keys by their position on the keyboard instead of by their labels \
+                     (Windows and Linux only)",
+                ),
+        )
+        .arg(
+            Arg::with_name("read-only")
+                .long("read-only")
+                .help("Starts in read-only mode, in which nothing can be modified"),
+        )
+        .arg(
+            Arg::with_name("flat-rings")
+                .long("flat-rings")
+                .help("Draws rings without shadows showing how deeply they are nested"),
+        )
+        .arg(
+            Arg::with_name("tapered-rings")
+                .long("tapered-rings")
+                .help("Draws rings thinner the more deeply they are nested, down to a hairline"),
+        )
+        .arg(
+            Arg::with_name("depth-of-field")
+                .long("depth-of-field")
+                .help(
+                "Fades rings and strings nested far below the focused value into the background",
+            ),
+        )
+        .arg(
+            Arg::with_name("power-saving")
+                .long("power-saving")
+                .help("Starts in power saving mode, even when not running on battery"),
+        )
+        .arg(
+            Arg::with_name("collect-garbage")
+                .long("collect-garbage")
+                .help("Removes values that can't be reached any more as the arena grows, instead of only when compacting with f5"),
+        )
+        .arg(
+            Arg::with_name("prune-orphans")
+                .long("prune-orphans")
+                .help("Removes values as soon as they have been removed from everything containing them, unless a register is bound to them"),
+        )
+        .arg(
+            Arg::with_name("prune-pinned")
+                .long("prune-pinned")
+                .requires("prune-orphans")
+                .help("Removes values kept only by being pinned to a slot too, unpinning them"),
+        )
+        .arg(
+            Arg::with_name("author")
+                .long("author")
+                .value_name("NAME")
+                .takes_value(true)
+                .help("Records NAME as the author of every value you create, along with when you created it"),
+        )
+        .arg(
+            Arg::with_name("ui-scale")
+                .long("ui-scale")
+                .value_name("FACTOR")
+                .takes_value(true)
+                .help("Only draws text and details once they are FACTOR times larger than usual, from 0.25 to 8 (default 1)"),
+        )
+        .arg(
+            Arg::with_name("preview-words")
+                .long("preview-words")
+                .value_name("WORDS")
+                .takes_value(true)
+                .help("Draws only the first WORDS words of long strings until they are focused, or every word if WORDS is 0 (default 24)"),
+        )
+        .arg(
+            Arg::with_name("truncate-middle")
+                .long("truncate-middle")
+                .help("Shortens strings too small to read from the middle, keeping both of their ends"),
+        )
+        .arg(
+            Arg::with_name("window-size")
+                .long("window-size")
+                .value_name("WIDTHxHEIGHT")
+                .takes_value(true)
+                .help("Opens the window at the given size, like 1280x720"),
+        )
+        .arg(
+            Arg::with_name("fullscreen")
+                .long("fullscreen")
+                .help("Opens the window fullscreen"),
+        )
+        .arg(
+            Arg::with_name("track-usage")
+                .long("track-usage")
+                .help("Keeps track of how often and how recently values are visited"),
+        )
+        .arg(
+            Arg::with_name("changed-window")
+                .long("changed-window")
+                .value_name("WINDOW")
+                .takes_value(true)
+                .help("Keeps values in the set of changed values for WINDOW, a number of changes like 20 or a time like 90s, 15m or 2h (default 15m)"),
+        )
+        .arg(
+            Arg::with_name("max-image-size")
+                .long("max-image-size")
+                .value_name("PIXELS")
+                .takes_value(true)
+                .help("Shrinks imported images wider or taller than PIXELS to fit (default 4096)"),
+        )
+        .arg(
+            Arg::with_name("import")
+                .long("import")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Imports an image or text file once the window opens, as if it were dropped onto it"),
+        )
+        .arg(
+            Arg::with_name("no-text-sections")
+                .long("no-text-sections")
+                .help("Imports text files as a single section instead of splitting them at headings"),
+        )
+        .arg(
+            Arg::with_name("link-images")
+                .long("link-images")
+                .help("Imports images as links to their files, which are only read while they are on screen"),
+        )
+        .arg(
+            Arg::with_name("memory-budget")
+                .long("memory-budget")
+                .value_name("MEGABYTES")
+                .takes_value(true)
+                .help("Warns before importing images would take kakoi over MEGABYTES of memory"),
+        )
+        .arg(
+            Arg::with_name("dump-layout")
+                .long("dump-layout")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Writes where everything is drawn out to FILE as JSON once the first frame is drawn, then exits"),
+        )
+        .arg(
+            Arg::with_name("record-session")
+                .long("record-session")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Writes every key pressed out to FILE as a test replaying them when the window closes"),
+        )
+        .arg(
+            Arg::with_name("background")
+                .long("background")
+                .help("Opens a hidden window that hides again instead of exiting when closed, until --summon quit"),
+        )
+        .arg(
+            Arg::with_name("summon")
+                .long("summon")
+                .value_name("WHAT")
+                .takes_value(true)
+                .possible_values(&["show", "capture", "quit"])
+                .help("Shows the window of the kakoi running in the background, ready to capture a string into the inbox with capture, or closes it with quit"),
+        )
+        .arg(
+            Arg::with_name("settings")
+                .long("settings")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Applies the settings in a bundle written by --export-settings, in place of the flags for them"),
+        )
+        .arg(
+            Arg::with_name("settings-parts")
+                .long("settings-parts")
+                .value_name("PARTS")
+                .takes_value(true)
+                .use_delimiter(true)
+                .possible_values(&["appearance", "keys", "behavior"])
+                .help("Applies only these parts of the --settings bundle, like appearance,keys (default: all of them)"),
+        )
+        .arg(
+            Arg::with_name("export-settings")
+                .long("export-settings")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Writes the settings chosen by the other flags out to FILE as a bundle to share, then exits"),
+        )
+        .subcommand(
+            SubCommand::with_name("open-url")
+                .about("Opens a new window displaying the value a kakoi:// link names")
+                .arg(Arg::with_name("URL").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Writes the tutorial, or an imported file, out as a Markdown or org-mode outline, as a web page, or as JSON")
+                .arg(Arg::with_name("OUTPUT").required(true))
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Exports a file, imported as if it were dropped onto the window, instead of the tutorial"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["markdown", "org", "html", "json"])
+                        .help("The markup to write the outline in, html for a web page, or json for the whole arena (default: chosen from OUTPUT's extension)"),
+                ),
+        );
+    #[cfg(feature = "profiling")]
+    let app = app.arg(
+        Arg::with_name("trace-output")
+            .long("trace-output")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("Writes a chrome://tracing compatible trace of kakoi's internals to FILE"),
+    );
+    let matches = app.get_matches();
+
+    #[cfg(feature = "profiling")]
+    let profiler = match matches.value_of("trace-output") {
+        Some(path) => Profiler::chrome_trace(path),
+        None => Profiler::disabled(),
+    };
+    #[cfg(not(feature = "profiling"))]
+    let profiler = Profiler::disabled();
+
+    if let Some(export_matches) = matches.subcommand_matches("export") {
+        run_export(export_matches);
+        return;
+    }
+
+    if let Some(summons) = matches.value_of("summon").and_then(Summons::parse) {
+        if let Err(e) = background::summon(summons) {
+            eprintln!(
+                "can't reach a kakoi running in the background (start one with --background): {}",
+                e
+            );
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let focus = matches.subcommand_matches("open-url").map(|open_url| {
+        let url = open_url.value_of("URL").unwrap();
+        match DeepLink::parse(url) {
+            Some(DeepLink {
+                file: Some(file), ..
+            }) => {
+                eprintln!(
+                    "can't open {}: arenas can't be loaded from files yet",
+                    file.display()
+                );
+                std::process::exit(1);
+            }
+            Some(link) => link.uuid,
+            None => {
+                eprintln!("{} isn't a kakoi:// link", url);
+                std::process::exit(1);
+            }
+        }
+    });
+
+    if matches.is_present("create-window")
+        || focus.is_some()
+        || matches.is_present("import")
+        || matches.is_present("dump-layout")
+        || matches.is_present("export-settings")
+        || matches.is_present("settings")
+        || matches.is_present("background")
+        || matches.is_present("record-session")
+    {
+        let mut options = kakoi::window::Options {
+            validate: matches.is_present("validate"),
+            binding_mode: if matches.is_present("scancode-bindings") {
+                BindingMode::Scancode
+            } else {
+                BindingMode::VirtualKey
+            },
+            read_only: matches.is_present("read-only"),
+            flat_rings: matches.is_present("flat-rings"),
+            tapered_rings: matches.is_present("tapered-rings"),
+            depth_of_field: matches.is_present("depth-of-field"),
+            power_saving: matches.is_present("power-saving"),
+            ui_scale: matches
+                .value_of("ui-scale")
+                .and_then(kakoi::window::parse_ui_scale),
+            preview_words: match matches
+                .value_of("preview-words")
+                .and_then(|words| words.parse().ok())
+            {
+                Some(0) => None,
+                Some(words) => Some(words),
+                None => Some(DEFAULT_PREVIEW_WORDS),
+            },
+            truncate_middle: matches.is_present("truncate-middle"),
+            window_size: matches
+                .value_of("window-size")
+                .and_then(CaptureRequest::parse)
+                .map(|size| (size.width, size.height)),
+            fullscreen: matches.is_present("fullscreen"),
+            focus,
+            track_usage: matches.is_present("track-usage"),
+            change_window: matches
+                .value_of("changed-window")
+                .and_then(ChangeWindow::parse)
+                .unwrap_or_default(),
+            import_policy: ImportPolicy {
+                max_dimension: matches
+                    .value_of("max-image-size")
+                    .and_then(|pixels| pixels.parse().ok())
+                    .unwrap_or(DEFAULT_MAX_DIMENSION),
+                memory_budget: matches
+                    .value_of("memory-budget")
+                    .and_then(|megabytes| megabytes.parse::<u64>().ok())
+                    .map(|megabytes| megabytes * 1_000_000),
+                split_text: !matches.is_present("no-text-sections"),
+                link_images: matches.is_present("link-images"),
+            },
+            import: matches.value_of("import").map(Into::into),
+            dump_layout: matches.value_of("dump-layout").map(Into::into),
+            background: matches.is_present("background"),
+            record_session: matches.value_of("record-session").map(Into::into),
+            collect_garbage: matches.is_present("collect-garbage"),
+            prune_orphans: if matches.is_present("prune-orphans") {
+                Some(Pruning {
+                    keep_pinned: !matches.is_present("prune-pinned"),
+                })
+            } else {
+                None
+            },
+            author: matches.value_of("author").map(Into::into),
+        };
+        if let Some(path) = matches.value_of("settings") {
+            apply_settings(path, &matches, &mut options);
+        }
+        if let Some(path) = matches.value_of("export-settings") {
+            match settings::export(path, &options) {
+                Ok(()) => println!("wrote settings to {}", path),
+                Err(e) => {
+                    eprintln!("can't write settings to {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        kakoi::window::create_window(profiler, options);
+    }
+}
+
+/// Applies the bundle at `path` to `options`, or lists what is wrong with it
+/// and exits. See `kakoi::settings`.
+fn apply_settings(path: &str, matches: &ArgMatches, options: &mut kakoi::window::Options) {
+    let bundle = match Bundle::load(path) {
+        Ok(bundle) => bundle,
+        Err(problems) => {
+            eprintln!("can't apply the settings in {}:", path);
+            for problem in problems {
+                eprintln!("  {}", problem);
+            }
+            std::process::exit(1);
+        }
+    };
+    let parts: Vec<Part> = match matches.values_of("settings-parts") {
+        Some(names) => names.filter_map(Part::parse).collect(),
+        None => Part::ALL.to_vec(),
+    };
+    let applied = bundle.apply(&parts, options);
+    let skipped: Vec<&str> = bundle
+        .parts()
+        .into_iter()
+        .filter(|part| !parts.contains(part))
+        .map(Part::name)
+        .collect();
+    if skipped.is_empty() {
+        println!("applied {} settings from {}", applied, path);
+    } else {
+        println!(
+            "applied {} settings from {}, leaving out {}",
+            applied,
+            path,
+            skipped.join(", ")
+        );
+    }
+}
 
-    if matches.is_present("create-window") {
-        kakoi::window::create_window();
+/// Exports the tutorial, or the file given with `--from`, without opening a
+/// window. See `kakoi::export` and `kakoi::html`.
+fn run_export(matches: &ArgMatches) {
+    let output = matches.value_of("OUTPUT").unwrap();
+    let page = match matches.value_of("format") {
+        Some(format) => format == "html",
+        None => html::is_html_path(output),
+    };
+    let json = match matches.value_of("format") {
+        Some(format) => format == "json",
+        None => json::is_json_path(output),
+    };
+    let format = matches
+        .value_of("format")
+        .and_then(OutlineFormat::parse)
+        .unwrap_or_else(|| OutlineFormat::from_path(output));
+    let mut arena = Arena::new();
+    let root = match matches.value_of("from") {
+        Some(path) => {
+            let policy = ImportPolicy::default();
+            let progress = Progress::unobserved();
+            let root = if text_import::is_text_file(path) {
+                ImportedText::load(path, policy, &progress).map(|text| text.insert(&mut arena))
+            } else if import::is_image_file(path) {
+                ImportedImage::load(path, policy, &progress)
+                    .map(|imported| arena.image(imported.image))
+            } else {
+                Blob::load(path, &progress).map(|blob| arena.blob(blob))
+            };
+            match root {
+                Some(root) => root,
+                None => {
+                    eprintln!("can't import {}", path);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => tutorial::build(&mut arena, BindingMode::VirtualKey),
+    };
+    let exported = if page {
+        html::export(&arena, root, output).map(|page| page.describe(output))
+    } else if json {
+        json::export(&arena, output).map(|count| format!("exported {} values to {}", count, output))
+    } else {
+        export::export(&arena, root, output, format).map(|outline| outline.describe(output))
+    };
+    match exported {
+        Ok(description) => println!("{}", description),
+        Err(e) => {
+            eprintln!("can't export to {}: {}", output, e);
+            std::process::exit(1);
+        }
     }
 }
@@ -0,0 +1,172 @@
+//! # Blobs
+//!
+//! Files that are neither images nor text, like PDFs, can still be dragged
+//! onto the window (see [`crate::import`]) to keep them alongside the notes
+//! they belong with. They are kept as they are, as [`Structure::Blob`]s,
+//! along with the name of the file they were read from and their [MIME type],
+//! which is guessed from the file name's extension (see [`mime_type`]).
+//!
+//! Kakoi can't display what is inside of a blob, so a blob is drawn as an
+//! icon naming its type, like `PDF`, above its file name (see
+//! [`Blob::label`]).
+//!
+//! Like images, the same contents are only ever inserted once (see
+//! [`Arena::blob`]), even if they were read from files with different names.
+//! The name of the file read first is kept.
+//!
+//! [`Structure::Blob`]: crate::arena::Structure::Blob
+//! [`Arena::blob`]: crate::arena::Arena::blob
+//! [MIME type]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/MIME_types
+
+use crate::import::{format_byte_size, read_file};
+use crate::task::Progress;
+use std::path::Path;
+
+/// The MIME type of files whose type can't be guessed.
+pub const UNKNOWN_MIME_TYPE: &str = "application/octet-stream";
+
+/// The MIME types of files with each extension, for the kinds of files most
+/// likely to be kept alongside notes.
+const MIME_TYPES: [(&str, &str); 16] = [
+    ("pdf", "application/pdf"),
+    ("epub", "application/epub+zip"),
+    ("zip", "application/zip"),
+    ("doc", "application/msword"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("xls", "application/vnd.ms-excel"),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+];
+
+/// The longest extension drawn as a blob's icon. Longer ones are drawn as
+/// `FILE` instead.
+const MAX_ICON_LENGTH: usize = 4;
+
+/// Guesses the MIME type of the file at `path` from its extension, returning
+/// [`UNKNOWN_MIME_TYPE`] if it isn't one Kakoi knows about.
+pub fn mime_type<P: AsRef<Path>>(path: P) -> &'static str {
+    let extension = path
+        .as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    MIME_TYPES
+        .iter()
+        .find(|(known, _)| Some(*known) == extension.as_deref())
+        .map_or(UNKNOWN_MIME_TYPE, |&(_, mime)| mime)
+}
+
+/// The contents of a file, stored as they are. See [the module-level
+/// documentation](crate::blob).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blob {
+    pub bytes: Vec<u8>,
+    pub mime: String,
+    /// The name of the file the blob was read from, without its directory.
+    pub name: String,
+}
+
+impl Blob {
+    /// Reads the whole file at `path` into a blob. Returns [`None`] if the
+    /// file can't be read or `progress` is cancelled.
+    ///
+    /// Meant to be run as a [`Task`](crate::task::Task).
+    pub fn load<P: AsRef<Path>>(path: P, progress: &Progress) -> Option<Self> {
+        let path = path.as_ref();
+        let bytes = read_file(path, 1.0, progress)?;
+        Some(Self {
+            bytes,
+            mime: mime_type(path).to_string(),
+            name: path.file_name()?.to_string_lossy().into_owned(),
+        })
+    }
+
+    /// The text drawn in place of the blob's contents, like `PDF`: its
+    /// extension in upper case, or `FILE` if it doesn't have a short one.
+    pub fn icon(&self) -> String {
+        match Path::new(&self.name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some(extension)
+                if extension.len() <= MAX_ICON_LENGTH
+                    && extension.chars().all(|c| c.is_ascii_alphanumeric()) =>
+            {
+                extension.to_uppercase()
+            }
+            _ => "FILE".to_string(),
+        }
+    }
+
+    /// What the blob is drawn as: its [icon](Blob::icon) above its name.
+    pub fn label(&self) -> String {
+        format!("{}\n{}", self.icon(), self.name)
+    }
+
+    /// A short description of the blob to display alongside its preview, like
+    /// `"report.pdf, application/pdf, 2.4 MB"`.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}, {}, {}",
+            self.name,
+            self.mime,
+            format_byte_size(self.bytes.len() as u64)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::Arena;
+
+    #[test]
+    fn naming() {
+        assert_eq!("application/pdf", mime_type("notes/Report.PDF"));
+        assert_eq!(UNKNOWN_MIME_TYPE, mime_type("archive.tar.xz7"));
+        assert_eq!(UNKNOWN_MIME_TYPE, mime_type("Makefile"));
+        let blob = |name: &str| Blob {
+            bytes: vec![0; 2_400_000],
+            mime: mime_type(name).to_string(),
+            name: name.to_string(),
+        };
+        assert_eq!("PDF\nreport.pdf", blob("report.pdf").label());
+        assert_eq!("FILE", blob("Makefile").icon());
+        assert_eq!("FILE", blob("slides.keynote").icon());
+        assert_eq!(
+            "report.pdf, application/pdf, 2.4 MB",
+            blob("report.pdf").describe()
+        );
+    }
+
+    #[test]
+    fn inserting() {
+        let mut arena = Arena::new();
+        let blob = |name: &str, bytes: &[u8]| Blob {
+            bytes: bytes.to_vec(),
+            mime: mime_type(name).to_string(),
+            name: name.to_string(),
+        };
+        let report = arena.blob(blob("report.pdf", b"%PDF"));
+        assert_eq!(report, arena.blob(blob("copy of report.pdf", b"%PDF")));
+        assert_ne!(report, arena.blob(blob("other.pdf", b"%PDF-1.7")));
+        assert_ne!(report, arena.string("%PDF"));
+    }
+}
@@ -50,6 +50,26 @@ impl Camera {
         &self.target
     }
 
+    /// The vertical field of view, in degrees.
+    pub fn fovy(&self) -> f32 {
+        self.fovy
+    }
+
+    /// Half of the width and half of the height of the region the camera
+    /// can see at z = 0, in the coordinates described in
+    /// [`crate::spatial_tree`]. The camera backs away from tall windows so
+    /// that the unit square always fits, which makes this at least one in
+    /// both directions.
+    pub fn visible_half_extent(&self) -> (f32, f32) {
+        let distance = if self.aspect > 1.0 {
+            self.eye.z
+        } else {
+            self.eye.z / self.aspect
+        };
+        let half_height = distance * (0.5 * self.fovy).to_radians().tan();
+        (half_height * self.aspect, half_height)
+    }
+
     pub fn view_projection_matrix(&mut self) -> &Matrix4<f32> {
         if self.view_projection_matrix_cache.is_none() {
             let view = if self.aspect() > 1.0 {
@@ -9,17 +9,41 @@ struct ZoomedConfig {
     angle: f64,
 }
 
+struct RingsConfig {
+    radius: f64,
+    positions: Vec<Polar>,
+}
+
 enum Layout {
     Equal(EqualConfig),
     Zoomed(ZoomedConfig),
+    Rings(RingsConfig),
 }
 
+/// The number of circles that must be enclosed before a [`CirclePositioner`]
+/// considers arranging them in several concentric rings (see
+/// [`fit_ring_circles`]) instead of a single ring. Several rings can fit bigger
+/// circles for as few as seven circles, but small sets are easier to read when
+/// their elements are all around the edge.
+pub const RING_THRESHOLD: u64 = 16;
+
 #[derive(Clone, Copy)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
 }
 
+/// A position relative to the center of an enclosing circle, in [polar
+/// coordinates].
+///
+/// [polar coordinates]: https://en.wikipedia.org/wiki/Polar_coordinate_system
+#[derive(Clone, Copy, Debug)]
+pub struct Polar {
+    pub distance: f64,
+    /// Counterclockwise from the positive x axis, in radians.
+    pub angle: f64,
+}
+
 #[derive(Clone, Copy)]
 pub struct Circle {
     pub center: Point,
@@ -54,6 +78,24 @@ impl CirclePositioner {
     }
 }
 
+/// Turns `circle` counterclockwise by `angle` radians about `center`.
+pub fn rotate_about(circle: Circle, center: Point, angle: f64) -> Circle {
+    let p = rotate(
+        Point {
+            x: circle.center.x - center.x,
+            y: circle.center.y - center.y,
+        },
+        angle,
+    );
+    Circle {
+        center: Point {
+            x: p.x + center.x,
+            y: p.y + center.y,
+        },
+        radius: circle.radius,
+    }
+}
+
 fn rotate(p: Point, angle: f64) -> Point {
     let fac = angle.cos();
     let fas = angle.sin();
@@ -260,6 +302,17 @@ fn position_circle_n(
             enclosed_circles,
             focus_angle,
         ),
+        Layout::Rings(RingsConfig { radius, positions }) => {
+            let Polar { distance, angle } = positions[n as usize];
+            let theta = angle + focus_angle;
+            Circle {
+                center: Point {
+                    x: center.x + distance * theta.cos(),
+                    y: center.y + distance * theta.sin(),
+                },
+                radius: *radius,
+            }
+        }
     }
 }
 
@@ -290,6 +343,15 @@ impl Iterator for CirclePositioner {
 fn make_circle_layout(enclosing_radius: f64, enclosed_circles: u64, zoom: f64) -> Layout {
     if zoom == 0.0 {
         let (radius, angle) = fit_equal_circles(enclosing_radius, enclosed_circles);
+        if enclosed_circles > RING_THRESHOLD {
+            let (rings_radius, positions) = fit_ring_circles(enclosing_radius, enclosed_circles);
+            if rings_radius > radius {
+                return Layout::Rings(RingsConfig {
+                    radius: rings_radius,
+                    positions,
+                });
+            }
+        }
         Layout::Equal(EqualConfig { radius, angle })
     } else {
         let zoomed_radius = calculate_zoomed_radius(enclosing_radius, enclosed_circles, zoom);
@@ -387,6 +449,198 @@ fn fit_equal_circles(outer_radius: f64, inner_circle_count: u64) -> (f64, f64) {
     }
 }
 
+/// Returns the distance from the center of an enclosing circle of each ring of
+/// circles of radius `radius` that fits inside of it, alongside the number of
+/// circles that fit on that ring. The outermost ring comes first.
+///
+/// Each ring touches the one outside of it. If the innermost ring would be too
+/// small to hold two circles, a single circle is placed at the center instead.
+fn ring_capacities(enclosing_radius: f64, radius: f64) -> Vec<(f64, u64)> {
+    use std::f64::consts::PI;
+
+    let mut rings = Vec::new();
+    let mut distance = enclosing_radius - radius;
+    while distance >= 0.0 {
+        if distance < radius {
+            rings.push((0.0, 1));
+            break;
+        }
+        // Each circle on the ring takes up an angle of 2 * asin(radius /
+        // distance). The small constant keeps rounding errors from losing a
+        // circle when they fit exactly.
+        let capacity = (PI / (radius / distance).asin() + 1e-9).floor() as u64;
+        rings.push((distance, capacity));
+        distance -= 2.0 * radius;
+    }
+    rings
+}
+
+/// Fits `enclosed_circles` circles (each of the same size) inside of an
+/// enclosing circle, arranged in several concentric rings. Returns the radius
+/// of each circle and the position of each circle relative to the center of
+/// the enclosing circle.
+///
+/// When there are many circles, a single ring (see [`CirclePositioner`]) leaves
+/// the inside of the enclosing circle empty. Filling it with more rings lets
+/// each circle be much bigger.
+///
+/// The radius is made as large as possible. The outer rings are filled first,
+/// and the circles on each ring are evenly spaced, starting from the left of
+/// the enclosing circle (the same as the first circle of a
+/// [`CirclePositioner`]) and going counterclockwise.
+pub fn fit_ring_circles(enclosing_radius: f64, enclosed_circles: u64) -> (f64, Vec<Polar>) {
+    use std::f64::consts::PI;
+
+    if enclosing_radius <= 0.0 || enclosed_circles == 0 {
+        return (0.0, vec![]);
+    }
+
+    let fits = |radius: f64| -> bool {
+        ring_capacities(enclosing_radius, radius)
+            .iter()
+            .map(|&(_, capacity)| capacity)
+            .sum::<u64>()
+            >= enclosed_circles
+    };
+
+    // A single ring always fits, so its radius is a lower bound. The number of
+    // circles that fit doesn't always shrink as the radius grows, so this might
+    // not find the very largest radius, but it never finds one that doesn't
+    // fit.
+    let (mut low, _) = fit_equal_circles(enclosing_radius, enclosed_circles);
+    let mut high = enclosing_radius;
+    for _ in 0..53 {
+        let mid = 0.5 * (low + high);
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    let radius = low;
+
+    let mut remaining = enclosed_circles;
+    let mut positions = Vec::with_capacity(enclosed_circles as usize);
+    for (distance, capacity) in ring_capacities(enclosing_radius, radius) {
+        let count = capacity.min(remaining);
+        let step = 2.0 * PI / count as f64;
+        for i in 0..count {
+            positions.push(Polar {
+                distance,
+                angle: PI + i as f64 * step,
+            });
+        }
+        remaining -= count;
+        if remaining == 0 {
+            break;
+        }
+    }
+    (radius, positions)
+}
+
+/// Fits circles along the inside circumference of a larger enclosing circle,
+/// like [`CirclePositioner`], except that the radius of each circle is
+/// proportional to its weight.
+///
+/// Each circle touches the enclosing circle. The radii are made as large as
+/// possible without any of the circles overlapping. The first circle is placed
+/// on the left of the enclosing circle (the same as the first circle of a
+/// [`CirclePositioner`]), and the rest follow counterclockwise in the order of
+/// `weights`. Negative weights are treated as zero. If every weight is zero,
+/// the circles are all the same size.
+///
+/// Finding the radii places every circle dozens of times, checking it against
+/// every circle before it, so this takes time quadratic in the number of
+/// circles. It is meant for small sets.
+pub fn fit_weighted_circles(enclosing_radius: f64, center: Point, weights: &[f64]) -> Vec<Circle> {
+    use std::f64::consts::PI;
+
+    let total: f64 = weights.iter().map(|w| w.max(0.0)).sum();
+    let weights: Vec<f64> = if total > 0.0 {
+        weights.iter().map(|w| w.max(0.0) / total).collect()
+    } else {
+        vec![1.0; weights.len()]
+    };
+    let n = weights.len();
+    if n == 0 || enclosing_radius <= 0.0 {
+        return vec![];
+    } else if n == 1 {
+        return vec![Circle {
+            center,
+            radius: enclosing_radius,
+        }];
+    }
+
+    // The angle between the centers of two neighbouring circles (as seen from
+    // the center of the enclosing circle) that makes them touch, given that
+    // their radii are `weight * scale`.
+    let angle = |a: f64, b: f64, scale: f64| -> f64 {
+        let (ra, rb) = (a * scale, b * scale);
+        let (da, db) = (enclosing_radius - ra, enclosing_radius - rb);
+        if da <= 0.0 || db <= 0.0 {
+            PI
+        } else {
+            // Law of cosines on the triangle formed by the three centers.
+            let cos = (da * da + db * db - (ra + rb) * (ra + rb)) / (2.0 * da * db);
+            cos.max(-1.0).min(1.0).acos()
+        }
+    };
+    // Places each circle at the smallest angle (counterclockwise from the
+    // first circle) that keeps it from overlapping any of the circles before
+    // it. Neighbours aren't enough to check, since a small circle between two
+    // big ones doesn't keep the big ones apart. Also returns the angle needed
+    // to go all the way around back to the first circle.
+    let place = |scale: f64| -> (Vec<f64>, f64) {
+        let mut thetas: Vec<f64> = Vec::with_capacity(n);
+        for i in 0..n {
+            let theta = (0..i)
+                .map(|j| thetas[j] + angle(weights[j], weights[i], scale))
+                .fold(0.0, f64::max);
+            thetas.push(theta);
+        }
+        let total = (1..n)
+            .map(|j| thetas[j] + angle(weights[j], weights[0], scale))
+            .fold(0.0, f64::max);
+        (thetas, total)
+    };
+
+    // Two circles can't both touch the enclosing circle without overlapping
+    // if their radii add up to more than the enclosing radius, so the two
+    // biggest circles limit how big the circles can get. Within that limit,
+    // we search for the scale that makes the circles go exactly once around.
+    let mut sorted = weights.clone();
+    sorted.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    let max_scale = enclosing_radius / (sorted[0] + sorted[1]);
+    let (mut low, mut high) = (0.0, max_scale);
+    for _ in 0..53 {
+        let mid = 0.5 * (low + high);
+        if place(mid).1 > 2.0 * PI {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    let scale = low;
+
+    // If the circles don't go all the way around, spread out the gap evenly.
+    let (thetas, total) = place(scale);
+    let gap = (2.0 * PI - total).max(0.0) / n as f64;
+    (0..n)
+        .map(|i| {
+            let theta = PI + thetas[i] + gap * i as f64;
+            let radius = weights[i] * scale;
+            let dist = enclosing_radius - radius;
+            Circle {
+                center: Point {
+                    x: center.x + dist * theta.cos(),
+                    y: center.y + dist * theta.sin(),
+                },
+                radius,
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -467,6 +721,81 @@ mod tests {
         assert!(approx_eq!(f64, t, 0.302, ulps = 3, epsilon = 0.001));
     }
 
+    #[test]
+    fn weighted_circles_equal_weights() {
+        let origin = Point { x: 0.0, y: 0.0 };
+        let circles = fit_weighted_circles(100.0, origin, &[1.0; 8]);
+        let (radius, _) = fit_equal_circles(100.0, 8);
+        assert_eq!(8, circles.len());
+        for circle in circles {
+            assert!(approx_eq!(f64, circle.radius, radius, epsilon = 0.001));
+        }
+    }
+
+    #[test]
+    fn weighted_circles_two() {
+        let origin = Point { x: 0.0, y: 0.0 };
+        let circles = fit_weighted_circles(100.0, origin, &[1.0, 3.0]);
+        assert!(approx_eq!(f64, circles[0].radius, 25.0, epsilon = 0.001));
+        assert!(approx_eq!(f64, circles[1].radius, 75.0, epsilon = 0.001));
+        assert!(approx_eq!(f64, circles[0].center.x, -75.0, epsilon = 0.001));
+        assert!(approx_eq!(f64, circles[1].center.x, 25.0, epsilon = 0.001));
+    }
+
+    #[test]
+    fn weighted_circles_dont_overlap() {
+        let origin = Point { x: 0.0, y: 0.0 };
+        let circles = fit_weighted_circles(100.0, origin, &[5.0, 1.0, 2.0, 1.0, 3.0]);
+        for (i, a) in circles.iter().enumerate() {
+            let from_origin = (a.center.x.powi(2) + a.center.y.powi(2)).sqrt();
+            assert!(from_origin + a.radius <= 100.001);
+            for b in circles.iter().skip(i + 1) {
+                let dist =
+                    ((a.center.x - b.center.x).powi(2) + (a.center.y - b.center.y).powi(2)).sqrt();
+                assert!(dist >= a.radius + b.radius - 0.001);
+            }
+        }
+    }
+
+    #[test]
+    fn ring_circles_dont_overlap() {
+        let (radius, positions) = fit_ring_circles(100.0, 100);
+        assert_eq!(100, positions.len());
+        let centers: Vec<Point> = positions
+            .iter()
+            .map(|p| Point {
+                x: p.distance * p.angle.cos(),
+                y: p.distance * p.angle.sin(),
+            })
+            .collect();
+        for (i, a) in centers.iter().enumerate() {
+            assert!((a.x.powi(2) + a.y.powi(2)).sqrt() + radius <= 100.001);
+            for b in centers.iter().skip(i + 1) {
+                let dist = ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt();
+                assert!(dist >= 2.0 * radius - 0.001);
+            }
+        }
+    }
+
+    #[test]
+    fn ring_circles_beat_single_ring() {
+        let (single_radius, _) = fit_equal_circles(100.0, 100);
+        let (radius, _) = fit_ring_circles(100.0, 100);
+        assert!(radius > 2.0 * single_radius);
+        // The positioner should pick the rings, too.
+        let circles: Vec<Circle> =
+            CirclePositioner::new(100.0, 100, 0.0, Point { x: 0.0, y: 0.0 }, 0.0).collect();
+        assert_eq!(100, circles.len());
+        assert!(approx_eq!(f64, circles[0].radius, radius, epsilon = 0.001));
+    }
+
+    #[test]
+    fn one_ring_circle() {
+        let (radius, positions) = fit_ring_circles(100.0, 1);
+        assert!(approx_eq!(f64, radius, 100.0, epsilon = 0.001));
+        assert!(approx_eq!(f64, positions[0].distance, 0.0, epsilon = 0.001));
+    }
+
     #[test]
     fn zoomed_radius_full_zoom() {
         let zoomed_radius = calculate_zoomed_radius(100.0, 3, 1.0);
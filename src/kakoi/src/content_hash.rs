@@ -0,0 +1,139 @@
+//! # Content hashes
+//!
+//! Strings, numbers, images, linked images, files (see [`crate::blob`]) and
+//! custom leaves (see [`crate::leaf`]) are only ever inserted into an
+//! [`Arena`] once: the arena remembers the [`ContentHash`] of each of them,
+//! and inserting something that hashes the same as a value that is already
+//! there returns that value instead.
+//!
+//! The standard library's hasher is free to change between Rust releases,
+//! and hashes integers in the byte order of whatever machine it runs on, so
+//! its hashes can't be saved alongside an arena. Content hashes are the first
+//! 128 bits of a [BLAKE3] hash, which never changes, over bytes laid out the
+//! same way everywhere. Each kind of value is tagged, so that a string never
+//! hashes the same as a file holding the same bytes.
+//!
+//! Nobody knows how to make two values that hash the same, but the arena
+//! still compares the contents of values that do before treating them as
+//! one, so a collision would only mean that the second value isn't
+//! deduplicated.
+//!
+//! [`Arena`]: crate::arena::Arena
+//! [BLAKE3]: https://github.com/BLAKE3-team/BLAKE3
+
+use std::convert::TryInto;
+use std::fmt;
+use std::hash::Hasher;
+use std::path::Path;
+
+/// A stable hash of the contents of a leaf value. See [the module-level
+/// documentation](crate::content_hash).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ContentHash(pub u128);
+
+impl ContentHash {
+    pub fn of_string(string: &str) -> Self {
+        Self::tagged("string", string.as_bytes())
+    }
+
+    pub fn of_number(number: f64) -> Self {
+        Self::tagged("number", &number.to_bits().to_le_bytes())
+    }
+
+    /// The hash of a file holding `bytes`. What the file was called, and its
+    /// type, are left out, so that the same file is only kept once.
+    pub fn of_blob(bytes: &[u8]) -> Self {
+        Self::tagged("blob", bytes)
+    }
+
+    pub fn of_image(image: &image::RgbaImage) -> Self {
+        let mut hasher = ContentHasher::tagged("image");
+        hasher.write(&image.width().to_le_bytes());
+        hasher.write(&image.height().to_le_bytes());
+        hasher.write(image.as_raw());
+        hasher.finish_content()
+    }
+
+    /// The hash of a reference to the image file at `path`. The file itself
+    /// isn't read, so two paths to the same file hash differently.
+    pub fn of_image_ref(path: &Path) -> Self {
+        Self::tagged("image_ref", path.to_string_lossy().as_bytes())
+    }
+
+    fn tagged(tag: &str, bytes: &[u8]) -> Self {
+        let mut hasher = ContentHasher::tagged(tag);
+        hasher.write(bytes);
+        hasher.finish_content()
+    }
+}
+
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+/// Computes a [`ContentHash`] from the bytes written into it.
+///
+/// It is also a [`Hasher`], so that custom leaves can feed their contents
+/// into it (see [`CustomLeaf::hash_contents`]). Their hashes are only as
+/// stable as what they write: the integer methods of [`Hasher`] write in the
+/// machine's byte order, so leaves meant to be saved should write bytes.
+///
+/// [`CustomLeaf::hash_contents`]: crate::leaf::CustomLeaf::hash_contents
+#[derive(Debug, Clone)]
+pub struct ContentHasher {
+    hasher: blake3::Hasher,
+}
+
+impl ContentHasher {
+    /// Starts hashing a value of the kind named `tag`.
+    pub fn tagged(tag: &str) -> Self {
+        let mut hasher = Self {
+            hasher: blake3::Hasher::new(),
+        };
+        hasher.write(tag.as_bytes());
+        // Tags never contain a zero byte, so no tag is a prefix of another.
+        hasher.write(&[0]);
+        hasher
+    }
+
+    pub fn finish_content(&self) -> ContentHash {
+        let hash = self.hasher.finalize();
+        ContentHash(u128::from_be_bytes(
+            hash.as_bytes()[..16].try_into().unwrap(),
+        ))
+    }
+}
+
+impl Hasher for ContentHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        (self.finish_content().0 >> 64) as u64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stable() {
+        // The start of the BLAKE3 hash of "string\0a".
+        assert_eq!(
+            "e55220dfffb1ea2e657eb18e8a86441c",
+            ContentHash::of_string("a").to_string()
+        );
+        assert_eq!(ContentHash::of_string("a"), ContentHash::of_string("a"));
+        assert_ne!(ContentHash::of_string("a"), ContentHash::of_blob(b"a"));
+        assert_ne!(ContentHash::of_number(0.0), ContentHash::of_number(-0.0));
+        let image = |width, height| image::RgbaImage::new(width, height);
+        assert_ne!(
+            ContentHash::of_image(&image(2, 3)),
+            ContentHash::of_image(&image(3, 2))
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! # Render costs
+//!
+//! A set holding a few thousand strings, or a handful of large photos, can
+//! make every frame slow to draw, but nothing on screen says which value is to
+//! blame. [`CompleteAction::ToggleHeatmap`] colors a ring around everything
+//! drawn by how much it costs (see [`crate::render::heat`]), so that the
+//! expensive structures stand out.
+//!
+//! The [`Cost`] of an object counts everything laid out inside of it, along
+//! with the object itself:
+//!
+//! * how many objects there are,
+//! * roughly how many bytes of instance data are uploaded to draw them: a
+//!   ring, rectangle or image instance for each, or [`GLYPH_BYTES`] for each
+//!   character of a string,
+//! * and how many bytes the images among them take up on the GPU, at four
+//!   bytes a pixel.
+//!
+//! Only what is laid out is counted, so values too small to be drawn (see
+//! [`crate::spatial_tree`]) cost nothing, however large they are.
+//!
+//! The [`heat`] of an object is its share of the bytes of the layer it is in.
+//! The square root of the share is taken, so that objects with a small share
+//! still show up.
+//!
+//! [`CompleteAction::ToggleHeatmap`]: crate::input_manager::CompleteAction::ToggleHeatmap
+
+use crate::arena::{Arena, Structure};
+use crate::import::format_byte_size;
+use crate::render::circle::CircleConstraintInstance;
+use crate::render::image::RawTextureInstance;
+use crate::spatial_tree::LayoutNode;
+use std::mem::size_of;
+
+/// The size of the instance uploaded for each glyph of a string.
+pub const GLYPH_BYTES: usize = 52;
+
+/// What drawing an object and everything inside of it costs. See [the
+/// module-level documentation](crate::cost).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cost {
+    pub objects: usize,
+    pub instance_bytes: usize,
+    pub texture_bytes: usize,
+}
+
+impl Cost {
+    /// What drawing `node` alone costs.
+    fn of(arena: &Arena, node: &LayoutNode) -> Self {
+        let (instance_bytes, texture_bytes) = match &arena.slot_map[node.data.key].structure {
+            Structure::String(string) => (string.chars().count() * GLYPH_BYTES, 0),
+            Structure::Image(image) => {
+                let (width, height) = image.dimensions();
+                (
+                    size_of::<RawTextureInstance>(),
+                    width as usize * height as usize * 4,
+                )
+            }
+            // How large a linked image is isn't known until it is read.
+            Structure::ImageRef(_) => (size_of::<RawTextureInstance>(), 0),
+            _ => (size_of::<CircleConstraintInstance>(), 0),
+        };
+        Self {
+            objects: 1,
+            instance_bytes,
+            texture_bytes,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.instance_bytes + self.texture_bytes
+    }
+
+    fn add(&mut self, other: Cost) {
+        self.objects += other.objects;
+        self.instance_bytes += other.instance_bytes;
+        self.texture_bytes += other.texture_bytes;
+    }
+
+    /// Describes the cost, like `120 objects, 6.2 KB of instances, 8.3 MB of
+    /// textures`.
+    pub fn describe(&self) -> String {
+        format!(
+            "{} object{}, {} of instances, {} of textures",
+            self.objects,
+            if self.objects == 1 { "" } else { "s" },
+            format_byte_size(self.instance_bytes as u64),
+            format_byte_size(self.texture_bytes as u64)
+        )
+    }
+}
+
+/// Returns the cost of each of `nodes` along with everything inside of it, in
+/// the same order as `nodes`.
+pub fn costs(arena: &Arena, nodes: &[LayoutNode]) -> Vec<Cost> {
+    let mut costs: Vec<Cost> = nodes.iter().map(|node| Cost::of(arena, node)).collect();
+    // Every node comes after its parent, so going backwards adds each node to
+    // its parent only once everything inside of it has been added to it.
+    for (index, node) in nodes.iter().enumerate().rev() {
+        if let Some(parent) = node.parent {
+            let cost = costs[index];
+            costs[parent].add(cost);
+        }
+    }
+    costs
+}
+
+/// Returns how hot each of `nodes` is, from 0 for objects that cost nothing to
+/// 1 for the root of each layer, given the `costs` returned by [`costs`].
+pub fn heat(nodes: &[LayoutNode], costs: &[Cost]) -> Vec<f32> {
+    let mut totals = Vec::new();
+    for (node, cost) in nodes.iter().zip(costs) {
+        if node.parent.is_none() {
+            totals.resize(totals.len().max(node.root + 1), 0);
+            totals[node.root] = cost.bytes();
+        }
+    }
+    nodes
+        .iter()
+        .zip(costs)
+        .map(|(node, cost)| match totals.get(node.root) {
+            Some(&total) if total > 0 => (cost.bytes() as f32 / total as f32).sqrt(),
+            _ => 0.0,
+        })
+        .collect()
+}
+
+/// The total cost of everything in `nodes`, given the `costs` returned by
+/// [`costs`].
+pub fn total(nodes: &[LayoutNode], costs: &[Cost]) -> Cost {
+    let mut total = Cost::default();
+    for (node, cost) in nodes.iter().zip(costs) {
+        if node.parent.is_none() {
+            total.add(*cost);
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spatial_bound::SpatialBound;
+    use crate::spatial_tree::SpatialTreeData;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn adding_up() {
+        let mut arena = Arena::new();
+        let short = arena.string("ab");
+        let long = arena.string("abcdefgh");
+        let set = arena.set(vec![short, long]);
+        let node = |key, parent| LayoutNode {
+            data: SpatialTreeData {
+                key,
+                bounds: SpatialBound::Sphere(Sphere {
+                    center: (0.0, 0.0, 0.0).into(),
+                    radius: 1.0,
+                }),
+            },
+            parent,
+            depth: parent.map_or(0, |_| 1),
+            root: 0,
+        };
+        let nodes = [node(set, None), node(short, Some(0)), node(long, Some(0))];
+        let costs = costs(&arena, &nodes);
+        let ring = size_of::<CircleConstraintInstance>();
+        assert_eq!(
+            Cost {
+                objects: 3,
+                instance_bytes: ring + 10 * GLYPH_BYTES,
+                texture_bytes: 0,
+            },
+            costs[0]
+        );
+        assert_eq!(costs[0], total(&nodes, &costs));
+        let heat = heat(&nodes, &costs);
+        assert_eq!(1.0, heat[0]);
+        assert!(heat[1] < heat[2] && heat[2] < 1.0);
+    }
+}
@@ -0,0 +1,229 @@
+//! # Crash reports
+//!
+//! A bug that reaches one of Kakoi's `unwrap`s or `unreachable!`s panics,
+//! which closes the window. Nothing saves the arena as Kakoi runs (it is only
+//! written out on request, see [`crate::json`]), so everything in the arena
+//! would be lost with it. [`install`] makes the best of a panic:
+//!
+//! * The panic hook writes a bug report into a new directory in the system's
+//!   temporary directory, named like `kakoi-crash-1700000000-1234`. Its
+//!   [`REPORT`] file holds the panic message and where it happened, a
+//!   backtrace, the [`Options`] the window was opened with, and the last
+//!   [`JOURNAL_LENGTH`] actions that were performed (see [`record`]). Actions
+//!   are recorded along with any text typed for them, so a report should be
+//!   read through before it is shared.
+//! * The window then writes the whole arena into the same directory as JSON
+//!   named [`ARENA`] (see [`crate::json`]), and what its base layer displays,
+//!   along with everything inside of it, as a Markdown outline named
+//!   [`OUTLINE`] (see [`crate::export`]). Actions that change many values at
+//!   once are performed inside of a transaction (see [`Arena::begin`]), which
+//!   is rolled back first, so that neither is written from a half-finished
+//!   change. If that panics too, the report is left without them.
+//! * The next time a window opens, it says where the report is and merges
+//!   [`ARENA`] back in (see [`crate::json::from_json`]), binding the
+//!   [`RESTORED_REGISTER`] to a map of the registers it had, the same way
+//!   [`CompleteAction::ImportJson`] would.
+//!
+//! The outline is only there to be read: unlike [`ARENA`], it loses
+//! registers, tags and locks, only links to images, and turns a value found
+//! in several places into several copies.
+//!
+//! [`CompleteAction::ImportJson`]: crate::input_manager::CompleteAction::ImportJson
+//! [`Options`]: crate::window::Options
+//! [`Arena::begin`]: crate::arena::Arena::begin
+
+use crate::window::Options;
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// How many of the most recent actions a report lists.
+pub const JOURNAL_LENGTH: usize = 50;
+
+/// The name of the file in a report's directory describing the panic.
+pub const REPORT: &str = "report.txt";
+
+/// The name of the file in a report's directory holding an outline of what
+/// was open when the panic happened.
+pub const OUTLINE: &str = "outline.md";
+
+/// The name of the file in a report's directory holding the whole arena as
+/// JSON, as it was when the panic happened.
+pub const ARENA: &str = "arena.json";
+
+/// The register bound to the registers of the arena written out by the last
+/// crash, once it has been merged back in.
+pub const RESTORED_REGISTER: &str = "restored";
+
+/// The name of an empty file in a report's directory that is removed once a
+/// window has said where the report is (see [`take_unseen`]).
+const UNSEEN: &str = "unseen";
+
+/// What every report's directory is named starting with.
+const PREFIX: &str = "kakoi-crash-";
+
+/// What a report needs to know that the panic hook can't find out for itself.
+struct Journal {
+    /// The [`Options`] the window was opened with, pretty-printed.
+    settings: String,
+    actions: VecDeque<String>,
+    /// The directory the report was written to, once there has been a panic.
+    bundle: Option<PathBuf>,
+}
+
+static JOURNAL: Mutex<Option<Journal>> = Mutex::new(None);
+
+/// Sets a panic hook that writes a report the first time anything panics,
+/// after the default hook has printed the panic as usual. See [the
+/// module-level documentation](crate::crash).
+pub fn install(options: &Options) {
+    *JOURNAL.lock().unwrap() = Some(Journal {
+        settings: format!("{:#?}", options),
+        actions: VecDeque::new(),
+        bundle: None,
+    });
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        // The journal can't be waited on, since this thread could have
+        // panicked while holding it.
+        let mut journal = match JOURNAL.try_lock() {
+            Ok(journal) => journal,
+            Err(_) => return,
+        };
+        let journal = match journal.as_mut() {
+            Some(journal) if journal.bundle.is_none() => journal,
+            _ => return,
+        };
+        let actions: Vec<String> = journal.actions.iter().cloned().collect();
+        let report = report(
+            &info.to_string(),
+            &Backtrace::force_capture().to_string(),
+            &journal.settings,
+            &actions,
+        );
+        match write_bundle(&report) {
+            Ok(dir) => {
+                eprintln!("wrote a crash report to {}", dir.display());
+                journal.bundle = Some(dir);
+            }
+            Err(e) => eprintln!("couldn't write a crash report: {}", e),
+        }
+    }));
+}
+
+/// Adds `action` to the actions listed in a report, forgetting the oldest
+/// if there are more than [`JOURNAL_LENGTH`]. Does nothing unless
+/// [`install`] has been called.
+pub fn record(action: &str) {
+    if let Some(journal) = JOURNAL.lock().unwrap().as_mut() {
+        journal.actions.push_back(action.to_string());
+        if journal.actions.len() > JOURNAL_LENGTH {
+            journal.actions.pop_front();
+        }
+    }
+}
+
+/// Returns the directory a report was written to, if there has been a panic
+/// since [`install`] was called.
+pub fn bundle() -> Option<PathBuf> {
+    let journal = JOURNAL.try_lock().ok()?;
+    journal.as_ref()?.bundle.clone()
+}
+
+/// Writes the text of a report.
+fn report(message: &str, backtrace: &str, settings: &str, actions: &[String]) -> String {
+    let mut report = format!(
+        "kakoi {}\n\n{}\n\nbacktrace:\n{}\n\nsettings:\n{}\n\nlast {} actions, oldest first:\n",
+        env!("CARGO_PKG_VERSION"),
+        message,
+        backtrace,
+        settings,
+        actions.len()
+    );
+    for action in actions {
+        report.push_str(action);
+        report.push('\n');
+    }
+    report
+}
+
+/// Creates a new directory for a report in the system's temporary directory,
+/// and writes `report` into it.
+fn write_bundle(report: &str) -> io::Result<PathBuf> {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let dir = std::env::temp_dir().join(format!("{}{}-{}", PREFIX, seconds, std::process::id()));
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(REPORT), report)?;
+    fs::write(dir.join(UNSEEN), "")?;
+    Ok(dir)
+}
+
+/// Returns the directory of the most recent report written to `parent` that
+/// no window has said anything about yet, marking every such report as seen.
+fn take_unseen_in(parent: &Path) -> Option<PathBuf> {
+    let mut unseen: Vec<(u64, PathBuf)> = fs::read_dir(parent)
+        .ok()?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?;
+            let seconds = name.strip_prefix(PREFIX)?.split('-').next()?.parse().ok()?;
+            Some((seconds, path)).filter(|(_, path)| path.join(UNSEEN).exists())
+        })
+        .collect();
+    unseen.sort();
+    for (_, dir) in &unseen {
+        if let Err(e) = fs::remove_file(dir.join(UNSEEN)) {
+            log::warn!("couldn't mark {} as seen: {}", dir.display(), e);
+        }
+    }
+    unseen.pop().map(|(_, dir)| dir)
+}
+
+/// Like [`take_unseen_in`], looking in the system's temporary directory,
+/// where reports are written.
+pub fn take_unseen() -> Option<PathBuf> {
+    take_unseen_in(&std::env::temp_dir())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reporting() {
+        let actions = vec!["Undo".to_string(), "Confirm".to_string()];
+        let report = report("panicked at arena.rs:1:1", "0: main", "Options", &actions);
+        assert!(report.contains("panicked at arena.rs:1:1\n"));
+        assert!(report.ends_with("last 2 actions, oldest first:\nUndo\nConfirm\n"));
+    }
+
+    #[test]
+    fn taking_unseen() {
+        let parent = std::env::temp_dir().join(format!("kakoi-test-{}", std::process::id()));
+        for (name, unseen) in &[
+            ("kakoi-crash-10-1", true),
+            ("kakoi-crash-30-1", false),
+            ("kakoi-crash-20-1", true),
+        ] {
+            let dir = parent.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            if *unseen {
+                fs::write(dir.join(UNSEEN), "").unwrap();
+            }
+        }
+        assert_eq!(
+            Some(parent.join("kakoi-crash-20-1")),
+            take_unseen_in(&parent)
+        );
+        assert_eq!(None, take_unseen_in(&parent));
+        fs::remove_dir_all(&parent).unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+//! # Duplicate detection
+//!
+//! Strings and images are only ever stored once (see [`Arena::string`]), but
+//! that doesn't stop an arena that has been used for a long time from
+//! collecting values that only differ by a typo, like `colour` and `color`,
+//! or identical images that were inserted without going through the usual
+//! deduplication. [`CompleteAction::FindDuplicates`] looks for both on a
+//! worker thread, and shows the pairs it finds in a
+//! [`LayerKind::Suggestion`] layer for review. Every pair of strings is
+//! compared, so only the first [`MAX_STRINGS`] are, and the review says how
+//! many were left out.
+//!
+//! Merging a pair puts the value that is kept everywhere the other one is
+//! contained (see [`Arena::merge_into`]), including the registers bound to
+//! it, which the review lists. The value that is contained in more places
+//! is kept, so that as little as possible changes.
+//!
+//! Only values that are contained somewhere are compared, since nothing
+//! would change by merging the others. Strings that name registers or tags
+//! are left out, since merging them would rename them, and so are locked
+//! values (see [`Arena::is_locked`]).
+//!
+//! [`CompleteAction::FindDuplicates`]: crate::input_manager::CompleteAction::FindDuplicates
+//! [`LayerKind::Suggestion`]: crate::overlay::LayerKind::Suggestion
+
+use crate::arena::{Arena, ArenaKey, Kind, MapRoute, Route, Structure};
+use crate::task::Progress;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// How many characters two strings may differ by (see [`edit_distance`]) to
+/// be near-duplicates. At most a quarter of the characters of the shorter
+/// string may differ, so that short strings like `a` and `b` aren't paired.
+pub const MAX_EDIT_DISTANCE: usize = 2;
+
+/// Every pair of strings is compared, so only this many strings are compared
+/// at most.
+pub const MAX_STRINGS: usize = 2048;
+
+/// The name of the [`Task`](crate::task::Task) finding duplicates.
+pub const TASK_NAME: &str = "find duplicates";
+
+/// Why two values were paired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// The strings differ by this many characters.
+    NearDuplicate(usize),
+    /// The images have the same pixels.
+    IdenticalImage,
+}
+
+/// Two values that look like duplicates of each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    /// The value that stays where it is.
+    pub keep: ArenaKey,
+    /// The value that is replaced by `keep` everywhere it is contained.
+    pub merge: ArenaKey,
+    pub reason: Reason,
+}
+
+/// The number of characters that have to be inserted, removed or replaced to
+/// turn `a` into `b` (their [Levenshtein distance]), or [`None`] if that is
+/// more than `limit`.
+///
+/// [Levenshtein distance]: https://en.wikipedia.org/wiki/Levenshtein_distance
+pub fn edit_distance(a: &str, b: &str, limit: usize) -> Option<usize> {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    if a.len().max(b.len()) - a.len().min(b.len()) > limit {
+        return None;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let replace = previous[j] + if ca == cb { 0 } else { 1 };
+            current[j + 1] = replace.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        // Every later row is at least as large as the smallest entry in this
+        // one, so give up early once it is over the limit.
+        if current.iter().min().map_or(false, |&min| min > limit) {
+            return None;
+        }
+        previous = current;
+    }
+    Some(previous[b.len()]).filter(|&distance| distance <= limit)
+}
+
+/// Returns true if the value bound to `key` should be compared at all. See
+/// [the module-level documentation](crate::dedup).
+fn is_candidate(arena: &Arena, key: ArenaKey) -> bool {
+    let inclusions = &arena.slot_map[key].inclusions;
+    !inclusions.is_empty()
+        && !inclusions
+            .iter()
+            .any(|(_, route)| *route == Route::Map(MapRoute::Key))
+        && !arena.is_locked(key)
+}
+
+/// What [`find`] looks through, copied out of an arena so that it can be
+/// searched on a worker thread (see [`TASK_NAME`]).
+pub struct Snapshot {
+    /// The strings to compare, at most [`MAX_STRINGS`] of them.
+    strings: Vec<(ArenaKey, String)>,
+    /// How many strings were left out to stay under [`MAX_STRINGS`].
+    skipped: usize,
+    /// Pairs of images with the same pixels. Comparing images takes about as
+    /// long as copying them would, so they are compared straight away.
+    images: Vec<(ArenaKey, ArenaKey, Reason)>,
+    /// How many places each value in a pair is contained.
+    inclusions: HashMap<ArenaKey, usize>,
+}
+
+impl Snapshot {
+    pub fn take(arena: &Arena) -> Self {
+        let mut strings: Vec<(ArenaKey, String)> = arena
+            .keys_of_kind(Kind::String)
+            .into_iter()
+            .filter(|&key| is_candidate(arena, key))
+            .filter_map(|key| match &arena.slot_map[key].structure {
+                Structure::String(string) => Some((key, string.to_string())),
+                _ => None,
+            })
+            .collect();
+        strings.sort_by_key(|&(key, _)| key);
+        let skipped = strings.len().saturating_sub(MAX_STRINGS);
+        strings.truncate(MAX_STRINGS);
+        let images = identical_images(arena);
+        let inclusions = strings
+            .iter()
+            .map(|&(key, _)| key)
+            .chain(images.iter().flat_map(|&(a, b, _)| vec![a, b]))
+            .map(|key| (key, arena.slot_map[key].inclusions.len()))
+            .collect();
+        Self {
+            strings,
+            skipped,
+            images,
+            inclusions,
+        }
+    }
+}
+
+/// The possible duplicates found by [`find`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicates {
+    pub candidates: Vec<Candidate>,
+    /// How many strings weren't compared, since only [`MAX_STRINGS`] are.
+    pub skipped: usize,
+}
+
+impl Duplicates {
+    /// Summarizes the candidates, like `3 possible duplicates (2 similar
+    /// strings, 1 identical image)`, saying how many strings weren't
+    /// compared, if any.
+    pub fn describe(&self) -> String {
+        let images = self
+            .candidates
+            .iter()
+            .filter(|candidate| candidate.reason == Reason::IdenticalImage)
+            .count();
+        let strings = self.candidates.len() - images;
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        let mut description = format!(
+            "{} possible duplicate{} ({} similar string{}, {} identical image{})",
+            self.candidates.len(),
+            plural(self.candidates.len()),
+            strings,
+            plural(strings),
+            images,
+            plural(images)
+        );
+        if self.skipped > 0 {
+            description += &format!(
+                "; only the first {} strings were compared, {} weren't",
+                MAX_STRINGS, self.skipped
+            );
+        }
+        description
+    }
+}
+
+/// Pairs of near-duplicate strings in `strings`, alongside how many
+/// characters they differ by. Returns [`None`] if `progress` is cancelled
+/// first.
+fn similar_strings(
+    strings: &[(ArenaKey, String)],
+    progress: &Progress,
+) -> Option<Vec<(ArenaKey, ArenaKey, Reason)>> {
+    let mut pairs = Vec::new();
+    for (i, (a, a_text)) in strings.iter().enumerate() {
+        if progress.is_cancelled() {
+            return None;
+        }
+        // Each string is compared with the ones after it, so the later ones
+        // go faster.
+        let done = 1.0 - ((strings.len() - i) as f32 / strings.len() as f32).powi(2);
+        progress.report(done);
+        for (b, b_text) in &strings[i + 1..] {
+            let shorter = a_text.chars().count().min(b_text.chars().count());
+            let limit = MAX_EDIT_DISTANCE.min(shorter / 4);
+            if let Some(distance) = edit_distance(a_text, b_text, limit) {
+                pairs.push((*a, *b, Reason::NearDuplicate(distance)));
+            }
+        }
+    }
+    Some(pairs)
+}
+
+/// Pairs of images with the same pixels.
+fn identical_images(arena: &Arena) -> Vec<(ArenaKey, ArenaKey, Reason)> {
+    let mut by_hash: HashMap<u64, Vec<ArenaKey>> = HashMap::new();
+    for key in arena.keys_of_kind(Kind::Image) {
+        if let Structure::Image(image) = &arena.slot_map[key].structure {
+            if is_candidate(arena, key) {
+                let mut hasher = DefaultHasher::new();
+                (image.dimensions(), image.as_raw()).hash(&mut hasher);
+                by_hash.entry(hasher.finish()).or_default().push(key);
+            }
+        }
+    }
+    let pixels = |key: ArenaKey| match &arena.slot_map[key].structure {
+        Structure::Image(image) => image,
+        _ => unreachable!(),
+    };
+    let mut pairs = Vec::new();
+    for mut keys in by_hash.into_values() {
+        keys.sort();
+        for (i, &a) in keys.iter().enumerate() {
+            for &b in &keys[i + 1..] {
+                if pixels(a) == pixels(b) {
+                    pairs.push((a, b, Reason::IdenticalImage));
+                }
+            }
+        }
+    }
+    pairs
+}
+
+/// Finds values in `snapshot` that look like duplicates of each other, most
+/// alike first. No value is merged into more than one other value, and no
+/// value that is merged away is kept in another pair. Returns [`None`] if
+/// `progress` is cancelled first.
+pub fn find(snapshot: Snapshot, progress: &Progress) -> Option<Duplicates> {
+    let mut pairs = snapshot.images;
+    pairs.extend(similar_strings(&snapshot.strings, progress)?);
+    pairs.sort_by_key(|&(a, b, reason)| {
+        let distance = match reason {
+            Reason::IdenticalImage => 0,
+            Reason::NearDuplicate(distance) => distance,
+        };
+        (distance, a, b)
+    });
+    let inclusions = |key: ArenaKey| snapshot.inclusions[&key];
+    let (mut kept, mut merged) = (HashSet::new(), HashSet::new());
+    let mut candidates = Vec::new();
+    for (a, b, reason) in pairs {
+        let (keep, merge) = if inclusions(b) > inclusions(a) {
+            (b, a)
+        } else {
+            (a, b)
+        };
+        if merged.contains(&keep) || merged.contains(&merge) || kept.contains(&merge) {
+            continue;
+        }
+        kept.insert(keep);
+        merged.insert(merge);
+        candidates.push(Candidate {
+            keep,
+            merge,
+            reason,
+        });
+    }
+    Some(Duplicates {
+        candidates,
+        skipped: snapshot.skipped,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn distances() {
+        assert_eq!(Some(0), edit_distance("same", "same", 2));
+        assert_eq!(Some(1), edit_distance("colour", "color", 2));
+        assert_eq!(Some(2), edit_distance("kitten", "sittin", 2));
+        assert_eq!(None, edit_distance("kitten", "sitting", 2));
+        assert_eq!(None, edit_distance("a", "abcd", 2));
+    }
+
+    #[test]
+    fn finding() {
+        let mut arena = Arena::new();
+        let strings = arena.insert_strings(vec!["colour", "color", "a", "b", "unrelated"]);
+        let set = arena.set(strings.clone());
+        // "color" is contained in more places, so it is kept
+        arena.set(vec![strings[1]]);
+        // strings that aren't contained anywhere are left alone
+        arena.string("colours");
+        let found = |arena: &Arena| find(Snapshot::take(arena), &Progress::unobserved()).unwrap();
+        let duplicates = found(&arena);
+        assert_eq!(0, duplicates.skipped);
+        assert_eq!(
+            vec![Candidate {
+                keep: strings[1],
+                merge: strings[0],
+                reason: Reason::NearDuplicate(1),
+            }],
+            duplicates.candidates
+        );
+        arena.set_locked(set, true);
+        assert!(found(&arena).candidates.is_empty());
+    }
+}
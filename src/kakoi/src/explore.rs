@@ -0,0 +1,174 @@
+//! # Exploring
+//!
+//! Exploring wanders around the arena by itself, displaying a different value
+//! every [`STEP_INTERVAL`], so that things that haven't been looked at in a
+//! while turn up again. It is a [random walk] along containment: each step
+//! goes from the value being displayed to one of the values inside of it, or
+//! to one of the containers it is inside of.
+//!
+//! Steps are biased away from values visited recently, so that the walk
+//! doesn't keep going back and forth between the same few values. The last
+//! few values the walk itself went through are avoided, and so are values the
+//! user visited recently, if usage is being tracked (see
+//! [`Arena::record_visit`]).
+//!
+//! The maps pairing registers with their values are never stepped into. They
+//! contain the names of registers, which aren't anything the user made, and
+//! they would otherwise join nearly everything to everything else.
+//!
+//! [random walk]: https://en.wikipedia.org/wiki/Random_walk
+
+use crate::arena::{Arena, ArenaKey, Structure};
+use crate::sample::SplitMix64;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long each value is displayed for before stepping to the next one.
+pub const STEP_INTERVAL: Duration = Duration::from_secs(4);
+
+/// How many of the values the walk went through are avoided.
+const TRAIL_LENGTH: usize = 8;
+
+/// How much less likely a step to a value the walk recently went through is
+/// than a step to any other value.
+const TRAIL_WEIGHT: f64 = 0.05;
+
+/// How many visits ago (see [`Arena::visits_recorded`]) a value has to have
+/// been visited by the user for a step to it to be half as likely as a step to
+/// a value that was never visited.
+const RECENCY_HALF_LIFE: f64 = 16.0;
+
+/// A random walk over the arena. See [the module-level
+/// documentation](crate::explore).
+pub struct Exploration {
+    rng: SplitMix64,
+    /// The values the walk went through most recently, the most recent last.
+    trail: VecDeque<ArenaKey>,
+    next_step: Instant,
+}
+
+impl Exploration {
+    /// Starts a walk whose steps are chosen according to `seed`. The first
+    /// step is taken [`STEP_INTERVAL`] after `now`.
+    pub fn new(seed: u64, now: Instant) -> Self {
+        Self {
+            rng: SplitMix64(seed),
+            trail: VecDeque::new(),
+            next_step: now + STEP_INTERVAL,
+        }
+    }
+
+    /// The values the walk went through most recently, the most recent last.
+    pub fn trail(&self) -> impl Iterator<Item = ArenaKey> + '_ {
+        self.trail.iter().copied()
+    }
+
+    /// When the next step should be taken.
+    pub fn next_step(&self) -> Instant {
+        self.next_step
+    }
+
+    /// Puts the next step off until [`STEP_INTERVAL`] after `now`, for when it
+    /// can't be taken yet.
+    pub fn postpone(&mut self, now: Instant) {
+        self.next_step = now + STEP_INTERVAL;
+    }
+
+    /// Returns the value to step to from `from`, scheduling the step after it
+    /// for [`STEP_INTERVAL`] after `now`. Returns [`None`] if there is nowhere
+    /// to go from `from`.
+    pub fn step(&mut self, arena: &Arena, from: ArenaKey, now: Instant) -> Option<ArenaKey> {
+        self.postpone(now);
+        self.trail.push_back(from);
+        if self.trail.len() > TRAIL_LENGTH {
+            self.trail.pop_front();
+        }
+        let neighbours = neighbours(arena, from);
+        let weights: Vec<f64> = neighbours
+            .iter()
+            .map(|&neighbour| self.weight(arena, neighbour))
+            .collect();
+        let mut choice = self.rng.unit() * weights.iter().sum::<f64>();
+        for (&neighbour, weight) in neighbours.iter().zip(weights) {
+            if choice < weight {
+                return Some(neighbour);
+            }
+            choice -= weight;
+        }
+        // Rounding can leave a sliver of `choice` over.
+        neighbours.last().copied()
+    }
+
+    /// How likely a step to `key` is, relative to the other steps that could
+    /// be taken.
+    fn weight(&self, arena: &Arena, key: ArenaKey) -> f64 {
+        let mut weight = 1.0;
+        if self.trail.contains(&key) {
+            weight *= TRAIL_WEIGHT;
+        }
+        let last_visited = arena.usage(key).last_visited;
+        if last_visited > 0 {
+            let age = (arena.visits_recorded() - last_visited) as f64;
+            weight *= (age + 1.0) / (age + 1.0 + RECENCY_HALF_LIFE);
+        }
+        weight
+    }
+}
+
+/// Returns the values inside of the value bound to `key`, followed by the
+/// containers it is inside of, leaving out register maps. Values are sorted,
+/// so that the same walk takes the same steps every time.
+fn neighbours(arena: &Arena, key: ArenaKey) -> Vec<ArenaKey> {
+    let value = &arena.slot_map[key];
+    let mut inside: Vec<ArenaKey> = match &value.structure {
+        Structure::Set(set) => set.iter().copied().collect(),
+        Structure::List(list) => list.to_vec(),
+        Structure::Command(command) => command.to_vec(),
+        Structure::Map(map) => map.iter().flat_map(|(&k, &v)| vec![k, v]).collect(),
+        Structure::String(_)
+        | Structure::Image(_)
+        | Structure::ImageRef(_)
+        | Structure::Number(_)
+        | Structure::Blob(_)
+        | Structure::Custom(_) => vec![],
+    };
+    let mut containers: Vec<ArenaKey> = value
+        .inclusions
+        .iter()
+        .map(|&(container, _)| container)
+        .collect();
+    let register_maps: Vec<ArenaKey> = arena.register_maps().collect();
+    if register_maps.contains(&key) {
+        inside.clear();
+    }
+    containers.retain(|container| !register_maps.contains(container));
+    for neighbours in &mut [&mut inside, &mut containers] {
+        neighbours.sort();
+        neighbours.dedup();
+    }
+    inside.extend(containers);
+    inside.retain(|&neighbour| neighbour != key);
+    inside
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn walks_along_containment() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_empty_set("a");
+        arena.set_insert_string("a", "b").unwrap();
+        let set = arena.register("a").unwrap();
+        let b = arena.string("b");
+        let name = arena.string("a");
+        let now = Instant::now();
+        let mut exploration = Exploration::new(0, now);
+        assert_eq!(now + STEP_INTERVAL, exploration.next_step());
+        // Only the register map holds the name of the register.
+        assert_eq!(None, exploration.step(&arena, name, now));
+        assert_eq!(Some(set), exploration.step(&arena, b, now));
+        assert_eq!(Some(b), exploration.step(&arena, set, now));
+    }
+}
@@ -0,0 +1,397 @@
+//! # Exporting outlines
+//!
+//! A value, along with everything inside of it, can be written out as a nested
+//! outline in Markdown or [org-mode], so that notes gathered in Kakoi can be
+//! published or edited with other tools. This is done with
+//! [`CompleteAction::Export`], or from the command line with `kakoi export`.
+//!
+//! * Containers become headings, titled with the names of the registers bound
+//!   to them (see [`Arena::register_names`]), or with what they are if no
+//!   register is bound to them. Containers nested more deeply than
+//!   [`MAX_HEADING_LEVEL`] become list items instead, with their contents
+//!   nested beneath them.
+//! * Strings become list items. Lists keep their order, while the members of
+//!   sets are sorted so that exporting the same set twice gives the same
+//!   outline.
+//! * Images are saved as PNGs into a directory next to the outline, named after
+//!   their [`Value::uuid`](crate::arena::Value::uuid)s, and linked to.
+//! * Files (see [`crate::blob`]) are written as their names.
+//! * Custom leaves are written as their descriptions (see
+//!   [`CustomLeaf::describe`](crate::leaf::CustomLeaf::describe)).
+//!
+//! A container found inside of itself is only written out once. Anything that
+//! isn't a value (like where values are drawn, or how often they are visited)
+//! is left out.
+//!
+//! [org-mode]: https://orgmode.org
+//! [`CompleteAction::Export`]: crate::input_manager::CompleteAction::Export
+
+use crate::arena::{Arena, ArenaKey, Kind, Structure};
+use crate::number;
+use crate::string_class::StringClass;
+use std::{fs, io, path::Path};
+
+/// The most deeply nested heading an outline has. Containers nested any more
+/// deeply become list items. Markdown has no headings past the sixth level.
+pub const MAX_HEADING_LEVEL: usize = 6;
+
+/// The markup an outline is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutlineFormat {
+    Markdown,
+    Org,
+}
+
+impl OutlineFormat {
+    /// Parses the name of a format: `markdown` (or `md`), or `org`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "markdown" | "md" => Some(OutlineFormat::Markdown),
+            "org" => Some(OutlineFormat::Org),
+            _ => None,
+        }
+    }
+
+    /// Chooses the format an outline saved at `path` should be written in
+    /// from its extension: org-mode for `.org` files, and Markdown otherwise.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("org") => OutlineFormat::Org,
+            _ => OutlineFormat::Markdown,
+        }
+    }
+
+    fn heading(self, level: usize) -> String {
+        match self {
+            OutlineFormat::Markdown => "#".repeat(level),
+            OutlineFormat::Org => "*".repeat(level),
+        }
+    }
+
+    fn image_link(self, path: &str) -> String {
+        match self {
+            OutlineFormat::Markdown => format!("![]({})", path),
+            OutlineFormat::Org => format!("[[file:{}]]", path),
+        }
+    }
+}
+
+/// A value written out as an outline, alongside the images it links to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outline {
+    pub text: String,
+    /// Each image linked to, alongside the path it is linked to by, relative
+    /// to the outline.
+    pub images: Vec<(ArenaKey, String)>,
+    /// How many headings the outline has.
+    pub headings: usize,
+    /// How many list items the outline has.
+    pub items: usize,
+}
+
+impl Outline {
+    /// Writes `root`, and everything inside of it, out as an outline in
+    /// `format`. Images are linked to as files inside of `images_dir`, but
+    /// aren't saved there (see [`export`]).
+    pub fn new(arena: &Arena, root: ArenaKey, format: OutlineFormat, images_dir: &str) -> Self {
+        let mut writer = Writer {
+            arena,
+            format,
+            images_dir,
+            ancestors: Vec::new(),
+            outline: Outline {
+                text: String::new(),
+                images: Vec::new(),
+                headings: 0,
+                items: 0,
+            },
+        };
+        if is_container(arena, root) {
+            writer.container(root, None, 1, 0);
+        } else {
+            let text = writer.leaf(root);
+            writer.item(0, None, &text);
+        }
+        writer.outline
+    }
+
+    /// A short description of the outline once saved to `path`, like
+    /// `"exported 3 headings, 24 items and 2 images to notes.md"`.
+    pub fn describe<P: AsRef<Path>>(&self, path: P) -> String {
+        format!(
+            "exported {} heading{}, {} item{} and {} image{} to {}",
+            self.headings,
+            plural(self.headings),
+            self.items,
+            plural(self.items),
+            self.images.len(),
+            plural(self.images.len()),
+            path.as_ref().display()
+        )
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+/// Writes `root` out as an outline saved at `path`. The images it links to are
+/// saved into a directory next to it named after it, like `notes_files` for
+/// `notes.md`, which is only created if there are any.
+pub fn export<P: AsRef<Path>>(
+    arena: &Arena,
+    root: ArenaKey,
+    path: P,
+    format: OutlineFormat,
+) -> io::Result<Outline> {
+    let path = path.as_ref();
+    let stem = path
+        .file_stem()
+        .map_or("outline".into(), |stem| stem.to_string_lossy());
+    let images_dir = format!("{}_files", stem);
+    let outline = Outline::new(arena, root, format, &images_dir);
+    if !outline.images.is_empty() {
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+        fs::create_dir_all(parent.join(&images_dir))?;
+        for (image, link) in &outline.images {
+            if let Structure::Image(image) = &arena.slot_map[*image].structure {
+                image
+                    .save(parent.join(link))
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            }
+        }
+    }
+    fs::write(path, &outline.text)?;
+    Ok(outline)
+}
+
+fn is_container(arena: &Arena, key: ArenaKey) -> bool {
+    matches!(
+        arena.slot_map[key].structure.kind(),
+        Kind::Set | Kind::List | Kind::Map | Kind::Command
+    )
+}
+
+struct Writer<'a> {
+    arena: &'a Arena,
+    format: OutlineFormat,
+    images_dir: &'a str,
+    /// The containers being written out, from the outermost inward, so that
+    /// containers inside of themselves aren't written out forever.
+    ancestors: Vec<ArenaKey>,
+    outline: Outline,
+}
+
+impl<'a> Writer<'a> {
+    /// Writes out `container` as a heading at `level`, or as a list item
+    /// nested `indent` levels deep once headings run out. `label` is the
+    /// map key it is the value of, if any.
+    fn container(
+        &mut self,
+        container: ArenaKey,
+        label: Option<String>,
+        level: usize,
+        indent: usize,
+    ) {
+        let title = self.title(container, label);
+        if self.ancestors.contains(&container) {
+            self.item(indent, None, &format!("{} (see above)", title));
+            return;
+        }
+        self.ancestors.push(container);
+        let children = self.children(container);
+        if level <= MAX_HEADING_LEVEL {
+            self.outline.text += &format!("{} {}\n\n", self.format.heading(level), title);
+            self.outline.headings += 1;
+            // Subheadings end the list of the heading above them, so leaves
+            // go before any of them.
+            let (containers, leaves): (Vec<_>, Vec<_>) = children
+                .into_iter()
+                .partition(|&(_, child)| is_container(self.arena, child));
+            for (label, leaf) in &leaves {
+                let text = self.leaf(*leaf);
+                self.item(0, label.as_deref(), &text);
+            }
+            if !leaves.is_empty() {
+                self.outline.text.push('\n');
+            }
+            for (label, child) in containers {
+                self.container(child, label, level + 1, 0);
+            }
+        } else {
+            self.item(indent, None, &title);
+            for (label, child) in children {
+                if is_container(self.arena, child) {
+                    self.container(child, label, level + 1, indent + 1);
+                } else {
+                    let text = self.leaf(child);
+                    self.item(indent + 1, label.as_deref(), &text);
+                }
+            }
+        }
+        self.ancestors.pop();
+    }
+
+    /// Writes out a list item nested `indent` levels deep. Lines after the
+    /// first are indented to line up with the first.
+    fn item(&mut self, indent: usize, label: Option<&str>, text: &str) {
+        let margin = "  ".repeat(indent);
+        let text = match label {
+            Some(label) => format!("{}: {}", label, text),
+            None => text.to_string(),
+        };
+        let mut lines = text.lines();
+        self.outline.text += &format!("{}- {}\n", margin, lines.next().unwrap_or(""));
+        for line in lines {
+            if line.is_empty() {
+                self.outline.text.push('\n');
+            } else {
+                self.outline.text += &format!("{}  {}\n", margin, line);
+            }
+        }
+        self.outline.items += 1;
+    }
+
+    /// The text of the list item that `leaf` becomes.
+    fn leaf(&mut self, leaf: ArenaKey) -> String {
+        let arena = self.arena;
+        let value = &arena.slot_map[leaf];
+        match &value.structure {
+            Structure::String(string) => match (self.format, arena.string_class(leaf)) {
+                (OutlineFormat::Org, StringClass::Code) => format!(
+                    "#+begin_src\n{}\n#+end_src",
+                    StringClass::Code.display(string)
+                ),
+                _ => string.to_string(),
+            },
+            Structure::Image(_) => {
+                let link = format!("{}/{}.png", self.images_dir, value.uuid);
+                let text = self.format.image_link(&link);
+                if !self.outline.images.iter().any(|&(image, _)| image == leaf) {
+                    self.outline.images.push((leaf, link));
+                }
+                text
+            }
+            // Linked images are already files, so they are linked to as-is.
+            Structure::ImageRef(path) => self.format.image_link(&path.to_string_lossy()),
+            Structure::Number(number) => number::format(*number),
+            Structure::Blob(blob) => blob.name.clone(),
+            Structure::Custom(custom) => custom.leaf.describe(),
+            _ => String::new(),
+        }
+    }
+
+    /// The heading of `container`: the names of the registers bound to it,
+    /// the map key it is the value of, or else what it is.
+    fn title(&self, container: ArenaKey, label: Option<String>) -> String {
+        let names = self.arena.register_names(container);
+        if !names.is_empty() {
+            return names.join(", ");
+        }
+        if let Some(label) = label {
+            return label;
+        }
+        let (kind, count) = match &self.arena.slot_map[container].structure {
+            Structure::Set(members) => ("set", members.len()),
+            Structure::List(elements) => ("list", elements.len()),
+            Structure::Map(map) => ("map", map.len()),
+            Structure::Command(elements) => ("command", elements.len()),
+            _ => ("value", 0),
+        };
+        format!("{} of {}", kind, count)
+    }
+
+    /// The values inside of `container`, in the order they are written out,
+    /// each alongside the map key it is the value of (if any).
+    fn children(&self, container: ArenaKey) -> Vec<(Option<String>, ArenaKey)> {
+        match &self.arena.slot_map[container].structure {
+            Structure::Set(members) => {
+                let mut members: Vec<ArenaKey> = members.iter().copied().collect();
+                members.sort_by_cached_key(|&member| self.sort_key(member));
+                members.into_iter().map(|member| (None, member)).collect()
+            }
+            Structure::List(elements) | Structure::Command(elements) => {
+                elements.iter().map(|&element| (None, element)).collect()
+            }
+            Structure::Map(map) => {
+                let mut entries: Vec<(String, ArenaKey)> = map
+                    .iter()
+                    .map(|(&key, &value)| (self.sort_key(key).1, value))
+                    .collect();
+                entries.sort();
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (Some(key), value))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Orders strings alphabetically, followed by everything else in the
+    /// order of their uuids.
+    fn sort_key(&self, key: ArenaKey) -> (bool, String) {
+        let value = &self.arena.slot_map[key];
+        match &value.structure {
+            Structure::String(string) => (false, string.to_string()),
+            _ => (true, value.uuid.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outlines() {
+        let mut arena = Arena::new();
+        let strings = ["b", "a", "line one\nline two"]
+            .iter()
+            .map(|string| arena.string(string))
+            .collect::<Vec<_>>();
+        let inner = arena.set(strings[..2].iter().copied());
+        let image = arena.image(image::RgbaImage::new(1, 1));
+        let root = arena.list(vec![strings[2], inner, image]);
+        arena.bind_register("notes", root);
+        let uuid = arena.uuid(image).unwrap();
+
+        let outline = Outline::new(&arena, root, OutlineFormat::Markdown, "notes_files");
+        assert_eq!(
+            outline.text,
+            format!(
+                "# notes\n\n- line one\n  line two\n- ![](notes_files/{}.png)\n\n## set of 2\n\n- a\n- b\n\n",
+                uuid
+            )
+        );
+        assert_eq!(
+            outline.images,
+            vec![(image, format!("notes_files/{}.png", uuid))]
+        );
+        assert_eq!((outline.headings, outline.items), (2, 4));
+
+        let outline = Outline::new(&arena, inner, OutlineFormat::Org, "notes_files");
+        assert_eq!(outline.text, "* set of 2\n\n- a\n- b\n\n");
+
+        // Containers too deeply nested for headings become nested items.
+        let mut nested = arena.list(vec![strings[1]]);
+        for _ in 0..MAX_HEADING_LEVEL {
+            nested = arena.list(vec![nested]);
+        }
+        let outline = Outline::new(&arena, nested, OutlineFormat::Markdown, "");
+        assert_eq!(outline.headings, MAX_HEADING_LEVEL);
+        assert!(outline.text.ends_with("- list of 1\n  - a\n"));
+
+        assert_eq!(OutlineFormat::from_path("notes.org"), OutlineFormat::Org);
+        assert_eq!(OutlineFormat::from_path("notes"), OutlineFormat::Markdown);
+        assert_eq!(OutlineFormat::parse("MD"), Some(OutlineFormat::Markdown));
+    }
+}
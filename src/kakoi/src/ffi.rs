@@ -0,0 +1,377 @@
+//! # C bindings
+//!
+//! Exposes the core [`Arena`] operations through a C ABI so that programs
+//! written in other languages can build arenas. Only available with the `ffi`
+//! feature. To build a shared library, run
+//!
+//! ```text
+//! cargo rustc --lib --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! Values are referred to by [`ArenaKey`]s, which are passed across the
+//! boundary as `uint64_t`s (see [`KeyData::as_ffi`]). Strings are
+//! nul-terminated and must be valid UTF-8. Functions that can fail return
+//! `false` and leave their output parameters alone.
+//!
+//! An arena built here is handed to the viewer by writing it out as JSON with
+//! [`kakoi_arena_export_json`] (see [`crate::json`]), which the viewer merges
+//! in with `control+shift+i`.
+
+use crate::arena::{Arena, ArenaKey, Structure};
+use crate::json;
+use slotmap::{Key, KeyData};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+/// Converts a key received from C into an [`ArenaKey`], returning [`None`] if
+/// it doesn't refer to a value in `arena`. Keys carry the version of their
+/// slot, so a key whose slot has been reused since is rejected rather than
+/// referring to whatever is there now.
+fn key(arena: &Arena, key: u64) -> Option<ArenaKey> {
+    let key = ArenaKey::from(KeyData::from_ffi(key));
+    if arena.slot_map.contains_key(key) {
+        Some(key)
+    } else {
+        None
+    }
+}
+
+/// Returns the set bound to `register`, or [`None`] if it is unbound or bound
+/// to something else.
+fn set(arena: &mut Arena, register: &str) -> Option<ArenaKey> {
+    let set = arena.register(register)?;
+    match arena.slot_map[set].structure {
+        Structure::Set(_) => Some(set),
+        _ => None,
+    }
+}
+
+/// Borrows a nul-terminated UTF-8 string received from C.
+///
+/// # Safety
+///
+/// `string` must be null or point to a nul-terminated string.
+unsafe fn to_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        None
+    } else {
+        CStr::from_ptr(string).to_str().ok()
+    }
+}
+
+/// Creates a new, empty arena. It must be freed with [`kakoi_arena_free`].
+#[no_mangle]
+pub extern "C" fn kakoi_arena_new() -> *mut Arena {
+    Box::into_raw(Box::new(Arena::new()))
+}
+
+/// Frees an arena created by [`kakoi_arena_new`].
+///
+/// # Safety
+///
+/// `arena` must be null or have been returned by [`kakoi_arena_new`], and
+/// must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn kakoi_arena_free(arena: *mut Arena) {
+    if !arena.is_null() {
+        drop(Box::from_raw(arena));
+    }
+}
+
+/// Inserts a string into the arena, writing its key to `out`.
+///
+/// # Safety
+///
+/// `arena` must be a live arena, `string` must be null or point to a
+/// nul-terminated string, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn kakoi_arena_string(
+    arena: *mut Arena,
+    string: *const c_char,
+    out: *mut u64,
+) -> bool {
+    match (arena.as_mut(), to_str(string)) {
+        (Some(arena), Some(string)) => {
+            *out = arena.string(string).data().as_ffi();
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Inserts a `width` by `height` image into the arena, writing its key to
+/// `out`. `rgba` holds the pixels row by row, four bytes per pixel.
+///
+/// # Safety
+///
+/// `arena` must be a live arena, `rgba` must point to `width * height * 4`
+/// readable bytes, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn kakoi_arena_image(
+    arena: *mut Arena,
+    width: u32,
+    height: u32,
+    rgba: *const u8,
+    out: *mut u64,
+) -> bool {
+    let arena = match arena.as_mut() {
+        Some(arena) if !rgba.is_null() => arena,
+        _ => return false,
+    };
+    let len = width as usize * height as usize * 4;
+    let pixels = std::slice::from_raw_parts(rgba, len).to_vec();
+    match image::RgbaImage::from_raw(width, height, pixels) {
+        Some(image) => {
+            *out = arena.image(image).data().as_ffi();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Inserts a new map into the arena pairing `keys[i]` with `values[i]` for
+/// each `i` below `count`, writing its key to `out`. Returns `false`,
+/// inserting nothing, if any of the keys or values aren't in the arena.
+///
+/// # Safety
+///
+/// `arena` must be a live arena, `keys` and `values` must each point to
+/// `count` readable `uint64_t`s, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn kakoi_arena_map(
+    arena: *mut Arena,
+    keys: *const u64,
+    values: *const u64,
+    count: usize,
+    out: *mut u64,
+) -> bool {
+    let arena = match arena.as_mut() {
+        Some(arena) if !keys.is_null() && !values.is_null() => arena,
+        _ => return false,
+    };
+    let keys = std::slice::from_raw_parts(keys, count);
+    let values = std::slice::from_raw_parts(values, count);
+    let entries = keys
+        .iter()
+        .zip(values)
+        .map(|(&k, &v)| Some((key(arena, k)?, key(arena, v)?)))
+        .collect::<Option<Vec<_>>>();
+    match entries {
+        Some(entries) => {
+            *out = arena.map(entries).data().as_ffi();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Writes the key of the value bound to `register` to `out`. Returns `false`
+/// if the register isn't bound.
+///
+/// # Safety
+///
+/// `arena` must be a live arena, `register` must be null or point to a
+/// nul-terminated string, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn kakoi_arena_register(
+    arena: *mut Arena,
+    register: *const c_char,
+    out: *mut u64,
+) -> bool {
+    match (arena.as_mut(), to_str(register)) {
+        (Some(arena), Some(register)) => match arena.register(register) {
+            Some(key) => {
+                *out = key.data().as_ffi();
+                true
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// Binds `register` to the value `key`.
+///
+/// # Safety
+///
+/// `arena` must be a live arena and `register` must be null or point to a
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn kakoi_arena_bind_register(
+    arena: *mut Arena,
+    register: *const c_char,
+    value: u64,
+) -> bool {
+    match (arena.as_mut(), to_str(register)) {
+        (Some(arena), Some(register)) => match key(arena, value) {
+            Some(value) => {
+                arena.bind_register(register, value);
+                true
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// Binds `register` to a new, empty set.
+///
+/// # Safety
+///
+/// `arena` must be a live arena and `register` must be null or point to a
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn kakoi_arena_bind_register_to_empty_set(
+    arena: *mut Arena,
+    register: *const c_char,
+) -> bool {
+    match (arena.as_mut(), to_str(register)) {
+        (Some(arena), Some(register)) => {
+            arena.bind_register_to_empty_set(register);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Inserts the value `key` into the set bound to `set_register`. Returns
+/// `false` if the register isn't bound to a set.
+///
+/// # Safety
+///
+/// `arena` must be a live arena and `set_register` must be null or point to a
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn kakoi_arena_set_insert(
+    arena: *mut Arena,
+    set_register: *const c_char,
+    value: u64,
+) -> bool {
+    match (arena.as_mut(), to_str(set_register)) {
+        (Some(arena), Some(set_register)) => match (key(arena, value), set(arena, set_register)) {
+            (Some(value), Some(_)) => arena.set_insert_value(set_register, value).is_some(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Inserts `count` strings into the set bound to `set_register` in one go,
+/// which is much faster than inserting them one at a time (see
+/// [`Arena::insert_strings`] and [`Arena::set_insert_many`]). Returns `false`,
+/// inserting nothing, if the register isn't bound to a set or any of the
+/// strings are null or invalid.
+///
+/// # Safety
+///
+/// `arena` must be a live arena, `set_register` must be null or point to a
+/// nul-terminated string, and `strings` must point to `count` pointers that
+/// are each null or point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn kakoi_arena_set_insert_strings(
+    arena: *mut Arena,
+    set_register: *const c_char,
+    strings: *const *const c_char,
+    count: usize,
+) -> bool {
+    let (arena, set_register) = match (arena.as_mut(), to_str(set_register)) {
+        (Some(arena), Some(set_register)) if !strings.is_null() => (arena, set_register),
+        _ => return false,
+    };
+    let strings = std::slice::from_raw_parts(strings, count)
+        .iter()
+        .map(|&string| to_str(string))
+        .collect::<Option<Vec<_>>>();
+    // Check the set first, so that nothing is interned for a set that isn't
+    // there.
+    match (set(arena, set_register), strings) {
+        (Some(set), Some(strings)) => {
+            let keys = arena.insert_strings(strings);
+            arena.set_insert_many(set, keys).is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Writes the arena out as JSON to `path` (see [`json::export`]). Returns
+/// `false` if it couldn't be written.
+///
+/// # Safety
+///
+/// `arena` must be a live arena and `path` must be null or point to a
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn kakoi_arena_export_json(arena: *mut Arena, path: *const c_char) -> bool {
+    match (arena.as_ref(), to_str(path)) {
+        (Some(arena), Some(path)) => json::export(arena, path).is_ok(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn build_a_set() {
+        let register = CString::new("a").unwrap();
+        let hello = CString::new("hello").unwrap();
+        unsafe {
+            let arena = kakoi_arena_new();
+            let mut string = 0;
+            assert!(kakoi_arena_string(arena, hello.as_ptr(), &mut string));
+            assert!(kakoi_arena_bind_register_to_empty_set(
+                arena,
+                register.as_ptr()
+            ));
+            assert!(kakoi_arena_set_insert(arena, register.as_ptr(), string));
+            // "hello" was already inserted, so only "world" is new.
+            let words = [
+                CString::new("hello").unwrap(),
+                CString::new("world").unwrap(),
+            ];
+            let words = words.iter().map(|word| word.as_ptr()).collect::<Vec<_>>();
+            assert!(kakoi_arena_set_insert_strings(
+                arena,
+                register.as_ptr(),
+                words.as_ptr(),
+                words.len()
+            ));
+            // Keys that were never handed out are rejected.
+            assert!(!kakoi_arena_set_insert(arena, register.as_ptr(), u64::MAX));
+            // So are registers that aren't bound to sets.
+            let word = CString::new("word").unwrap();
+            assert!(kakoi_arena_bind_register(arena, word.as_ptr(), string));
+            assert!(!kakoi_arena_set_insert(arena, word.as_ptr(), string));
+            let unseen = [CString::new("unseen").unwrap()];
+            let unseen = unseen.iter().map(|word| word.as_ptr()).collect::<Vec<_>>();
+            assert!(!kakoi_arena_set_insert_strings(
+                arena,
+                word.as_ptr(),
+                unseen.as_ptr(),
+                unseen.len()
+            ));
+            assert_eq!(None, (*arena).lookup_string("unseen"));
+
+            let mut set = 0;
+            assert!(kakoi_arena_register(arena, register.as_ptr(), &mut set));
+            let set = key(&*arena, set).unwrap();
+            match &(*arena).slot_map[set].structure {
+                Structure::Set(set) => assert_eq!(2, set.len()),
+                _ => panic!(),
+            }
+
+            let mut map = 0;
+            let set = set.data().as_ffi();
+            assert!(kakoi_arena_map(arena, &string, &set, 1, &mut map));
+            assert!(!kakoi_arena_map(arena, &string, &u64::MAX, 1, &mut map));
+            let map = key(&*arena, map).unwrap();
+            match &(*arena).slot_map[map].structure {
+                Structure::Map(map) => assert_eq!(1, map.len()),
+                _ => panic!(),
+            }
+            kakoi_arena_free(arena);
+        }
+    }
+}
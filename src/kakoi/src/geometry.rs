@@ -0,0 +1,179 @@
+//! # Exporting layouts
+//!
+//! Where everything is drawn is decided by the [`SpatialTree`], which is
+//! rebuilt every time something changes and never shown directly. When a
+//! layout looks wrong, or when checking that a change to the layout code
+//! moves nothing it shouldn't, it helps to have the whole tree written down.
+//! [`CompleteAction::ExportLayout`] writes it out as JSON, and so does
+//! `kakoi --dump-layout FILE` once the first frame has been drawn.
+//!
+//! The JSON has three fields:
+//!
+//! * `screen`, the `width` and `height` of the window in logical pixels.
+//! * `camera`, the camera's `eye` and `target`, its `aspect` ratio, its
+//!   vertical field of view `fovy` in degrees, and its `view_projection`
+//!   matrix as a list of columns. The matrix takes the coordinates described
+//!   in [`crate::spatial_tree`] to clip space.
+//! * `nodes`, every object in the tree in the order of
+//!   [`SpatialTree::nodes`]. Each has the index of its `parent` in `nodes`
+//!   (or `null` for the root of a layer), the index of the `root` it
+//!   descends from, its `depth`, the `uuid` and `kind` of the value it
+//!   displays, its `center`, and its `shape`: either a `sphere` with a
+//!   `radius`, or a `rectangle` with a `width` and `height`.
+//!
+//! Numbers that aren't finite are written as `null`, since JSON has no way to
+//! write them.
+//!
+//! [`SpatialTree`]: crate::spatial_tree::SpatialTree
+//! [`SpatialTree::nodes`]: crate::spatial_tree::SpatialTree::nodes
+//! [`CompleteAction::ExportLayout`]: crate::input_manager::CompleteAction::ExportLayout
+
+use crate::arena::Arena;
+use crate::camera::Camera;
+use crate::spatial_bound::SpatialBound;
+use crate::spatial_tree::LayoutNode;
+use std::{fs, io, path::Path};
+
+/// Writes `value` as a JSON number.
+fn number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+/// Writes `values` as a JSON array of numbers.
+fn numbers<I: IntoIterator<Item = f32>>(values: I) -> String {
+    let values: Vec<String> = values.into_iter().map(number).collect();
+    format!("[{}]", values.join(", "))
+}
+
+fn node(arena: &Arena, node: &LayoutNode) -> String {
+    let value = &arena.slot_map[node.data.key];
+    let shape = match node.data.bounds {
+        SpatialBound::Sphere(sphere) => format!(
+            "\"center\": {}, \"shape\": \"sphere\", \"radius\": {}",
+            numbers(vec![sphere.center.x, sphere.center.y, sphere.center.z]),
+            number(sphere.radius)
+        ),
+        SpatialBound::SquareCuboid(cuboid) => format!(
+            "\"center\": {}, \"shape\": \"rectangle\", \"width\": {}, \"height\": {}",
+            numbers(vec![cuboid.center.x, cuboid.center.y, cuboid.center.z]),
+            number(cuboid.width()),
+            number(cuboid.height())
+        ),
+    };
+    format!(
+        "{{\"parent\": {}, \"root\": {}, \"depth\": {}, \"uuid\": \"{}\", \"kind\": \"{}\", {}}}",
+        node.parent
+            .map_or("null".to_string(), |parent| parent.to_string()),
+        node.root,
+        node.depth,
+        value.uuid,
+        value.structure.kind().name(),
+        shape
+    )
+}
+
+/// Describes `nodes`, laid out on a screen `screen_width` by `screen_height`
+/// logical pixels large and viewed through `camera`, as JSON. See [the
+/// module-level documentation](crate::geometry).
+pub fn to_json(
+    arena: &Arena,
+    nodes: &[LayoutNode],
+    camera: &mut Camera,
+    screen_width: f32,
+    screen_height: f32,
+) -> String {
+    let (eye, target) = (*camera.eye(), *camera.target());
+    let matrix = *camera.view_projection_matrix();
+    let columns: Vec<String> = (0..4)
+        .map(|i| numbers(vec![matrix[i].x, matrix[i].y, matrix[i].z, matrix[i].w]))
+        .collect();
+    let nodes: Vec<String> = nodes
+        .iter()
+        .map(|data| format!("    {}", node(arena, data)))
+        .collect();
+    format!(
+        "{{\n  \"screen\": {{\"width\": {}, \"height\": {}}},\n  \"camera\": {{\"eye\": {}, \"target\": {}, \"aspect\": {}, \"fovy\": {}, \"view_projection\": [{}]}},\n  \"nodes\": [\n{}\n  ]\n}}\n",
+        number(screen_width),
+        number(screen_height),
+        numbers(vec![eye.x, eye.y, eye.z]),
+        numbers(vec![target.x, target.y, target.z]),
+        number(camera.aspect()),
+        number(camera.fovy()),
+        columns.join(", "),
+        nodes.join(",\n")
+    )
+}
+
+/// Writes the JSON [`to_json`] describes to `path`, returning how many nodes
+/// were written.
+pub fn export<P: AsRef<Path>>(
+    path: P,
+    arena: &Arena,
+    nodes: &[LayoutNode],
+    camera: &mut Camera,
+    screen_width: f32,
+    screen_height: f32,
+) -> io::Result<usize> {
+    fs::write(
+        path,
+        to_json(arena, nodes, camera, screen_width, screen_height),
+    )?;
+    Ok(nodes.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spatial_tree::SpatialTreeData;
+    use crate::sphere::Sphere;
+    use crate::square_cuboid::SquareCuboid;
+
+    #[test]
+    fn writing() {
+        let mut arena = Arena::new();
+        let string = arena.string("hello");
+        let set = arena.set(vec![string]);
+        let nodes = [
+            LayoutNode {
+                data: SpatialTreeData {
+                    key: set,
+                    bounds: SpatialBound::Sphere(Sphere {
+                        center: (0.0, 0.0, 0.0).into(),
+                        radius: 1.0,
+                    }),
+                },
+                parent: None,
+                depth: 0,
+                root: 0,
+            },
+            LayoutNode {
+                data: SpatialTreeData {
+                    key: string,
+                    bounds: SpatialBound::SquareCuboid(SquareCuboid::from_dimensions(
+                        0.5,
+                        0.25,
+                        (0.0, 0.5, 0.0).into(),
+                    )),
+                },
+                parent: Some(0),
+                depth: 1,
+                root: 0,
+            },
+        ];
+        let mut camera = Camera::new(2.0);
+        let json = to_json(&arena, &nodes, &mut camera, 800.0, 400.0);
+        assert!(json.contains("\"screen\": {\"width\": 800, \"height\": 400}"));
+        assert!(json.contains("\"eye\": [0, 0, 2.5]"));
+        assert!(json.contains(&format!(
+            "{{\"parent\": null, \"root\": 0, \"depth\": 0, \"uuid\": \"{}\", \"kind\": \"set\", \"center\": [0, 0, 0], \"shape\": \"sphere\", \"radius\": 1}}",
+            arena.uuid(set).unwrap()
+        )));
+        assert!(json.contains("\"parent\": 0, \"root\": 0, \"depth\": 1"));
+        assert!(json.contains("\"shape\": \"rectangle\", \"width\": 0.5, \"height\": 0.25"));
+        assert_eq!("null", number(f32::NAN));
+    }
+}
@@ -0,0 +1,218 @@
+//! # Grouping suggestions
+//!
+//! Sets tend to grow into long, flat lists of loosely related values. Kakoi
+//! can look through the members of a set and suggest splitting them into
+//! groups of similar members, which the user can then accept or discard.
+//!
+//! Two members are similar if they have a lot in common structurally (see
+//! [`similarity`]), or, for strings, if their text is alike. Members are
+//! grouped by [single-linkage clustering]: any two members that are similar
+//! enough end up in the same group, along with everything either of them is
+//! grouped with.
+//!
+//! A suggestion is shown in a [`LayerKind::Suggestion`] layer as a new set
+//! containing a subset for each group (and, as-is, every member that wasn't
+//! grouped with anything). Accepting it replaces the members of the original
+//! set with those of the suggestion.
+//!
+//! [single-linkage clustering]: https://en.wikipedia.org/wiki/Single-linkage_clustering
+//! [`LayerKind::Suggestion`]: crate::overlay::LayerKind::Suggestion
+
+use crate::arena::{Arena, ArenaKey, Structure};
+use std::collections::{HashMap, HashSet};
+
+/// How similar two members must be (see [`similarity`]) to be grouped
+/// together.
+const SIMILARITY_THRESHOLD: f32 = 0.5;
+
+/// Every pair of members is compared, so sets larger than this aren't
+/// analyzed at all.
+pub const MAX_MEMBERS: usize = 1024;
+
+/// Returns the values directly inside of `key`, if it is a container.
+fn members(arena: &Arena, key: ArenaKey) -> Vec<ArenaKey> {
+    match &arena.slot_map[key].structure {
+        Structure::Set(set) => set.iter().copied().collect(),
+        Structure::List(list) => list.iter().copied().collect(),
+        Structure::Map(map) => map.iter().flat_map(|(&k, &v)| vec![k, v]).collect(),
+        Structure::Command(command) => command.iter().copied().collect(),
+        Structure::String(_)
+        | Structure::Image(_)
+        | Structure::ImageRef(_)
+        | Structure::Number(_)
+        | Structure::Blob(_)
+        | Structure::Custom(_) => vec![],
+    }
+}
+
+/// Describes `key` by what it contains and what it is contained in, leaving
+/// out the set being analyzed, which every member is inside of.
+fn features(arena: &Arena, set: ArenaKey, key: ArenaKey) -> HashSet<ArenaKey> {
+    let mut features: HashSet<ArenaKey> = members(arena, key).into_iter().collect();
+    features.extend(
+        arena.slot_map[key]
+            .inclusions
+            .iter()
+            .map(|&(container, _)| container)
+            .filter(|&container| container != set),
+    );
+    features
+}
+
+/// The size of the intersection of `a` and `b` divided by the size of their
+/// union, or zero if both are empty.
+fn jaccard<T: Eq + std::hash::Hash>(a: &HashSet<T>, b: &HashSet<T>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        0.0
+    } else {
+        a.intersection(b).count() as f32 / union as f32
+    }
+}
+
+/// Compares the pairs of adjacent characters in `a` and `b`, ignoring case
+/// (the [Sørensen–Dice coefficient] of their bigrams). Strings too short to
+/// have any bigrams are only similar if they are equal.
+///
+/// [Sørensen–Dice coefficient]: https://en.wikipedia.org/wiki/S%C3%B8rensen%E2%80%93Dice_coefficient
+fn string_similarity(a: &str, b: &str) -> f32 {
+    fn bigrams(string: &str) -> HashMap<(char, char), usize> {
+        let chars = string.to_lowercase().chars().collect::<Vec<_>>();
+        let mut bigrams = HashMap::new();
+        for pair in chars.windows(2) {
+            *bigrams.entry((pair[0], pair[1])).or_insert(0) += 1;
+        }
+        bigrams
+    }
+    let (a_bigrams, b_bigrams) = (bigrams(a), bigrams(b));
+    let total: usize = a_bigrams.values().chain(b_bigrams.values()).sum();
+    if total == 0 {
+        return if a.to_lowercase() == b.to_lowercase() {
+            1.0
+        } else {
+            0.0
+        };
+    }
+    let shared: usize = a_bigrams
+        .iter()
+        .map(|(bigram, &count)| count.min(b_bigrams.get(bigram).copied().unwrap_or(0)))
+        .sum();
+    2.0 * shared as f32 / total as f32
+}
+
+/// How alike the members `a` and `b` of `set` are, from zero to one. This is
+/// the larger of how much their contents and containers overlap, and (if both
+/// are strings) how alike their text is.
+pub fn similarity(arena: &Arena, set: ArenaKey, a: ArenaKey, b: ArenaKey) -> f32 {
+    let structural = jaccard(&features(arena, set, a), &features(arena, set, b));
+    let textual = match (&arena.slot_map[a].structure, &arena.slot_map[b].structure) {
+        (Structure::String(a), Structure::String(b)) => string_similarity(a, b),
+        _ => 0.0,
+    };
+    structural.max(textual)
+}
+
+/// Splits the members of the set bound to `set` into groups of similar
+/// members. Members that aren't similar to anything are in groups of their
+/// own.
+///
+/// Returns [`None`] if `set` isn't a set, is too large to analyze (see
+/// [`MAX_MEMBERS`]), or there is no useful grouping: every member is alone,
+/// or everything is in a single group.
+pub fn suggest(arena: &Arena, set: ArenaKey) -> Option<Vec<Vec<ArenaKey>>> {
+    let mut members = match &arena.slot_map.get(set)?.structure {
+        Structure::Set(set) if set.len() <= MAX_MEMBERS => set.iter().copied().collect::<Vec<_>>(),
+        _ => return None,
+    };
+    // Sets are unordered, so sort the members to make suggestions repeatable.
+    members.sort();
+
+    // Union-find over the indices of `members`.
+    let mut parents = (0..members.len()).collect::<Vec<_>>();
+    fn root(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+    for i in 0..members.len() {
+        for j in i + 1..members.len() {
+            if similarity(arena, set, members[i], members[j]) >= SIMILARITY_THRESHOLD {
+                let (ri, rj) = (root(&mut parents, i), root(&mut parents, j));
+                parents[rj] = ri;
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<ArenaKey>> = Vec::new();
+    let mut group_of_root = HashMap::new();
+    for (i, &member) in members.iter().enumerate() {
+        let r = root(&mut parents, i);
+        let group = *group_of_root.entry(r).or_insert_with(|| {
+            groups.push(vec![]);
+            groups.len() - 1
+        });
+        groups[group].push(member);
+    }
+
+    if groups.len() == 1 || groups.len() == members.len() {
+        None
+    } else {
+        Some(groups)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strings() {
+        assert!((string_similarity("Zebra", "zebras") - 8.0 / 9.0).abs() < 1e-6);
+        assert_eq!(0.0, string_similarity("apple", "zebra"));
+        assert_eq!(1.0, string_similarity("a", "A"));
+        assert_eq!(0.0, string_similarity("a", "b"));
+    }
+
+    #[test]
+    fn suggest_groups() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_empty_set("a");
+        for string in &["apple pie", "apple tart", "zebra", "zebras", "quux"] {
+            arena.set_insert_string("a", *string).unwrap();
+        }
+        let set = arena.register("a").unwrap();
+        let mut groups = suggest(&arena, set)
+            .unwrap()
+            .into_iter()
+            .map(|group| {
+                let mut group = group
+                    .into_iter()
+                    .map(|key| match &arena.slot_map[key].structure {
+                        Structure::String(string) => string.to_string(),
+                        _ => panic!(),
+                    })
+                    .collect::<Vec<_>>();
+                group.sort();
+                group
+            })
+            .collect::<Vec<_>>();
+        groups.sort();
+        assert_eq!(
+            vec![
+                vec!["apple pie".to_string(), "apple tart".to_string()],
+                vec!["quux".to_string()],
+                vec!["zebra".to_string(), "zebras".to_string()],
+            ],
+            groups
+        );
+
+        // Strings that have nothing in common aren't worth grouping.
+        arena.bind_register_to_empty_set("b");
+        arena.set_insert_string("b", "apple").unwrap();
+        arena.set_insert_string("b", "zebra").unwrap();
+        let set = arena.register("b").unwrap();
+        assert_eq!(None, suggest(&arena, set));
+    }
+}
@@ -0,0 +1,362 @@
+//! # Undo history
+//!
+//! Changes to the members of sets, whether made one at a time (like removing
+//! a value from a set, starring it, or putting the result of arithmetic into
+//! a set) or many at once by accepting a suggestion or a confirmation, can be
+//! undone with [`CompleteAction::Undo`], and redone with
+//! [`CompleteAction::Redo`]. So can changes to the elements of lists,
+//! rewriting strings, binding registers (including the first time something
+//! is starred or captured, which binds the register collecting them),
+//! changing maps (which is how tags are kept), locking values, and restoring
+//! an earlier version of a value (see [`Arena::restore_version`]). A
+//! [`History`] remembers the changes, most recent last.
+//!
+//! Local registers, and whatever is bound to `.`, aren't recorded, since
+//! they follow what is being looked at rather than what is kept. Neither is
+//! anything brought in from outside, like an imported file.
+//!
+//! The history doesn't know how to put a change back; whoever records it does.
+//! Each change is recorded as the change that puts it back, and putting a
+//! change back has to give the change that puts *that* back, so that the same
+//! changes go back and forth between undoing and redoing.
+//!
+//! Each step of the history is usually a single change. Changes recorded
+//! between [`History::begin_group`] and [`History::end_group`] make up a single
+//! step instead, so that anything that makes many changes at once (like a
+//! script, once Kakoi can run them) is undone all at once.
+//!
+//! Checkpoints name points in the history, like `before reorganizing
+//! projects`. [`CompleteAction::JumpToCheckpoint`] undoes or redoes every step
+//! between now and a checkpoint at once, and
+//! [`CompleteAction::Checkpoints`] lists them.
+//!
+//! Making a new change forgets every step that was undone, since it can't be
+//! redone on top of the new change, along with any checkpoints among them.
+//!
+//! [`Arena::restore_version`]: crate::arena::Arena::restore_version
+//! [`CompleteAction::Undo`]: crate::input_manager::CompleteAction::Undo
+//! [`CompleteAction::Redo`]: crate::input_manager::CompleteAction::Redo
+//! [`CompleteAction::JumpToCheckpoint`]: crate::input_manager::CompleteAction::JumpToCheckpoint
+//! [`CompleteAction::Checkpoints`]: crate::input_manager::CompleteAction::Checkpoints
+
+/// The most steps that can be undone. Older steps are forgotten.
+pub const MAX_STEPS: usize = 100;
+
+enum Entry<T> {
+    /// The changes that put back a step, in the order they were made.
+    Step(Vec<T>),
+    Checkpoint(String),
+}
+
+impl<T> Entry<T> {
+    fn is_checkpoint(&self, name: &str) -> bool {
+        matches!(self, Entry::Checkpoint(checkpoint) if checkpoint == name)
+    }
+}
+
+/// A checkpoint, as listed by [`History::checkpoints`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub name: String,
+    /// Whether the steps since the checkpoint (or, if it is undone, the steps
+    /// before it) have been undone.
+    pub undone: bool,
+    /// How many steps away the checkpoint is.
+    pub distance: usize,
+}
+
+/// Changes that can be undone and redone. See [the module-level
+/// documentation](crate::history).
+pub struct History<T> {
+    /// What can be undone, oldest first.
+    undo: Vec<Entry<T>>,
+    /// What can be redone, most recently undone last.
+    redo: Vec<Entry<T>>,
+    /// The changes recorded since [`History::begin_group`], if a group is
+    /// being recorded.
+    group: Option<Vec<T>>,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            group: None,
+        }
+    }
+}
+
+/// Moves the last entry of `from` onto `to`, putting its changes back with
+/// `put_back`. Returns false if `from` is empty.
+fn shift<T, F>(from: &mut Vec<Entry<T>>, to: &mut Vec<Entry<T>>, put_back: &mut F) -> bool
+where
+    F: FnMut(T) -> Option<T>,
+{
+    match from.pop() {
+        Some(Entry::Step(changes)) => {
+            // The changes are put back in the opposite order to the one they
+            // were made in, which makes the changes that put them back again
+            // come out in the right order.
+            let changes = changes.into_iter().rev().filter_map(put_back).collect();
+            to.push(Entry::Step(changes));
+            true
+        }
+        Some(checkpoint) => {
+            to.push(checkpoint);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Moves entries from `from` onto `to` up to and including the last step.
+/// Returns false, moving nothing, if `from` has no steps.
+fn step<T, F>(from: &mut Vec<Entry<T>>, to: &mut Vec<Entry<T>>, put_back: &mut F) -> bool
+where
+    F: FnMut(T) -> Option<T>,
+{
+    if !from.iter().any(|entry| matches!(entry, Entry::Step(_))) {
+        return false;
+    }
+    while let Some(Entry::Checkpoint(_)) = from.last() {
+        shift(from, to, put_back);
+    }
+    shift(from, to, put_back)
+}
+
+impl<T> History<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers `change`, which puts back something that was just changed.
+    pub fn record(&mut self, change: T) {
+        self.redo.clear();
+        match &mut self.group {
+            Some(group) => group.push(change),
+            None => self.push(Entry::Step(vec![change])),
+        }
+    }
+
+    fn push(&mut self, entry: Entry<T>) {
+        self.undo.push(entry);
+        let steps = self
+            .undo
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Step(_)))
+            .count();
+        if steps > MAX_STEPS {
+            let oldest = self
+                .undo
+                .iter()
+                .position(|entry| matches!(entry, Entry::Step(_)))
+                .unwrap();
+            self.undo.remove(oldest);
+        }
+    }
+
+    /// Starts recording changes as a single step, until
+    /// [`History::end_group`].
+    pub fn begin_group(&mut self) {
+        self.group.get_or_insert_with(Vec::new);
+    }
+
+    /// Finishes the step started by [`History::begin_group`].
+    pub fn end_group(&mut self) {
+        match self.group.take() {
+            Some(group) if !group.is_empty() => self.push(Entry::Step(group)),
+            _ => {}
+        }
+    }
+
+    /// Names this point in the history `name`, forgetting any other
+    /// checkpoint with the same name.
+    pub fn checkpoint(&mut self, name: &str) {
+        self.undo.retain(|entry| !entry.is_checkpoint(name));
+        self.redo.retain(|entry| !entry.is_checkpoint(name));
+        self.undo.push(Entry::Checkpoint(name.to_string()));
+    }
+
+    /// Undoes the last step, putting each of its changes back with
+    /// `put_back`. Returns false if there is nothing to undo.
+    pub fn undo<F: FnMut(T) -> Option<T>>(&mut self, mut put_back: F) -> bool {
+        step(&mut self.undo, &mut self.redo, &mut put_back)
+    }
+
+    /// Redoes the last step that was undone, like [`History::undo`].
+    pub fn redo<F: FnMut(T) -> Option<T>>(&mut self, mut put_back: F) -> bool {
+        step(&mut self.redo, &mut self.undo, &mut put_back)
+    }
+
+    /// Undoes or redoes every step between now and the checkpoint named
+    /// `name`, like [`History::undo`]. Returns how many steps were undone or
+    /// redone, or [`None`] if there is no such checkpoint.
+    pub fn jump<F: FnMut(T) -> Option<T>>(&mut self, name: &str, mut put_back: F) -> Option<usize> {
+        let mut steps = 0;
+        if self.undo.iter().any(|entry| entry.is_checkpoint(name)) {
+            while !self.undo.last()?.is_checkpoint(name) {
+                steps += matches!(self.undo.last(), Some(Entry::Step(_))) as usize;
+                shift(&mut self.undo, &mut self.redo, &mut put_back);
+            }
+        } else if self.redo.iter().any(|entry| entry.is_checkpoint(name)) {
+            while !self.redo.last()?.is_checkpoint(name) {
+                steps += matches!(self.redo.last(), Some(Entry::Step(_))) as usize;
+                shift(&mut self.redo, &mut self.undo, &mut put_back);
+            }
+            shift(&mut self.redo, &mut self.undo, &mut put_back);
+        } else {
+            return None;
+        }
+        Some(steps)
+    }
+
+    /// The changes [`History::undo`] would put back.
+    pub fn next_undo(&self) -> Vec<&T> {
+        Self::next_step(&self.undo)
+    }
+
+    /// The changes [`History::redo`] would put back.
+    pub fn next_redo(&self) -> Vec<&T> {
+        Self::next_step(&self.redo)
+    }
+
+    /// Every change that can be undone or redone, in no particular order.
+    pub fn changes(&self) -> Vec<&T> {
+        self.undo
+            .iter()
+            .chain(&self.redo)
+            .flat_map(|entry| match entry {
+                Entry::Step(changes) => changes.iter().collect(),
+                Entry::Checkpoint(_) => vec![],
+            })
+            .chain(self.group.iter().flatten())
+            .collect()
+    }
+
+    fn next_step(entries: &[Entry<T>]) -> Vec<&T> {
+        entries
+            .iter()
+            .rev()
+            .find_map(|entry| match entry {
+                Entry::Step(changes) => Some(changes.iter().collect()),
+                Entry::Checkpoint(_) => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// The changes [`History::jump`] would put back, in the order it would put
+    /// them back.
+    pub fn changes_to(&self, name: &str) -> Vec<&T> {
+        let entries = if self.undo.iter().any(|entry| entry.is_checkpoint(name)) {
+            &self.undo
+        } else {
+            &self.redo
+        };
+        entries
+            .iter()
+            .rev()
+            .take_while(|entry| !entry.is_checkpoint(name))
+            .flat_map(|entry| match entry {
+                Entry::Step(changes) => changes.iter().rev().collect(),
+                Entry::Checkpoint(_) => vec![],
+            })
+            .collect()
+    }
+
+    /// Every checkpoint, from the oldest to the newest.
+    pub fn checkpoints(&self) -> Vec<Checkpoint> {
+        let list = |entries: &[Entry<T>], undone: bool| {
+            let mut distance = 0;
+            let mut checkpoints = Vec::new();
+            for entry in entries.iter().rev() {
+                match entry {
+                    Entry::Step(_) => distance += 1,
+                    Entry::Checkpoint(name) => checkpoints.push(Checkpoint {
+                        name: name.clone(),
+                        undone,
+                        distance,
+                    }),
+                }
+            }
+            checkpoints
+        };
+        let mut checkpoints = list(&self.undo, false);
+        checkpoints.reverse();
+        checkpoints.extend(list(&self.redo, true));
+        checkpoints
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Changes a number, as recorded in a history of changes to it: `(from,
+    /// to)` puts the number back from `from` to `to`.
+    fn put_back(number: &mut i32) -> impl FnMut((i32, i32)) -> Option<(i32, i32)> + '_ {
+        move |(from, to)| {
+            assert_eq!(from, *number);
+            *number = to;
+            Some((to, from))
+        }
+    }
+
+    #[test]
+    fn undoing_and_redoing() {
+        let mut number = 0;
+        let mut history = History::new();
+        for next in 1..=3 {
+            history.record((next, number));
+            number = next;
+        }
+        assert!(history.undo(put_back(&mut number)));
+        assert!(history.undo(put_back(&mut number)));
+        assert_eq!(1, number);
+        assert!(history.redo(put_back(&mut number)));
+        assert_eq!(2, number);
+        // Recording a new change forgets what was undone.
+        history.record((5, number));
+        number = 5;
+        assert!(!history.redo(put_back(&mut number)));
+        assert!(history.undo(put_back(&mut number)));
+        assert_eq!(2, number);
+    }
+
+    #[test]
+    fn groups_and_checkpoints() {
+        let mut history = History::new();
+        history.record((1, 0));
+        history.checkpoint("one");
+        history.begin_group();
+        history.record((2, 1));
+        history.record((3, 2));
+        history.end_group();
+        history.checkpoint("three");
+        history.record((4, 3));
+        let mut number = 4;
+        assert_eq!(vec![&(4, 3), &(3, 2), &(2, 1)], history.changes_to("one"));
+        assert_eq!(Some(2), history.jump("one", put_back(&mut number)));
+        assert_eq!(1, number);
+        assert_eq!(
+            vec![
+                Checkpoint {
+                    name: "one".into(),
+                    undone: false,
+                    distance: 0,
+                },
+                Checkpoint {
+                    name: "three".into(),
+                    undone: true,
+                    distance: 1,
+                },
+            ],
+            history.checkpoints()
+        );
+        assert_eq!(Some(1), history.jump("three", put_back(&mut number)));
+        assert_eq!(3, number);
+        assert!(history.redo(put_back(&mut number)));
+        assert_eq!(4, number);
+        assert_eq!(None, history.jump("two", put_back(&mut number)));
+    }
+}
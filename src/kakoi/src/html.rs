@@ -0,0 +1,423 @@
+//! # Exporting HTML
+//!
+//! An outline (see [`crate::export`]) loses the shape of what it describes.
+//! A value can instead be written out as a single HTML page that draws it
+//! as nested circles, much like Kakoi does, so that it can be shared with
+//! anyone who has a web browser. [`CompleteAction::Export`] writes a page
+//! when given a path ending in `.html`, and so does `kakoi export`.
+//!
+//! Where each circle goes is worked out when the page is written, with the
+//! same [`CirclePositioner`] that arranges sets on screen, and the circles
+//! are drawn as an SVG. Everything the page needs is inside of it: images
+//! are embedded as base64 PNGs, and a few lines of JavaScript zoom in on a
+//! circle when it is clicked, and back out when the background is clicked or
+//! escape is pressed. Hovering over a string or a container shows what it
+//! says or which registers are bound to it.
+//!
+//! The page only approximates what is on screen:
+//!
+//! * Every container is drawn as a circle arranged like
+//!   [`LayoutStrategy::Circles`], turned by its [`Arena::rotation`], whatever
+//!   layout it would be drawn with. The elements of lists and commands are
+//!   placed around the circle in order.
+//! * Circles smaller than [`SMALLEST_RADIUS`] (the whole page being a circle of
+//!   radius one) are left out, along with everything inside of them.
+//! * A container found inside of itself is drawn empty the second time.
+//!
+//! [`CompleteAction::Export`]: crate::input_manager::CompleteAction::Export
+//! [`LayoutStrategy::Circles`]: crate::spatial_tree::LayoutStrategy::Circles
+
+use crate::arena::{Arena, ArenaKey, Structure};
+use crate::circle::{Circle, CirclePositioner, Point};
+use crate::number;
+use crate::render::circle::MIN_RADIUS;
+use std::{fs, io, path::Path};
+
+/// The radius of the smallest circle written out, where the value being
+/// exported is a circle of radius one.
+pub const SMALLEST_RADIUS: f64 = 0.002;
+
+const STYLE: &str = "\
+html, body { margin: 0; height: 100%; background: #202020; }
+svg { width: 100%; height: 100%; display: block; }
+circle { fill: rgba(255, 255, 255, 0.04); stroke: #d0d0d0; cursor: pointer; }
+text { fill: #f0f0f0; font-family: sans-serif; text-anchor: middle; dominant-baseline: central; pointer-events: none; }";
+
+const SCRIPT: &str = "\
+const svg = document.querySelector('svg');
+const trail = [];
+let view = svg.viewBox.baseVal;
+let current = [view.x, view.y, view.width];
+function zoom(target) {
+  const start = current.slice();
+  const began = performance.now();
+  function step(now) {
+    const t = Math.min(1, (now - began) / 300);
+    const e = t * t * (3 - 2 * t);
+    current = start.map((s, i) => s + (target[i] - s) * e);
+    svg.setAttribute('viewBox', current.concat([current[2]]).join(' '));
+    if (t < 1) requestAnimationFrame(step);
+  }
+  requestAnimationFrame(step);
+}
+svg.addEventListener('click', (event) => {
+  const circle = event.target.closest('circle');
+  if (circle) {
+    const r = +circle.getAttribute('r'), x = +circle.getAttribute('cx'), y = +circle.getAttribute('cy');
+    trail.push(current);
+    zoom([x - r, y - r, 2 * r]);
+  } else if (trail.length) {
+    zoom(trail.pop());
+  }
+});
+document.addEventListener('keydown', (event) => {
+  if (event.key === 'Escape' && trail.length) zoom(trail.pop());
+});";
+
+/// A value written out as an HTML page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub text: String,
+    /// How many circles the page draws.
+    pub circles: usize,
+    /// How many images are embedded in the page. Each is embedded once, no
+    /// matter how many times it is drawn.
+    pub images: usize,
+}
+
+impl Page {
+    /// Writes `root`, and everything inside of it, out as an HTML page.
+    pub fn new(arena: &Arena, root: ArenaKey) -> Self {
+        let mut writer = Writer {
+            arena,
+            ancestors: Vec::new(),
+            images: Vec::new(),
+            svg: String::new(),
+            circles: 0,
+        };
+        writer.value(
+            root,
+            Circle {
+                center: Point { x: 0.0, y: 0.0 },
+                radius: 1.0,
+            },
+        );
+        let mut defs = String::new();
+        for (index, &image) in writer.images.iter().enumerate() {
+            if let Some(png) = png(arena, image) {
+                defs += &format!(
+                    "<image id=\"image-{}\" width=\"1\" height=\"1\" \
+                     preserveAspectRatio=\"xMidYMid meet\" href=\"data:image/png;base64,{}\"/>\n",
+                    index,
+                    base64(&png)
+                );
+            }
+        }
+        let title = match arena.register_names(root) {
+            names if names.is_empty() => "kakoi".to_string(),
+            names => names.join(", "),
+        };
+        let text = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>\n{}\n</style>\n</head>\n<body>\n\
+             <svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-1 -1 2 2\">\n\
+             <defs>\n{}</defs>\n{}</svg>\n<script>\n{}\n</script>\n</body>\n</html>\n",
+            escape(&title),
+            STYLE,
+            defs,
+            writer.svg,
+            SCRIPT
+        );
+        Self {
+            text,
+            circles: writer.circles,
+            images: writer.images.len(),
+        }
+    }
+
+    /// A short description of the page once saved to `path`, like
+    /// `"exported 120 circles and 2 images to notes.html"`.
+    pub fn describe<P: AsRef<Path>>(&self, path: P) -> String {
+        format!(
+            "exported {} circle{} and {} image{} to {}",
+            self.circles,
+            if self.circles == 1 { "" } else { "s" },
+            self.images,
+            if self.images == 1 { "" } else { "s" },
+            path.as_ref().display()
+        )
+    }
+}
+
+/// Returns true if `path` is where a page should be written, rather than an
+/// outline: if it ends in `.html` or `.htm`.
+pub fn is_html_path<P: AsRef<Path>>(path: P) -> bool {
+    matches!(
+        path.as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase)
+            .as_deref(),
+        Some("html") | Some("htm")
+    )
+}
+
+/// Writes `root` out as an HTML page saved at `path`.
+pub fn export<P: AsRef<Path>>(arena: &Arena, root: ArenaKey, path: P) -> io::Result<Page> {
+    let page = Page::new(arena, root);
+    fs::write(path, &page.text)?;
+    Ok(page)
+}
+
+/// Escapes `text` so that it can be written inside of an element or an
+/// attribute.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Encodes `bytes` as base64, with padding.
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Encodes the image bound to `key` as a PNG. Linked images are read from
+/// disk, so that the page has everything it needs even if they are moved.
+pub fn png(arena: &Arena, key: ArenaKey) -> Option<Vec<u8>> {
+    let image = match &arena.slot_map[key].structure {
+        Structure::Image(image) => image::DynamicImage::ImageRgba8((**image).clone()),
+        Structure::ImageRef(path) => image::open(path.as_path()).ok()?,
+        _ => return None,
+    };
+    let mut png = Vec::new();
+    image
+        .write_to(&mut png, image::ImageOutputFormat::Png)
+        .ok()?;
+    Some(png)
+}
+
+/// Returns the circles `count` values are placed in inside of `circle`, like
+/// `handle_set` in [`crate::spatial_tree`] does.
+fn place(circle: Circle, count: usize, rotation: f64) -> Vec<Circle> {
+    let radius = if count == 1 {
+        circle.radius * MIN_RADIUS as f64
+    } else {
+        circle.radius
+    };
+    CirclePositioner::new(
+        radius * MIN_RADIUS as f64,
+        count as u64,
+        0.0,
+        circle.center,
+        rotation,
+    )
+    .collect()
+}
+
+struct Writer<'a> {
+    arena: &'a Arena,
+    /// The containers being written out, from the outermost inward, so that
+    /// containers inside of themselves aren't written out forever.
+    ancestors: Vec<ArenaKey>,
+    /// The images drawn so far, in the order they are embedded.
+    images: Vec<ArenaKey>,
+    svg: String,
+    circles: usize,
+}
+
+impl<'a> Writer<'a> {
+    /// Writes out a circle, with a tooltip if `title` isn't empty. The y axis
+    /// points up in Kakoi but down in SVG, so it is flipped.
+    fn circle(&mut self, circle: Circle, title: &str) {
+        let Circle { center, radius } = circle;
+        if title.is_empty() {
+            self.svg += &format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" stroke-width=\"{}\"/>\n",
+                center.x,
+                -center.y,
+                radius,
+                radius * 0.02
+            );
+        } else {
+            self.svg += &format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" stroke-width=\"{}\"><title>{}</title></circle>\n",
+                center.x,
+                -center.y,
+                radius,
+                radius * 0.02,
+                escape(title)
+            );
+        }
+        self.circles += 1;
+    }
+
+    /// Writes `text` across the middle of `circle`, as large as fits.
+    fn text(&mut self, circle: Circle, text: &str) {
+        let lines: Vec<&str> = text.lines().collect();
+        let longest = lines.iter().map(|line| line.chars().count()).max();
+        let longest = longest.unwrap_or(0).max(1) as f64;
+        let size = (1.6 * circle.radius / (longest * 0.6).max(lines.len() as f64 * 1.2))
+            .min(circle.radius * 0.5);
+        let top = -circle.center.y - size * 1.2 * (lines.len() as f64 - 1.0) / 2.0;
+        self.svg += &format!("<text font-size=\"{}\">", size);
+        for (i, line) in lines.iter().enumerate() {
+            self.svg += &format!(
+                "<tspan x=\"{}\" y=\"{}\">{}</tspan>",
+                circle.center.x,
+                top + i as f64 * size * 1.2,
+                escape(line)
+            );
+        }
+        self.svg += "</text>\n";
+    }
+
+    /// Writes out `key`, and everything inside of it, inside of `circle`.
+    fn value(&mut self, key: ArenaKey, circle: Circle) {
+        if circle.radius < SMALLEST_RADIUS {
+            return;
+        }
+        let arena = self.arena;
+        let children: Vec<ArenaKey> = match &arena.slot_map[key].structure {
+            Structure::String(string) => {
+                self.circle(circle, string);
+                self.text(circle, string);
+                return;
+            }
+            Structure::Image(_) | Structure::ImageRef(_) => {
+                self.circle(circle, "");
+                let index = match self.images.iter().position(|&image| image == key) {
+                    Some(index) => index,
+                    None => {
+                        self.images.push(key);
+                        self.images.len() - 1
+                    }
+                };
+                // The largest square that fits inside of the circle.
+                let side = circle.radius * std::f64::consts::SQRT_2;
+                self.svg += &format!(
+                    "<use href=\"#image-{}\" transform=\"translate({} {}) scale({})\"/>\n",
+                    index,
+                    circle.center.x - side / 2.0,
+                    -circle.center.y - side / 2.0,
+                    side
+                );
+                return;
+            }
+            Structure::Number(number) => {
+                let text = number::format(*number);
+                self.circle(circle, &text);
+                self.text(circle, &text);
+                return;
+            }
+            Structure::Blob(blob) => {
+                self.circle(circle, &blob.describe());
+                self.text(circle, &blob.label());
+                return;
+            }
+            Structure::Custom(custom) => {
+                let description = custom.leaf.describe();
+                self.circle(circle, &description);
+                self.text(circle, &description);
+                return;
+            }
+            Structure::Set(members) => arena.arrange(members),
+            Structure::List(elements) | Structure::Command(elements) => elements.to_vec(),
+            Structure::Map(map) => {
+                self.container(key, circle, |writer, circle| {
+                    let pairs = place(circle, map.len(), arena.rotation(key) as f64);
+                    for (pair, (&k, &v)) in pairs.into_iter().zip(map.iter()) {
+                        writer.circle(pair, "");
+                        let places = CirclePositioner::new(pair.radius, 2, 0.0, pair.center, 0.0);
+                        for (place, value) in places.zip(vec![k, v]) {
+                            writer.value(value, place);
+                        }
+                    }
+                });
+                return;
+            }
+        };
+        self.container(key, circle, |writer, circle| {
+            let places = place(circle, children.len(), arena.rotation(key) as f64);
+            for (place, child) in places.into_iter().zip(children) {
+                writer.value(child, place);
+            }
+        });
+    }
+
+    /// Writes out the circle around `container`, and then its contents with
+    /// `contents` unless it is inside of itself.
+    fn container<F>(&mut self, container: ArenaKey, circle: Circle, contents: F)
+    where
+        F: FnOnce(&mut Self, Circle),
+    {
+        self.circle(circle, &self.arena.register_names(container).join(", "));
+        if self.ancestors.contains(&container) {
+            return;
+        }
+        self.ancestors.push(container);
+        contents(self, circle);
+        self.ancestors.pop();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9v", base64(b"foo"));
+        assert_eq!("Zm9vYmFy", base64(b"foobar"));
+        assert_eq!("a &amp; &quot;b&quot;", escape("a & \"b\""));
+    }
+
+    #[test]
+    fn pages() {
+        let mut arena = Arena::new();
+        let a = arena.string("a <b>");
+        let image = arena.image(image::RgbaImage::new(1, 1));
+        let inner = arena.set(vec![a, image]);
+        let root = arena.set(vec![inner, image]);
+        arena.bind_register("notes", root);
+        let page = Page::new(&arena, root);
+        // The root, the set inside of it, and the three values inside of
+        // those.
+        assert_eq!((5, 1), (page.circles, page.images));
+        assert!(page.text.contains("<title>notes</title>"));
+        assert!(page.text.contains("a &lt;b&gt;"));
+        assert_eq!(1, page.text.matches("data:image/png;base64,").count());
+        assert_eq!(2, page.text.matches("href=\"#image-0\"").count());
+
+        assert!(is_html_path("notes.HTML"));
+        assert!(!is_html_path("notes.md"));
+    }
+}
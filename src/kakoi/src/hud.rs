@@ -0,0 +1,254 @@
+//! # Heads-up display
+//!
+//! Some things are shown in the same place on screen no matter what is being
+//! looked at, like the ghost of a value being moved or the bar of pinned
+//! values. Each of these is a [`Widget`] anchored to an edge or a corner of the
+//! window (see [`Anchor`]). A [`Hud`] collects widgets and decides where each
+//! of them goes.
+//!
+//! Unlike [layers](crate::overlay::Layer), which are placed inside of the
+//! square described in [`crate::spatial_tree`], widgets are placed relative to
+//! what is actually visible, which is wider than that square on wide windows
+//! and taller on tall ones (see [`Camera::visible_half_extent`]). Widgets with
+//! the same anchor are stacked next to each other, in the order they were
+//! added, starting from the anchor.
+//!
+//! While a string or register is being entered, a minibuffer spanning the
+//! bottom of the window displays it (see [`InputManager::prompt`]), and
+//! widgets along the bottom are raised above it.
+//!
+//! Widgets are laid out by the [`SpatialTree`] above every layer, so they are
+//! drawn after (and on top of) everything else.
+//!
+//! [`Camera::visible_half_extent`]: crate::camera::Camera::visible_half_extent
+//! [`SpatialTree`]: crate::spatial_tree::SpatialTree
+//! [`InputManager::prompt`]: crate::input_manager::InputManager::prompt
+
+use crate::arena::ArenaKey;
+use crate::square_cuboid::SquareCuboid;
+
+/// The gap between a widget and the edge of the window it is anchored to.
+const MARGIN: f32 = 0.05;
+
+/// The gap between neighbouring widgets with the same anchor.
+const SPACING: f32 = 0.03;
+
+/// The height of the minibuffer.
+const MINIBUFFER_HEIGHT: f32 = 0.1;
+
+/// The edge or corner of the window a [`Widget`] is placed against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Which side of the window this anchor is on, horizontally and
+    /// vertically: -1 for the left (or bottom), 1 for the right (or top), and
+    /// 0 for the middle.
+    fn sides(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (-1.0, 1.0),
+            Anchor::Top => (0.0, 1.0),
+            Anchor::TopRight => (1.0, 1.0),
+            Anchor::Left => (-1.0, 0.0),
+            Anchor::Right => (1.0, 0.0),
+            Anchor::BottomLeft => (-1.0, -1.0),
+            Anchor::Bottom => (0.0, -1.0),
+            Anchor::BottomRight => (1.0, -1.0),
+        }
+    }
+}
+
+/// A value displayed in a square region of the window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Widget {
+    pub anchor: Anchor,
+    /// The side length of the widget's region, in the coordinates described
+    /// in [`crate::spatial_tree`].
+    pub size: f32,
+    /// The value displayed inside of the widget.
+    pub key: ArenaKey,
+    /// If given, the widget is drawn as a ring labelled with this, with the
+    /// value inside of it.
+    pub label: Option<String>,
+}
+
+impl Widget {
+    pub fn new(anchor: Anchor, size: f32, key: ArenaKey) -> Self {
+        Self {
+            anchor,
+            size,
+            key,
+            label: None,
+        }
+    }
+
+    /// Draws the widget as a ring labelled with `label`.
+    pub fn labelled<S: Into<String>>(self, label: S) -> Self {
+        Self {
+            label: Some(label.into()),
+            ..self
+        }
+    }
+}
+
+/// A collection of [`Widget`]s.
+///
+/// See [the module-level documentation](crate::hud) for more information.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Hud {
+    widgets: Vec<Widget>,
+    minibuffer: Option<String>,
+}
+
+impl Hud {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `widget` after every widget added before it, returning its index
+    /// among them.
+    pub fn push(&mut self, widget: Widget) -> usize {
+        self.widgets.push(widget);
+        self.widgets.len() - 1
+    }
+
+    pub fn widgets(&self) -> &[Widget] {
+        &self.widgets
+    }
+
+    /// The text displayed in the minibuffer, if it is displayed.
+    pub fn minibuffer(&self) -> Option<&str> {
+        self.minibuffer.as_deref()
+    }
+
+    pub fn set_minibuffer(&mut self, minibuffer: Option<String>) {
+        self.minibuffer = minibuffer;
+    }
+
+    /// Returns the region of the screen the minibuffer is displayed in, if it
+    /// is displayed, given the same extent as [`Hud::layout`].
+    pub fn minibuffer_bounds(&self, (half_width, half_height): (f32, f32)) -> Option<SquareCuboid> {
+        self.minibuffer.as_ref().map(|_| {
+            SquareCuboid::from_dimensions(
+                2.0 * (half_width - MARGIN),
+                MINIBUFFER_HEIGHT,
+                (0.0, -half_height + MARGIN + 0.5 * MINIBUFFER_HEIGHT, 0.0).into(),
+            )
+        })
+    }
+
+    /// Returns the region of the screen each widget is placed in, in the order
+    /// they were added, given half of the width and half of the height of the
+    /// visible region of the screen.
+    ///
+    /// Widgets along the top and bottom of the window are stacked in a row,
+    /// and widgets along the left and right sides are stacked in a column.
+    /// Widgets in the middle of an edge are centered along it.
+    pub fn layout(&self, (half_width, half_height): (f32, f32)) -> Vec<SquareCuboid> {
+        let raised = match self.minibuffer {
+            Some(_) => MINIBUFFER_HEIGHT + SPACING,
+            None => 0.0,
+        };
+        let mut bounds = vec![None; self.widgets.len()];
+        for &anchor in &[
+            Anchor::TopLeft,
+            Anchor::Top,
+            Anchor::TopRight,
+            Anchor::Left,
+            Anchor::Right,
+            Anchor::BottomLeft,
+            Anchor::Bottom,
+            Anchor::BottomRight,
+        ] {
+            let stack: Vec<usize> = (0..self.widgets.len())
+                .filter(|&index| self.widgets[index].anchor == anchor)
+                .collect();
+            let length = stack
+                .iter()
+                .map(|&index| self.widgets[index].size + SPACING)
+                .sum::<f32>()
+                - SPACING;
+            let (x_side, y_side) = anchor.sides();
+            // Rows are stacked along x, and columns along y.
+            let row = y_side != 0.0;
+            let (along_side, along_half) = if row {
+                (x_side, half_width)
+            } else {
+                (y_side, half_height)
+            };
+            // Stacks in a corner start at the corner and grow towards the
+            // middle of the edge. Stacks in the middle of an edge are
+            // centered.
+            let (mut cursor, direction) = match along_side {
+                side if side < 0.0 => (-along_half + MARGIN, 1.0),
+                side if side > 0.0 => (along_half - MARGIN, -1.0),
+                _ => (-0.5 * length, 1.0),
+            };
+            for index in stack {
+                let size = self.widgets[index].size;
+                let along = cursor + direction * 0.5 * size;
+                cursor += direction * (size + SPACING);
+                let (x, y) = if row && y_side < 0.0 {
+                    (along, raised - (half_height - MARGIN - 0.5 * size))
+                } else if row {
+                    (along, half_height - MARGIN - 0.5 * size)
+                } else {
+                    (x_side * (half_width - MARGIN - 0.5 * size), along)
+                };
+                bounds[index] = Some(SquareCuboid::from_dimensions(
+                    size,
+                    size,
+                    (x, y, 0.0).into(),
+                ));
+            }
+        }
+        bounds.into_iter().map(Option::unwrap).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use slotmap::SlotMap;
+
+    #[test]
+    fn stacking() {
+        let mut slot_map: SlotMap<ArenaKey, ()> = SlotMap::with_key();
+        let key = slot_map.insert(());
+        let mut hud = Hud::new();
+        let first = hud.push(Widget::new(Anchor::BottomLeft, 0.2, key));
+        let corner = hud.push(Widget::new(Anchor::TopRight, 0.4, key));
+        let second = hud.push(Widget::new(Anchor::BottomLeft, 0.1, key).labelled("2"));
+        let bounds = hud.layout((2.0, 1.0));
+        let at = |index: usize, x: f32, y: f32| {
+            let center = bounds[index].center;
+            (center.x - x).abs() < 1e-6 && (center.y - y).abs() < 1e-6
+        };
+        assert!(at(first, -2.0 + MARGIN + 0.1, -1.0 + MARGIN + 0.1));
+        assert!(at(corner, 2.0 - MARGIN - 0.2, 1.0 - MARGIN - 0.2));
+        assert!(at(
+            second,
+            -2.0 + MARGIN + 0.2 + SPACING + 0.05,
+            -1.0 + MARGIN + 0.05
+        ));
+
+        hud.set_minibuffer(Some("s register: ".into()));
+        let bounds = hud.layout((2.0, 1.0));
+        let at = |index: usize, x: f32, y: f32| {
+            let center = bounds[index].center;
+            (center.x - x).abs() < 1e-6 && (center.y - y).abs() < 1e-6
+        };
+        let raised = MINIBUFFER_HEIGHT + SPACING;
+        assert!(at(first, -2.0 + MARGIN + 0.1, -1.0 + MARGIN + 0.1 + raised));
+        assert!(at(corner, 2.0 - MARGIN - 0.2, 1.0 - MARGIN - 0.2));
+    }
+}
@@ -0,0 +1,401 @@
+//! # Importing files
+//!
+//! Images and text files can be dragged onto the window to import them (see
+//! [`crate::text_import`] for how text files are turned into values). Any
+//! other file is imported as it is, as a [blob](crate::blob). Rather
+//! than being inserted right away, a dropped file is first shown in a
+//! [`LayerKind::Preview`] layer along with its size, so that a file dropped by
+//! mistake can be dismissed before it ends up inside of a set. Pressing return
+//! inserts it into the set bound to `.`, and pressing escape discards it.
+//! Files can also be imported at startup, with `kakoi --import FILE`.
+//!
+//! Files are read and decoded in a [`Task`](crate::task::Task), so the
+//! window stays responsive while importing large files.
+//!
+//! Decoded images take up four bytes per pixel in memory, and again on the
+//! GPU, so a 50 megapixel photo would take up 200 MB twice over. Imports are
+//! bounded by an [`ImportPolicy`]: images larger than its
+//! [`max_dimension`](ImportPolicy::max_dimension) are shrunk as they are
+//! imported, and the preview warns when inserting an image would take Kakoi
+//! over its [`memory_budget`](ImportPolicy::memory_budget). Other files can't
+//! be shrunk, so one that would take Kakoi over its budget isn't read at all
+//! (see [`ImportPolicy::refusal`]). Photos are turned
+//! upright according to their [EXIF orientation] first, so that they are
+//! shrunk (and displayed) the right way around. Shrunk images only keep their
+//! smaller pixels; the original file is left where it was.
+//!
+//! To keep hundreds of photos around without holding all of their pixels,
+//! images can be imported as links instead (see
+//! [`link_images`](ImportPolicy::link_images)): only the path of the file is
+//! kept, and it is read whenever the image is on screen (see
+//! [`Structure::ImageRef`]). A linked image is gone if its file is moved.
+//!
+//! Whether a file is an image is decided by its first few bytes, falling back
+//! on its extension for formats that don't start with a signature (see
+//! [`is_image_file`]).
+//!
+//! There is no clipboard support yet, so dropping files is the only way to
+//! import images.
+//!
+//! [`LayerKind::Preview`]: crate::overlay::LayerKind::Preview
+//! [`Structure::ImageRef`]: crate::arena::Structure::ImageRef
+//! [EXIF orientation]: https://en.wikipedia.org/wiki/Exif
+
+use crate::task::Progress;
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use std::{fs::File, io::Read, path::Path};
+
+/// How many bytes of a file are read between progress reports.
+const READ_CHUNK_SIZE: usize = 1 << 20;
+
+/// How many bytes at the start of a file are enough to recognize an image
+/// format by.
+const SIGNATURE_SIZE: usize = 16;
+
+/// The default [`ImportPolicy::max_dimension`]. Most GPUs can't hold textures
+/// much larger than this anyway.
+pub const DEFAULT_MAX_DIMENSION: u32 = 4096;
+
+/// Limits on how much memory imported images take up, and how imported text
+/// is split up. See [the module-level documentation](crate::import).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportPolicy {
+    /// Images wider or taller than this many pixels are shrunk to fit, keeping
+    /// their aspect ratio.
+    pub max_dimension: u32,
+    /// How many bytes the arena may take up before importing an image comes
+    /// with a warning, if there is a limit.
+    pub memory_budget: Option<u64>,
+    /// Whether imported text files are split into a section per heading. See
+    /// [`crate::text_import`].
+    pub split_text: bool,
+    /// Whether images are imported as links to their files rather than as
+    /// pixels.
+    pub link_images: bool,
+}
+
+impl Default for ImportPolicy {
+    fn default() -> Self {
+        Self {
+            max_dimension: DEFAULT_MAX_DIMENSION,
+            memory_budget: None,
+            split_text: true,
+            link_images: false,
+        }
+    }
+}
+
+impl ImportPolicy {
+    /// Returns a warning to show alongside the preview of `imported` if
+    /// inserting it into an arena already taking up `in_use` bytes would go
+    /// over the memory budget.
+    pub fn warning(&self, imported: &ImportedImage, in_use: u64) -> Option<String> {
+        let adding = imported.image.as_raw().len() as u64;
+        self.over_budget(adding, in_use)
+            .map(|over| format!("inserting this brings kakoi to {}", over))
+    }
+
+    /// Returns why a file of `byte_size` bytes can't be imported as a blob
+    /// (see [`crate::blob`]) into an arena already taking up `in_use` bytes,
+    /// if it would go over the memory budget. Blobs are kept as they are, so
+    /// rather than warning about them once they have been read, they aren't
+    /// read at all.
+    pub fn refusal(&self, byte_size: u64, in_use: u64) -> Option<String> {
+        self.over_budget(byte_size, in_use).map(|over| {
+            format!(
+                "didn't import a file of {}: it would bring kakoi to {}",
+                format_byte_size(byte_size),
+                over
+            )
+        })
+    }
+
+    /// Describes how far over the memory budget adding `adding` bytes to an
+    /// arena already taking up `in_use` bytes would go, if it would.
+    fn over_budget(&self, adding: u64, in_use: u64) -> Option<String> {
+        let budget = self.memory_budget?;
+        let after = in_use + adding;
+        if after > budget {
+            Some(format!(
+                "{}, over its budget of {}",
+                format_byte_size(after),
+                format_byte_size(budget)
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// An image that has been read from a file but not yet inserted into a set.
+#[derive(Debug)]
+pub struct ImportedImage {
+    pub image: image::RgbaImage,
+    /// The size of the file the image was read from, in bytes.
+    pub byte_size: u64,
+    /// The width and height of the image before it was shrunk to fit the
+    /// [`ImportPolicy`], after turning it upright.
+    pub original_dimensions: (u32, u32),
+}
+
+impl ImportedImage {
+    /// Reads and decodes the image stored at `path`, turning it upright and
+    /// shrinking it to fit `policy`. Returns [`None`] if the file can't be
+    /// read, isn't an image, or `progress` is cancelled.
+    ///
+    /// Meant to be run as a [`Task`](crate::task::Task). Reading the file
+    /// accounts for the first half of the progress, and decoding it for the
+    /// second.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        policy: ImportPolicy,
+        progress: &Progress,
+    ) -> Option<Self> {
+        let bytes = read_file(path, 0.5, progress)?;
+        let mut image = image::load_from_memory(&bytes).ok()?;
+        if let Some(orientation) = exif_orientation(&bytes) {
+            image = orient(image, orientation);
+        }
+        let original_dimensions = image.dimensions();
+        let (width, height) = original_dimensions;
+        if width.max(height) > policy.max_dimension {
+            image = image.resize(
+                policy.max_dimension,
+                policy.max_dimension,
+                FilterType::Triangle,
+            );
+        }
+        progress.report(1.0);
+        Some(Self {
+            image: image.into_rgba8(),
+            byte_size: bytes.len() as u64,
+            original_dimensions,
+        })
+    }
+
+    /// A short description of the image to display alongside its preview, like
+    /// `"1920 × 1080, 2.4 MB"`, or `"4096 × 3072 (shrunk from 8000 × 6000),
+    /// 24.1 MB"` if it was shrunk.
+    pub fn describe(&self) -> String {
+        let (width, height) = self.image.dimensions();
+        let shrunk = if self.original_dimensions == (width, height) {
+            String::new()
+        } else {
+            format!(
+                " (shrunk from {} × {})",
+                self.original_dimensions.0, self.original_dimensions.1
+            )
+        };
+        format!(
+            "{} × {}{}, {}",
+            width,
+            height,
+            shrunk,
+            format_byte_size(self.byte_size)
+        )
+    }
+}
+
+/// Returns the EXIF orientation of the JPEG file `bytes`, from 1 (upright) to
+/// 8, if it has one.
+fn exif_orientation(bytes: &[u8]) -> Option<u16> {
+    // JPEG files start with a start-of-image marker, followed by segments made
+    // of a marker and a big-endian length (which counts itself).
+    if bytes.get(..2)? != [0xff, 0xd8] {
+        return None;
+    }
+    let mut at = 2;
+    let exif = loop {
+        let header = bytes.get(at..at + 4)?;
+        if header[0] != 0xff || header[1] == 0xda {
+            // Anything after the start of the scan is image data.
+            return None;
+        }
+        let length = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let data = bytes.get(at + 4..at + 2 + length)?;
+        if header[1] == 0xe1 && data.starts_with(b"Exif\0\0") {
+            break &data[6..];
+        }
+        at += 2 + length;
+    };
+    // EXIF data is laid out like a TIFF file, in either byte order.
+    let big_endian = match exif.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |at: usize| -> Option<u16> {
+        let bytes = [*exif.get(at)?, *exif.get(at + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |at: usize| -> Option<u32> {
+        let first = u16_at(at)? as u32;
+        let second = u16_at(at + 2)? as u32;
+        Some(if big_endian {
+            first << 16 | second
+        } else {
+            second << 16 | first
+        })
+    };
+    let ifd = u32_at(4)? as usize;
+    let entries = u16_at(ifd)? as usize;
+    (0..entries)
+        .map(|entry| ifd + 2 + 12 * entry)
+        .find(|&entry| u16_at(entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// The EXIF tag holding the orientation of an image.
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Turns `image` upright, given its EXIF `orientation`.
+fn orient(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Returns true if the file at `path` should be imported as an image rather
+/// than as a [blob](crate::blob).
+pub fn is_image_file<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    let mut signature = Vec::with_capacity(SIGNATURE_SIZE);
+    let read = File::open(path)
+        .and_then(|file| file.take(SIGNATURE_SIZE as u64).read_to_end(&mut signature));
+    (read.is_ok() && image::guess_format(&signature).is_ok())
+        || image::ImageFormat::from_path(path).is_ok()
+}
+
+/// Reads the whole file at `path`, reporting progress from zero up to `share`
+/// as it goes. Returns [`None`] if the file can't be read or `progress` is
+/// cancelled.
+pub fn read_file<P: AsRef<Path>>(path: P, share: f32, progress: &Progress) -> Option<Vec<u8>> {
+    let mut file = File::open(path).ok()?;
+    let byte_size = file.metadata().ok()?.len();
+    let mut bytes = Vec::with_capacity(byte_size as usize);
+    let mut chunk = vec![0; READ_CHUNK_SIZE];
+    loop {
+        if progress.is_cancelled() {
+            return None;
+        }
+        match file.read(&mut chunk).ok()? {
+            0 => break,
+            read => bytes.extend_from_slice(&chunk[..read]),
+        }
+        progress.report(share * bytes.len() as f32 / byte_size.max(1) as f32);
+    }
+    Some(bytes)
+}
+
+/// Formats `bytes` using the largest unit that keeps the number at least one.
+pub fn format_byte_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit + 1 < UNITS.len() {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!("999 B", format_byte_size(999));
+        assert_eq!("1.0 KB", format_byte_size(1000));
+        assert_eq!("2.4 MB", format_byte_size(2_400_000));
+        assert_eq!("3000.0 TB", format_byte_size(3_000_000_000_000_000));
+    }
+
+    #[test]
+    fn describe() {
+        let imported = ImportedImage {
+            image: image::RgbaImage::new(16, 9),
+            byte_size: 512,
+            original_dimensions: (16, 9),
+        };
+        assert_eq!("16 × 9, 512 B", imported.describe());
+        let shrunk = ImportedImage {
+            original_dimensions: (32, 18),
+            ..imported
+        };
+        assert_eq!("16 × 9 (shrunk from 32 × 18), 512 B", shrunk.describe());
+
+        let policy = ImportPolicy {
+            memory_budget: Some(1000),
+            ..ImportPolicy::default()
+        };
+        // 16 × 9 pixels take up 576 bytes.
+        assert_eq!(None, policy.warning(&shrunk, 424));
+        assert!(policy.warning(&shrunk, 425).is_some());
+        assert_eq!(None, policy.refusal(576, 424));
+        assert!(policy.refusal(576, 425).is_some());
+    }
+
+    #[test]
+    fn orientation() {
+        // A JPEG holding nothing but an EXIF segment, in both byte orders.
+        let jpeg = |big_endian: bool| {
+            let u16 = |n: u16| {
+                if big_endian {
+                    n.to_be_bytes().to_vec()
+                } else {
+                    n.to_le_bytes().to_vec()
+                }
+            };
+            let u32 = |n: u32| {
+                if big_endian {
+                    n.to_be_bytes().to_vec()
+                } else {
+                    n.to_le_bytes().to_vec()
+                }
+            };
+            let tiff = [
+                if big_endian { b"MM" } else { b"II" }.to_vec(),
+                u16(42),
+                // The offset of the first (and only) directory.
+                u32(8),
+                u16(1),
+                // A single short holding the orientation, padded to four
+                // bytes.
+                u16(ORIENTATION_TAG),
+                u16(3),
+                u32(1),
+                u16(6),
+                vec![0, 0],
+            ]
+            .concat();
+            let mut bytes = vec![0xff, 0xd8, 0xff, 0xe1];
+            bytes.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(b"Exif\0\0");
+            bytes.extend_from_slice(&tiff);
+            bytes.extend_from_slice(&[0xff, 0xda]);
+            bytes
+        };
+        assert_eq!(Some(6), exif_orientation(&jpeg(true)));
+        assert_eq!(Some(6), exif_orientation(&jpeg(false)));
+        assert_eq!(None, exif_orientation(b"not a jpeg"));
+
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::new(2, 1));
+        assert_eq!((1, 2), orient(image, 6).dimensions());
+    }
+}
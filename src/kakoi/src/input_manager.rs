@@ -31,10 +31,41 @@
 //! initial input state can be produced by [`KeyBinder::start_state`]. The
 //! current [`InputState`] is contained within the [`InputManager`].
 //!
+//! Keys are matched against key bindings either by what the keyboard layout
+//! says they are or by their physical position on the keyboard; see
+//! [`BindingMode`]. The text of strings doesn't come from key presses at all,
+//! but from the characters produced by the keyboard layout (see
+//! [`InputManager::process_character`]), so that any symbol on the user's
+//! keyboard can be typed.
+//!
+//! Some bindings finish by choosing from a few options instead (see
+//! [`InputRequirementDescriptor::Choice`]), which are drawn as a palette in
+//! the minibuffer so that nothing has to be typed out.
+//!
+//! Some bindings are counted (see [`InputRequirementDescriptor::Count`]): a
+//! number typed before them, with number keys that aren't bound to anything,
+//! says which position of a list they act on, like `3 control+shift+x`
+//! removing the third element.
+//!
+//! String and register prompts remember what was entered into them. While one
+//! is waiting for input, up and down go back and forth through what was
+//! entered into prompts of the same kind before, and the prompt is displayed
+//! in a minibuffer along the bottom of the window (see [`InputManager::prompt`]).
+//! Pressing tab in a register prompt starts typing the name of a register out,
+//! which reaches registers whose names are longer than a single key; pressing
+//! tab again completes the name as far as the names of existing registers
+//! allow.
+//!
 //! [finite state machine]: https://en.wikipedia.org/wiki/Finite-state_machine
 
+use crate::arena::{
+    CHANGED_REGISTER, LOCAL_REGISTER_SIGIL, PIN_SLOTS, RECENT_REGISTER, STARRED_REGISTER,
+};
+use crate::number::OPERATION_CHOICES;
+use crate::spatial_tree::LAYOUT_CHOICES;
 use slotmap::{new_key_type, SlotMap};
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
 
 /// Describes a command the user has inputted.
@@ -64,10 +95,33 @@ pub enum CompleteAction {
     ///
     /// Removes the value bound to register_b from the set bound to register_a.
     SetRemove(String, String),
+    /// ToggleMembership(register_a, register_b)
+    ///
+    /// Removes the value bound to register_b from the set bound to register_a
+    /// if it is a member, and inserts it otherwise. The message line says
+    /// which it was.
+    ToggleMembership(String, String),
     /// InsertStringIntoSetRegister(register, string)
     ///
     /// Inserts `string` into the set bound to a register.
     InsertStringIntoSetRegister(String, String),
+    /// InsertNumberIntoSetRegister(register, text)
+    ///
+    /// Inserts the number written out in text into the set bound to a
+    /// register. See [`crate::number`].
+    InsertNumberIntoSetRegister(String, String),
+    /// Arithmetic(operation, register_a, register_b, register_c)
+    ///
+    /// Inserts the result of operation, one of the
+    /// [`OPERATION_CHOICES`](crate::number::OPERATION_CHOICES), on the
+    /// numbers bound to register_b and register_c into the set bound to
+    /// register_a.
+    Arithmetic(String, String, String, String),
+    /// CaptureString(string)
+    ///
+    /// Inserts `string` into the inbox set, no matter what is being displayed.
+    /// See [`crate::arena::Arena::capture_string`].
+    CaptureString(String),
     /// SelectRegister(register_to_focus)
     ///
     /// Binds the register `.` to the value stored in register_to_focus. This
@@ -87,14 +141,512 @@ pub enum CompleteAction {
     ///
     /// Binds a register to an empty set.
     BindRegisterToEmptySet(String),
+    /// BindRegisterToEmptyList(register)
+    ///
+    /// Binds a register to an empty list.
+    BindRegisterToEmptyList(String),
+    /// ListPush(register_a, register_b)
+    ///
+    /// Inserts the value bound to register_b at the end of the list bound to
+    /// register_a.
+    ListPush(String, String),
+    /// ListPop(register)
+    ///
+    /// Removes the last element of the list bound to a register.
+    ListPop(String),
+    /// ListInsertAt(register_a, position, register_b)
+    ///
+    /// Inserts the value bound to register_b into the list bound to
+    /// register_a, so that it ends up at position (counting from 1). The
+    /// positions of list actions come from the number typed before their key
+    /// binding (see [`count`]), and are 1 if no number was typed.
+    ListInsertAt(String, usize, String),
+    /// ListRemoveAt(register, position)
+    ///
+    /// Removes the element at position from the list bound to a register.
+    ListRemoveAt(String, usize),
+    /// ListSwap(register, position)
+    ///
+    /// Exchanges the element at position in the list bound to a register with
+    /// the element after it.
+    ListSwap(String, usize),
+    /// ListMove(register, position, to)
+    ///
+    /// Moves the element at position in the list bound to a register so that
+    /// it ends up at the position written out in to.
+    ListMove(String, usize, String),
     /// Registers
     ///
     /// Binds the register `.` to the map of register-value bindings.
     Registers,
     /// Back
     ///
-    /// Binds the register `.` to the previously-focused value.
+    /// Binds the register `.` to the previously-focused value. If an overlay is
+    /// open, closes it instead.
     Back,
+    /// PushOverlay(register)
+    ///
+    /// Opens a panel above the current view displaying the value bound to
+    /// register. Until the panel is closed, selecting values changes what the
+    /// panel displays instead of changing the `.` register.
+    PushOverlay(String),
+    /// PopOverlay
+    ///
+    /// Closes the topmost overlay, if there is one.
+    PopOverlay,
+    /// ToggleLayout
+    ///
+    /// Switches the topmost layer to the next way of arranging containers
+    /// (circles, circles sized by their contents, or a treemap).
+    ToggleLayout,
+    /// Validate
+    ///
+    /// Checks the inclusions of every value in the arena for
+    /// inconsistencies, logging and repairing any that are found. Meant for
+    /// debugging.
+    Validate,
+    /// RestartTutorial
+    ///
+    /// Rebuilds the tutorial from scratch and displays its first step. See
+    /// [`crate::tutorial`].
+    RestartTutorial,
+    /// Confirm
+    ///
+    /// Accepts whatever the topmost overlay is asking about. For an image
+    /// preview, this inserts the image into the set bound to `.` (see
+    /// [`crate::import`]). For a grouping suggestion, this regroups the set
+    /// it was made for (see [`crate::grouping`]).
+    Confirm,
+    /// SelectNext
+    ///
+    /// Selects the next value inside of what the topmost layer displays. The
+    /// selected value is surrounded by a ring.
+    SelectNext,
+    /// Descend
+    ///
+    /// Displays the selected value in the topmost layer, like clicking on it.
+    Descend,
+    /// CancelTasks
+    ///
+    /// Stops every running [`crate::task::Task`], discarding their results.
+    CancelTasks,
+    /// OpenUrl
+    ///
+    /// Opens the selected string in a web browser, or the string the topmost
+    /// layer displays if nothing is selected, as long as it is a
+    /// [`StringClass::Url`](crate::string_class::StringClass::Url). Deep links
+    /// (see [`crate::link`]) are followed to the value they name instead, and
+    /// custom leaves are opened in whatever program they open themselves in
+    /// (see [`crate::leaf::CustomLeaf::open_external`]).
+    OpenUrl,
+    /// CopyLink
+    ///
+    /// Copies a deep link to the selected value (or the value the topmost
+    /// layer displays, if nothing is selected) to the clipboard. See
+    /// [`crate::link`].
+    CopyLink,
+    /// Capture(resolution)
+    ///
+    /// Saves the value the topmost layer displays as a PNG of the given
+    /// resolution, like `4096x4096`. See [`crate::render::capture`].
+    Capture(String),
+    /// FlattenOnce
+    ///
+    /// Asks to replace each member of the set the topmost layer displays that
+    /// is itself a set with that set's members.
+    FlattenOnce,
+    /// Rewrite(pattern, replacement)
+    ///
+    /// Asks to replace whatever matches the regular expression pattern with
+    /// replacement in each string inside of the set or list the topmost layer
+    /// displays. See [`crate::rewrite`].
+    Rewrite(String, String),
+    /// Undo
+    ///
+    /// Puts back what the last change changed: the members of a set, the
+    /// elements of a list, the strings changed by an accepted rewrite, what
+    /// a register was bound to, the entries of a map (and so tags), or
+    /// whether a value was locked. Undoing again undoes the change before
+    /// that. See [`crate::history`] for what isn't put back.
+    Undo,
+    /// Redo
+    ///
+    /// Makes the last change that was undone again.
+    Redo,
+    /// Checkpoint(name)
+    ///
+    /// Names this point in the undo history, so that
+    /// [`CompleteAction::JumpToCheckpoint`] can come back to it.
+    Checkpoint(String),
+    /// JumpToCheckpoint(name)
+    ///
+    /// Undoes or redoes every change between now and the checkpoint named
+    /// `name` at once.
+    JumpToCheckpoint(String),
+    /// Checkpoints
+    ///
+    /// Lists every checkpoint, and how many changes away it is.
+    Checkpoints,
+    /// Query(query)
+    ///
+    /// Runs `query`, showing the values matching it in a panel, and saves it
+    /// so that it can be run again. See [`crate::query`].
+    Query(String),
+    /// RunQuery
+    ///
+    /// Runs the selected string (or, if nothing is selected, the string the
+    /// topmost layer displays) as a query, without saving it again.
+    RunQuery,
+    /// FindInto(register, query)
+    ///
+    /// Binds register to a new set of every value matching `query`, without
+    /// showing it or saving the query. See [`crate::query`].
+    FindInto(String, String),
+    /// CycleValueLayout
+    ///
+    /// Changes how the selected value (or, if nothing is selected, the value
+    /// the topmost layer displays) is always arranged, cycling through every
+    /// [`LayoutStrategy`](crate::spatial_tree::LayoutStrategy) and back to
+    /// arranging it automatically.
+    CycleValueLayout,
+    /// SetValueLayout(layout)
+    ///
+    /// Like [`CompleteAction::CycleValueLayout`], but arranges the value with
+    /// layout, one of the
+    /// [`LAYOUT_CHOICES`](crate::spatial_tree::LAYOUT_CHOICES), straight
+    /// away.
+    SetValueLayout(String),
+    /// Rotate(steps)
+    ///
+    /// Turns the members of the selected container (or, if nothing is
+    /// selected, the container the topmost layer displays) by steps twelfths
+    /// of a full turn, counterclockwise if steps is positive and clockwise if
+    /// it is negative (see [`Arena::rotation`](crate::arena::Arena::rotation)).
+    Rotate(i32),
+    /// MarkMove
+    ///
+    /// Marks the selected value to be moved out of the set the base layer
+    /// displays. Confirming while the base layer displays another set moves
+    /// it there, and popping the (empty) overlay stack gives up.
+    MarkMove,
+    /// SuggestGrouping
+    ///
+    /// Suggests splitting the members of the set the topmost layer displays
+    /// into groups of similar members. See [`crate::grouping`].
+    SuggestGrouping,
+    /// FindDuplicates
+    ///
+    /// Looks through every value for strings that differ by a typo and
+    /// identical images in the background, and shows the pairs it finds for
+    /// review once it is done. Confirming
+    /// merges the selected pair, or every pair if none is selected. See
+    /// [`crate::dedup`].
+    FindDuplicates,
+    /// ToggleSample
+    ///
+    /// Switches the topmost layer between displaying every member of its set
+    /// and displaying a random sample of them. See [`crate::sample`].
+    ToggleSample,
+    /// Reshuffle
+    ///
+    /// Displays a different random sample of the set the topmost layer
+    /// displays, if it is displaying a sample.
+    Reshuffle,
+    /// ToggleReadOnly
+    ///
+    /// Switches read-only mode on or off. While it is on, every action that
+    /// [mutates](CompleteAction::mutates) the arena is rejected.
+    ToggleReadOnly,
+    /// TogglePowerSaving
+    ///
+    /// Switches power saving mode on or off. See [`crate::power`].
+    TogglePowerSaving,
+    /// ToggleLock
+    ///
+    /// Locks (or unlocks) the selected value, or the value the topmost layer
+    /// displays if nothing is selected, so that neither it nor anything inside
+    /// of it can be modified. See [`crate::arena::Arena::is_locked`].
+    ToggleLock,
+    /// ToggleSharing
+    ///
+    /// Switches on or off drawing curves between the places where the same
+    /// value is displayed more than once. See
+    /// [`crate::render::sharing::SharingRenderer`].
+    ToggleSharing,
+    /// ToggleHeatmap
+    ///
+    /// Switches on or off surrounding everything drawn with a ring colored by
+    /// how much it costs to draw, to find what makes frames slow. See
+    /// [`crate::cost`].
+    ToggleHeatmap,
+    /// ToggleStar
+    ///
+    /// Stars (or unstars) the selected value, or the value the topmost layer
+    /// displays if nothing is selected. See
+    /// [`crate::arena::Arena::toggle_star`].
+    ToggleStar,
+    /// Tag(tag)
+    ///
+    /// Tags the selected value (or the value the topmost layer displays, if
+    /// nothing is selected) with tag. See [`crate::arena::Arena::tag`].
+    Tag(String),
+    /// Untag(tag)
+    ///
+    /// Removes tag from the selected value, or the value the topmost layer
+    /// displays if nothing is selected.
+    Untag(String),
+    /// ShowTag(tag)
+    ///
+    /// Shows every value tagged with tag in a panel, like the results of a
+    /// query.
+    ShowTag(String),
+    /// Drop(from, onto, copy)
+    ///
+    /// Drops the fromth value that can be selected in the topmost layer onto
+    /// the ontoth one. If the value it is dropped onto is a set, the dropped
+    /// value is moved into it (or copied, if copy is true). Otherwise, if the
+    /// topmost layer displays a list, the dropped value is moved to where the
+    /// value it is dropped onto is. Not bound to any key: it is performed by
+    /// dragging values with the mouse.
+    Drop(usize, usize, bool),
+    /// Highlight(query)
+    ///
+    /// Highlights every value matching query (see [`crate::query`]) wherever
+    /// it is displayed in the topmost layer, dimming everything else. An
+    /// empty query stops highlighting.
+    Highlight(String),
+    /// NextMatch
+    ///
+    /// Selects the next value highlighted by [`CompleteAction::Highlight`],
+    /// displaying a value it is inside of in the topmost layer if it isn't
+    /// already displayed there.
+    NextMatch,
+    /// PreviousMatch
+    ///
+    /// Like [`CompleteAction::NextMatch`], but goes back to the previous
+    /// value.
+    PreviousMatch,
+    /// Export(path)
+    ///
+    /// Writes the selected value (or the value the topmost layer displays, if
+    /// nothing is selected) out to path as a Markdown or org-mode outline,
+    /// depending on its extension (see [`crate::export`]), or as a web page
+    /// if it ends in `.html` (see [`crate::html`]). If it ends in `.json`,
+    /// the whole arena is written out instead (see [`crate::json`]).
+    Export(String),
+    /// ExportLayout(path)
+    ///
+    /// Writes where everything on screen is drawn out to path as JSON. See
+    /// [`crate::geometry`].
+    ExportLayout(String),
+    /// ImportJson(register, path)
+    ///
+    /// Merges the arena written out as JSON to path by
+    /// [`CompleteAction::Export`] into this one, binding register to a map
+    /// pairing the names of its registers with their values. See
+    /// [`crate::json`].
+    ImportJson(String, String),
+    /// Diff(register, path)
+    ///
+    /// Compares the arena written out as JSON to path by
+    /// [`CompleteAction::Export`] with this one, binding register to a map
+    /// describing what changed since. See [`crate::arena::diff`] and
+    /// [`crate::arena::Arena::diff_view`].
+    Diff(String, String),
+    /// Versions
+    ///
+    /// Shows the earlier versions of the selected string or set in a panel,
+    /// oldest first. See [`crate::arena::Arena::versions`].
+    Versions,
+    /// RestoreVersion
+    ///
+    /// Puts back the earlier version selected in the panel shown by
+    /// [`CompleteAction::Versions`], keeping what it replaces as a version.
+    /// See [`crate::arena::Arena::restore_version`].
+    RestoreVersion,
+    /// ScaleUi(steps)
+    ///
+    /// Makes text and other small details need a number of steps more room
+    /// on screen before they are drawn, or less if steps is negative. This
+    /// is on top of the scale factor of the display.
+    ScaleUi(i32),
+    /// Pin(slot)
+    ///
+    /// Pins the value the topmost layer displays to slot, which is shown in a
+    /// bar along the bottom of the screen. See [`crate::arena::Arena::pin`].
+    Pin(usize),
+    /// RefocusPin(slot)
+    ///
+    /// Displays the value pinned to slot in the topmost layer, like selecting
+    /// it.
+    RefocusPin(usize),
+    /// ToggleFullscreen
+    ///
+    /// Switches the window between filling the screen it is on (without
+    /// borders) and being an ordinary window.
+    ToggleFullscreen,
+    /// Minimize
+    ///
+    /// Minimizes the window.
+    Minimize,
+    /// SetWindowSize(size)
+    ///
+    /// Resizes the window to size, like `1280x720`, leaving fullscreen if
+    /// necessary.
+    SetWindowSize(String),
+    /// CycleUsageOrder
+    ///
+    /// Switches between arranging the members of sets with the most recently
+    /// visited ones first, with the most often visited ones first, and in no
+    /// particular order. See [`crate::arena::Arena::arrange`].
+    CycleUsageOrder,
+    /// Explore
+    ///
+    /// Starts (or stops) wandering around the arena, displaying a different
+    /// value every few seconds. Whether it started or stopped is shown on the
+    /// message line. See [`crate::explore`].
+    Explore,
+    /// Compact
+    ///
+    /// Removes values that can't be reached any more and releases memory that
+    /// has built up over a long session, reporting how much was released. See
+    /// [`crate::arena::Arena::collect_garbage`] and
+    /// [`crate::arena::Arena::compact`].
+    Compact,
+    /// Inspect
+    ///
+    /// Displays the selected image (or the image the topmost layer displays,
+    /// if nothing is selected) on its own so that it can be zoomed into and
+    /// panned around. Key bindings switch to [`InputMode::Inspect`] until
+    /// inspecting stops.
+    Inspect,
+    /// InspectPan(dx, dy)
+    ///
+    /// Moves the part of the inspected image being displayed by a step to the
+    /// right (dx) and down (dy). Negative steps move left and up.
+    InspectPan(i32, i32),
+    /// InspectZoom(steps)
+    ///
+    /// Magnifies the inspected image by a number of steps, or shrinks it if
+    /// steps is negative.
+    InspectZoom(i32),
+    /// InspectActualSize
+    ///
+    /// Switches between drawing one pixel of the inspected image per pixel of
+    /// the screen and fitting the whole image on screen.
+    InspectActualSize,
+    /// StopInspecting
+    ///
+    /// Stops inspecting an image, switching key bindings back to
+    /// [`InputMode::Normal`].
+    StopInspecting,
+}
+
+impl CompleteAction {
+    /// Returns true if this action changes what is stored in the arena,
+    /// rather than only what is displayed.
+    ///
+    /// Binding the `.` register only changes what is displayed. Running a
+    /// query doesn't count either, even though it normally saves the query.
+    /// Whether [`CompleteAction::Confirm`] changes anything depends on what
+    /// is being confirmed, so it isn't counted here.
+    pub fn mutates(&self) -> bool {
+        match self {
+            CompleteAction::SetInsert(..)
+            | CompleteAction::SetUnion(..)
+            | CompleteAction::SetRemove(..)
+            | CompleteAction::ToggleMembership(..)
+            | CompleteAction::InsertStringIntoSetRegister(..)
+            | CompleteAction::InsertNumberIntoSetRegister(..)
+            | CompleteAction::Arithmetic(..)
+            | CompleteAction::CaptureString(_)
+            | CompleteAction::BindRegisterToString(..)
+            | CompleteAction::BindRegisterToEmptySet(_)
+            | CompleteAction::BindRegisterToEmptyList(_)
+            | CompleteAction::ListPush(..)
+            | CompleteAction::ListPop(_)
+            | CompleteAction::ListInsertAt(..)
+            | CompleteAction::ListRemoveAt(..)
+            | CompleteAction::ListSwap(..)
+            | CompleteAction::ListMove(..)
+            | CompleteAction::FindInto(..)
+            | CompleteAction::ImportJson(..)
+            | CompleteAction::Diff(..)
+            | CompleteAction::RestoreVersion
+            | CompleteAction::CycleValueLayout
+            | CompleteAction::SetValueLayout(_)
+            | CompleteAction::Rotate(_)
+            | CompleteAction::MarkMove
+            | CompleteAction::SuggestGrouping
+            | CompleteAction::FindDuplicates
+            | CompleteAction::FlattenOnce
+            | CompleteAction::Rewrite(..)
+            | CompleteAction::Undo
+            | CompleteAction::Redo
+            | CompleteAction::JumpToCheckpoint(_)
+            | CompleteAction::ToggleLock
+            | CompleteAction::ToggleStar
+            | CompleteAction::Tag(_)
+            | CompleteAction::Untag(_)
+            | CompleteAction::Drop(..)
+            | CompleteAction::Pin(_) => true,
+            CompleteAction::BindRegisterToRegisterValue(register, _) => register != ".",
+            _ => false,
+        }
+    }
+
+    /// Returns true if this can change many values at once, so that it is
+    /// worth performing inside of a transaction (see
+    /// [`Arena::begin`](crate::arena::Arena::begin)), so that a panic partway
+    /// through doesn't leave it half done.
+    pub fn is_compound(&self) -> bool {
+        matches!(
+            self,
+            CompleteAction::SetUnion(..)
+                | CompleteAction::Confirm
+                | CompleteAction::SuggestGrouping
+                | CompleteAction::FlattenOnce
+                | CompleteAction::Rewrite(..)
+                | CompleteAction::Undo
+                | CompleteAction::Redo
+                | CompleteAction::JumpToCheckpoint(_)
+                | CompleteAction::Drop(..)
+                | CompleteAction::RestoreVersion
+        )
+    }
+}
+
+/// The way in which key presses are matched against key bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingMode {
+    /// Keys are identified by what the current keyboard layout says they are.
+    /// A binding for `key("s")` is triggered by whichever key types an 's',
+    /// wherever it is on the keyboard. This keeps the bindings mnemonic.
+    VirtualKey,
+    /// Keys are identified by their physical position, named after the key in
+    /// that position on a US QWERTY keyboard. A binding for `key("s")` is
+    /// triggered by the key to the right of caps lock, no matter what is
+    /// printed on it. This keeps the bindings in the same place on every
+    /// layout. Only Windows and Linux say where keys are; elsewhere, this
+    /// behaves like [`BindingMode::VirtualKey`]. See
+    /// [`crate::input_map::scancode_to_keyname_string`].
+    Scancode,
+}
+
+impl Default for BindingMode {
+    fn default() -> Self {
+        Self::VirtualKey
+    }
+}
+
+/// Which set of key bindings key presses are matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    /// The default key bindings.
+    Normal,
+    /// Key bindings for moving around an image being inspected. See
+    /// [`CompleteAction::Inspect`].
+    Inspect,
 }
 
 /// Encapsulates everything needed to process user keyboard input.
@@ -103,41 +655,138 @@ pub enum CompleteAction {
 /// information.
 pub struct InputManager {
     key_binder: KeyBinder,
+    inspect_key_binder: KeyBinder,
+    mode: InputMode,
     input_state: InputState,
     pressed_keys: PressedKeys,
+    binding_mode: BindingMode,
+    /// Associates the names of keys that have been pressed (according to
+    /// `binding_mode`) with what the current keyboard layout calls them. See
+    /// [`InputManager::key_label`].
+    key_labels: HashMap<String, &'static str>,
+    memory: PromptMemory,
 }
 
 impl InputManager {
-    /// Creates a new [`InputManager`] backed with the default key bindings.
-    pub fn new() -> Self {
+    /// Creates a new [`InputManager`] backed with the default key bindings,
+    /// which are matched against key presses according to `binding_mode`.
+    pub fn new(binding_mode: BindingMode) -> Self {
         let mut key_binder = KeyBinder::new();
         key_binder.with_default_bindings();
+        let mut inspect_key_binder = KeyBinder::new();
+        inspect_key_binder.with_inspect_bindings();
         let input_state = key_binder.start_state().unwrap();
         Self {
             key_binder,
+            inspect_key_binder,
+            mode: InputMode::Normal,
             input_state,
             pressed_keys: PressedKeys {
                 shift_pressed: false,
+                control_pressed: false,
             },
+            binding_mode,
+            key_labels: HashMap::new(),
+            memory: PromptMemory::default(),
+        }
+    }
+
+    pub fn binding_mode(&self) -> BindingMode {
+        self.binding_mode
+    }
+
+    pub fn mode(&self) -> InputMode {
+        self.mode
+    }
+
+    /// Switches which key bindings key presses are matched against,
+    /// discarding any partially entered input.
+    pub fn set_mode(&mut self, mode: InputMode) {
+        self.mode = mode;
+        self.input_state = self.key_binder().start_state().unwrap();
+    }
+
+    fn key_binder(&self) -> &KeyBinder {
+        match self.mode {
+            InputMode::Normal => &self.key_binder,
+            InputMode::Inspect => &self.inspect_key_binder,
         }
     }
 
+    /// Returns the name that the current keyboard layout gives to the key
+    /// that `key` (as passed to [`key`] when binding it) refers to, keeping
+    /// any modifiers in front of it, like `control+shift+`.
+    ///
+    /// When using [`BindingMode::Scancode`], `key("s")` might refer to a key
+    /// labelled 'o' (on Dvorak, for instance). winit has no way of asking
+    /// what the layout calls a key, so labels are learned as keys are
+    /// pressed. Until a key has been pressed, its label is `key` itself.
+    pub fn key_label(&self, key: &str) -> String {
+        for &modifiers in &["control+shift+", "control+"] {
+            if let Some(unmodified) = key.strip_prefix(modifiers) {
+                return format!("{}{}", modifiers, self.key_label(unmodified));
+            }
+        }
+        self.key_labels.get(key).copied().unwrap_or(key).to_string()
+    }
+
+    /// Returns true if either control key is held down.
+    pub fn control_pressed(&self) -> bool {
+        self.pressed_keys.control_pressed
+    }
+
     /// Receives keyboard input from the window, accumulating it. Returns a
     /// [`CompleteAction`] if the current accumulation of input is complete,
     /// as is determined by the [`KeyBinder`].
     pub fn process_input(&mut self, keyboard_input: &KeyboardInput) -> Option<CompleteAction> {
         let pressed = keyboard_input.state == ElementState::Pressed;
         if let Some(virtual_key_code) = &keyboard_input.virtual_keycode {
-            // Update `modifiers` if shift was pressed or unpressed.
-            match virtual_key_code {
+            // Update `modifiers` if shift or control was pressed or unpressed.
+            let modifier = match virtual_key_code {
                 VirtualKeyCode::LShift | VirtualKeyCode::RShift => {
-                    self.pressed_keys.shift_pressed = pressed
+                    self.pressed_keys.shift_pressed = pressed;
+                    true
                 }
-                _ => {}
+                VirtualKeyCode::LControl | VirtualKeyCode::RControl => {
+                    self.pressed_keys.control_pressed = pressed;
+                    true
+                }
+                _ => false,
+            };
+
+            let label = crate::input_map::vk_to_keyname_string(virtual_key_code);
+            let name = match self.binding_mode {
+                BindingMode::VirtualKey => label,
+                BindingMode::Scancode => {
+                    crate::input_map::scancode_to_keyname_string(keyboard_input.scancode)
+                        .unwrap_or(label)
+                }
+            };
+            if pressed {
+                self.key_labels.insert(name.into(), label);
             }
 
+            // Keys pressed while holding control are named after the modifiers
+            // held, like `control+shift+1`, so that they can be bound
+            // separately from the keys themselves.
+            let modified_name;
+            let name = if self.pressed_keys.control_pressed && !modifier {
+                let shift = if self.pressed_keys.shift_pressed {
+                    "shift+"
+                } else {
+                    ""
+                };
+                modified_name = format!("control+{}{}", shift, name);
+                modified_name.as_str()
+            } else {
+                name
+            };
+
             let input = Input {
-                virtual_key_code,
+                event: InputEvent::Key {
+                    virtual_key_code,
+                    name,
+                },
                 pressed_keys: &self.pressed_keys,
             };
 
@@ -146,7 +795,11 @@ impl InputManager {
             // key becomes something that can be tracked inside of the key
             // binder.
             if pressed {
-                self.key_binder.process_input(&mut self.input_state, input)
+                let key_binder = match self.mode {
+                    InputMode::Normal => &self.key_binder,
+                    InputMode::Inspect => &self.inspect_key_binder,
+                };
+                key_binder.process_input(&mut self.input_state, input, &mut self.memory)
             } else {
                 None
             }
@@ -154,6 +807,232 @@ impl InputManager {
             None
         }
     }
+
+    /// Starts over in [`InputMode::Normal`] as if the key called `name` had
+    /// just been pressed, with no modifiers held, discarding any partially
+    /// entered input. This lets a key binding be started from outside of the
+    /// window (see [`crate::background`]), which never saw the key press.
+    pub fn press(
+        &mut self,
+        virtual_key_code: VirtualKeyCode,
+        name: &str,
+    ) -> Option<CompleteAction> {
+        self.set_mode(InputMode::Normal);
+        self.pressed_keys = PressedKeys {
+            shift_pressed: false,
+            control_pressed: false,
+        };
+        let input = Input {
+            event: InputEvent::Key {
+                virtual_key_code: &virtual_key_code,
+                name,
+            },
+            pressed_keys: &self.pressed_keys,
+        };
+        self.key_binder
+            .process_input(&mut self.input_state, input, &mut self.memory)
+    }
+
+    /// Returns the action that finishing the string being entered would
+    /// complete, as if what has been typed so far were the whole string. Returns
+    /// [`None`] if no string is being entered.
+    ///
+    /// This lets what is being typed be shown before it is finished.
+    pub fn partial_action(&self) -> Option<CompleteAction> {
+        self.key_binder().partial_action(&self.input_state)
+    }
+
+    /// Returns the string, register or choice prompt waiting for input, if
+    /// there is one. The text of a choice prompt is its palette of options.
+    /// The keys of its key binding are named by [`InputManager::key_label`].
+    pub fn prompt(&self) -> Option<Prompt> {
+        let (kind, text) = self.key_binder().prompt(&self.input_state)?;
+        let keys = self.input_state.processed_keys;
+        let entered = self
+            .input_state
+            .processed_input
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                if index < keys {
+                    self.key_label(entry)
+                } else {
+                    entry.clone()
+                }
+            })
+            .collect();
+        Some(Prompt {
+            kind,
+            entered,
+            text,
+        })
+    }
+
+    /// Sets the names of the registers that register names typed out in
+    /// register prompts are completed to.
+    pub fn set_register_names(&mut self, register_names: Vec<String>) {
+        self.memory.register_names = register_names;
+    }
+
+    /// Receives a character typed by the user, as produced by the current
+    /// keyboard layout. Characters are only used when entering strings (key
+    /// bindings and registers are entered with
+    /// [`process_input`](InputManager::process_input)), which lets the user
+    /// type any symbol their layout has, not just the ones on a US keyboard.
+    pub fn process_character(&mut self, character: char) -> Option<CompleteAction> {
+        let input = Input {
+            event: InputEvent::Character(character),
+            pressed_keys: &self.pressed_keys,
+        };
+        let key_binder = match self.mode {
+            InputMode::Normal => &self.key_binder,
+            InputMode::Inspect => &self.inspect_key_binder,
+        };
+        key_binder.process_input(&mut self.input_state, input, &mut self.memory)
+    }
+}
+
+/// The most entries each kind of prompt remembers.
+const HISTORY_LENGTH: usize = 100;
+
+/// The kinds of input a [`Prompt`] can wait for. Each kind has its own
+/// history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PromptKind {
+    String,
+    Register,
+    Choice,
+}
+
+/// A prompt waiting for the user to enter a string or a register, or to make
+/// a choice. See
+/// [`InputManager::prompt`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    /// What was entered before the prompt, starting with the keys of its key
+    /// binding.
+    pub entered: Vec<String>,
+    /// What has been entered into the prompt so far.
+    pub text: String,
+}
+
+impl fmt::Display for Prompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            PromptKind::String => "string",
+            PromptKind::Register => "register",
+            PromptKind::Choice => "choice",
+        };
+        write!(f, "{} {}: {}", self.entered.join(" "), kind, self.text)
+    }
+}
+
+/// What prompts remember between uses.
+#[derive(Default)]
+struct PromptMemory {
+    /// What was entered into each kind of prompt, oldest first.
+    history: HashMap<PromptKind, Vec<String>>,
+    /// The names register names are completed to. See
+    /// [`InputManager::set_register_names`].
+    register_names: Vec<String>,
+}
+
+impl PromptMemory {
+    fn history(&self, kind: PromptKind) -> &[String] {
+        self.history.get(&kind).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds `entry` to the end of the history of `kind`, moving it there if it
+    /// was entered before.
+    fn remember(&mut self, kind: PromptKind, entry: &str) {
+        // Every option of a choice is always on screen, so there is nothing
+        // to go back to.
+        if entry.is_empty() || kind == PromptKind::Choice {
+            return;
+        }
+        let history = self.history.entry(kind).or_default();
+        history.retain(|old| old != entry);
+        history.push(entry.into());
+        if history.len() > HISTORY_LENGTH {
+            history.remove(0);
+        }
+    }
+
+    /// Returns the longest name that every register name starting with
+    /// `prefix` starts with, if any register name starts with `prefix`.
+    fn complete(&self, prefix: &str) -> Option<String> {
+        let mut candidates = self
+            .register_names
+            .iter()
+            .filter(|name| name.starts_with(prefix));
+        let mut completion = candidates.next()?.as_str();
+        for candidate in candidates {
+            let common = completion
+                .char_indices()
+                .zip(candidate.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(completion.len().min(candidate.len()), |((index, _), _)| {
+                    index
+                });
+            completion = &completion[..common];
+        }
+        Some(completion.into())
+    }
+}
+
+/// Where a prompt is in its history, while it is being gone through with up
+/// and down.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Browsing {
+    index: usize,
+    /// What had been entered before going through the history, which is
+    /// restored by going forward past its end.
+    draft: String,
+}
+
+/// Replaces `text` with the entry of `history` before (if `older`) or after
+/// the one being displayed, keeping track of where we are in `browsing`.
+fn browse(browsing: &mut Option<Browsing>, text: &mut String, history: &[String], older: bool) {
+    if history.is_empty() {
+        return;
+    }
+    let index = match browsing {
+        None if older => {
+            *browsing = Some(Browsing {
+                index: history.len() - 1,
+                draft: text.clone(),
+            });
+            history.len() - 1
+        }
+        None => return,
+        Some(Browsing { index, .. }) if older => {
+            *index = index.saturating_sub(1);
+            *index
+        }
+        Some(Browsing { index, .. }) if *index + 1 < history.len() => {
+            *index += 1;
+            *index
+        }
+        Some(Browsing { draft, .. }) => {
+            *text = std::mem::take(draft);
+            *browsing = None;
+            return;
+        }
+    };
+    *text = history[index].clone();
+}
+
+/// Returns the position named by the number typed before a key binding (see
+/// [`count`]), which is 1 if none was typed.
+fn position(count: &str) -> usize {
+    count.parse().unwrap_or(1)
+}
+
+/// Returns the pin slot named by the key that was pressed to bind it, like 3 for
+/// `control+shift+3`.
+fn pin_slot(key: &str) -> usize {
+    key.rsplit('+').next().unwrap().parse().unwrap()
 }
 
 /// A stage of user input inside a [`KeyBinder`].
@@ -166,8 +1045,12 @@ enum InputAccumulationStage {
     InputRequirement(InputRequirement),
     /// The final stage of a series of user inputs. Holds a function that takes
     /// the user input (as was accumulated in previous stages) and produces a
-    /// [`CompleteAction`].
-    Done(fn(accumulated_input: &mut Vec<String>) -> CompleteAction),
+    /// [`CompleteAction`]. If the [`bool`] is true, the number typed before
+    /// the key binding (see [`count`]) comes first in the user input.
+    Done(
+        fn(accumulated_input: &mut Vec<String>) -> CompleteAction,
+        bool,
+    ),
 }
 
 /// Associates descriptions of user input with [`CompleteAction`]s.
@@ -197,12 +1080,58 @@ impl KeyBinder {
         self.bind(vec![key("e")], |_| {
             CompleteAction::BindRegisterToEmptySet(".".into())
         });
+        self.bind(vec![key("control+o")], |_| {
+            CompleteAction::BindRegisterToEmptyList(".".into())
+        });
+        self.bind(vec![key("control+i"), register()], |v| {
+            let register = v.pop().unwrap();
+            CompleteAction::ListPush(".".into(), register)
+        });
+        self.bind(vec![key("control+x")], |_| {
+            CompleteAction::ListPop(".".into())
+        });
+        self.bind(vec![count(), key("control+u"), register()], |v| {
+            let register = v.pop().unwrap();
+            CompleteAction::ListInsertAt(".".into(), position(&v[0]), register)
+        });
+        self.bind(vec![count(), key("control+shift+x")], |v| {
+            CompleteAction::ListRemoveAt(".".into(), position(&v[0]))
+        });
+        self.bind(vec![count(), key("control+w")], |v| {
+            CompleteAction::ListSwap(".".into(), position(&v[0]))
+        });
+        self.bind(vec![count(), key("control+m"), string()], |v| {
+            let to = v.pop().unwrap();
+            CompleteAction::ListMove(".".into(), position(&v[0]), to)
+        });
         self.bind(vec![key("v")], |_| CompleteAction::Registers);
         self.bind(vec![key("p")], |_| CompleteAction::Back);
         self.bind(vec![key("t"), string()], |v| {
             let string = v.pop().unwrap();
             CompleteAction::InsertStringIntoSetRegister(".".into(), string)
         });
+        self.bind(vec![key("c"), string()], |v| {
+            let string = v.pop().unwrap();
+            CompleteAction::CaptureString(string)
+        });
+        self.bind(vec![key("="), string()], |v| {
+            let number = v.pop().unwrap();
+            CompleteAction::InsertNumberIntoSetRegister(".".into(), number)
+        });
+        self.bind(
+            vec![
+                key("control+a"),
+                choice(OPERATION_CHOICES),
+                register(),
+                register(),
+            ],
+            |v| {
+                let b = v.pop().unwrap();
+                let a = v.pop().unwrap();
+                let operation = v.pop().unwrap();
+                CompleteAction::Arithmetic(operation, ".".into(), a, b)
+            },
+        );
         self.bind(vec![key("i"), register()], |v| {
             let register = v.pop().unwrap();
             CompleteAction::SetInsert(".".into(), register)
@@ -215,6 +1144,159 @@ impl KeyBinder {
             let register = v.pop().unwrap();
             CompleteAction::SetRemove(".".into(), register)
         });
+        self.bind(vec![key("f3"), register()], |v| {
+            let register = v.pop().unwrap();
+            CompleteAction::ToggleMembership(".".into(), register)
+        });
+        self.bind(vec![key("o"), register()], |v| {
+            let register = v.pop().unwrap();
+            CompleteAction::PushOverlay(register)
+        });
+        self.bind(vec![key("escape")], |_| CompleteAction::PopOverlay);
+        self.bind(vec![key("l")], |_| CompleteAction::ToggleLayout);
+        self.bind(vec![key("f12")], |_| CompleteAction::Validate);
+        self.bind(vec![key("f1")], |_| CompleteAction::RestartTutorial);
+        self.bind(vec![key("return")], |_| CompleteAction::Confirm);
+        self.bind(vec![key("tab")], |_| CompleteAction::SelectNext);
+        self.bind(vec![key("d")], |_| CompleteAction::Descend);
+        self.bind(vec![key("x")], |_| CompleteAction::CancelTasks);
+        self.bind(vec![key("u")], |_| CompleteAction::OpenUrl);
+        self.bind(vec![key("control+l")], |_| CompleteAction::CopyLink);
+        self.bind(vec![key("w"), string()], |v| {
+            let resolution = v.pop().unwrap();
+            CompleteAction::Capture(resolution)
+        });
+        self.bind(vec![key("g")], |_| CompleteAction::SuggestGrouping);
+        self.bind(vec![key("control+d")], |_| CompleteAction::FindDuplicates);
+        self.bind(vec![key("f")], |_| CompleteAction::FlattenOnce);
+        self.bind(vec![key("control+r"), string(), string()], |v| {
+            let replacement = v.pop().unwrap();
+            let pattern = v.pop().unwrap();
+            CompleteAction::Rewrite(pattern, replacement)
+        });
+        self.bind(vec![key("z")], |_| CompleteAction::Undo);
+        self.bind(vec![key("control+y")], |_| CompleteAction::Redo);
+        self.bind(vec![key("control+c"), string()], |v| {
+            let name = v.pop().unwrap();
+            CompleteAction::Checkpoint(name)
+        });
+        self.bind(vec![key("control+j"), string()], |v| {
+            let name = v.pop().unwrap();
+            CompleteAction::JumpToCheckpoint(name)
+        });
+        self.bind(vec![key("control+shift+c")], |_| {
+            CompleteAction::Checkpoints
+        });
+        self.bind(vec![key("y")], |_| CompleteAction::MarkMove);
+        self.bind(vec![key("k")], |_| CompleteAction::CycleValueLayout);
+        self.bind(vec![key("control+k"), choice(LAYOUT_CHOICES)], |v| {
+            let layout = v.pop().unwrap();
+            CompleteAction::SetValueLayout(layout)
+        });
+        self.bind(vec![key("[")], |_| CompleteAction::Rotate(1));
+        self.bind(vec![key("]")], |_| CompleteAction::Rotate(-1));
+        self.bind(vec![key("q"), string()], |v| {
+            let query = v.pop().unwrap();
+            CompleteAction::Query(query)
+        });
+        self.bind(vec![key("j")], |_| CompleteAction::RunQuery);
+        self.bind(vec![key("control+f"), register(), string()], |v| {
+            let query = v.pop().unwrap();
+            let register = v.pop().unwrap();
+            CompleteAction::FindInto(register, query)
+        });
+        self.bind(vec![key("m")], |_| CompleteAction::ToggleSample);
+        self.bind(vec![key("n")], |_| CompleteAction::Reshuffle);
+        self.bind(vec![key("f2")], |_| CompleteAction::ToggleReadOnly);
+        self.bind(vec![key("control+h")], |_| CompleteAction::ToggleHeatmap);
+        self.bind(vec![key("control+b")], |_| {
+            CompleteAction::TogglePowerSaving
+        });
+        self.bind(vec![key("h")], |_| CompleteAction::ToggleLock);
+        self.bind(vec![key("a")], |_| CompleteAction::ToggleSharing);
+        self.bind(vec![key("control+s")], |_| CompleteAction::ToggleStar);
+        self.bind(vec![key("control+shift+s")], |_| {
+            CompleteAction::SelectRegister(STARRED_REGISTER.into())
+        });
+        self.bind(vec![key("control+t"), string()], |v| {
+            let tag = v.pop().unwrap();
+            CompleteAction::Tag(tag)
+        });
+        self.bind(vec![key("control+shift+t"), string()], |v| {
+            let tag = v.pop().unwrap();
+            CompleteAction::Untag(tag)
+        });
+        self.bind(vec![key("control+g"), string()], |v| {
+            let tag = v.pop().unwrap();
+            CompleteAction::ShowTag(tag)
+        });
+        self.bind(vec![key("control+q"), string()], |v| {
+            let query = v.pop().unwrap();
+            CompleteAction::Highlight(query)
+        });
+        self.bind(vec![key("control+n")], |_| CompleteAction::NextMatch);
+        self.bind(vec![key("control+p")], |_| CompleteAction::PreviousMatch);
+        self.bind(vec![key("control+e"), string()], |v| {
+            let path = v.pop().unwrap();
+            CompleteAction::Export(path)
+        });
+        self.bind(vec![key("control+shift+e"), string()], |v| {
+            let path = v.pop().unwrap();
+            CompleteAction::ExportLayout(path)
+        });
+        self.bind(vec![key("control+shift+i"), register(), string()], |v| {
+            let path = v.pop().unwrap();
+            let register = v.pop().unwrap();
+            CompleteAction::ImportJson(register, path)
+        });
+        self.bind(vec![key("control+shift+d"), register(), string()], |v| {
+            let path = v.pop().unwrap();
+            let register = v.pop().unwrap();
+            CompleteAction::Diff(register, path)
+        });
+        self.bind(vec![key("control+v")], |_| CompleteAction::Versions);
+        self.bind(vec![key("control+shift+v")], |_| {
+            CompleteAction::RestoreVersion
+        });
+        self.bind(vec![key("control+=")], |_| CompleteAction::ScaleUi(1));
+        self.bind(vec![key("control+-")], |_| CompleteAction::ScaleUi(-1));
+        self.bind(vec![key("f4")], |_| CompleteAction::Inspect);
+        self.bind(vec![key("f5")], |_| CompleteAction::Compact);
+        self.bind(vec![key("f6")], |_| CompleteAction::CycleUsageOrder);
+        self.bind(vec![key("f7")], |_| {
+            CompleteAction::SelectRegister(RECENT_REGISTER.into())
+        });
+        self.bind(vec![key("control+f7")], |_| {
+            CompleteAction::SelectRegister(CHANGED_REGISTER.into())
+        });
+        self.bind(vec![key("f10")], |_| CompleteAction::Explore);
+        self.bind(vec![key("f11")], |_| CompleteAction::ToggleFullscreen);
+        self.bind(vec![key("f9")], |_| CompleteAction::Minimize);
+        self.bind(vec![key("f8"), string()], |v| {
+            let size = v.pop().unwrap();
+            CompleteAction::SetWindowSize(size)
+        });
+        for slot in 1..=PIN_SLOTS {
+            self.bind(vec![key(&format!("control+shift+{}", slot))], |v| {
+                CompleteAction::Pin(pin_slot(&v.pop().unwrap()))
+            });
+            self.bind(vec![key(&format!("control+{}", slot))], |v| {
+                CompleteAction::RefocusPin(pin_slot(&v.pop().unwrap()))
+            });
+        }
+    }
+
+    /// Registers the key bindings used while inspecting an image.
+    fn with_inspect_bindings(&mut self) {
+        self.bind(vec![key("left")], |_| CompleteAction::InspectPan(-1, 0));
+        self.bind(vec![key("right")], |_| CompleteAction::InspectPan(1, 0));
+        self.bind(vec![key("up")], |_| CompleteAction::InspectPan(0, -1));
+        self.bind(vec![key("down")], |_| CompleteAction::InspectPan(0, 1));
+        self.bind(vec![key("=")], |_| CompleteAction::InspectZoom(1));
+        self.bind(vec![key("-")], |_| CompleteAction::InspectZoom(-1));
+        self.bind(vec![key("1")], |_| CompleteAction::InspectActualSize);
+        self.bind(vec![key("escape")], |_| CompleteAction::StopInspecting);
+        self.bind(vec![key("f4")], |_| CompleteAction::StopInspecting);
     }
 
     /// Associates a description of user input with a function that takes that
@@ -231,12 +1313,16 @@ impl KeyBinder {
     /// both of the same type (expecting the user to input a key).
     fn bind(
         &mut self,
-        descriptors: Vec<InputRequirementDescriptor>,
+        mut descriptors: Vec<InputRequirementDescriptor>,
         action_constructor: fn(accumulated_input: &mut Vec<String>) -> CompleteAction,
     ) {
+        let counted = matches!(descriptors.first(), Some(InputRequirementDescriptor::Count));
+        if counted {
+            descriptors.remove(0);
+        }
         let action_key = self
             .slot_map
-            .insert(InputAccumulationStage::Done(action_constructor));
+            .insert(InputAccumulationStage::Done(action_constructor, counted));
         // Insert the descriptors in the reverse order. We need to do this in
         // reverse because each InputRequirement needs to know the KeyBinderKey
         // of the NEXT InputRequirement; key(a) in [key(a), key(b)] needs to
@@ -291,13 +1377,68 @@ impl KeyBinder {
         self.start_stage.map(|start_state| InputState {
             current_stage: start_state,
             processed_input: vec![],
+            processed_keys: 0,
             current_processor: None,
+            count: String::new(),
         })
     }
 
+    /// Returns the kind of prompt `input_state` is waiting at, alongside what
+    /// has been entered into it so far. See [`InputManager::prompt`].
+    fn prompt(&self, input_state: &InputState) -> Option<(PromptKind, String)> {
+        let requirement = match self.slot_map.get(input_state.current_stage)? {
+            InputAccumulationStage::InputRequirement(requirement) => requirement,
+            InputAccumulationStage::Done(..) => return None,
+        };
+        let kind = requirement.prompt_kind()?;
+        let text = match &input_state.current_processor {
+            Some(InputProcessor::Choice { options, index }) => palette(options, *index),
+            None => match requirement {
+                InputRequirement::Choice(options, _) => palette(options, 0),
+                _ => String::new(),
+            },
+            Some(InputProcessor::String(StringProcessor { string, .. })) => string.clone(),
+            Some(InputProcessor::Register {
+                name: Some(name), ..
+            }) => name.clone(),
+            Some(InputProcessor::Register { local: true, .. }) => LOCAL_REGISTER_SIGIL.to_string(),
+            _ => String::new(),
+        };
+        Some((kind, text))
+    }
+
+    /// See [`InputManager::partial_action`].
+    fn partial_action(&self, input_state: &InputState) -> Option<CompleteAction> {
+        let next_stage = match self.slot_map.get(input_state.current_stage)? {
+            InputAccumulationStage::InputRequirement(InputRequirement::String(next_stage)) => {
+                next_stage
+            }
+            _ => return None,
+        };
+        let (action_constructor, counted) = match self.slot_map.get(*next_stage)? {
+            InputAccumulationStage::Done(action_constructor, counted) => {
+                (action_constructor, *counted)
+            }
+            _ => return None,
+        };
+        // The processor isn't created until the first input of the stage
+        // arrives, so the string may not have been started yet.
+        let string = match &input_state.current_processor {
+            Some(InputProcessor::String(StringProcessor { string, .. })) => string.clone(),
+            _ => String::new(),
+        };
+        let mut accumulated_input = input_state.processed_input.clone();
+        if counted {
+            accumulated_input.insert(0, input_state.count.clone());
+        }
+        accumulated_input.push(string);
+        Some(action_constructor(&mut accumulated_input))
+    }
+
     /// Modifies `input_state` based on `input`, possibly returning a
     /// [`CompleteAction`] if `input` moves `input_state` into a
-    /// [`InputAccumulationStage::Done`] stage.
+    /// [`InputAccumulationStage::Done`] stage. Whatever is entered into string
+    /// and register prompts is remembered in `memory`.
     ///
     /// This function will panic with [`unreachable!`] if `input_state` is
     /// already in a [`InputAccumulationStage::Done`] at the time of calling.
@@ -309,10 +1450,25 @@ impl KeyBinder {
         &self,
         input_state: &mut InputState,
         input: Input<'a>,
+        memory: &mut PromptMemory,
     ) -> Option<CompleteAction> {
         match self.slot_map.get(input_state.current_stage).unwrap() {
-            InputAccumulationStage::Done(_) => unreachable!(),
+            InputAccumulationStage::Done(..) => unreachable!(),
             InputAccumulationStage::InputRequirement(processing) => {
+                if let InputEvent::Key { name, .. } = input.event {
+                    // Number keys that aren't bound themselves are typed
+                    // before a key binding to count it.
+                    let digit = name.len() == 1 && name.chars().all(|c| c.is_ascii_digit());
+                    if digit
+                        && Some(input_state.current_stage) == self.start_stage
+                        && processing.next_stage(&name.to_string()).is_none()
+                    {
+                        if input_state.count.len() < MAX_COUNT_DIGITS {
+                            input_state.count.push_str(name);
+                        }
+                        return None;
+                    }
+                }
                 // if this is the first time process_input has been called with
                 // input_state in its current stage, it does not yet have a
                 // processor. Create it.
@@ -325,25 +1481,35 @@ impl KeyBinder {
                 // `input_state` later.
                 let mut next_value = None;
                 let mut next_stage = None;
-                processor.process(input).map(|result| {
+                processor.process(input, memory).map(|result| {
                     next_stage = Some(processing.next_stage(&result)).unwrap();
                     next_value = Some(result);
                 });
                 // (later):
                 next_value.map(|next_value| {
+                    if let Some(kind) = processing.prompt_kind() {
+                        memory.remember(kind, &next_value);
+                    } else if input_state.processed_keys == input_state.processed_input.len() {
+                        input_state.processed_keys += 1;
+                    }
                     input_state.processed_input.push(next_value);
                     input_state.current_processor = None;
                 });
                 next_stage.map(|next_stage| input_state.current_stage = *next_stage);
-                if let InputAccumulationStage::Done(action_constructor) =
+                if let InputAccumulationStage::Done(action_constructor, counted) =
                     self.slot_map.get(input_state.current_stage).unwrap()
                 {
+                    let count = std::mem::take(&mut input_state.count);
+                    if *counted {
+                        input_state.processed_input.insert(0, count);
+                    }
                     let complete_action = action_constructor(&mut input_state.processed_input);
 
                     // return `input_state` to the start state, so its memory
                     // can be re-used for handling future inputs.
 
                     input_state.processed_input.clear();
+                    input_state.processed_keys = 0;
                     // if we don't reset to a non-Done stage, we could hit the
                     // unreachable! code above.
                     input_state.current_stage = self.start_stage.unwrap();
@@ -369,8 +1535,18 @@ pub struct InputState {
     /// contain the input being currently accumulated in the current stage
     /// (that's a part of `current_processor`).
     processed_input: Vec<String>,
+    /// How many of the entries at the start of `processed_input` are keys of
+    /// a key binding, rather than something entered into a prompt.
+    processed_keys: usize,
+    /// The digits of the number typed before the key binding, if any. See
+    /// [`count`].
+    count: String,
 }
 
+/// The most digits the number typed before a key binding can have. See
+/// [`count`].
+const MAX_COUNT_DIGITS: usize = 6;
+
 new_key_type! {
     /// Key for referring to [`InputAccumulationStage`]s in a [`KeyBinder`].
     pub struct KeyBinderKey;
@@ -391,17 +1567,48 @@ pub fn register() -> InputRequirementDescriptor {
     InputRequirementDescriptor::Register
 }
 
+/// Shorthand for `InputRequirementDescriptor::Count`.
+pub fn count() -> InputRequirementDescriptor {
+    InputRequirementDescriptor::Count
+}
+
+/// Shorthand for `InputRequirementDescriptor::Choice(options)`.
+pub fn choice(options: &'static [&'static str]) -> InputRequirementDescriptor {
+    InputRequirementDescriptor::Choice(options)
+}
+
+/// Writes out `options` the way a choice prompt draws them, numbered and with
+/// the option at `index` highlighted, like `1 red  [2 green]  3 blue`.
+fn palette(options: &[&str], index: usize) -> String {
+    options
+        .iter()
+        .enumerate()
+        .map(|(i, option)| {
+            if i == index {
+                format!("[{} {}]", i + 1, option)
+            } else {
+                format!("{} {}", i + 1, option)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
 /// Describes the type of input we expect to receive in a current stage.
 ///
-/// Use the [`key`], [`string`], and [`register`] functions to create these less
-/// verbosely.
+/// Use the [`key`], [`string`], [`register`], and [`choice`] functions to
+/// create these less verbosely.
 pub enum InputRequirementDescriptor {
     /// We expect the user to press a specific key. The key we expect the user
     /// to press is the [`String`].
     Key(String),
     /// We expect the user to enter a register. Entering a register is the same
     /// as entering a Key, except that we move on to the next stage regardless
-    /// of which register was entered.
+    /// of which register was entered. Pressing the [`LOCAL_REGISTER_SIGIL`]
+    /// first enters a local register named after the key pressed next (see
+    /// [`crate::arena::is_local_register`]). Pressing tab first instead types
+    /// the name of a register out, finishing it with enter (see [the
+    /// module-level documentation](crate::input_manager)).
     Register,
     /// We expect the user to enter a string. Strings are entered by pressing a
     /// series of keys (the characters of the string) followed by shift+enter to
@@ -414,6 +1621,21 @@ pub enum InputRequirementDescriptor {
     /// depending on whether or not the string entered matches a regular
     /// expression.
     String,
+    /// We expect the user to choose one of a few options, like a color or a
+    /// layout. The options are drawn as a palette in the minibuffer (see
+    /// [`InputManager::prompt`]). Left and right (or up and down) move
+    /// between them, and enter chooses the highlighted one; pressing a number
+    /// key chooses that option straight away. The name of the chosen option
+    /// is what is entered, and we move onto the next stage regardless of
+    /// which option was chosen.
+    Choice(&'static [&'static str]),
+    /// We expect the user may have typed a number before the key binding,
+    /// like the 3 in `3 control+x`, using number keys that aren't bound to
+    /// anything themselves. The number (or an empty string, if none was
+    /// typed) comes first in the user input, before the keys of the key
+    /// binding. This can only start a key binding, and isn't a stage of its
+    /// own: a number typed before any other key binding is ignored.
+    Count,
 }
 
 impl InputRequirementDescriptor {
@@ -423,13 +1645,15 @@ impl InputRequirementDescriptor {
     /// of input.
     ///
     /// Arguments:
-    /// 
+    ///
     /// * `key`: the next stage of input.
     fn to_input_requirement(self, key: KeyBinderKey) -> InputRequirement {
         match self {
             Self::Register => InputRequirement::Register(key),
             Self::String => InputRequirement::String(key),
+            Self::Choice(options) => InputRequirement::Choice(options, key),
             Self::Key(code) => InputRequirement::Key(vec![(code, key)].into_iter().collect()),
+            Self::Count => panic!("a count can only start a key binding"),
         }
     }
 }
@@ -443,6 +1667,9 @@ enum InputRequirement {
     /// The user will input an entire [`String`]. We unconditionally move onto a
     /// single next stage, represented by the [`KeyBinderKey`].
     String(KeyBinderKey),
+    /// The user will choose one of the options. We unconditionally move onto a
+    /// single next stage, represented by the [`KeyBinderKey`].
+    Choice(&'static [&'static str], KeyBinderKey),
     /// The user will input a single key. To determine what stage to go to next,
     /// we look up they key in the [`HashMap`].
     Key(HashMap<String, KeyBinderKey>),
@@ -453,49 +1680,180 @@ enum InputRequirement {
 struct StringProcessor {
     string: String,
     done: bool,
+    /// Whether or not a key has been pressed since we started accumulating
+    /// the string. Characters arrive after the key press that typed them, so
+    /// the character typed by the key that moved us into this stage would
+    /// otherwise end up at the start of the string.
+    key_pressed: bool,
+    browsing: Option<Browsing>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 enum InputProcessor {
-    Register,
+    /// `local` is true once the [`LOCAL_REGISTER_SIGIL`] has been pressed, in
+    /// which case the next key names a local register. `name` is the name
+    /// being typed out, once tab (or up) has been pressed.
+    Register {
+        local: bool,
+        name: Option<String>,
+        browsing: Option<Browsing>,
+    },
     Key,
     String(StringProcessor),
+    /// `index` is the position of the highlighted option among `options`.
+    Choice {
+        options: &'static [&'static str],
+        index: usize,
+    },
 }
 
 struct PressedKeys {
     shift_pressed: bool,
+    control_pressed: bool,
 }
 
 pub struct Input<'a> {
     pressed_keys: &'a PressedKeys,
-    virtual_key_code: &'a VirtualKeyCode,
+    event: InputEvent<'a>,
+}
+
+enum InputEvent<'a> {
+    /// A key was pressed. `name` is what the key is called for the purpose of
+    /// matching key bindings (see [`BindingMode`]).
+    Key {
+        virtual_key_code: &'a VirtualKeyCode,
+        name: &'a str,
+    },
+    /// The keyboard layout produced a character.
+    Character(char),
 }
 
 impl InputProcessor {
-    fn process(&mut self, input: Input) -> Option<String> {
+    fn process(&mut self, input: Input, memory: &PromptMemory) -> Option<String> {
         match self {
-            Self::Register => {
-                Some(crate::input_map::vk_to_keyname_string(input.virtual_key_code).into())
-            }
-            Self::Key => {
-                Some(crate::input_map::vk_to_keyname_string(input.virtual_key_code).into())
-            }
-            Self::String(StringProcessor { string, done }) => {
+            Self::Register {
+                name: Some(name),
+                browsing,
+                ..
+            } => match input.event {
+                InputEvent::Key {
+                    virtual_key_code, ..
+                } => {
+                    match virtual_key_code {
+                        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter
+                            if !name.is_empty() =>
+                        {
+                            return Some(name.clone());
+                        }
+                        VirtualKeyCode::Delete => {
+                            name.pop();
+                        }
+                        VirtualKeyCode::Tab => {
+                            if let Some(completion) = memory.complete(name) {
+                                *name = completion;
+                            }
+                        }
+                        VirtualKeyCode::Up | VirtualKeyCode::Down => browse(
+                            browsing,
+                            name,
+                            memory.history(PromptKind::Register),
+                            *virtual_key_code == VirtualKeyCode::Up,
+                        ),
+                        _ => {}
+                    }
+                    None
+                }
+                InputEvent::Character(character) => {
+                    if !character.is_control() {
+                        name.push(character);
+                    }
+                    None
+                }
+            },
+            Self::Register {
+                local,
+                name,
+                browsing,
+            } => match input.event {
+                // Names are typed out from tab, or from the most recent entry
+                // in the history.
+                InputEvent::Key {
+                    virtual_key_code: VirtualKeyCode::Tab,
+                    ..
+                } if !*local => {
+                    *name = Some(String::new());
+                    None
+                }
+                InputEvent::Key {
+                    virtual_key_code: VirtualKeyCode::Up,
+                    ..
+                } if !*local => {
+                    let mut typed = String::new();
+                    browse(
+                        browsing,
+                        &mut typed,
+                        memory.history(PromptKind::Register),
+                        true,
+                    );
+                    *name = Some(typed);
+                    None
+                }
+                InputEvent::Key { name, .. } if *local => {
+                    Some(format!("{}{}", LOCAL_REGISTER_SIGIL, name))
+                }
+                InputEvent::Key { name, .. } if name.chars().eq(Some(LOCAL_REGISTER_SIGIL)) => {
+                    *local = true;
+                    None
+                }
+                InputEvent::Key { name, .. } => Some(name.into()),
+                InputEvent::Character(_) => None,
+            },
+            Self::Key => match input.event {
+                InputEvent::Key { name, .. } => Some(name.into()),
+                InputEvent::Character(_) => None,
+            },
+            Self::Choice { options, index } => match input.event {
+                InputEvent::Key {
+                    virtual_key_code,
+                    name,
+                } => match virtual_key_code {
+                    VirtualKeyCode::Left | VirtualKeyCode::Up => {
+                        *index = (*index + options.len() - 1) % options.len();
+                        None
+                    }
+                    VirtualKeyCode::Right | VirtualKeyCode::Down => {
+                        *index = (*index + 1) % options.len();
+                        None
+                    }
+                    VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                        Some(options[*index].into())
+                    }
+                    // Number keys work whether or not control is still held
+                    // from the key binding.
+                    _ => name
+                        .rsplit('+')
+                        .next()
+                        .and_then(|number| number.parse::<usize>().ok())
+                        .and_then(|number| options.get(number.checked_sub(1)?))
+                        .map(|&option| option.into()),
+                },
+                InputEvent::Character(_) => None,
+            },
+            Self::String(StringProcessor {
+                string,
+                done,
+                key_pressed,
+                browsing,
+            }) => {
                 enum Do {
                     Insert(String),
                     Delete(bool),
+                    /// Go through the history, towards older entries if true.
+                    Browse(bool),
                     Done,
                     Nothing,
                 }
 
-                let fix_case = |str: &'static str| -> String {
-                    if input.pressed_keys.shift_pressed {
-                        str.to_uppercase()
-                    } else {
-                        str.into()
-                    }
-                };
-
                 let enter = || -> Do {
                     if input.pressed_keys.shift_pressed {
                         Do::Done
@@ -504,80 +1862,31 @@ impl InputProcessor {
                     }
                 };
 
-                let d = match input.virtual_key_code {
-                    VirtualKeyCode::Key1 => Do::Insert("1".into()),
-                    VirtualKeyCode::Key2 => Do::Insert("2".into()),
-                    VirtualKeyCode::Key3 => Do::Insert("3".into()),
-                    VirtualKeyCode::Key4 => Do::Insert("4".into()),
-                    VirtualKeyCode::Key5 => Do::Insert("5".into()),
-                    VirtualKeyCode::Key6 => Do::Insert("6".into()),
-                    VirtualKeyCode::Key7 => Do::Insert("7".into()),
-                    VirtualKeyCode::Key8 => Do::Insert("8".into()),
-                    VirtualKeyCode::Key9 => Do::Insert("9".into()),
-                    VirtualKeyCode::Key0 => Do::Insert("0".into()),
-                    VirtualKeyCode::A => Do::Insert(fix_case("a")),
-                    VirtualKeyCode::B => Do::Insert(fix_case("b")),
-                    VirtualKeyCode::C => Do::Insert(fix_case("c")),
-                    VirtualKeyCode::D => Do::Insert(fix_case("d")),
-                    VirtualKeyCode::E => Do::Insert(fix_case("e")),
-                    VirtualKeyCode::F => Do::Insert(fix_case("f")),
-                    VirtualKeyCode::G => Do::Insert(fix_case("g")),
-                    VirtualKeyCode::H => Do::Insert(fix_case("h")),
-                    VirtualKeyCode::I => Do::Insert(fix_case("i")),
-                    VirtualKeyCode::J => Do::Insert(fix_case("j")),
-                    VirtualKeyCode::K => Do::Insert(fix_case("k")),
-                    VirtualKeyCode::L => Do::Insert(fix_case("l")),
-                    VirtualKeyCode::M => Do::Insert(fix_case("m")),
-                    VirtualKeyCode::N => Do::Insert(fix_case("n")),
-                    VirtualKeyCode::O => Do::Insert(fix_case("o")),
-                    VirtualKeyCode::P => Do::Insert(fix_case("p")),
-                    VirtualKeyCode::Q => Do::Insert(fix_case("q")),
-                    VirtualKeyCode::R => Do::Insert(fix_case("r")),
-                    VirtualKeyCode::S => Do::Insert(fix_case("s")),
-                    VirtualKeyCode::T => Do::Insert(fix_case("t")),
-                    VirtualKeyCode::U => Do::Insert(fix_case("u")),
-                    VirtualKeyCode::V => Do::Insert(fix_case("v")),
-                    VirtualKeyCode::W => Do::Insert(fix_case("w")),
-                    VirtualKeyCode::X => Do::Insert(fix_case("x")),
-                    VirtualKeyCode::Y => Do::Insert(fix_case("y")),
-                    VirtualKeyCode::Z => Do::Insert(fix_case("z")),
-                    VirtualKeyCode::Delete => Do::Delete(input.pressed_keys.shift_pressed),
-                    VirtualKeyCode::Return => enter(),
-                    VirtualKeyCode::Space => Do::Insert(" ".into()),
-                    VirtualKeyCode::Numpad0 => Do::Insert("0".into()),
-                    VirtualKeyCode::Numpad1 => Do::Insert("1".into()),
-                    VirtualKeyCode::Numpad2 => Do::Insert("2".into()),
-                    VirtualKeyCode::Numpad3 => Do::Insert("3".into()),
-                    VirtualKeyCode::Numpad4 => Do::Insert("4".into()),
-                    VirtualKeyCode::Numpad5 => Do::Insert("5".into()),
-                    VirtualKeyCode::Numpad6 => Do::Insert("6".into()),
-                    VirtualKeyCode::Numpad7 => Do::Insert("7".into()),
-                    VirtualKeyCode::Numpad8 => Do::Insert("8".into()),
-                    VirtualKeyCode::Numpad9 => Do::Insert("9".into()),
-                    VirtualKeyCode::Divide => Do::Insert("/".into()),
-                    VirtualKeyCode::Decimal => Do::Insert(".".into()),
-                    VirtualKeyCode::NumpadComma => Do::Insert(",".into()),
-                    VirtualKeyCode::NumpadEnter => enter(),
-                    VirtualKeyCode::NumpadEquals => Do::Insert("=".into()),
-                    VirtualKeyCode::Multiply => Do::Insert("*".into()),
-                    VirtualKeyCode::Subtract => Do::Insert("-".into()),
-                    VirtualKeyCode::Apostrophe => Do::Insert("'".into()),
-                    VirtualKeyCode::At => Do::Insert("@".into()),
-                    VirtualKeyCode::Backslash => Do::Insert("\\".into()),
-                    VirtualKeyCode::Colon => Do::Insert(":".into()),
-                    VirtualKeyCode::Comma => Do::Insert(",".into()),
-                    VirtualKeyCode::Equals => Do::Insert("=".into()),
-                    VirtualKeyCode::Grave => Do::Insert("`".into()),
-                    VirtualKeyCode::LBracket => Do::Insert("[".into()),
-                    VirtualKeyCode::Minus => Do::Insert("-".into()),
-                    VirtualKeyCode::Period => Do::Insert(".".into()),
-                    VirtualKeyCode::Add => Do::Insert("+".into()),
-                    VirtualKeyCode::RBracket => Do::Insert("]".into()),
-                    VirtualKeyCode::Semicolon => Do::Insert(";".into()),
-                    VirtualKeyCode::Slash => Do::Insert("/".into()),
-                    VirtualKeyCode::Tab => Do::Insert("\t".into()),
-                    VirtualKeyCode::Underline => Do::Insert("_".into()),
-                    _ => Do::Nothing,
+                // Printable characters are inserted as they arrive from the
+                // keyboard layout. Only keys that don't type anything we want to
+                // keep (or that type something we want to handle differently,
+                // like enter) are handled by their key code.
+                let d = match input.event {
+                    InputEvent::Key {
+                        virtual_key_code, ..
+                    } => {
+                        *key_pressed = true;
+                        match virtual_key_code {
+                            VirtualKeyCode::Delete => Do::Delete(input.pressed_keys.shift_pressed),
+                            VirtualKeyCode::Return => enter(),
+                            VirtualKeyCode::NumpadEnter => enter(),
+                            VirtualKeyCode::Up => Do::Browse(true),
+                            VirtualKeyCode::Down => Do::Browse(false),
+                            _ => Do::Nothing,
+                        }
+                    }
+                    InputEvent::Character(character) => {
+                        if *key_pressed && (!character.is_control() || character == '\t') {
+                            Do::Insert(character.into())
+                        } else {
+                            Do::Nothing
+                        }
+                    }
                 };
                 match d {
                     Do::Insert(mut to_append) => {
@@ -608,6 +1917,9 @@ impl InputProcessor {
                             .collect::<String>()
                         };
                     }
+                    Do::Browse(older) => {
+                        browse(browsing, string, memory.history(PromptKind::String), older);
+                    }
                     Do::Done => *done = true,
                     Do::Nothing => {}
                 }
@@ -661,17 +1973,279 @@ impl InputRequirement {
         match self {
             InputRequirement::Register(k) => Some(k),
             InputRequirement::String(k) => Some(k),
+            InputRequirement::Choice(_, k) => Some(k),
             InputRequirement::Key(map) => map.get(data),
         }
     }
+
+    /// The kind of prompt this stage is, if it is one.
+    fn prompt_kind(&self) -> Option<PromptKind> {
+        match self {
+            InputRequirement::Register(_) => Some(PromptKind::Register),
+            InputRequirement::String(_) => Some(PromptKind::String),
+            InputRequirement::Choice(..) => Some(PromptKind::Choice),
+            InputRequirement::Key(_) => None,
+        }
+    }
+
     fn processor(&self) -> InputProcessor {
         match self {
-            Self::Register(_) => InputProcessor::Register,
+            Self::Register(_) => InputProcessor::Register {
+                local: false,
+                name: None,
+                browsing: None,
+            },
             Self::Key(_) => InputProcessor::Key,
+            Self::Choice(options, _) => InputProcessor::Choice {
+                options: *options,
+                index: 0,
+            },
             Self::String(_) => InputProcessor::String(StringProcessor {
                 string: "".into(),
                 done: false,
+                key_pressed: false,
+                browsing: None,
             }),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[allow(deprecated)]
+    fn press(scancode: u32, virtual_keycode: VirtualKeyCode) -> KeyboardInput {
+        KeyboardInput {
+            scancode,
+            state: ElementState::Pressed,
+            virtual_keycode: Some(virtual_keycode),
+            modifiers: Default::default(),
+        }
+    }
+
+    #[test]
+    fn string_entry_uses_characters() {
+        let mut input_manager = InputManager::new(BindingMode::VirtualKey);
+        assert_eq!(
+            None,
+            input_manager.process_input(&press(0x14, VirtualKeyCode::T))
+        );
+        // The 't' typed by the key binding shouldn't end up in the string.
+        assert_eq!(None, input_manager.process_character('t'));
+        assert_eq!(
+            None,
+            input_manager.process_input(&press(0x03, VirtualKeyCode::Key2))
+        );
+        assert_eq!(None, input_manager.process_character('é'));
+        input_manager.process_input(&press(0x2a, VirtualKeyCode::LShift));
+        assert_eq!(
+            Some(CompleteAction::InsertStringIntoSetRegister(
+                ".".into(),
+                "é".into()
+            )),
+            input_manager.process_input(&press(0x1c, VirtualKeyCode::Return))
+        );
+    }
+
+    #[test]
+    fn partial_strings() {
+        let mut input_manager = InputManager::new(BindingMode::VirtualKey);
+        assert_eq!(None, input_manager.partial_action());
+        input_manager.process_input(&press(0x14, VirtualKeyCode::T));
+        let partial = |string: &str| {
+            Some(CompleteAction::InsertStringIntoSetRegister(
+                ".".into(),
+                string.into(),
+            ))
+        };
+        assert_eq!(partial(""), input_manager.partial_action());
+        input_manager.process_character('t');
+        input_manager.process_input(&press(0x1e, VirtualKeyCode::A));
+        input_manager.process_character('a');
+        assert_eq!(partial("a"), input_manager.partial_action());
+        input_manager.process_input(&press(0x2a, VirtualKeyCode::LShift));
+        input_manager.process_input(&press(0x1c, VirtualKeyCode::Return));
+        assert_eq!(None, input_manager.partial_action());
+    }
+
+    #[test]
+    fn prompts() {
+        let mut input_manager = InputManager::new(BindingMode::VirtualKey);
+        input_manager.set_register_names(vec!["inbox".into(), "input".into(), "queries".into()]);
+        assert_eq!(None, input_manager.prompt());
+        input_manager.process_input(&press(0x1f, VirtualKeyCode::S));
+        input_manager.process_input(&press(0x0f, VirtualKeyCode::Tab));
+        input_manager.process_character('\t');
+        input_manager.process_input(&press(0x17, VirtualKeyCode::I));
+        input_manager.process_character('i');
+        input_manager.process_input(&press(0x0f, VirtualKeyCode::Tab));
+        assert_eq!(
+            "s register: in",
+            input_manager.prompt().unwrap().to_string()
+        );
+        input_manager.process_input(&press(0x30, VirtualKeyCode::B));
+        input_manager.process_character('b');
+        input_manager.process_input(&press(0x0f, VirtualKeyCode::Tab));
+        assert_eq!(
+            Some(CompleteAction::SelectRegister("inbox".into())),
+            input_manager.process_input(&press(0x1c, VirtualKeyCode::Return))
+        );
+        assert_eq!(None, input_manager.prompt());
+
+        // Up goes back through what was entered before.
+        input_manager.process_input(&press(0x1f, VirtualKeyCode::S));
+        input_manager.process_input(&press(0x48, VirtualKeyCode::Up));
+        let prompt = input_manager.prompt().unwrap();
+        assert_eq!(PromptKind::Register, prompt.kind);
+        assert_eq!("inbox", prompt.text);
+        input_manager.process_input(&press(0x50, VirtualKeyCode::Down));
+        assert_eq!("", input_manager.prompt().unwrap().text);
+    }
+
+    #[test]
+    fn choices() {
+        let mut input_manager = InputManager::new(BindingMode::VirtualKey);
+        input_manager.process_input(&press(0x1d, VirtualKeyCode::LControl));
+        input_manager.process_input(&press(0x25, VirtualKeyCode::K));
+        assert_eq!(
+            "control+k choice: [1 automatic]  2 circles  3 weighted circles  4 treemap  5 zoomed",
+            input_manager.prompt().unwrap().to_string()
+        );
+        input_manager.process_input(&press(0x4b, VirtualKeyCode::Left));
+        assert_eq!(
+            "1 automatic  2 circles  3 weighted circles  4 treemap  [5 zoomed]",
+            input_manager.prompt().unwrap().text
+        );
+        assert_eq!(
+            Some(CompleteAction::SetValueLayout("zoomed".into())),
+            input_manager.process_input(&press(0x1c, VirtualKeyCode::Return))
+        );
+        input_manager.process_input(&press(0x25, VirtualKeyCode::K));
+        assert_eq!(
+            Some(CompleteAction::SetValueLayout("treemap".into())),
+            input_manager.process_input(&press(0x05, VirtualKeyCode::Key4))
+        );
+    }
+
+    #[test]
+    fn capture_starts_string_entry() {
+        let mut input_manager = InputManager::new(BindingMode::VirtualKey);
+        assert_eq!(
+            None,
+            input_manager.process_input(&press(0x2e, VirtualKeyCode::C))
+        );
+        assert_eq!(None, input_manager.process_character('c'));
+        assert_eq!(
+            None,
+            input_manager.process_input(&press(0x17, VirtualKeyCode::I))
+        );
+        assert_eq!(None, input_manager.process_character('i'));
+        input_manager.process_input(&press(0x2a, VirtualKeyCode::LShift));
+        assert_eq!(
+            Some(CompleteAction::CaptureString("i".into())),
+            input_manager.process_input(&press(0x1c, VirtualKeyCode::Return))
+        );
+    }
+
+    #[test]
+    fn local_register_entry() {
+        let mut input_manager = InputManager::new(BindingMode::VirtualKey);
+        assert_eq!(
+            None,
+            input_manager.process_input(&press(0x1f, VirtualKeyCode::S))
+        );
+        assert_eq!(
+            None,
+            input_manager.process_input(&press(0x33, VirtualKeyCode::Comma))
+        );
+        assert_eq!(
+            Some(CompleteAction::SelectRegister(",a".into())),
+            input_manager.process_input(&press(0x1e, VirtualKeyCode::A))
+        );
+    }
+
+    #[test]
+    fn pin_bindings() {
+        let mut input_manager = InputManager::new(BindingMode::VirtualKey);
+        input_manager.process_input(&press(0x1d, VirtualKeyCode::LControl));
+        assert_eq!(
+            Some(CompleteAction::RefocusPin(4)),
+            input_manager.process_input(&press(0x05, VirtualKeyCode::Key4))
+        );
+        input_manager.process_input(&press(0x2a, VirtualKeyCode::LShift));
+        assert_eq!(
+            Some(CompleteAction::Pin(9)),
+            input_manager.process_input(&press(0x0a, VirtualKeyCode::Key9))
+        );
+    }
+
+    #[test]
+    fn counted_bindings() {
+        let mut input_manager = InputManager::new(BindingMode::VirtualKey);
+        input_manager.process_input(&press(0x03, VirtualKeyCode::Key2));
+        input_manager.process_input(&press(0x04, VirtualKeyCode::Key3));
+        input_manager.process_input(&press(0x1d, VirtualKeyCode::LControl));
+        input_manager.process_input(&press(0x2a, VirtualKeyCode::LShift));
+        assert_eq!(
+            Some(CompleteAction::ListRemoveAt(".".into(), 23)),
+            input_manager.process_input(&press(0x2d, VirtualKeyCode::X))
+        );
+        // The count is used up, and counts 1 if no number is typed.
+        let mut release = press(0x2a, VirtualKeyCode::LShift);
+        release.state = ElementState::Released;
+        input_manager.process_input(&release);
+        assert_eq!(
+            Some(CompleteAction::ListSwap(".".into(), 1)),
+            input_manager.process_input(&press(0x11, VirtualKeyCode::W))
+        );
+    }
+
+    #[test]
+    fn inspect_mode() {
+        let mut input_manager = InputManager::new(BindingMode::VirtualKey);
+        assert_eq!(
+            Some(CompleteAction::Inspect),
+            input_manager.process_input(&press(0x3e, VirtualKeyCode::F4))
+        );
+        input_manager.set_mode(InputMode::Inspect);
+        assert_eq!(
+            Some(CompleteAction::InspectPan(-1, 0)),
+            input_manager.process_input(&press(0x4b, VirtualKeyCode::Left))
+        );
+        // Keys bound in the normal mode do nothing while inspecting.
+        assert_eq!(
+            None,
+            input_manager.process_input(&press(0x2f, VirtualKeyCode::V))
+        );
+        assert_eq!(
+            Some(CompleteAction::StopInspecting),
+            input_manager.process_input(&press(0x01, VirtualKeyCode::Escape))
+        );
+        input_manager.set_mode(InputMode::Normal);
+        assert_eq!(
+            Some(CompleteAction::Registers),
+            input_manager.process_input(&press(0x2f, VirtualKeyCode::V))
+        );
+    }
+
+    #[test]
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    fn scancode_bindings() {
+        // The key where QWERTY has 'v' types 'k' on Dvorak.
+        let mut input_manager = InputManager::new(BindingMode::Scancode);
+        assert_eq!(
+            Some(CompleteAction::Registers),
+            input_manager.process_input(&press(0x2f, VirtualKeyCode::K))
+        );
+        assert_eq!("k", input_manager.key_label("v"));
+        assert_eq!(
+            "control+shift+k",
+            input_manager.key_label("control+shift+v")
+        );
+        assert_eq!("e", input_manager.key_label("e"));
+        // The key where QWERTY has 's' types 'o' on Dvorak.
+        input_manager.process_input(&press(0x1f, VirtualKeyCode::O));
+        assert_eq!("o register: ", input_manager.prompt().unwrap().to_string());
+    }
+}
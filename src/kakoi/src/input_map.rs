@@ -164,4 +164,94 @@ pub fn vk_to_keyname_string(code: &VirtualKeyCode) -> &'static str {
         VirtualKeyCode::Paste => "paste",
         VirtualKeyCode::Cut => "cut",
     }
-}
\ No newline at end of file
+}
+
+/// Returns the name of the key at the physical position `scancode` on a US
+/// QWERTY keyboard, or [`None`] if `scancode` isn't in the main block of keys.
+///
+/// This lets key bindings refer to where a key is rather than what is printed
+/// on it, so that (for instance) the bindings on the home row stay on the home
+/// row no matter which keyboard layout is in use. The names are the same as the
+/// ones returned by [`vk_to_keyname_string`] for a US QWERTY layout.
+///
+/// The scancodes are the ones reported by winit on Windows and Linux (PC
+/// scancode set 1). Other platforms number their keys differently, so there
+/// this always returns [`None`], and keys are bound by their labels instead.
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+pub fn scancode_to_keyname_string(scancode: u32) -> Option<&'static str> {
+    let name = match scancode {
+        0x01 => "escape",
+        0x02 => "1",
+        0x03 => "2",
+        0x04 => "3",
+        0x05 => "4",
+        0x06 => "5",
+        0x07 => "6",
+        0x08 => "7",
+        0x09 => "8",
+        0x0a => "9",
+        0x0b => "0",
+        0x0c => "-",
+        0x0d => "=",
+        0x0e => "back",
+        0x0f => "tab",
+        0x10 => "q",
+        0x11 => "w",
+        0x12 => "e",
+        0x13 => "r",
+        0x14 => "t",
+        0x15 => "y",
+        0x16 => "u",
+        0x17 => "i",
+        0x18 => "o",
+        0x19 => "p",
+        0x1a => "[",
+        0x1b => "]",
+        0x1c => "return",
+        0x1e => "a",
+        0x1f => "s",
+        0x20 => "d",
+        0x21 => "f",
+        0x22 => "g",
+        0x23 => "h",
+        0x24 => "j",
+        0x25 => "k",
+        0x26 => "l",
+        0x27 => ";",
+        0x28 => "'",
+        0x29 => "`",
+        0x2b => "\\",
+        0x2c => "z",
+        0x2d => "x",
+        0x2e => "c",
+        0x2f => "v",
+        0x30 => "b",
+        0x31 => "n",
+        0x32 => "m",
+        0x33 => ",",
+        0x34 => ".",
+        0x35 => "/",
+        0x39 => "space",
+        0x3b => "f1",
+        0x3c => "f2",
+        0x3d => "f3",
+        0x3e => "f4",
+        0x3f => "f5",
+        0x40 => "f6",
+        0x41 => "f7",
+        0x42 => "f8",
+        0x43 => "f9",
+        0x44 => "f10",
+        0x57 => "f11",
+        0x58 => "f12",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Other platforms number their keys differently, so no scancode has a name
+/// there.
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn scancode_to_keyname_string(_scancode: u32) -> Option<&'static str> {
+    None
+}
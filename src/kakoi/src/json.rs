@@ -0,0 +1,961 @@
+//! # Exporting and importing JSON
+//!
+//! Outlines and web pages (see [`crate::export`] and [`crate::html`]) are
+//! meant to be read by people. To get everything out of an arena and into
+//! scripts and other tools, [`to_json`] writes the whole arena out as JSON
+//! instead: everything that can be reached from a register (see
+//! [`Arena::saved_values`]). Values nothing refers to any more are left out,
+//! and so are the registers Kakoi derives from how the arena was used, like
+//! the set bound to the [`CHANGED_REGISTER`], since they describe what
+//! happened while Kakoi was running rather than what was entered into it.
+//! [`CompleteAction::Export`] writes it when given a path ending in `.json`,
+//! whatever is selected, and so does `kakoi export`. It can also be reached
+//! as [`crate::arena::export::to_json`].
+//!
+//! Values refer to each other by their [`Value::uuid`]s, which stay the same
+//! however often the arena is exported. The JSON has four fields:
+//!
+//! * `registers`, pairing the name of each register with the uuid of the
+//!   value bound to it.
+//! * `local_registers`, pairing the uuid of each value that has local
+//!   registers (see [`crate::arena::is_local_register`]) with its registers,
+//!   written like `registers`.
+//! * `merged`, pairing the uuid of each value that was merged into another
+//!   (see [`Arena::merge_into`]) with the uuid of the value it was merged
+//!   into, so that links to it (see [`crate::link`]) still lead somewhere.
+//! * `values`, pairing the uuid of every other value with an object whose
+//!   `kind` says what else it has:
+//!   * a `set` has its `members`, a `list` or `command` its `elements`, and a
+//!     `map` its `entries` as pairs of a key and a value, all as uuids;
+//!   * a `string` has its `text`, and a `number` its `value`;
+//!   * an `image` has its `width` and `height`, and its pixels as a base64
+//!     `png`, and a `linked_image` the `path` of the file it is read from;
+//!   * a `file` (see [`crate::blob`]) has its `name`, its `mime` type, and its
+//!     contents as base64 `bytes`;
+//!   * a `custom` leaf (see [`crate::leaf`]) has its `type`, and the bytes it
+//!     serializes to as base64 `bytes`.
+//!
+//!   Values whose [`Metadata`] was recorded also have the time they were
+//!   `created`, in seconds since 1970, and their `author`, if known.
+//!
+//! Values, and the members of sets and maps, are sorted by uuid, so that
+//! exporting the same arena twice gives the same JSON. Anything that isn't a
+//! value (like where values are drawn, or how often they are visited) is left
+//! out.
+//!
+//! [`from_json`] reads it back in, merging it into an existing arena
+//! (performed by [`CompleteAction::ImportJson`]). Values keep their uuids, so
+//! importing the same JSON twice, or JSON exported from this very arena, only
+//! stores each value once. Like [`Arena::merge`], a container that is already
+//! in the arena is used as-is, rather than having its members replaced. The
+//! imported registers don't replace any of the arena's own: they are put in a
+//! new map, pairing their names with their values, which is bound to a
+//! register of your choosing. Local registers (like the earlier versions of a
+//! value, see [`Arena::versions`]) are bound for the values that are new to
+//! the arena; a value that was already there keeps its own. Likewise, a value
+//! that was already merged into another stays merged into that one.
+//!
+//! Importing is all-or-nothing: if anything can't be inserted, like an image
+//! that can't be decoded, the whole import is rolled back (see
+//! [`Arena::begin`]).
+//!
+//! [`load`] reads it into an arena of its own instead, to see what changed
+//! since it was exported (see [`crate::arena::diff`], performed by
+//! [`CompleteAction::Diff`]).
+//!
+//! [`Value::uuid`]: crate::arena::Value::uuid
+//! [`CHANGED_REGISTER`]: crate::arena::CHANGED_REGISTER
+//! [`Metadata`]: crate::arena::Metadata
+//! [`CompleteAction::Export`]: crate::input_manager::CompleteAction::Export
+//! [`CompleteAction::ImportJson`]: crate::input_manager::CompleteAction::ImportJson
+//! [`CompleteAction::Diff`]: crate::input_manager::CompleteAction::Diff
+
+use crate::arena::{Arena, ArenaKey, Kind, Metadata, Structure};
+use crate::blob::Blob;
+use crate::html::{base64, png};
+use crate::number;
+use std::collections::{HashMap, HashSet};
+use std::str::CharIndices;
+use std::time::{Duration, UNIX_EPOCH};
+use std::{fs, io, path::Path};
+use uuid::Uuid;
+
+/// Returns true if `path` ends in `.json`, so that exporting to it should
+/// write JSON.
+pub fn is_json_path<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("json"))
+}
+
+/// Writes `text` as a JSON string.
+fn string(text: &str) -> String {
+    let mut written = String::with_capacity(text.len() + 2);
+    written.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => written += "\\\"",
+            '\\' => written += "\\\\",
+            '\n' => written += "\\n",
+            '\r' => written += "\\r",
+            '\t' => written += "\\t",
+            c if (c as u32) < 0x20 => written += &format!("\\u{:04x}", c as u32),
+            c => written.push(c),
+        }
+    }
+    written.push('"');
+    written
+}
+
+fn uuid(arena: &Arena, key: ArenaKey) -> String {
+    string(&arena.slot_map[key].uuid.to_string())
+}
+
+/// Writes the uuids of `keys` as a JSON array, sorted.
+fn uuids<I: IntoIterator<Item = ArenaKey>>(arena: &Arena, keys: I) -> String {
+    let mut uuids: Vec<String> = keys.into_iter().map(|key| uuid(arena, key)).collect();
+    uuids.sort_unstable();
+    format!("[{}]", uuids.join(", "))
+}
+
+/// Writes the registers in the map `registers` as a JSON object pairing their
+/// names with the uuids of their values, leaving out those whose values aren't
+/// in `saved`.
+fn registers(arena: &Arena, registers: ArenaKey, saved: &HashSet<ArenaKey>) -> String {
+    let mut fields: Vec<String> = match &arena.slot_map[registers].structure {
+        Structure::Map(map) => map
+            .iter()
+            .filter(|&(&name, value)| saved.contains(value) && !arena.is_derived_register(name))
+            .filter_map(|(&name, &value)| match &arena.slot_map[name].structure {
+                Structure::String(name) => {
+                    Some(format!("{}: {}", string(name), uuid(arena, value)))
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+    fields.sort_unstable();
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn value(arena: &Arena, key: ArenaKey) -> String {
+    let described = match &arena.slot_map[key].structure {
+        Structure::Set(set) => format!(
+            "{{\"kind\": \"set\", \"members\": {}}}",
+            uuids(arena, set.iter().copied())
+        ),
+        Structure::List(list) | Structure::Command(list) => {
+            let elements: Vec<String> = list.iter().map(|&element| uuid(arena, element)).collect();
+            format!(
+                "{{\"kind\": \"{}\", \"elements\": [{}]}}",
+                arena.slot_map[key].structure.kind().name(),
+                elements.join(", ")
+            )
+        }
+        Structure::Map(map) => {
+            let mut entries: Vec<String> = map
+                .iter()
+                .map(|(&k, &v)| format!("[{}, {}]", uuid(arena, k), uuid(arena, v)))
+                .collect();
+            entries.sort_unstable();
+            format!(
+                "{{\"kind\": \"map\", \"entries\": [{}]}}",
+                entries.join(", ")
+            )
+        }
+        Structure::String(text) => format!("{{\"kind\": \"string\", \"text\": {}}}", string(text)),
+        Structure::Number(n) => format!(
+            "{{\"kind\": \"number\", \"value\": {}}}",
+            number::format(*n)
+        ),
+        Structure::Image(image) => format!(
+            "{{\"kind\": \"image\", \"width\": {}, \"height\": {}, \"png\": {}}}",
+            image.width(),
+            image.height(),
+            string(&png(arena, key).map_or(String::new(), |png| base64(&png)))
+        ),
+        Structure::ImageRef(path) => format!(
+            "{{\"kind\": \"linked_image\", \"path\": {}}}",
+            string(&path.to_string_lossy())
+        ),
+        Structure::Blob(blob) => format!(
+            "{{\"kind\": \"file\", \"name\": {}, \"mime\": {}, \"bytes\": {}}}",
+            string(&blob.name),
+            string(&blob.mime),
+            string(&base64(&blob.bytes))
+        ),
+        Structure::Custom(custom) => format!(
+            "{{\"kind\": \"custom\", \"type\": {}, \"bytes\": {}}}",
+            string(custom.leaf.type_name()),
+            string(&base64(&custom.leaf.serialize()))
+        ),
+    };
+    let mut metadata = String::new();
+    if let Some(created) = arena
+        .metadata(key)
+        .and_then(|metadata| metadata.created?.duration_since(UNIX_EPOCH).ok())
+    {
+        metadata += &format!(", \"created\": {}", number::format(created.as_secs_f64()));
+    }
+    if let Some(author) = arena
+        .metadata(key)
+        .and_then(|metadata| metadata.author.as_ref())
+    {
+        metadata += &format!(", \"author\": {}", string(author));
+    }
+    // Put the metadata inside of the object's closing brace.
+    format!("{}{}}}", &described[..described.len() - 1], metadata)
+}
+
+/// Describes every value in `arena` worth saving as JSON. See [the
+/// module-level documentation](crate::json).
+pub fn to_json(arena: &Arena) -> String {
+    describe(arena).0
+}
+
+/// Like [`to_json`], also returning how many values were described.
+fn describe(arena: &Arena) -> (String, usize) {
+    let saved = arena.saved_values();
+    let register_maps: HashSet<ArenaKey> = arena.register_maps().collect();
+    let mut local_registers: Vec<String> = saved
+        .iter()
+        .filter_map(|&owner| {
+            let map = arena.local_registers(owner)?;
+            Some(format!(
+                "    {}: {}",
+                uuid(arena, owner),
+                registers(arena, map, &saved)
+            ))
+        })
+        .collect();
+    local_registers.sort_unstable();
+    let mut merged: Vec<String> = arena
+        .merged_uuids()
+        .filter(|(_, into)| {
+            let into = arena.lookup_uuid(into);
+            into.map_or(false, |into| saved.contains(&into))
+        })
+        .map(|(from, into)| {
+            let (from, into) = (from.to_string(), into.to_string());
+            format!("{}: {}", string(&from), string(&into))
+        })
+        .collect();
+    merged.sort_unstable();
+    let mut values: Vec<(String, ArenaKey)> = saved
+        .iter()
+        .filter(|key| !register_maps.contains(key))
+        .map(|&key| (uuid(arena, key), key))
+        .collect();
+    values.sort_unstable();
+    let count = values.len();
+    let values: Vec<String> = values
+        .into_iter()
+        .map(|(uuid, key)| format!("    {}: {}", uuid, value(arena, key)))
+        .collect();
+    let json = format!(
+        "{{\n  \"registers\": {},\n  \"local_registers\": {{\n{}\n  }},\n  \"merged\": {{{}}},\n  \"values\": {{\n{}\n  }}\n}}\n",
+        registers(arena, arena.register_map, &saved),
+        local_registers.join(",\n"),
+        merged.join(", "),
+        values.join(",\n")
+    );
+    (json, count)
+}
+
+/// Writes the JSON [`to_json`] describes to `path`, returning how many values
+/// were written.
+pub fn export<P: AsRef<Path>>(arena: &Arena, path: P) -> io::Result<usize> {
+    let (json, count) = describe(arena);
+    fs::write(path, json)?;
+    Ok(count)
+}
+
+/// A JSON value, as read by [`parse`].
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// The fields of an object, in the order they were written.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Returns the field of this object named `name`.
+    fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(fields) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+/// Reads JSON one byte at a time.
+struct Parser<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.at).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.at += 1;
+        }
+    }
+
+    /// Skips whitespace and then `expected`, or returns [`None`] if something
+    /// else comes first.
+    fn expect(&mut self, expected: &str) -> Option<()> {
+        self.skip_whitespace();
+        if self.text[self.at..].starts_with(expected) {
+            self.at += expected.len();
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'n' => self.expect("null").map(|_| Json::Null),
+            b't' => self.expect("true").map(|_| Json::Bool(true)),
+            b'f' => self.expect("false").map(|_| Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' => {
+                self.at += 1;
+                let mut elements = vec![];
+                if self.expect("]").is_none() {
+                    loop {
+                        elements.push(self.value()?);
+                        if self.expect(",").is_none() {
+                            self.expect("]")?;
+                            break;
+                        }
+                    }
+                }
+                Some(Json::Array(elements))
+            }
+            b'{' => {
+                self.at += 1;
+                let mut fields = vec![];
+                if self.expect("}").is_none() {
+                    loop {
+                        self.skip_whitespace();
+                        let name = self.string()?;
+                        self.expect(":")?;
+                        fields.push((name, self.value()?));
+                        if self.expect(",").is_none() {
+                            self.expect("}")?;
+                            break;
+                        }
+                    }
+                }
+                Some(Json::Object(fields))
+            }
+            _ => {
+                let rest = &self.text[self.at..];
+                let len = rest
+                    .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+                    .unwrap_or_else(|| rest.len());
+                self.at += len;
+                rest[..len].parse().ok().map(Json::Number)
+            }
+        }
+    }
+
+    /// Reads a string, starting at its opening quote.
+    fn string(&mut self) -> Option<String> {
+        if self.peek()? != b'"' {
+            return None;
+        }
+        self.at += 1;
+        let mut text = String::new();
+        let mut chars = self.text[self.at..].char_indices();
+        loop {
+            let (i, c) = chars.next()?;
+            match c {
+                '"' => {
+                    self.at += i + 1;
+                    return Some(text);
+                }
+                '\\' => match chars.next()?.1 {
+                    '"' => text.push('"'),
+                    '\\' => text.push('\\'),
+                    '/' => text.push('/'),
+                    'b' => text.push('\u{8}'),
+                    'f' => text.push('\u{c}'),
+                    'n' => text.push('\n'),
+                    'r' => text.push('\r'),
+                    't' => text.push('\t'),
+                    'u' => {
+                        let mut code = hex(&mut chars)?;
+                        if (0xd800..0xdc00).contains(&code) {
+                            // The first half of a surrogate pair, which must
+                            // be followed by the second half.
+                            if chars.next()?.1 != '\\' || chars.next()?.1 != 'u' {
+                                return None;
+                            }
+                            let low = hex(&mut chars)?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return None;
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        text.push(std::char::from_u32(code)?);
+                    }
+                    _ => return None,
+                },
+                c => text.push(c),
+            }
+        }
+    }
+}
+
+/// Reads the four hexadecimal digits of a `\u` escape.
+fn hex(chars: &mut CharIndices) -> Option<u32> {
+    let digits: String = (0..4)
+        .map(|_| chars.next().map(|(_, c)| c))
+        .collect::<Option<_>>()?;
+    u32::from_str_radix(&digits, 16).ok()
+}
+
+/// Reads `text` as JSON, or returns [`None`] if it isn't.
+fn parse(text: &str) -> Option<Json> {
+    let mut parser = Parser { text, at: 0 };
+    let json = parser.value()?;
+    parser.skip_whitespace();
+    if parser.at == text.len() {
+        Some(json)
+    } else {
+        None
+    }
+}
+
+/// Decodes `text` as written by [`base64`].
+fn unbase64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=').as_bytes();
+    let mut decoded = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut n = 0;
+        for (i, &c) in chunk.iter().enumerate() {
+            let digit = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return None,
+            };
+            n |= (digit as u32) << (18 - 6 * i);
+        }
+        decoded.extend_from_slice(&n.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(decoded)
+}
+
+/// Inserts the value described by `value` into `arena`, or returns [`None`]
+/// if it doesn't describe one. Sets, lists and maps are inserted empty.
+fn insert(arena: &mut Arena, value: &Json) -> Option<ArenaKey> {
+    let bytes = |field: &str| unbase64(value.get(field)?.as_str()?);
+    Some(match value.get("kind")?.as_str()? {
+        "set" => arena.set(vec![]),
+        "list" => arena.list(vec![]),
+        "map" => arena.map(vec![]),
+        "string" => arena.string(value.get("text")?.as_str()?),
+        "number" => arena.number(value.get("value")?.as_f64()?),
+        "image" => arena.image(image::load_from_memory(&bytes("png")?).ok()?.into_rgba8()),
+        "linked_image" => arena.image_ref(value.get("path")?.as_str()?),
+        "file" => arena.blob(Blob {
+            name: value.get("name")?.as_str()?.to_string(),
+            mime: value.get("mime")?.as_str()?.to_string(),
+            bytes: bytes("bytes")?,
+        }),
+        "custom" => arena.deserialize_leaf(value.get("type")?.as_str()?, &bytes("bytes")?)?,
+        _ => return None,
+    })
+}
+
+/// Returns the metadata of the value described by `value`, if it has any and
+/// when it was created can be represented.
+fn metadata(value: &Json) -> Option<Metadata> {
+    let created = match value.get("created").and_then(Json::as_f64) {
+        Some(seconds) => {
+            let since = Duration::try_from_secs_f64(seconds).ok()?;
+            Some(UNIX_EPOCH.checked_add(since)?)
+        }
+        None => None,
+    };
+    let author = value
+        .get("author")
+        .and_then(Json::as_str)
+        .map(str::to_string);
+    if created.is_none() && author.is_none() {
+        None
+    } else {
+        Some(Metadata { created, author })
+    }
+}
+
+/// Returns the uuids in the array `json`.
+fn uuid_array(json: &Json) -> Option<Vec<Uuid>> {
+    json.as_array()?
+        .iter()
+        .map(|uuid| Uuid::parse_str(uuid.as_str()?).ok())
+        .collect()
+}
+
+/// Returns the uuids of the values contained in the container described by
+/// `value`, with the keys and values of maps taking turns, or an empty list if
+/// it describes a leaf. Returns [`None`] if it doesn't describe a value.
+fn contents(value: &Json) -> Option<Vec<Uuid>> {
+    match value.get("kind")?.as_str()? {
+        "set" => uuid_array(value.get("members")?),
+        "list" | "command" => uuid_array(value.get("elements")?),
+        "map" => value
+            .get("entries")?
+            .as_array()?
+            .iter()
+            .map(|entry| {
+                let entry = uuid_array(entry)?;
+                Some(entry).filter(|entry| entry.len() == 2)
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|entries| entries.concat()),
+        "string" | "number" | "image" | "linked_image" | "file" | "custom" => Some(vec![]),
+        _ => None,
+    }
+}
+
+/// Merges the arena described by `text` (as written by [`to_json`]) into
+/// `arena`, binding `register` to a map pairing the names of its registers
+/// with their values. Returns how many of its values were new to `arena`, or
+/// [`None`] if `text` doesn't describe an arena. See [the module-level
+/// documentation](crate::json).
+///
+/// Everything is checked before anything is inserted, except for whether
+/// images can be decoded and custom leaves deserialized. If one can't be,
+/// the import is rolled back, leaving `arena` as it was. If a transaction is
+/// already open, rolling back is left to whoever opened it.
+pub fn from_json(arena: &mut Arena, text: &str, register: &str) -> Option<usize> {
+    let json = parse(text)?;
+    let transaction = arena.begin().is_some();
+    let imported = import_into(arena, &json, register);
+    if transaction {
+        match imported {
+            Some(_) => arena.commit(),
+            None => arena.rollback(),
+        };
+    }
+    imported
+}
+
+/// Performs [`from_json`], once `json` has been parsed.
+fn import_into(arena: &mut Arena, json: &Json, register: &str) -> Option<usize> {
+    let len = arena.slot_map.len();
+    let registers = insert_all(arena, json)?;
+    let inserted = arena.slot_map.len() - len;
+
+    let registers: Vec<(ArenaKey, ArenaKey)> = registers
+        .into_iter()
+        .map(|(name, key)| (arena.string(name), key))
+        .collect();
+    let map = arena.map(registers);
+    arena.bind_register(register, map);
+    Some(inserted)
+}
+
+/// Reads the arena described by `text` (as written by [`to_json`]) into an
+/// arena of its own, with its registers bound like they were, or returns
+/// [`None`] if `text` doesn't describe an arena. Meant for comparing it with
+/// another arena (see [`crate::arena::diff`]).
+pub fn read(text: &str) -> Option<Arena> {
+    let json = parse(text)?;
+    let mut arena = Arena::new();
+    for (name, key) in insert_all(&mut arena, &json)? {
+        arena.bind_register(name, key);
+    }
+    // Every arena starts out with a few values of its own.
+    arena.collect_garbage();
+    Some(arena)
+}
+
+/// Returns the registers described by `registers`, a JSON object pairing
+/// their names with uuids.
+fn register_uuids(registers: &Json) -> Option<Vec<(&str, Uuid)>> {
+    registers
+        .as_object()?
+        .iter()
+        .map(|(name, uuid)| Some((name.as_str(), Uuid::parse_str(uuid.as_str()?).ok()?)))
+        .collect()
+}
+
+/// Inserts every value described by `json` into `arena`, returning its
+/// registers paired with their values. See [`from_json`].
+fn insert_all<'a>(arena: &mut Arena, json: &'a Json) -> Option<Vec<(&'a str, ArenaKey)>> {
+    let mut values = vec![];
+    let mut contained: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for (uuid, value) in json.get("values")?.as_object()? {
+        let uuid = Uuid::parse_str(uuid).ok()?;
+        contained.insert(uuid, contents(value)?);
+        values.push((uuid, value));
+    }
+    let registers = register_uuids(json.get("registers")?)?;
+    // Older exports may not have any local registers.
+    let local_registers = match json.get("local_registers") {
+        Some(local_registers) => local_registers
+            .as_object()?
+            .iter()
+            .map(|(owner, registers)| {
+                Some((Uuid::parse_str(owner).ok()?, register_uuids(registers)?))
+            })
+            .collect::<Option<Vec<_>>>()?,
+        None => vec![],
+    };
+    // Nor may they have any merged values.
+    let merged = match json.get("merged") {
+        Some(merged) => register_uuids(merged)?
+            .into_iter()
+            .map(|(from, into)| Some((Uuid::parse_str(from).ok()?, into)))
+            .collect::<Option<Vec<_>>>()?,
+        None => vec![],
+    };
+    let mut mentioned = contained
+        .values()
+        .flatten()
+        .chain(registers.iter().map(|(_, uuid)| uuid))
+        .chain(local_registers.iter().flat_map(|(owner, registers)| {
+            std::iter::once(owner).chain(registers.iter().map(|(_, uuid)| uuid))
+        }))
+        .chain(merged.iter().map(|(_, into)| into));
+    if !mentioned.all(|uuid| contained.contains_key(uuid)) {
+        return None;
+    }
+
+    // Insert every value, leaving containers empty for now, since they may
+    // contain values that haven't been inserted yet (or even themselves).
+    let mut keys: HashMap<Uuid, ArenaKey> = HashMap::new();
+    // The values that weren't in the arena before.
+    let mut new = HashSet::new();
+    let mut containers = vec![];
+    let mut commands = HashMap::new();
+    for (uuid, value) in values {
+        if let Some(key) = arena.lookup_uuid(&uuid) {
+            keys.insert(uuid, key);
+        } else if value.get("kind")?.as_str()? == "command" {
+            commands.insert(uuid, value);
+        } else {
+            let before = arena.slot_map.len();
+            let key = insert(arena, value)?;
+            // A leaf with the same contents as one that was already in the
+            // arena is that leaf, whatever its uuid.
+            if arena.slot_map.len() > before {
+                arena.restore_uuid(key, uuid)?;
+                arena.set_metadata(key, metadata(value))?;
+                containers.push((uuid, key));
+                new.insert(uuid);
+            }
+            keys.insert(uuid, key);
+        }
+    }
+
+    // Commands can't be changed once they are inserted, so each one waits
+    // until the commands it contains have been inserted. Containers already
+    // have their keys, even though they are still empty. Commands that
+    // contain themselves, directly or not, leave out the ones that haven't.
+    let mut waiting: Vec<Uuid> = commands.keys().copied().collect();
+    while !waiting.is_empty() {
+        let ready = waiting
+            .iter()
+            .position(|uuid| contained[uuid].iter().all(|uuid| keys.contains_key(uuid)));
+        let uuid = waiting.remove(ready.unwrap_or(0));
+        let elements: Vec<ArenaKey> = contained[&uuid]
+            .iter()
+            .filter_map(|uuid| keys.get(uuid).copied())
+            .collect();
+        let key = arena.command(elements);
+        arena.restore_uuid(key, uuid)?;
+        arena.set_metadata(key, metadata(commands[&uuid]))?;
+        keys.insert(uuid, key);
+        new.insert(uuid);
+    }
+
+    // Every value has a key by now, so the containers can be filled in.
+    for (uuid, key) in containers {
+        let members: Vec<ArenaKey> = contained[&uuid].iter().map(|uuid| keys[uuid]).collect();
+        match arena.slot_map[key].structure.kind() {
+            Kind::Set => arena.set_insert_many(key, members)?,
+            Kind::List => arena.list_replace(key, members).map(drop)?,
+            Kind::Map => {
+                for entry in members.chunks(2) {
+                    arena.map_insert(key, entry[0], entry[1])?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (owner, registers) in local_registers {
+        if !new.contains(&owner) {
+            continue;
+        }
+        for (name, uuid) in registers {
+            arena.bind_local_register(keys[&owner], name, keys[&uuid])?;
+        }
+    }
+    for (from, into) in merged {
+        // `into` may have been left out for being a leaf that was already in
+        // the arena, under another uuid.
+        let into = arena.slot_map[keys[&into]].uuid;
+        arena.restore_merged_uuid(from, into);
+    }
+    Some(
+        registers
+            .into_iter()
+            .map(|(name, uuid)| (name, keys[&uuid]))
+            .collect(),
+    )
+}
+
+/// Reads the JSON at `path` and merges it into `arena` with [`from_json`],
+/// returning how many of its values were new to `arena`.
+pub fn import<P: AsRef<Path>>(arena: &mut Arena, path: P, register: &str) -> io::Result<usize> {
+    let text = fs::read_to_string(path)?;
+    from_json(arena, &text, register).ok_or_else(not_an_arena)
+}
+
+/// Reads the JSON at `path` into an arena of its own with [`read`].
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Arena> {
+    let text = fs::read_to_string(path)?;
+    read(&text).ok_or_else(not_an_arena)
+}
+
+fn not_an_arena() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "not an arena exported as JSON")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::{Change, Resolution, CHANGED_REGISTER};
+
+    #[test]
+    fn escaping() {
+        assert_eq!(r#""a \"b\"\\c\n\u0001""#, string("a \"b\"\\c\n\u{1}"));
+        assert!(is_json_path("arena.JSON"));
+        assert!(!is_json_path("arena.md"));
+    }
+
+    #[test]
+    fn arena() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_empty_set("a");
+        arena.set_insert_string("a", "milk").unwrap();
+        let two = arena.number(2.0);
+        arena.bind_register("b", two);
+        let json = to_json(&arena);
+        let a = arena.register("a").unwrap();
+        let milk = arena.lookup_string("milk").unwrap();
+        let (a, milk) = (uuid(&arena, a), uuid(&arena, milk));
+        assert!(json.contains(&format!("\"a\": {}", a)));
+        assert!(json.contains(&format!(
+            "{}: {{\"kind\": \"set\", \"members\": [{}]}}",
+            a, milk
+        )));
+        assert!(json.contains(&format!(
+            "{}: {{\"kind\": \"string\", \"text\": \"milk\"}}",
+            milk
+        )));
+        assert!(json.contains("{\"kind\": \"number\", \"value\": 2}"));
+        assert_eq!(crate::arena::export::to_json(&arena), json);
+
+        // Neither garbage nor derived registers are written out.
+        arena.string("garbage");
+        let changed = arena.set(vec![two]);
+        arena.bind_register(CHANGED_REGISTER, changed);
+        let json = to_json(&arena);
+        assert!(!json.contains("garbage"));
+        assert!(!json.contains(&uuid(&arena, changed)));
+        assert!(!json.contains("\"changed\""));
+    }
+
+    #[test]
+    fn parsing() {
+        let text = "a \"b\"\\c\n\u{1}\u{1f600}";
+        assert_eq!(Some(Json::String(text.into())), parse(&string(text)));
+        assert_eq!(
+            Some(Json::String("\u{1f600}".into())),
+            parse(r#""\ud83d\ude00""#)
+        );
+        assert_eq!(
+            Some(Json::Object(vec![(
+                "a".into(),
+                Json::Array(vec![Json::Number(-1.5), Json::Bool(true), Json::Null])
+            )])),
+            parse(r#" { "a" : [-1.5, true, null] } "#)
+        );
+        assert_eq!(None, parse("[1, 2"));
+        assert_eq!(None, parse("[1] 2"));
+        let cases: [&[u8]; 5] = [b"", b"a", b"ab", b"abc", b"abcd"];
+        for bytes in cases.iter() {
+            assert_eq!(Some(bytes.to_vec()), unbase64(&base64(bytes)));
+        }
+    }
+
+    #[test]
+    fn import() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_empty_set("a");
+        arena.set_insert_string("a", "milk").unwrap();
+        let (a, two) = (arena.register("a").unwrap(), arena.number(2.0));
+        let list = arena.list(vec![two, a]);
+        let map = arena.map(vec![(two, list)]);
+        arena.bind_register("b", map);
+        arena.record_metadata(Some("ada".into()));
+        let late = arena.string("late");
+        arena.bind_register("c", late);
+        let draft = arena.string("draft");
+        arena.record_version(late, draft).unwrap();
+        let created = UNIX_EPOCH + Duration::from_secs(90);
+        arena.stamp_metadata(created);
+        let json = to_json(&arena);
+
+        let mut imported = Arena::new();
+        assert_eq!(None, from_json(&mut imported, "{}", "old"));
+        assert!(from_json(&mut imported, &json, "old").unwrap() >= 5);
+        let registers = imported.register("old").unwrap();
+        let uuid_of = |arena: &Arena, key| arena.slot_map[key].uuid;
+        let register = |arena: &Arena, name| match &arena.slot_map[registers].structure {
+            Structure::Map(map) => map
+                .iter()
+                .find(|(&k, _)| arena.lookup_string(name) == Some(k))
+                .map(|(_, &v)| v),
+            _ => None,
+        };
+        let b = register(&imported, "b").unwrap();
+        assert_eq!(uuid_of(&arena, map), uuid_of(&imported, b));
+        assert_eq!(to_json(&imported).matches("milk").count(), 1);
+        assert!(imported.validate().is_empty());
+        let a = register(&imported, "a").unwrap();
+        let milk = imported.lookup_string("milk").unwrap();
+        assert_eq!(Some(true), imported.set_contains(a, milk));
+        let late = imported.lookup_string("late").unwrap();
+        let metadata = Metadata {
+            created: Some(created),
+            author: Some("ada".into()),
+        };
+        assert_eq!(Some(&metadata), imported.metadata(late));
+        assert_eq!(None, imported.metadata(milk));
+        let draft = imported.lookup_string("draft").unwrap();
+        let versions = imported.versions(late).unwrap();
+        assert_eq!(Some(&[draft][..]), imported.elements(versions));
+
+        // Importing it again, or into the arena it came from, only binds the
+        // register.
+        assert_eq!(Some(0), from_json(&mut imported, &json, "again"));
+        assert_eq!(Some(0), from_json(&mut arena, &json, "old"));
+
+        // Nothing is left behind by an import that fails partway through.
+        let broken = r#"{"registers": {}, "values": {
+            "67e55044-10b1-426f-9247-bb680e5fe0c8": {"kind": "string", "text": "first"},
+            "67e55044-10b1-426f-9247-bb680e5fe0c9": {"kind": "image", "png": "AAAA"}
+        }}"#;
+        let len = imported.slot_map.len();
+        assert_eq!(None, from_json(&mut imported, broken, "broken"));
+        assert_eq!(len, imported.slot_map.len());
+        assert_eq!(None, imported.lookup_string("first"));
+    }
+
+    #[test]
+    fn unchanged_by_round_trip() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_empty_set("a");
+        arena.set_insert_string("a", "milk").unwrap();
+        let (a, two) = (arena.register("a").unwrap(), arena.number(2.0));
+        let list = arena.list(vec![two, a]);
+        arena.bind_register("b", list);
+        arena.bind_local_register(a, ",c", two).unwrap();
+        let changed = arena.set(vec![two]);
+        arena.bind_register(CHANGED_REGISTER, changed);
+
+        let read = read(&to_json(&arena)).unwrap();
+        assert_eq!(Vec::<Change>::new(), crate::arena::diff(&arena, &read));
+    }
+
+    #[test]
+    fn merged_uuids() {
+        let mut arena = Arena::new();
+        let (colour, color) = (arena.string("colour"), arena.string("color"));
+        let set = arena.set(vec![colour, color]);
+        arena.bind_register("a", set);
+        arena.merge_into(colour, color).unwrap();
+        let uuid = arena.uuid(colour).unwrap();
+        let json = to_json(&arena);
+
+        let mut imported = Arena::new();
+        assert!(from_json(&mut imported, &json, "i").is_some());
+        let color = imported.lookup_string("color").unwrap();
+        assert_eq!(Resolution::Merged(color), imported.resolve_uuid(&uuid));
+        let read = read(&json).unwrap();
+        let color = read.lookup_string("color").unwrap();
+        assert_eq!(Resolution::Merged(color), read.resolve_uuid(&uuid));
+    }
+
+    #[test]
+    fn set_holding_a_command() {
+        let mut arena = Arena::new();
+        let open = arena.string("open");
+        let command = arena.command(vec![open]);
+        let set = arena.set(vec![command]);
+        arena.bind_register("a", set);
+        let json = to_json(&arena);
+
+        let mut read = read(&json).unwrap();
+        let a = read.register("a").unwrap();
+        let command = read.lookup_uuid(&arena.slot_map[command].uuid).unwrap();
+        assert_eq!(Some(true), read.set_contains(a, command));
+        assert!(read.validate().is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+//! # Custom leaves
+//!
+//! Strings and images are the only leaves Kakoi knows about by itself. Crates
+//! using Kakoi as a library can add their own (audio clips, PDFs, and so on)
+//! by implementing [`CustomLeaf`] and inserting values of that type with
+//! [`Arena::custom`]. The arena stores them as boxed trait objects in
+//! [`Structure::Custom`], and asks them everything it needs to know about
+//! them through the trait:
+//!
+//! * Like strings and images, custom leaves are deduplicated by hashing them
+//!   (see [`CustomLeaf::hash_contents`]), so inserting the same leaf twice
+//!   returns the same value.
+//! * Each leaf is drawn either as a thumbnail, rendered once when it is
+//!   inserted (see [`CustomLeaf::thumbnail`]), or, if it doesn't have one, as
+//!   a short description (see [`CustomLeaf::describe`]).
+//! * Leaves can be turned into bytes with [`CustomLeaf::serialize`], and back
+//!   with the [`Deserializer`] registered for their type with
+//!   [`Arena::register_leaf_type`]. This is how they are written out as JSON
+//!   and read back in (see [`crate::json`]).
+//! * Leaves can be opened in another program, if it makes sense for them, with
+//!   [`CustomLeaf::open_external`]. This is done with
+//!   [`CompleteAction::OpenUrl`], like opening a URL.
+//!
+//! This trait is meant to be a stable interface: methods may be added to it
+//! over time, but only with default implementations.
+//!
+//! [`Arena::custom`]: crate::arena::Arena::custom
+//! [`Arena::register_leaf_type`]: crate::arena::Arena::register_leaf_type
+//! [`Structure::Custom`]: crate::arena::Structure::Custom
+//! [`CompleteAction::OpenUrl`]: crate::input_manager::CompleteAction::OpenUrl
+
+use std::fmt;
+use std::hash::Hasher;
+use std::io;
+
+/// A leaf value of a type defined outside of Kakoi. See [the module-level
+/// documentation](crate::leaf).
+pub trait CustomLeaf: fmt::Debug + Send {
+    /// The name of this type of leaf, like `audio clip`. Each type registered
+    /// with an arena must have a different name.
+    fn type_name(&self) -> &'static str;
+
+    /// Feeds everything that distinguishes this leaf from others of the same
+    /// type into `hasher`. Leaves of the same type that hash and
+    /// [serialize](CustomLeaf::serialize) the same are treated as the same
+    /// value. Writing bytes, rather than integers, keeps the hash the same on
+    /// every machine (see [`crate::content_hash`]).
+    fn hash_contents(&self, hasher: &mut dyn Hasher);
+
+    /// Returns the bytes that this type's [`Deserializer`] turns back into an
+    /// equivalent leaf.
+    fn serialize(&self) -> Vec<u8>;
+
+    /// Returns a copy of this leaf, for copying it into another arena.
+    fn duplicate(&self) -> Box<dyn CustomLeaf>;
+
+    /// A few words describing this leaf, drawn in place of it when it has no
+    /// thumbnail.
+    fn describe(&self) -> String;
+
+    /// Renders a picture of this leaf, drawn in place of it. This is only
+    /// called once, when the leaf is inserted into an arena.
+    fn thumbnail(&self) -> Option<image::RgbaImage> {
+        None
+    }
+
+    /// Opens this leaf in another program, like playing an audio clip in a
+    /// music player. Returns [`None`] if leaves of this type can't be opened.
+    fn open_external(&self) -> Option<io::Result<()>> {
+        None
+    }
+}
+
+/// Turns the bytes returned by [`CustomLeaf::serialize`] back into a leaf, or
+/// returns [`None`] if they don't describe one.
+pub type Deserializer = fn(&[u8]) -> Option<Box<dyn CustomLeaf>>;
+
+/// A [`CustomLeaf`], alongside the thumbnail it rendered when it was inserted.
+#[derive(Debug)]
+pub struct CustomValue {
+    pub leaf: Box<dyn CustomLeaf>,
+    pub thumbnail: Option<image::RgbaImage>,
+}
+
+impl CustomValue {
+    /// Renders the thumbnail of `leaf`, if it has one.
+    pub fn new(leaf: Box<dyn CustomLeaf>) -> Self {
+        let thumbnail = leaf.thumbnail();
+        Self { leaf, thumbnail }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::{Arena, Structure};
+    use std::hash::Hash;
+
+    #[derive(Debug, Clone)]
+    struct Clip(String);
+
+    impl CustomLeaf for Clip {
+        fn type_name(&self) -> &'static str {
+            "clip"
+        }
+
+        fn hash_contents(&self, mut hasher: &mut dyn Hasher) {
+            self.0.hash(&mut hasher);
+        }
+
+        fn serialize(&self) -> Vec<u8> {
+            self.0.clone().into_bytes()
+        }
+
+        fn duplicate(&self) -> Box<dyn CustomLeaf> {
+            Box::new(self.clone())
+        }
+
+        fn describe(&self) -> String {
+            format!("clip {}", self.0)
+        }
+    }
+
+    #[test]
+    fn custom_leaves() {
+        let mut arena = Arena::new();
+        let clip = arena.custom(Box::new(Clip("a".into())));
+        assert_eq!(clip, arena.custom(Box::new(Clip("a".into()))));
+        assert_ne!(clip, arena.custom(Box::new(Clip("b".into()))));
+        // Leaves are hashed along with their type, so a string with the same
+        // contents is a different value.
+        assert_ne!(clip, arena.string("a"));
+
+        assert_eq!(None, arena.deserialize_leaf("clip", b"a"));
+        arena.register_leaf_type("clip", |bytes| {
+            let name = String::from_utf8(bytes.to_vec()).ok()?;
+            Some(Box::new(Clip(name)))
+        });
+        let bytes = match &arena.slot_map[clip].structure {
+            Structure::Custom(custom) => custom.leaf.serialize(),
+            _ => panic!(),
+        };
+        assert_eq!(Some(clip), arena.deserialize_leaf("clip", &bytes));
+        assert_eq!(None, arena.deserialize_leaf("clip", &[0xff]));
+    }
+}
@@ -1,14 +1,48 @@
 pub mod arena;
+pub mod background;
+pub mod blob;
 pub mod camera;
 pub mod circle;
+pub mod content_hash;
+pub mod cost;
+pub mod crash;
+pub mod dedup;
+pub mod explore;
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod forest;
+pub mod geometry;
+pub mod grouping;
+pub mod history;
+pub mod html;
+pub mod hud;
+pub mod import;
 pub mod input_manager;
 pub mod input_map;
+pub mod json;
+pub mod leaf;
+pub mod link;
+pub mod number;
+pub mod overlay;
+pub mod perform;
+pub mod power;
+pub mod profiling;
+pub mod query;
 pub mod render;
+pub mod replay;
+pub mod rewrite;
+pub mod sample;
+pub mod search;
 pub mod sampling_config;
+pub mod settings;
 pub mod spatial_bound;
 pub mod spatial_tree;
 pub mod sphere;
 pub mod square_cuboid;
 pub mod state;
+pub mod string_class;
+pub mod task;
+pub mod text_import;
+pub mod tutorial;
 pub mod window;
@@ -0,0 +1,146 @@
+//! # Deep links
+//!
+//! A deep link is a URL naming a single value, so that a reference to it can
+//! be pasted into other tools (or into Kakoi itself) and followed straight
+//! back to it. Links look like `kakoi://FILE/UUID`, where `FILE` is the
+//! [percent-encoded] path of the arena file holding the value and `UUID` is
+//! the value's [`Value::uuid`].
+//!
+//! Kakoi doesn't keep arenas in files of its own yet (they are only written
+//! out as JSON, see [`crate::json`]), so every link made today names a value
+//! in the running arena, which is written as an empty `FILE`:
+//! `kakoi:///67e55044-10b1-426f-9247-bb680e5fe0c8`. Links are followed from
+//! inside of Kakoi with [`CompleteAction::OpenUrl`], and from the command line
+//! with `kakoi open-url`.
+//!
+//! Values are named by their uuid rather than by a path through the
+//! containers above them: a value can be inside of any number of containers,
+//! and its uuid stays the same as they change.
+//!
+//! A link to a value that has since been merged into another (see
+//! [`Arena::merge_into`]) leads to the other value, and a link to a uuid that
+//! nothing has is reported as a stale reference rather than silently doing
+//! nothing. See [`Arena::resolve_uuid`].
+//!
+//! [percent-encoded]: https://datatracker.ietf.org/doc/html/rfc3986#section-2.1
+//! [`Value::uuid`]: crate::arena::Value::uuid
+//! [`Arena::merge_into`]: crate::arena::Arena::merge_into
+//! [`Arena::resolve_uuid`]: crate::arena::Arena::resolve_uuid
+//! [`CompleteAction::OpenUrl`]: crate::input_manager::CompleteAction::OpenUrl
+
+use std::fmt;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// The start of every deep link.
+pub const SCHEME: &str = "kakoi://";
+
+/// A parsed deep link. See [the module-level documentation](crate::link).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeepLink {
+    /// The arena file the value is in, or [`None`] for the running arena.
+    pub file: Option<PathBuf>,
+    pub uuid: Uuid,
+}
+
+impl DeepLink {
+    /// Returns a link to the value with the given `uuid` in the running arena.
+    pub fn new(uuid: Uuid) -> Self {
+        Self { file: None, uuid }
+    }
+
+    /// Parses a link like `kakoi://FILE/UUID`. Returns [`None`] if `link`
+    /// isn't a deep link.
+    pub fn parse(link: &str) -> Option<Self> {
+        let rest = link.strip_prefix(SCHEME)?;
+        // Slashes in the file are percent-encoded, so the last one separates
+        // it from the uuid.
+        let slash = rest.rfind('/')?;
+        let file = percent_decode(&rest[..slash])?;
+        let uuid = Uuid::parse_str(&rest[slash + 1..]).ok()?;
+        let file = if file.is_empty() {
+            None
+        } else {
+            Some(file.into())
+        };
+        Some(Self { file, uuid })
+    }
+}
+
+impl fmt::Display for DeepLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file = match &self.file {
+            Some(file) => percent_encode(&file.to_string_lossy()),
+            None => String::new(),
+        };
+        write!(f, "{}{}/{}", SCHEME, file, self.uuid)
+    }
+}
+
+/// Replaces every byte of `string` other than letters, digits, `-`, `.`, `_`,
+/// and `~` with `%` followed by its value in hexadecimal.
+pub fn percent_encode(string: &str) -> String {
+    let mut encoded = String::with_capacity(string.len());
+    for byte in string.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Undoes [`percent_encode`]. Returns [`None`] if a `%` isn't followed by two
+/// hexadecimal digits, or if the decoded bytes aren't UTF-8.
+pub fn percent_decode(string: &str) -> Option<String> {
+    let bytes = string.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(index + 1..index + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn links_round_trip() {
+        let uuid = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        let link = DeepLink::new(uuid);
+        assert_eq!(
+            "kakoi:///67e55044-10b1-426f-9247-bb680e5fe0c8",
+            link.to_string()
+        );
+        assert_eq!(
+            Some(link),
+            DeepLink::parse(&DeepLink::new(uuid).to_string())
+        );
+
+        let link = DeepLink {
+            file: Some("/home/me/my notes/é.kakoi".into()),
+            uuid,
+        };
+        let text = link.to_string();
+        assert!(text.starts_with("kakoi://%2Fhome%2Fme%2Fmy%20notes%2F%C3%A9.kakoi/"));
+        assert_eq!(Some(link), DeepLink::parse(&text));
+
+        assert_eq!(None, DeepLink::parse("https://example.com"));
+        assert_eq!(None, DeepLink::parse("kakoi:///not-a-uuid"));
+        assert_eq!(
+            None,
+            DeepLink::parse("kakoi://%2/67e55044-10b1-426f-9247-bb680e5fe0c8")
+        );
+    }
+}
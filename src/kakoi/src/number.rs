@@ -0,0 +1,133 @@
+//! # Numbers
+//!
+//! A string of digits can't be added to another, or told apart from a string
+//! that happens to look like a number, so numbers are values of their own
+//! ([`Structure::Number`]). `=` followed by a number, like `= 12.5`, inserts
+//! it into the set bound to `.`. `control+a` followed by an [`Operation`] and
+//! two registers bound to numbers inserts the result of the operation on them
+//! into the set bound to `.` (see [`CompleteAction::Arithmetic`]).
+//!
+//! Numbers are stored as [`f64`]s. Only finite numbers are stored: entering
+//! `inf`, or dividing by zero, is refused rather than inserting a value that
+//! can't be computed with.
+//!
+//! Like strings, the same number is only ever inserted once (see
+//! [`Arena::number`]).
+//!
+//! [`Structure::Number`]: crate::arena::Structure::Number
+//! [`Arena::number`]: crate::arena::Arena::number
+//! [`CompleteAction::Arithmetic`]: crate::input_manager::CompleteAction::Arithmetic
+
+use crate::arena::Arena;
+
+/// The names of the [`Operation`]s, in the order `control+a` offers them.
+pub const OPERATION_CHOICES: &[&str] = &["add", "subtract", "multiply", "divide"];
+
+/// Something that can be done to two numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl Operation {
+    /// Reads the name of an operation, one of the [`OPERATION_CHOICES`].
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim() {
+            "add" => Some(Operation::Add),
+            "subtract" => Some(Operation::Subtract),
+            "multiply" => Some(Operation::Multiply),
+            "divide" => Some(Operation::Divide),
+            _ => None,
+        }
+    }
+
+    /// Does this to `a` and `b`, in that order. Returns [`None`] if the
+    /// result isn't a finite number.
+    pub fn apply(self, a: f64, b: f64) -> Option<f64> {
+        let result = match self {
+            Operation::Add => a + b,
+            Operation::Subtract => a - b,
+            Operation::Multiply => a * b,
+            Operation::Divide => a / b,
+        };
+        finite(result)
+    }
+}
+
+/// Returns `number` if it is finite, turning `-0` into `0` so that there is
+/// only one zero.
+fn finite(number: f64) -> Option<f64> {
+    if number.is_finite() {
+        Some(number + 0.0)
+    } else {
+        None
+    }
+}
+
+/// Reads a number written out in decimal, like `-3`, `0.25` or `1e6`.
+/// Returns [`None`] if `text` isn't a finite number.
+pub fn parse(text: &str) -> Option<f64> {
+    let text = text.trim();
+    // Rust reads `inf` and `NaN` as numbers too.
+    if !text.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    finite(text.parse().ok()?)
+}
+
+/// Does `operation` (one of the [`OPERATION_CHOICES`]) to the numbers bound
+/// to the registers `a` and `b`. Returns [`None`] if either isn't bound to a
+/// number, or if the result isn't finite.
+pub fn compute(arena: &mut Arena, operation: &str, a: &str, b: &str) -> Option<f64> {
+    let operation = Operation::parse(operation)?;
+    let a = arena.register(a).and_then(|key| arena.as_number(key))?;
+    let b = arena.register(b).and_then(|key| arena.as_number(key))?;
+    operation.apply(a, b)
+}
+
+/// Writes `number` out the way it is drawn, like `12.5` or `3`.
+pub fn format(number: f64) -> String {
+    number.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(Some(12.5), parse(" 12.5 "));
+        assert_eq!(Some(1e6), parse("1e6"));
+        assert_eq!(Some(0.0), parse("-0"));
+        assert_eq!(None, parse("inf"));
+        assert_eq!(None, parse("twelve"));
+        assert_eq!("3", format(3.0));
+        assert_eq!("-0.25", format(-0.25));
+        assert_eq!(Some(Operation::Divide), Operation::parse("divide"));
+        assert_eq!(Some(-1.0), Operation::Subtract.apply(2.0, 3.0));
+        assert_eq!(Some(0.5), Operation::Divide.apply(1.0, 2.0));
+        assert_eq!(None, Operation::Divide.apply(1.0, 0.0));
+        for choice in OPERATION_CHOICES {
+            assert!(Operation::parse(choice).is_some());
+        }
+    }
+
+    #[test]
+    fn computing() {
+        let mut arena = Arena::new();
+        let (two, three) = (arena.number(2.0), arena.number(3.0));
+        assert_eq!(two, arena.number(2.0));
+        assert_eq!(Some(2.0), arena.as_number(two));
+        arena.bind_register("a", two);
+        arena.bind_register("b", three);
+        assert_eq!(Some(6.0), compute(&mut arena, "multiply", "a", "b"));
+        assert_eq!(None, compute(&mut arena, "multiply", "a", "c"));
+        let string = arena.string("3");
+        assert_eq!(None, arena.as_number(string));
+        arena.bind_register("b", string);
+        assert_eq!(None, compute(&mut arena, "add", "a", "b"));
+    }
+}
@@ -0,0 +1,412 @@
+//! # Layered views
+//!
+//! What is displayed on screen is described by an [`OverlayStack`]. The bottom
+//! of the stack is always the base view, which shows the value bound to the
+//! `.` register. Other layers (modal panels, confirmations, the command
+//! palette, image previews, grouping suggestions, notices) can be pushed on top of it
+//! and popped off again when they are no longer needed.
+//!
+//! Each [`Layer`] has a focus (the value it displays) and, optionally, a
+//! message (a value displayed in a strip along the bottom of the layer, like a
+//! status line). Each kind of layer occupies a different region of the screen,
+//! which is described by [`Layer::bounds`]. The [`SpatialTree`] lays out every
+//! layer in the stack, from the bottom up, inside of its region.
+//!
+//! Only the topmost layer receives input. See [`OverlayStack::top`].
+//!
+//! While a value is being moved from one set to another, a ghost of it is
+//! shown in a badge in the corner of the screen, above every layer. See
+//! [`OverlayStack::ghost`]. Pinned values (see [`Arena::pin`]) are shown in a
+//! bar along the bottom of the screen, also above every layer. See
+//! [`OverlayStack::pins`]. Both are [widgets](crate::hud) on the heads-up
+//! display returned by [`OverlayStack::hud`], which also holds the minibuffer
+//! displaying the prompt being entered (see [`OverlayStack::minibuffer`]).
+//! When no prompt is being entered, the minibuffer doubles as a message line,
+//! saying what the last action did without getting in the way of the next
+//! one (see [`OverlayStack::message_line`]).
+//!
+//! Values matching a query can be highlighted where they are displayed in the
+//! topmost layer, dimming everything else. See [`OverlayStack::highlights`].
+//!
+//! [`Arena::pin`]: crate::arena::Arena::pin
+//! [`SpatialTree`]: crate::spatial_tree::SpatialTree
+
+use crate::arena::ArenaKey;
+use crate::hud::{Anchor, Hud, Widget};
+use crate::sample::Sample;
+use crate::spatial_tree::LayoutStrategy;
+use crate::square_cuboid::SquareCuboid;
+use std::collections::HashSet;
+
+/// The purpose of a [`Layer`], which determines where it is placed on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerKind {
+    /// The view of the `.` register. Fills the whole screen. There is exactly
+    /// one of these, and it is always at the bottom of the stack.
+    Base,
+    /// A modal panel displaying some value above the base view.
+    Panel,
+    /// A small prompt asking the user to confirm something, like a preview of
+    /// flattening the set displayed by the layer beneath it.
+    Confirmation,
+    /// A small panel telling the user something, like why what they asked
+    /// for wasn't done.
+    Notice,
+    /// A strip along the top of the screen for choosing commands.
+    Palette,
+    /// A large panel showing an image or text that is waiting to be imported.
+    /// See [`crate::import`].
+    Preview,
+    /// A large panel showing a suggested way of grouping the members of the
+    /// set displayed by the layer beneath it. See [`crate::grouping`].
+    Suggestion,
+    /// The whole screen, showing an image being inspected. See
+    /// [`CompleteAction::Inspect`].
+    ///
+    /// [`CompleteAction::Inspect`]: crate::input_manager::CompleteAction::Inspect
+    Inspection,
+}
+
+/// A single view in an [`OverlayStack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layer {
+    /// Where this layer is displayed.
+    pub kind: LayerKind,
+    /// The value displayed by this layer.
+    pub focus: ArenaKey,
+    /// A value displayed along the bottom of this layer, if any.
+    pub message: Option<ArenaKey>,
+    /// How the values in this layer are arranged.
+    pub layout: LayoutStrategy,
+    /// The index of the selected value among the values directly inside of
+    /// the focus, in the order they were laid out, if any value is selected.
+    /// See [`SpatialTree::selectable`].
+    ///
+    /// [`SpatialTree::selectable`]: crate::spatial_tree::SpatialTree::selectable
+    pub selected: Option<usize>,
+    /// If the focus is a set, only this sample of its members is displayed
+    /// (see [`crate::sample`]). The message then says how many members there
+    /// are in total.
+    pub sample: Option<Sample>,
+}
+
+/// Fraction of a layer's height taken up by its message, if it has one.
+const MESSAGE_HEIGHT: f32 = 0.15;
+
+impl Layer {
+    pub fn new(kind: LayerKind, focus: ArenaKey) -> Self {
+        Self {
+            kind,
+            focus,
+            message: None,
+            layout: LayoutStrategy::Circles,
+            selected: None,
+            sample: None,
+        }
+    }
+
+    /// Displays `focus` instead of what was displayed before. Whatever was
+    /// selected or sampled in the old focus is forgotten.
+    pub fn refocus(&mut self, focus: ArenaKey) {
+        self.focus = focus;
+        self.selected = None;
+        if self.sample.take().is_some() {
+            self.message = None;
+        }
+    }
+
+    /// Returns the region of the screen (see [the spatial tree
+    /// documentation](crate::spatial_tree)) that this layer is laid out in.
+    pub fn bounds(&self) -> SquareCuboid {
+        match self.kind {
+            LayerKind::Base | LayerKind::Inspection => {
+                SquareCuboid::from_dimensions(2.0, 2.0, (0.0, 0.0, 0.0).into())
+            }
+            LayerKind::Panel => SquareCuboid::from_dimensions(1.5, 1.5, (0.0, 0.0, 0.0).into()),
+            LayerKind::Confirmation | LayerKind::Notice => {
+                SquareCuboid::from_dimensions(1.0, 0.5, (0.0, 0.0, 0.0).into())
+            }
+            LayerKind::Palette => SquareCuboid::from_dimensions(2.0, 0.3, (0.0, 0.85, 0.0).into()),
+            LayerKind::Preview | LayerKind::Suggestion => {
+                SquareCuboid::from_dimensions(1.8, 1.8, (0.0, 0.0, 0.0).into())
+            }
+        }
+    }
+
+    /// Moves the selection to the next of `count` selectable values, wrapping
+    /// around to the first. If there is nothing to select, the selection is
+    /// cleared.
+    pub fn select_next(&mut self, count: usize) {
+        self.selected = match (self.selected, count) {
+            (_, 0) => None,
+            (Some(selected), _) => Some((selected + 1) % count),
+            (None, _) => Some(0),
+        };
+    }
+
+    /// Splits [`Layer::bounds`] into the region the focus is laid out in and
+    /// the region the message is laid out in. If the layer has no message, the
+    /// focus gets the entire region.
+    pub fn focus_and_message_bounds(&self) -> (SquareCuboid, Option<SquareCuboid>) {
+        let bounds = self.bounds();
+        match self.message {
+            None => (bounds, None),
+            Some(_) => {
+                let (width, height) = bounds.dimensions_2d();
+                let message_height = height * MESSAGE_HEIGHT;
+                let focus_height = height - message_height;
+                let top = bounds.center.y + height * 0.5;
+                let focus = SquareCuboid::from_dimensions(
+                    width,
+                    focus_height,
+                    (bounds.center.x, top - focus_height * 0.5, 0.0).into(),
+                );
+                let message = SquareCuboid::from_dimensions(
+                    width,
+                    message_height,
+                    (
+                        bounds.center.x,
+                        top - focus_height - message_height * 0.5,
+                        0.0,
+                    )
+                        .into(),
+                );
+                (focus, Some(message))
+            }
+        }
+    }
+}
+
+/// The side length of the badge the ghost of a value being moved is shown in.
+const GHOST_SIZE: f32 = 0.4;
+
+/// The side length of the badge each pinned value is shown in.
+const PIN_SIZE: f32 = 0.12;
+
+/// A stack of [`Layer`]s, the bottom of which is always a
+/// [`LayerKind::Base`] layer.
+///
+/// See [the module-level documentation](crate::overlay) for more information.
+#[derive(Debug)]
+pub struct OverlayStack {
+    layers: Vec<Layer>,
+    ghost: Option<ArenaKey>,
+    pins: Vec<(usize, ArenaKey)>,
+    minibuffer: Option<String>,
+    message_line: Option<String>,
+    highlights: Option<HashSet<ArenaKey>>,
+}
+
+impl OverlayStack {
+    /// Creates a stack containing only a base layer focused on `base_focus`.
+    pub fn new(base_focus: ArenaKey) -> Self {
+        Self {
+            layers: vec![Layer::new(LayerKind::Base, base_focus)],
+            ghost: None,
+            pins: Vec::new(),
+            minibuffer: None,
+            message_line: None,
+            highlights: None,
+        }
+    }
+
+    /// Places `layer` on top of the stack, so that it receives input.
+    ///
+    /// Base layers may only be at the bottom of the stack, so attempting to
+    /// push one does nothing and returns [`None`].
+    pub fn push(&mut self, layer: Layer) -> Option<()> {
+        if layer.kind == LayerKind::Base {
+            None
+        } else {
+            self.layers.push(layer);
+            Some(())
+        }
+    }
+
+    /// Removes and returns the topmost layer. The base layer is never removed,
+    /// so this returns [`None`] if it is the only layer left.
+    pub fn pop(&mut self) -> Option<Layer> {
+        if self.layers.len() > 1 {
+            self.layers.pop()
+        } else {
+            None
+        }
+    }
+
+    /// The layer that receives input.
+    pub fn top(&self) -> &Layer {
+        self.layers.last().unwrap()
+    }
+
+    pub fn top_mut(&mut self) -> &mut Layer {
+        self.layers.last_mut().unwrap()
+    }
+
+    /// The layer at the bottom of the stack, which shows the `.` register.
+    pub fn base(&self) -> &Layer {
+        &self.layers[0]
+    }
+
+    pub fn base_mut(&mut self) -> &mut Layer {
+        &mut self.layers[0]
+    }
+
+    /// Returns true if there are any layers above the base layer.
+    pub fn is_modal(&self) -> bool {
+        self.layers.len() > 1
+    }
+
+    /// Iterates over the layers from the bottom of the stack to the top.
+    pub fn iter(&self) -> impl Iterator<Item = &Layer> {
+        self.layers.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Layer> {
+        self.layers.iter_mut()
+    }
+
+    /// Every value the stack refers to: what each layer displays, its
+    /// message, the ghost, the pins, and the highlighted values.
+    pub fn keys(&self) -> Vec<ArenaKey> {
+        let mut keys: Vec<ArenaKey> = self
+            .layers
+            .iter()
+            .flat_map(|layer| std::iter::once(layer.focus).chain(layer.message))
+            .collect();
+        keys.extend(self.ghost);
+        keys.extend(self.pins.iter().map(|&(_, key)| key));
+        keys.extend(self.highlights.iter().flatten());
+        keys
+    }
+
+    /// The value being moved, if any, which is displayed above every layer
+    /// in the bottom right corner of the screen. The ghost never receives
+    /// input.
+    pub fn ghost(&self) -> Option<ArenaKey> {
+        self.ghost
+    }
+
+    pub fn set_ghost(&mut self, ghost: Option<ArenaKey>) {
+        self.ghost = ghost;
+    }
+
+    /// The pinned values alongside their slots, each of which is displayed
+    /// above every layer in a row along the bottom left of the screen,
+    /// labelled with its slot. Like the ghost, pins never receive input.
+    pub fn pins(&self) -> &[(usize, ArenaKey)] {
+        &self.pins
+    }
+
+    pub fn set_pins(&mut self, pins: Vec<(usize, ArenaKey)>) {
+        self.pins = pins;
+    }
+
+    /// The prompt being entered, if any, which is displayed above every layer
+    /// in a minibuffer along the bottom of the screen.
+    pub fn minibuffer(&self) -> Option<&str> {
+        self.minibuffer.as_deref()
+    }
+
+    pub fn set_minibuffer(&mut self, minibuffer: Option<String>) {
+        self.minibuffer = minibuffer;
+    }
+
+    /// A message displayed in the minibuffer while no prompt is being
+    /// entered. Unlike a [`LayerKind::Notice`], it doesn't take any input.
+    pub fn message_line(&self) -> Option<&str> {
+        self.message_line.as_deref()
+    }
+
+    /// Replaces the message line, returning what it said before.
+    pub fn set_message_line(&mut self, message_line: Option<String>) -> Option<String> {
+        std::mem::replace(&mut self.message_line, message_line)
+    }
+
+    /// The values highlighted in the topmost layer, if any. Wherever one of
+    /// them is displayed in that layer it is surrounded by a halo, and
+    /// everything else in the layer is dimmed. See
+    /// [`CompleteAction::Highlight`].
+    ///
+    /// [`CompleteAction::Highlight`]: crate::input_manager::CompleteAction::Highlight
+    pub fn highlights(&self) -> Option<&HashSet<ArenaKey>> {
+        self.highlights.as_ref()
+    }
+
+    pub fn set_highlights(&mut self, highlights: Option<HashSet<ArenaKey>>) {
+        self.highlights = highlights;
+    }
+
+    /// The widgets displayed above every layer: the ghost, if there is one,
+    /// followed by the pins, along with the minibuffer (or the message line,
+    /// if no prompt is being entered).
+    pub fn hud(&self) -> Hud {
+        let mut hud = Hud::new();
+        let minibuffer = self.minibuffer.as_ref().or(self.message_line.as_ref());
+        hud.set_minibuffer(minibuffer.cloned());
+        if let Some(ghost) = self.ghost {
+            hud.push(Widget::new(Anchor::BottomRight, GHOST_SIZE, ghost));
+        }
+        for &(slot, pin) in &self.pins {
+            hud.push(Widget::new(Anchor::BottomLeft, PIN_SIZE, pin).labelled(slot.to_string()));
+        }
+        hud
+    }
+
+    /// The region of the screen the ghost is displayed in, if there is one,
+    /// given the [visible extent](crate::camera::Camera::visible_half_extent)
+    /// of the screen.
+    pub fn ghost_bounds(&self, visible_half_extent: (f32, f32)) -> Option<SquareCuboid> {
+        // The ghost is always the first widget.
+        self.ghost
+            .map(|_| self.hud().layout(visible_half_extent).swap_remove(0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use slotmap::SlotMap;
+
+    #[test]
+    fn push_pop() {
+        let mut slot_map: SlotMap<ArenaKey, ()> = SlotMap::with_key();
+        let base = slot_map.insert(());
+        let panel = slot_map.insert(());
+        let mut stack = OverlayStack::new(base);
+        assert!(!stack.is_modal());
+        assert!(stack.push(Layer::new(LayerKind::Base, panel)).is_none());
+        assert!(stack.push(Layer::new(LayerKind::Panel, panel)).is_some());
+        assert!(stack.is_modal());
+        assert_eq!(panel, stack.top().focus);
+        assert_eq!(base, stack.base().focus);
+        assert_eq!(Some(Layer::new(LayerKind::Panel, panel)), stack.pop());
+        assert_eq!(None, stack.pop());
+        assert_eq!(base, stack.top().focus);
+    }
+
+    #[test]
+    fn message_line() {
+        let mut slot_map: SlotMap<ArenaKey, ()> = SlotMap::with_key();
+        let mut stack = OverlayStack::new(slot_map.insert(()));
+        assert_eq!(None, stack.set_message_line(Some("told".into())));
+        assert_eq!(Some("told"), stack.hud().minibuffer());
+        // A prompt takes precedence over the message line.
+        stack.set_minibuffer(Some("prompt".into()));
+        assert_eq!(Some("prompt"), stack.hud().minibuffer());
+        assert!(!stack.is_modal());
+    }
+
+    #[test]
+    fn select_next_wraps() {
+        let mut slot_map: SlotMap<ArenaKey, ()> = SlotMap::with_key();
+        let mut layer = Layer::new(LayerKind::Base, slot_map.insert(()));
+        layer.select_next(2);
+        assert_eq!(Some(0), layer.selected);
+        layer.select_next(2);
+        assert_eq!(Some(1), layer.selected);
+        layer.select_next(2);
+        assert_eq!(Some(0), layer.selected);
+        layer.select_next(0);
+        assert_eq!(None, layer.selected);
+    }
+}
@@ -0,0 +1,332 @@
+//! # Performing actions
+//!
+//! Many [`CompleteAction`]s change nothing but the [`Arena`], like inserting
+//! into sets and binding registers. [`apply`] performs those, both for the
+//! window and for a [`Session`](crate::replay::Session) replaying input
+//! without one, so that the two can't drift apart. Every other action needs
+//! more than the arena (a camera, a history, the layers on screen, ...), and
+//! is left to whoever asked for it.
+//!
+//! Before performing an action, check [`rejection`]: nothing can be changed
+//! in read-only mode, and nothing locked (see [`Arena::is_locked`]) can be
+//! changed at all.
+
+use crate::arena::{Arena, ArenaKey, Structure, INBOX_REGISTER};
+use crate::input_manager::CompleteAction;
+use crate::number;
+use crate::query::{self, Query};
+use crate::search::SearchIndex;
+
+/// What [`apply`] did with an action.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The action was performed, alongside anything worth telling the user
+    /// about what it did.
+    Performed(Option<String>),
+    /// The action changed nothing, alongside why, if that is worth telling
+    /// the user.
+    Failed(Option<String>),
+    /// The action needs more than the arena, so nothing was done.
+    Unsupported,
+}
+
+/// Returns true if `register` is bound to a set.
+fn is_set(arena: &mut Arena, register: &str) -> bool {
+    match arena.register(register) {
+        Some(set) => matches!(arena.slot_map[set].structure, Structure::Set(_)),
+        None => false,
+    }
+}
+
+/// Returns true if `register` is bound to a list.
+fn is_list(arena: &mut Arena, register: &str) -> bool {
+    let list = arena.register(register);
+    list.and_then(|list| arena.elements(list)).is_some()
+}
+
+fn not_a_set(register: &str) -> Outcome {
+    Outcome::Failed(Some(format!("{} isn't bound to a set", register)))
+}
+
+fn not_a_list(register: &str) -> Outcome {
+    Outcome::Failed(Some(format!("{} isn't bound to a list", register)))
+}
+
+fn no_position(register: &str, position: usize) -> Outcome {
+    Outcome::Failed(Some(format!(
+        "the list bound to {} has no position {}",
+        register, position
+    )))
+}
+
+/// Returns the list bound to `register`, alongside the index of its element
+/// at `position`, counting positions from 1 (see
+/// [`CompleteAction::ListInsertAt`]). If `after_last`, the position after
+/// its last element counts too. Returns [`None`] if the list has no such
+/// position.
+fn index(
+    arena: &mut Arena,
+    register: &str,
+    position: usize,
+    after_last: bool,
+) -> Option<(ArenaKey, usize)> {
+    let list = arena.register(register)?;
+    let len = arena.elements(list)?.len() + after_last as usize;
+    let index = position.checked_sub(1).filter(|&index| index < len)?;
+    Some((list, index))
+}
+
+/// Returns [`Outcome::Performed`] if `result` is [`Some`], and
+/// [`Outcome::Failed`] otherwise.
+fn performed<T>(result: Option<T>) -> Outcome {
+    match result {
+        Some(_) => Outcome::Performed(None),
+        None => Outcome::Failed(None),
+    }
+}
+
+/// Performs `action`, if it changes nothing but `arena`. `index` (see
+/// [`query::run`]) speeds up finding, if it is up to date.
+pub fn apply(arena: &mut Arena, action: &CompleteAction, index: Option<&SearchIndex>) -> Outcome {
+    match action.clone() {
+        CompleteAction::SetInsert(set, _)
+        | CompleteAction::SetRemove(set, _)
+        | CompleteAction::ToggleMembership(set, _)
+        | CompleteAction::InsertStringIntoSetRegister(set, _)
+        | CompleteAction::InsertNumberIntoSetRegister(set, _)
+        | CompleteAction::Arithmetic(_, set, _, _)
+            if !is_set(arena, &set) =>
+        {
+            not_a_set(&set)
+        }
+        CompleteAction::SetUnion(set, other) if !is_set(arena, &set) || !is_set(arena, &other) => {
+            not_a_set(if is_set(arena, &set) { &other } else { &set })
+        }
+        CompleteAction::ListPush(list, _)
+        | CompleteAction::ListPop(list)
+        | CompleteAction::ListInsertAt(list, ..)
+        | CompleteAction::ListRemoveAt(list, _)
+        | CompleteAction::ListSwap(list, _)
+        | CompleteAction::ListMove(list, ..)
+            if !is_list(arena, &list) =>
+        {
+            not_a_list(&list)
+        }
+        CompleteAction::SetInsert(set, member) => performed(arena.set_insert(set, member)),
+        CompleteAction::SetUnion(set, other) => performed(arena.set_union(set, other)),
+        CompleteAction::SetRemove(set, member) => performed(arena.set_remove(set, member)),
+        CompleteAction::ToggleMembership(set, member) => match arena.set_toggle(set, member) {
+            Some(true) => Outcome::Performed(Some("inserted into the set".to_string())),
+            Some(false) => Outcome::Performed(Some("removed from the set".to_string())),
+            None => Outcome::Failed(None),
+        },
+        CompleteAction::ListPush(list, value) => performed(arena.list_push(list, value)),
+        CompleteAction::ListPop(list) => performed(arena.list_pop(list)),
+        CompleteAction::ListInsertAt(list, position, value) => {
+            match index(arena, &list, position, true) {
+                Some((key, index)) => match arena.register(value) {
+                    Some(value) => performed(arena.list_insert_at(key, index, value)),
+                    None => Outcome::Failed(None),
+                },
+                None => no_position(&list, position),
+            }
+        }
+        CompleteAction::ListRemoveAt(list, position) => {
+            match index(arena, &list, position, false) {
+                Some((key, index)) => performed(arena.list_remove_at(key, index)),
+                None => no_position(&list, position),
+            }
+        }
+        CompleteAction::ListSwap(list, position) => {
+            match (
+                index(arena, &list, position, false),
+                index(arena, &list, position + 1, false),
+            ) {
+                (Some((key, a)), Some((_, b))) => performed(arena.list_swap(key, a, b)),
+                (Some(_), None) => no_position(&list, position + 1),
+                (None, _) => no_position(&list, position),
+            }
+        }
+        CompleteAction::ListMove(list, position, to) => match to.trim().parse() {
+            Ok(to) => match (
+                index(arena, &list, position, false),
+                index(arena, &list, to, false),
+            ) {
+                (Some((key, from)), Some((_, to))) => performed(arena.list_move(key, from, to)),
+                (Some(_), None) => no_position(&list, to),
+                (None, _) => no_position(&list, position),
+            },
+            Err(_) => Outcome::Failed(Some(format!("{} isn't a position", to.trim()))),
+        },
+        CompleteAction::InsertStringIntoSetRegister(set, string) => {
+            performed(arena.set_insert_string(set, string))
+        }
+        CompleteAction::InsertNumberIntoSetRegister(set, text) => match number::parse(&text) {
+            Some(number) => performed(arena.set_insert_number(set, number)),
+            None => Outcome::Failed(Some(format!("{} isn't a number", text.trim()))),
+        },
+        CompleteAction::Arithmetic(operation, set, a, b) => {
+            match number::compute(arena, &operation, &a, &b) {
+                Some(number) => performed(arena.set_insert_number(set, number)),
+                None => Outcome::Failed(Some(format!(
+                    "can't {} the numbers bound to {} and {}",
+                    operation, a, b
+                ))),
+            }
+        }
+        CompleteAction::BindRegisterToEmptySet(register) => {
+            arena.bind_register_to_empty_set(register);
+            Outcome::Performed(None)
+        }
+        CompleteAction::BindRegisterToEmptyList(register) => {
+            arena.bind_register_to_empty_list(register);
+            Outcome::Performed(None)
+        }
+        CompleteAction::BindRegisterToRegisterValue(to_be_bound, to_lookup) => {
+            arena.bind_register_to_register_value(to_be_bound, to_lookup);
+            Outcome::Performed(None)
+        }
+        CompleteAction::BindRegisterToString(register, string) => {
+            arena.bind_register_to_string(register, string);
+            Outcome::Performed(None)
+        }
+        CompleteAction::CaptureString(string) => {
+            arena.capture_string(string);
+            Outcome::Performed(None)
+        }
+        CompleteAction::FindInto(register, text) => match Query::parse(&text) {
+            Some(query) => {
+                let results = query::run(arena, &query, index);
+                let count = results.len();
+                let set = arena.set(results);
+                arena.bind_register(register.as_str(), set);
+                Outcome::Performed(Some(format!(
+                    "bound {} values matching {} to {}",
+                    count, text, register
+                )))
+            }
+            None => Outcome::Failed(Some(format!("can't parse query '{}'", text))),
+        },
+        _ => Outcome::Unsupported,
+    }
+}
+
+/// Returns the values that [`apply`] would modify by performing `action`,
+/// other than by binding a register: the set or list bound to the register
+/// it changes.
+pub fn modified(arena: &mut Arena, action: &CompleteAction) -> Vec<ArenaKey> {
+    let register = match action {
+        CompleteAction::SetInsert(register, _)
+        | CompleteAction::SetUnion(register, _)
+        | CompleteAction::SetRemove(register, _)
+        | CompleteAction::ToggleMembership(register, _)
+        | CompleteAction::InsertStringIntoSetRegister(register, _)
+        | CompleteAction::InsertNumberIntoSetRegister(register, _)
+        | CompleteAction::Arithmetic(_, register, _, _)
+        | CompleteAction::ListPush(register, _)
+        | CompleteAction::ListPop(register)
+        | CompleteAction::ListInsertAt(register, ..)
+        | CompleteAction::ListRemoveAt(register, _)
+        | CompleteAction::ListSwap(register, _)
+        | CompleteAction::ListMove(register, ..) => register.as_str(),
+        CompleteAction::CaptureString(_) => INBOX_REGISTER,
+        _ => return vec![],
+    };
+    arena.register(register).into_iter().collect()
+}
+
+/// Returns why an action can't be performed, if it would modify something
+/// that can't be modified: anything at all (if it `mutates`) in `read_only`
+/// mode, or any of `modified` that is locked (see [`Arena::is_locked`]).
+pub fn rejection(
+    arena: &Arena,
+    read_only: bool,
+    mutates: bool,
+    modified: &[ArenaKey],
+) -> Option<&'static str> {
+    if read_only && mutates {
+        Some("nothing can be changed in read-only mode; f2 turns it off")
+    } else if modified.iter().any(|&key| arena.is_locked(key)) {
+        Some("that is locked; h unlocks it")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locked_sets_are_rejected() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_empty_set("a");
+        let a = arena.register("a").unwrap();
+        let action = CompleteAction::SetInsert("a".to_string(), ".".to_string());
+        let modified = modified(&mut arena, &action);
+        assert_eq!(vec![a], modified);
+        assert_eq!(None, rejection(&arena, false, true, &modified));
+        assert!(rejection(&arena, true, true, &modified).is_some());
+        arena.set_locked(a, true);
+        assert!(rejection(&arena, false, true, &modified).is_some());
+    }
+
+    #[test]
+    fn positions() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_empty_list("l");
+        for text in &["a", "b", "c"] {
+            arena.bind_register_to_string("v", *text);
+            arena.list_push("l", "v").unwrap();
+        }
+        let l = "l".to_string();
+        let texts = |arena: &mut Arena| -> Vec<String> {
+            let list = arena.register("l").unwrap();
+            let elements = arena.elements(list).unwrap().to_vec();
+            elements
+                .into_iter()
+                .map(|key| match &arena.slot_map[key].structure {
+                    Structure::String(text) => text.clone(),
+                    _ => unreachable!(),
+                })
+                .collect()
+        };
+        let run = |arena: &mut Arena, action| apply(arena, &action, None);
+        run(&mut arena, CompleteAction::ListSwap(l.clone(), 1));
+        assert_eq!(vec!["b", "a", "c"], texts(&mut arena));
+        run(
+            &mut arena,
+            CompleteAction::ListMove(l.clone(), 3, "1".into()),
+        );
+        assert_eq!(vec!["c", "b", "a"], texts(&mut arena));
+        run(&mut arena, CompleteAction::ListRemoveAt(l.clone(), 2));
+        assert_eq!(vec!["c", "a"], texts(&mut arena));
+        arena.bind_register_to_string("v", "d");
+        let insert = CompleteAction::ListInsertAt(l.clone(), 3, "v".into());
+        assert_eq!(Outcome::Performed(None), run(&mut arena, insert));
+        assert_eq!(vec!["c", "a", "d"], texts(&mut arena));
+        assert_eq!(
+            Outcome::Failed(Some("the list bound to l has no position 4".to_string())),
+            run(&mut arena, CompleteAction::ListSwap(l.clone(), 3))
+        );
+        assert_eq!(
+            no_position("l", 0),
+            run(&mut arena, CompleteAction::ListRemoveAt(l, 0))
+        );
+    }
+
+    #[test]
+    fn non_sets_fail() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_string("a", "a");
+        let action = CompleteAction::SetInsert("a".to_string(), ".".to_string());
+        assert_eq!(
+            Outcome::Failed(Some("a isn't bound to a set".to_string())),
+            apply(&mut arena, &action, None)
+        );
+        assert_eq!(
+            Outcome::Unsupported,
+            apply(&mut arena, &CompleteAction::Undo, None)
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! # Power saving
+//!
+//! Redrawing the window as often as the display refreshes, and multisampling
+//! every ring while doing so, noticeably drains a laptop's battery. In power
+//! saving mode ([`PowerState::Saving`]) Kakoi does less work:
+//!
+//! * Animations (and the progress bars of [`Task`](crate::task::Task)s, which
+//!   are only polled when a frame is drawn) are redrawn at most
+//!   [`SAVING_FRAME_RATE`] times a second.
+//! * Rings are drawn without multisampling (see
+//!   [`CircleRenderer::set_multisampling`]) and without shadows (see
+//!   [`CircleRenderer::set_depth_cues`]).
+//!
+//! Kakoi starts in power saving mode when it is started on battery power (see
+//! [`on_battery`]), or with `kakoi --power-saving`, and
+//! [`CompleteAction::TogglePowerSaving`] switches it on and off. Nothing is
+//! computed speculatively in the background, so there is no other work to
+//! hold off on.
+//!
+//! [`CircleRenderer::set_multisampling`]: crate::render::circle::CircleRenderer::set_multisampling
+//! [`CircleRenderer::set_depth_cues`]: crate::render::circle::CircleRenderer::set_depth_cues
+//! [`CompleteAction::TogglePowerSaving`]: crate::input_manager::CompleteAction::TogglePowerSaving
+
+use std::time::Duration;
+
+/// How many times a second animations are redrawn at most in power saving
+/// mode.
+pub const SAVING_FRAME_RATE: u32 = 20;
+
+/// Whether Kakoi is saving power. See [the module-level
+/// documentation](crate::power).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    Normal,
+    Saving,
+}
+
+impl Default for PowerState {
+    fn default() -> Self {
+        PowerState::Normal
+    }
+}
+
+impl PowerState {
+    /// Saves power if running on battery, and doesn't otherwise.
+    pub fn detect() -> Self {
+        if on_battery() {
+            PowerState::Saving
+        } else {
+            PowerState::Normal
+        }
+    }
+
+    /// Switches between saving power and not.
+    pub fn toggled(self) -> Self {
+        match self {
+            PowerState::Normal => PowerState::Saving,
+            PowerState::Saving => PowerState::Normal,
+        }
+    }
+
+    /// The shortest time between frames of an animation, or [`None`] if
+    /// animations are redrawn as often as the display allows.
+    pub fn frame_interval(self) -> Option<Duration> {
+        match self {
+            PowerState::Normal => None,
+            PowerState::Saving => Some(Duration::from_secs(1) / SAVING_FRAME_RATE),
+        }
+    }
+
+    /// Whether the edges of rings are smoothed by multisampling them.
+    pub fn multisampling(self) -> bool {
+        self == PowerState::Normal
+    }
+
+    /// Whether rings cast shadows, unless they were turned off anyway.
+    pub fn depth_cues(self) -> bool {
+        self == PowerState::Normal
+    }
+}
+
+/// Returns true if this computer has a battery that is discharging. This is
+/// only known on Linux, where it is read from `/sys/class/power_supply`;
+/// elsewhere, computers are assumed to be plugged in.
+pub fn on_battery() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let supplies = match std::fs::read_dir("/sys/class/power_supply") {
+            Ok(supplies) => supplies,
+            Err(_) => return false,
+        };
+        let read = |path: std::path::PathBuf| std::fs::read_to_string(path).unwrap_or_default();
+        supplies.filter_map(Result::ok).any(|supply| {
+            read(supply.path().join("type")).trim() == "Battery"
+                && read(supply.path().join("status")).trim() == "Discharging"
+        })
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        false
+    }
+}
@@ -0,0 +1,56 @@
+//! # Performance tracing
+//!
+//! The main subsystems of Kakoi (input handling, action application, spatial
+//! tree construction, instance building, and the render passes) are
+//! instrumented with [`tracing`] spans. Spans and events are forwarded to the
+//! [`log`] crate, so running with `RUST_LOG=debug` prints them.
+//!
+//! When Kakoi is built with the `profiling` feature, the timings of these spans
+//! can instead be written to a file with [`Profiler::chrome_trace`]. The file
+//! can be opened in `chrome://tracing` (or any other viewer that understands
+//! the Chrome trace event format, such as [speedscope]) to see a flamegraph of
+//! where time is being spent.
+//!
+//! [speedscope]: https://www.speedscope.app
+
+/// Keeps a trace file open while Kakoi is running.
+///
+/// The trace is written out when the [`Profiler`] is dropped, so it must be
+/// kept alive until Kakoi exits.
+pub struct Profiler {
+    #[cfg(feature = "profiling")]
+    _guard: Option<tracing_chrome::FlushGuard>,
+}
+
+impl Profiler {
+    /// A [`Profiler`] that doesn't record anything.
+    pub fn disabled() -> Self {
+        Self {
+            #[cfg(feature = "profiling")]
+            _guard: None,
+        }
+    }
+
+    /// Records every span and event to `path` in the Chrome trace event format.
+    ///
+    /// This installs a global [`tracing`] subscriber, so it may only be called
+    /// once.
+    #[cfg(feature = "profiling")]
+    pub fn chrome_trace(path: &str) -> Self {
+        use tracing_subscriber::prelude::*;
+
+        let (chrome_layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+            .file(path)
+            .include_args(true)
+            .build();
+        // `set_global_default` is used instead of `SubscriberInitExt::init`
+        // because the latter also captures `log` records, which would prevent
+        // `env_logger` from being initialized later on.
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(chrome_layer))
+            .expect("a global tracing subscriber was already set");
+
+        Self {
+            _guard: Some(guard),
+        }
+    }
+}
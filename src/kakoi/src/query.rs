@@ -0,0 +1,530 @@
+//! # Queries
+//!
+//! Values can be found by writing a query describing them, like
+//!
+//! ```text
+//! contains("foo") and type(set) and size > 3
+//! ```
+//!
+//! Running a query produces a new set of every value matching it. The text of
+//! every query that is run is saved in the set bound to the
+//! [`QUERIES_REGISTER`], so a query can be run again by selecting it. Any
+//! other string can be run as a query in the same way, so binding a query to
+//! a register gives it a name.
+//!
+//! Queries are made of the following predicates, combined with `and`, `or`,
+//! `not`, and parentheses (`not` binds tightest, then `and`, then `or`):
+//!
+//! * `contains("text")`: strings containing `text`, ignoring case, and the
+//! containers directly containing such a string.
+//! * `starts_with("text")`: strings starting with `text`, ignoring case, and
+//! the containers directly containing such a string.
+//! * `is("text")`: the string `text` itself, and the containers directly
+//! containing it.
+//! * `tag("name")`: values tagged with `name` (see
+//! [`Arena::tag`](crate::arena::Arena::tag)), which may be written with a
+//! leading `#`.
+//! * `author("name")`: values created by `name`, if that was recorded (see
+//! [`Arena::record_metadata`](crate::arena::Arena::record_metadata)).
+//! * `type(kind)`: values of the given kind: `set`, `list`, `map`, `image`,
+//! `linked_image`, `string`, `number` (see [`crate::number`]), `file` (see
+//! [`crate::blob`]), `command`, or `custom` (see [`crate::leaf`]).
+//! * `size < n` (or `<=`, `=`, `!=`, `>=`, `>`): containers with that many
+//! members, strings with that many characters, or files with that many bytes.
+//! Images, numbers and custom leaves have no size.
+//!
+//! Rather than checking every value in the arena, [`run`] first narrows down
+//! the values that could possibly match using indexes: the [`Kind`] of every
+//! value (see [`Arena::keys_of_kind`]), the deduplication of strings (see
+//! [`Arena::lookup_string`]), and the [inclusions](crate::arena::Value) of
+//! matching strings. `contains` queries are narrowed down further by a
+//! [`SearchIndex`] once one has been built (see [`crate::search`]).
+//!
+//! [`QUERIES_REGISTER`]: crate::arena::QUERIES_REGISTER
+
+use crate::arena::{tag_name, Arena, ArenaKey, Kind, Structure};
+use crate::search::SearchIndex;
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// How `size` is compared against a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn compare(self, a: usize, b: usize) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b,
+            Comparison::GreaterOrEqual => a >= b,
+            Comparison::Greater => a > b,
+        }
+    }
+}
+
+/// A parsed query. See [the module-level documentation](crate::query).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// `contains("text")`. The text is stored in lowercase.
+    Contains(String),
+    /// `starts_with("text")`. The text is stored in lowercase.
+    StartsWith(String),
+    /// `is("text")`.
+    Is(String),
+    /// `tag("name")`. The name is stored without a leading `#`.
+    Tag(String),
+    /// `author("name")`.
+    Author(String),
+    /// `type(kind)`.
+    Type(Kind),
+    /// `size < n`, and so on.
+    Size(Comparison, usize),
+    Not(Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(usize),
+    Open,
+    Close,
+    Compare(Comparison),
+}
+
+/// Splits `query` into [`Token`]s. Returns [`None`] if it contains anything
+/// that isn't part of the language, like an unterminated string.
+fn tokenize(query: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars: Peekable<CharIndices> = query.char_indices().peekable();
+    // Consumes the next character if it is `expected`.
+    fn next_is(chars: &mut Peekable<CharIndices>, expected: char) -> bool {
+        match chars.peek() {
+            Some(&(_, c)) if c == expected => {
+                chars.next();
+                true
+            }
+            _ => false,
+        }
+    }
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '<' if next_is(&mut chars, '=') => Token::Compare(Comparison::LessOrEqual),
+            '<' => Token::Compare(Comparison::Less),
+            '>' if next_is(&mut chars, '=') => Token::Compare(Comparison::GreaterOrEqual),
+            '>' => Token::Compare(Comparison::Greater),
+            '=' => {
+                next_is(&mut chars, '=');
+                Token::Compare(Comparison::Equal)
+            }
+            '!' if next_is(&mut chars, '=') => Token::Compare(Comparison::NotEqual),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next()? {
+                        (_, '"') => break,
+                        (_, '\\') => text.push(chars.next()?.1),
+                        (_, c) => text.push(c),
+                    }
+                }
+                Token::Text(text)
+            }
+            c if c.is_ascii_digit() || c.is_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        end = i + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let word = &query[start..end];
+                match word.parse() {
+                    Ok(number) => Token::Number(number),
+                    Err(_) if c.is_ascii_digit() => return None,
+                    Err(_) => Token::Word(word.to_lowercase()),
+                }
+            }
+            _ => return None,
+        };
+        tokens.push(token);
+    }
+    Some(tokens)
+}
+
+/// A recursive descent parser over [`Token`]s.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, token: Token) -> Option<()> {
+        if self.advance()? == token {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    /// Consumes the next token if it is the keyword `word`.
+    fn keyword(&mut self, word: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w == word => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Option<Query> {
+        let mut query = self.and()?;
+        while self.keyword("or") {
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Some(query)
+    }
+
+    fn and(&mut self) -> Option<Query> {
+        let mut query = self.not()?;
+        while self.keyword("and") {
+            query = Query::And(Box::new(query), Box::new(self.not()?));
+        }
+        Some(query)
+    }
+
+    fn not(&mut self) -> Option<Query> {
+        if self.keyword("not") {
+            Some(Query::Not(Box::new(self.not()?)))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> Option<Query> {
+        match self.advance()? {
+            Token::Open => {
+                let query = self.or()?;
+                self.expect(Token::Close)?;
+                Some(query)
+            }
+            Token::Word(word) => match word.as_str() {
+                "contains" | "starts_with" | "is" | "tag" | "author" => {
+                    self.expect(Token::Open)?;
+                    let text = match self.advance()? {
+                        Token::Text(text) => text,
+                        _ => return None,
+                    };
+                    self.expect(Token::Close)?;
+                    Some(match word.as_str() {
+                        "contains" => Query::Contains(text.to_lowercase()),
+                        "starts_with" => Query::StartsWith(text.to_lowercase()),
+                        "tag" => Query::Tag(tag_name(&text).to_string()),
+                        "author" => Query::Author(text),
+                        _ => Query::Is(text),
+                    })
+                }
+                "type" => {
+                    self.expect(Token::Open)?;
+                    let kind = match self.advance()? {
+                        Token::Word(kind) => match kind.as_str() {
+                            "set" => Kind::Set,
+                            "list" => Kind::List,
+                            "map" => Kind::Map,
+                            "image" => Kind::Image,
+                            "linked_image" => Kind::ImageRef,
+                            "string" => Kind::String,
+                            "number" => Kind::Number,
+                            "file" => Kind::Blob,
+                            "command" => Kind::Command,
+                            "custom" => Kind::Custom,
+                            _ => return None,
+                        },
+                        _ => return None,
+                    };
+                    self.expect(Token::Close)?;
+                    Some(Query::Type(kind))
+                }
+                "size" => match (self.advance()?, self.advance()?) {
+                    (Token::Compare(comparison), Token::Number(n)) => {
+                        Some(Query::Size(comparison, n))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Returns the values directly inside of the value bound to `key`.
+fn members(arena: &Arena, key: ArenaKey) -> Vec<ArenaKey> {
+    match &arena.slot_map[key].structure {
+        Structure::Set(set) => set.iter().copied().collect(),
+        Structure::List(list) => list.to_vec(),
+        Structure::Map(map) => map.iter().flat_map(|(&k, &v)| vec![k, v]).collect(),
+        Structure::Command(command) => command.to_vec(),
+        Structure::String(_)
+        | Structure::Image(_)
+        | Structure::ImageRef(_)
+        | Structure::Number(_)
+        | Structure::Blob(_)
+        | Structure::Custom(_) => vec![],
+    }
+}
+
+/// Returns `strings` alongside every value directly containing one of them.
+fn with_containers(arena: &Arena, strings: Vec<ArenaKey>) -> HashSet<ArenaKey> {
+    let containers = strings
+        .iter()
+        .flat_map(|&string| arena.slot_map[string].inclusions.iter())
+        .map(|&(container, _)| container)
+        .collect::<Vec<_>>();
+    strings.into_iter().chain(containers).collect()
+}
+
+impl Query {
+    /// Parses `query`. Returns [`None`] if it isn't a valid query.
+    pub fn parse(query: &str) -> Option<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(query)?,
+            position: 0,
+        };
+        let query = parser.or()?;
+        if parser.peek().is_none() {
+            Some(query)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if the value bound to `key` matches this query.
+    pub fn matches(&self, arena: &Arena, key: ArenaKey) -> bool {
+        let is_string = |key: ArenaKey, predicate: &dyn Fn(&str) -> bool| match &arena.slot_map[key]
+            .structure
+        {
+            Structure::String(string) => predicate(string),
+            _ => false,
+        };
+        let string_or_member = |predicate: &dyn Fn(&str) -> bool| {
+            is_string(key, predicate)
+                || members(arena, key)
+                    .into_iter()
+                    .any(|member| is_string(member, predicate))
+        };
+        match self {
+            Query::Contains(text) => {
+                string_or_member(&|string: &str| string.to_lowercase().contains(text.as_str()))
+            }
+            Query::StartsWith(text) => {
+                string_or_member(&|string: &str| string.to_lowercase().starts_with(text.as_str()))
+            }
+            Query::Is(text) => string_or_member(&|string: &str| string == text.as_str()),
+            Query::Tag(tag) => arena.tags(key).contains(&tag.as_str()),
+            Query::Author(author) => {
+                arena
+                    .metadata(key)
+                    .and_then(|metadata| metadata.author.as_deref())
+                    == Some(author.as_str())
+            }
+            Query::Type(kind) => arena.slot_map[key].structure.kind() == *kind,
+            Query::Size(comparison, n) => {
+                let size = match &arena.slot_map[key].structure {
+                    Structure::String(string) => string.chars().count(),
+                    Structure::Map(map) => map.len(),
+                    Structure::Blob(blob) => blob.bytes.len(),
+                    Structure::Image(_)
+                    | Structure::ImageRef(_)
+                    | Structure::Number(_)
+                    | Structure::Custom(_) => return false,
+                    _ => members(arena, key).len(),
+                };
+                comparison.compare(size, *n)
+            }
+            Query::Not(query) => !query.matches(arena, key),
+            Query::And(a, b) => a.matches(arena, key) && b.matches(arena, key),
+            Query::Or(a, b) => a.matches(arena, key) || b.matches(arena, key),
+        }
+    }
+
+    /// Returns a set of values that includes every value matching this query
+    /// (and possibly some that don't), or [`None`] if any value could match.
+    /// Strings are looked up in `index` if it is given.
+    fn candidates(&self, arena: &Arena, index: Option<&SearchIndex>) -> Option<HashSet<ArenaKey>> {
+        match self {
+            Query::Contains(text) => {
+                let strings: Vec<ArenaKey> = match index.and_then(|index| index.candidates(text)) {
+                    Some(strings) => strings.into_iter().collect(),
+                    None => arena.keys_of_kind(Kind::String),
+                };
+                Some(with_containers(
+                    arena,
+                    strings
+                        .into_iter()
+                        .filter(|&key| match arena.slot_map.get(key).map(|v| &v.structure) {
+                            Some(Structure::String(string)) => {
+                                string.to_lowercase().contains(text.as_str())
+                            }
+                            _ => false,
+                        })
+                        .collect(),
+                ))
+            }
+            Query::StartsWith(text) => Some(with_containers(
+                arena,
+                arena.strings_where(|string| string.to_lowercase().starts_with(text.as_str())),
+            )),
+            Query::Is(text) => Some(with_containers(
+                arena,
+                arena.lookup_string(text).into_iter().collect(),
+            )),
+            Query::Tag(tag) => Some(arena.tagged(tag).into_iter().collect()),
+            Query::Author(author) => Some(arena.authored_by(author).into_iter().collect()),
+            Query::Type(kind) => Some(arena.keys_of_kind(*kind).into_iter().collect()),
+            Query::Size(_, _) | Query::Not(_) => None,
+            Query::And(a, b) => match (a.candidates(arena, index), b.candidates(arena, index)) {
+                (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
+                (Some(candidates), None) | (None, Some(candidates)) => Some(candidates),
+                (None, None) => None,
+            },
+            Query::Or(a, b) => {
+                let mut candidates = a.candidates(arena, index)?;
+                candidates.extend(b.candidates(arena, index)?);
+                Some(candidates)
+            }
+        }
+    }
+}
+
+/// Returns every value matching `query`, leaving out the maps that pair
+/// registers with their values (see [`Arena::register_maps`]). `index`, if
+/// given, should have caught up with `arena` (see [`SearchIndex::catch_up`]).
+pub fn run(arena: &Arena, query: &Query, index: Option<&SearchIndex>) -> Vec<ArenaKey> {
+    let register_maps = arena.register_maps().collect::<HashSet<_>>();
+    let candidates: Vec<ArenaKey> = match query.candidates(arena, index) {
+        Some(candidates) => candidates.into_iter().collect(),
+        None => arena.slot_map.keys().collect(),
+    };
+    candidates
+        .into_iter()
+        .filter(|key| !register_maps.contains(key) && query.matches(arena, *key))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Some(Query::And(
+                Box::new(Query::And(
+                    Box::new(Query::Contains("foo".into())),
+                    Box::new(Query::Type(Kind::Set))
+                )),
+                Box::new(Query::Size(Comparison::Greater, 3))
+            )),
+            Query::parse(r#"contains("FOO") and type(set) and size > 3"#)
+        );
+        assert_eq!(
+            Some(Query::Or(
+                Box::new(Query::Not(Box::new(Query::Is("a \"b\"".into())))),
+                Box::new(Query::Size(Comparison::LessOrEqual, 2))
+            )),
+            Query::parse(r#"not is("a \"b\"") or (size <= 2)"#)
+        );
+        assert_eq!(
+            Some(Query::StartsWith("foo".into())),
+            Query::parse(r#"starts_with("Foo")"#)
+        );
+        assert_eq!(
+            Some(Query::Tag("todo".into())),
+            Query::parse(r##"tag("#todo")"##)
+        );
+        assert_eq!(None, Query::parse("contains(foo)"));
+        assert_eq!(None, Query::parse("type(set) and"));
+        assert_eq!(None, Query::parse(r#"is("unterminated)"#));
+        assert_eq!(None, Query::parse("size > 3x"));
+    }
+
+    #[test]
+    fn run_query() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_empty_set("a");
+        for string in &["food", "bar", "baz", "qux"] {
+            arena.set_insert_string("a", *string).unwrap();
+        }
+        arena.bind_register_to_empty_set("b");
+        arena.set_insert_string("b", "Foo").unwrap();
+        let (a, b) = (arena.register("a").unwrap(), arena.register("b").unwrap());
+        let food = arena.lookup_string("food").unwrap();
+        let foo = arena.lookup_string("Foo").unwrap();
+
+        let results = |query: &str| {
+            run(&arena, &Query::parse(query).unwrap(), None)
+                .into_iter()
+                .collect::<HashSet<_>>()
+        };
+        let set = |keys: &[ArenaKey]| keys.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(set(&[a, b, food, foo]), results(r#"contains("foo")"#));
+        assert_eq!(
+            set(&[a]),
+            results(r#"contains("foo") and type(set) and size > 3"#)
+        );
+        assert_eq!(set(&[b]), results(r#"is("Foo") and not type(string)"#));
+        assert_eq!(set(&[a, b, food, foo]), results(r#"starts_with("FO")"#));
+        assert_eq!(set(&[a]), results(r#"starts_with("ba") and type(set)"#));
+        assert_eq!(
+            set(&[foo]),
+            results(r#"type(string) and size = 3 and contains("o")"#)
+        );
+    }
+
+    #[test]
+    fn run_metadata_query() {
+        let mut arena = Arena::new();
+        arena.record_metadata(Some("ada".into()));
+        let (a, b) = (arena.string("a"), arena.string("b"));
+        arena.stamp_metadata(std::time::SystemTime::now());
+        arena.tag(a, "todo").unwrap();
+        arena.tag(b, "idea").unwrap();
+        let results = |arena: &Arena, query: &str| run(arena, &Query::parse(query).unwrap(), None);
+        assert_eq!(vec![a], results(&arena, r#"tag("todo")"#));
+        assert_eq!(
+            vec![b],
+            results(
+                &arena,
+                r#"author("ada") and type(string) and not tag("todo")"#
+            )
+        );
+    }
+}
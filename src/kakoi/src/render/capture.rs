@@ -0,0 +1,190 @@
+//! # Capturing values as images
+//!
+//! A value (along with everything inside of it) can be drawn offscreen and
+//! saved as a PNG, for use in papers and slides. Captures are drawn at a size
+//! chosen by the user rather than the size of the window, and only contain the
+//! value itself: overlays, the selection ring, and the progress bar are left
+//! out. Since values are laid out until their contents become too small to see
+//! (see [`crate::spatial_tree`]), larger captures show more deeply nested
+//! values than the window does.
+//!
+//! See [`Renderer::capture`](super::renderer::Renderer::capture).
+
+use uuid::Uuid;
+
+/// The largest width or height of a capture, in pixels. This is the largest
+/// texture size that every backend supports.
+pub const MAX_SIZE: u32 = 8192;
+
+/// A capture that was asked for but not yet drawn. Captures need access to the
+/// GPU, which only happens while rendering, so they are drawn by the
+/// [`State`](crate::state::State) after handling input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureRequest {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CaptureRequest {
+    /// Parses a resolution typed by the user, like `4096x2048` or `4096` (for
+    /// a square). Returns [`None`] unless both sides are between one and
+    /// [`MAX_SIZE`].
+    pub fn parse(resolution: &str) -> Option<Self> {
+        let resolution = resolution.trim();
+        let (width, height) = match resolution.find(|c: char| c == 'x' || c == '×') {
+            Some(index) => {
+                let separator = resolution[index..].chars().next().unwrap();
+                (
+                    &resolution[..index],
+                    &resolution[index + separator.len_utf8()..],
+                )
+            }
+            None => (resolution, resolution),
+        };
+        let parse = |side: &str| match side.trim().parse() {
+            Ok(side) if (1..=MAX_SIZE).contains(&side) => Some(side),
+            _ => None,
+        };
+        Some(Self {
+            width: parse(width)?,
+            height: parse(height)?,
+        })
+    }
+
+    /// The name of the file to save a capture of the value with the given
+    /// `uuid` to. Captures of the same value at the same size overwrite each
+    /// other.
+    pub fn file_name(&self, uuid: Uuid) -> String {
+        format!("kakoi-{}-{}x{}.png", uuid, self.width, self.height)
+    }
+}
+
+/// A texture to draw a capture onto, along with a buffer to read it back
+/// through.
+pub struct CaptureTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    /// Rows of pixels copied into `buffer` must be aligned to
+    /// [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`], so each row is followed by
+    /// some padding.
+    padded_bytes_per_row: u32,
+}
+
+impl CaptureTarget {
+    pub fn new(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: sc_desc.width,
+                height: sc_desc.height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: sc_desc.format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+            label: Some("capture texture"),
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bytes_per_row = 4 * sc_desc.width;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (bytes_per_row + alignment - 1) / alignment * alignment;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("capture buffer"),
+            size: (padded_bytes_per_row * sc_desc.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            texture,
+            view,
+            buffer,
+            format: sc_desc.format,
+            width: sc_desc.width,
+            height: sc_desc.height,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// The view to draw the capture onto.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Copies what was drawn into the buffer. Must be called after drawing,
+    /// before the commands are submitted.
+    pub fn copy(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        command_encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &self.buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: self.padded_bytes_per_row,
+                    rows_per_image: self.height,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth: 1,
+            },
+        );
+    }
+
+    /// Waits for the capture to be drawn and reads it back. The commands
+    /// recorded by [`CaptureTarget::copy`] must have been submitted.
+    pub fn read(self, device: &wgpu::Device) -> Option<image::RgbaImage> {
+        let slice = self.buffer.slice(..);
+        let mapped = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(mapped).ok()?;
+
+        let bytes_per_row = 4 * self.width as usize;
+        let mut pixels = Vec::with_capacity(bytes_per_row * self.height as usize);
+        for row in slice
+            .get_mapped_range()
+            .chunks(self.padded_bytes_per_row as usize)
+        {
+            pixels.extend_from_slice(&row[..bytes_per_row]);
+        }
+        self.buffer.unmap();
+
+        // Swap chains usually prefer BGRA, but PNGs are RGBA.
+        match self.format {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+                for pixel in pixels.chunks_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            _ => {}
+        }
+        image::RgbaImage::from_raw(self.width, self.height, pixels)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let request = |width, height| Some(CaptureRequest { width, height });
+        assert_eq!(request(4096, 2048), CaptureRequest::parse("4096x2048"));
+        assert_eq!(request(4096, 2048), CaptureRequest::parse(" 4096 × 2048 "));
+        assert_eq!(request(512, 512), CaptureRequest::parse("512"));
+        assert_eq!(None, CaptureRequest::parse("0x512"));
+        assert_eq!(None, CaptureRequest::parse("16384"));
+        assert_eq!(None, CaptureRequest::parse("big"));
+    }
+}
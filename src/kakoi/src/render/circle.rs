@@ -1,33 +1,97 @@
+use super::context::RenderContext;
+use crate::sampling_config::SamplingConfig;
 use crate::sphere::Sphere;
-use crate::{camera::Camera, sampling_config::SamplingConfig};
 use wgpu::util::DeviceExt;
 
-/// A view-projection matrix encoded as a
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct Uniforms {
-    view_proj: [[f32; 4]; 4],
+pub const MIN_RADIUS: f32 = 0.98;
+pub const MAX_RADIUS: f32 = 1.0;
+
+/// How many samples are drawn per pixel of a ring while multisampling. See
+/// [`CircleRenderer::set_multisampling`].
+pub const MULTISAMPLE_COUNT: u32 = 4;
+
+/// The thickness of the ring in the vertex buffer, as a fraction of its
+/// radius. Rings of any other thickness are made by moving the inner edge of
+/// this one (and the shadow inside of it) in circle.vert. Must match
+/// `MESH_THICKNESS` there.
+const MESH_THICKNESS: f32 = MAX_RADIUS - MIN_RADIUS;
+
+/// How thick rings are drawn depending on how deeply they are nested, as
+/// fractions of their radius. See [`CircleRenderer::set_ring_thickness`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RingThickness {
+    /// The thickness of rings at the top of their layer.
+    pub outer: f32,
+    /// How much thinner each level of nesting makes a ring.
+    pub per_depth: f32,
+    /// The thinnest a ring can get.
+    pub hairline: f32,
 }
 
-impl Uniforms {
-    fn new(view_projection_matrix: cgmath::Matrix4<f32>) -> Self {
-        Self {
-            view_proj: view_projection_matrix.into(),
-        }
+impl RingThickness {
+    /// Every ring is as thick as the ring in the vertex buffer.
+    pub const UNIFORM: Self = Self {
+        outer: MESH_THICKNESS,
+        per_depth: 0.0,
+        hairline: MESH_THICKNESS,
+    };
+
+    /// Outer rings are as thick as usual, and rings get thinner the more
+    /// deeply they are nested, down to a hairline. Rings never get thicker
+    /// than usual, since the elements inside of a ring are laid out to fit
+    /// within [`MIN_RADIUS`].
+    pub const TAPERED: Self = Self {
+        outer: MESH_THICKNESS,
+        per_depth: 0.004,
+        hairline: 0.005,
+    };
+
+    /// The thickness of a ring nested `depth` levels deep.
+    pub fn at(&self, depth: usize) -> f32 {
+        (self.outer - depth as f32 * self.per_depth).max(self.hairline)
     }
 }
 
-pub const MIN_RADIUS: f32 = 0.98;
-pub const MAX_RADIUS: f32 = 1.0;
+impl Default for RingThickness {
+    fn default() -> Self {
+        Self::UNIFORM
+    }
+}
+
+/// The inner radius of the shadow drawn just inside of each ring, which fades
+/// out from the ring towards the center. How dark it is depends on how deeply
+/// the ring is nested (see [`CircleRenderer::set_depth_cues`]). Must match
+/// `SHADOW_RADIUS` in circle.frag.
+const SHADOW_RADIUS: f32 = 0.9;
+
+/// How many levels below the top of a layer rings and strings are drawn at
+/// full strength when depth fading is on (see
+/// [`CircleRenderer::set_depth_fading`]).
+const FADE_START_DEPTH: usize = 2;
+
+/// How much closer to the background each level of nesting past
+/// [`FADE_START_DEPTH`] brings a ring or string, and the closest it can get.
+const FADE_PER_DEPTH: f32 = 0.2;
+const MAX_FADE: f32 = 0.85;
+
+/// How far towards the background something nested `depth` levels deep is
+/// faded when depth fading is on, from 0 (not at all) to 1 (invisible).
+pub fn depth_fade(depth: usize) -> f32 {
+    (depth.saturating_sub(FADE_START_DEPTH) as f32 * FADE_PER_DEPTH).min(MAX_FADE)
+}
+
+/// How far towards the background dimmed rings and strings are faded. See
+/// [`CircleRenderer::set_dimming`].
+pub const DIM_FADE: f32 = 0.7;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 3],
+pub(super) struct Vertex {
+    pub(super) position: [f32; 3],
 }
 
 impl Vertex {
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    pub(super) fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
             step_mode: wgpu::InputStepMode::Vertex,
@@ -39,7 +103,7 @@ impl Vertex {
         }
     }
 
-    fn make_circle(steps: u32, min_radius: f32, max_radius: f32) -> Vec<Vertex> {
+    pub(super) fn make_circle(steps: u32, min_radius: f32, max_radius: f32) -> Vec<Vertex> {
         let mut theta = 0.0;
         let mut result = Vec::new();
         let step = 2.0 * std::f32::consts::PI / steps as f32;
@@ -71,24 +135,38 @@ impl Vertex {
     }
 
     fn circle() -> Vec<Vertex> {
-        Self::make_circle(200, MIN_RADIUS, MAX_RADIUS)
+        let mut circle = Self::make_circle(200, MIN_RADIUS, MAX_RADIUS);
+        circle.append(&mut Self::make_circle(200, SHADOW_RADIUS, MIN_RADIUS));
+        circle
     }
 }
 
 pub struct CircleRenderer {
-    pub constraints: Vec<Sphere>,
+    /// Each ring to draw, alongside how deeply it is nested and whether it is
+    /// dimmed.
+    pub constraints: Vec<(Sphere, usize, bool)>,
     instances_cache: Option<wgpu::Buffer>,
-    render_pipeline: wgpu::RenderPipeline,
+    depth_cues: bool,
+    depth_fading: bool,
+    dimming: bool,
+    thickness: RingThickness,
+    multisampled_pipeline: wgpu::RenderPipeline,
+    single_sampled_pipeline: wgpu::RenderPipeline,
+    /// Whether rings are multisampled. See
+    /// [`CircleRenderer::set_multisampling`].
+    multisampling: bool,
     vertex_buffer: wgpu::Buffer,
-    uniform_buffer: wgpu::Buffer,
-    uniform_buffer_stale: bool,
-    uniform_bind_group: wgpu::BindGroup,
+    framebuffer_stale: bool,
     vertex_buffer_data: Vec<Vertex>,
     sampling_config: SamplingConfig,
 }
 
 impl CircleRenderer {
-    pub fn new<'a>(device: &'a wgpu::Device, sc_desc: &'a wgpu::SwapChainDescriptor) -> Self {
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        context: &'a RenderContext,
+    ) -> Self {
         let vertex_buffer_data = Vertex::circle();
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("CircleConstraint vertex buffer"),
@@ -96,140 +174,108 @@ impl CircleRenderer {
             usage: wgpu::BufferUsage::VERTEX,
         });
 
-        let vs_module =
-            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.vert.spv"));
-        let fs_module =
-            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.frag.spv"));
-
-        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("circle renderer uniform buffer"),
-            size: std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("uniform_bind_group_layout"),
-            });
-
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-            label: Some("uniform_bind_group"),
-        });
-
-        let sample_count = 4;
         let multisampled_framebuffer =
-            Self::create_mutisampled_framebuffer(&device, &sc_desc, sample_count);
+            Self::create_mutisampled_framebuffer(&device, &sc_desc, MULTISAMPLE_COUNT);
         let sampling_config = SamplingConfig::Multi {
-            sample_count,
+            sample_count: MULTISAMPLE_COUNT,
             multisampled_framebuffer,
         };
-
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&uniform_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vs_module,
-                entry_point: "main",
-                buffers: &[Vertex::desc(), CircleConstraintInstance::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &fs_module,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc_desc.format,
-                    alpha_blend: wgpu::BlendState::REPLACE,
-                    color_blend: wgpu::BlendState::REPLACE,
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: sample_count,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
+        let multisampled_pipeline =
+            Self::create_pipeline(device, sc_desc, context, MULTISAMPLE_COUNT);
+        let single_sampled_pipeline = Self::create_pipeline(device, sc_desc, context, 1);
 
         Self {
             constraints: Vec::new(),
             instances_cache: None,
-            render_pipeline,
+            depth_cues: true,
+            depth_fading: false,
+            dimming: false,
+            thickness: RingThickness::default(),
+            multisampled_pipeline,
+            single_sampled_pipeline,
+            multisampling: true,
             vertex_buffer,
-            uniform_buffer,
-            uniform_buffer_stale: true,
-            uniform_bind_group,
+            framebuffer_stale: false,
             vertex_buffer_data,
             sampling_config,
         }
     }
 
-    pub fn with_instance<'a>(&mut self, sphere: Sphere) {
-        self.constraints.push(sphere);
+    /// Draws a ring around `sphere`, which is nested `depth` levels deep in
+    /// its layer.
+    pub fn with_instance<'a>(&mut self, sphere: Sphere, depth: usize) {
+        self.constraints.push((sphere, depth, self.dimming));
     }
 
-    pub fn resize<'a>(
-        &mut self,
-    ) {
-        self.uniform_buffer_stale = true;
+    /// Chooses whether rings cast shadows that darken the more deeply they are
+    /// nested, making nesting easier to perceive at a glance. With depth cues
+    /// off, rings are drawn flat. Depth cues are on by default.
+    pub fn set_depth_cues(&mut self, depth_cues: bool) {
+        self.depth_cues = depth_cues;
+        self.instances_cache = None;
+    }
+
+    /// Chooses whether rings nested deeply below the top of their layer fade
+    /// into the background (see [`depth_fade`]), keeping attention on the
+    /// focused value and its members while still hinting at what is inside of
+    /// them. Depth fading is off by default.
+    pub fn set_depth_fading(&mut self, depth_fading: bool) {
+        self.depth_fading = depth_fading;
+        self.instances_cache = None;
+    }
+
+    /// Chooses how thick rings are drawn depending on how deeply they are
+    /// nested. Rings are all as thick as each other by default
+    /// ([`RingThickness::UNIFORM`]).
+    pub fn set_ring_thickness(&mut self, thickness: RingThickness) {
+        self.thickness = thickness;
+        self.instances_cache = None;
+    }
+
+    /// Chooses whether the rings added from now on are dimmed, fading them
+    /// towards the background by [`DIM_FADE`]. This is how everything but the
+    /// values highlighted by [`CompleteAction::Highlight`] is dimmed.
+    ///
+    /// [`CompleteAction::Highlight`]: crate::input_manager::CompleteAction::Highlight
+    pub fn set_dimming(&mut self, dimming: bool) {
+        self.dimming = dimming;
+    }
+
+    /// Chooses whether rings are drawn with [`MULTISAMPLE_COUNT`] samples per
+    /// pixel, smoothing their edges, or with a single sample, which takes less
+    /// work (see [`crate::power`]). Rings are multisampled by default.
+    pub fn set_multisampling(&mut self, multisampling: bool) {
+        self.multisampling = multisampling;
+        self.framebuffer_stale = true;
+    }
+
+    pub fn resize(&mut self) {
+        self.framebuffer_stale = true;
     }
 
     pub fn render<'a>(
         &mut self,
         device: &'a wgpu::Device,
-        queue: &'a mut wgpu::Queue,
         sc_desc: &'a wgpu::SwapChainDescriptor,
         command_encoder: &'a mut wgpu::CommandEncoder,
         texture_view: &'a wgpu::TextureView,
-        camera: &'a mut Camera,
+        context: &'a RenderContext,
     ) {
-        if self.uniform_buffer_stale {
-            queue.write_buffer(
-                &self.uniform_buffer,
-                0,
-                bytemuck::cast_slice(&[Uniforms::new(*camera.view_projection_matrix())]),
-            );
-            self.sampling_config = match self.sampling_config {
-                SamplingConfig::Single => SamplingConfig::Single,
-                SamplingConfig::Multi { sample_count, .. } => SamplingConfig::Multi {
-                    sample_count,
+        let _span = tracing::trace_span!("circle_render").entered();
+        if self.framebuffer_stale {
+            self.sampling_config = if self.multisampling {
+                SamplingConfig::Multi {
+                    sample_count: MULTISAMPLE_COUNT,
                     multisampled_framebuffer: Self::create_mutisampled_framebuffer(
                         device,
                         sc_desc,
-                        sample_count,
+                        MULTISAMPLE_COUNT,
                     ),
-                },
+                }
+            } else {
+                SamplingConfig::Single
             };
-            self.uniform_buffer_stale = false;
+            self.framebuffer_stale = false;
         }
 
         // This is supposed to be rgb(33,33,33,256), but it ends up being a bit too dark on screen.
@@ -278,11 +324,20 @@ impl CircleRenderer {
             depth_stencil_attachment: None,
         });
 
-        let instance_buffer =
-            Self::build_instances(&mut self.instances_cache, &self.constraints, device);
+        let instance_buffer = Self::build_instances(
+            &mut self.instances_cache,
+            &self.constraints,
+            self.depth_cues,
+            self.depth_fading,
+            self.thickness,
+            device,
+        );
 
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_pipeline(match self.sampling_config {
+            SamplingConfig::Single => &self.single_sampled_pipeline,
+            SamplingConfig::Multi { .. } => &self.multisampled_pipeline,
+        });
+        render_pass.set_bind_group(0, context.camera_bind_group(), &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
         let width = sc_desc.width as f32;
@@ -298,19 +353,40 @@ impl CircleRenderer {
 
     pub fn invalidate(&mut self) {
         self.constraints = Vec::new();
+        self.dimming = false;
         self.instances_cache = None;
     }
 
     fn build_instances<'a, 'b>(
         instances_cache: &'b mut Option<wgpu::Buffer>,
-        constraints: &'b Vec<Sphere>,
+        constraints: &'b Vec<(Sphere, usize, bool)>,
+        depth_cues: bool,
+        depth_fading: bool,
+        thickness: RingThickness,
         device: &'a wgpu::Device,
     ) -> &'b wgpu::Buffer {
         let mut instances: Vec<CircleConstraintInstance> = Vec::new();
 
         if instances_cache.is_none() {
-            for sphere in constraints {
-                instances.push(CircleConstraintInstance::new(sphere));
+            let _span =
+                tracing::debug_span!("circle_build_instances", count = constraints.len()).entered();
+            for (sphere, depth, dimmed) in constraints {
+                let mut fade = if depth_fading {
+                    depth_fade(*depth)
+                } else {
+                    0.0
+                };
+                if *dimmed {
+                    fade = fade.max(DIM_FADE);
+                }
+                let ring_thickness = thickness.at(*depth);
+                // Without depth cues, every ring is drawn as if it were at the
+                // top, which casts no shadow.
+                let depth = if depth_cues { *depth } else { 0 };
+                let mut instance = CircleConstraintInstance::with_depth(sphere, depth);
+                instance.fade = fade;
+                instance.thickness = ring_thickness;
+                instances.push(instance);
             }
 
             *instances_cache = Some(
@@ -325,6 +401,70 @@ impl CircleRenderer {
         instances_cache.as_ref().unwrap()
     }
 
+    /// Creates the pipeline rings are drawn with, drawing `sample_count`
+    /// samples per pixel.
+    fn create_pipeline(
+        device: &wgpu::Device,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        context: &RenderContext,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        let vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.vert.spv"));
+        let fs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.frag.spv"));
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[context.camera_layout()],
+                push_constant_ranges: &[],
+            });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc(), CircleConstraintInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                // Rings are opaque, but their shadows are blended with
+                // whatever they are drawn over.
+                targets: &[wgpu::ColorTargetState {
+                    format: sc_desc.format,
+                    alpha_blend: wgpu::BlendState {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    color_blend: wgpu::BlendState {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        })
+    }
+
     fn create_mutisampled_framebuffer(
         device: &wgpu::Device,
         sc_desc: &wgpu::SwapChainDescriptor,
@@ -355,18 +495,44 @@ impl CircleRenderer {
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CircleConstraintInstance {
     model: [[f32; 4]; 4],
+    depth: f32,
+    /// How far the ring is faded towards the background. See [`depth_fade`].
+    fade: f32,
+    /// How thick the ring is, as a fraction of its radius. See
+    /// [`RingThickness`].
+    thickness: f32,
 }
 
 impl CircleConstraintInstance {
     pub fn new(sphere: &Sphere) -> Self {
+        Self::with_depth(sphere, 0)
+    }
+
+    /// Like [`CircleConstraintInstance::new`], for a ring nested `depth`
+    /// levels deep. Only circle.frag makes use of the depth.
+    pub fn with_depth(sphere: &Sphere, depth: usize) -> Self {
         let scale = cgmath::Matrix4::from_scale(sphere.radius);
         let translation = cgmath::Matrix4::from_translation(sphere.center);
         Self {
             model: (translation * scale).into(),
+            depth: depth as f32,
+            fade: 0.0,
+            thickness: MESH_THICKNESS,
+        }
+    }
+
+    /// Like [`CircleConstraintInstance::new`], for a ring `thickness` thick
+    /// (as a fraction of its radius) drawn with a fragment shader that reads
+    /// `tint` where circle.frag reads the fade, like heat.frag.
+    pub fn with_tint(sphere: &Sphere, tint: f32, thickness: f32) -> Self {
+        Self {
+            fade: tint,
+            thickness,
+            ..Self::new(sphere)
         }
     }
 
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    pub(super) fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         use std::mem::size_of;
         wgpu::VertexBufferLayout {
             array_stride: size_of::<CircleConstraintInstance>() as wgpu::BufferAddress,
@@ -392,6 +558,21 @@ impl CircleConstraintInstance {
                     shader_location: 4,
                     format: wgpu::VertexFormat::Float4,
                 },
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float,
+                },
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 17]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float,
+                },
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 18]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float,
+                },
             ],
         }
     }
@@ -0,0 +1,152 @@
+use crate::camera::Camera;
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CameraUniforms {
+    view_proj: [[f32; 4]; 4],
+}
+
+/// GPU state shared by every renderer that draws with its own pipeline.
+///
+/// The view-projection matrix of the [`Camera`] lives in a single uniform
+/// buffer, which is written once per frame by [`RenderContext::write_camera`]
+/// before anything is drawn. Every pipeline is created with
+/// [`RenderContext::camera_layout`] and binds
+/// [`RenderContext::camera_bind_group`], so no renderer can draw with a
+/// matrix left over from before the window was resized.
+///
+/// The shaders expect the camera at `set=0, binding=0`, except for the image
+/// shaders, whose textures are at set 0 and whose camera is at set 1.
+/// Renderers filled in with the selection fragment shader also bind a
+/// [`ColorUniform`] at set 1.
+///
+/// Strings are not drawn with a pipeline of our own. The [`TextRenderer`]
+/// hands the matrix to `wgpu_glyph` as part of each section's transformation.
+///
+/// [`TextRenderer`]: super::text::TextRenderer
+pub struct RenderContext {
+    camera_buffer: wgpu::Buffer,
+    camera_layout: wgpu::BindGroupLayout,
+    camera_bind_group: wgpu::BindGroup,
+    color_layout: wgpu::BindGroupLayout,
+}
+
+impl RenderContext {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("camera uniform buffer"),
+            size: std::mem::size_of::<CameraUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let camera_layout = uniform_layout(
+            device,
+            wgpu::ShaderStage::VERTEX,
+            "camera uniform bind group layout",
+        );
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera uniform bind group"),
+        });
+
+        let color_layout = uniform_layout(
+            device,
+            wgpu::ShaderStage::FRAGMENT,
+            "color uniform bind group layout",
+        );
+
+        Self {
+            camera_buffer,
+            camera_layout,
+            camera_bind_group,
+            color_layout,
+        }
+    }
+
+    /// Uploads the view-projection matrix of `camera`. Must be called once per
+    /// frame, before any renderer draws.
+    pub fn write_camera(&self, queue: &wgpu::Queue, camera: &mut Camera) {
+        let uniforms = CameraUniforms {
+            view_proj: (*camera.view_projection_matrix()).into(),
+        };
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    }
+
+    pub fn camera_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.camera_layout
+    }
+
+    pub fn camera_bind_group(&self) -> &wgpu::BindGroup {
+        &self.camera_bind_group
+    }
+
+    pub fn color_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.color_layout
+    }
+}
+
+/// Creates the layout of a bind group holding a single uniform buffer.
+fn uniform_layout(
+    device: &wgpu::Device,
+    visibility: wgpu::ShaderStage,
+    label: &str,
+) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+        label: Some(label),
+    })
+}
+
+/// The color that the selection fragment shader fills everything in with.
+pub struct ColorUniform {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ColorUniform {
+    pub fn new(
+        device: &wgpu::Device,
+        context: &RenderContext,
+        color: [f32; 4],
+        label: &str,
+    ) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&[color]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: context.color_layout(),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some(label),
+        });
+        Self { buffer, bind_group }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, color: [f32; 4]) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[color]));
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
@@ -0,0 +1,85 @@
+//! # Draw ordering
+//!
+//! Each renderer queues its own instances, so without any coordination
+//! between them, everything drawn by one renderer ends up on top of everything
+//! drawn by the renderers before it. An image deep inside of one set could then
+//! cover up a string near the top of an unrelated, overlapping set.
+//!
+//! To prevent this, every instance is queued alongside a [`DrawOrder`]. The
+//! [`Renderer`] gathers the distinct orders of every queued instance with
+//! [`batches`], then asks each renderer to draw just the instances with each
+//! order in turn, from the lowest order to the highest. Renderers keep their
+//! instances sorted by order, so the instances in a batch can be found with
+//! [`batch_range`].
+//!
+//! Circles are the exception. They are drawn into a multisampled framebuffer
+//! that is resolved onto the frame, which would erase anything drawn before
+//! them, so they are always drawn first, underneath everything else.
+//!
+//! [`Renderer`]: crate::render::renderer::Renderer
+
+use std::ops::Range;
+
+/// Where an instance is placed in the drawing order. Instances with smaller
+/// orders are drawn first, so they end up underneath instances with larger
+/// orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DrawOrder {
+    /// The index of the [`Layer`](crate::overlay::Layer) the instance belongs
+    /// to, counting up from the bottom of the overlay stack. Every instance in
+    /// a layer is drawn above every instance in the layers below it.
+    pub layer: usize,
+    /// The depth of the instance in the spatial tree, plus the z-offset of the
+    /// value it displays (see [`Arena::z_offset`]).
+    ///
+    /// [`Arena::z_offset`]: crate::arena::Arena::z_offset
+    pub z: i64,
+}
+
+/// Returns each distinct order in `orders` once, from lowest to highest. Each
+/// of these is drawn as a single batch.
+pub fn batches<I: IntoIterator<Item = DrawOrder>>(orders: I) -> Vec<DrawOrder> {
+    let mut batches: Vec<DrawOrder> = orders.into_iter().collect();
+    batches.sort_unstable();
+    batches.dedup();
+    batches
+}
+
+/// Returns the range of `sorted` whose elements have the order `order`.
+///
+/// `sorted` must be sorted by `order_of`.
+pub fn batch_range<T, F: Fn(&T) -> DrawOrder>(
+    sorted: &[T],
+    order: DrawOrder,
+    order_of: F,
+) -> Range<usize> {
+    let start = sorted.partition_point(|t| order_of(t) < order);
+    let end = sorted.partition_point(|t| order_of(t) <= order);
+    start..end
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn order(layer: usize, z: i64) -> DrawOrder {
+        DrawOrder { layer, z }
+    }
+
+    #[test]
+    fn batches_are_sorted_and_distinct() {
+        assert_eq!(
+            vec![order(0, -1), order(0, 2), order(1, 0)],
+            batches(vec![order(1, 0), order(0, 2), order(0, -1), order(0, 2)])
+        );
+    }
+
+    #[test]
+    fn batch_ranges() {
+        let sorted = vec![order(0, 0), order(0, 1), order(0, 1), order(1, 0)];
+        assert_eq!(0..1, batch_range(&sorted, order(0, 0), |&o| o));
+        assert_eq!(1..3, batch_range(&sorted, order(0, 1), |&o| o));
+        assert_eq!(3..4, batch_range(&sorted, order(1, 0), |&o| o));
+        assert_eq!(3..3, batch_range(&sorted, order(0, 5), |&o| o));
+    }
+}
@@ -0,0 +1,187 @@
+use super::circle::{CircleConstraintInstance, Vertex};
+use super::context::{ColorUniform, RenderContext};
+use crate::sphere::Sphere;
+use crate::square_cuboid::SquareCuboid;
+use wgpu::util::DeviceExt;
+
+/// The color of the veil. Everything beneath it is blended halfway towards
+/// the (black) background.
+const COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+
+/// The number of vertices in the two triangles making up the veil.
+const VERTEX_COUNT: usize = 6;
+
+/// Returns the triangles filling in `square_cuboid`.
+fn make_veil(square_cuboid: &SquareCuboid) -> [Vertex; VERTEX_COUNT] {
+    let (width, height) = square_cuboid.dimensions_2d();
+    let center = square_cuboid.center;
+    let (left, right) = (center.x - 0.5 * width, center.x + 0.5 * width);
+    let (bottom, top) = (center.y - 0.5 * height, center.y + 0.5 * height);
+    let vertex = |x, y| Vertex {
+        position: [x, y, center.z],
+    };
+    [
+        vertex(left, top),
+        vertex(left, bottom),
+        vertex(right, bottom),
+        vertex(left, top),
+        vertex(right, bottom),
+        vertex(right, top),
+    ]
+}
+
+/// Makes the badge showing the value being moved (see
+/// [`OverlayStack::ghost`]) look like a semi-transparent ghost of it, by
+/// drawing a translucent veil over the badge.
+///
+/// Like the progress bar, the veil is drawn on top of everything else and is
+/// filled in with the selection fragment shader. Unlike everything else, it is
+/// blended with what is beneath it.
+///
+/// [`OverlayStack::ghost`]: crate::overlay::OverlayStack::ghost
+pub struct GhostRenderer {
+    bounds: Option<SquareCuboid>,
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    color: ColorUniform,
+}
+
+impl GhostRenderer {
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        context: &'a RenderContext,
+    ) -> Self {
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ghost renderer vertex buffer"),
+            size: (VERTEX_COUNT * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // The vertices of the veil are already in place, so it is drawn with a
+        // single identity instance, like rectangles.
+        let identity = CircleConstraintInstance::new(&Sphere {
+            center: (0.0, 0.0, 0.0).into(),
+            radius: 1.0,
+        });
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("ghost renderer instance buffer"),
+            contents: bytemuck::cast_slice(&[identity]),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.vert.spv"));
+        let fs_module = device
+            .create_shader_module(&wgpu::include_spirv!("../shaders/build/selection.frag.spv"));
+
+        let color = ColorUniform::new(device, context, COLOR, "ghost renderer color uniform");
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("ghost renderer pipeline layout"),
+                bind_group_layouts: &[context.camera_layout(), context.color_layout()],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("ghost renderer pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc(), CircleConstraintInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc_desc.format,
+                    alpha_blend: wgpu::BlendState {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    color_blend: wgpu::BlendState {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            bounds: None,
+            render_pipeline,
+            vertex_buffer,
+            instance_buffer,
+            color,
+        }
+    }
+
+    /// Veils the region `bounds`, or nothing if there is no ghost.
+    pub fn set_bounds(&mut self, bounds: Option<SquareCuboid>) {
+        self.bounds = bounds;
+    }
+
+    pub fn render<'a>(
+        &mut self,
+        queue: &'a mut wgpu::Queue,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        texture_view: &'a wgpu::TextureView,
+        context: &'a RenderContext,
+    ) {
+        let bounds = match &self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let _span = tracing::trace_span!("ghost_render").entered();
+
+        queue.write_buffer(
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&make_veil(bounds)),
+        );
+
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("ghost renderer render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, context.camera_bind_group(), &[]);
+        render_pass.set_bind_group(1, self.color.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        let width = sc_desc.width as f32;
+        let height = sc_desc.height as f32;
+        render_pass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
+        render_pass.draw(0..VERTEX_COUNT as u32, 0..1);
+    }
+}
@@ -0,0 +1,166 @@
+use super::circle::{CircleConstraintInstance, Vertex, MAX_RADIUS, MIN_RADIUS};
+use super::context::RenderContext;
+use super::selection::sphere_around;
+use crate::spatial_bound::SpatialBound;
+use crate::sphere::Sphere;
+use wgpu::util::DeviceExt;
+
+/// How thick the ring around something that costs nothing is, as a fraction
+/// of its radius.
+const MIN_THICKNESS: f32 = 0.02;
+
+/// How thick the ring around the root of a layer is.
+const MAX_THICKNESS: f32 = 0.08;
+
+/// Draws a ring around everything drawn, colored and thickened by how much it
+/// costs to draw (see [`crate::cost`]).
+///
+/// Each ring is an instance of a single ring, drawn on top of everything but
+/// the selection with heat.frag, which reads each ring's heat from its
+/// instance (see [`CircleConstraintInstance::with_tint`]).
+pub struct HeatRenderer {
+    rings: Vec<(Sphere, f32)>,
+    instance_buffer_cache: Option<wgpu::Buffer>,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl HeatRenderer {
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        context: &'a RenderContext,
+    ) -> Self {
+        let vertices = Vertex::make_circle(200, MIN_RADIUS, MAX_RADIUS);
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("heat renderer vertex buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.vert.spv"));
+        let fs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/heat.frag.spv"));
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("heat renderer pipeline layout"),
+                bind_group_layouts: &[context.camera_layout()],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("heat renderer pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc(), CircleConstraintInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc_desc.format,
+                    alpha_blend: wgpu::BlendState {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    color_blend: wgpu::BlendState {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            rings: Vec::new(),
+            instance_buffer_cache: None,
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            render_pipeline,
+        }
+    }
+
+    /// Surrounds each of `bounds` with a ring as hot as the heat alongside
+    /// it (from 0 to 1), replacing the rings drawn before. Pass no bounds to
+    /// stop drawing rings.
+    pub fn set_rings(&mut self, bounds: &[(SpatialBound, f32)]) {
+        self.rings = bounds
+            .iter()
+            .map(|(bound, heat)| (sphere_around(bound), heat.max(0.0).min(1.0)))
+            .collect();
+        self.instance_buffer_cache = None;
+    }
+
+    pub fn render<'a>(
+        &mut self,
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        texture_view: &'a wgpu::TextureView,
+        context: &'a RenderContext,
+    ) {
+        if self.rings.is_empty() {
+            return;
+        }
+        let _span = tracing::trace_span!("heat_render").entered();
+
+        let rings = &self.rings;
+        let instance_buffer = self.instance_buffer_cache.get_or_insert_with(|| {
+            let instances: Vec<CircleConstraintInstance> = rings
+                .iter()
+                .map(|(sphere, heat)| {
+                    let thickness = MIN_THICKNESS + heat * (MAX_THICKNESS - MIN_THICKNESS);
+                    CircleConstraintInstance::with_tint(sphere, *heat, thickness)
+                })
+                .collect();
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("heat renderer instance buffer"),
+                contents: bytemuck::cast_slice(&instances),
+                usage: wgpu::BufferUsage::VERTEX,
+            })
+        });
+
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("heat renderer render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, context.camera_bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        let width = sc_desc.width as f32;
+        let height = sc_desc.height as f32;
+        render_pass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
+        render_pass.draw(0..self.vertex_count, 0..self.rings.len() as u32);
+    }
+}
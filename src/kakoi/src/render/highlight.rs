@@ -0,0 +1,176 @@
+use super::circle::{CircleConstraintInstance, Vertex};
+use super::context::{ColorUniform, RenderContext};
+use super::selection::sphere_around;
+use crate::spatial_bound::SpatialBound;
+use crate::sphere::Sphere;
+use wgpu::util::DeviceExt;
+
+/// The color of the halos.
+const COLOR: [f32; 4] = [1.0, 0.9, 0.35, 0.85];
+
+/// How much larger than the value it surrounds a halo is.
+const SCALE: f32 = 1.1;
+
+/// The inner radius of a halo, as a fraction of its outer radius.
+const INNER_RADIUS: f32 = 0.92;
+
+/// Draws a halo around each place a highlighted value is displayed (see
+/// [`SpatialTree::highlighted`]), so that the values matching a query can be
+/// seen where they are, rather than only in a set of results.
+///
+/// Like the selection ring, the halos are drawn on top of everything else and
+/// are filled in with the selection fragment shader. Each halo is an instance
+/// of a single ring.
+///
+/// [`SpatialTree::highlighted`]: crate::spatial_tree::SpatialTree::highlighted
+pub struct HighlightRenderer {
+    halos: Vec<Sphere>,
+    instance_buffer_cache: Option<wgpu::Buffer>,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    render_pipeline: wgpu::RenderPipeline,
+    color: ColorUniform,
+}
+
+impl HighlightRenderer {
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        context: &'a RenderContext,
+    ) -> Self {
+        let vertices = Vertex::make_circle(200, INNER_RADIUS, 1.0);
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("highlight renderer vertex buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.vert.spv"));
+        let fs_module = device
+            .create_shader_module(&wgpu::include_spirv!("../shaders/build/selection.frag.spv"));
+
+        let color = ColorUniform::new(device, context, COLOR, "highlight renderer color uniform");
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("highlight renderer pipeline layout"),
+                bind_group_layouts: &[context.camera_layout(), context.color_layout()],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("highlight renderer pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc(), CircleConstraintInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc_desc.format,
+                    alpha_blend: wgpu::BlendState {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    color_blend: wgpu::BlendState {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            halos: Vec::new(),
+            instance_buffer_cache: None,
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            render_pipeline,
+            color,
+        }
+    }
+
+    /// Surrounds each of `bounds` with a halo, replacing the halos drawn
+    /// before. Pass no bounds to stop drawing halos.
+    pub fn set_halos(&mut self, bounds: &[SpatialBound]) {
+        self.halos = bounds
+            .iter()
+            .map(|bound| {
+                let sphere = sphere_around(bound);
+                Sphere {
+                    center: sphere.center,
+                    radius: sphere.radius * SCALE,
+                }
+            })
+            .collect();
+        self.instance_buffer_cache = None;
+    }
+
+    pub fn render<'a>(
+        &mut self,
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        texture_view: &'a wgpu::TextureView,
+        context: &'a RenderContext,
+    ) {
+        if self.halos.is_empty() {
+            return;
+        }
+        let _span = tracing::trace_span!("highlight_render").entered();
+
+        let halos = &self.halos;
+        let instance_buffer = self.instance_buffer_cache.get_or_insert_with(|| {
+            let instances: Vec<CircleConstraintInstance> =
+                halos.iter().map(CircleConstraintInstance::new).collect();
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("highlight renderer instance buffer"),
+                contents: bytemuck::cast_slice(&instances),
+                usage: wgpu::BufferUsage::VERTEX,
+            })
+        });
+
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("highlight renderer render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, context.camera_bind_group(), &[]);
+        render_pass.set_bind_group(1, self.color.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        let width = sc_desc.width as f32;
+        let height = sc_desc.height as f32;
+        render_pass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
+        render_pass.draw(0..self.vertex_count, 0..self.halos.len() as u32);
+    }
+}
@@ -1,24 +1,16 @@
+use super::context::RenderContext;
+use super::draw_order::{batch_range, DrawOrder};
 use crate::arena::{ArenaKey, Structure, Value};
+use crate::import::{ImportPolicy, ImportedImage};
 use crate::spatial_bound::SpatialBound;
-use crate::{camera::Camera, spatial_tree::SpatialTreeData};
+use crate::spatial_tree::SpatialTreeData;
+use crate::task::{Progress, Task, TaskStatus};
+use image::RgbaImage;
 use slotmap::SlotMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use wgpu::util::DeviceExt;
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct Uniforms {
-    view_proj: [[f32; 4]; 4],
-}
-
-impl Uniforms {
-    fn new(view_projection_matrix: cgmath::Matrix4<f32>) -> Self {
-        Self {
-            view_proj: view_projection_matrix.into(),
-        }
-    }
-}
-
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct Vertex {
@@ -79,9 +71,92 @@ impl Vertex {
     }
 }
 
+/// The most an image can be magnified while inspecting it, relative to fitting
+/// the whole image in its bounds.
+pub const MAX_ZOOM: f32 = 64.0;
+
+/// The color of the square drawn in place of a linked image until its pixels
+/// arrive.
+const PLACEHOLDER_COLOR: [u8; 4] = [96, 96, 96, 255];
+
+/// The part of an image that is displayed in its bounds, which can be moved
+/// around and magnified while the image is being inspected.
+///
+/// Positions are texture coordinates, which go from 0 at the left (or top) of
+/// the image to 1 at the right (or bottom).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageView {
+    /// The point of the image displayed in the middle of its bounds.
+    pub center: (f32, f32),
+    /// How many times larger the image is drawn than it would be if it fit
+    /// inside of its bounds. Always between 1 and [`MAX_ZOOM`].
+    pub zoom: f32,
+}
+
+impl Default for ImageView {
+    /// Displays the whole image.
+    fn default() -> Self {
+        Self {
+            center: (0.5, 0.5),
+            zoom: 1.0,
+        }
+    }
+}
+
+impl ImageView {
+    /// Moves the view by `dx` and `dy`, measured in widths (or heights) of the
+    /// part of the image being displayed. The view never leaves the image.
+    pub fn pan(self, dx: f32, dy: f32) -> Self {
+        Self {
+            center: (
+                self.center.0 + dx / self.zoom,
+                self.center.1 + dy / self.zoom,
+            ),
+            ..self
+        }
+        .clamped()
+    }
+
+    /// Magnifies the image `factor` times more than it is now, keeping the
+    /// same point in the middle.
+    pub fn zoom_by(self, factor: f32) -> Self {
+        self.with_zoom(self.zoom * factor)
+    }
+
+    pub fn with_zoom(self, zoom: f32) -> Self {
+        Self { zoom, ..self }.clamped()
+    }
+
+    fn clamped(self) -> Self {
+        let zoom = self.zoom.max(1.0).min(MAX_ZOOM);
+        let half = 0.5 / zoom;
+        let clamp = |center: f32| center.max(half).min(1.0 - half);
+        Self {
+            center: (clamp(self.center.0), clamp(self.center.1)),
+            zoom,
+        }
+    }
+
+    /// The texture coordinates of the top left corner of the part of the image
+    /// being displayed, followed by its width and height.
+    fn texture_transform(&self) -> [f32; 4] {
+        let size = 1.0 / self.zoom;
+        [
+            self.center.0 - 0.5 * size,
+            self.center.1 - 0.5 * size,
+            size,
+            size,
+        ]
+    }
+}
+
 struct BoundTextureInstances {
     diffuse_bind_group: wgpu::BindGroup,
-    raw_texture_instances: Vec<RawTextureInstance>,
+    /// The width and height of the texture, in pixels.
+    dimensions: (u32, u32),
+    /// The instances of the texture, sorted by their [`DrawOrder`] once the
+    /// `buffer_cache` has been built.
+    raw_texture_instances: Vec<(DrawOrder, RawTextureInstance)>,
     buffer_cache: Option<wgpu::Buffer>,
 }
 
@@ -90,39 +165,75 @@ struct TextureInstances {
 }
 
 impl BoundTextureInstances {
+    /// Uploads `image` as a texture bound with `layout`, ready to have
+    /// instances drawn with it.
+    fn new(
+        device: &wgpu::Device,
+        queue: &mut wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        image: &RgbaImage,
+    ) -> Self {
+        Self {
+            diffuse_bind_group: bind_texture(device, queue, layout, image),
+            dimensions: image.dimensions(),
+            buffer_cache: None,
+            raw_texture_instances: Vec::new(),
+        }
+    }
+
+    /// Sorts the instances by their [`DrawOrder`] and places them in a
+    /// buffer, in that order, unless this has already been done.
     fn instantiate_buffer_cache<'a, 'b>(
         buffer_cache: &'b mut Option<wgpu::Buffer>,
-        instances: &'b Vec<RawTextureInstance>,
+        instances: &'b mut Vec<(DrawOrder, RawTextureInstance)>,
         device: &'a wgpu::Device,
-    ) -> &'b wgpu::Buffer {
-        if buffer_cache.is_none() {
+    ) {
+        if buffer_cache.is_none() && !instances.is_empty() {
+            let _span =
+                tracing::debug_span!("image_build_instances", count = instances.len()).entered();
+            instances.sort_by_key(|&(order, _)| order);
+            let raw_instances: Vec<RawTextureInstance> =
+                instances.iter().map(|&(_, raw)| raw).collect();
             let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("image renderer instance buffer"),
-                contents: bytemuck::cast_slice(instances),
+                contents: bytemuck::cast_slice(&raw_instances),
                 usage: wgpu::BufferUsage::VERTEX,
             });
 
             *buffer_cache = Some(buffer);
         }
-
-        buffer_cache.as_ref().unwrap()
     }
 }
 
 pub struct ImageRenderer {
     bound: HashMap<ArenaKey, BoundTextureInstances>,
+    /// The images that are displayed with something other than the default
+    /// [`ImageView`].
+    views: HashMap<ArenaKey, ImageView>,
     unbound: HashMap<ArenaKey, TextureInstances>,
+    /// The linked images whose files couldn't be read, which aren't tried
+    /// again until [`ImageRenderer::compact`] is called.
+    missing: HashSet<ArenaKey>,
+    /// The linked images being read and decoded. See
+    /// [`ImageRenderer::prepare`].
+    decoding: HashMap<ArenaKey, Task<RgbaImage>>,
+    /// Drawn in place of the linked images being decoded. Uploaded the first
+    /// time it is needed.
+    placeholder: Option<BoundTextureInstances>,
+    /// See [`ImageRenderer::set_blocking`].
+    blocking: bool,
     vertex_buffer_data: Vec<Vertex>,
     vertex_buffer: wgpu::Buffer,
     texture_bind_group_layout: wgpu::BindGroupLayout,
-    uniform_buffer: wgpu::Buffer,
-    uniform_buffer_stale: bool,
-    uniform_bind_group: wgpu::BindGroup,
     render_pipeline: wgpu::RenderPipeline,
 }
 
 impl ImageRenderer {
-    pub fn new<'a>(device: &'a wgpu::Device, sc_desc: &'a wgpu::SwapChainDescriptor) -> Self {
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        context: &'a RenderContext,
+    ) -> Self {
         let vertex_buffer_data = Vertex::square();
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("image renderer vertex buffer"),
@@ -135,37 +246,6 @@ impl ImageRenderer {
         let fs_module =
             device.create_shader_module(&wgpu::include_spirv!("../shaders/build/image.frag.spv"));
 
-        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("image renderer uniform buffer"),
-            size: std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("image renderer uniform bind group layout"),
-            });
-
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-            label: Some("image renderer uniform bind group"),
-        });
-
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
@@ -196,7 +276,7 @@ impl ImageRenderer {
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("image renderer pipeline layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &uniform_bind_group_layout],
+                bind_group_layouts: &[&texture_bind_group_layout, context.camera_layout()],
                 push_constant_ranges: &[],
             });
 
@@ -235,18 +315,20 @@ impl ImageRenderer {
 
         Self {
             bound: HashMap::new(),
+            views: HashMap::new(),
             unbound: HashMap::new(),
+            missing: HashSet::new(),
+            decoding: HashMap::new(),
+            placeholder: None,
+            blocking: false,
             vertex_buffer_data,
             vertex_buffer,
             texture_bind_group_layout,
-            uniform_buffer_stale: true,
-            uniform_buffer,
-            uniform_bind_group,
             render_pipeline,
         }
     }
 
-    pub fn with_image<'a>(&mut self, spatial_tree_data: SpatialTreeData) {
+    pub fn with_image<'a>(&mut self, spatial_tree_data: SpatialTreeData, order: DrawOrder) {
         self.unbound
             .entry(spatial_tree_data.key)
             .or_insert(TextureInstances {
@@ -255,30 +337,223 @@ impl ImageRenderer {
             .instances
             .push(TextureInstance {
                 sphere: spatial_tree_data.bounds,
+                order,
             });
     }
 
-    pub fn resize(&mut self) {
-        self.uniform_buffer_stale = true;
+    /// Displays only part of the image bound to `key`, or the whole image if
+    /// `view` is [`None`]. Takes effect the next time the image is laid out.
+    pub fn set_view(&mut self, key: ArenaKey, view: Option<ImageView>) {
+        match view {
+            Some(view) => self.views.insert(key, view),
+            None => self.views.remove(&key),
+        };
     }
 
-    pub fn render<'a>(
+    /// The [`DrawOrder`]s of every image that will be drawn.
+    pub fn draw_orders<'a>(&'a self) -> impl Iterator<Item = DrawOrder> + 'a {
+        let unbound = self
+            .unbound
+            .values()
+            .flat_map(|instances| instances.instances.iter().map(|instance| instance.order));
+        let bound = self
+            .bound
+            .values()
+            .chain(self.placeholder.as_ref())
+            .flat_map(|instances| {
+                instances
+                    .raw_texture_instances
+                    .iter()
+                    .map(|&(order, _)| order)
+            });
+        unbound.chain(bound)
+    }
+
+    /// The width and height, in pixels, of the texture uploaded for the image
+    /// bound to `key`, if it has been uploaded.
+    pub fn dimensions(&self, key: ArenaKey) -> Option<(u32, u32)> {
+        self.bound.get(&key).map(|bound| bound.dimensions)
+    }
+
+    /// Uploads every image that will be drawn this frame, unless it was
+    /// already uploaded. Must be called before [`ImageRenderer::render`].
+    ///
+    /// Linked images (see [`Structure::ImageRef`]) are only read from disk
+    /// while some part of them is inside of the region the camera can see,
+    /// whose half extents are `visible_half_extent` (see
+    /// [`Camera::visible_half_extent`]). They are decoded on a [`Task`], with
+    /// a placeholder drawn in their place until their pixels arrive, and their
+    /// textures are released as soon as they leave that region, so that only
+    /// the ones on screen take up memory.
+    ///
+    /// [`Camera::visible_half_extent`]: crate::camera::Camera::visible_half_extent
+    pub fn prepare<'a>(
         &mut self,
         device: &'a wgpu::Device,
         queue: &'a mut wgpu::Queue,
-        command_encoder: &'a mut wgpu::CommandEncoder,
-        texture_view: &'a wgpu::TextureView,
-        camera: &'a mut Camera,
         store: &'a SlotMap<ArenaKey, Value>,
+        visible_half_extent: (f32, f32),
     ) {
-        if self.uniform_buffer_stale {
-            queue.write_buffer(
-                &self.uniform_buffer,
-                0,
-                bytemuck::cast_slice(&[Uniforms::new(*camera.view_projection_matrix())]),
+        let layout = &self.texture_bind_group_layout;
+        let mut decoded = vec![];
+        let missing = &mut self.missing;
+        self.decoding.retain(|&key, task| match task.poll() {
+            TaskStatus::Running => true,
+            TaskStatus::Finished(image) => {
+                decoded.push((key, image));
+                false
+            }
+            TaskStatus::Cancelled => {
+                missing.insert(key);
+                false
+            }
+        });
+        for (key, image) in decoded {
+            let bound = BoundTextureInstances::new(device, queue, layout, &image);
+            self.bound.insert(key, bound);
+        }
+
+        if self.placeholder.is_none() {
+            let pixel = RgbaImage::from_pixel(1, 1, image::Rgba(PLACEHOLDER_COLOR));
+            self.placeholder = Some(BoundTextureInstances::new(device, queue, layout, &pixel));
+        }
+        let placeholder = self.placeholder.as_mut().unwrap();
+        placeholder.raw_texture_instances.clear();
+        placeholder.buffer_cache = None;
+        let mut drawn = HashSet::new();
+        let mut pending = HashMap::new();
+        for (image_key, mut unbound_image_instance) in std::mem::take(&mut self.unbound) {
+            let view = self.views.get(&image_key).copied().unwrap_or_default();
+            let structure = &store.get(image_key).unwrap().structure;
+            if let Structure::ImageRef(_) = structure {
+                unbound_image_instance
+                    .instances
+                    .retain(|instance| instance.sphere.overlaps(visible_half_extent));
+                if unbound_image_instance.instances.is_empty() {
+                    continue;
+                }
+                drawn.insert(image_key);
+            }
+            if !self.bound.contains_key(&image_key) {
+                let linked;
+                let image: &RgbaImage = match structure {
+                    Structure::Image(i) => i,
+                    Structure::ImageRef(_) if self.missing.contains(&image_key) => continue,
+                    Structure::ImageRef(path) if self.blocking => {
+                        let _span = tracing::debug_span!("image_read_linked").entered();
+                        match load_linked(path, &Progress::unobserved()) {
+                            Some(image) => {
+                                linked = image;
+                                &linked
+                            }
+                            None => {
+                                self.missing.insert(image_key);
+                                continue;
+                            }
+                        }
+                    }
+                    Structure::ImageRef(path) => {
+                        let path = path.clone();
+                        self.decoding.entry(image_key).or_insert_with(|| {
+                            Task::spawn("decode linked image", move |progress| {
+                                load_linked(&path, progress)
+                            })
+                        });
+                        placeholder.raw_texture_instances.extend(
+                            unbound_image_instance
+                                .instances
+                                .iter()
+                                .map(|i| (i.order, i.to_raw(1.0, ImageView::default()))),
+                        );
+                        pending.insert(image_key, unbound_image_instance);
+                        continue;
+                    }
+                    Structure::Custom(custom) => custom.thumbnail.as_ref().unwrap(),
+                    _ => panic!(),
+                };
+                let bound = BoundTextureInstances::new(device, queue, layout, image);
+                self.bound.insert(image_key, bound);
+            }
+            let bound = self.bound.get_mut(&image_key).unwrap();
+            let aspect_ratio = bound.dimensions.0 as f32 / bound.dimensions.1 as f32;
+            bound.buffer_cache = None;
+            bound.raw_texture_instances.extend(
+                unbound_image_instance
+                    .instances
+                    .drain(..)
+                    .map(|i| (i.order, i.to_raw(aspect_ratio, view))),
             );
-            self.uniform_buffer_stale = false;
         }
+        // Linked images still being decoded are tried again next frame.
+        self.unbound = pending;
+
+        self.decoding.retain(|key, task| {
+            if !drawn.contains(key) {
+                task.cancel();
+            }
+            drawn.contains(key)
+        });
+        self.bound.retain(|&key, instances| {
+            let linked = matches!(
+                store.get(key).map(|value| &value.structure),
+                Some(Structure::ImageRef(_))
+            );
+            !linked || !instances.raw_texture_instances.is_empty()
+        });
+
+        for bound_texture_instances in self.bound.values_mut().chain(self.placeholder.as_mut()) {
+            let BoundTextureInstances {
+                buffer_cache,
+                raw_texture_instances: instances,
+                ..
+            } = bound_texture_instances;
+            BoundTextureInstances::instantiate_buffer_cache(buffer_cache, instances, device);
+        }
+    }
+
+    /// Returns true if any linked images are being decoded, in which case the
+    /// window should keep redrawing until their pixels arrive.
+    pub fn is_decoding(&self) -> bool {
+        !self.decoding.is_empty()
+    }
+
+    /// Makes linked images be read and decoded while preparing the frame that
+    /// first draws them, rather than on a [`Task`], like for captures (see
+    /// [`crate::render::capture`]), which are only drawn once.
+    pub fn set_blocking(&mut self, blocking: bool) {
+        self.blocking = blocking;
+    }
+
+    /// Draws the images whose order is `order`.
+    pub fn render<'a>(
+        &mut self,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        texture_view: &'a wgpu::TextureView,
+        context: &'a RenderContext,
+        order: DrawOrder,
+    ) {
+        let batches: Vec<_> = self
+            .bound
+            .values()
+            .chain(self.placeholder.as_ref())
+            .filter_map(|bound_texture_instances| {
+                let range = batch_range(
+                    &bound_texture_instances.raw_texture_instances,
+                    order,
+                    |&(order, _)| order,
+                );
+                let buffer = bound_texture_instances.buffer_cache.as_ref()?;
+                if range.is_empty() {
+                    None
+                } else {
+                    Some((&bound_texture_instances.diffuse_bind_group, buffer, range))
+                }
+            })
+            .collect();
+        if batches.is_empty() {
+            return;
+        }
+        let _span = tracing::trace_span!("image_render").entered();
 
         let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("image renderer render pass"),
@@ -294,120 +569,29 @@ impl ImageRenderer {
         });
 
         render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, context.camera_bind_group(), &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
 
-        let texture_bind_group_layout = &self.texture_bind_group_layout;
-
-        for (image_key, mut unbound_image_instance) in self.unbound.drain() {
-            let image = match &store.get(image_key).unwrap().structure {
-                Structure::Image(i) => i,
-                _ => panic!(),
-            };
-            let dimensions = image.dimensions();
-            let aspect_ratio = dimensions.0 as f32 / dimensions.1 as f32;
-
-            let size = {
-                wgpu::Extent3d {
-                    width: dimensions.0,
-                    height: dimensions.1,
-                    depth: 1,
-                }
-            };
-
-            let texture = device.create_texture(&wgpu::TextureDescriptor {
-                label: None,
-                size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
-            });
-
-            queue.write_texture(
-                wgpu::TextureCopyView {
-                    texture: &texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                },
-                image.as_ref(),
-                wgpu::TextureDataLayout {
-                    offset: 0,
-                    bytes_per_row: 4 * dimensions.0,
-                    rows_per_image: dimensions.1,
-                },
-                size,
+        for (diffuse_bind_group, buffer, range) in batches {
+            render_pass.set_bind_group(0, diffuse_bind_group, &[]);
+            render_pass.set_vertex_buffer(1, buffer.slice(..));
+            render_pass.draw(
+                0..self.vertex_buffer_data.len() as _,
+                range.start as u32..range.end as u32,
             );
-
-            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-            let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Linear,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                ..Default::default()
-            });
-
-            let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: texture_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&sampler),
-                    },
-                ],
-                label: Some("image renderer diffuse bind group"),
-            });
-            self.bound
-                .entry(image_key)
-                .or_insert_with(|| BoundTextureInstances {
-                    diffuse_bind_group,
-                    buffer_cache: None,
-                    raw_texture_instances: Vec::with_capacity(
-                        unbound_image_instance.instances.len(),
-                    ),
-                })
-                .raw_texture_instances
-                .append(
-                    &mut unbound_image_instance
-                        .instances
-                        .drain(..)
-                        .map(|i| i.to_raw(aspect_ratio))
-                        .collect(),
-                );
         }
+    }
 
-        for (_, bound_texture_instances) in &mut self.bound {
-            let BoundTextureInstances {
-                diffuse_bind_group,
-                buffer_cache,
-                raw_texture_instances: instances,
-            } = bound_texture_instances;
-            if instances.len() > 0 {
-                render_pass.set_bind_group(0, diffuse_bind_group, &[]);
-                render_pass.set_vertex_buffer(
-                    1,
-                    BoundTextureInstances::instantiate_buffer_cache(
-                        buffer_cache,
-                        instances,
-                        device,
-                    )
-                    .slice(..),
-                );
-                render_pass.draw(
-                    0..self.vertex_buffer_data.len() as _,
-                    0..instances.len() as _,
-                );
-            }
-        }
+    /// Releases the textures of images that aren't being drawn, returning how
+    /// many were released. They are uploaded again if they are drawn later,
+    /// and linked images that couldn't be read are tried again.
+    pub fn compact(&mut self) -> usize {
+        let before = self.bound.len();
+        self.bound
+            .retain(|_, instances| !instances.raw_texture_instances.is_empty());
+        self.views.shrink_to_fit();
+        self.missing.clear();
+        before - self.bound.len()
     }
 
     pub fn invalidate(&mut self) {
@@ -419,13 +603,91 @@ impl ImageRenderer {
     }
 }
 
+/// Reads and decodes the linked image stored at `path`, shrinking it as images
+/// are when they are imported (see [`ImportPolicy`]).
+fn load_linked(path: &Path, progress: &Progress) -> Option<RgbaImage> {
+    let image = ImportedImage::load(path, ImportPolicy::default(), progress);
+    if image.is_none() && !progress.is_cancelled() {
+        tracing::warn!("couldn't read the linked image {}", path.display());
+    }
+    image.map(|imported| imported.image)
+}
+
+/// Uploads `image` as a texture and binds it with `layout`.
+fn bind_texture(
+    device: &wgpu::Device,
+    queue: &mut wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    image: &RgbaImage,
+) -> wgpu::BindGroup {
+    let dimensions = image.dimensions();
+    let size = wgpu::Extent3d {
+        width: dimensions.0,
+        height: dimensions.1,
+        depth: 1,
+    };
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+    });
+
+    queue.write_texture(
+        wgpu::TextureCopyView {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        image.as_ref(),
+        wgpu::TextureDataLayout {
+            offset: 0,
+            bytes_per_row: 4 * dimensions.0,
+            rows_per_image: dimensions.1,
+        },
+        size,
+    );
+
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Nearest,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+        ],
+        label: Some("image renderer diffuse bind group"),
+    })
+}
+
 struct TextureInstance {
     sphere: SpatialBound,
+    order: DrawOrder,
 }
 
 impl TextureInstance {
-    fn to_raw(&self, aspect_ratio: f32) -> RawTextureInstance {
-        RawTextureInstance::new(&self.sphere, aspect_ratio)
+    fn to_raw(&self, aspect_ratio: f32, view: ImageView) -> RawTextureInstance {
+        RawTextureInstance::new(&self.sphere, aspect_ratio, view)
     }
 }
 
@@ -433,16 +695,19 @@ impl TextureInstance {
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct RawTextureInstance {
     model: [[f32; 4]; 4],
+    /// See [`ImageView::texture_transform`].
+    texture_transform: [f32; 4],
 }
 
 impl RawTextureInstance {
-    pub fn new(bound: &SpatialBound, aspect_ratio: f32) -> Self {
+    pub fn new(bound: &SpatialBound, aspect_ratio: f32, view: ImageView) -> Self {
         let self_bound = SpatialBound::cuboid_inside_bound(bound, aspect_ratio);
         let scale =
             cgmath::Matrix4::from_nonuniform_scale(self_bound.width(), self_bound.height(), 1.0);
         let translation = cgmath::Matrix4::from_translation(self_bound.center);
         Self {
             model: (translation * scale).into(),
+            texture_transform: view.texture_transform(),
         }
     }
 
@@ -472,7 +737,28 @@ impl RawTextureInstance {
                     shader_location: 5,
                     format: wgpu::VertexFormat::Float4,
                 },
+                wgpu::VertexAttribute {
+                    offset: size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float4,
+                },
             ],
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn views_stay_inside_the_image() {
+        let view = ImageView::default().pan(1.0, -1.0);
+        assert_eq!(ImageView::default(), view);
+        let view = ImageView::default().zoom_by(4.0).pan(10.0, -10.0);
+        assert_eq!((0.875, 0.125), view.center);
+        assert_eq!([0.75, 0.0, 0.25, 0.25], view.texture_transform());
+        assert_eq!(MAX_ZOOM, view.zoom_by(1000.0).zoom);
+        assert_eq!(1.0, view.with_zoom(0.5).zoom);
+    }
+}
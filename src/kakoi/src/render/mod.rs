@@ -1,5 +1,15 @@
+pub mod capture;
 pub mod circle;
+pub mod context;
+pub mod draw_order;
+pub mod ghost;
+pub mod heat;
+pub mod highlight;
 pub mod image;
+pub mod progress;
 pub mod renderer;
+pub mod selection;
+pub mod sharing;
 pub mod text;
-pub mod rectangle;
\ No newline at end of file
+pub mod underline;
+pub mod rectangle;
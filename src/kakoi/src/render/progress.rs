@@ -0,0 +1,177 @@
+use super::circle::{CircleConstraintInstance, Vertex};
+use super::context::{ColorUniform, RenderContext};
+use crate::sphere::Sphere;
+use wgpu::util::DeviceExt;
+
+/// Height of the progress bar, in the coordinates described in
+/// [`crate::spatial_tree`].
+const HEIGHT: f32 = 0.02;
+
+/// The color of the progress bar, in linear RGB.
+const COLOR: [f32; 4] = [0.2, 0.45, 1.0, 1.0];
+
+/// The number of vertices in the two triangles making up the bar.
+const VERTEX_COUNT: usize = 6;
+
+/// Returns the triangles of a bar along the top of the screen that is
+/// `progress` of the way across.
+fn make_bar(progress: f32) -> [Vertex; VERTEX_COUNT] {
+    let (left, right) = (-1.0, -1.0 + 2.0 * progress);
+    let (bottom, top) = (1.0 - HEIGHT, 1.0);
+    let vertex = |x, y| Vertex {
+        position: [x, y, 0.0],
+    };
+    [
+        vertex(left, top),
+        vertex(left, bottom),
+        vertex(right, bottom),
+        vertex(left, top),
+        vertex(right, bottom),
+        vertex(right, top),
+    ]
+}
+
+/// Draws a bar along the top of the screen showing the progress of the
+/// running [`Task`](crate::task::Task)s.
+///
+/// Like the selection ring, the bar is drawn on top of everything else and
+/// is filled in with the selection fragment shader.
+pub struct ProgressRenderer {
+    progress: Option<f32>,
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    color: ColorUniform,
+}
+
+impl ProgressRenderer {
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        context: &'a RenderContext,
+    ) -> Self {
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("progress renderer vertex buffer"),
+            size: (VERTEX_COUNT * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // The vertices of the bar are already in place, so it is drawn with a
+        // single identity instance, like rectangles.
+        let identity = CircleConstraintInstance::new(&Sphere {
+            center: (0.0, 0.0, 0.0).into(),
+            radius: 1.0,
+        });
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("progress renderer instance buffer"),
+            contents: bytemuck::cast_slice(&[identity]),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.vert.spv"));
+        let fs_module = device
+            .create_shader_module(&wgpu::include_spirv!("../shaders/build/selection.frag.spv"));
+
+        let color = ColorUniform::new(device, context, COLOR, "progress renderer color uniform");
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("progress renderer pipeline layout"),
+                bind_group_layouts: &[context.camera_layout(), context.color_layout()],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("progress renderer pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc(), CircleConstraintInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc_desc.format,
+                    alpha_blend: wgpu::BlendState::REPLACE,
+                    color_blend: wgpu::BlendState::REPLACE,
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            progress: None,
+            render_pipeline,
+            vertex_buffer,
+            instance_buffer,
+            color,
+        }
+    }
+
+    /// Shows the bar filled `progress` of the way, or hides it if there is
+    /// nothing running.
+    pub fn set_progress(&mut self, progress: Option<f32>) {
+        self.progress = progress;
+    }
+
+    pub fn render<'a>(
+        &mut self,
+        queue: &'a mut wgpu::Queue,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        texture_view: &'a wgpu::TextureView,
+        context: &'a RenderContext,
+    ) {
+        let progress = match self.progress {
+            Some(progress) => progress,
+            None => return,
+        };
+        let _span = tracing::trace_span!("progress_render").entered();
+
+        queue.write_buffer(
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&make_bar(progress)),
+        );
+
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("progress renderer render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, context.camera_bind_group(), &[]);
+        render_pass.set_bind_group(1, self.color.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        let width = sc_desc.width as f32;
+        let height = sc_desc.height as f32;
+        render_pass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
+        render_pass.draw(0..VERTEX_COUNT as u32, 0..1);
+    }
+}
@@ -1,20 +1,15 @@
-use crate::{sampling_config::SamplingConfig, square_cuboid::SquareCuboid};
+use super::circle::CircleConstraintInstance;
+use super::context::RenderContext;
+use super::draw_order::{batch_range, DrawOrder};
+use crate::sphere::Sphere;
+use crate::square_cuboid::SquareCuboid;
+use wgpu::util::DeviceExt;
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct Uniforms {
-    view_proj: [[f32; 4]; 4],
-}
-
-impl Uniforms {
-    fn new(view_projection_matrix: cgmath::Matrix4<f32>) -> Self {
-        Self {
-            view_proj: view_projection_matrix.into(),
-        }
-    }
-}
+/// Thickness of the border of a rectangle, as a fraction of its shortest side.
+pub const THICKNESS: f32 = 0.01;
 
-pub const THICKNESS: f32 = 0.02;
+/// The number of vertices created by `Vertex::make_rectangle`.
+const VERTICES_PER_RECTANGLE: usize = 24;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -35,15 +30,21 @@ impl Vertex {
         }
     }
 
+    /// Creates the triangles making up the border of `square_cuboid`.
+    ///
+    /// Unlike circles, rectangles aren't drawn by instancing a single mesh.
+    /// Scaling a mesh to the right width and height would also stretch its
+    /// border, so the vertices of each rectangle are placed on screen directly.
     fn make_rectangle(square_cuboid: &SquareCuboid) -> Vec<Vertex> {
         let (width, height) = square_cuboid.dimensions_2d();
+        let thickness = THICKNESS * width.min(height);
 
-        let x11 = 0.5 * width - THICKNESS;
-        let y11 = 0.5 * height - THICKNESS;
-        let x12 = x11 + THICKNESS;
+        let x11 = 0.5 * width - thickness;
+        let y11 = 0.5 * height - thickness;
+        let x12 = x11 + thickness;
         let y12 = y11;
         let x13 = x12;
-        let y13 = y11 + THICKNESS;
+        let y13 = y11 + thickness;
 
         let t11 = vec![(x13, y13), (-x13, y13), (-x12, y12)];
         let t12 = vec![(x13, y13), (-x12, y12), (x12, y12)];
@@ -62,34 +63,196 @@ impl Vertex {
         let t21f = flip_horizontal(&t21);
         let t22f = flip_horizontal(&t22);
 
-        let mut result = Vec::with_capacity(24);
+        let mut result = Vec::with_capacity(VERTICES_PER_RECTANGLE);
         for mut v in [t11, t12, t21, t22, t11f, t12f, t21f, t22f] {
             result.append(&mut v);
         }
 
+        let center = square_cuboid.center;
         result
             .into_iter()
             .map(|(x, y)| Vertex {
-                position: [x, y, 0.0],
+                position: [x + center.x, y + center.y, center.z],
             })
             .collect()
     }
 }
 
+/// Draws the borders of [`SquareCuboid`]s.
+///
+/// This reuses the circle shaders, which draw any mesh transformed by a
+/// per-instance model matrix. Since the vertices of each rectangle are already
+/// in place (see `Vertex::make_rectangle`), a single identity instance is
+/// drawn.
+///
+/// Rectangles are drawn after circles onto the same frame, so this doesn't
+/// clear the screen. Their edges are axis-aligned, so they are not
+/// multisampled. Like strings and images, they are drawn in batches according
+/// to their [`DrawOrder`] (see [`crate::render::draw_order`]).
 pub struct RectangleRenderer {
-    constraints: Vec<SquareCuboid>,
-    instances_cache: Option<wgpu::Buffer>,
+    constraints: Vec<(DrawOrder, SquareCuboid)>,
+    vertex_buffer_cache: Option<wgpu::Buffer>,
+    instance_buffer: wgpu::Buffer,
     render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    uniform_buffer: wgpu::Buffer,
-    uniform_buffer_stale: bool,
-    uniform_bind_group: wgpu::BindGroup,
-    vertex_buffer_data: Vec<Vertex>,
-    sampling_config: SamplingConfig,
 }
 
 impl RectangleRenderer {
-    pub fn new<'a>(device: &'a wgpu::Device, sc_desc: &'a wgpu::SwapChainDescriptor) -> Self {
-        todo!()
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        context: &'a RenderContext,
+    ) -> Self {
+        // The vertices of each rectangle are already in place, so they are
+        // drawn with a single identity instance.
+        let identity = CircleConstraintInstance::new(&Sphere {
+            center: (0.0, 0.0, 0.0).into(),
+            radius: 1.0,
+        });
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("rectangle renderer instance buffer"),
+            contents: bytemuck::cast_slice(&[identity]),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.vert.spv"));
+        let fs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.frag.spv"));
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("rectangle renderer pipeline layout"),
+                bind_group_layouts: &[context.camera_layout()],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("rectangle renderer pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc(), CircleConstraintInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc_desc.format,
+                    alpha_blend: wgpu::BlendState::REPLACE,
+                    color_blend: wgpu::BlendState::REPLACE,
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            constraints: Vec::new(),
+            vertex_buffer_cache: None,
+            instance_buffer,
+            render_pipeline,
+        }
+    }
+
+    pub fn with_instance(&mut self, square_cuboid: SquareCuboid, order: DrawOrder) {
+        self.constraints.push((order, square_cuboid));
+    }
+
+    /// The [`DrawOrder`]s of every rectangle that will be drawn.
+    pub fn draw_orders<'a>(&'a self) -> impl Iterator<Item = DrawOrder> + 'a {
+        self.constraints.iter().map(|&(order, _)| order)
+    }
+
+    /// Uploads everything needed to draw this frame. Must be called before
+    /// [`RectangleRenderer::render`].
+    pub fn prepare<'a>(&mut self, device: &'a wgpu::Device) {
+        Self::build_vertices(&mut self.vertex_buffer_cache, &mut self.constraints, device);
+    }
+
+    /// Draws the rectangles whose order is `order`.
+    pub fn render<'a>(
+        &mut self,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        texture_view: &'a wgpu::TextureView,
+        context: &'a RenderContext,
+        order: DrawOrder,
+    ) {
+        let range = batch_range(&self.constraints, order, |&(order, _)| order);
+        if range.is_empty() {
+            return;
+        }
+        let _span = tracing::trace_span!("rectangle_render").entered();
+
+        let vertex_buffer = match &self.vertex_buffer_cache {
+            Some(vertex_buffer) => vertex_buffer,
+            None => return,
+        };
+
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("rectangle renderer render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, context.camera_bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        let width = sc_desc.width as f32;
+        let height = sc_desc.height as f32;
+        render_pass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
+        let start = (range.start * VERTICES_PER_RECTANGLE) as u32;
+        let end = (range.end * VERTICES_PER_RECTANGLE) as u32;
+        render_pass.draw(start..end, 0..1);
+    }
+
+    pub fn invalidate(&mut self) {
+        self.constraints = Vec::new();
+        self.vertex_buffer_cache = None;
+    }
+
+    /// Sorts the rectangles by their [`DrawOrder`] and places their vertices
+    /// in a buffer, in that order, unless this has already been done.
+    fn build_vertices<'a, 'b>(
+        vertex_buffer_cache: &'b mut Option<wgpu::Buffer>,
+        constraints: &'b mut Vec<(DrawOrder, SquareCuboid)>,
+        device: &'a wgpu::Device,
+    ) {
+        if vertex_buffer_cache.is_none() && !constraints.is_empty() {
+            let _span = tracing::debug_span!("rectangle_build_vertices", count = constraints.len())
+                .entered();
+            constraints.sort_by_key(|&(order, _)| order);
+            let vertices: Vec<Vertex> = constraints
+                .iter()
+                .flat_map(|(_, square_cuboid)| Vertex::make_rectangle(square_cuboid))
+                .collect();
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("rectangle renderer vertex buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsage::VERTEX,
+            });
+            *vertex_buffer_cache = Some(buffer);
+        }
     }
 }
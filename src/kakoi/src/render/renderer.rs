@@ -1,28 +1,438 @@
-use super::{circle::CircleRenderer, image::ImageRenderer, text::TextRenderer};
+use super::{
+    capture::{CaptureRequest, CaptureTarget},
+    circle::{CircleRenderer, RingThickness},
+    context::RenderContext,
+    draw_order,
+    ghost::GhostRenderer,
+    heat::HeatRenderer,
+    highlight::HighlightRenderer,
+    image::{ImageRenderer, ImageView},
+    progress::ProgressRenderer,
+    rectangle::RectangleRenderer,
+    selection::SelectionRenderer,
+    sharing::SharingRenderer,
+    text::TextRenderer,
+    underline::UnderlineRenderer,
+};
+use crate::blob::Blob;
 use crate::camera::Camera;
-use crate::spatial_tree::SpatialTree;
+use crate::content_hash::ContentHash;
+use crate::cost;
+use crate::crash;
+use crate::dedup::{self, Candidate, Duplicates};
+use crate::explore::Exploration;
+use crate::export::{self, OutlineFormat};
+use crate::geometry;
+use crate::grouping;
+use crate::history::History;
+use crate::html;
+use crate::import::{self, ImportedImage};
+use crate::json;
+use crate::link::DeepLink;
+use crate::overlay::{Layer, LayerKind, OverlayStack};
+use crate::perform::{self, Outcome};
+use crate::power::PowerState;
+use crate::query::{self, Query};
+use crate::rewrite;
+use crate::sample::Sample;
+use crate::search::{self, SearchIndex, Snapshot};
+use crate::spatial_bound::SpatialBound;
+use crate::spatial_tree::{self, LayoutStrategy, SpatialTree};
+use crate::string_class::{self, StringClass};
+use crate::task::{Task, TaskStatus};
+use crate::text_import::{self, ImportedText};
+use crate::tutorial;
+use crate::window::{Options, WindowRequest, UI_SCALE_RANGE};
 use crate::{
-    arena::{Arena, ArenaKey},
-    input_manager::{CompleteAction, InputManager},
+    arena::{
+        self, tag_name, Arena, ArenaKey, Pruning, Resolution, Structure, UsageOrder,
+        INBOX_REGISTER, MAX_VERSIONED_MEMBERS, QUERIES_REGISTER, STARRED_REGISTER, TAGS_REGISTER,
+    },
+    input_manager::{CompleteAction, InputManager, InputMode},
 };
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+/// Draws every value laid out in a [`SpatialTree`] onto `texture_view`, in
+/// [`DrawOrder`](draw_order::DrawOrder). The renderers must have been given
+/// their instances by [`SpatialTree::new`] or [`SpatialTree::rebuild`].
+///
+/// This is where the view-projection matrix of `camera` is uploaded for the
+/// frame, so anything drawn with `context` afterwards shares it.
+fn draw_values<'a>(
+    device: &'a wgpu::Device,
+    queue: &'a mut wgpu::Queue,
+    sc_desc: &'a wgpu::SwapChainDescriptor,
+    command_encoder: &'a mut wgpu::CommandEncoder,
+    texture_view: &'a wgpu::TextureView,
+    camera: &'a mut Camera,
+    context: &'a RenderContext,
+    store: &'a Arena,
+    circle_renderer: &'a mut CircleRenderer,
+    rectangle_renderer: &'a mut RectangleRenderer,
+    text_renderer: &'a mut TextRenderer,
+    underline_renderer: &'a mut UnderlineRenderer,
+    image_renderer: &'a mut ImageRenderer,
+) {
+    context.write_camera(queue, camera);
+    // Circles clear the frame, and are always drawn underneath everything
+    // else. See the documentation of the draw_order module.
+    circle_renderer.render(device, sc_desc, command_encoder, texture_view, context);
+    rectangle_renderer.prepare(device);
+    text_renderer.prepare(&store.slot_map, sc_desc, camera);
+    underline_renderer.prepare(device, text_renderer.underlines());
+    image_renderer.prepare(device, queue, &store.slot_map, camera.visible_half_extent());
+    let batches = draw_order::batches(
+        rectangle_renderer
+            .draw_orders()
+            .chain(text_renderer.draw_orders())
+            .chain(image_renderer.draw_orders()),
+    );
+    for order in batches {
+        rectangle_renderer.render(sc_desc, command_encoder, texture_view, context, order);
+        text_renderer.render(
+            &store.slot_map,
+            device,
+            command_encoder,
+            texture_view,
+            order,
+        );
+        underline_renderer.render(sc_desc, command_encoder, texture_view, context, order);
+        image_renderer.render(command_encoder, texture_view, context, order);
+    }
+    text_renderer.finish();
+}
+
+/// How many times larger each step of [`CompleteAction::InspectZoom`] makes
+/// the inspected image.
+const INSPECT_ZOOM_STEP: f32 = 1.5;
+
+/// How many times larger each step of [`CompleteAction::ScaleUi`] makes the
+/// UI scale.
+const UI_SCALE_STEP: f32 = 1.25;
+
+/// How far each step of [`CompleteAction::InspectPan`] moves the inspected
+/// image, as a fraction of the part of it that is displayed.
+const INSPECT_PAN_STEP: f32 = 0.25;
+
+/// How long it takes a set arranged with [`LayoutStrategy::Zoomed`] to turn
+/// from one enlarged member to the next, in seconds.
+const CAROUSEL_DURATION: f32 = 0.25;
+
+/// How many pixels of scrolling on a touchpad count as scrolling one line
+/// with a mouse wheel.
+const PIXELS_PER_LINE: f32 = 40.0;
+
+/// How many pixels the mouse has to move with its button held down before a
+/// click becomes a drag.
+const DRAG_THRESHOLD: f32 = 6.0;
+
+/// How many times more values the arena has to hold than it did after garbage
+/// was last collected before it is collected again, when it is collected
+/// automatically. See [`Options::collect_garbage`].
+const GARBAGE_GROWTH: usize = 2;
+
+/// A set arranged with [`LayoutStrategy::Zoomed`] turning from one carousel
+/// position (see [`Arena::carousel_position`]) to another.
+#[derive(Clone, Copy)]
+struct CarouselAnimation {
+    set: ArenaKey,
+    from: f32,
+    to: f32,
+    started: Instant,
+}
+
+/// A value being dragged with the mouse. See [`CompleteAction::Drop`].
+#[derive(Clone, Copy)]
+struct Drag {
+    /// The position of the dragged value among the values that can be
+    /// selected in the topmost layer.
+    index: usize,
+    /// Where the mouse button was pressed, in pixels.
+    start: (f32, f32),
+    /// Whether the mouse has moved far enough to be dragging rather than
+    /// clicking.
+    dragging: bool,
+    /// The position of the value the dragged value would be dropped onto, if
+    /// it were dropped now.
+    onto: Option<usize>,
+}
+
+/// A change that [`CompleteAction::Undo`] puts back (see [`crate::history`]
+/// for what isn't recorded).
+#[derive(Clone)]
+enum Undo {
+    /// The set whose members were replaced (see [`Renderer::replace_set`]),
+    /// alongside a new set holding the members it had before.
+    ReplaceSet(ArenaKey, ArenaKey),
+    /// The set or list whose strings were rewritten (see
+    /// [`Renderer::rewrite_strings`]), alongside the previous contents of the
+    /// strings that changed.
+    Rewrite(ArenaKey, Vec<(ArenaKey, String)>),
+    /// The set whose members changed (see [`Renderer::record_changes`]),
+    /// alongside the members to remove from it and the members to insert
+    /// back into it.
+    Members(ArenaKey, Vec<ArenaKey>, Vec<ArenaKey>),
+    /// The list whose elements changed (see [`Renderer::record_changes`]),
+    /// alongside the elements it had before.
+    Elements(ArenaKey, Vec<ArenaKey>),
+    /// The (non-local) register that was bound (see
+    /// [`Renderer::record_changes`]), alongside what it was bound to before,
+    /// if anything.
+    Register(String, Option<ArenaKey>),
+    /// The map whose entries changed (see [`Renderer::record_changes`]),
+    /// alongside what each key that changed was paired with before, if
+    /// anything.
+    Map(ArenaKey, Vec<(ArenaKey, Option<ArenaKey>)>),
+    /// The value that was locked or unlocked, alongside whether it was
+    /// locked directly before.
+    Lock(ArenaKey, bool),
+    /// The string or set an earlier version was restored into, alongside
+    /// that version, which holds what it replaced (see
+    /// [`Arena::restore_version`]).
+    Version(ArenaKey, ArenaKey),
+}
+
+impl Undo {
+    /// The set, list or map that undoing this changes, if it changes one
+    /// rather than a register or a lock.
+    fn container(&self) -> Option<ArenaKey> {
+        match self {
+            Undo::ReplaceSet(set, _) => Some(*set),
+            Undo::Rewrite(container, _) => Some(*container),
+            Undo::Members(set, _, _) => Some(*set),
+            Undo::Elements(list, _) => Some(*list),
+            Undo::Register(..) | Undo::Lock(..) => None,
+            Undo::Map(map, _) => Some(*map),
+            Undo::Version(key, _) => Some(*key),
+        }
+    }
+
+    /// Every value this refers to, which has to be kept around so that it
+    /// can be put back.
+    fn keys(&self) -> Vec<ArenaKey> {
+        match self {
+            Undo::ReplaceSet(set, old) => vec![*set, *old],
+            Undo::Rewrite(container, previous) => std::iter::once(*container)
+                .chain(previous.iter().map(|&(key, _)| key))
+                .collect(),
+            Undo::Members(set, remove, insert) => std::iter::once(*set)
+                .chain(remove.iter().copied())
+                .chain(insert.iter().copied())
+                .collect(),
+            Undo::Elements(list, elements) => std::iter::once(*list)
+                .chain(elements.iter().copied())
+                .collect(),
+            Undo::Register(_, value) => value.iter().copied().collect(),
+            Undo::Map(map, previous) => std::iter::once(*map)
+                .chain(
+                    previous
+                        .iter()
+                        .flat_map(|&(key, value)| std::iter::once(key).chain(value)),
+                )
+                .collect(),
+            Undo::Lock(key, _) => vec![*key],
+            Undo::Version(key, version) => vec![*key, *version],
+        }
+    }
+}
+
+/// What the containers and registers an action changes held before it was
+/// performed, so that [`Renderer::record_changes`] can record how they
+/// changed.
+#[derive(Default)]
+struct Before {
+    sets: Vec<(ArenaKey, HashSet<ArenaKey>)>,
+    lists: Vec<(ArenaKey, Vec<ArenaKey>)>,
+    maps: Vec<(ArenaKey, HashMap<ArenaKey, ArenaKey>)>,
+    registers: Vec<(String, Option<ArenaKey>)>,
+}
+
+impl Before {
+    fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+            && self.lists.is_empty()
+            && self.maps.is_empty()
+            && self.registers.is_empty()
+    }
+}
+
+/// The contents (see [`Arena::fingerprint`]) of the values an action may
+/// change, from before it was performed, so that what it really changed can
+/// be told afterwards: previews, for instance, change nothing until they are
+/// confirmed.
+struct Fingerprints {
+    /// The containers the action modifies (see [`Renderer::modified_sets`]).
+    containers: Vec<(ArenaKey, Option<u64>)>,
+    /// The maps holding registers.
+    registers: Vec<(ArenaKey, Option<u64>)>,
+    /// How many strings had been rewritten (see
+    /// [`Arena::rewritten_strings`]).
+    rewritten: usize,
+}
+
+impl Fingerprints {
+    fn take(arena: &Arena, containers: &[ArenaKey]) -> Self {
+        let fingerprint = |key: ArenaKey| (key, arena.fingerprint(key));
+        Self {
+            containers: containers.iter().copied().map(fingerprint).collect(),
+            registers: arena.register_maps().map(fingerprint).collect(),
+            rewritten: arena.rewritten_strings().len(),
+        }
+    }
+
+    /// Returns the containers that have changed since, counting the strings
+    /// inside of them being rewritten.
+    fn changed(&self, arena: &Arena) -> Vec<ArenaKey> {
+        let rewritten = arena.rewritten_strings().len() != self.rewritten;
+        self.containers
+            .iter()
+            .filter(|&&(key, fingerprint)| rewritten || arena.fingerprint(key) != fingerprint)
+            .map(|&(key, _)| key)
+            .collect()
+    }
+
+    /// Returns true if any register has been bound or unbound since.
+    fn rebound(&self, arena: &Arena) -> bool {
+        arena.register_maps().count() != self.registers.len()
+            || self
+                .registers
+                .iter()
+                .any(|&(map, fingerprint)| arena.fingerprint(map) != fingerprint)
+    }
+}
+
+/// The results of the [`Task`]s started by the renderer.
+enum TaskOutput {
+    Import(ImportedImage),
+    ImportText(ImportedText),
+    ImportBlob(Blob, ContentHash),
+    /// An import that was refused, and why.
+    Refused(String),
+    SearchIndex(SearchIndex),
+    Duplicates(Duplicates),
+}
 
 pub struct Renderer {
     store: Arena,
     camera: Camera,
+    context: RenderContext,
     width: f32,
     height: f32,
     selected_node_history: Vec<ArenaKey>,
     text_renderer: TextRenderer,
     circle_renderer: CircleRenderer,
+    rectangle_renderer: RectangleRenderer,
     image_renderer: ImageRenderer,
+    underline_renderer: UnderlineRenderer,
+    selection_renderer: SelectionRenderer,
+    progress_renderer: ProgressRenderer,
+    ghost_renderer: GhostRenderer,
+    sharing_renderer: SharingRenderer,
+    highlight_renderer: HighlightRenderer,
+    heat_renderer: HeatRenderer,
+    tasks: Vec<Task<TaskOutput>>,
+    capture_request: Option<CaptureRequest>,
+    window_request: Option<WindowRequest>,
+    /// The changes made to sets and strings, so that they can be undone and
+    /// redone.
+    history: History<Undo>,
+    /// The rewrite (see [`CompleteAction::Rewrite`]) waiting to be confirmed:
+    /// the value showing its preview, the set or list it rewrites, and the
+    /// edits it makes.
+    rewriting: Option<(ArenaKey, ArenaKey, Vec<(ArenaKey, String)>)>,
+    /// The list of possible duplicates being reviewed (see
+    /// [`CompleteAction::FindDuplicates`]), alongside the pair each of its
+    /// elements stands for.
+    reviewing: Option<(ArenaKey, Vec<Candidate>)>,
+    /// The set that the value being moved (see [`CompleteAction::MarkMove`])
+    /// is moved out of, alongside that value.
+    moving: Option<(ArenaKey, ArenaKey)>,
+    /// Whether actions that modify the arena are rejected. See
+    /// [`CompleteAction::ToggleReadOnly`].
+    read_only: bool,
+    /// Whether anything has been changed. There is nowhere to save changes
+    /// to yet, so once something changes this stays true. See
+    /// [`Renderer::title`].
+    dirty: bool,
+    /// Whether values displayed more than once are connected by curves. See
+    /// [`CompleteAction::ToggleSharing`].
+    show_sharing: bool,
+    /// Whether everything drawn is surrounded by a ring showing how much it
+    /// costs to draw. See [`CompleteAction::ToggleHeatmap`].
+    show_heatmap: bool,
+    /// The values highlighted by [`CompleteAction::Highlight`], in the order
+    /// [`CompleteAction::NextMatch`] steps through them.
+    matches: Vec<ArenaKey>,
+    /// Speeds up queries once it has been built. See [`crate::search`].
+    search_index: Option<SearchIndex>,
+    /// How many values the arena held after garbage was last collected. See
+    /// [`Renderer::collect_garbage`].
+    collected_at: usize,
+    /// The position in `matches` of the value last stepped to, if any.
+    current_match: Option<usize>,
+    /// The image being inspected, alongside the part of it being displayed.
+    /// See [`CompleteAction::Inspect`].
+    inspecting: Option<(ArenaKey, ImageView)>,
+    /// The value whose earlier versions are being shown, alongside the list
+    /// holding them. See [`CompleteAction::Versions`].
+    browsing_versions: Option<(ArenaKey, ArenaKey)>,
+    /// The set being turned by scrolling, if it hasn't finished turning yet.
+    carousel: Option<CarouselAnimation>,
+    /// The walk displaying a different value every few seconds, if one is
+    /// underway. See [`CompleteAction::Explore`].
+    exploring: Option<Exploration>,
+    /// The string being entered into `.`, drawn where it will be once it is
+    /// inserted. See [`Renderer::update_provisional`].
+    provisional: Option<String>,
+    /// The value being dragged with the mouse, if the mouse button is held
+    /// down over one.
+    drag: Option<Drag>,
+    /// Lines scrolled that haven't added up to a whole step of the carousel
+    /// yet.
+    scrolled: f32,
+    /// Physical pixels per logical pixel on the display the window is on.
+    /// See [`Renderer::set_scale_factor`].
+    scale_factor: f32,
+    /// How many times larger than usual things must be before they are drawn,
+    /// on top of `scale_factor`. See [`CompleteAction::ScaleUi`].
+    ui_scale: f32,
+    /// Whether less work is done to save power. See [`crate::power`].
+    power: PowerState,
+    /// When the last frame started being drawn.
+    last_frame: Instant,
+    /// When the renderer was created. Animations are timed from here.
+    started: Instant,
     cursor_position: (f32, f32),
     indication_tree: SpatialTree,
     input_manager: InputManager,
+    overlay_stack: OverlayStack,
+    options: Options,
 }
 
 impl Renderer {
-    pub fn new<'a>(device: &'a wgpu::Device, sc_desc: &'a wgpu::SwapChainDescriptor) -> Self {
+    /// Creates a renderer drawing to a window on a display with the given
+    /// scale factor. See [`Renderer::set_scale_factor`].
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        scale_factor: f32,
+        options: Options,
+    ) -> Self {
         let mut arena = Arena::new();
+        // There is no way to load a saved arena yet, so every run is a first
+        // run. Greet the user with the tutorial.
+        let tutorial = tutorial::build(&mut arena, options.binding_mode);
+        arena.bind_register(".", tutorial);
+        arena.set_usage_tracking(options.track_usage);
+        arena.set_change_window(options.change_window);
+        if let Some(author) = &options.author {
+            arena.record_metadata(Some(author.clone()));
+        }
         // {
         //     let kakoi_example_1 = {
         //         let kakoi_example_1 =
@@ -51,33 +461,176 @@ impl Renderer {
         //         .for_each(|k| arena.set_insert_value(".", k).unwrap());
         // }
         let camera = Camera::new(sc_desc.width as f32 / sc_desc.height as f32);
-        let mut circle_renderer = CircleRenderer::new(device, sc_desc);
+        let context = RenderContext::new(device);
+        let mut circle_renderer = CircleRenderer::new(device, sc_desc, &context);
+        circle_renderer.set_depth_fading(options.depth_of_field);
+        if options.tapered_rings {
+            circle_renderer.set_ring_thickness(RingThickness::TAPERED);
+        }
+        let ui_scale = options.ui_scale.unwrap_or(1.0);
         let mut text_renderer = TextRenderer::new(device, sc_desc);
-        let mut image_renderer = ImageRenderer::new(device, sc_desc);
+        text_renderer.set_depth_fading(options.depth_of_field);
+        text_renderer.set_preview_words(options.preview_words);
+        text_renderer.set_truncation(options.truncation());
+        text_renderer.set_pixel_scale(scale_factor * ui_scale);
+        let mut image_renderer = ImageRenderer::new(device, sc_desc, &context);
+        let mut rectangle_renderer = RectangleRenderer::new(device, sc_desc, &context);
+        let underline_renderer = UnderlineRenderer::new(device, sc_desc, &context);
+        let selection_renderer = SelectionRenderer::new(device, sc_desc, &context);
+        let progress_renderer = ProgressRenderer::new(device, sc_desc, &context);
+        let ghost_renderer = GhostRenderer::new(device, sc_desc, &context);
+        let sharing_renderer = SharingRenderer::new(device, sc_desc, &context);
+        let highlight_renderer = HighlightRenderer::new(device, sc_desc, &context);
+        let heat_renderer = HeatRenderer::new(device, sc_desc, &context);
         let selected_key = arena.register(".").unwrap();
+        let overlay_stack = OverlayStack::new(selected_key);
         let spatial_tree = SpatialTree::new(
-            &arena.slot_map,
-            selected_key,
+            &arena,
+            &overlay_stack,
+            None,
             &mut text_renderer,
             &mut image_renderer,
             &mut circle_renderer,
-            sc_desc.width as f32,
-            sc_desc.height as f32,
+            &mut rectangle_renderer,
+            sc_desc.width as f32 / (scale_factor * ui_scale),
+            sc_desc.height as f32 / (scale_factor * ui_scale),
         );
-        let input_manager = InputManager::new();
-        Self {
+        let input_manager = InputManager::new(options.binding_mode);
+        let collected_at = arena.slot_map.len();
+        let mut renderer = Self {
             store: arena,
             camera,
+            context,
             width: sc_desc.width as f32,
             height: sc_desc.height as f32,
             selected_node_history: vec![],
             text_renderer,
             circle_renderer,
+            rectangle_renderer,
             image_renderer,
+            underline_renderer,
+            selection_renderer,
+            progress_renderer,
+            ghost_renderer,
+            sharing_renderer,
+            highlight_renderer,
+            heat_renderer,
+            tasks: vec![],
+            capture_request: None,
+            window_request: None,
+            history: History::new(),
+            rewriting: None,
+            reviewing: None,
+            moving: None,
+            read_only: options.read_only,
+            dirty: false,
+            show_sharing: false,
+            show_heatmap: false,
+            matches: Vec::new(),
+            search_index: None,
+            collected_at,
+            current_match: None,
+            inspecting: None,
+            browsing_versions: None,
+            carousel: None,
+            exploring: None,
+            provisional: None,
+            drag: None,
+            scrolled: 0.0,
+            scale_factor,
+            ui_scale,
+            power: if options.power_saving {
+                PowerState::Saving
+            } else {
+                PowerState::detect()
+            },
+            last_frame: Instant::now(),
+            started: Instant::now(),
             cursor_position: (0.0, 0.0),
             indication_tree: spatial_tree,
             input_manager,
+            overlay_stack,
+            options,
+        };
+        renderer.apply_power_state();
+        if let Some(uuid) = renderer.options.focus {
+            if renderer.follow_link(&DeepLink::new(uuid)) {
+                renderer.rebuild_indication_tree();
+            }
         }
+        if let Some(path) = renderer.options.import.clone() {
+            renderer.import_file(path);
+        }
+        if let Some(dir) = crash::take_unseen() {
+            let (arena, register) = (dir.join(crash::ARENA), crash::RESTORED_REGISTER);
+            let restore = match json::import(&mut renderer.store, arena, register) {
+                Ok(_) => format!("; the registers it had are in {}", register),
+                Err(_) => String::new(),
+            };
+            renderer.notify(&format!(
+                "kakoi closed unexpectedly last time, and wrote a report to {}{}",
+                dir.display(),
+                restore
+            ));
+        }
+        renderer
+    }
+
+    /// Writes the arena out to `dir` as JSON, and what the base layer
+    /// displays as an outline, after a panic, first rolling back the action
+    /// that panicked if it was performed inside of a transaction. See
+    /// [`crate::crash`].
+    pub fn rescue(&mut self, dir: &Path) -> io::Result<()> {
+        self.store.rollback();
+        let arena = json::export(&self.store, dir.join(crash::ARENA));
+        let outline = export::export(
+            &self.store,
+            self.overlay_stack.base().focus,
+            dir.join(crash::OUTLINE),
+            OutlineFormat::Markdown,
+        );
+        arena.and(outline).map(|_| ())
+    }
+
+    /// Replaces every GPU resource with a new one created on `device`, keeping
+    /// everything else (the arena, the overlay stack, and so on) as it was.
+    /// This is how rendering recovers after the device it was using is lost.
+    pub fn recreate<'a>(
+        &mut self,
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+    ) {
+        self.context = RenderContext::new(device);
+        self.circle_renderer = CircleRenderer::new(device, sc_desc, &self.context);
+        self.apply_power_state();
+        self.circle_renderer
+            .set_depth_fading(self.options.depth_of_field);
+        if self.options.tapered_rings {
+            self.circle_renderer
+                .set_ring_thickness(RingThickness::TAPERED);
+        }
+        self.text_renderer = TextRenderer::new(device, sc_desc);
+        self.text_renderer
+            .set_depth_fading(self.options.depth_of_field);
+        self.text_renderer
+            .set_preview_words(self.options.preview_words);
+        self.text_renderer.set_truncation(self.options.truncation());
+        self.text_renderer.set_pixel_scale(self.pixel_scale());
+        self.image_renderer = ImageRenderer::new(device, sc_desc, &self.context);
+        if let Some((image, view)) = self.inspecting {
+            self.image_renderer.set_view(image, Some(view));
+        }
+        self.rectangle_renderer = RectangleRenderer::new(device, sc_desc, &self.context);
+        self.underline_renderer = UnderlineRenderer::new(device, sc_desc, &self.context);
+        self.selection_renderer = SelectionRenderer::new(device, sc_desc, &self.context);
+        self.progress_renderer = ProgressRenderer::new(device, sc_desc, &self.context);
+        self.ghost_renderer = GhostRenderer::new(device, sc_desc, &self.context);
+        self.sharing_renderer = SharingRenderer::new(device, sc_desc, &self.context);
+        self.highlight_renderer = HighlightRenderer::new(device, sc_desc, &self.context);
+        self.heat_renderer = HeatRenderer::new(device, sc_desc, &self.context);
+        // Resizing lays everything out again, which hands the new renderers
+        // everything they need to draw.
+        self.resize(sc_desc);
     }
 
     pub fn resize<'a>(&mut self, sc_desc: &'a wgpu::SwapChainDescriptor) {
@@ -88,11 +641,47 @@ impl Renderer {
 
         self.circle_renderer.resize();
         self.text_renderer.resize();
-        self.image_renderer.resize();
 
         self.rebuild_indication_tree();
     }
 
+    /// Sets how many physical pixels make up a logical pixel on the display
+    /// the window is on, which changes when the window moves to another
+    /// display. Everything is laid out again, since what is large enough to
+    /// be seen or read depends on it.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.scale_factor = scale_factor;
+        self.text_renderer.set_pixel_scale(self.pixel_scale());
+        self.rebuild_indication_tree();
+    }
+
+    /// Physical pixels per logical pixel, including the UI scale.
+    fn pixel_scale(&self) -> f32 {
+        self.scale_factor * self.ui_scale
+    }
+
+    /// The size of the window in logical pixels. Layouts are culled in these,
+    /// so that values disappear at the same apparent size on every display
+    /// (see [`SpatialBound::is_visible`]).
+    fn logical_size(&self) -> (f32, f32) {
+        let pixel_scale = self.pixel_scale();
+        (self.width / pixel_scale, self.height / pixel_scale)
+    }
+
+    /// Writes where everything on screen is drawn out to `path` as JSON,
+    /// returning how many objects were written. See [`crate::geometry`].
+    pub fn export_layout<P: AsRef<Path>>(&mut self, path: P) -> io::Result<usize> {
+        let (width, height) = self.logical_size();
+        geometry::export(
+            path,
+            &self.store,
+            &self.indication_tree.nodes(),
+            &mut self.camera,
+            width,
+            height,
+        )
+    }
+
     pub fn render<'a>(
         &mut self,
         device: &'a wgpu::Device,
@@ -101,30 +690,260 @@ impl Renderer {
         command_encoder: &'a mut wgpu::CommandEncoder,
         texture_view: &'a wgpu::TextureView,
     ) {
-        self.circle_renderer.render(
+        let _span = tracing::trace_span!("render").entered();
+        self.last_frame = Instant::now();
+        self.poll_tasks();
+        self.animate_carousel();
+        self.explore();
+        self.evict_changes();
+        draw_values(
             device,
             queue,
             sc_desc,
             command_encoder,
             texture_view,
             &mut self.camera,
+            &self.context,
+            &self.store,
+            &mut self.circle_renderer,
+            &mut self.rectangle_renderer,
+            &mut self.text_renderer,
+            &mut self.underline_renderer,
+            &mut self.image_renderer,
         );
-        self.text_renderer.render(
-            &self.store.slot_map,
+        self.sharing_renderer.render(
             device,
             sc_desc,
             command_encoder,
             texture_view,
-            &mut self.camera,
+            &self.context,
+        );
+        self.highlight_renderer.render(
+            device,
+            sc_desc,
+            command_encoder,
+            texture_view,
+            &self.context,
         );
-        self.image_renderer.render(
+        self.heat_renderer.render(
             device,
+            sc_desc,
+            command_encoder,
+            texture_view,
+            &self.context,
+        );
+        self.ghost_renderer
+            .render(queue, sc_desc, command_encoder, texture_view, &self.context);
+        self.selection_renderer.render(
             queue,
+            sc_desc,
             command_encoder,
             texture_view,
-            &mut self.camera,
-            &self.store.slot_map,
+            &self.context,
+            self.started.elapsed().as_secs_f32(),
         );
+        self.progress_renderer
+            .render(queue, sc_desc, command_encoder, texture_view, &self.context);
+    }
+
+    /// Returns when the window should next be redrawn even if nothing happens
+    /// in the meantime, if ever.
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        // Animations wait for their next frame while saving power, rather than
+        // being redrawn right away (see `Renderer::frame_rate_capped`).
+        let frame = self
+            .power
+            .frame_interval()
+            .filter(|_| self.is_animating())
+            .map(|interval| self.last_frame + interval);
+        let step = self.exploring.as_ref().map(Exploration::next_step);
+        let eviction = self.store.next_change_eviction();
+        frame.into_iter().chain(step).chain(eviction).min()
+    }
+
+    /// Returns the title the window should have: the name of the register the
+    /// value the topmost layer displays is bound to (if any), the arena being
+    /// edited, and a `*` if it has unsaved changes.
+    ///
+    /// Kakoi doesn't keep arenas in files of its own yet (writing one out as
+    /// JSON, see [`crate::json`], exports a copy), so every arena is untitled,
+    /// and every change is unsaved.
+    pub fn title(&self) -> String {
+        let arena = format!("untitled{}", if self.dirty { "*" } else { "" });
+        let focus = self.overlay_stack.top().focus;
+        match self.store.register_names(focus).first() {
+            Some(name) => format!("{} — {} — kakoi", name, arena),
+            None => format!("{} — kakoi", arena),
+        }
+    }
+
+    /// Returns true if animations are only redrawn every so often, at
+    /// [`Renderer::next_wakeup`], instead of as soon as the last frame is
+    /// drawn.
+    pub fn frame_rate_capped(&self) -> bool {
+        self.power.frame_interval().is_some()
+    }
+
+    /// Makes the renderers do as much (or as little) work as the power state
+    /// calls for. See [`crate::power`].
+    fn apply_power_state(&mut self) {
+        self.circle_renderer
+            .set_multisampling(self.power.multisampling());
+        self.circle_renderer
+            .set_depth_cues(!self.options.flat_rings && self.power.depth_cues());
+    }
+
+    /// Returns true if something on screen is animated, in which case the
+    /// window should keep redrawing.
+    pub fn is_animating(&self) -> bool {
+        self.selection_renderer.is_animating()
+            || !self.tasks.is_empty()
+            || self.carousel.is_some()
+            || self.image_renderer.is_decoding()
+    }
+
+    /// Applies the results of every [`Task`] that finished since the last
+    /// frame, and updates the progress bar for the ones that are still
+    /// running.
+    fn poll_tasks(&mut self) {
+        let mut finished = Vec::new();
+        self.tasks.retain(|task| match task.poll() {
+            TaskStatus::Running => true,
+            TaskStatus::Finished(output) => {
+                finished.push(output);
+                false
+            }
+            TaskStatus::Cancelled => {
+                log::info!("task '{}' was cancelled or failed", task.name());
+                false
+            }
+        });
+        for output in finished {
+            match output {
+                TaskOutput::Import(imported) => self.preview_image(imported),
+                TaskOutput::ImportText(imported) => self.preview_text(imported),
+                TaskOutput::ImportBlob(blob, hash) => self.preview_blob(blob, hash),
+                TaskOutput::Refused(message) => {
+                    self.notify(&message);
+                }
+                TaskOutput::SearchIndex(index) => self.search_index = Some(index),
+                TaskOutput::Duplicates(duplicates) => {
+                    self.review_duplicates(duplicates);
+                }
+            }
+        }
+        let progress = if self.tasks.is_empty() {
+            None
+        } else {
+            let total: f32 = self.tasks.iter().map(Task::progress).sum();
+            Some(total / self.tasks.len() as f32)
+        };
+        self.progress_renderer.set_progress(progress);
+    }
+
+    /// Returns true if a [`Task`] named `name` is still running.
+    fn is_running(&self, name: &str) -> bool {
+        self.tasks.iter().any(|task| task.name() == name)
+    }
+
+    /// Takes the capture asked for by the last action, if there was one. See
+    /// [`crate::render::capture`].
+    pub fn take_capture_request(&mut self) -> Option<CaptureRequest> {
+        self.capture_request.take()
+    }
+
+    /// Starts capturing a string into the inbox as if `c` had been pressed,
+    /// discarding anything partially entered. See [`crate::background`].
+    pub fn begin_capture(&mut self) -> bool {
+        let complete_action = self
+            .input_manager
+            .press(winit::event::VirtualKeyCode::C, "c");
+        self.apply_input(complete_action)
+    }
+
+    /// Takes the change to the window asked for by the last action, if there
+    /// was one.
+    pub fn take_window_request(&mut self) -> Option<WindowRequest> {
+        self.window_request.take()
+    }
+
+    /// Draws the value displayed by the topmost layer (and everything inside
+    /// of it) offscreen, at the size given by `request`. Returns the image
+    /// alongside the [`Uuid`](uuid::Uuid) of the value, or [`None`] if the
+    /// image couldn't be read back.
+    pub fn capture<'a>(
+        &self,
+        device: &'a wgpu::Device,
+        queue: &'a mut wgpu::Queue,
+        format: wgpu::TextureFormat,
+        request: CaptureRequest,
+    ) -> Option<(uuid::Uuid, image::RgbaImage)> {
+        let _span = tracing::debug_span!("capture").entered();
+        let top = self.overlay_stack.top();
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            format,
+            width: request.width,
+            height: request.height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        // The capture has nothing to do with what is on screen, so it gets
+        // renderers (and a layout, and a camera uniform) of its own.
+        let mut camera = Camera::new(request.width as f32 / request.height as f32);
+        let context = RenderContext::new(device);
+        let mut circle_renderer = CircleRenderer::new(device, &sc_desc, &context);
+        circle_renderer.set_depth_cues(!self.options.flat_rings);
+        circle_renderer.set_depth_fading(self.options.depth_of_field);
+        if self.options.tapered_rings {
+            circle_renderer.set_ring_thickness(RingThickness::TAPERED);
+        }
+        let mut rectangle_renderer = RectangleRenderer::new(device, &sc_desc, &context);
+        // Captures aren't shown on any display, so each of their pixels is
+        // taken to be a logical pixel.
+        let mut text_renderer = TextRenderer::new(device, &sc_desc);
+        text_renderer.set_depth_fading(self.options.depth_of_field);
+        text_renderer.set_preview_words(self.options.preview_words);
+        text_renderer.set_truncation(self.options.truncation());
+        let mut underline_renderer = UnderlineRenderer::new(device, &sc_desc, &context);
+        let mut image_renderer = ImageRenderer::new(device, &sc_desc, &context);
+        image_renderer.set_blocking(true);
+        let mut overlay_stack = OverlayStack::new(top.focus);
+        overlay_stack.base_mut().layout = top.layout;
+        SpatialTree::new(
+            &self.store,
+            &overlay_stack,
+            None,
+            &mut text_renderer,
+            &mut image_renderer,
+            &mut circle_renderer,
+            &mut rectangle_renderer,
+            request.width as f32,
+            request.height as f32,
+        );
+
+        let target = CaptureTarget::new(device, &sc_desc);
+        let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("capture encoder"),
+        });
+        draw_values(
+            device,
+            queue,
+            &sc_desc,
+            &mut command_encoder,
+            target.view(),
+            &mut camera,
+            &context,
+            &self.store,
+            &mut circle_renderer,
+            &mut rectangle_renderer,
+            &mut text_renderer,
+            &mut underline_renderer,
+            &mut image_renderer,
+        );
+        target.copy(&mut command_encoder);
+        queue.submit(std::iter::once(command_encoder.finish()));
+        let image = target.read(device)?;
+        Some((self.store.uuid(top.focus)?, image))
     }
 
     pub fn post_render(&mut self) {
@@ -133,118 +952,2157 @@ impl Renderer {
     }
 
     fn rebuild_indication_tree(&mut self) {
+        let _span = tracing::debug_span!("rebuild_indication_tree").entered();
         self.circle_renderer.invalidate();
+        self.rectangle_renderer.invalidate();
         self.text_renderer.invalidate();
         self.image_renderer.invalidate();
+        self.underline_renderer.invalidate();
 
-        let selected_index = self.store.register(".").unwrap();
+        let focus = self.store.register(".").unwrap();
+        let base = self.overlay_stack.base_mut();
+        if base.focus != focus {
+            base.refocus(focus);
+        }
+        let pins = self.store.pins();
+        self.overlay_stack.set_pins(pins);
+        // Keep the zoom of the inspected image up to date, since it depends on
+        // the size of the window.
+        if let Some((image, view)) = self.inspecting {
+            let message = self.describe_inspection(image, view);
+            let message = self.store.string(&message);
+            if let Some(layer) = self
+                .overlay_stack
+                .iter_mut()
+                .find(|layer| layer.kind == LayerKind::Inspection)
+            {
+                layer.message = Some(message);
+            }
+        }
+        // Keep the member counts of samples up to date.
+        for layer in self.overlay_stack.iter_mut() {
+            if let Some(sample) = layer.sample {
+                let total = match &self.store.slot_map[layer.focus].structure {
+                    Structure::Set(set) => set.len(),
+                    _ => 0,
+                };
+                layer.message = Some(self.store.string(&sample.describe(total)));
+            }
+        }
 
+        let (width, height) = self.logical_size();
         self.indication_tree.rebuild(
-            &self.store.slot_map,
-            selected_index,
+            &self.store,
+            &self.overlay_stack,
+            self.provisional.as_deref(),
             &mut self.text_renderer,
             &mut self.image_renderer,
             &mut self.circle_renderer,
-            self.width,
-            self.height,
+            &mut self.rectangle_renderer,
+            width,
+            height,
         );
+
+        let selection = self
+            .overlay_stack
+            .top()
+            .selected
+            .and_then(|selected| self.indication_tree.selectable(selected));
+        self.selection_renderer
+            .select(selection.map(|selection| selection.bounds));
+        self.ghost_renderer.set_bounds(
+            self.overlay_stack
+                .ghost_bounds(self.camera.visible_half_extent()),
+        );
+        if self.show_sharing {
+            self.sharing_renderer
+                .set_groups(&self.indication_tree.shared(width, height));
+        } else {
+            self.sharing_renderer.set_groups(&[]);
+        }
+        match self.overlay_stack.highlights() {
+            Some(highlights) => self
+                .highlight_renderer
+                .set_halos(&self.indication_tree.highlighted(highlights, width, height)),
+            None => self.highlight_renderer.set_halos(&[]),
+        }
+        if self.show_heatmap {
+            let nodes = self.indication_tree.nodes();
+            let costs = cost::costs(&self.store, &nodes);
+            let rings: Vec<_> = nodes
+                .iter()
+                .zip(cost::heat(&nodes, &costs))
+                .map(|(node, heat)| (node.data.bounds, heat))
+                .collect();
+            self.heat_renderer.set_rings(&rings);
+        } else {
+            self.heat_renderer.set_rings(&[]);
+        }
     }
 
-    pub fn input<'a>(&mut self, event: &winit::event::WindowEvent) -> bool {
-        use winit::event::*;
-        match event {
-            WindowEvent::KeyboardInput { input, .. } => {
-                let should_rebuild = match self.input_manager.process_input(input) {
-                    Some(complete_action) => match complete_action {
-                        CompleteAction::SetInsert(register_to_modify, other_register) => self
-                            .store
-                            .set_insert(register_to_modify, other_register)
-                            .is_some(),
-                        CompleteAction::SetUnion(register_to_modify, other_register) => self
-                            .store
-                            .set_union(register_to_modify, other_register)
-                            .is_some(),
-                        CompleteAction::BindRegisterToEmptySet(register) => {
-                            self.store.bind_register_to_empty_set(register);
-                            true
+    /// Says what drawing everything costs. See [`crate::cost`].
+    fn describe_costs(&mut self) -> Option<()> {
+        let nodes = self.indication_tree.nodes();
+        let costs = cost::costs(&self.store, &nodes);
+        let total = cost::total(&nodes, &costs);
+        self.notify(&format!("drawing {}", total.describe()))
+    }
+
+    /// Returns the selected value in the topmost layer, or the value that layer
+    /// displays if nothing is selected.
+    fn selected_or_focus(&self) -> ArenaKey {
+        let top = self.overlay_stack.top();
+        top.selected
+            .and_then(|selected| self.indication_tree.selectable(selected))
+            .map_or(top.focus, |selection| selection.key)
+    }
+
+    /// Turns the set the topmost layer displays by `steps` members, enlarging
+    /// another one of them. Returns false if that set isn't arranged with
+    /// [`LayoutStrategy::Zoomed`].
+    fn turn_carousel(&mut self, steps: i32) -> bool {
+        let top = self.overlay_stack.top();
+        let set = top.focus;
+        let zoomed = matches!(self.store.slot_map[set].structure, Structure::Set(_))
+            && spatial_tree::container_layout(&self.store, set, top.layout)
+                == LayoutStrategy::Zoomed;
+        if !zoomed || steps == 0 {
+            return false;
+        }
+        let from = self.store.carousel_position(set);
+        // Scrolling again before the last turn finishes continues on from
+        // where that turn was headed.
+        let to = match self.carousel {
+            Some(carousel) if carousel.set == set => carousel.to,
+            _ => from.round(),
+        } + steps as f32;
+        self.carousel = Some(CarouselAnimation {
+            set,
+            from,
+            to,
+            started: Instant::now(),
+        });
+        true
+    }
+
+    /// Moves the set being turned by [`Renderer::turn_carousel`] along for
+    /// this frame, laying everything out again.
+    fn animate_carousel(&mut self) {
+        let carousel = match self.carousel {
+            Some(carousel) => carousel,
+            None => return,
+        };
+        let t = (carousel.started.elapsed().as_secs_f32() / CAROUSEL_DURATION).min(1.0);
+        let eased = 1.0 - (1.0 - t) * (1.0 - t);
+        self.store.set_carousel_position(
+            carousel.set,
+            carousel.from + eased * (carousel.to - carousel.from),
+        );
+        if t >= 1.0 {
+            self.carousel = None;
+        }
+        self.rebuild_indication_tree();
+    }
+
+    /// Takes the next step of the walk started by [`CompleteAction::Explore`],
+    /// if it is due. Steps are put off while a modal layer is displayed, since
+    /// refocusing would replace what it displays. The walk stops once there is
+    /// nowhere left to go.
+    fn explore(&mut self) {
+        let now = Instant::now();
+        let exploring = match &mut self.exploring {
+            Some(exploring) if exploring.next_step() <= now => exploring,
+            _ => return,
+        };
+        if self.overlay_stack.is_modal() {
+            exploring.postpone(now);
+            return;
+        }
+        let from = self.overlay_stack.base().focus;
+        match exploring.step(&self.store, from, now) {
+            Some(key) => {
+                self.focus(key);
+                self.rebuild_indication_tree();
+            }
+            None => {
+                self.exploring = None;
+                self.tell("stopped exploring: there is nowhere to go from here");
+                self.rebuild_indication_tree();
+            }
+        }
+    }
+
+    /// Takes the values that changed too long ago out of the set of changed
+    /// values (see [`Arena::evict_changes`]).
+    fn evict_changes(&mut self) {
+        if self.store.evict_changes(Instant::now()) {
+            self.rebuild_indication_tree();
+        }
+    }
+
+    /// Keeps the string being entered with [`CompleteAction::InsertStringIntoSetRegister`]
+    /// up to date, so that it is drawn as it is typed rather than only once
+    /// it is finished. Nothing is inserted into the arena until then. Returns
+    /// true if the string changed, in which case everything needs to be laid
+    /// out again.
+    fn update_provisional(&mut self) -> bool {
+        let provisional = match self.input_manager.partial_action() {
+            Some(CompleteAction::InsertStringIntoSetRegister(register, string))
+                if register == "." && !self.read_only =>
+            {
+                Some(string)
+            }
+            _ => None,
+        };
+        let changed = provisional != self.provisional;
+        self.provisional = provisional;
+        changed
+    }
+
+    /// Keeps the minibuffer displaying the string or register prompt waiting
+    /// for input, if there is one. Returns true if the minibuffer changed.
+    fn update_minibuffer(&mut self) -> bool {
+        let minibuffer = self.input_manager.prompt().map(|prompt| prompt.to_string());
+        let changed = minibuffer.as_deref() != self.overlay_stack.minibuffer();
+        self.overlay_stack.set_minibuffer(minibuffer);
+        changed
+    }
+
+    /// Displays the value that `link` names, like selecting it, or says why
+    /// that isn't possible. Returns true if anything on screen changed.
+    fn follow_link(&mut self, link: &DeepLink) -> bool {
+        let reason = match (&link.file, self.store.resolve_uuid(&link.uuid)) {
+            (Some(file), _) => format!(
+                "can't open {}: arenas can't be loaded from files yet",
+                file.display()
+            ),
+            (None, Resolution::Found(key)) => {
+                self.focus(key);
+                return true;
+            }
+            (None, Resolution::Merged(key)) => {
+                self.focus(key);
+                self.notify("that value was merged into this one");
+                return true;
+            }
+            (None, Resolution::Stale) => {
+                format!("stale reference: nothing here has the uuid {}", link.uuid)
+            }
+        };
+        log::warn!("couldn't follow {}: {}", link, reason);
+        self.notify(&reason).is_some()
+    }
+
+    /// Starts inspecting `image`, displaying the whole of it above every layer.
+    /// Returns [`None`] if `image` isn't an image.
+    fn inspect(&mut self, image: ArenaKey) -> Option<()> {
+        match self.store.slot_map[image].structure {
+            Structure::Image(_) | Structure::ImageRef(_) => {}
+            _ => return None,
+        }
+        self.overlay_stack
+            .push(Layer::new(LayerKind::Inspection, image))?;
+        self.input_manager.set_mode(InputMode::Inspect);
+        self.set_inspection_view(image, ImageView::default());
+        Some(())
+    }
+
+    fn set_inspection_view(&mut self, image: ArenaKey, view: ImageView) {
+        self.inspecting = Some((image, view));
+        self.image_renderer.set_view(image, Some(view));
+    }
+
+    /// Stops inspecting the image being inspected. Returns false if no image
+    /// was being inspected.
+    fn stop_inspecting(&mut self) -> bool {
+        self.input_manager.set_mode(InputMode::Normal);
+        match self.inspecting.take() {
+            Some((image, _)) => {
+                self.image_renderer.set_view(image, None);
+                while self.overlay_stack.top().kind != LayerKind::Inspection {
+                    self.overlay_stack.pop();
+                }
+                self.overlay_stack.pop();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns how many pixels of the screen each pixel of `image` is drawn
+    /// across while it is inspected without being zoomed into.
+    fn inspection_scale(&self, image: ArenaKey) -> Option<f32> {
+        let (width, height) = match &self.store.slot_map[image].structure {
+            Structure::Image(image) => image.dimensions(),
+            // Linked images are only read once they are drawn.
+            Structure::ImageRef(_) => self.image_renderer.dimensions(image)?,
+            _ => return None,
+        };
+        let layer = self
+            .overlay_stack
+            .iter()
+            .find(|layer| layer.kind == LayerKind::Inspection)?;
+        // The layer's message describes the zoom, so it is laid out as if it
+        // already had one.
+        let (focus, _) = Layer {
+            message: Some(image),
+            ..*layer
+        }
+        .focus_and_message_bounds();
+        let fitted = SpatialBound::cuboid_inside_bound(
+            &SpatialBound::SquareCuboid(focus),
+            width as f32 / height as f32,
+        );
+        let (_, half_height) = self.camera.visible_half_extent();
+        let pixels_per_unit = self.height / (2.0 * half_height);
+        Some(fitted.width() * pixels_per_unit / width as f32)
+    }
+
+    /// Describes how large the inspected `image` is drawn, and how to move
+    /// around it.
+    fn describe_inspection(&self, image: ArenaKey, view: ImageView) -> String {
+        let percentage = self.inspection_scale(image).map_or(String::new(), |scale| {
+            format!("{:.0}% ", 100.0 * scale * view.zoom)
+        });
+        format!(
+            "{}(arrows pan, = and - zoom, 1 switches to actual size, escape stops)",
+            percentage
+        )
+    }
+
+    /// Gives up on moving the value marked with [`CompleteAction::MarkMove`].
+    /// Returns false if nothing was being moved.
+    fn cancel_move(&mut self) -> bool {
+        self.overlay_stack.set_ghost(None);
+        self.moving.take().is_some()
+    }
+
+    /// Displays `key` in the topmost layer of the overlay stack. If that is the
+    /// base layer, `key` is bound to the `.` register and the previous value of
+    /// `.` is remembered so that we can go back to it later.
+    fn focus(&mut self, key: ArenaKey) {
+        self.store.record_visit(key);
+        if self.overlay_stack.is_modal() {
+            self.overlay_stack.top_mut().refocus(key);
+        } else {
+            self.selected_node_history
+                .push(self.store.register(".").unwrap());
+            self.store.bind_register(".", key);
+        }
+    }
+
+    /// Makes the set bound to `set` contain exactly the members of the set
+    /// bound to `with`, returning a new set holding the members it had before.
+    fn swap_set(&mut self, set: ArenaKey, with: ArenaKey) -> Option<ArenaKey> {
+        let members = match &self.store.slot_map.get(set)?.structure {
+            Structure::Set(members) => members.iter().copied().collect::<Vec<_>>(),
+            _ => return None,
+        };
+        let old = self.store.set(members);
+        self.store.set_replace(set, with)?;
+        Some(old)
+    }
+
+    /// Like [`Renderer::swap_set`], remembering the old members so that the
+    /// change can be undone with [`CompleteAction::Undo`].
+    fn replace_set(&mut self, set: ArenaKey, with: ArenaKey) -> Option<()> {
+        let old = self.swap_set(set, with)?;
+        self.history.record(Undo::ReplaceSet(set, old));
+        let members = self.members(old).unwrap_or_default();
+        self.record_set_version(set, members);
+        Some(())
+    }
+
+    /// Keeps a copy of `members` as the latest earlier version of `set` (see
+    /// [`Arena::record_version`]), unless there are too many of them to be
+    /// worth copying or `set` is one that kakoi keeps up itself, like the set
+    /// of starred values.
+    ///
+    /// Only sets replaced all at once are versioned. Inserting or removing a
+    /// member can be undone instead, and copying the whole set every time
+    /// would keep its removed members from ever being collected as garbage.
+    fn record_set_version(&mut self, set: ArenaKey, members: HashSet<ArenaKey>) {
+        let internal = arena::DERIVED_REGISTERS
+            .iter()
+            .chain(&[STARRED_REGISTER])
+            .any(|register| self.store.register(*register) == Some(set));
+        if !internal && members.len() <= MAX_VERSIONED_MEMBERS {
+            let version = self.store.set(members);
+            self.store.record_version(set, version);
+        }
+    }
+
+    /// Changes the strings inside of `container` (see
+    /// [`Arena::rewrite_strings`]), remembering their previous contents so
+    /// that the change can be undone with [`CompleteAction::Undo`]. Returns
+    /// false if nothing changed.
+    fn rewrite_strings(&mut self, container: ArenaKey, edits: Vec<(ArenaKey, String)>) -> bool {
+        let previous = self.store.rewrite_strings(edits);
+        if previous.is_empty() {
+            return false;
+        }
+        self.text_renderer.forget_previews();
+        for (key, old) in &previous {
+            let version = self.store.string(old);
+            self.store.record_version(*key, version);
+        }
+        self.history.record(Undo::Rewrite(container, previous));
+        true
+    }
+
+    /// Puts back `change`, returning the change that puts it back again (see
+    /// [`crate::history`]).
+    fn put_back(&mut self, change: Undo) -> Option<Undo> {
+        match change {
+            Undo::ReplaceSet(set, old) => self
+                .swap_set(set, old)
+                .map(|current| Undo::ReplaceSet(set, current)),
+            Undo::Rewrite(container, previous) => {
+                let current = self.store.rewrite_strings(previous);
+                self.text_renderer.forget_previews();
+                Some(Undo::Rewrite(container, current))
+            }
+            Undo::Members(set, remove, insert) => self
+                .store
+                .set_change(set, &insert, &remove)
+                .map(|()| Undo::Members(set, insert, remove)),
+            Undo::Elements(list, elements) => self
+                .store
+                .list_replace(list, elements)
+                .map(|current| Undo::Elements(list, current)),
+            Undo::Register(register, value) => {
+                let current = self.store.register(register.as_str());
+                match value {
+                    Some(value) => self.store.bind_register(register.as_str(), value),
+                    None => {
+                        self.store.unbind_register(register.as_str());
+                    }
+                }
+                Some(Undo::Register(register, current))
+            }
+            Undo::Map(map, previous) => {
+                let entries = self.store.entries(map)?;
+                let current = previous
+                    .iter()
+                    .map(|&(key, _)| (key, entries.get(&key).copied()))
+                    .collect();
+                for (key, value) in previous {
+                    match value {
+                        Some(value) => self.store.map_insert(map, key, value),
+                        None => self.store.map_remove(map, key),
+                    };
+                }
+                Some(Undo::Map(map, current))
+            }
+            Undo::Lock(key, locked) => {
+                let current = self.store.is_locked_directly(key);
+                self.store.set_locked(key, locked);
+                Some(Undo::Lock(key, current))
+            }
+            Undo::Version(key, version) => {
+                self.store.restore_version(key, version)?;
+                self.text_renderer.forget_previews();
+                Some(Undo::Version(key, version))
+            }
+        }
+    }
+
+    /// Removes the values that can't be reached from a register (see
+    /// [`Arena::collect_garbage`]), apart from those the renderer still
+    /// refers to (see [`Renderer::referenced`]). Returns how many values were
+    /// removed.
+    fn collect_garbage(&mut self) -> usize {
+        let keep = self.referenced();
+        let removed = self.store.collect_garbage_keeping(&keep);
+        if removed > 0 {
+            self.text_renderer.forget_previews();
+        }
+        self.collected_at = self.store.slot_map.len();
+        removed
+    }
+
+    /// Removes the values that nothing contains any more (see
+    /// [`Arena::prune_orphans_keeping`]), apart from those the renderer still
+    /// refers to. Returns how many values were removed.
+    fn prune_orphans(&mut self, pruning: Pruning) -> usize {
+        let mut keep = self.referenced();
+        if !pruning.keep_pinned {
+            // The pins drawn on screen are only copies of the arena's, which
+            // are brought up to date once it has been pruned.
+            let pins = self.overlay_stack.pins().to_vec();
+            keep.retain(|key| !pins.iter().any(|&(_, pinned)| pinned == *key));
+        }
+        let removed = self.store.prune_orphans_keeping(&keep, pruning);
+        if removed > 0 {
+            self.text_renderer.forget_previews();
+        }
+        removed
+    }
+
+    /// Returns the values the renderer refers to outside of the arena: what
+    /// is displayed, what can be undone or redone, and so on.
+    fn referenced(&self) -> Vec<ArenaKey> {
+        let mut keep = self.overlay_stack.keys();
+        keep.extend(&self.selected_node_history);
+        keep.extend(self.history.changes().into_iter().flat_map(Undo::keys));
+        if let Some((preview, container, edits)) = &self.rewriting {
+            keep.extend(&[*preview, *container]);
+            keep.extend(edits.iter().map(|&(key, _)| key));
+        }
+        if let Some((report, candidates)) = &self.reviewing {
+            keep.push(*report);
+            for candidate in candidates {
+                keep.extend(&[candidate.keep, candidate.merge]);
+            }
+        }
+        if let Some((from, value)) = self.moving {
+            keep.extend(&[from, value]);
+        }
+        keep.extend(&self.matches);
+        keep.extend(self.inspecting.map(|(image, _)| image));
+        if let Some((owner, versions)) = self.browsing_versions {
+            keep.extend(&[owner, versions]);
+        }
+        keep.extend(self.carousel.map(|carousel| carousel.set));
+        if let Some(exploring) = &self.exploring {
+            keep.extend(exploring.trail());
+        }
+        keep
+    }
+
+    /// Returns the members of `set`, or [`None`] if it isn't a set.
+    fn members(&self, set: ArenaKey) -> Option<HashSet<ArenaKey>> {
+        match &self.store.slot_map.get(set)?.structure {
+            Structure::Set(members) => Some(members.iter().copied().collect()),
+            _ => None,
+        }
+    }
+
+    /// Performs `action` with [`perform::apply`], showing whatever it has to
+    /// say. Returns true if it was performed, or if something was shown.
+    fn perform(&mut self, action: &CompleteAction) -> bool {
+        match perform::apply(&mut self.store, action, self.search_index.as_ref()) {
+            Outcome::Performed(None) => true,
+            Outcome::Performed(Some(message)) => {
+                self.tell(&message);
+                true
+            }
+            Outcome::Failed(Some(message)) => self.notify(&message).is_some(),
+            Outcome::Failed(None) | Outcome::Unsupported => false,
+        }
+    }
+
+    /// Returns the elements of `list`, or [`None`] if it isn't a list.
+    fn elements(&self, list: ArenaKey) -> Option<Vec<ArenaKey>> {
+        self.store.elements(list).map(<[ArenaKey]>::to_vec)
+    }
+
+    /// Returns true if the changes `action` makes to the members of sets (or
+    /// the elements of lists, or the entries of maps) are recorded by
+    /// [`Renderer::record_changes`]. Other actions either change no members,
+    /// or record their own changes.
+    fn records_members(&self, action: &CompleteAction) -> bool {
+        match action {
+            CompleteAction::SetInsert(..)
+            | CompleteAction::SetUnion(..)
+            | CompleteAction::SetRemove(..)
+            | CompleteAction::ToggleMembership(..)
+            | CompleteAction::InsertStringIntoSetRegister(..)
+            | CompleteAction::InsertNumberIntoSetRegister(..)
+            | CompleteAction::Arithmetic(..)
+            | CompleteAction::CaptureString(_)
+            | CompleteAction::ListPush(..)
+            | CompleteAction::ListPop(_)
+            | CompleteAction::ListInsertAt(..)
+            | CompleteAction::ListRemoveAt(..)
+            | CompleteAction::ListSwap(..)
+            | CompleteAction::ListMove(..)
+            | CompleteAction::ToggleStar
+            | CompleteAction::Tag(_)
+            | CompleteAction::Untag(_)
+            | CompleteAction::Drop(..) => true,
+            CompleteAction::Confirm => match self.overlay_stack.top().kind {
+                LayerKind::Preview => true,
+                LayerKind::Base => self.moving.is_some(),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns the (non-local) registers other than `.` that `action` binds,
+    /// so that binding them can be undone. Binding `.` only moves around.
+    fn bound_registers(&self, action: &CompleteAction) -> Vec<String> {
+        let registers = match action {
+            CompleteAction::BindRegisterToEmptySet(register)
+            | CompleteAction::BindRegisterToEmptyList(register)
+            | CompleteAction::BindRegisterToRegisterValue(register, _)
+            | CompleteAction::BindRegisterToString(register, _) => vec![register.clone()],
+            CompleteAction::CaptureString(_) => vec![INBOX_REGISTER.to_string()],
+            CompleteAction::ToggleStar => vec![STARRED_REGISTER.to_string()],
+            CompleteAction::Tag(_) | CompleteAction::Untag(_) => vec![TAGS_REGISTER.to_string()],
+            CompleteAction::Pin(slot) => vec![arena::pin_register(*slot)],
+            _ => vec![],
+        };
+        registers
+            .into_iter()
+            .filter(|register| register != "." && !arena::is_local_register(register))
+            .collect()
+    }
+
+    /// Returns what `changing` (the containers `action` modifies) and the
+    /// registers `action` binds hold before it is performed.
+    fn before(&mut self, action: &CompleteAction, changing: &[ArenaKey]) -> Before {
+        let mut containers = if self.records_members(action) {
+            changing.to_vec()
+        } else {
+            vec![]
+        };
+        containers.sort();
+        containers.dedup();
+        Before {
+            sets: containers
+                .iter()
+                .filter_map(|&set| Some((set, self.members(set)?)))
+                .collect(),
+            lists: containers
+                .iter()
+                .filter_map(|&list| Some((list, self.elements(list)?)))
+                .collect(),
+            maps: containers
+                .iter()
+                .filter_map(|&map| Some((map, self.store.entries(map)?.clone())))
+                .collect(),
+            registers: self
+                .bound_registers(action)
+                .into_iter()
+                .map(|register| {
+                    let value = self.store.register(register.as_str());
+                    (register, value)
+                })
+                .collect(),
+        }
+    }
+
+    /// Records how the registers, maps, lists and sets in `before` have
+    /// changed since, as a single step, so that the changes can be undone
+    /// with [`CompleteAction::Undo`].
+    fn record_changes(&mut self, before: Before) {
+        self.history.begin_group();
+        for (register, old) in before.registers {
+            if self.store.register(register.as_str()) != old {
+                self.history.record(Undo::Register(register, old));
+            }
+        }
+        for (map, old) in before.maps {
+            let new = match self.store.entries(map) {
+                Some(new) => new,
+                None => continue,
+            };
+            let changed: Vec<(ArenaKey, Option<ArenaKey>)> = old
+                .iter()
+                .filter(|&(key, value)| new.get(key) != Some(value))
+                .map(|(&key, &value)| (key, Some(value)))
+                .chain(
+                    new.keys()
+                        .filter(|key| !old.contains_key(key))
+                        .map(|&key| (key, None)),
+                )
+                .collect();
+            if !changed.is_empty() {
+                self.history.record(Undo::Map(map, changed));
+            }
+        }
+        for (list, old) in before.lists {
+            if self.elements(list).map_or(false, |new| new != old) {
+                self.history.record(Undo::Elements(list, old));
+            }
+        }
+        for (set, old) in before.sets {
+            let new = match self.members(set) {
+                Some(new) => new,
+                None => continue,
+            };
+            let inserted: Vec<ArenaKey> = new.difference(&old).copied().collect();
+            let removed: Vec<ArenaKey> = old.difference(&new).copied().collect();
+            if !inserted.is_empty() || !removed.is_empty() {
+                self.history.record(Undo::Members(set, inserted, removed));
+            }
+        }
+        self.history.end_group();
+    }
+
+    /// Calls `travel` with the history and a way to put changes back, so that
+    /// it can undo or redo them.
+    fn travel<R, F>(&mut self, travel: F) -> R
+    where
+        F: FnOnce(&mut History<Undo>, &mut dyn FnMut(Undo) -> Option<Undo>) -> R,
+    {
+        let mut history = std::mem::take(&mut self.history);
+        let result = travel(&mut history, &mut |change| self.put_back(change));
+        self.history = history;
+        result
+    }
+
+    /// Shows every checkpoint (see [`crate::history`]) in a notice layer.
+    fn list_checkpoints(&mut self) -> bool {
+        let checkpoints = self.history.checkpoints();
+        if checkpoints.is_empty() {
+            let notice = format!(
+                "there are no checkpoints; {} makes one",
+                self.input_manager.key_label("control+c")
+            );
+            return self.notify(&notice).is_some();
+        }
+        let lines: Vec<String> = checkpoints
+            .iter()
+            .map(|checkpoint| {
+                let steps = if checkpoint.distance == 1 {
+                    "step"
+                } else {
+                    "steps"
+                };
+                match (checkpoint.distance, checkpoint.undone) {
+                    (0, _) => format!("{} (here)", checkpoint.name),
+                    (distance, false) => {
+                        format!("{} ({} {} back)", checkpoint.name, distance, steps)
+                    }
+                    (distance, true) => {
+                        format!("{} ({} {} ahead)", checkpoint.name, distance, steps)
+                    }
+                }
+            })
+            .collect();
+        let lines = self.store.insert_strings(lines);
+        let list = self.store.list(lines);
+        let mut layer = Layer::new(LayerKind::Notice, list);
+        layer.message = Some(self.store.string("control+j jumps to a checkpoint"));
+        self.overlay_stack.push(layer).is_some()
+    }
+
+    /// Shows a sample of what replacing every match of `regex` with
+    /// `replacement` would change in the strings inside of `container` (see
+    /// [`crate::rewrite`]) in a confirmation layer, waiting for the user to
+    /// accept or discard it.
+    fn preview_rewrite(&mut self, container: ArenaKey, regex: &Regex, replacement: &str) -> bool {
+        let rewrite = match rewrite::plan(&self.store, container, regex, replacement) {
+            Some(rewrite) => rewrite,
+            None => {
+                return self
+                    .notify("only sets and lists can be rewritten")
+                    .is_some()
+            }
+        };
+        if rewrite.edits.is_empty() {
+            return self.notify(&rewrite.describe()).is_some();
+        }
+        let lines = rewrite.preview(&self.store);
+        let lines = self.store.insert_strings(lines);
+        let preview = self.store.list(lines);
+        let message = self.store.string(&format!(
+            "{}; enter accepts, escape discards",
+            rewrite.describe()
+        ));
+        let mut layer = Layer::new(LayerKind::Confirmation, preview);
+        layer.message = Some(message);
+        self.rewriting = Some((preview, container, rewrite.edits));
+        self.overlay_stack.push(layer).is_some()
+    }
+
+    /// Shows what the set bound to `set` would contain after flattening it
+    /// once (see [`Arena::set_flatten_once`]) in a confirmation layer, waiting
+    /// for the user to accept or discard it.
+    fn preview_flatten(&mut self, set: ArenaKey) -> Option<()> {
+        let flattened = self.store.set_flatten_once(set)?;
+        let count = match &self.store.slot_map[flattened].structure {
+            Structure::Set(members) => members.len(),
+            _ => unreachable!(),
+        };
+        let message = self.store.string(&format!(
+            "{} members after flattening; enter accepts, escape discards",
+            count
+        ));
+        let mut layer = Layer::new(LayerKind::Confirmation, flattened);
+        layer.message = Some(message);
+        self.overlay_stack.push(layer)
+    }
+
+    /// Brings the search index up to date before a query is run, or starts
+    /// building it in the background if it is worth building and isn't being
+    /// built already. See [`crate::search`].
+    fn update_search_index(&mut self) {
+        match &mut self.search_index {
+            Some(index) => index.catch_up(&self.store),
+            None => {
+                if !self.is_running(search::TASK_NAME) && search::worth_indexing(&self.store) {
+                    let snapshot = Snapshot::take(&self.store);
+                    self.tasks
+                        .push(Task::spawn(search::TASK_NAME, move |progress| {
+                            SearchIndex::build(snapshot, progress).map(TaskOutput::SearchIndex)
+                        }));
+                }
+            }
+        }
+    }
+
+    /// Runs `query` (whose text is `text`) and shows the values matching it as
+    /// a new set in a panel.
+    fn show_query_results(&mut self, text: &str, query: &Query) -> Option<()> {
+        self.update_search_index();
+        let results = query::run(&self.store, query, self.search_index.as_ref());
+        let message = self
+            .store
+            .string(&format!("{} values match {}", results.len(), text));
+        let mut layer = Layer::new(LayerKind::Panel, self.store.set(results));
+        layer.message = Some(message);
+        self.overlay_stack.push(layer)
+    }
+
+    /// Highlights every value matching `query` (see
+    /// [`CompleteAction::Highlight`]). Returns false if nothing matches.
+    fn highlight(&mut self, query: &Query) -> bool {
+        self.update_search_index();
+        let mut matches = query::run(&self.store, query, self.search_index.as_ref());
+        matches.sort();
+        self.current_match = None;
+        if matches.is_empty() {
+            self.matches.clear();
+            self.overlay_stack.set_highlights(None);
+            return false;
+        }
+        self.overlay_stack
+            .set_highlights(Some(matches.iter().copied().collect()));
+        self.matches = matches;
+        true
+    }
+
+    /// Selects the value `steps` matches after the one last stepped to (see
+    /// [`CompleteAction::NextMatch`]), wrapping around. Returns false if
+    /// nothing is highlighted.
+    fn step_match(&mut self, steps: isize) -> bool {
+        let count = self.matches.len() as isize;
+        if count == 0 {
+            return false;
+        }
+        let index = match self.current_match {
+            Some(current) => (current as isize + steps).rem_euclid(count),
+            // The first step forward goes to the first match, and the first
+            // step back to the last.
+            None if steps > 0 => 0,
+            None => count - 1,
+        } as usize;
+        self.current_match = Some(index);
+        let key = self.matches[index];
+        self.show_match(key);
+        true
+    }
+
+    /// Selects `key` in the topmost layer. If that layer doesn't display it
+    /// where it can be selected, it displays a value `key` is inside of
+    /// instead: the one with the fewest leaves, so that as little else as
+    /// possible is displayed alongside it. Values with nothing but register
+    /// maps around them are displayed themselves.
+    fn show_match(&mut self, key: ArenaKey) {
+        if self.indication_tree.selectable_index(key).is_none() {
+            let register_maps: Vec<ArenaKey> = self.store.register_maps().collect();
+            let container = self.store.slot_map[key]
+                .inclusions
+                .iter()
+                .map(|&(container, _)| container)
+                .filter(|container| !register_maps.contains(container))
+                .min_by_key(|&container| (self.store.leaf_count(container), container));
+            match container {
+                Some(container) => self.focus(container),
+                None => self.focus(key),
+            }
+            self.rebuild_indication_tree();
+        }
+        self.overlay_stack.top_mut().selected = self.indication_tree.selectable_index(key);
+    }
+
+    /// Shows every value tagged with `tag` as a new set in a panel, like
+    /// [`Renderer::show_query_results`].
+    fn show_tagged(&mut self, tag: &str) -> Option<()> {
+        let tagged = self.store.tagged(tag);
+        let message = self.store.string(&format!(
+            "{} values tagged #{}",
+            tagged.len(),
+            tag_name(tag)
+        ));
+        let mut layer = Layer::new(LayerKind::Panel, self.store.set(tagged));
+        layer.message = Some(message);
+        self.overlay_stack.push(layer)
+    }
+
+    /// Shows the earlier versions of the selected string or set (see
+    /// [`Arena::versions`]) in a panel, oldest first.
+    fn show_versions(&mut self) -> Option<()> {
+        let owner = self.selected_or_focus();
+        let versions = match self.store.versions(owner) {
+            Some(versions) => versions,
+            None => return self.notify("there are no earlier versions of this"),
+        };
+        let count = self.elements(versions).map_or(0, |elements| elements.len());
+        let message = self.store.string(&format!(
+            "{} earlier versions, oldest first; control+shift+v restores the selected one",
+            count
+        ));
+        let mut layer = Layer::new(LayerKind::Panel, versions);
+        layer.message = Some(message);
+        self.overlay_stack.push(layer)?;
+        self.browsing_versions = Some((owner, versions));
+        Some(())
+    }
+
+    /// Puts back the earlier version selected in the panel shown by
+    /// [`Renderer::show_versions`], keeping what it replaces as a version in
+    /// its place (see [`Arena::restore_version`]).
+    fn restore_version(&mut self) -> Option<()> {
+        let (owner, versions) = self.browsing_versions?;
+        let top = self.overlay_stack.top();
+        if top.focus != versions {
+            return None;
+        }
+        let version = top
+            .selected
+            .and_then(|selected| self.indication_tree.selectable(selected))?
+            .key;
+        self.store.restore_version(owner, version)?;
+        self.text_renderer.forget_previews();
+        self.history.record(Undo::Version(owner, version));
+        Some(())
+    }
+
+    /// Shows `groups` (see [`grouping::suggest`]) as a new set in a suggestion
+    /// layer, waiting for the user to accept or discard it.
+    fn suggest_grouping(&mut self, groups: Vec<Vec<ArenaKey>>) -> Option<()> {
+        let grouped = groups.iter().filter(|group| group.len() > 1).count();
+        let members = groups
+            .into_iter()
+            .map(|group| match group.as_slice() {
+                [member] => *member,
+                _ => self.store.set(group),
+            })
+            .collect::<Vec<_>>();
+        // Like previewed images, the suggestion is only included anywhere once
+        // it is accepted.
+        let suggestion = self.store.set(members);
+        let message = self.store.string(&format!(
+            "{} groups; enter accepts, escape discards",
+            grouped
+        ));
+        let mut layer = Layer::new(LayerKind::Suggestion, suggestion);
+        layer.message = Some(message);
+        self.overlay_stack.push(layer)
+    }
+
+    /// Shows the pairs of possible duplicates in `duplicates` (see
+    /// [`crate::dedup`]) in a suggestion layer for review, or a notice if
+    /// there aren't any. Each pair is shown as a list of the value that is
+    /// kept followed by the value merged into it.
+    fn review_duplicates(&mut self, mut duplicates: Duplicates) -> Option<()> {
+        // Values may have been removed or locked while they were compared.
+        let store = &self.store;
+        duplicates.candidates.retain(|candidate| {
+            [candidate.keep, candidate.merge]
+                .iter()
+                .all(|&key| store.slot_map.contains_key(key) && !store.is_locked(key))
+        });
+        if duplicates.candidates.is_empty() {
+            return self.notify("no possible duplicates found");
+        }
+        let mut rebound: Vec<&str> = duplicates
+            .candidates
+            .iter()
+            .flat_map(|candidate| self.store.register_names(candidate.merge))
+            .collect();
+        rebound.sort_unstable();
+        rebound.dedup();
+        let mut message = duplicates.describe();
+        if !rebound.is_empty() {
+            message += &format!("; merging rebinds {}", rebound.join(", "));
+        }
+        message += "; enter merges the selected pair (or every pair), escape closes";
+        let candidates = duplicates.candidates;
+        let pairs = candidates
+            .iter()
+            .map(|candidate| self.store.list(vec![candidate.keep, candidate.merge]))
+            .collect::<Vec<_>>();
+        let report = self.store.list(pairs);
+        let message = self.store.string(&message);
+        let mut layer = Layer::new(LayerKind::Suggestion, report);
+        layer.message = Some(message);
+        self.reviewing = Some((report, candidates));
+        self.overlay_stack.push(layer)
+    }
+
+    /// Merges the pair of possible duplicates selected in the review started
+    /// by [`Renderer::review_duplicates`], or every pair if none is selected.
+    /// Merged pairs are taken out of the review, which closes once none are
+    /// left.
+    fn merge_duplicates(&mut self) -> bool {
+        let (report, mut candidates) = match self.reviewing.take() {
+            Some(reviewing) => reviewing,
+            None => return false,
+        };
+        let pairs = match &self.store.slot_map[report].structure {
+            Structure::List(pairs) => pairs.to_vec(),
+            _ => unreachable!(),
+        };
+        let selected = self.selected_or_focus();
+        let chosen = if selected == report {
+            (0..candidates.len()).collect::<Vec<_>>()
+        } else {
+            let pair = (0..candidates.len()).find(|&i| {
+                let candidate = candidates[i];
+                [pairs[i], candidate.keep, candidate.merge].contains(&selected)
+            });
+            match pair {
+                Some(i) => vec![i],
+                None => {
+                    self.reviewing = Some((report, candidates));
+                    return false;
+                }
+            }
+        };
+        // Removing from the back keeps the indices of the rest in place.
+        for &i in chosen.iter().rev() {
+            let candidate = candidates.remove(i);
+            if self
+                .store
+                .merge_into(candidate.merge, candidate.keep)
+                .is_none()
+            {
+                log::warn!("couldn't merge {:?}", candidate);
+            }
+            self.store.list_remove_at(report, i);
+        }
+        if candidates.is_empty() {
+            self.overlay_stack.pop();
+        } else {
+            self.reviewing = Some((report, candidates));
+        }
+        true
+    }
+
+    /// Shows `imported` in a preview layer, waiting for the user to confirm
+    /// or cancel the import. See [`crate::import`].
+    fn preview_image(&mut self, imported: ImportedImage) {
+        let in_use = self.store.memory_usage().total() as u64;
+        let message = match self.options.import_policy.warning(&imported, in_use) {
+            Some(warning) => format!("{}; {}", imported.describe(), warning),
+            None => imported.describe(),
+        };
+        let message = self.store.string(&message);
+        // The image has to be in the arena to be displayed, but it isn't
+        // inserted into any set until the import is confirmed.
+        let image = self.store.image(imported.image);
+        let mut layer = Layer::new(LayerKind::Preview, image);
+        layer.message = Some(message);
+        self.overlay_stack.push(layer);
+        self.rebuild_indication_tree();
+    }
+
+    /// Starts importing the file at `path` in the background, as text if it
+    /// is a text file (see [`text_import::is_text_file`]), as an image if it
+    /// is an image (see [`import::is_image_file`]), and as a blob otherwise,
+    /// unless it is too large for the memory budget (see
+    /// [`ImportPolicy::refusal`](crate::import::ImportPolicy::refusal)).
+    /// It is previewed once it has been read. Images are previewed right away
+    /// if they are linked to rather than read (see
+    /// [`ImportPolicy::link_images`](crate::import::ImportPolicy::link_images)).
+    fn import_file(&mut self, path: PathBuf) {
+        let name = format!("import {}", path.display());
+        let policy = self.options.import_policy;
+        if policy.link_images && import::is_image_file(&path) {
+            self.preview_linked_image(path);
+            return;
+        }
+        self.tasks.push(if text_import::is_text_file(&path) {
+            Task::spawn(name, move |progress| {
+                ImportedText::load(path, policy, progress).map(TaskOutput::ImportText)
+            })
+        } else if import::is_image_file(&path) {
+            Task::spawn(name, move |progress| {
+                ImportedImage::load(path, policy, progress).map(TaskOutput::Import)
+            })
+        } else {
+            let in_use = self.store.memory_usage().total() as u64;
+            Task::spawn(name, move |progress| {
+                let size = fs::metadata(&path).ok()?.len();
+                if let Some(refusal) = policy.refusal(size, in_use) {
+                    return Some(TaskOutput::Refused(refusal));
+                }
+                let blob = Blob::load(path, progress)?;
+                let hash = ContentHash::of_blob(&blob.bytes);
+                Some(TaskOutput::ImportBlob(blob, hash))
+            })
+        });
+    }
+
+    /// Shows a link to the image file at `path` in a preview layer, like
+    /// [`Renderer::preview_image`].
+    fn preview_linked_image(&mut self, path: PathBuf) {
+        // The link has to keep working whatever directory Kakoi is run from.
+        let path = fs::canonicalize(&path).unwrap_or(path);
+        let message = self.store.string(&format!("a link to {}", path.display()));
+        let image = self.store.image_ref(path);
+        let mut layer = Layer::new(LayerKind::Preview, image);
+        layer.message = Some(message);
+        self.overlay_stack.push(layer);
+        self.rebuild_indication_tree();
+    }
+
+    /// Shows `blob`, whose [`ContentHash`] is `hash`, in a preview layer, like
+    /// [`Renderer::preview_image`].
+    fn preview_blob(&mut self, blob: Blob, hash: ContentHash) {
+        let message = self.store.string(&blob.describe());
+        let blob = self.store.blob_hashed(blob, hash);
+        let mut layer = Layer::new(LayerKind::Preview, blob);
+        layer.message = Some(message);
+        self.overlay_stack.push(layer);
+        self.rebuild_indication_tree();
+    }
+
+    /// Shows `imported` in a preview layer, like [`Renderer::preview_image`].
+    fn preview_text(&mut self, imported: ImportedText) {
+        let message = self.store.string(&imported.describe());
+        // Like previewed images, the text is only included anywhere once the
+        // import is confirmed.
+        let list = imported.insert(&mut self.store);
+        let mut layer = Layer::new(LayerKind::Preview, list);
+        layer.message = Some(message);
+        self.overlay_stack.push(layer);
+        self.rebuild_indication_tree();
+    }
+
+    /// Performs [`CompleteAction::Drop`], changing nothing and returning
+    /// [`None`] if the value can't be dropped there.
+    fn drop_value(&mut self, from: usize, onto: usize, copy: bool) -> Option<()> {
+        let focus = self.overlay_stack.top().focus;
+        let value = self.indication_tree.selectable(from)?.key;
+        let target = self.indication_tree.selectable(onto)?.key;
+        let into_set = matches!(self.store.slot_map[target].structure, Structure::Set(_));
+        let in_list = match self.store.slot_map[focus].structure {
+            Structure::List(_) => true,
+            Structure::Set(_) => false,
+            _ => return None,
+        };
+        match (in_list, into_set, copy) {
+            // Lists can display the same set twice, but a set can't be put
+            // inside of itself.
+            _ if into_set && value == target => None,
+            (_, true, true) => self.store.set_insert_many(target, vec![value]),
+            (false, true, false) => self.store.set_move(value, focus, target),
+            (true, true, false) => self.store.list_move_to_set(focus, from, target),
+            (true, false, false) => self.store.list_move(focus, from, onto),
+            (true, false, true) => self.store.list_insert_at(focus, onto, value),
+            (false, false, _) => None,
+        }
+    }
+
+    /// Follows the mouse while a value is being dragged, showing the value
+    /// under a ghost and selecting the value it would be dropped onto. Returns
+    /// true if anything needs to be redrawn.
+    fn update_drag(&mut self) -> bool {
+        let mut drag = match self.drag {
+            Some(drag) => drag,
+            None => return false,
+        };
+        let (x, y) = self.cursor_position;
+        let distance = (x - drag.start.0).hypot(y - drag.start.1);
+        if !drag.dragging && distance < DRAG_THRESHOLD {
+            return false;
+        }
+        let started = !drag.dragging;
+        drag.dragging = true;
+        let onto = self
+            .indication_tree
+            .click_index(self.width, self.height, x, y)
+            .filter(|&onto| onto != drag.index);
+        let changed = started || onto != drag.onto;
+        drag.onto = onto;
+        self.drag = Some(drag);
+        if changed {
+            let dragged = self.indication_tree.selectable(drag.index);
+            self.overlay_stack
+                .set_ghost(dragged.map(|dragged| dragged.key));
+            self.overlay_stack.top_mut().selected = onto;
+            self.rebuild_indication_tree();
+        }
+        changed
+    }
+
+    /// Finishes dragging a value when the mouse button is released, dropping
+    /// it where it is (see [`CompleteAction::Drop`]). If the mouse didn't move
+    /// far enough to be dragging, the value is clicked on instead, displaying
+    /// it. Returns true if anything needs to be redrawn.
+    fn finish_drag(&mut self) -> bool {
+        let drag = match self.drag.take() {
+            Some(drag) => drag,
+            None => return false,
+        };
+        if !drag.dragging {
+            return match self.indication_tree.selectable(drag.index) {
+                Some(clicked) => {
+                    self.focus(clicked.key);
+                    self.rebuild_indication_tree();
+                    true
+                }
+                None => false,
+            };
+        }
+        // The ghost goes back to showing the value marked for moving, if
+        // there is one.
+        self.overlay_stack
+            .set_ghost(self.moving.map(|(_, value)| value));
+        match drag.onto {
+            Some(onto) => {
+                let copy = self.input_manager.control_pressed();
+                self.apply_action(Some(CompleteAction::Drop(drag.index, onto, copy)))
+            }
+            None => {
+                self.rebuild_indication_tree();
+                true
+            }
+        }
+    }
+
+    /// Shows `text` in the message line (see [`OverlayStack::message_line`])
+    /// until the next key press. Unlike a notice, it doesn't have to be
+    /// dismissed, so it suits saying what an action did.
+    fn tell(&mut self, text: &str) {
+        self.overlay_stack.set_message_line(Some(text.to_string()));
+    }
+
+    /// Shows `text` in a notice layer, until it is dismissed.
+    fn notify(&mut self, text: &str) -> Option<()> {
+        let notice = self.store.string(text);
+        self.store.leave_unstamped(notice);
+        self.overlay_stack
+            .push(Layer::new(LayerKind::Notice, notice))
+    }
+
+    /// Returns the sets that `action` would modify, if it were performed now.
+    /// Only sets are ever modified by more than rebinding a register, apart
+    /// from the strings inside of a set or list being rewritten, for which
+    /// the set or list is returned.
+    fn modified_sets(&mut self, action: &CompleteAction) -> Vec<ArenaKey> {
+        let top = *self.overlay_stack.top();
+        let sets = match action {
+            CompleteAction::ToggleStar => vec![self.store.register(STARRED_REGISTER)],
+            CompleteAction::Tag(tag) | CompleteAction::Untag(tag) => {
+                vec![self.store.register(TAGS_REGISTER), self.store.tag_set(tag)]
+            }
+            CompleteAction::Drop(_, onto, _) => vec![
+                Some(top.focus),
+                self.indication_tree
+                    .selectable(*onto)
+                    .map(|selection| selection.key),
+            ],
+            CompleteAction::MarkMove
+            | CompleteAction::SuggestGrouping
+            | CompleteAction::FlattenOnce => vec![Some(top.focus)],
+            CompleteAction::Rewrite(..) => vec![Some(top.focus)],
+            CompleteAction::RestoreVersion => {
+                vec![self.browsing_versions.map(|(owner, _)| owner)]
+            }
+            CompleteAction::Undo => self
+                .history
+                .next_undo()
+                .into_iter()
+                .map(Undo::container)
+                .collect(),
+            CompleteAction::Redo => self
+                .history
+                .next_redo()
+                .into_iter()
+                .map(Undo::container)
+                .collect(),
+            CompleteAction::JumpToCheckpoint(name) => self
+                .history
+                .changes_to(name.trim())
+                .into_iter()
+                .map(Undo::container)
+                .collect(),
+            CompleteAction::Confirm => match top.kind {
+                LayerKind::Preview => vec![self.store.register(".")],
+                LayerKind::Base => match self.moving {
+                    Some((from, _)) => vec![Some(from), Some(top.focus)],
+                    None => vec![],
+                },
+                LayerKind::Suggestion
+                    if self
+                        .reviewing
+                        .as_ref()
+                        .map_or(false, |(report, _)| *report == top.focus) =>
+                {
+                    let (_, candidates) = self.reviewing.as_ref().unwrap();
+                    candidates
+                        .iter()
+                        .flat_map(|candidate| &self.store.slot_map[candidate.merge].inclusions)
+                        .map(|&(container, _)| Some(container))
+                        .collect()
+                }
+                LayerKind::Suggestion | LayerKind::Confirmation => {
+                    vec![self
+                        .overlay_stack
+                        .iter()
+                        .rev()
+                        .nth(1)
+                        .map(|layer| layer.focus)]
+                }
+                _ => vec![],
+            },
+            _ => perform::modified(&mut self.store, action)
+                .into_iter()
+                .map(Some)
+                .collect(),
+        };
+        sets.into_iter().flatten().collect()
+    }
+
+    /// Returns why `action` can't be performed, if it would modify something
+    /// that can't be modified: anything at all in read-only mode, or anything
+    /// locked (see [`Arena::is_locked`]).
+    fn rejection(&mut self, action: &CompleteAction) -> Option<&'static str> {
+        let sets = self.modified_sets(action);
+        let mutates = match action {
+            CompleteAction::Confirm => !sets.is_empty(),
+            _ => action.mutates(),
+        };
+        perform::rejection(&self.store, self.read_only, mutates, &sets)
+    }
+
+    /// Repairs the inclusions of every value in the arena, logging each
+    /// inconsistency that had to be fixed. See [`Arena::repair`].
+    fn validate(&mut self) {
+        for inconsistency in self.store.repair() {
+            log::warn!("repaired arena inconsistency: {:?}", inconsistency);
+        }
+    }
+
+    /// Performs `complete_action`, if there is one, and rebuilds the spatial
+    /// tree if anything changed. Returns whether or not the screen needs to be
+    /// redrawn.
+    fn apply_action(&mut self, complete_action: Option<CompleteAction>) -> bool {
+        let rejection = complete_action
+            .as_ref()
+            .and_then(|action| self.rejection(action));
+        if let Some(reason) = rejection {
+            log::info!("rejected {:?}: {}", complete_action, reason);
+            self.notify(reason);
+            self.rebuild_indication_tree();
+            return true;
+        }
+        // Found before the action is performed, since performing it can change
+        // what the topmost layer displays.
+        let changing = complete_action
+            .as_ref()
+            .map(|action| self.modified_sets(action))
+            .unwrap_or_default();
+        let mutates = match &complete_action {
+            Some(CompleteAction::Confirm) => !changing.is_empty(),
+            Some(action) => action.mutates(),
+            None => false,
+        };
+        let before = match &complete_action {
+            Some(action) => self.before(action, &changing),
+            None => Before::default(),
+        };
+        let fingerprints = Fingerprints::take(&self.store, &changing);
+        // These change what the arena remembers about values (or undo and
+        // redo that) rather than the values themselves, which fingerprints
+        // can't tell apart.
+        let remembered = matches!(
+            complete_action,
+            Some(CompleteAction::ToggleLock)
+                | Some(CompleteAction::CycleValueLayout)
+                | Some(CompleteAction::SetValueLayout(_))
+                | Some(CompleteAction::Rotate(_))
+                | Some(CompleteAction::Undo)
+                | Some(CompleteAction::Redo)
+                | Some(CompleteAction::JumpToCheckpoint(_))
+        );
+        // Left open if performing the action panics, so that
+        // `Renderer::rescue` can roll it back.
+        let transaction = mutates
+            && complete_action
+                .as_ref()
+                .map_or(false, CompleteAction::is_compound)
+            && self.store.begin().is_some();
+        let should_rebuild = match complete_action {
+            Some(complete_action) => {
+                let _span =
+                    tracing::debug_span!("apply_action", action = ?complete_action).entered();
+                crash::record(&format!("{:?}", complete_action));
+                match complete_action {
+                    CompleteAction::BindRegisterToRegisterValue(ref to_be_bound, _)
+                        if to_be_bound == "." =>
+                    {
+                        self.selected_node_history
+                            .push(self.store.register(".").unwrap());
+                        self.perform(&complete_action)
+                    }
+                    CompleteAction::FindInto(..) => {
+                        self.update_search_index();
+                        self.perform(&complete_action)
+                    }
+                    CompleteAction::SetInsert(..)
+                    | CompleteAction::SetUnion(..)
+                    | CompleteAction::SetRemove(..)
+                    | CompleteAction::ToggleMembership(..)
+                    | CompleteAction::BindRegisterToEmptySet(_)
+                    | CompleteAction::BindRegisterToEmptyList(_)
+                    | CompleteAction::ListPush(..)
+                    | CompleteAction::ListPop(_)
+                    | CompleteAction::ListInsertAt(..)
+                    | CompleteAction::ListRemoveAt(..)
+                    | CompleteAction::ListSwap(..)
+                    | CompleteAction::ListMove(..)
+                    | CompleteAction::InsertStringIntoSetRegister(..)
+                    | CompleteAction::InsertNumberIntoSetRegister(..)
+                    | CompleteAction::Arithmetic(..)
+                    | CompleteAction::CaptureString(_)
+                    | CompleteAction::BindRegisterToRegisterValue(..)
+                    | CompleteAction::BindRegisterToString(..) => self.perform(&complete_action),
+                    CompleteAction::SelectRegister(register) => self
+                        .store
+                        .register(register)
+                        .map(|key| self.focus(key))
+                        .is_some(),
+                    CompleteAction::Back if self.overlay_stack.is_modal() => {
+                        self.overlay_stack.pop().is_some()
+                    }
+                    CompleteAction::Back => self
+                        .selected_node_history
+                        .pop()
+                        .map(|selected_index| {
+                            self.store.bind_register(".", selected_index);
+                        })
+                        .is_some(),
+                    CompleteAction::Registers => {
+                        self.focus(self.store.register_map);
+                        true
+                    }
+                    CompleteAction::PushOverlay(register) => self
+                        .store
+                        .register(register)
+                        .and_then(|key| self.overlay_stack.push(Layer::new(LayerKind::Panel, key)))
+                        .is_some(),
+                    CompleteAction::PopOverlay => {
+                        self.overlay_stack.pop().is_some() || self.cancel_move()
+                    }
+                    CompleteAction::Query(text) => match Query::parse(&text) {
+                        Some(query) => {
+                            // Run before it is saved, so that it doesn't
+                            // find itself. Queries can still be run when they
+                            // can't be saved.
+                            let shown = self.show_query_results(&text, &query).is_some();
+                            let saved = self.store.register(QUERIES_REGISTER);
+                            if !self.read_only
+                                && !saved.map_or(false, |set| self.store.is_locked(set))
+                            {
+                                self.store.save_query(text.as_str());
+                            }
+                            shown
+                        }
+                        None => {
+                            log::warn!("can't parse query '{}'", text);
+                            false
+                        }
+                    },
+                    CompleteAction::RunQuery => {
+                        let top = self.overlay_stack.top();
+                        let key = top
+                            .selected
+                            .and_then(|selected| self.indication_tree.selectable(selected))
+                            .map_or(top.focus, |selection| selection.key);
+                        let text = match &self.store.slot_map[key].structure {
+                            Structure::String(text) => Some(text.to_string()),
+                            _ => None,
+                        };
+                        match text.map(|text| (Query::parse(&text), text)) {
+                            Some((Some(query), text)) => {
+                                self.show_query_results(&text, &query).is_some()
+                            }
+                            Some((None, text)) => {
+                                log::warn!("can't parse query '{}'", text);
+                                false
+                            }
+                            None => false,
+                        }
+                    }
+                    CompleteAction::CycleValueLayout => {
+                        let top = self.overlay_stack.top();
+                        let key = top
+                            .selected
+                            .and_then(|selected| self.indication_tree.selectable(selected))
+                            .map_or(top.focus, |selection| selection.key);
+                        let layout = LayoutStrategy::next_override(self.store.layout_override(key));
+                        self.store.set_layout_override(key, layout);
+                        true
+                    }
+                    CompleteAction::SetValueLayout(layout) => {
+                        match LayoutStrategy::parse_override(&layout) {
+                            Some(layout) => {
+                                let key = self.selected_or_focus();
+                                self.store.set_layout_override(key, layout);
+                                true
+                            }
+                            None => {
+                                log::info!("{} isn't a layout", layout);
+                                false
+                            }
+                        }
+                    }
+                    CompleteAction::Rotate(steps) => {
+                        let key = self.selected_or_focus();
+                        let step = std::f32::consts::PI / 6.0;
+                        let rotation = self.store.rotation(key) + steps as f32 * step;
+                        self.store.set_rotation(key, rotation);
+                        true
+                    }
+                    CompleteAction::MarkMove => {
+                        let top = self.overlay_stack.top();
+                        let marked = top
+                            .selected
+                            .and_then(|selected| self.indication_tree.selectable(selected));
+                        match marked {
+                            Some(marked)
+                                if !self.overlay_stack.is_modal()
+                                    && matches!(
+                                        self.store.slot_map[top.focus].structure,
+                                        Structure::Set(_)
+                                    ) =>
+                            {
+                                self.moving = Some((top.focus, marked.key));
+                                self.overlay_stack.set_ghost(Some(marked.key));
+                                true
+                            }
+                            _ => false,
+                        }
+                    }
+                    CompleteAction::ToggleLayout => {
+                        let layer = self.overlay_stack.top_mut();
+                        layer.layout = layer.layout.next();
+                        true
+                    }
+                    CompleteAction::ToggleSharing => {
+                        self.show_sharing = !self.show_sharing;
+                        true
+                    }
+                    CompleteAction::ToggleHeatmap => {
+                        self.show_heatmap = !self.show_heatmap;
+                        if self.show_heatmap {
+                            self.describe_costs();
                         }
-                        CompleteAction::SetRemove(set_register, removal_register) => self
-                            .store
-                            .set_remove(set_register, removal_register)
-                            .is_some(),
-                        CompleteAction::InsertStringIntoSetRegister(register, string) => {
-                            self.store.set_insert_string(register, string).is_some()
-                        }
-                        CompleteAction::SelectRegister(register) => {
-                            self.selected_node_history
-                                .push(self.store.register(".").unwrap());
-                            self.store
-                                .bind_register_to_register_value(".".into(), register);
+                        true
+                    }
+                    CompleteAction::Export(path) => {
+                        let path = path.trim();
+                        let value = self.selected_or_focus();
+                        let exported = if html::is_html_path(path) {
+                            html::export(&self.store, value, path).map(|page| page.describe(path))
+                        } else if json::is_json_path(path) {
+                            json::export(&self.store, path)
+                                .map(|count| format!("exported {} values to {}", count, path))
+                        } else {
+                            export::export(&self.store, value, path, OutlineFormat::from_path(path))
+                                .map(|outline| outline.describe(path))
+                        };
+                        let message = match exported {
+                            Ok(description) => description,
+                            Err(e) => format!("couldn't export to {}: {}", path, e),
+                        };
+                        self.notify(&message).is_some()
+                    }
+                    CompleteAction::ExportLayout(path) => {
+                        let path = path.trim();
+                        let message = match self.export_layout(path) {
+                            Ok(count) => format!("exported {} nodes to {}", count, path),
+                            Err(e) => format!("couldn't export to {}: {}", path, e),
+                        };
+                        self.notify(&message).is_some()
+                    }
+                    CompleteAction::ImportJson(register, path) => {
+                        let path = path.trim();
+                        let message = match json::import(&mut self.store, path, &register) {
+                            Ok(count) => format!(
+                                "imported {} new values from {} into {}",
+                                count, path, register
+                            ),
+                            Err(e) => format!("couldn't import {}: {}", path, e),
+                        };
+                        self.notify(&message).is_some()
+                    }
+                    CompleteAction::Diff(register, path) => {
+                        let path = path.trim();
+                        let message = match json::load(path) {
+                            Ok(old) => {
+                                let changes = arena::diff(&old, &self.store);
+                                let view = self.store.diff_view(&old, &changes);
+                                self.store.bind_register(register.as_str(), view);
+                                format!("{} changes since {}, in {}", changes.len(), path, register)
+                            }
+                            Err(e) => format!("couldn't read {}: {}", path, e),
+                        };
+                        self.notify(&message).is_some()
+                    }
+                    CompleteAction::ScaleUi(steps) => {
+                        let (min, max) = UI_SCALE_RANGE;
+                        self.ui_scale = (self.ui_scale * UI_SCALE_STEP.powi(steps))
+                            .max(min)
+                            .min(max);
+                        self.text_renderer.set_pixel_scale(self.pixel_scale());
+                        self.notify(&format!("UI scale: {:.0}%", 100.0 * self.ui_scale))
+                            .is_some()
+                    }
+                    CompleteAction::Pin(slot) => {
+                        let focus = self.overlay_stack.top().focus;
+                        self.store.pin(slot, focus).is_some()
+                    }
+                    CompleteAction::RefocusPin(slot) => match self.store.pinned(slot) {
+                        Some(pinned) => {
+                            self.focus(pinned);
                             true
                         }
-                        CompleteAction::BindRegisterToRegisterValue(to_be_bound, to_lookup) => {
-                            if to_be_bound == "." {
-                                self.selected_node_history
-                                    .push(self.store.register(".").unwrap());
+                        None => false,
+                    },
+                    CompleteAction::ToggleFullscreen => {
+                        self.window_request = Some(WindowRequest::ToggleFullscreen);
+                        // The window redraws itself once it has changed.
+                        false
+                    }
+                    CompleteAction::Minimize => {
+                        self.window_request = Some(WindowRequest::Minimize);
+                        false
+                    }
+                    CompleteAction::SetWindowSize(size) => {
+                        // Window sizes are written like capture resolutions.
+                        match CaptureRequest::parse(&size) {
+                            Some(CaptureRequest { width, height }) => {
+                                self.window_request = Some(WindowRequest::Resize(width, height))
                             }
-                            self.store
-                                .bind_register_to_register_value(to_be_bound, to_lookup);
+                            None => log::warn!("can't resize the window to '{}'", size),
+                        }
+                        false
+                    }
+                    CompleteAction::CycleUsageOrder => {
+                        if self.store.is_tracking_usage() {
+                            let order = UsageOrder::next(self.store.usage_order());
+                            self.store.set_usage_order(order);
+                            self.notify(&format!("arranging sets {}", UsageOrder::describe(order)))
+                                .is_some()
+                        } else {
+                            self.notify(
+                                "usage isn't being tracked (start kakoi with --track-usage)",
+                            )
+                            .is_some()
+                        }
+                    }
+                    CompleteAction::Explore => {
+                        // These are told on the message line, since a notice
+                        // would be modal and hold up the walk until dismissed.
+                        if self.exploring.take().is_some() {
+                            self.tell("stopped exploring");
+                        } else {
+                            // Seed with the time, so that each walk goes
+                            // somewhere different.
+                            let seed = self.started.elapsed().as_nanos() as u64;
+                            self.exploring = Some(Exploration::new(seed, Instant::now()));
+                            let message = format!(
+                                "exploring (press {} again to stop)",
+                                self.input_manager.key_label("f10")
+                            );
+                            self.tell(&message);
+                        }
+                        true
+                    }
+                    CompleteAction::Compact => {
+                        let garbage = self.collect_garbage();
+                        let report = self.store.compact();
+                        let textures = self.image_renderer.compact();
+                        log::info!(
+                            "collected {} values and compacted the arena: {:?}",
+                            garbage,
+                            report
+                        );
+                        self.notify(&format!(
+                            "removed {} unreachable values; compacted {}; released {} unused image textures",
+                            garbage,
+                            report.describe(),
+                            textures
+                        ))
+                        .is_some()
+                    }
+                    CompleteAction::Inspect => {
+                        let key = self.selected_or_focus();
+                        self.inspect(key).is_some()
+                    }
+                    CompleteAction::InspectPan(dx, dy) => match self.inspecting {
+                        Some((image, view)) => {
+                            let view = view
+                                .pan(dx as f32 * INSPECT_PAN_STEP, dy as f32 * INSPECT_PAN_STEP);
+                            self.set_inspection_view(image, view);
                             true
                         }
-                        CompleteAction::BindRegisterToString(register, string) => {
-                            self.store.bind_register_to_string(register, string);
+                        None => false,
+                    },
+                    CompleteAction::InspectZoom(steps) => match self.inspecting {
+                        Some((image, view)) => {
+                            let view = view.zoom_by(INSPECT_ZOOM_STEP.powi(steps));
+                            self.set_inspection_view(image, view);
                             true
                         }
-                        CompleteAction::Back => self
-                            .selected_node_history
-                            .pop()
-                            .map(|selected_index| {
-                                self.store.bind_register(".", selected_index);
-                            })
-                            .is_some(),
-                        CompleteAction::Registers => {
-                            self.selected_node_history
-                                .push(self.store.register(".").unwrap());
-                            self.store.bind_register(".", self.store.register_map);
+                        None => false,
+                    },
+                    CompleteAction::InspectActualSize => {
+                        match self.inspecting.and_then(|(image, view)| {
+                            Some((image, view, self.inspection_scale(image)?))
+                        }) {
+                            Some((image, view, scale)) => {
+                                let actual_size = 1.0 / scale;
+                                // Switching back fits the whole image on
+                                // screen again.
+                                let zoom = if (view.zoom - actual_size).abs() < 0.01 * actual_size {
+                                    1.0
+                                } else {
+                                    actual_size
+                                };
+                                self.set_inspection_view(image, view.with_zoom(zoom));
+                                true
+                            }
+                            None => false,
+                        }
+                    }
+                    CompleteAction::StopInspecting => self.stop_inspecting(),
+                    CompleteAction::Validate => {
+                        self.validate();
+                        true
+                    }
+                    CompleteAction::RestartTutorial => {
+                        let binding_mode = self.input_manager.binding_mode();
+                        let tutorial = tutorial::build(&mut self.store, binding_mode);
+                        self.focus(tutorial);
+                        true
+                    }
+                    CompleteAction::SelectNext => {
+                        let count = self.indication_tree.selectable_count();
+                        self.overlay_stack.top_mut().select_next(count);
+                        true
+                    }
+                    CompleteAction::Descend => self
+                        .overlay_stack
+                        .top()
+                        .selected
+                        .and_then(|selected| self.indication_tree.selectable(selected))
+                        .map(|selection| self.focus(selection.key))
+                        .is_some(),
+                    CompleteAction::CancelTasks => {
+                        self.tasks.iter().for_each(Task::cancel);
+                        !self.tasks.is_empty()
+                    }
+                    CompleteAction::Capture(resolution) => {
+                        match CaptureRequest::parse(&resolution) {
+                            Some(request) => self.capture_request = Some(request),
+                            None => log::warn!("can't capture at '{}'", resolution),
+                        }
+                        // Nothing on screen changes.
+                        false
+                    }
+                    CompleteAction::OpenUrl => {
+                        let top = self.overlay_stack.top();
+                        let key = top
+                            .selected
+                            .and_then(|selected| self.indication_tree.selectable(selected))
+                            .map_or(top.focus, |selection| selection.key);
+                        let url = match (
+                            self.store.string_class(key),
+                            &self.store.slot_map[key].structure,
+                        ) {
+                            (StringClass::Url, Structure::String(url)) => url.clone(),
+                            (_, Structure::Custom(custom)) => {
+                                match custom.leaf.open_external() {
+                                    Some(Err(e)) => {
+                                        log::warn!("couldn't open {:?}: {}", custom.leaf, e)
+                                    }
+                                    Some(Ok(())) => {}
+                                    None => log::info!(
+                                        "{} values can't be opened",
+                                        custom.leaf.type_name()
+                                    ),
+                                }
+                                // Nothing on screen changes.
+                                return false;
+                            }
+                            _ => return false,
+                        };
+                        match DeepLink::parse(&url) {
+                            Some(link) => self.follow_link(&link),
+                            None => {
+                                if let Err(e) = string_class::open_url(&url) {
+                                    log::warn!("couldn't open {}: {}", url, e);
+                                }
+                                // Nothing on screen changes.
+                                false
+                            }
+                        }
+                    }
+                    CompleteAction::CopyLink => {
+                        let key = self.selected_or_focus();
+                        let link = DeepLink::new(self.store.uuid(key).unwrap()).to_string();
+                        let message = match string_class::copy_to_clipboard(&link) {
+                            Ok(()) => format!("copied {}", link),
+                            Err(e) => {
+                                log::warn!("couldn't copy {}: {}", link, e);
+                                format!("couldn't copy {}: {}", link, e)
+                            }
+                        };
+                        self.notify(&message).is_some()
+                    }
+                    CompleteAction::ToggleSample => {
+                        let top = self.overlay_stack.top_mut();
+                        if top.sample.take().is_some() {
+                            top.message = None;
+                            true
+                        } else if top.message.is_none()
+                            && matches!(self.store.slot_map[top.focus].structure, Structure::Set(_))
+                        {
+                            // Seed with the set's uuid, so that each set has its
+                            // own first sample.
+                            let seed = self.store.uuid(top.focus).unwrap().as_u128() as u64;
+                            top.sample = Some(Sample::new(seed));
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    CompleteAction::Reshuffle => {
+                        let top = self.overlay_stack.top_mut();
+                        top.sample = top.sample.map(Sample::reshuffle);
+                        top.sample.is_some()
+                    }
+                    CompleteAction::SuggestGrouping => {
+                        let focus = self.overlay_stack.top().focus;
+                        match grouping::suggest(&self.store, focus) {
+                            Some(groups) => self.suggest_grouping(groups).is_some(),
+                            None => {
+                                log::info!("no grouping to suggest");
+                                false
+                            }
+                        }
+                    }
+                    CompleteAction::FlattenOnce => {
+                        let focus = self.overlay_stack.top().focus;
+                        self.preview_flatten(focus).is_some()
+                    }
+                    CompleteAction::FindDuplicates if self.is_running(dedup::TASK_NAME) => {
+                        self.notify("still looking for duplicates").is_some()
+                    }
+                    CompleteAction::FindDuplicates => {
+                        let snapshot = dedup::Snapshot::take(&self.store);
+                        self.tasks
+                            .push(Task::spawn(dedup::TASK_NAME, move |progress| {
+                                dedup::find(snapshot, progress).map(TaskOutput::Duplicates)
+                            }));
+                        true
+                    }
+                    CompleteAction::Rewrite(pattern, replacement) => {
+                        let focus = self.overlay_stack.top().focus;
+                        match Regex::new(&pattern) {
+                            Ok(regex) => self.preview_rewrite(focus, &regex, &replacement),
+                            Err(_) => self
+                                .notify(&format!("{} isn't a valid pattern", pattern))
+                                .is_some(),
+                        }
+                    }
+                    CompleteAction::TogglePowerSaving => {
+                        self.power = self.power.toggled();
+                        self.apply_power_state();
+                        self.notify(match self.power {
+                            PowerState::Saving => "power saving is on",
+                            PowerState::Normal => "power saving is off",
+                        })
+                        .is_some()
+                    }
+                    CompleteAction::ToggleReadOnly => {
+                        self.read_only = !self.read_only;
+                        self.notify(if self.read_only {
+                            "read-only mode is on"
+                        } else {
+                            "read-only mode is off"
+                        })
+                        .is_some()
+                    }
+                    CompleteAction::ToggleLock => {
+                        let key = self.selected_or_focus();
+                        let locked = !self.store.is_locked_directly(key);
+                        self.store.set_locked(key, locked);
+                        self.history.record(Undo::Lock(key, !locked));
+                        true
+                    }
+                    CompleteAction::ToggleStar => {
+                        let key = self.selected_or_focus();
+                        self.store.toggle_star(key);
+                        true
+                    }
+                    CompleteAction::Tag(tag) => {
+                        let key = self.selected_or_focus();
+                        self.store.tag(key, &tag).is_some()
+                    }
+                    CompleteAction::Untag(tag) => {
+                        let key = self.selected_or_focus();
+                        match self.store.untag(key, &tag) {
+                            Some(()) => true,
+                            None => {
+                                log::info!("that value isn't tagged #{}", tag_name(&tag));
+                                false
+                            }
+                        }
+                    }
+                    CompleteAction::ShowTag(tag) => self.show_tagged(&tag).is_some(),
+                    CompleteAction::Versions => self.show_versions().is_some(),
+                    CompleteAction::RestoreVersion => {
+                        let notice = format!(
+                            "select one of the earlier versions shown by {}",
+                            self.input_manager.key_label("control+v")
+                        );
+                        self.restore_version().is_some() || self.notify(&notice).is_some()
+                    }
+                    CompleteAction::Highlight(text) if text.trim().is_empty() => {
+                        self.matches.clear();
+                        self.current_match = None;
+                        self.overlay_stack.set_highlights(None);
+                        true
+                    }
+                    CompleteAction::Highlight(text) => match Query::parse(&text) {
+                        Some(query) => {
+                            if !self.highlight(&query) {
+                                self.notify(&format!("nothing matches {}", text));
+                            }
                             true
                         }
+                        None => {
+                            log::warn!("can't parse query '{}'", text);
+                            false
+                        }
                     },
-                    None => false,
-                };
-                if should_rebuild {
-                    self.rebuild_indication_tree();
-                    true
+                    CompleteAction::Drop(from, onto, copy) => {
+                        match self.drop_value(from, onto, copy) {
+                            Some(()) => true,
+                            None => {
+                                log::info!("can't drop that there");
+                                false
+                            }
+                        }
+                    }
+                    CompleteAction::NextMatch => self.step_match(1),
+                    CompleteAction::PreviousMatch => self.step_match(-1),
+                    CompleteAction::Undo => self.travel(|history, put_back| history.undo(put_back)),
+                    CompleteAction::Redo => self.travel(|history, put_back| history.redo(put_back)),
+                    CompleteAction::Checkpoint(name) => {
+                        self.history.checkpoint(name.trim());
+                        self.notify(&format!("marked this point as {}", name.trim()))
+                            .is_some()
+                    }
+                    CompleteAction::JumpToCheckpoint(name) => {
+                        let name = name.trim();
+                        match self.travel(|history, put_back| history.jump(name, put_back)) {
+                            Some(steps) => self
+                                .notify(&format!("went {} steps to {}", steps, name))
+                                .is_some(),
+                            None => self
+                                .notify(&format!("there's no checkpoint named {}", name))
+                                .is_some(),
+                        }
+                    }
+                    CompleteAction::Checkpoints => self.list_checkpoints(),
+                    CompleteAction::Confirm => {
+                        let top = *self.overlay_stack.top();
+                        match top.kind {
+                            LayerKind::Preview => {
+                                self.store.set_insert_value(".", top.focus).is_some()
+                                    && self.overlay_stack.pop().is_some()
+                            }
+                            LayerKind::Base => match self.moving {
+                                Some((from, value)) => {
+                                    match self.store.set_move(value, from, top.focus) {
+                                        Some(()) => self.cancel_move(),
+                                        None => {
+                                            log::info!("can't move the marked value here");
+                                            false
+                                        }
+                                    }
+                                }
+                                None => false,
+                            },
+                            LayerKind::Suggestion
+                                if self
+                                    .reviewing
+                                    .as_ref()
+                                    .map_or(false, |(report, _)| *report == top.focus) =>
+                            {
+                                self.merge_duplicates()
+                            }
+                            LayerKind::Suggestion | LayerKind::Confirmation => {
+                                self.overlay_stack.pop();
+                                let set = self.overlay_stack.top().focus;
+                                match self.rewriting.take() {
+                                    Some((preview, container, edits)) if preview == top.focus => {
+                                        self.rewrite_strings(container, edits)
+                                    }
+                                    _ => self.replace_set(set, top.focus).is_some(),
+                                }
+                            }
+                            LayerKind::Notice => self.overlay_stack.pop().is_some(),
+                            _ => false,
+                        }
+                    }
+                }
+            }
+            None => false,
+        };
+        if transaction {
+            self.store.commit();
+        }
+        if should_rebuild {
+            if !before.is_empty() {
+                self.record_changes(before);
+            }
+            let changed = fingerprints.changed(&self.store);
+            // Moving around rebinds `.`, which isn't a change.
+            let rebound = mutates && fingerprints.rebound(&self.store);
+            self.dirty |= !changed.is_empty() || rebound || (mutates && remembered);
+            let now = Instant::now();
+            for key in changed {
+                self.store.record_change(key, now);
+            }
+            if mutates
+                && self.options.collect_garbage
+                && self.store.slot_map.len() >= self.collected_at * GARBAGE_GROWTH
+            {
+                let garbage = self.collect_garbage();
+                // The textures of collected images would otherwise stay
+                // uploaded until the arena is compacted by hand.
+                let textures = if garbage > 0 {
+                    self.image_renderer.compact()
                 } else {
-                    false
+                    0
+                };
+                log::info!(
+                    "collected {} values and released {} image textures",
+                    garbage,
+                    textures
+                );
+            }
+            if let Some(pruning) = self.options.prune_orphans.filter(|_| mutates) {
+                let pruned = self.prune_orphans(pruning);
+                if pruned > 0 {
+                    log::info!("pruned {} orphaned values", pruned);
                 }
             }
-            WindowEvent::MouseInput { button, state, .. } if *state == ElementState::Pressed => {
-                match button {
-                    MouseButton::Left => {
-                        self.indication_tree
-                            .click(
-                                self.width,
-                                self.height,
-                                self.cursor_position.0,
-                                self.cursor_position.1,
-                            )
-                            .map(|selected_index| {
-                                self.selected_node_history
-                                    .push(self.store.register(".").unwrap());
-                                // self.selected_index = selected_index;
-                                self.store.bind_register(".", selected_index);
-                                self.rebuild_indication_tree();
-                                true
-                            })
-                            .unwrap_or(false)
-                    }
-                    _ => false,
+            self.store.stamp_metadata(SystemTime::now());
+            if self.options.validate {
+                self.validate();
+            }
+            self.rebuild_indication_tree();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Applies the action completed by a key press or character, if there is
+    /// one, and redraws the string being entered and the minibuffer if they
+    /// changed. Returns true if anything needs to be redrawn.
+    fn apply_input(&mut self, complete_action: Option<CompleteAction>) -> bool {
+        let prompted = self.update_minibuffer();
+        let typed = self.update_provisional() || prompted;
+        let applied = self.apply_action(complete_action);
+        if typed && !applied {
+            self.rebuild_indication_tree();
+        }
+        typed || applied
+    }
+
+    pub fn input<'a>(&mut self, event: &winit::event::WindowEvent) -> bool {
+        use winit::event::*;
+        let _span = tracing::trace_span!("input").entered();
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => {
+                // Pressing a key dismisses whatever the message line said.
+                let told = input.state == ElementState::Pressed
+                    && self.overlay_stack.set_message_line(None).is_some();
+                // Registers are bound and unbound all the time, so the names
+                // to complete are refreshed on every key press.
+                let registers = self
+                    .store
+                    .registers()
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                self.input_manager.set_register_names(registers);
+                let complete_action = {
+                    let _span = tracing::trace_span!("process_input").entered();
+                    self.input_manager.process_input(input)
+                };
+                let applied = self.apply_input(complete_action);
+                if told && !applied {
+                    self.rebuild_indication_tree();
                 }
+                applied || told
             }
+            WindowEvent::ReceivedCharacter(character) => {
+                let complete_action = self.input_manager.process_character(*character);
+                self.apply_input(complete_action)
+            }
+            WindowEvent::DroppedFile(path) => {
+                self.import_file(path.clone());
+                true
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Left,
+                state,
+                ..
+            } => match state {
+                // Values are displayed when the button is released rather
+                // than when it is pressed, since pressing it might start a
+                // drag instead.
+                ElementState::Pressed => {
+                    self.drag = self
+                        .indication_tree
+                        .click_index(
+                            self.width,
+                            self.height,
+                            self.cursor_position.0,
+                            self.cursor_position.1,
+                        )
+                        .map(|index| Drag {
+                            index,
+                            start: self.cursor_position,
+                            dragging: false,
+                            onto: None,
+                        });
+                    false
+                }
+                ElementState::Released => self.finish_drag(),
+            },
             WindowEvent::CursorMoved { position, .. } => {
                 self.cursor_position = (position.x as f32, position.y as f32);
+                self.update_drag();
                 true
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scrolled += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                // Scrolling down turns the carousel forwards.
+                let steps = -self.scrolled.trunc();
+                self.scrolled = self.scrolled.fract();
+                self.turn_carousel(steps as i32)
+            }
             _ => false,
         }
     }
@@ -0,0 +1,226 @@
+use super::circle::{CircleConstraintInstance, Vertex};
+use super::context::{ColorUniform, RenderContext};
+use crate::spatial_bound::SpatialBound;
+use crate::sphere::Sphere;
+use wgpu::util::DeviceExt;
+
+/// How much larger than the selected value the ring is, at the smallest and
+/// largest points of its pulse.
+const MIN_SCALE: f32 = 1.04;
+const MAX_SCALE: f32 = 1.08;
+
+/// The color of the ring at the dimmest and brightest points of its pulse, in
+/// linear RGB.
+const DIM_COLOR: [f32; 3] = [0.6, 0.3, 0.0];
+const BRIGHT_COLOR: [f32; 3] = [1.0, 0.55, 0.05];
+
+/// Seconds taken by one pulse of the ring.
+const PULSE_PERIOD: f32 = 1.6;
+
+/// Returns how far through its pulse the ring is after `seconds`, from zero
+/// (smallest and dimmest) to one (largest and brightest).
+fn pulse(seconds: f32) -> f32 {
+    0.5 - 0.5 * (seconds / PULSE_PERIOD * 2.0 * std::f32::consts::PI).cos()
+}
+
+/// Returns the smallest sphere surrounding `bound`.
+pub(super) fn sphere_around(bound: &SpatialBound) -> Sphere {
+    match bound {
+        SpatialBound::Sphere(sphere) => *sphere,
+        SpatialBound::SquareCuboid(cuboid) => {
+            let (width, height) = cuboid.dimensions_2d();
+            Sphere {
+                center: cuboid.center,
+                radius: 0.5 * (width * width + height * height).sqrt(),
+            }
+        }
+    }
+}
+
+/// Draws a pulsing ring around the selected value of the topmost
+/// [`Layer`](crate::overlay::Layer), so that it is clear which value
+/// [`CompleteAction::Descend`] will act on.
+///
+/// The ring is drawn on top of everything else, after all of the
+/// [`DrawOrder`] batches. Like rectangles, it isn't multisampled. While there
+/// is a selection, the ring changes every frame, so the window keeps redrawing
+/// (see [`SelectionRenderer::is_animating`]).
+///
+/// [`CompleteAction::Descend`]: crate::input_manager::CompleteAction::Descend
+/// [`DrawOrder`]: crate::render::draw_order::DrawOrder
+pub struct SelectionRenderer {
+    selection: Option<Sphere>,
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    instance_buffer: wgpu::Buffer,
+    color: ColorUniform,
+}
+
+impl SelectionRenderer {
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        context: &'a RenderContext,
+    ) -> Self {
+        let vertices = Vertex::make_circle(200, 0.96, 1.0);
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("selection renderer vertex buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("selection renderer instance buffer"),
+            size: std::mem::size_of::<CircleConstraintInstance>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.vert.spv"));
+        let fs_module = device
+            .create_shader_module(&wgpu::include_spirv!("../shaders/build/selection.frag.spv"));
+
+        let color = ColorUniform::new(
+            device,
+            context,
+            [DIM_COLOR[0], DIM_COLOR[1], DIM_COLOR[2], 1.0],
+            "selection renderer color uniform",
+        );
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("selection renderer pipeline layout"),
+                bind_group_layouts: &[context.camera_layout(), context.color_layout()],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("selection renderer pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc(), CircleConstraintInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc_desc.format,
+                    alpha_blend: wgpu::BlendState::REPLACE,
+                    color_blend: wgpu::BlendState::REPLACE,
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            selection: None,
+            render_pipeline,
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            instance_buffer,
+            color,
+        }
+    }
+
+    /// Surrounds `bound` with the ring, or hides the ring if there is no
+    /// selection.
+    pub fn select(&mut self, bound: Option<SpatialBound>) {
+        self.selection = bound.as_ref().map(sphere_around);
+    }
+
+    /// Returns true if the ring is visible, in which case it needs to be
+    /// redrawn every frame to animate it.
+    pub fn is_animating(&self) -> bool {
+        self.selection.is_some()
+    }
+
+    /// Draws the ring as it looks `seconds` after Kakoi started.
+    pub fn render<'a>(
+        &mut self,
+        queue: &'a mut wgpu::Queue,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        texture_view: &'a wgpu::TextureView,
+        context: &'a RenderContext,
+        seconds: f32,
+    ) {
+        let selection = match self.selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        let _span = tracing::trace_span!("selection_render").entered();
+
+        let pulse = pulse(seconds);
+        let lerp = |min: f32, max: f32| min + (max - min) * pulse;
+        let ring = Sphere {
+            center: selection.center,
+            radius: selection.radius * lerp(MIN_SCALE, MAX_SCALE),
+        };
+        self.color.write(
+            queue,
+            [
+                lerp(DIM_COLOR[0], BRIGHT_COLOR[0]),
+                lerp(DIM_COLOR[1], BRIGHT_COLOR[1]),
+                lerp(DIM_COLOR[2], BRIGHT_COLOR[2]),
+                1.0,
+            ],
+        );
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&[CircleConstraintInstance::new(&ring)]),
+        );
+
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("selection renderer render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, context.camera_bind_group(), &[]);
+        render_pass.set_bind_group(1, self.color.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        let width = sc_desc.width as f32;
+        let height = sc_desc.height as f32;
+        render_pass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pulse_cycles() {
+        assert!(pulse(0.0).abs() < 1e-6);
+        assert!((pulse(PULSE_PERIOD * 0.5) - 1.0).abs() < 1e-6);
+        assert!(pulse(PULSE_PERIOD).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,249 @@
+use super::circle::{CircleConstraintInstance, Vertex};
+use super::context::{ColorUniform, RenderContext};
+use crate::spatial_bound::SpatialBound;
+use crate::sphere::Sphere;
+use cgmath::{InnerSpace, Vector3};
+use wgpu::util::DeviceExt;
+
+/// The color of the curves. They are faint, so that they don't get in the way
+/// of what they connect.
+const COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.25];
+
+/// Thickness of a curve, in the coordinates described in
+/// [`crate::spatial_tree`].
+const THICKNESS: f32 = 0.004;
+
+/// How far the middle of a curve bends away from the straight line between
+/// its ends, as a fraction of the distance between them.
+const BEND: f32 = 0.2;
+
+/// The number of straight pieces each curve is made of.
+const SEGMENTS: usize = 24;
+
+/// The number of vertices in each curve: two triangles per piece.
+const VERTICES_PER_CURVE: usize = SEGMENTS * 6;
+
+/// Returns the triangles of a curve from `from` to `to`, bending to the left of
+/// the line between them.
+fn make_curve(from: Vector3<f32>, to: Vector3<f32>) -> Vec<Vertex> {
+    let along = to - from;
+    let normal = Vector3::new(-along.y, along.x, 0.0);
+    let control = from + 0.5 * along + BEND * normal;
+    // A point on the quadratic Bézier curve through `control`.
+    let point = |t: f32| {
+        let u = 1.0 - t;
+        u * u * from + 2.0 * u * t * control + t * t * to
+    };
+    // The two edges of the curve at `t`, offset from it along its normal.
+    let edges = |t: f32| {
+        let tangent = 2.0 * (1.0 - t) * (control - from) + 2.0 * t * (to - control);
+        let offset = match tangent.magnitude() {
+            length if length > 0.0 => {
+                Vector3::new(-tangent.y, tangent.x, 0.0) * (0.5 * THICKNESS / length)
+            }
+            _ => Vector3::new(0.0, 0.0, 0.0),
+        };
+        (point(t) + offset, point(t) - offset)
+    };
+    let vertex = |position: Vector3<f32>| Vertex {
+        position: [position.x, position.y, from.z.max(to.z)],
+    };
+    let mut vertices = Vec::with_capacity(VERTICES_PER_CURVE);
+    for segment in 0..SEGMENTS {
+        let (a, b) = edges(segment as f32 / SEGMENTS as f32);
+        let (c, d) = edges((segment + 1) as f32 / SEGMENTS as f32);
+        for &position in &[a, b, c, c, b, d] {
+            vertices.push(vertex(position));
+        }
+    }
+    vertices
+}
+
+/// Draws faint curves between the places where the same value is displayed
+/// more than once (see [`SpatialTree::shared`]), so that it is visible which
+/// values are shared between containers rather than merely equal.
+///
+/// Each group of places is connected in a chain, in the order they were laid
+/// out, rather than every place being connected to every other one. Like the
+/// ghost's veil, the curves are drawn on top of everything else, are filled
+/// in with the selection fragment shader, and are blended with what is
+/// beneath them.
+///
+/// [`SpatialTree::shared`]: crate::spatial_tree::SpatialTree::shared
+pub struct SharingRenderer {
+    curves: Vec<(Vector3<f32>, Vector3<f32>)>,
+    vertex_buffer_cache: Option<wgpu::Buffer>,
+    instance_buffer: wgpu::Buffer,
+    render_pipeline: wgpu::RenderPipeline,
+    color: ColorUniform,
+}
+
+impl SharingRenderer {
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        context: &'a RenderContext,
+    ) -> Self {
+        // The vertices of each curve are already in place, so they are drawn
+        // with a single identity instance, like rectangles.
+        let identity = CircleConstraintInstance::new(&Sphere {
+            center: (0.0, 0.0, 0.0).into(),
+            radius: 1.0,
+        });
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("sharing renderer instance buffer"),
+            contents: bytemuck::cast_slice(&[identity]),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.vert.spv"));
+        let fs_module = device
+            .create_shader_module(&wgpu::include_spirv!("../shaders/build/selection.frag.spv"));
+
+        let color = ColorUniform::new(device, context, COLOR, "sharing renderer color uniform");
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("sharing renderer pipeline layout"),
+                bind_group_layouts: &[context.camera_layout(), context.color_layout()],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("sharing renderer pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc(), CircleConstraintInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc_desc.format,
+                    alpha_blend: wgpu::BlendState {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    color_blend: wgpu::BlendState {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            curves: Vec::new(),
+            vertex_buffer_cache: None,
+            instance_buffer,
+            render_pipeline,
+            color,
+        }
+    }
+
+    /// Connects each group of bounds in `groups` with curves, replacing the
+    /// curves drawn before. Pass no groups to stop drawing curves.
+    pub fn set_groups(&mut self, groups: &[Vec<SpatialBound>]) {
+        let center = |bound: &SpatialBound| SpatialBound::sphere_inside_bound(bound).center;
+        self.curves = groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .windows(2)
+                    .map(move |pair| (center(&pair[0]), center(&pair[1])))
+            })
+            .collect();
+        self.vertex_buffer_cache = None;
+    }
+
+    pub fn render<'a>(
+        &mut self,
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        texture_view: &'a wgpu::TextureView,
+        context: &'a RenderContext,
+    ) {
+        if self.curves.is_empty() {
+            return;
+        }
+        let _span = tracing::trace_span!("sharing_render").entered();
+
+        let curves = &self.curves;
+        let vertex_buffer = self.vertex_buffer_cache.get_or_insert_with(|| {
+            let vertices: Vec<Vertex> = curves
+                .iter()
+                .flat_map(|&(from, to)| make_curve(from, to))
+                .collect();
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("sharing renderer vertex buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsage::VERTEX,
+            })
+        });
+
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("sharing renderer render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, context.camera_bind_group(), &[]);
+        render_pass.set_bind_group(1, self.color.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        let width = sc_desc.width as f32;
+        let height = sc_desc.height as f32;
+        render_pass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
+        render_pass.draw(0..(self.curves.len() * VERTICES_PER_CURVE) as u32, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn curves_join_their_ends() {
+        let from = Vector3::new(-0.5, 0.0, 0.0);
+        let to = Vector3::new(0.5, 0.0, 0.0);
+        let vertices = make_curve(from, to);
+        assert_eq!(VERTICES_PER_CURVE, vertices.len());
+        let near = |vertex: &Vertex, point: Vector3<f32>| {
+            let [x, y, _] = vertex.position;
+            (Vector3::new(x, y, 0.0) - point).magnitude() <= THICKNESS
+        };
+        assert!(near(&vertices[0], from));
+        assert!(near(&vertices[VERTICES_PER_CURVE - 1], to));
+        // The middle of the curve bends away from the straight line.
+        let middle = &vertices[VERTICES_PER_CURVE / 2];
+        assert!(middle.position[1] > 0.5 * BEND);
+    }
+}
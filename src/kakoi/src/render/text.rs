@@ -1,19 +1,201 @@
+use super::circle::{depth_fade, DIM_FADE, MIN_RADIUS};
+use super::draw_order::{batch_range, DrawOrder};
 use crate::arena::{ArenaKey, Structure, Value};
 use crate::camera::Camera;
 use crate::spatial_bound::SpatialBound;
 use crate::spatial_tree::SpatialTreeData;
+use crate::sphere::Sphere;
+use crate::square_cuboid::SquareCuboid;
+use crate::string_class::{self, StringClass};
 use cgmath::Vector3;
 use slotmap::SlotMap;
-use wgpu_glyph::{GlyphBrush, GlyphCruncher};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use unicode_segmentation::UnicodeSegmentation;
+use wgpu_glyph::{
+    ab_glyph::{Font, ScaleFont},
+    FontId, GlyphBrush, GlyphCruncher,
+};
+
+/// The smallest point scale at which we consider text to be readable, in
+/// logical pixels. This is multiplied by the scale factor of the display (see
+/// [`TextRenderer::set_pixel_scale`]) to get the smallest point scale in
+/// physical pixels, so that text becomes unreadable at the same apparent size
+/// on every display.
+///
+/// Strings that would have to be drawn smaller than this to fit inside of
+/// their bounds are shortened with an [`ELLIPSIS`] instead (see
+/// [`Truncation`]).
+pub const MIN_READABLE_TEXT_SCALE: f32 = 10.0;
+
+/// Stands in for the part of a string that was removed to make it fit.
+pub const ELLIPSIS: &str = "\u{2026}";
+
+/// The color of ordinary text.
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// The color of [`StringClass::Url`]s and their underlines.
+pub const URL_COLOR: [f32; 4] = [0.35, 0.6, 1.0, 1.0];
+
+/// Thickness of the underline beneath a [`StringClass::Url`], as a fraction of
+/// the height of its text.
+const UNDERLINE_THICKNESS: f32 = 0.06;
+
+/// The font used for most text.
+const REGULAR_FONT: FontId = FontId(0);
+
+/// The font used for [`StringClass::Code`].
+const MONOSPACE_FONT: FontId = FontId(1);
+
+/// Returns the font and color to draw strings of class `class` with.
+fn style(class: StringClass) -> (FontId, [f32; 4]) {
+    match class {
+        StringClass::Plain | StringClass::Date { .. } => (REGULAR_FONT, TEXT_COLOR),
+        StringClass::Url => (REGULAR_FONT, URL_COLOR),
+        StringClass::Code => (MONOSPACE_FONT, TEXT_COLOR),
+    }
+}
+
+/// How many words of a long string are drawn when it isn't the focus of its
+/// layer, unless told otherwise (see [`TextRenderer::set_preview_words`]).
+pub const DEFAULT_PREVIEW_WORDS: usize = 24;
+
+/// How many graphemes a word in a preview may have before it is cut short, so
+/// that long strings without spaces get small previews too.
+const MAX_PREVIEW_WORD_LENGTH: usize = 16;
+
+/// Returns the first `words` words of `text` followed by an ellipsis, broken
+/// onto lines so that they form a block about as wide as it is tall, or
+/// [`None`] if `text` is short enough to be drawn whole.
+fn preview(text: &str, words: usize) -> Option<String> {
+    let mut remaining = text.split_whitespace();
+    let mut shortened = false;
+    let kept: Vec<String> = remaining
+        .by_ref()
+        .take(words)
+        .map(|word| {
+            let graphemes = word.graphemes(true).collect::<Vec<_>>();
+            if graphemes.len() > MAX_PREVIEW_WORD_LENGTH {
+                shortened = true;
+                Truncation::End.apply(&graphemes, MAX_PREVIEW_WORD_LENGTH)
+            } else {
+                word.to_string()
+            }
+        })
+        .collect();
+    if remaining.next().is_none() && !shortened {
+        return None;
+    }
+    let lengths: Vec<usize> = kept
+        .iter()
+        .map(|word| word.graphemes(true).count())
+        .collect();
+    // Glyphs are about half as wide as lines are tall, so a block of text is
+    // square when its lines are about twice as many graphemes long as there
+    // are lines.
+    let total: usize = lengths.iter().sum::<usize>() + lengths.len();
+    let longest = lengths.iter().copied().max().unwrap_or(0);
+    let width = ((2 * total) as f32).sqrt().ceil() as usize;
+    let width = width.max(longest);
+    let mut result = String::new();
+    let mut line_length = 0;
+    for (word, length) in kept.iter().zip(lengths) {
+        if line_length > 0 && line_length + 1 + length > width {
+            result.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            result.push(' ');
+            line_length += 1;
+        }
+        result.push_str(word);
+        line_length += length;
+    }
+    if !result.ends_with(ELLIPSIS) {
+        result.push_str(ELLIPSIS);
+    }
+    Some(result)
+}
+
+/// Height of a ring label, as a fraction of the radius of its ring.
+const LABEL_HEIGHT: f32 = 0.08;
+
+/// The widest angle, in radians, that a curved ring label may span across the
+/// top of its ring.
+const LABEL_ARC: f32 = std::f32::consts::FRAC_PI_2;
+
+/// The widest a straight ring label may be, as a fraction of the diameter of
+/// its ring.
+const STRAIGHT_LABEL_WIDTH: f32 = 0.8;
+
+/// The smallest point scale at which ring labels are curved. Each grapheme of
+/// a curved label is drawn separately, which isn't worth doing when the label
+/// is too small for its curvature to be noticeable, so smaller labels are
+/// drawn straight instead.
+const MIN_CURVED_LABEL_SCALE: f32 = 2.0 * MIN_READABLE_TEXT_SCALE;
+
+/// The point scale labels are measured at before being sized to fit their
+/// rings.
+const LABEL_LAYOUT_SCALE: f32 = 20.0;
+
+/// Where to remove graphemes from a string that is too long to be drawn at a
+/// readable size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Truncation {
+    /// Keep the beginning of the string: `"a long stri…"`.
+    End,
+    /// Keep the beginning and the end of the string: `"a long…tring"`.
+    Middle,
+}
+
+impl Truncation {
+    /// Returns `graphemes` shortened to `keep` graphemes plus an ellipsis.
+    fn apply(self, graphemes: &[&str], keep: usize) -> String {
+        match self {
+            Truncation::End => {
+                let mut result = graphemes[..keep].concat();
+                result.push_str(ELLIPSIS);
+                result
+            }
+            Truncation::Middle => {
+                let tail = keep / 2;
+                let head = keep - tail;
+                let mut result = graphemes[..head].concat();
+                result.push_str(ELLIPSIS);
+                result.push_str(&graphemes[graphemes.len() - tail..].concat());
+                result
+            }
+        }
+    }
+}
 
 pub struct TextRenderer {
-    constraints: Vec<SpatialTreeData>,
+    /// Each string to draw, alongside how deeply it is nested in its layer
+    /// and whether it is dimmed.
+    constraints: Vec<(DrawOrder, SpatialTreeData, StringClass, usize, bool)>,
     instances_cache: Vec<TextConstraintInstance>,
     instances_cache_stale: bool,
     glyph_brush: wgpu_glyph::GlyphBrush<()>,
     staging_belt: wgpu::util::StagingBelt,
     local_pool: futures::executor::LocalPool,
     local_spawner: futures::executor::LocalSpawner,
+    truncation: Truncation,
+    /// Physical pixels per logical pixel. See [`TextRenderer::set_pixel_scale`].
+    pixel_scale: f32,
+    depth_fading: bool,
+    dimming: bool,
+    labels: Vec<(DrawOrder, Sphere, String)>,
+    label_glyphs_cache: Vec<LabelGlyph>,
+    /// Text drawn like a string without being in the arena. See
+    /// [`TextRenderer::with_provisional`].
+    provisional: Vec<(DrawOrder, SpatialBound, String)>,
+    /// How many words of a long string are drawn when it isn't the focus of
+    /// its layer, or [`None`] if strings are always drawn whole. See
+    /// [`TextRenderer::set_preview_words`].
+    preview_words: Option<usize>,
+    /// The preview of each string drawn so far, or [`None`] if it is drawn
+    /// whole. Kept between frames, since finding a preview means looking
+    /// through the string.
+    previews: HashMap<ArenaKey, Option<String>>,
 }
 
 impl TextRenderer {
@@ -28,12 +210,18 @@ impl TextRenderer {
         let local_pool = futures::executor::LocalPool::new();
         let local_spawner = local_pool.spawner();
 
+        // The order of these fonts must match REGULAR_FONT and MONOSPACE_FONT.
         let glyph_brush = {
-            let font = wgpu_glyph::ab_glyph::FontArc::try_from_slice(include_bytes!(
+            let regular = wgpu_glyph::ab_glyph::FontArc::try_from_slice(include_bytes!(
                 "../resources/fonts/CooperHewitt-OTF-public/CooperHewitt-Book.otf"
             ))
             .unwrap();
-            wgpu_glyph::GlyphBrushBuilder::using_font(font).build(&device, sc_desc.format)
+            let monospace = wgpu_glyph::ab_glyph::FontArc::try_from_slice(include_bytes!(
+                "../resources/fonts/DejaVuSansMono/DejaVuSansMono.ttf"
+            ))
+            .unwrap();
+            wgpu_glyph::GlyphBrushBuilder::using_fonts(vec![regular, monospace])
+                .build(&device, sc_desc.format)
         };
 
         Self {
@@ -44,46 +232,193 @@ impl TextRenderer {
             staging_belt,
             local_pool,
             local_spawner,
+            truncation: Truncation::End,
+            pixel_scale: 1.0,
+            depth_fading: false,
+            dimming: false,
+            labels: Vec::new(),
+            label_glyphs_cache: Vec::new(),
+            provisional: Vec::new(),
+            preview_words: None,
+            previews: HashMap::new(),
         }
     }
 
-    pub fn with_instance<'a>(&mut self, spatial_tree_data: SpatialTreeData) {
-        self.constraints.push(spatial_tree_data);
+    /// Chooses how many words of a long string are drawn when it isn't the
+    /// focus of its layer, or draws every string whole if `words` is
+    /// [`None`]. Previews are broken onto several lines, so that they fill the
+    /// circles they are drawn in. The focus of a layer is always drawn whole.
+    pub fn set_preview_words(&mut self, words: Option<usize>) {
+        self.preview_words = words;
+        self.forget_previews();
+    }
+
+    /// Forgets every preview found so far. Must be called when strings are
+    /// changed in place (see [`crate::arena::Arena::rewrite_strings`]).
+    pub fn forget_previews(&mut self) {
+        self.previews.clear();
+        self.instances_cache_stale = true;
+    }
+
+    /// Changes where strings that are too small to read get shortened.
+    pub fn set_truncation(&mut self, truncation: Truncation) {
+        self.truncation = truncation;
+        self.instances_cache_stale = true;
+    }
+
+    /// Sets how many physical pixels make up a logical pixel: the scale factor
+    /// of the display the window is on, times the UI scale chosen by the user.
+    /// The point scales below which strings are shortened and labels are
+    /// straightened or hidden are multiplied by this.
+    pub fn set_pixel_scale(&mut self, pixel_scale: f32) {
+        self.pixel_scale = pixel_scale;
+        self.instances_cache_stale = true;
+    }
+
+    /// Chooses whether strings nested deeply below the top of their layer
+    /// fade into the background, like rings do. See
+    /// [`CircleRenderer::set_depth_fading`].
+    ///
+    /// [`CircleRenderer::set_depth_fading`]: super::circle::CircleRenderer::set_depth_fading
+    pub fn set_depth_fading(&mut self, depth_fading: bool) {
+        self.depth_fading = depth_fading;
+    }
+
+    /// Chooses whether the strings added from now on are dimmed, like rings.
+    /// See [`CircleRenderer::set_dimming`].
+    ///
+    /// [`CircleRenderer::set_dimming`]: super::circle::CircleRenderer::set_dimming
+    pub fn set_dimming(&mut self, dimming: bool) {
+        self.dimming = dimming;
+    }
+
+    /// Draws the string displayed by `spatial_tree_data`, which is nested
+    /// `depth` levels deep in its layer.
+    pub fn with_instance<'a>(
+        &mut self,
+        spatial_tree_data: SpatialTreeData,
+        order: DrawOrder,
+        class: StringClass,
+        depth: usize,
+    ) {
+        self.constraints
+            .push((order, spatial_tree_data, class, depth, self.dimming));
+    }
+
+    /// Labels the ring drawn around `sphere` with `label`, written along the
+    /// inside of its top arc. Labels that don't fit along the arc at a
+    /// comfortable size are drawn straight instead, and labels that can't be
+    /// read at all aren't drawn.
+    pub fn with_label(&mut self, sphere: Sphere, order: DrawOrder, label: String) {
+        self.labels.push((order, sphere, label));
+    }
+
+    /// Draws `text` inside of `bound` as if it were a string, without it
+    /// having to be in the arena. This is how a string is drawn while it is
+    /// still being entered, how custom leaves without thumbnails are
+    /// described (see [`crate::leaf`]), and how the tags of a value are
+    /// written on its badge (see [`crate::arena::Arena::tag`]).
+    pub fn with_provisional(&mut self, bound: SpatialBound, order: DrawOrder, text: String) {
+        self.provisional.push((order, bound, text));
     }
 
     pub fn resize<'a>(&mut self) {
         self.instances_cache_stale = true;
     }
 
-    pub fn render<'a>(
+    /// The [`DrawOrder`]s of every string that will be drawn.
+    pub fn draw_orders<'a>(&'a self) -> impl Iterator<Item = DrawOrder> + 'a {
+        self.constraints
+            .iter()
+            .map(|&(order, _, _, _, _)| order)
+            .chain(self.labels.iter().map(|&(order, _, _)| order))
+            .chain(self.provisional.iter().map(|&(order, _, _)| order))
+    }
+
+    /// The regions beneath [`StringClass::Url`]s that should be underlined,
+    /// alongside the [`DrawOrder`]s of their strings. Only meaningful after
+    /// [`TextRenderer::prepare`].
+    pub fn underlines<'a>(&'a self) -> impl Iterator<Item = (DrawOrder, SquareCuboid)> + 'a {
+        self.instances_cache.iter().filter_map(|instance| {
+            instance
+                .underline
+                .map(|underline| (instance.order, underline))
+        })
+    }
+
+    /// Lays out every string that will be drawn this frame. Must be called
+    /// before [`TextRenderer::render`].
+    pub fn prepare<'a>(
         &mut self,
         store: &'a SlotMap<ArenaKey, Value>,
-        device: &'a wgpu::Device,
         sc_desc: &'a wgpu::SwapChainDescriptor,
-        encoder: &'a mut wgpu::CommandEncoder,
-        texture_view: &'a wgpu::TextureView,
         camera: &'a mut Camera,
     ) {
         Self::build_instances(
             store,
             &mut self.instances_cache,
             self.instances_cache_stale,
-            &self.constraints,
+            &mut self.constraints,
+            &self.provisional,
+            &mut self.previews,
+            self.preview_words,
             &mut self.glyph_brush,
             camera.view_projection_matrix(),
             sc_desc,
+            self.truncation,
+            self.pixel_scale,
+        );
+        Self::build_labels(
+            &mut self.label_glyphs_cache,
+            self.instances_cache_stale,
+            &mut self.labels,
+            &self.glyph_brush,
+            camera.view_projection_matrix(),
+            sc_desc,
+            self.pixel_scale,
         );
         self.instances_cache_stale = false;
-        for instance in &self.instances_cache {
-            let text = match &store.get(instance.key).unwrap().structure {
-                Structure::String(s) => s,
-                _ => panic!(),
+    }
+
+    /// Draws the strings whose order is `order`.
+    pub fn render<'a>(
+        &mut self,
+        store: &'a SlotMap<ArenaKey, Value>,
+        device: &'a wgpu::Device,
+        encoder: &'a mut wgpu::CommandEncoder,
+        texture_view: &'a wgpu::TextureView,
+        order: DrawOrder,
+    ) {
+        let range = batch_range(&self.instances_cache, order, |instance| instance.order);
+        let label_range = batch_range(&self.label_glyphs_cache, order, |glyph| glyph.order);
+        if range.is_empty() && label_range.is_empty() {
+            return;
+        }
+        let _span = tracing::trace_span!("text_render").entered();
+        for instance in &self.instances_cache[range] {
+            let text = match &instance.display_text {
+                Some(display_text) => display_text,
+                None => match &store.get(instance.key.unwrap()).unwrap().structure {
+                    Structure::String(s) => s.as_ref(),
+                    _ => panic!(),
+                },
             };
+            let (font_id, mut color) = style(instance.class);
+            let mut fade = if self.depth_fading {
+                depth_fade(instance.depth)
+            } else {
+                0.0
+            };
+            if instance.dimmed {
+                fade = fade.max(DIM_FADE);
+            }
+            color[3] *= 1.0 - fade;
             let section = wgpu_glyph::Section {
                 screen_position: (-instance.width * 0.5, -instance.height * 0.5),
                 bounds: (f32::INFINITY, f32::INFINITY),
-                text: vec![wgpu_glyph::Text::new(text.as_ref())
-                    .with_color([1.0, 1.0, 1.0, 1.0])
+                text: vec![wgpu_glyph::Text::new(text)
+                    .with_color(color)
+                    .with_font_id(font_id)
                     .with_scale(instance.text_scale)],
                 ..wgpu_glyph::Section::default()
             };
@@ -98,7 +433,32 @@ impl TextRenderer {
                 )
                 .unwrap(); // It seems like this function always returns Ok(())...?
         }
+        for glyph in &self.label_glyphs_cache[label_range] {
+            let section = wgpu_glyph::Section {
+                screen_position: (-glyph.width * 0.5, -glyph.height * 0.5),
+                bounds: (f32::INFINITY, f32::INFINITY),
+                text: vec![wgpu_glyph::Text::new(&glyph.text)
+                    .with_color(TEXT_COLOR)
+                    .with_font_id(REGULAR_FONT)
+                    .with_scale(glyph.text_scale)],
+                ..wgpu_glyph::Section::default()
+            };
+            self.glyph_brush.queue(&section);
+            self.glyph_brush
+                .draw_queued_with_transform(
+                    device,
+                    &mut self.staging_belt,
+                    encoder,
+                    texture_view,
+                    glyph.transformation,
+                )
+                .unwrap();
+        }
+    }
 
+    /// Must be called once every batch has been drawn with
+    /// [`TextRenderer::render`], before the frame is submitted.
+    pub fn finish(&mut self) {
         self.staging_belt.finish();
     }
 
@@ -114,6 +474,9 @@ impl TextRenderer {
 
     pub fn invalidate(&mut self) {
         self.constraints.clear();
+        self.labels.clear();
+        self.provisional.clear();
+        self.dimming = false;
         self.instances_cache_stale = true;
     }
 
@@ -121,36 +484,303 @@ impl TextRenderer {
         store: &'b SlotMap<ArenaKey, Value>,
         instances_cache: &'a mut Vec<TextConstraintInstance>,
         instances_cache_stale: bool,
-        constraints: &'a Vec<SpatialTreeData>,
+        constraints: &'a mut Vec<(DrawOrder, SpatialTreeData, StringClass, usize, bool)>,
+        provisional: &'a [(DrawOrder, SpatialBound, String)],
+        previews: &'a mut HashMap<ArenaKey, Option<String>>,
+        preview_words: Option<usize>,
         glyph_brush: &'b mut wgpu_glyph::GlyphBrush<()>,
         view_projection_matrix: &'a cgmath::Matrix4<f32>,
         sc_desc: &'b wgpu::SwapChainDescriptor,
+        truncation: Truncation,
+        pixel_scale: f32,
     ) {
         if instances_cache_stale {
+            let _span =
+                tracing::debug_span!("text_build_instances", count = constraints.len()).entered();
             instances_cache.clear();
-            for SpatialTreeData { key, bounds: bound } in constraints {
-                instances_cache.push(TextConstraintInstance::new(
+            constraints.sort_by_key(|&(order, _, _, _, _)| order);
+            for (order, SpatialTreeData { key, bounds: bound }, class, depth, dimmed) in
+                constraints.iter()
+            {
+                // Only plain strings are previewed, since cutting short and
+                // rewrapping code or links would make them harder to read.
+                let preview = match (preview_words, class) {
+                    (Some(words), StringClass::Plain) if *depth > 0 => previews
+                        .entry(*key)
+                        .or_insert_with(|| match &store[*key].structure {
+                            Structure::String(s) => preview(s, words),
+                            _ => None,
+                        })
+                        .as_deref(),
+                    _ => None,
+                };
+                let mut instance = TextConstraintInstance::new(
                     store,
                     key,
+                    preview,
+                    *order,
+                    *class,
+                    glyph_brush,
+                    bound,
+                    view_projection_matrix,
+                    sc_desc.width as f32,
+                    sc_desc.height as f32,
+                    truncation,
+                    pixel_scale,
+                );
+                instance.depth = *depth;
+                instance.dimmed = *dimmed;
+                instances_cache.push(instance);
+            }
+            for (order, bound, text) in provisional {
+                instances_cache.push(TextConstraintInstance::provisional(
+                    text,
+                    *order,
                     glyph_brush,
                     bound,
                     view_projection_matrix,
                     sc_desc.width as f32,
                     sc_desc.height as f32,
+                    truncation,
+                    pixel_scale,
                 ));
             }
+            // Instances are drawn in batches of the same order, so they need
+            // to stay sorted.
+            instances_cache.sort_by_key(|instance| instance.order);
         } else {
             for instance in instances_cache {
                 instance.set_view_projection_matrix(view_projection_matrix);
             }
         }
     }
+
+    fn build_labels<'a, 'b>(
+        label_glyphs_cache: &'a mut Vec<LabelGlyph>,
+        label_glyphs_cache_stale: bool,
+        labels: &'a mut Vec<(DrawOrder, Sphere, String)>,
+        glyph_brush: &'b wgpu_glyph::GlyphBrush<()>,
+        view_projection_matrix: &'a cgmath::Matrix4<f32>,
+        sc_desc: &'b wgpu::SwapChainDescriptor,
+        pixel_scale: f32,
+    ) {
+        if label_glyphs_cache_stale {
+            label_glyphs_cache.clear();
+            labels.sort_by_key(|&(order, _, _)| order);
+            for (order, sphere, label) in labels.iter() {
+                LabelGlyph::layout(
+                    label_glyphs_cache,
+                    glyph_brush,
+                    *order,
+                    sphere,
+                    label,
+                    view_projection_matrix,
+                    sc_desc.width as f32,
+                    sc_desc.height as f32,
+                    pixel_scale,
+                );
+            }
+        } else {
+            for glyph in label_glyphs_cache {
+                glyph.set_view_projection_matrix(view_projection_matrix);
+            }
+        }
+    }
+}
+
+/// A piece of a ring label: a single grapheme of a curved label, or the whole
+/// of a straight one.
+#[derive(Debug)]
+struct LabelGlyph {
+    order: DrawOrder,
+    text: String,
+    /// Point scale of the text.
+    text_scale: f32,
+    /// The width, in pixels, that the text will be rendered at.
+    width: f32,
+    /// The height, in pixels, that the text will be rendered at.
+    height: f32,
+    /// The center of the text's bounding box.
+    center: Vector3<f32>,
+    /// How far the text is rotated clockwise, in radians.
+    angle: f32,
+    /// Scalar used to convert a transformation in our coordinate system to
+    /// glyph_brush's coordinate system.
+    transform_scale: f32,
+    transformation: [f32; 16],
+}
+
+impl LabelGlyph {
+    /// Lays out `label` along the ring drawn around `sphere`, pushing its
+    /// pieces onto `glyphs`. `pixel_scale` is the number of physical pixels
+    /// per logical pixel (see [`TextRenderer::set_pixel_scale`]).
+    fn layout(
+        glyphs: &mut Vec<LabelGlyph>,
+        glyph_brush: &GlyphBrush<()>,
+        order: DrawOrder,
+        sphere: &Sphere,
+        label: &str,
+        view_projection_matrix: &cgmath::Matrix4<f32>,
+        viewport_width: f32,
+        viewport_height: f32,
+        pixel_scale: f32,
+    ) {
+        let font = &glyph_brush.fonts()[REGULAR_FONT.0];
+        let scaled_font = font.as_scaled(LABEL_LAYOUT_SCALE);
+        let graphemes = label
+            .graphemes(true)
+            .map(|grapheme| {
+                let advance: f32 = grapheme
+                    .chars()
+                    .map(|c| scaled_font.h_advance(font.glyph_id(c)))
+                    .sum();
+                (grapheme, advance)
+            })
+            .collect::<Vec<_>>();
+        let advance: f32 = graphemes.iter().map(|&(_, advance)| advance).sum();
+        if advance <= 0.0 {
+            return;
+        }
+        let line_height = scaled_font.height();
+
+        // Pixels per unit of virtual coordinate space. The shorter side of the
+        // screen spans from -1 to 1 (see `screen_to_view_coordinates` in the
+        // spatial_tree module).
+        let pixels_per_unit = 0.5 * viewport_width.min(viewport_height);
+        let transform_scale = 1.0 / pixels_per_unit;
+        // The point scale at which text is `height` tall.
+        let text_scale = |height: f32| LABEL_LAYOUT_SCALE * height * pixels_per_unit / line_height;
+        let ring = sphere.radius * MIN_RADIUS;
+
+        let height = (LABEL_HEIGHT * sphere.radius).min(LABEL_ARC * ring * line_height / advance);
+        if text_scale(height) >= MIN_CURVED_LABEL_SCALE * pixel_scale {
+            // Center each grapheme on the arc halfway between the ring and
+            // the label's inner edge, rotated so that its top faces outward.
+            let radius = ring - 0.5 * height;
+            let units_per_advance = height / line_height;
+            let mut angle = -0.5 * advance * units_per_advance / radius;
+            for (grapheme, grapheme_advance) in graphemes {
+                let sweep = grapheme_advance * units_per_advance / radius;
+                let middle = angle + 0.5 * sweep;
+                angle += sweep;
+                if grapheme.trim().is_empty() {
+                    continue;
+                }
+                glyphs.push(Self::new(
+                    order,
+                    grapheme.to_string(),
+                    text_scale(height),
+                    grapheme_advance * units_per_advance * pixels_per_unit,
+                    height * pixels_per_unit,
+                    sphere.center + radius * cgmath::vec3(middle.sin(), middle.cos(), 0.0),
+                    middle,
+                    transform_scale,
+                    view_projection_matrix,
+                ));
+            }
+        } else {
+            let max_width = STRAIGHT_LABEL_WIDTH * 2.0 * ring;
+            let height = (LABEL_HEIGHT * sphere.radius).min(max_width * line_height / advance);
+            if text_scale(height) >= MIN_READABLE_TEXT_SCALE * pixel_scale {
+                // Place the label as high as it can go without its top
+                // corners poking out of the ring.
+                let width = advance * height / line_height;
+                let y = (ring.powi(2) - (0.5 * width).powi(2)).sqrt() - 0.5 * height;
+                glyphs.push(Self::new(
+                    order,
+                    label.to_string(),
+                    text_scale(height),
+                    width * pixels_per_unit,
+                    height * pixels_per_unit,
+                    sphere.center + cgmath::vec3(0.0, y, 0.0),
+                    0.0,
+                    transform_scale,
+                    view_projection_matrix,
+                ));
+            }
+        }
+    }
+
+    fn new(
+        order: DrawOrder,
+        text: String,
+        text_scale: f32,
+        width: f32,
+        height: f32,
+        center: Vector3<f32>,
+        angle: f32,
+        transform_scale: f32,
+        view_projection_matrix: &cgmath::Matrix4<f32>,
+    ) -> Self {
+        Self {
+            order,
+            text,
+            text_scale,
+            width,
+            height,
+            center,
+            angle,
+            transform_scale,
+            transformation: Self::calculate_transformation(
+                view_projection_matrix,
+                center,
+                angle,
+                transform_scale,
+            ),
+        }
+    }
+
+    fn set_view_projection_matrix(&mut self, view_projection_matrix: &cgmath::Matrix4<f32>) {
+        self.transformation = Self::calculate_transformation(
+            view_projection_matrix,
+            self.center,
+            self.angle,
+            self.transform_scale,
+        )
+    }
+
+    /// Like [`TextConstraintInstance::calculate_transformation`], but also
+    /// rotates the text clockwise by `angle` radians.
+    fn calculate_transformation(
+        view_projection_matrix: &cgmath::Matrix4<f32>,
+        center: Vector3<f32>,
+        angle: f32,
+        scale: f32,
+    ) -> [f32; 16] {
+        let transformation = cgmath::Matrix4::from_nonuniform_scale(scale, -scale, 1.0);
+        let transformation = cgmath::Matrix4::from_angle_z(cgmath::Rad(-angle)) * transformation;
+        let transformation = cgmath::Matrix4::from_translation(center) * transformation;
+        *(view_projection_matrix * transformation).as_mut()
+    }
 }
 
 #[derive(Debug)]
 pub struct TextConstraintInstance {
-    /// Location of the text we want to render in an [Arena].
-    key: ArenaKey,
+    /// Location of the text we want to render in an [Arena], or [`None`] if
+    /// the text isn't in the arena (in which case it is in `display_text`).
+    key: Option<ArenaKey>,
+
+    /// When the text is drawn relative to everything else on screen.
+    order: DrawOrder,
+
+    /// How the string bound to `key` is styled.
+    class: StringClass,
+
+    /// How deeply the text is nested in its layer. Provisional text is always
+    /// at the top.
+    depth: usize,
+
+    /// Whether the text is dimmed. See [`TextRenderer::set_dimming`].
+    dimmed: bool,
+
+    /// The text to draw in place of the string bound to `key`, if it is
+    /// displayed differently because of its `class` (see
+    /// [`StringClass::display`]) or could not be drawn at a readable size (see
+    /// [`Truncation`]).
+    display_text: Option<String>,
+
+    /// The region beneath the text to fill in, if it is underlined.
+    underline: Option<SquareCuboid>,
 
     /// Point scale of the text.
     text_scale: f32,
@@ -175,57 +805,188 @@ pub struct TextConstraintInstance {
 }
 
 impl TextConstraintInstance {
+    /// Sizes the string bound to `key` to fit inside of `bound`, drawing
+    /// `preview` in its place if there is one.
     pub fn new(
         store: &SlotMap<ArenaKey, Value>,
         key: &ArenaKey,
+        preview: Option<&str>,
+        order: DrawOrder,
+        class: StringClass,
         glyph_brush: &mut wgpu_glyph::GlyphBrush<()>,
         bound: &SpatialBound,
         view_projection_matrix: &cgmath::Matrix4<f32>,
         viewport_width: f32,
         viewport_height: f32,
+        truncation: Truncation,
+        pixel_scale: f32,
     ) -> Self {
-        let text = match &store.get(*key).unwrap().structure {
-            Structure::String(s) => s,
+        let text = match (&store.get(*key).unwrap().structure, preview) {
+            (Structure::String(_), Some(preview)) => Cow::Owned(preview.to_string()),
+            (Structure::String(s), None) => class.display(s),
             _ => panic!(),
         };
+        Self::fit(
+            Some(*key),
+            text,
+            order,
+            class,
+            glyph_brush,
+            bound,
+            view_projection_matrix,
+            viewport_width,
+            viewport_height,
+            truncation,
+            pixel_scale,
+        )
+    }
 
-        // Use an arbitrary default scale (20.0) to determine the aspect ratio
-        // of the text's bounding box.
-        let mut section = wgpu_glyph::Section {
-            screen_position: (0.0, 0.0),
-            bounds: (f32::INFINITY, f32::INFINITY),
-            text: vec![wgpu_glyph::Text::new(&text)
-                .with_color([0.0, 0.0, 0.0, 1.0])
-                .with_scale(20.0)],
-            ..wgpu_glyph::Section::default()
-        };
-        let (tw, th) = Self::text_dimensions(glyph_brush, &section);
-        // The true aspect ratio (what you would see on screen) is (tw / th).
-        // Since our spatial bound parameter comes from virtual coordinate space
-        // (which goes from -1..1 in all dimensions), we need to squish /
-        // stretch our aspect ratio to account for later transformations.
-        let aspect_ratio = (tw / th) * (viewport_height / viewport_width);
+    /// Like [`TextConstraintInstance::new`], but for text that isn't in the
+    /// arena. See [`TextRenderer::with_provisional`].
+    fn provisional(
+        text: &str,
+        order: DrawOrder,
+        glyph_brush: &mut wgpu_glyph::GlyphBrush<()>,
+        bound: &SpatialBound,
+        view_projection_matrix: &cgmath::Matrix4<f32>,
+        viewport_width: f32,
+        viewport_height: f32,
+        truncation: Truncation,
+        pixel_scale: f32,
+    ) -> Self {
+        let class = string_class::classify(text);
+        let text = Cow::Owned(class.display(text).into_owned());
+        Self::fit(
+            None,
+            text,
+            order,
+            class,
+            glyph_brush,
+            bound,
+            view_projection_matrix,
+            viewport_width,
+            viewport_height,
+            truncation,
+            pixel_scale,
+        )
+    }
 
-        // Now that we've got our adjusted aspect ratio, we need to calculate
-        // the desired size (in virtual coordinate space) of our text (as
-        // opposed to the arbitrary, 20pt one we've got now).
-        let cuboid = SpatialBound::cuboid_inside_bound(bound, aspect_ratio);
-        let (width, height) = {
-            let (w, h) = cuboid.dimensions_2d();
-            (w * 0.5 * viewport_width, h * 0.5 * viewport_height)
+    /// Sizes `text` to fit inside of `bound`, shortening it if it can't be
+    /// drawn at a readable size. Text that is [`Cow::Owned`] is drawn in place
+    /// of the string bound to `key`.
+    fn fit(
+        key: Option<ArenaKey>,
+        text: Cow<str>,
+        order: DrawOrder,
+        class: StringClass,
+        glyph_brush: &mut wgpu_glyph::GlyphBrush<()>,
+        bound: &SpatialBound,
+        view_projection_matrix: &cgmath::Matrix4<f32>,
+        viewport_width: f32,
+        viewport_height: f32,
+        truncation: Truncation,
+        pixel_scale: f32,
+    ) -> Self {
+        let (font_id, _) = style(class);
+        let min_text_scale = MIN_READABLE_TEXT_SCALE * pixel_scale;
+
+        let mut fit = TextFit::new(
+            glyph_brush,
+            &text,
+            font_id,
+            bound,
+            viewport_width,
+            viewport_height,
+        );
+        let mut display_text = match &text {
+            Cow::Borrowed(_) => None,
+            Cow::Owned(text) => Some(text.clone()),
         };
-        // 'diff' gives the amount to scale our (currently 20pt) text so that it
-        // fits nicely in our desired bounding box.
-        let diff = width / tw;
-        section.text[0].scale = (section.text[0].scale.x * diff).into();
+
+        if fit.text_scale < min_text_scale {
+            // The whole string would be too small to read, so find the largest
+            // number of graphemes that can be kept (alongside an ellipsis) while
+            // still being readable. Removing graphemes never makes the text
+            // wider, so the fitted scale grows as `keep` shrinks and we can
+            // binary search for it.
+            let graphemes = text.graphemes(true).collect::<Vec<_>>();
+            if graphemes.len() > 1 {
+                let (mut low, mut high) = (1, graphemes.len() - 1);
+                let mut best = None;
+                while low <= high {
+                    let keep = low + (high - low) / 2;
+                    let candidate = truncation.apply(&graphemes, keep);
+                    let candidate_fit = TextFit::new(
+                        glyph_brush,
+                        &candidate,
+                        font_id,
+                        bound,
+                        viewport_width,
+                        viewport_height,
+                    );
+                    if candidate_fit.text_scale >= min_text_scale {
+                        best = Some((candidate, candidate_fit));
+                        low = keep + 1;
+                    } else {
+                        high = keep - 1;
+                    }
+                }
+                // If not even a single grapheme is readable, show as little as
+                // possible so that the string is at least recognizable as one.
+                let (candidate, candidate_fit) = best.unwrap_or_else(|| {
+                    let candidate = truncation.apply(&graphemes, 1);
+                    let candidate_fit = TextFit::new(
+                        glyph_brush,
+                        &candidate,
+                        font_id,
+                        bound,
+                        viewport_width,
+                        viewport_height,
+                    );
+                    (candidate, candidate_fit)
+                });
+                fit = candidate_fit;
+                display_text = Some(candidate);
+            }
+        }
+
+        let TextFit {
+            text_scale,
+            width,
+            height,
+            cuboid,
+        } = fit;
 
         let virtual_height = SpatialBound::cuboid_inside_bound(bound, width / height).height();
 
-        let text_scale = section.text[0].scale.x;
         let transform_scale = virtual_height / height;
 
+        let underline = match class {
+            StringClass::Url => {
+                let (cuboid_width, cuboid_height) = cuboid.dimensions_2d();
+                let thickness = cuboid_height * UNDERLINE_THICKNESS;
+                Some(SquareCuboid::from_dimensions(
+                    cuboid_width,
+                    thickness,
+                    (
+                        cuboid.center.x,
+                        cuboid.center.y - 0.5 * (cuboid_height - thickness),
+                        cuboid.center.z,
+                    )
+                        .into(),
+                ))
+            }
+            _ => None,
+        };
+
         Self {
-            key: *key,
+            key,
+            order,
+            class,
+            depth: 0,
+            dimmed: false,
+            display_text,
+            underline,
             width: width,
             height: height,
             text_scale,
@@ -239,16 +1000,6 @@ impl TextConstraintInstance {
         }
     }
 
-    fn text_dimensions(
-        glyph_brush: &mut GlyphBrush<()>,
-        section: &wgpu_glyph::Section,
-    ) -> (f32, f32) {
-        match glyph_brush.glyph_bounds(section.clone()) {
-            Some(rect) => (rect.width(), rect.height()),
-            None => (1.0, 1.0),
-        }
-    }
-
     fn set_view_projection_matrix(&mut self, view_projection_matrix: &cgmath::Matrix4<f32>) {
         self.transformation = Self::calculate_transformation(
             view_projection_matrix,
@@ -277,3 +1028,113 @@ impl TextConstraintInstance {
         *(view_projection_matrix * transformation).as_mut()
     }
 }
+
+/// The size at which a piece of text fits inside of a [`SpatialBound`].
+///
+/// Text is measured with glyph_brush's default layout, whose line breaker
+/// follows Unicode line breaking (UAX #14). Since the section is unbounded,
+/// only mandatory breaks (like newlines) ever start a new line: strings are
+/// shrunk and then truncated to fit rather than wrapped. Previews (see
+/// [`TextRenderer::set_preview_words`]) are broken onto lines before they are
+/// measured.
+///
+/// glyph_brush places glyphs left to right in the order they are stored and
+/// doesn't shape them, so Arabic and Hebrew text is drawn backwards and
+/// unjoined. Fixing that needs a bidi implementation (like the `unicode-bidi`
+/// crate) to detect each paragraph's direction and reorder each line into
+/// visual order before it is queued, and a shaper for joining forms. Both
+/// belong here once strings wrap, since reordering has to happen per line.
+struct TextFit {
+    /// Point scale of the text.
+    text_scale: f32,
+    /// The width, in pixels, that the text will be rendered at.
+    width: f32,
+    /// The height, in pixels, that the text will be rendered at.
+    height: f32,
+    /// The box (in virtual coordinate space) that the text fills.
+    cuboid: SquareCuboid,
+}
+
+impl TextFit {
+    fn new(
+        glyph_brush: &mut GlyphBrush<()>,
+        text: &str,
+        font_id: FontId,
+        bound: &SpatialBound,
+        viewport_width: f32,
+        viewport_height: f32,
+    ) -> Self {
+        // Use an arbitrary default scale (20.0) to determine the aspect ratio
+        // of the text's bounding box.
+        let section = wgpu_glyph::Section {
+            screen_position: (0.0, 0.0),
+            bounds: (f32::INFINITY, f32::INFINITY),
+            text: vec![wgpu_glyph::Text::new(text)
+                .with_color([0.0, 0.0, 0.0, 1.0])
+                .with_font_id(font_id)
+                .with_scale(20.0)],
+            ..wgpu_glyph::Section::default()
+        };
+        let (tw, th) = Self::text_dimensions(glyph_brush, &section);
+        // The true aspect ratio (what you would see on screen) is (tw / th).
+        // Since our spatial bound parameter comes from virtual coordinate space
+        // (which goes from -1..1 in all dimensions), we need to squish /
+        // stretch our aspect ratio to account for later transformations.
+        let aspect_ratio = (tw / th) * (viewport_height / viewport_width);
+
+        // Now that we've got our adjusted aspect ratio, we need to calculate
+        // the desired size (in virtual coordinate space) of our text (as
+        // opposed to the arbitrary, 20pt one we've got now).
+        let cuboid = SpatialBound::cuboid_inside_bound(bound, aspect_ratio);
+        let (width, height) = {
+            let (w, h) = cuboid.dimensions_2d();
+            (w * 0.5 * viewport_width, h * 0.5 * viewport_height)
+        };
+        // 'diff' gives the amount to scale our (currently 20pt) text so that it
+        // fits nicely in our desired bounding box.
+        let diff = width / tw;
+
+        Self {
+            text_scale: section.text[0].scale.x * diff,
+            width,
+            height,
+            cuboid,
+        }
+    }
+
+    fn text_dimensions(
+        glyph_brush: &mut GlyphBrush<()>,
+        section: &wgpu_glyph::Section,
+    ) -> (f32, f32) {
+        match glyph_brush.glyph_bounds(section.clone()) {
+            Some(rect) => (rect.width(), rect.height()),
+            None => (1.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn truncation() {
+        let graphemes = "kakoi".graphemes(true).collect::<Vec<_>>();
+        assert_eq!("kak\u{2026}", Truncation::End.apply(&graphemes, 3));
+        assert_eq!("ka\u{2026}i", Truncation::Middle.apply(&graphemes, 3));
+        assert_eq!("k\u{2026}", Truncation::Middle.apply(&graphemes, 1));
+    }
+
+    #[test]
+    fn previews() {
+        assert_eq!(None, preview("short enough", 2));
+        assert_eq!(
+            Some("one\ntwo\nthree\u{2026}".to_string()),
+            preview("one two three four", 3)
+        );
+        assert_eq!(
+            Some("abcdefghijklmnop\u{2026}".to_string()),
+            preview("abcdefghijklmnopqrstuvwxyz", 3)
+        );
+    }
+}
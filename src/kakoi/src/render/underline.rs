@@ -0,0 +1,208 @@
+use super::circle::{CircleConstraintInstance, Vertex};
+use super::context::{ColorUniform, RenderContext};
+use super::draw_order::{batch_range, DrawOrder};
+use super::text::URL_COLOR;
+use crate::sphere::Sphere;
+use crate::square_cuboid::SquareCuboid;
+use wgpu::util::DeviceExt;
+
+/// The number of vertices in the two triangles making up an underline.
+const VERTICES_PER_UNDERLINE: usize = 6;
+
+/// Returns the triangles filling in `square_cuboid`.
+fn make_underline(square_cuboid: &SquareCuboid) -> [Vertex; VERTICES_PER_UNDERLINE] {
+    let (width, height) = square_cuboid.dimensions_2d();
+    let center = square_cuboid.center;
+    let (left, right) = (center.x - 0.5 * width, center.x + 0.5 * width);
+    let (bottom, top) = (center.y - 0.5 * height, center.y + 0.5 * height);
+    let vertex = |x, y| Vertex {
+        position: [x, y, center.z],
+    };
+    [
+        vertex(left, top),
+        vertex(left, bottom),
+        vertex(right, bottom),
+        vertex(left, top),
+        vertex(right, bottom),
+        vertex(right, top),
+    ]
+}
+
+/// Draws the lines beneath strings that are
+/// [`StringClass::Url`](crate::string_class::StringClass::Url)s.
+///
+/// Where the lines go is only known once the [`TextRenderer`] has fitted its
+/// strings, so they are passed to [`UnderlineRenderer::prepare`] every frame
+/// rather than being added while building the spatial tree. Each line is drawn
+/// in the same [`DrawOrder`] batch as its string.
+///
+/// [`TextRenderer`]: super::text::TextRenderer
+pub struct UnderlineRenderer {
+    constraints: Vec<(DrawOrder, SquareCuboid)>,
+    vertex_buffer_cache: Option<wgpu::Buffer>,
+    instance_buffer: wgpu::Buffer,
+    render_pipeline: wgpu::RenderPipeline,
+    color: ColorUniform,
+}
+
+impl UnderlineRenderer {
+    pub fn new<'a>(
+        device: &'a wgpu::Device,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        context: &'a RenderContext,
+    ) -> Self {
+        // Like rectangles, the vertices of each line are already in place, so
+        // they are drawn with a single identity instance.
+        let identity = CircleConstraintInstance::new(&Sphere {
+            center: (0.0, 0.0, 0.0).into(),
+            radius: 1.0,
+        });
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("underline renderer instance buffer"),
+            contents: bytemuck::cast_slice(&[identity]),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        let vs_module =
+            device.create_shader_module(&wgpu::include_spirv!("../shaders/build/circle.vert.spv"));
+        let fs_module = device
+            .create_shader_module(&wgpu::include_spirv!("../shaders/build/selection.frag.spv"));
+
+        let color = ColorUniform::new(
+            device,
+            context,
+            URL_COLOR,
+            "underline renderer color uniform",
+        );
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("underline renderer pipeline layout"),
+                bind_group_layouts: &[context.camera_layout(), context.color_layout()],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("underline renderer pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vs_module,
+                entry_point: "main",
+                buffers: &[Vertex::desc(), CircleConstraintInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fs_module,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc_desc.format,
+                    alpha_blend: wgpu::BlendState::REPLACE,
+                    color_blend: wgpu::BlendState::REPLACE,
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            constraints: Vec::new(),
+            vertex_buffer_cache: None,
+            instance_buffer,
+            render_pipeline,
+            color,
+        }
+    }
+
+    /// Uploads everything needed to draw this frame. `underlines` are only
+    /// used if the lines were invalidated since they were last uploaded. Must
+    /// be called before [`UnderlineRenderer::render`].
+    pub fn prepare<'a, I: IntoIterator<Item = (DrawOrder, SquareCuboid)>>(
+        &mut self,
+        device: &'a wgpu::Device,
+        underlines: I,
+    ) {
+        if self.vertex_buffer_cache.is_none() {
+            self.constraints = underlines.into_iter().collect();
+            if self.constraints.is_empty() {
+                return;
+            }
+            let _span =
+                tracing::debug_span!("underline_build_vertices", count = self.constraints.len())
+                    .entered();
+            self.constraints.sort_by_key(|&(order, _)| order);
+            let vertices: Vec<Vertex> = self
+                .constraints
+                .iter()
+                .flat_map(|(_, square_cuboid)| make_underline(square_cuboid).to_vec())
+                .collect();
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("underline renderer vertex buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsage::VERTEX,
+            });
+            self.vertex_buffer_cache = Some(buffer);
+        }
+    }
+
+    /// Draws the lines whose order is `order`.
+    pub fn render<'a>(
+        &mut self,
+        sc_desc: &'a wgpu::SwapChainDescriptor,
+        command_encoder: &'a mut wgpu::CommandEncoder,
+        texture_view: &'a wgpu::TextureView,
+        context: &'a RenderContext,
+        order: DrawOrder,
+    ) {
+        let range = batch_range(&self.constraints, order, |&(order, _)| order);
+        if range.is_empty() {
+            return;
+        }
+        let _span = tracing::trace_span!("underline_render").entered();
+
+        let vertex_buffer = match &self.vertex_buffer_cache {
+            Some(vertex_buffer) => vertex_buffer,
+            None => return,
+        };
+
+        let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("underline renderer render pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, context.camera_bind_group(), &[]);
+        render_pass.set_bind_group(1, self.color.bind_group(), &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        let width = sc_desc.width as f32;
+        let height = sc_desc.height as f32;
+        render_pass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
+        let start = (range.start * VERTICES_PER_UNDERLINE) as u32;
+        let end = (range.end * VERTICES_PER_UNDERLINE) as u32;
+        render_pass.draw(start..end, 0..1);
+    }
+
+    pub fn invalidate(&mut self) {
+        self.constraints = Vec::new();
+        self.vertex_buffer_cache = None;
+    }
+}
@@ -0,0 +1,422 @@
+//! # Replaying input
+//!
+//! Most of what Kakoi does starts with a key press: the [`InputManager`] turns
+//! key presses into [`CompleteAction`]s, which are then applied to the
+//! [`Arena`]. A [`Session`] feeds key presses and typed characters through an
+//! [`InputManager`] and applies the actions that come out of it to an arena,
+//! without a window or a GPU, so that the whole way from key presses to the
+//! arena can be tested:
+//!
+//! ```
+//! use kakoi::arena::Arena;
+//! use kakoi::replay::Session;
+//! use winit::event::VirtualKeyCode;
+//!
+//! let mut session = Session::with_arena(Arena::new());
+//! session.press(VirtualKeyCode::T);
+//! session.type_text("milk");
+//! session.chord(&[VirtualKeyCode::LShift], VirtualKeyCode::Return);
+//! session.assert_strings(".", &["milk"]);
+//! ```
+//!
+//! Only the actions that change nothing but the arena, like inserting into
+//! sets and binding registers, are applied, the same way the window applies
+//! them (see [`crate::perform`]), and they are rejected the same way in
+//! read-only mode or when they would change something locked. Every other
+//! action is kept in [`Session::unapplied`], so that tests can check that it
+//! was asked for.
+//!
+//! Writing out key presses by hand is tedious, so `kakoi --record-session
+//! FILE` records every key pressed and character typed in the window, and
+//! writes them out to `FILE` as a test replaying them when the window is
+//! closed (see [`Recorder`]). The test still needs assertions about what the
+//! session should have done.
+
+use crate::arena::{Arena, ArenaKey, Structure};
+use crate::input_manager::{BindingMode, CompleteAction, InputManager};
+use crate::perform::{self, Outcome};
+use crate::tutorial;
+use std::{fs, io, path::Path};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+/// Something done on the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Down(VirtualKeyCode),
+    Up(VirtualKeyCode),
+    /// A character typed, as produced by the keyboard layout.
+    Character(char),
+}
+
+#[allow(deprecated)]
+fn keyboard_input(key: VirtualKeyCode, state: ElementState) -> KeyboardInput {
+    KeyboardInput {
+        scancode: 0,
+        state,
+        virtual_keycode: Some(key),
+        modifiers: Default::default(),
+    }
+}
+
+/// Keys pressed and characters typed, applied to an arena without a window.
+/// See [the module-level documentation](crate::replay).
+pub struct Session {
+    pub arena: Arena,
+    pub input_manager: InputManager,
+    /// Every action that was asked for but couldn't be applied without a
+    /// window, in the order they were asked for.
+    pub unapplied: Vec<CompleteAction>,
+    /// Whether nothing can be changed, like the read-only mode of the
+    /// window.
+    pub read_only: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    /// Starts a session looking at the tutorial, like the window does.
+    pub fn new() -> Self {
+        let mut arena = Arena::new();
+        let tutorial = tutorial::build(&mut arena, BindingMode::VirtualKey);
+        arena.bind_register(".", tutorial);
+        Self::with_arena(arena)
+    }
+
+    /// Starts a session with `arena`, which is left as it is.
+    pub fn with_arena(arena: Arena) -> Self {
+        Self {
+            arena,
+            input_manager: InputManager::new(BindingMode::VirtualKey),
+            unapplied: Vec::new(),
+            read_only: false,
+        }
+    }
+
+    /// Performs `step`, applying the action it completes, if any.
+    pub fn step(&mut self, step: Step) {
+        let action = match step {
+            Step::Down(key) | Step::Up(key) => {
+                let registers = self
+                    .arena
+                    .registers()
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                self.input_manager.set_register_names(registers);
+                let state = if step == Step::Down(key) {
+                    ElementState::Pressed
+                } else {
+                    ElementState::Released
+                };
+                self.input_manager
+                    .process_input(&keyboard_input(key, state))
+            }
+            Step::Character(character) => self.input_manager.process_character(character),
+        };
+        if let Some(action) = action {
+            if !self.apply(&action) {
+                self.unapplied.push(action);
+            }
+        }
+    }
+
+    /// Performs every one of `steps` in order.
+    pub fn steps<I: IntoIterator<Item = Step>>(&mut self, steps: I) {
+        for step in steps {
+            self.step(step);
+        }
+    }
+
+    pub fn down(&mut self, key: VirtualKeyCode) {
+        self.step(Step::Down(key));
+    }
+
+    pub fn up(&mut self, key: VirtualKeyCode) {
+        self.step(Step::Up(key));
+    }
+
+    /// Presses and releases `key`.
+    pub fn press(&mut self, key: VirtualKeyCode) {
+        self.down(key);
+        self.up(key);
+    }
+
+    /// Presses `key` while holding down `modifiers`, like
+    /// `chord(&[VirtualKeyCode::LControl], VirtualKeyCode::Z)`.
+    pub fn chord(&mut self, modifiers: &[VirtualKeyCode], key: VirtualKeyCode) {
+        for &modifier in modifiers {
+            self.down(modifier);
+        }
+        self.press(key);
+        for &modifier in modifiers.iter().rev() {
+            self.up(modifier);
+        }
+    }
+
+    /// Types each character of `text`, without pressing any keys. This is how
+    /// strings are entered once a key binding asks for one.
+    pub fn type_text(&mut self, text: &str) {
+        for character in text.chars() {
+            self.step(Step::Character(character));
+        }
+    }
+
+    /// Applies `action` to the arena with [`perform::apply`], like the window
+    /// does, returning false if it can't be applied without a window, or if
+    /// it failed or was rejected (see [`perform::rejection`]).
+    pub fn apply(&mut self, action: &CompleteAction) -> bool {
+        let arena = &mut self.arena;
+        match action {
+            CompleteAction::ToggleReadOnly => {
+                self.read_only = !self.read_only;
+                return true;
+            }
+            CompleteAction::SelectRegister(register) => {
+                return match arena.register(register.as_str()) {
+                    Some(key) => {
+                        arena.bind_register(".", key);
+                        true
+                    }
+                    None => false,
+                };
+            }
+            _ => {}
+        }
+        let modified = perform::modified(arena, action);
+        let rejection = perform::rejection(arena, self.read_only, action.mutates(), &modified);
+        if let Some(reason) = rejection {
+            log::info!("rejected {:?}: {}", action, reason);
+            return false;
+        }
+        matches!(perform::apply(arena, action, None), Outcome::Performed(_))
+    }
+
+    /// Returns the members of the set bound to `register`, or [`None`] if it
+    /// isn't bound to a set.
+    pub fn members(&mut self, register: &str) -> Option<Vec<ArenaKey>> {
+        let set = self.arena.register(register)?;
+        match &self.arena.slot_map[set].structure {
+            Structure::Set(members) => Some(members.iter().copied().collect()),
+            _ => None,
+        }
+    }
+
+    /// Returns what the strings in the set bound to `register` say, sorted,
+    /// or [`None`] if it isn't bound to a set.
+    pub fn strings(&mut self, register: &str) -> Option<Vec<String>> {
+        let mut strings: Vec<String> = self
+            .members(register)?
+            .into_iter()
+            .filter_map(|member| match &self.arena.slot_map[member].structure {
+                Structure::String(string) => Some(string.to_string()),
+                _ => None,
+            })
+            .collect();
+        strings.sort();
+        Some(strings)
+    }
+
+    /// Panics unless the set bound to `register` contains exactly the strings
+    /// `expected` (in any order), alongside any values that aren't strings.
+    pub fn assert_strings(&mut self, register: &str, expected: &[&str]) {
+        let mut expected: Vec<String> = expected.iter().map(|&s| s.to_string()).collect();
+        expected.sort();
+        match self.strings(register) {
+            Some(strings) => assert_eq!(
+                expected, strings,
+                "the strings in the set bound to {}",
+                register
+            ),
+            None => panic!("{} isn't bound to a set", register),
+        }
+    }
+}
+
+/// Records what is done on the keyboard in the window, to be replayed by a
+/// [`Session`]. See [the module-level documentation](crate::replay).
+#[derive(Debug, Default)]
+pub struct Recorder {
+    steps: Vec<Step>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Records a key press or release. Keys without a
+    /// [`VirtualKeyCode`] can't be replayed, so they are left out.
+    pub fn key(&mut self, input: &KeyboardInput) {
+        if let Some(key) = input.virtual_keycode {
+            self.steps.push(match input.state {
+                ElementState::Pressed => Step::Down(key),
+                ElementState::Released => Step::Up(key),
+            });
+        }
+    }
+
+    pub fn character(&mut self, character: char) {
+        self.steps.push(Step::Character(character));
+    }
+
+    /// Writes out a test named `name` replaying every step recorded so far.
+    /// Keys pressed and released straight away become [`Session::press`],
+    /// and characters typed in a row become [`Session::type_text`]. The test
+    /// expects [`Session`] and [`VirtualKeyCode`] to have been imported.
+    pub fn test_source(&self, name: &str) -> String {
+        let mut body = String::new();
+        let mut steps = self.steps.iter().peekable();
+        while let Some(&step) = steps.next() {
+            match step {
+                Step::Down(key) if steps.peek() == Some(&&Step::Up(key)) => {
+                    steps.next();
+                    body += &format!("    session.press(VirtualKeyCode::{:?});\n", key);
+                }
+                Step::Down(key) => {
+                    body += &format!("    session.down(VirtualKeyCode::{:?});\n", key)
+                }
+                Step::Up(key) => body += &format!("    session.up(VirtualKeyCode::{:?});\n", key),
+                Step::Character(character) => {
+                    let mut text = character.to_string();
+                    while let Some(&&Step::Character(character)) = steps.peek() {
+                        text.push(character);
+                        steps.next();
+                    }
+                    body += &format!("    session.type_text({:?});\n", text);
+                }
+            }
+        }
+        format!(
+            "#[test]\nfn {}() {{\n    let mut session = Session::new();\n{}    // What should the session have done?\n    // session.assert_strings(\".\", &[]);\n}}\n",
+            name, body
+        )
+    }
+
+    /// Writes [`Recorder::test_source`] out to `path`, naming the test after
+    /// the file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let name: String = path
+            .file_stem()
+            .map_or("recorded_session".into(), |stem| stem.to_string_lossy())
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        fs::write(path, self.test_source(&name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sessions() {
+        let mut session = Session::new();
+        let tutorial = session.arena.register(".").unwrap();
+        session.press(VirtualKeyCode::T);
+        session.type_text("milk");
+        session.chord(&[VirtualKeyCode::LShift], VirtualKeyCode::Return);
+        assert_eq!(Some(tutorial), session.arena.register("."));
+        assert!(session.strings(".").unwrap().contains(&"milk".to_string()));
+        // Undoing needs a window, so it is only asked for.
+        session.press(VirtualKeyCode::Z);
+        assert_eq!(vec![CompleteAction::Undo], session.unapplied);
+
+        let mut empty = Session::with_arena(Arena::new());
+        empty.press(VirtualKeyCode::T);
+        empty.type_text("a b");
+        empty.chord(&[VirtualKeyCode::LShift], VirtualKeyCode::Return);
+        empty.assert_strings(".", &["a b"]);
+    }
+
+    #[test]
+    fn rejections() {
+        let mut session = Session::with_arena(Arena::new());
+        let insert = |session: &mut Session| {
+            session.press(VirtualKeyCode::T);
+            session.type_text("milk");
+            session.chord(&[VirtualKeyCode::LShift], VirtualKeyCode::Return);
+        };
+        let selected = session.arena.register(".").unwrap();
+        session.arena.set_locked(selected, true);
+        insert(&mut session);
+        session.assert_strings(".", &[]);
+        session.arena.set_locked(selected, false);
+        session.press(VirtualKeyCode::F2);
+        insert(&mut session);
+        session.assert_strings(".", &[]);
+        session.press(VirtualKeyCode::F2);
+        insert(&mut session);
+        session.assert_strings(".", &["milk"]);
+    }
+
+    #[test]
+    fn numbers() {
+        let mut session = Session::with_arena(Arena::new());
+        session.press(VirtualKeyCode::Equals);
+        session.type_text("2.5");
+        session.chord(&[VirtualKeyCode::LShift], VirtualKeyCode::Return);
+        let four = session.arena.number(4.0);
+        let half = session.arena.number(0.5);
+        session.arena.bind_register("a", four);
+        session.arena.bind_register("b", half);
+        session.chord(&[VirtualKeyCode::LControl], VirtualKeyCode::A);
+        // The fourth operation is division.
+        session.press(VirtualKeyCode::Key4);
+        session.press(VirtualKeyCode::A);
+        session.press(VirtualKeyCode::B);
+        let mut numbers: Vec<f64> = session
+            .members(".")
+            .unwrap()
+            .into_iter()
+            .filter_map(|member| session.arena.as_number(member))
+            .collect();
+        numbers.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(vec![2.5, 8.0], numbers);
+    }
+
+    #[test]
+    fn lists() {
+        let mut session = Session::with_arena(Arena::new());
+        let (a, b) = (session.arena.string("a"), session.arena.string("b"));
+        session.arena.bind_register("a", a);
+        session.arena.bind_register("b", b);
+        session.chord(&[VirtualKeyCode::LControl], VirtualKeyCode::O);
+        for register in [VirtualKeyCode::B, VirtualKeyCode::A, VirtualKeyCode::B].iter() {
+            session.chord(&[VirtualKeyCode::LControl], VirtualKeyCode::I);
+            session.press(*register);
+        }
+        session.chord(&[VirtualKeyCode::LControl], VirtualKeyCode::X);
+        let list = session.arena.register(".").unwrap();
+        assert_eq!(Some(&[b, a][..]), session.arena.elements(list));
+    }
+
+    #[test]
+    fn recording() {
+        let mut recorder = Recorder::new();
+        let press = |key| keyboard_input(key, ElementState::Pressed);
+        let release = |key| keyboard_input(key, ElementState::Released);
+        recorder.key(&press(VirtualKeyCode::T));
+        recorder.key(&release(VirtualKeyCode::T));
+        recorder.character('t');
+        recorder.character('"');
+        recorder.key(&press(VirtualKeyCode::LShift));
+        recorder.key(&press(VirtualKeyCode::Return));
+        assert_eq!(
+            "#[test]\nfn typing() {\n    let mut session = Session::new();\n    \
+             session.press(VirtualKeyCode::T);\n    session.type_text(\"t\\\"\");\n    \
+             session.down(VirtualKeyCode::LShift);\n    session.down(VirtualKeyCode::Return);\n    \
+             // What should the session have done?\n    // session.assert_strings(\".\", &[]);\n}\n",
+            recorder.test_source("typing")
+        );
+    }
+}
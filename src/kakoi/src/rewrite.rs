@@ -0,0 +1,152 @@
+//! # Find and replace
+//!
+//! Renaming things one string at a time gets tedious when a whole set of them
+//! follows the same pattern. [`CompleteAction::Rewrite`] finds every string
+//! directly inside of the set or list the topmost layer displays that matches
+//! a [regular expression](regex), and replaces what matched. Replacements
+//! can refer to what was captured with `$1` (or `${1}`, when letters or
+//! digits follow it).
+//!
+//! The strings are changed in place (see [`Arena::rewrite_strings`]), so they
+//! change everywhere they appear, not just in the set or list being
+//! rewritten. Before anything changes, a [`LayerKind::Confirmation`] layer
+//! shows a sample of the changes, waiting for the user to accept or discard
+//! them. Accepted changes can be undone all at once with
+//! [`CompleteAction::Undo`].
+//!
+//! Some strings are left alone, since changing them would change more than
+//! their text:
+//!
+//! * Strings that are locked (see [`Arena::is_locked`]).
+//! * Strings that are the keys of maps, like the names of registers and tags.
+//! * Strings whose new text is already stored elsewhere, since strings are
+//!   only ever stored once. Rewriting would have to merge the two values.
+//!
+//! [`CompleteAction::Rewrite`]: crate::input_manager::CompleteAction::Rewrite
+//! [`CompleteAction::Undo`]: crate::input_manager::CompleteAction::Undo
+//! [`LayerKind::Confirmation`]: crate::overlay::LayerKind::Confirmation
+
+use crate::arena::{Arena, ArenaKey, MapRoute, Route, Structure};
+use regex::Regex;
+use std::collections::HashSet;
+
+/// How many changes are shown before accepting a rewrite.
+pub const PREVIEW_LINES: usize = 8;
+
+/// The changes a rewrite would make, each pairing the key of a string with its
+/// new text.
+pub struct Rewrite {
+    pub edits: Vec<(ArenaKey, String)>,
+    /// How many matching strings are left alone. See [the module-level
+    /// documentation](crate::rewrite).
+    pub skipped: usize,
+}
+
+/// Returns true if changing the string bound to `key` would change more than
+/// its text.
+fn is_protected(arena: &Arena, key: ArenaKey) -> bool {
+    arena.is_locked(key)
+        || arena.slot_map[key]
+            .inclusions
+            .iter()
+            .any(|(_, route)| *route == Route::Map(MapRoute::Key))
+}
+
+/// Finds what replacing every match of `regex` with `replacement` would change
+/// in the strings directly inside of `container`. Returns [`None`] if
+/// `container` isn't a set or list.
+pub fn plan(
+    arena: &Arena,
+    container: ArenaKey,
+    regex: &Regex,
+    replacement: &str,
+) -> Option<Rewrite> {
+    let members: Vec<ArenaKey> = match &arena.slot_map.get(container)?.structure {
+        Structure::Set(members) => arena.arrange(members),
+        Structure::List(elements) => elements.to_vec(),
+        _ => return None,
+    };
+    let mut rewrite = Rewrite {
+        edits: Vec::new(),
+        skipped: 0,
+    };
+    let mut seen = HashSet::new();
+    let mut claimed = HashSet::new();
+    for key in members {
+        let string = match &arena.slot_map[key].structure {
+            Structure::String(string) if seen.insert(key) => string,
+            _ => continue,
+        };
+        let replaced = regex.replace_all(string, replacement);
+        if replaced == **string {
+            continue;
+        }
+        let replaced = replaced.into_owned();
+        let taken = arena
+            .lookup_string(&replaced)
+            .map_or(false, |other| other != key);
+        if is_protected(arena, key) || taken || !claimed.insert(replaced.clone()) {
+            rewrite.skipped += 1;
+        } else {
+            rewrite.edits.push((key, replaced));
+        }
+    }
+    Some(rewrite)
+}
+
+impl Rewrite {
+    /// Describes the first few changes, one per line, like `old → new`.
+    pub fn preview(&self, arena: &Arena) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .edits
+            .iter()
+            .take(PREVIEW_LINES)
+            .map(|(key, new)| match &arena.slot_map[*key].structure {
+                Structure::String(old) => format!("{} → {}", old, new),
+                _ => unreachable!(),
+            })
+            .collect();
+        if self.edits.len() > PREVIEW_LINES {
+            lines.push(format!("… and {} more", self.edits.len() - PREVIEW_LINES));
+        }
+        lines
+    }
+
+    /// Summarizes how many strings would change, and how many are left alone.
+    pub fn describe(&self) -> String {
+        let mut description = format!("{} strings would change", self.edits.len());
+        if self.skipped > 0 {
+            description.push_str(&format!(", {} left alone", self.skipped));
+        }
+        description
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn planning() {
+        let mut arena = Arena::new();
+        let strings = arena.insert_strings(vec!["img_1", "img_2", "notes", "photo_2"]);
+        let list = arena.list(strings.clone());
+        arena.bind_register("img_1", list);
+        let regex = Regex::new("^img_(\\d)$").unwrap();
+        let rewrite = super::plan(&arena, list, &regex, "photo_$1").unwrap();
+        // img_1 names a register, and photo_2 is already taken
+        assert_eq!(2, rewrite.skipped);
+        assert!(rewrite.edits.is_empty());
+        let regex = Regex::new("_").unwrap();
+        let rewrite = super::plan(&arena, list, &regex, "-").unwrap();
+        assert_eq!(
+            vec![
+                (strings[1], "img-2".to_string()),
+                (strings[3], "photo-2".to_string())
+            ],
+            rewrite.edits
+        );
+        assert_eq!(1, rewrite.skipped);
+        assert!(super::plan(&arena, strings[0], &regex, "-").is_none());
+    }
+}
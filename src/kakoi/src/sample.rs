@@ -0,0 +1,125 @@
+//! # Sampling large sets
+//!
+//! Some sets have far too many members to make out on screen at once. Instead
+//! of displaying every member of such a set, a [`Layer`] can display a random
+//! [`Sample`] of them, alongside a message saying how many members there are in
+//! total. Reshuffling the sample shows a different selection of members, which
+//! gives a feel for what is in the set without having to page through it.
+//!
+//! Samples are chosen with a seeded random number generator, so the same
+//! sample of an unchanged set always contains the same members.
+//!
+//! [`Layer`]: crate::overlay::Layer
+
+use crate::arena::ArenaKey;
+use std::collections::HashSet;
+
+/// How many members a sample contains, unless the set is smaller than this.
+pub const DEFAULT_SAMPLE_SIZE: usize = 64;
+
+/// A random selection of (at most) `size` members of a set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub size: usize,
+    pub seed: u64,
+}
+
+/// The [SplitMix64] random number generator. It is tiny, fast, and good
+/// enough for choosing samples. The number it holds is its seed, at first.
+///
+/// [SplitMix64]: https://prng.di.unimi.it/splitmix64.c
+pub struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number from zero up to (but not including) `bound`.
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /// Returns a number from zero up to (but not including) one.
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Sample {
+    /// A sample of [`DEFAULT_SAMPLE_SIZE`] members chosen according to
+    /// `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            size: DEFAULT_SAMPLE_SIZE,
+            seed,
+        }
+    }
+
+    /// A sample of the same size with different members.
+    pub fn reshuffle(self) -> Self {
+        Self {
+            seed: SplitMix64(self.seed).next_u64(),
+            ..self
+        }
+    }
+
+    /// Chooses the members of `set` in this sample, using [reservoir
+    /// sampling]. Members are considered in sorted order, since the order a
+    /// [`HashSet`] is iterated in isn't the same from one run to the next.
+    ///
+    /// [reservoir sampling]: https://en.wikipedia.org/wiki/Reservoir_sampling
+    pub fn choose(&self, set: &HashSet<ArenaKey>) -> HashSet<ArenaKey> {
+        if set.len() <= self.size {
+            return set.clone();
+        }
+        let mut members = set.iter().copied().collect::<Vec<_>>();
+        members.sort();
+        let mut rng = SplitMix64(self.seed);
+        let mut reservoir = members[..self.size].to_vec();
+        for (i, &member) in members.iter().enumerate().skip(self.size) {
+            let j = rng.below(i + 1);
+            if j < self.size {
+                reservoir[j] = member;
+            }
+        }
+        reservoir.into_iter().collect()
+    }
+
+    /// The message displayed alongside a sample of a set of `total` members.
+    pub fn describe(&self, total: usize) -> String {
+        format!(
+            "{} of {} members; n reshuffles",
+            self.size.min(total),
+            total
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use slotmap::SlotMap;
+
+    #[test]
+    fn choose() {
+        let mut slot_map: SlotMap<ArenaKey, ()> = SlotMap::with_key();
+        let set = (0..1000)
+            .map(|_| slot_map.insert(()))
+            .collect::<HashSet<_>>();
+        let sample = Sample::new(7);
+        let chosen = sample.choose(&set);
+        assert_eq!(DEFAULT_SAMPLE_SIZE, chosen.len());
+        assert!(chosen.is_subset(&set));
+        assert_eq!(chosen, sample.choose(&set.clone()));
+        assert_ne!(chosen, sample.reshuffle().choose(&set));
+
+        let small = set.iter().copied().take(3).collect::<HashSet<_>>();
+        assert_eq!(small, sample.choose(&small));
+        assert_eq!("3 of 3 members; n reshuffles", sample.describe(3));
+    }
+}
@@ -0,0 +1,218 @@
+//! # Search index
+//!
+//! Without an index, a `contains("text")` query (see [`crate::query`]) has to
+//! look through every string in the arena, which takes a noticeable moment
+//! once there are a hundred thousand of them. A [`SearchIndex`] remembers
+//! which strings contain each trigram (three characters in a row, ignoring
+//! case), so that only the strings containing every trigram of the text have
+//! to be looked at. Text shorter than a trigram can't be looked up, so
+//! queries for it still look through every string.
+//!
+//! Building the index takes as long as several queries without it, so it is
+//! built on a worker thread (see [`crate::task`]) from a [`Snapshot`] of the
+//! strings, the first time a query is run in an arena with at least
+//! [`MIN_STRINGS`] strings. Until it is ready, queries look through every
+//! string like before.
+//!
+//! Once built, the index catches up with the arena before each query (see
+//! [`SearchIndex::catch_up`]): it indexes the strings added since it last
+//! looked, and the strings changed by [`Arena::rewrite_strings`] (see
+//! [`Arena::rewritten_strings`]). What a changed string used to say is left
+//! in the index, since queries check every string they are given anyway.
+//!
+//! Strings removed by [`Arena::collect_garbage`] or pruned (see
+//! [`Arena::prune_orphans_keeping`]) are left in the index too, and skipped
+//! by queries.
+//!
+//! The index isn't written out along with the arena (see [`crate::json`]),
+//! since it can be built again from the strings; it is built the first time
+//! it is needed after Kakoi starts.
+
+use crate::arena::{Arena, ArenaKey, Kind, Structure};
+use crate::task::Progress;
+use std::collections::{HashMap, HashSet};
+
+/// The name of the [`Task`](crate::task::Task) building the index.
+pub const TASK_NAME: &str = "index strings";
+
+/// How many strings an arena needs before it is worth indexing. Looking
+/// through fewer strings than this is quick enough.
+pub const MIN_STRINGS: usize = 10_000;
+
+/// How many strings are indexed between reports of progress.
+const REPORT_EVERY: usize = 1024;
+
+type Trigram = [char; 3];
+
+/// Returns every distinct trigram of `text`, which should already be in
+/// lowercase.
+fn trigrams(text: &str) -> HashSet<Trigram> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .windows(3)
+        .map(|window| [window[0], window[1], window[2]])
+        .collect()
+}
+
+/// Copies of the strings in an arena, to build a [`SearchIndex`] from on a
+/// worker thread.
+pub struct Snapshot {
+    strings: Vec<(ArenaKey, String)>,
+    /// How many strings had been changed when the snapshot was taken.
+    rewritten: usize,
+}
+
+impl Snapshot {
+    pub fn take(arena: &Arena) -> Self {
+        let strings = arena
+            .keys_of_kind(Kind::String)
+            .into_iter()
+            .filter_map(|key| match &arena.slot_map[key].structure {
+                Structure::String(string) => Some((key, string.to_string())),
+                _ => None,
+            })
+            .collect();
+        Self {
+            strings,
+            rewritten: arena.rewritten_strings().len(),
+        }
+    }
+}
+
+/// Which strings contain each trigram. See [the module-level
+/// documentation](crate::search).
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    postings: HashMap<Trigram, Vec<ArenaKey>>,
+    indexed: HashSet<ArenaKey>,
+    /// How many entries of [`Arena::rewritten_strings`] have been indexed.
+    rewritten: usize,
+}
+
+/// Returns true if `arena` has enough strings to be worth indexing.
+pub fn worth_indexing(arena: &Arena) -> bool {
+    arena.keys_of_kind(Kind::String).len() >= MIN_STRINGS
+}
+
+impl SearchIndex {
+    /// Indexes every string in `snapshot`, returning [`None`] if `progress`
+    /// is cancelled first.
+    pub fn build(snapshot: Snapshot, progress: &Progress) -> Option<Self> {
+        let mut index = Self {
+            rewritten: snapshot.rewritten,
+            ..Self::default()
+        };
+        let count = snapshot.strings.len();
+        for (i, (key, string)) in snapshot.strings.into_iter().enumerate() {
+            if i % REPORT_EVERY == 0 {
+                if progress.is_cancelled() {
+                    return None;
+                }
+                progress.report(i as f32 / count as f32);
+            }
+            index.insert(key, &string);
+        }
+        Some(index)
+    }
+
+    fn insert(&mut self, key: ArenaKey, string: &str) {
+        for trigram in trigrams(&string.to_lowercase()) {
+            let keys = self.postings.entry(trigram).or_default();
+            if keys.last() != Some(&key) {
+                keys.push(key);
+            }
+        }
+        self.indexed.insert(key);
+    }
+
+    /// Indexes the strings added to `arena` or changed since the index last
+    /// caught up with it.
+    pub fn catch_up(&mut self, arena: &Arena) {
+        let rewritten = arena.rewritten_strings();
+        for &key in &rewritten[self.rewritten.min(rewritten.len())..] {
+            // Strings removed by `Arena::collect_garbage` are still listed.
+            if let Some(Structure::String(string)) =
+                arena.slot_map.get(key).map(|value| &value.structure)
+            {
+                self.insert(key, string);
+            }
+        }
+        self.rewritten = rewritten.len();
+        // Every key is checked, rather than only looking when the number of
+        // strings changes, since strings can be removed as others are added.
+        for key in arena.keys_of_kind(Kind::String) {
+            if self.indexed.contains(&key) {
+                continue;
+            }
+            if let Structure::String(string) = &arena.slot_map[key].structure {
+                self.insert(key, string);
+            }
+        }
+    }
+
+    /// Returns the strings that might contain `text` (which should already be
+    /// in lowercase), including every string that does, or [`None`] if `text`
+    /// is too short to look up.
+    pub fn candidates(&self, text: &str) -> Option<HashSet<ArenaKey>> {
+        let mut postings = Vec::new();
+        for trigram in trigrams(text) {
+            match self.postings.get(&trigram) {
+                Some(keys) => postings.push(keys),
+                None => return Some(HashSet::new()),
+            }
+        }
+        postings.sort_by_key(|keys| keys.len());
+        let (rarest, rest) = postings.split_first()?;
+        let rest: Vec<HashSet<ArenaKey>> = rest
+            .iter()
+            .map(|keys| keys.iter().copied().collect())
+            .collect();
+        Some(
+            rarest
+                .iter()
+                .copied()
+                .filter(|key| rest.iter().all(|keys| keys.contains(key)))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn catching_up() {
+        let mut arena = Arena::new();
+        let apple = arena.string("Apple pie");
+        let maple = arena.string("maple syrup");
+        arena.string("pear");
+        let mut index =
+            SearchIndex::build(Snapshot::take(&arena), &Progress::unobserved()).unwrap();
+        let set = |keys: &[ArenaKey]| keys.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(Some(set(&[apple, maple])), index.candidates("ple"));
+        assert_eq!(Some(set(&[apple])), index.candidates("apple pie"));
+        assert_eq!(Some(HashSet::new()), index.candidates("plum"));
+        assert_eq!(None, index.candidates("ap"));
+
+        let plum = arena.string("plum");
+        arena.rewrite_strings(vec![(maple, "maple plums".to_string())]);
+        index.catch_up(&arena);
+        assert_eq!(Some(set(&[plum, maple])), index.candidates("plum"));
+    }
+
+    #[test]
+    fn catching_up_after_removal() {
+        let mut arena = Arena::new();
+        let pear = arena.string("pear");
+        let mut index =
+            SearchIndex::build(Snapshot::take(&arena), &Progress::unobserved()).unwrap();
+        let before = arena.keys_of_kind(Kind::String).len();
+        arena.collect_garbage();
+        assert!(arena.slot_map.get(pear).is_none());
+        let peach = arena.string("peach");
+        assert_eq!(before, arena.keys_of_kind(Kind::String).len());
+        index.catch_up(&arena);
+        assert!(index.candidates("pea").unwrap().contains(&peach));
+    }
+}
@@ -0,0 +1,398 @@
+//! # Settings bundles
+//!
+//! Most of Kakoi's [`Options`] are chosen with command line flags, which makes
+//! them awkward to share: a team that wants everyone to draw rings the same
+//! way and bind keys by their position has to pass the same flags around.
+//! `kakoi --export-settings FILE` writes the settings chosen by the other
+//! flags out to a bundle instead, and `kakoi --settings FILE` reads one back.
+//!
+//! A bundle is a text file starting with the line [`HEADER`], followed by
+//! `name = value` lines grouped into [`Part`]s by `[part]` lines:
+//!
+//! ```text
+//! kakoi-settings 1
+//!
+//! [appearance]
+//! flat_rings = false
+//! ui_scale = 1.5
+//!
+//! [keys]
+//! binding_mode = scancode
+//! ```
+//!
+//! Lines starting with `#` are comments. Settings that are left out keep
+//! whatever value the flags gave them, so a bundle can be as small as a single
+//! setting. Settings that only make sense on one machine, like the size of the
+//! window or a file to import, can't be bundled.
+//!
+//! A bundle is checked completely before anything in it is applied: if any
+//! line can't be understood, every problem is reported and nothing changes.
+//! `--settings-parts` applies only some parts of a bundle, like
+//! `--settings-parts appearance,keys` to take someone's look and key bindings
+//! but keep one's own import limits. The settings a bundle applies take
+//! precedence over the flags for them.
+//!
+//! There is no way to rebind individual keys yet (see
+//! [`crate::input_manager`]), so the `keys` part only says how key presses
+//! are matched against the bindings.
+//!
+//! [`Options`]: crate::window::Options
+
+use crate::arena::{ChangeWindow, Pruning};
+use crate::input_manager::BindingMode;
+use crate::window::{self, Options};
+use std::{fs, io, path::Path};
+
+/// The first line of every bundle, naming the version of the format.
+pub const HEADER: &str = "kakoi-settings 1";
+
+/// A group of related settings, which can be applied separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Part {
+    /// How values are drawn.
+    Appearance,
+    /// How key presses are matched against key bindings.
+    Keys,
+    /// How values are imported and how changes are tracked.
+    Behavior,
+}
+
+impl Part {
+    pub const ALL: [Part; 3] = [Part::Appearance, Part::Keys, Part::Behavior];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Part::Appearance => "appearance",
+            Part::Keys => "keys",
+            Part::Behavior => "behavior",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|part| part.name() == name.trim())
+    }
+}
+
+/// A single setting read from a bundle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Setting {
+    FlatRings(bool),
+    TaperedRings(bool),
+    DepthOfField(bool),
+    UiScale(f32),
+    PreviewWords(Option<usize>),
+    TruncateMiddle(bool),
+    BindingMode(BindingMode),
+    PowerSaving(bool),
+    TrackUsage(bool),
+    ChangedWindow(ChangeWindow),
+    MaxImageSize(u32),
+    MemoryBudget(Option<u64>),
+    TextSections(bool),
+    LinkImages(bool),
+    CollectGarbage(bool),
+    PruneOrphans(Option<Pruning>),
+}
+
+impl Setting {
+    /// Reads the setting named `name` in `part` from `value`, or says why it
+    /// can't.
+    fn parse(part: Part, name: &str, value: &str) -> Result<Self, String> {
+        let flag = || match value {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("{} must be true or false", name)),
+        };
+        let number = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("{} must be a whole number", name))
+        };
+        let setting = match (part, name) {
+            (Part::Appearance, "flat_rings") => Setting::FlatRings(flag()?),
+            (Part::Appearance, "tapered_rings") => Setting::TaperedRings(flag()?),
+            (Part::Appearance, "depth_of_field") => Setting::DepthOfField(flag()?),
+            (Part::Appearance, "ui_scale") => match window::parse_ui_scale(value) {
+                Some(factor) => Setting::UiScale(factor),
+                None => return Err("ui_scale must be a positive number".to_string()),
+            },
+            (Part::Appearance, "preview_words") => {
+                Setting::PreviewWords(Some(number()? as usize).filter(|&words| words > 0))
+            }
+            (Part::Appearance, "truncate_middle") => Setting::TruncateMiddle(flag()?),
+            (Part::Keys, "binding_mode") => Setting::BindingMode(match value {
+                "virtual_key" => BindingMode::VirtualKey,
+                "scancode" => BindingMode::Scancode,
+                _ => return Err("binding_mode must be virtual_key or scancode".to_string()),
+            }),
+            (Part::Behavior, "power_saving") => Setting::PowerSaving(flag()?),
+            (Part::Behavior, "track_usage") => Setting::TrackUsage(flag()?),
+            (Part::Behavior, "changed_window") => match ChangeWindow::parse(value) {
+                Some(window) => Setting::ChangedWindow(window),
+                None => {
+                    return Err(
+                        "changed_window must be a number of changes, or a time like 15m"
+                            .to_string(),
+                    )
+                }
+            },
+            (Part::Behavior, "max_image_size") => Setting::MaxImageSize(number()? as u32),
+            (Part::Behavior, "memory_budget") if value == "none" => Setting::MemoryBudget(None),
+            (Part::Behavior, "memory_budget") => Setting::MemoryBudget(Some(number()? * 1_000_000)),
+            (Part::Behavior, "text_sections") => Setting::TextSections(flag()?),
+            (Part::Behavior, "link_images") => Setting::LinkImages(flag()?),
+            (Part::Behavior, "collect_garbage") => Setting::CollectGarbage(flag()?),
+            (Part::Behavior, "prune_orphans") => Setting::PruneOrphans(match value {
+                "off" => None,
+                "keep_pinned" => Some(Pruning { keep_pinned: true }),
+                "all" => Some(Pruning { keep_pinned: false }),
+                _ => return Err("prune_orphans must be off, keep_pinned or all".to_string()),
+            }),
+            _ => return Err(format!("there's no setting {} in [{}]", name, part.name())),
+        };
+        Ok(setting)
+    }
+
+    fn apply(self, options: &mut Options) {
+        match self {
+            Setting::FlatRings(flat) => options.flat_rings = flat,
+            Setting::TaperedRings(tapered) => options.tapered_rings = tapered,
+            Setting::DepthOfField(depth_of_field) => options.depth_of_field = depth_of_field,
+            Setting::UiScale(factor) => options.ui_scale = Some(factor),
+            Setting::PreviewWords(words) => options.preview_words = words,
+            Setting::TruncateMiddle(middle) => options.truncate_middle = middle,
+            Setting::BindingMode(mode) => options.binding_mode = mode,
+            Setting::PowerSaving(power_saving) => options.power_saving = power_saving,
+            Setting::TrackUsage(track_usage) => options.track_usage = track_usage,
+            Setting::ChangedWindow(window) => options.change_window = window,
+            Setting::MaxImageSize(pixels) => options.import_policy.max_dimension = pixels,
+            Setting::MemoryBudget(bytes) => options.import_policy.memory_budget = bytes,
+            Setting::TextSections(split) => options.import_policy.split_text = split,
+            Setting::LinkImages(link) => options.import_policy.link_images = link,
+            Setting::CollectGarbage(collect) => options.collect_garbage = collect,
+            Setting::PruneOrphans(pruning) => options.prune_orphans = pruning,
+        }
+    }
+}
+
+/// Writes `changed_window` the way [`ChangeWindow::parse`] reads it.
+fn changed_window(window: ChangeWindow) -> String {
+    match window {
+        ChangeWindow::Changes(changes) => changes.to_string(),
+        ChangeWindow::Time(time) => format!("{}s", time.as_secs()),
+    }
+}
+
+/// Writes every setting in `options` that can be bundled. See [the
+/// module-level documentation](crate::settings).
+pub fn write(options: &Options) -> String {
+    let policy = &options.import_policy;
+    format!(
+        "{}\n\n[appearance]\nflat_rings = {}\ntapered_rings = {}\ndepth_of_field = {}\nui_scale = {}\npreview_words = {}\ntruncate_middle = {}\n\n[keys]\nbinding_mode = {}\n\n[behavior]\npower_saving = {}\ntrack_usage = {}\nchanged_window = {}\nmax_image_size = {}\nmemory_budget = {}\ntext_sections = {}\nlink_images = {}\ncollect_garbage = {}\nprune_orphans = {}\n",
+        HEADER,
+        options.flat_rings,
+        options.tapered_rings,
+        options.depth_of_field,
+        options.ui_scale.unwrap_or(1.0),
+        options.preview_words.unwrap_or(0),
+        options.truncate_middle,
+        match options.binding_mode {
+            BindingMode::VirtualKey => "virtual_key",
+            BindingMode::Scancode => "scancode",
+        },
+        options.power_saving,
+        options.track_usage,
+        changed_window(options.change_window),
+        policy.max_dimension,
+        policy
+            .memory_budget
+            .map_or("none".to_string(), |bytes| (bytes / 1_000_000).to_string()),
+        policy.split_text,
+        policy.link_images,
+        options.collect_garbage,
+        match options.prune_orphans {
+            None => "off",
+            Some(Pruning { keep_pinned: true }) => "keep_pinned",
+            Some(Pruning { keep_pinned: false }) => "all",
+        }
+    )
+}
+
+/// Writes the bundle [`write`] describes to `path`.
+pub fn export<P: AsRef<Path>>(path: P, options: &Options) -> io::Result<()> {
+    fs::write(path, write(options))
+}
+
+/// The settings read from a bundle, ready to be applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bundle {
+    settings: Vec<(Part, Setting)>,
+}
+
+impl Bundle {
+    /// Reads a bundle, or lists everything wrong with it, each problem
+    /// starting with the number of the line it is on.
+    pub fn parse(text: &str) -> Result<Self, Vec<String>> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            Some((number, line)) if line.starts_with("kakoi-settings ") => {
+                return Err(vec![format!(
+                    "line {}: this bundle is for a different version of kakoi ({})",
+                    number, line
+                )])
+            }
+            _ => return Err(vec!["this isn't a kakoi settings bundle".to_string()]),
+        }
+        let mut part = None;
+        // Whether the settings under the current part are being skipped,
+        // since there's no part with its name.
+        let mut skipping = false;
+        let mut settings: Vec<(Part, &str, Setting)> = Vec::new();
+        let mut problems = Vec::new();
+        for (number, line) in lines {
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                part = Part::parse(name);
+                skipping = part.is_none();
+                if skipping {
+                    problems.push(format!("line {}: there's no part named {}", number, name));
+                }
+                continue;
+            }
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => {
+                    problems.push(format!("line {}: expected name = value", number));
+                    continue;
+                }
+            };
+            let part = match part {
+                Some(part) => part,
+                None if skipping => continue,
+                None => {
+                    problems.push(format!("line {}: {} comes before any [part]", number, name));
+                    continue;
+                }
+            };
+            if settings.iter().any(|&(p, n, _)| p == part && n == name) {
+                problems.push(format!("line {}: {} is set twice", number, name));
+                continue;
+            }
+            match Setting::parse(part, name, value) {
+                Ok(setting) => settings.push((part, name, setting)),
+                Err(problem) => problems.push(format!("line {}: {}", number, problem)),
+            }
+        }
+        if problems.is_empty() {
+            Ok(Self {
+                settings: settings
+                    .into_iter()
+                    .map(|(part, _, setting)| (part, setting))
+                    .collect(),
+            })
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Reads the bundle in the file at `path`, like [`Bundle::parse`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Vec<String>> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) => Err(vec![format!("can't read {}: {}", path.display(), e)]),
+        }
+    }
+
+    /// The parts that have at least one setting in the bundle.
+    pub fn parts(&self) -> Vec<Part> {
+        Part::ALL
+            .iter()
+            .copied()
+            .filter(|part| self.settings.iter().any(|(p, _)| p == part))
+            .collect()
+    }
+
+    /// Applies the settings in `parts` to `options`, returning how many were
+    /// applied.
+    pub fn apply(&self, parts: &[Part], options: &mut Options) -> usize {
+        let mut applied = 0;
+        for (part, setting) in &self.settings {
+            if parts.contains(part) {
+                setting.apply(options);
+                applied += 1;
+            }
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut options = Options {
+            tapered_rings: true,
+            ui_scale: Some(1.5),
+            truncate_middle: true,
+            binding_mode: BindingMode::Scancode,
+            change_window: ChangeWindow::Changes(20),
+            prune_orphans: Some(Pruning { keep_pinned: false }),
+            ..Options::default()
+        };
+        options.import_policy.memory_budget = Some(512_000_000);
+        options.import_policy.link_images = true;
+        let bundle = Bundle::parse(&write(&options)).unwrap();
+        let mut read = Options::default();
+        assert_eq!(16, bundle.apply(&Part::ALL, &mut read));
+        assert!(read.tapered_rings);
+        assert_eq!(Some(1.5), read.ui_scale);
+        assert!(read.truncate_middle);
+        assert_eq!(BindingMode::Scancode, read.binding_mode);
+        assert_eq!(ChangeWindow::Changes(20), read.change_window);
+        assert_eq!(Some(512_000_000), read.import_policy.memory_budget);
+        assert!(read.import_policy.link_images);
+        assert_eq!(Some(Pruning { keep_pinned: false }), read.prune_orphans);
+        // Only the parts asked for are applied.
+        let mut keys_only = Options::default();
+        assert_eq!(1, bundle.apply(&[Part::Keys], &mut keys_only));
+        assert_eq!(BindingMode::Scancode, keys_only.binding_mode);
+        assert!(!keys_only.tapered_rings);
+    }
+
+    #[test]
+    fn problems() {
+        let text = "kakoi-settings 1\n[appearance]\nflat_rings = maybe\n# fine\nflat_rings = true\nflat_rings = false\n[colors]\nbackground = red\n";
+        assert_eq!(
+            Err(vec![
+                "line 3: flat_rings must be true or false".to_string(),
+                "line 6: flat_rings is set twice".to_string(),
+                "line 7: there's no part named colors".to_string(),
+            ]),
+            Bundle::parse(text)
+        );
+        assert_eq!(
+            Err(vec!["this isn't a kakoi settings bundle".to_string()]),
+            Bundle::parse("flat_rings = true")
+        );
+        let bundle = Bundle::parse("kakoi-settings 1\n[keys]\nbinding_mode = scancode").unwrap();
+        assert_eq!(vec![Part::Keys], bundle.parts());
+        // UI scales that are too large are clamped, rather than rejected.
+        let bundle = Bundle::parse("kakoi-settings 1\n[appearance]\nui_scale = 100").unwrap();
+        let mut read = Options::default();
+        bundle.apply(&[Part::Appearance], &mut read);
+        assert_eq!(Some(window::UI_SCALE_RANGE.1), read.ui_scale);
+    }
+}
@@ -1,7 +1,42 @@
 #version 450
 
+// Every ring is drawn in the same color. Rings aren't colored by type or
+// identity, so hue never carries meaning on its own and there is nothing yet
+// for a color-blind safe palette to replace. If rings ever gain such colors,
+// they should also differ by something other than hue (like a dash pattern, or
+// their thickness, which circle.vert already takes per instance), so that they
+// stay distinguishable.
+//
+// Inside of each ring is a shadow (see render/circle.rs), which fades out
+// towards the center. The more deeply the ring is nested, the darker its
+// shadow, so that nesting can be perceived at a glance.
+//
+// Rings nested far below the top of their layer may also be faded towards the
+// background (see depth_fade in render/circle.rs), which is done by making
+// both the ring and its shadow partly transparent.
+
+layout(location=0) in float v_radius;
+layout(location=1) in float v_depth;
+layout(location=2) in float v_fade;
+
 layout(location=0) out vec4 color;
 
+// These must match the constants of the same names in render/circle.rs.
+const float MIN_RADIUS = 0.98;
+const float SHADOW_RADIUS = 0.9;
+
+// How much darker each level of nesting makes a shadow, and the darkest a
+// shadow can get.
+const float SHADE_PER_DEPTH = 0.06;
+const float MAX_SHADE = 0.45;
+
 void main() {
-  color = vec4(1.0);
-}
\ No newline at end of file
+  float opacity = 1.0 - v_fade;
+  if (v_radius >= MIN_RADIUS - 0.001) {
+    color = vec4(1.0, 1.0, 1.0, opacity);
+  } else {
+    float shade = min(v_depth * SHADE_PER_DEPTH, MAX_SHADE);
+    float fade = smoothstep(SHADOW_RADIUS, MIN_RADIUS, v_radius);
+    color = vec4(0.0, 0.0, 0.0, shade * fade * opacity);
+  }
+}
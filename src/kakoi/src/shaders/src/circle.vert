@@ -8,11 +8,41 @@ layout(location=1) in vec4 model_matrix_0;
 layout(location=2) in vec4 model_matrix_1;
 layout(location=3) in vec4 model_matrix_2;
 layout(location=4) in vec4 model_matrix_3;
+// How deeply the ring is nested. Only used by circle.frag.
+layout(location=5) in float depth;
+// How far the ring is faded towards the background. Only used by circle.frag.
+layout(location=6) in float fade;
+// How thick the ring is, as a fraction of its radius.
+layout(location=7) in float thickness;
 
 layout(set=0, binding=0) uniform Uniforms { mat4 view_projection_matrix; };
 
+// The distance of the vertex from the center of the circle, before scaling.
+layout(location=0) out float v_radius;
+layout(location=1) out float v_depth;
+layout(location=2) out float v_fade;
+
+// The thickness of the ring in the vertex buffer of render/circle.rs. These
+// must match the constants of the same names there.
+const float MIN_RADIUS = 0.98;
+const float MAX_RADIUS = 1.0;
+const float MESH_THICKNESS = MAX_RADIUS - MIN_RADIUS;
+
 void main() {
   mat4 model_matrix = mat4(model_matrix_0, model_matrix_1, model_matrix_2, model_matrix_3);
   mat4 transformation = view_projection_matrix * model_matrix;
-  gl_Position = transformation * vec4(position, 1.0);
+  float radius = length(position.xy);
+  // Every vertex inside of the outer edge moves along with the inner edge of
+  // the ring, so that the ring is as thick as asked and its shadow stays just
+  // inside of it. Everything drawn at the thickness of the mesh (which is
+  // everything but rings) stays where it is. v_radius is left alone, so that
+  // circle.frag can still tell the ring and its shadow apart.
+  vec3 moved = position;
+  if (radius > 0.0 && radius < MAX_RADIUS - 0.0001) {
+    moved.xy *= (radius + MESH_THICKNESS - thickness) / radius;
+  }
+  gl_Position = transformation * vec4(moved, 1.0);
+  v_radius = radius;
+  v_depth = depth;
+  v_fade = fade;
 }
@@ -0,0 +1,22 @@
+#version 450
+
+// Colors the rings drawn by render/heat.rs by how much what they surround
+// costs to draw (see cost.rs), from blue for the cheapest through yellow to
+// red for the most expensive. circle.vert passes the heat along in place of
+// the fade. Hotter rings are also thicker, so that the heat can be told apart
+// without telling the colors apart.
+
+layout(location=0) in float v_radius;
+layout(location=1) in float v_depth;
+layout(location=2) in float v_heat;
+
+layout(location=0) out vec4 color;
+
+const vec3 COLD = vec3(0.2, 0.45, 1.0);
+const vec3 WARM = vec3(1.0, 0.85, 0.2);
+const vec3 HOT = vec3(1.0, 0.15, 0.1);
+
+void main() {
+  vec3 rgb = v_heat < 0.5 ? mix(COLD, WARM, v_heat * 2.0) : mix(WARM, HOT, v_heat * 2.0 - 1.0);
+  color = vec4(rgb, 0.85);
+}
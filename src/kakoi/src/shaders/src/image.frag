@@ -1,6 +1,7 @@
 #version 450
 
 layout(location=0) in vec2 v_texture_position;
+layout(location=1) flat in float v_zoomed;
 
 layout(location=0) out vec4 color;
 
@@ -8,5 +9,12 @@ layout(set=0, binding=0) uniform texture2D t_diffuse;
 layout(set=0, binding=1) uniform sampler s_diffuse;
 
 void main() {
-    color = texture(sampler2D(t_diffuse, s_diffuse), v_texture_position);
-}
\ No newline at end of file
+    vec2 position = v_texture_position;
+    // Zoomed-in images are being inspected, so each pixel is drawn as a sharp
+    // square instead of being blurred into its neighbours.
+    if (v_zoomed > 0.5) {
+        vec2 size = vec2(textureSize(sampler2D(t_diffuse, s_diffuse), 0));
+        position = (floor(position * size) + 0.5) / size;
+    }
+    color = texture(sampler2D(t_diffuse, s_diffuse), position);
+}
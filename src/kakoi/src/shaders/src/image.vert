@@ -10,7 +10,11 @@ layout(location=3) in vec4 model_matrix_1;
 layout(location=4) in vec4 model_matrix_2;
 layout(location=5) in vec4 model_matrix_3;
 
+// The corner of the part of the texture being displayed (xy), and its size (zw).
+layout(location=6) in vec4 texture_transform;
+
 layout(location=0) out vec2 v_texture_position;
+layout(location=1) flat out float v_zoomed;
 
 layout(set=1, binding=0)
 uniform Uniforms {
@@ -24,6 +28,7 @@ void main() {
         model_matrix_2, 
         model_matrix_3
     );
-    v_texture_position = texture_position;
+    v_texture_position = texture_transform.xy + texture_position * texture_transform.zw;
+    v_zoomed = texture_transform.z < 1.0 ? 1.0 : 0.0;
     gl_Position = view_projection_matrix * model_matrix * vec4(position, 1.0);
-}
\ No newline at end of file
+}
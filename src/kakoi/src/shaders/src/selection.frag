@@ -0,0 +1,16 @@
+#version 450
+
+// Fills everything with a single color. Used for the selection ring, the
+// progress bar, the underlines beneath links, the ghost's veil, the curves
+// between shared values, and the halos around highlighted values.
+
+layout(location=0) out vec4 color;
+
+// The camera is bound at set 0 for circle.vert. See render/context.rs.
+layout(set=1, binding=0) uniform Color {
+  vec4 fill_color;
+};
+
+void main() {
+  color = fill_color;
+}
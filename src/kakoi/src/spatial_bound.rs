@@ -3,6 +3,10 @@ use crate::{
     square_cuboid::{Orientation, SquareCuboid},
 };
 
+/// The size, in logical pixels, below which bounds are too small to be seen.
+/// See [`SpatialBound::is_visible`].
+pub const MIN_VISIBLE_SIZE: f32 = 1.0;
+
 #[derive(Debug, Clone, Copy)]
 pub enum SpatialBound {
     Sphere(Sphere),
@@ -116,12 +120,34 @@ impl SpatialBound {
 
     /// Determines if the bounds are large enough to be visible on screen.
     /// Returns true if they are, and false otherwise.
+    ///
+    /// The size of the screen is given in logical pixels, which are physical
+    /// pixels divided by the scale factor of the display (and by any UI scale
+    /// chosen on top of that). Bounds are culled once they are smaller than
+    /// [`MIN_VISIBLE_SIZE`] logical pixels, so they disappear at the same
+    /// apparent size on high and low density displays alike.
     pub fn is_visible(&self, screen_width: f32, screen_height: f32) -> bool {
         match self {
-            SpatialBound::Sphere(s) => s.screen_radius(screen_width, screen_height) > 1.0,
+            SpatialBound::Sphere(s) => {
+                s.screen_radius(screen_width, screen_height) > MIN_VISIBLE_SIZE
+            }
             SpatialBound::SquareCuboid(s) => {
-                s.min_screen_dimension(screen_width, screen_height) > 1.0
+                s.min_screen_dimension(screen_width, screen_height) > MIN_VISIBLE_SIZE
             }
         }
     }
+
+    /// Returns true if any part of the bounds lies inside of the region the
+    /// camera can see, given half of its width and height (see
+    /// [`Camera::visible_half_extent`]).
+    ///
+    /// [`Camera::visible_half_extent`]: crate::camera::Camera::visible_half_extent
+    pub fn overlaps(&self, visible_half_extent: (f32, f32)) -> bool {
+        let (center, half_width, half_height) = match self {
+            SpatialBound::Sphere(s) => (s.center, s.radius, s.radius),
+            SpatialBound::SquareCuboid(s) => (s.center, 0.5 * s.width(), 0.5 * s.height()),
+        };
+        let (visible_width, visible_height) = visible_half_extent;
+        center.x.abs() - half_width < visible_width && center.y.abs() - half_height < visible_height
+    }
 }
@@ -16,12 +16,15 @@
 //! be. The `center` of the sphere denotes the location of the center of the
 //! object. These object-sphere pairs are represented as [`SpatialTreeData`],
 //!
-//! Each [`SpatialTreeData`] is arranged in a [`SpatialTree`]. The root of this
-//! tree contains the currently-selected object centered at `(0,0)` with a
-//! radius of `1.0`. If the currently-selected object is a container (a set or
-//! map), then its node has children representing the size and locations of the
-//! objects within the container. If an object is not a container (a string or
-//! image), then its node does not have any children.
+//! Each [`SpatialTreeData`] is arranged in a [`SpatialTree`]. The tree has one
+//! root for each [`Layer`] of the [`OverlayStack`] (and one more for the
+//! layer's message, if it has one). The root of the base layer contains the
+//! currently-selected object centered at `(0,0)` with a radius of `1.0`; the
+//! roots of the other layers are placed according to [`Layer::bounds`]. If an
+//! object is a container (a set or map), then its node has children
+//! representing the size and locations of the objects within the container. If
+//! an object is not a container (a string or image), then its node does not
+//! have any children.
 //!
 //! The same object may appear more than once on screen with possibly differing
 //! positions and sizes each time. Each visual instance of an object has
@@ -29,21 +32,43 @@
 //! five times on screen, then there will be exactly five [`SpatialTreeData`]s
 //! arranged in the [`SpatialTree`] that refer to it.
 //!
+//! Each layer chooses how containers are drawn with its [`LayoutStrategy`].
+//! By default, containers are circles with their elements packed around the
+//! inside. Alternatively, containers can be drawn as rectangles that are
+//! divided up between their elements (a [treemap]), which wastes less space.
+//! Sets of mostly strings are drawn as treemaps whatever their layer's
+//! strategy is, and any container can be made to always be drawn one way (see
+//! [`container_layout`]). Lists and commands, whose elements are in order,
+//! are always drawn with their elements stacked from top to bottom. Lists are
+//! framed by a rectangle, so that they can be told apart from sets.
+//!
 //! [rooted tree]: https://en.wikipedia.org/wiki/Tree_(graph_theory)#Rooted_tree
+//! [treemap]: https://en.wikipedia.org/wiki/Treemapping
+//! [`Layer`]: crate::overlay::Layer
+//! [`Layer::bounds`]: crate::overlay::Layer::bounds
 
+use crate::arena::Arena;
 use crate::arena::Structure;
-use crate::arena::Value;
-use crate::circle::{Circle, CirclePositioner, Point};
+use crate::blob::Blob;
+use crate::camera::Camera;
+use crate::circle::{
+    fit_weighted_circles, rotate_about, Circle, CirclePositioner, Point, RING_THRESHOLD,
+};
 use crate::forest::Forest;
+use crate::leaf::CustomValue;
+use crate::number;
+use crate::overlay::OverlayStack;
 use crate::render::circle::{CircleRenderer, MIN_RADIUS};
+use crate::render::draw_order::DrawOrder;
 use crate::render::image::ImageRenderer;
+use crate::render::rectangle::{RectangleRenderer, THICKNESS};
+use crate::sample::Sample;
 use crate::spatial_bound::SpatialBound;
 use crate::sphere::Sphere;
-use crate::square_cuboid::Orientation;
 use crate::square_cuboid::SquareCuboid;
+use crate::string_class::StringClass;
 use crate::{arena::ArenaKey, render::text::TextRenderer};
 use slotmap::new_key_type;
-use slotmap::SlotMap;
 use std::collections::{vec_deque::VecDeque, HashMap, HashSet};
 
 new_key_type! {
@@ -68,63 +93,351 @@ pub struct SpatialTreeData {
     pub bounds: SpatialBound,
 }
 
+/// An object in a [`SpatialTree`], alongside where it sits in the tree. See
+/// [`SpatialTree::nodes`].
+#[derive(Debug, Clone, Copy)]
+pub struct LayoutNode {
+    pub data: SpatialTreeData,
+    /// The position of the object this one is directly inside of, in the
+    /// list of nodes, or [`None`] if this is the root of a layer.
+    pub parent: Option<usize>,
+    /// How many objects this one is inside of.
+    pub depth: usize,
+    /// The position of the root this object descends from among the roots of
+    /// the tree, from the base layer up.
+    pub root: usize,
+}
+
+/// The way in which containers and their elements are arranged on screen.
+///
+/// See [the module-level documentation](crate::spatial_tree) for more
+/// information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutStrategy {
+    /// Containers are circles. Their elements are packed around the inside of
+    /// the circle, in several concentric rings if there are many of them (see
+    /// [`crate::circle::RING_THRESHOLD`]).
+    Circles,
+    /// Like [`LayoutStrategy::Circles`], except that the elements of sets are
+    /// sized according to how much they contain (see [`Arena::leaf_count`]),
+    /// so that large substructures stand out. Maps, and sets with more than
+    /// [`crate::circle::RING_THRESHOLD`] members, are laid out the same way as
+    /// with [`LayoutStrategy::Circles`].
+    WeightedCircles,
+    /// Containers are rectangles. Each element gets a share of the rectangle
+    /// proportional to its weight.
+    Treemap,
+    /// Like [`LayoutStrategy::Circles`], except that one element of each set
+    /// is enlarged, with the others packed around it. Scrolling turns the set
+    /// the topmost layer displays like a carousel, enlarging the next element
+    /// (see [`Arena::carousel_position`]).
+    Zoomed,
+}
+
+/// The name of every way a container can always be arranged (see
+/// [`Arena::layout_override`]), in the order [`LayoutStrategy::next_override`]
+/// cycles through them, starting with arranging it automatically. See
+/// [`LayoutStrategy::parse_override`].
+pub const LAYOUT_CHOICES: &[&str] = &[
+    "automatic",
+    "circles",
+    "weighted circles",
+    "treemap",
+    "zoomed",
+];
+
+impl LayoutStrategy {
+    /// Parses one of the [`LAYOUT_CHOICES`], returning `Some(None)` for
+    /// arranging a container automatically.
+    pub fn parse_override(name: &str) -> Option<Option<Self>> {
+        match name {
+            "automatic" => Some(None),
+            "circles" => Some(Some(Self::Circles)),
+            "weighted circles" => Some(Some(Self::WeightedCircles)),
+            "treemap" => Some(Some(Self::Treemap)),
+            "zoomed" => Some(Some(Self::Zoomed)),
+            _ => None,
+        }
+    }
+
+    /// Returns the [`LayoutStrategy`] to switch to after this one, cycling
+    /// through all of them.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Circles => Self::WeightedCircles,
+            Self::WeightedCircles => Self::Treemap,
+            Self::Treemap => Self::Zoomed,
+            Self::Zoomed => Self::Circles,
+        }
+    }
+
+    /// Like [`LayoutStrategy::next`], but also cycles through [`None`], which
+    /// stands for arranging a value automatically (see
+    /// [`Arena::layout_override`]).
+    pub fn next_override(layout: Option<Self>) -> Option<Self> {
+        match layout {
+            None => Some(Self::Circles),
+            Some(Self::Zoomed) => None,
+            Some(layout) => Some(layout.next()),
+        }
+    }
+}
+
+/// How much larger than the others the enlarged element of a set arranged with
+/// [`LayoutStrategy::Zoomed`] is, from 0 (the same size) to 1 (as large as it
+/// can be).
+const ZOOM: f64 = 0.6;
+
+/// Sets in which at least this fraction of the members are strings are
+/// arranged as treemaps by [`container_layout`].
+const TEXT_HEAVY_FRACTION: f32 = 0.75;
+
+/// The radius of the badge drawn on starred values, and how far its center is
+/// from the center of the value, both as fractions of the value's radius.
+const STAR_RADIUS: f32 = 0.1;
+const STAR_DISTANCE: f32 = 0.85;
+
+/// The radius of the badge drawn on tagged values, as a fraction of the
+/// value's radius. It is as far from the center of the value as the badge
+/// drawn on starred values.
+const TAG_RADIUS: f32 = 0.14;
+
+/// The radius of the region the value of a labelled [widget](crate::hud) is
+/// laid out in, as a fraction of the radius of the ring drawn around it,
+/// leaving room for the ring's label.
+const LABELLED_INNER_RADIUS: f32 = 0.7;
+
+/// Decides how the container bound to `key` is arranged when its layer is
+/// arranged according to `layout`.
+///
+/// Containers with an [`Arena::layout_override`] are always arranged that way.
+/// Otherwise, sets consisting mostly of strings (see [`TEXT_HEAVY_FRACTION`])
+/// are arranged as treemaps, since text fills rectangles much better than it
+/// fills the small circles packed inside of a larger one. Everything else
+/// follows `layout`.
+pub fn container_layout(arena: &Arena, key: ArenaKey, layout: LayoutStrategy) -> LayoutStrategy {
+    if let Some(layout) = arena.layout_override(key) {
+        return layout;
+    }
+    match &arena.slot_map[key].structure {
+        Structure::Set(set) if !set.is_empty() => {
+            let strings = set
+                .iter()
+                .filter(|&&member| matches!(arena.slot_map[member].structure, Structure::String(_)))
+                .count();
+            if strings as f32 >= TEXT_HEAVY_FRACTION * set.len() as f32 {
+                LayoutStrategy::Treemap
+            } else {
+                layout
+            }
+        }
+        _ => layout,
+    }
+}
+
 /// A tree containing `SpatialTreeData`
 ///
 /// See [the module-level documentation](crate::spatial_tree) for more
 /// information.
 pub struct SpatialTree {
-    /// The single-tree [`Forest`] backing our tree.
+    /// The [`Forest`] backing our tree.
     forest: Forest<SpatialTreeKey, SpatialTreeData>,
-    /// The root node of the tree.
-    root: SpatialTreeKey,
+    /// Every root in the `forest`, ordered from the bottom layer of the
+    /// [`OverlayStack`] to the top.
+    roots: Vec<SpatialTreeKey>,
+    /// The root of the focus of the topmost layer, which is the only layer
+    /// that can be clicked on.
+    top_root: SpatialTreeKey,
 }
 
-/// Creates, or regenerates, a spatial tree.
+/// Generates the spatial trees for each layer of an [`OverlayStack`].
 ///
-/// Removes an existing tree, if it exists, and generates a new one in its
-/// place. Registers layout data with the appropriate renderers. The tree is
-/// generated until we either run out of objects to layout, or the objects
-/// become too small to be seen on screen.
+/// Removes the existing trees rooted at `existing_roots` and generates new
+/// ones in their place. Returns the new roots, ordered from bottom to top,
+/// alongside the root of the topmost layer's focus.
+fn rebuild_layers(
+    forest: &mut Forest<SpatialTreeKey, SpatialTreeData>,
+    existing_roots: &[SpatialTreeKey],
+    arena: &Arena,
+    overlay_stack: &OverlayStack,
+    provisional: Option<&str>,
+    text_renderer: &mut TextRenderer,
+    image_renderer: &mut ImageRenderer,
+    circle_renderer: &mut CircleRenderer,
+    rectangle_renderer: &mut RectangleRenderer,
+    screen_width: f32,
+    screen_height: f32,
+) -> (Vec<SpatialTreeKey>, SpatialTreeKey) {
+    for &root in existing_roots {
+        forest.remove_root(root);
+    }
+
+    let mut roots = Vec::new();
+    let mut top_root = None;
+    let top_layer = overlay_stack.iter().count() - 1;
+    for (layer_index, layer) in overlay_stack.iter().enumerate() {
+        let (focus_bounds, message_bounds) = layer.focus_and_message_bounds();
+        let focus_root = rebuild_tree(
+            forest,
+            arena,
+            layer.focus,
+            SpatialBound::SquareCuboid(focus_bounds),
+            layer.layout,
+            layer.sample,
+            // Strings are entered into `.`, which the base layer displays.
+            provisional.filter(|_| layer_index == 0),
+            // Only the topmost layer is searched (see `OverlayStack::highlights`).
+            overlay_stack
+                .highlights()
+                .filter(|_| layer_index == top_layer),
+            layer_index,
+            text_renderer,
+            image_renderer,
+            circle_renderer,
+            rectangle_renderer,
+            screen_width,
+            screen_height,
+        );
+        roots.push(focus_root);
+        top_root = Some(focus_root);
+        if let (Some(message), Some(message_bounds)) = (layer.message, message_bounds) {
+            roots.push(rebuild_tree(
+                forest,
+                arena,
+                message,
+                SpatialBound::SquareCuboid(message_bounds),
+                layer.layout,
+                None,
+                None,
+                None,
+                layer_index,
+                text_renderer,
+                image_renderer,
+                circle_renderer,
+                rectangle_renderer,
+                screen_width,
+                screen_height,
+            ));
+        }
+    }
+
+    // Widgets are placed relative to what is actually visible, rather than
+    // inside of the unit square. Labelled widgets are drawn as a ring with
+    // their label, with their value inside of it. The minibuffer is drawn as a
+    // rectangle with the prompt inside of it.
+    let hud = overlay_stack.hud();
+    let extent = Camera::new(screen_width / screen_height).visible_half_extent();
+    let order = DrawOrder {
+        layer: overlay_stack.iter().count(),
+        z: i64::MAX,
+    };
+    for (widget, bounds) in hud.widgets().iter().zip(hud.layout(extent)) {
+        let bound = match &widget.label {
+            Some(label) => {
+                let ring = SpatialBound::sphere_inside_cuboid(&bounds);
+                circle_renderer.with_instance(ring, 0);
+                text_renderer.with_label(ring, order, label.clone());
+                SpatialBound::Sphere(Sphere {
+                    center: ring.center,
+                    radius: ring.radius * LABELLED_INNER_RADIUS,
+                })
+            }
+            None => SpatialBound::SquareCuboid(bounds),
+        };
+        roots.push(rebuild_tree(
+            forest,
+            arena,
+            widget.key,
+            bound,
+            LayoutStrategy::Circles,
+            None,
+            None,
+            None,
+            overlay_stack.iter().count(),
+            text_renderer,
+            image_renderer,
+            circle_renderer,
+            rectangle_renderer,
+            screen_width,
+            screen_height,
+        ));
+    }
+    if let (Some(text), Some(bounds)) = (hud.minibuffer(), hud.minibuffer_bounds(extent)) {
+        rectangle_renderer.with_instance(bounds, order);
+        let (width, height) = bounds.dimensions_2d();
+        let inside = bounds.shrink(THICKNESS * width.min(height));
+        text_renderer.with_provisional(SpatialBound::SquareCuboid(inside), order, text.into());
+    }
+
+    // An OverlayStack always has at least its base layer.
+    (roots, top_root.unwrap())
+}
+
+/// Creates a spatial tree.
+///
+/// Generates a new tree as a root of `forest`. Registers layout data with the
+/// appropriate renderers. The tree is generated until we either run out of
+/// objects to layout, or the objects become too small to be seen on screen.
 ///
 /// Arguments:
 ///
-/// * `forest`: Single-tree [`Forest`] possibly holding an existing tree.
-/// * `existing_tree_root`: The root of the existing tree to remove, if any.
-/// * `slot_map`: Object storage.
+/// * `forest`: [`Forest`] to insert the new tree into.
+/// * `arena`: Object storage.
 /// * `start`: Object to place at the root of the tree.
-/// * `{text,image,circle}_renderer`: Queues instances to be drawn later.
-/// * `screen_{width,height}`: Size of screen in pixels. Used to determine of
-/// objects are visible on screen.
+/// * `bounds`: The region of the screen the tree is laid out in.
+/// * `layout`: How containers are arranged, unless [`container_layout`]
+/// decides otherwise.
+/// * `sample`: If `start` is a set, the [`Sample`] of its members to lay out
+/// instead of all of them.
+/// * `provisional`: If `start` is a set, a string being entered into it. It is
+/// laid out after the set's members as if it were already one of them, inside
+/// of a ring of its own (see [`handle_provisional`]).
+/// * `highlights`: Values to highlight. Everything that is neither one of them
+/// nor inside of one of them is dimmed.
+/// * `layer`: Index of the [`Layer`](crate::overlay::Layer) being laid out,
+/// used to decide the [`DrawOrder`] of each object.
+/// * `{text,image,circle,rectangle}_renderer`: Queues instances to be drawn
+/// later.
+/// * `screen_{width,height}`: Size of screen in logical pixels (see
+/// [`SpatialBound::is_visible`]). Used to determine of objects are visible on
+/// screen.
 fn rebuild_tree(
     forest: &mut Forest<SpatialTreeKey, SpatialTreeData>,
-    existing_tree_root: Option<SpatialTreeKey>,
-    slot_map: &SlotMap<ArenaKey, Value>,
+    arena: &Arena,
     start: ArenaKey,
+    bounds: SpatialBound,
+    layout: LayoutStrategy,
+    sample: Option<Sample>,
+    provisional: Option<&str>,
+    highlights: Option<&HashSet<ArenaKey>>,
+    layer: usize,
     text_renderer: &mut TextRenderer,
     image_renderer: &mut ImageRenderer,
     circle_renderer: &mut CircleRenderer,
+    rectangle_renderer: &mut RectangleRenderer,
     screen_width: f32,
     screen_height: f32,
 ) -> SpatialTreeKey {
-    existing_tree_root.map(|root| forest.remove_root(root));
-
-    let root = forest.insert_root(SpatialTreeData {
-        key: start,
-        bounds: SpatialBound::SquareCuboid(SquareCuboid {
-            length: 2.0,
-            depth: 2.0,
-            center: (0.0, 0.0, 0.0).into(),
-            orientation: Orientation::Horizontal,
-        }),
-    });
-
-    // We search through the slot_map for objects by starting with the root,
+    let _span = tracing::debug_span!("rebuild_tree").entered();
+    let root = forest.insert_root(SpatialTreeData { key: start, bounds });
+
+    // Counts of objects that were arranged and of objects that were skipped
+    // because they were too small to be seen, for profiling.
+    let mut laid_out = 0usize;
+    let mut culled = 0usize;
+
+    // We search through the arena for objects by starting with the root,
     // then moving to its contained objects (if any), then their contained
     // objects, and so on. Each processing step pops a value from the queue (the
     // current object to arrange), and then pushes zero or more values to the
     // queue (the contained objects to be arranged in further processing steps).
-    let mut todo: VecDeque<SpatialTreeKey> = vec![root].into_iter().collect();
-    while let Some(spatial_tree_key) = todo.pop_front() {
+    // Alongside each object is its depth in the tree, and whether it is
+    // highlighted or inside of a highlighted object.
+    let is_highlighted =
+        |key: ArenaKey| highlights.map_or(true, |highlights| highlights.contains(&key));
+    let mut todo: VecDeque<(SpatialTreeKey, i64, bool)> =
+        vec![(root, 0, is_highlighted(start))].into_iter().collect();
+    while let Some((spatial_tree_key, depth, lit)) = todo.pop_front() {
         let spatial_tree_data = forest.get(spatial_tree_key).copied().unwrap();
         // Ensure that the object we want to arrange is actually visible on
         // screen. If it isn't, ignore this object and move on to the next loop
@@ -133,22 +446,161 @@ fn rebuild_tree(
             .bounds
             .is_visible(screen_width, screen_height);
         if visible_on_screen {
-            match &slot_map.get(spatial_tree_data.key).unwrap().structure {
-                Structure::String(_) => handle_string(text_renderer, spatial_tree_data),
-                Structure::Image(_) => handle_image(image_renderer, spatial_tree_data),
-                Structure::Set(set) => handle_set(circle_renderer, spatial_tree_data, set.as_ref()),
-                Structure::List(_) => todo!(),
-                // Structure::List(list) => handle_list(list_renderer, spatial_tree_data, list.as_ref()),
-                Structure::Map(map) => handle_map(circle_renderer, spatial_tree_data, map.as_ref()),
-                Structure::Command(command) => handle_command(spatial_tree_data, command.as_ref()),
+            laid_out += 1;
+            circle_renderer.set_dimming(!lit);
+            text_renderer.set_dimming(!lit);
+            let order = DrawOrder {
+                layer,
+                z: depth + arena.z_offset(spatial_tree_data.key) as i64,
+            };
+            let layout = container_layout(arena, spatial_tree_data.key, layout);
+            if arena.is_starred(spatial_tree_data.key) {
+                handle_star(circle_renderer, spatial_tree_data, depth);
+            }
+            handle_tags(
+                circle_renderer,
+                text_renderer,
+                arena,
+                spatial_tree_data,
+                order,
+                depth,
+            );
+            match &arena.slot_map.get(spatial_tree_data.key).unwrap().structure {
+                Structure::String(_) => handle_string(
+                    text_renderer,
+                    spatial_tree_data,
+                    order,
+                    arena.string_class(spatial_tree_data.key),
+                    depth,
+                ),
+                Structure::Image(_) | Structure::ImageRef(_) => {
+                    handle_image(image_renderer, spatial_tree_data, order)
+                }
+                Structure::Number(number) => {
+                    handle_number(text_renderer, spatial_tree_data, order, *number)
+                }
+                Structure::Blob(blob) => handle_blob(text_renderer, spatial_tree_data, order, blob),
+                Structure::Set(set) => {
+                    let sampled;
+                    let set = match sample {
+                        Some(sample) if spatial_tree_key == root => {
+                            sampled = sample.choose(set);
+                            &sampled
+                        }
+                        _ => set.as_ref(),
+                    };
+                    if layout != LayoutStrategy::Treemap {
+                        handle_label(text_renderer, arena, spatial_tree_data, order);
+                    }
+                    let rotation = arena.rotation(spatial_tree_data.key) as f64;
+                    let mut members = arena.arrange(set);
+                    let provisional = provisional.filter(|_| spatial_tree_key == root);
+                    if provisional.is_some() {
+                        // The set stands in for the string being entered, so
+                        // that it is given a place like any other member.
+                        members.push(spatial_tree_data.key);
+                    }
+                    let mut children = match layout {
+                        LayoutStrategy::Circles => handle_set(
+                            circle_renderer,
+                            spatial_tree_data,
+                            depth,
+                            &members,
+                            None,
+                            None,
+                            rotation,
+                        ),
+                        LayoutStrategy::WeightedCircles => handle_set(
+                            circle_renderer,
+                            spatial_tree_data,
+                            depth,
+                            &members,
+                            Some(arena),
+                            None,
+                            rotation,
+                        ),
+                        LayoutStrategy::Zoomed => handle_set(
+                            circle_renderer,
+                            spatial_tree_data,
+                            depth,
+                            &members,
+                            None,
+                            Some(arena.carousel_position(spatial_tree_data.key)),
+                            rotation,
+                        ),
+                        LayoutStrategy::Treemap => handle_set_treemap(
+                            rectangle_renderer,
+                            spatial_tree_data,
+                            &members,
+                            order,
+                        ),
+                    };
+                    if let Some(text) = provisional {
+                        let place = children.pop().unwrap();
+                        handle_provisional(
+                            circle_renderer,
+                            rectangle_renderer,
+                            text_renderer,
+                            place,
+                            depth + 1,
+                            DrawOrder {
+                                layer,
+                                z: order.z + 1,
+                            },
+                            text,
+                        );
+                    }
+                    children
+                }
+                Structure::List(list) => {
+                    handle_label(text_renderer, arena, spatial_tree_data, order);
+                    handle_framed_list(rectangle_renderer, spatial_tree_data, list, order)
+                }
+                Structure::Map(map) => match layout {
+                    LayoutStrategy::Circles
+                    | LayoutStrategy::WeightedCircles
+                    | LayoutStrategy::Zoomed => {
+                        handle_label(text_renderer, arena, spatial_tree_data, order);
+                        handle_map(
+                            circle_renderer,
+                            spatial_tree_data,
+                            depth,
+                            map.as_ref(),
+                            arena.rotation(spatial_tree_data.key) as f64,
+                        )
+                    }
+                    LayoutStrategy::Treemap => handle_map_treemap(
+                        rectangle_renderer,
+                        spatial_tree_data,
+                        map.as_ref(),
+                        order,
+                    ),
+                },
+                Structure::Command(command) => handle_list(spatial_tree_data, command.as_ref()),
+                Structure::Custom(custom) => handle_custom(
+                    image_renderer,
+                    text_renderer,
+                    spatial_tree_data,
+                    order,
+                    custom,
+                ),
             }
             .into_iter()
             .for_each(|child_data| {
-                todo.push_back(forest.insert_child(spatial_tree_key, child_data));
+                let child_lit = lit || is_highlighted(child_data.key);
+                let child = forest.insert_child(spatial_tree_key, child_data);
+                todo.push_back((child, depth + 1, child_lit));
             });
+        } else {
+            culled += 1;
         }
     }
 
+    circle_renderer.set_dimming(false);
+    text_renderer.set_dimming(false);
+
+    tracing::debug!(laid_out, culled, "rebuilt spatial tree");
+
     root
 }
 
@@ -159,60 +611,187 @@ impl SpatialTree {
     /// more information.
     pub fn rebuild(
         &mut self,
-        slot_map: &SlotMap<ArenaKey, Value>,
-        start: ArenaKey,
+        arena: &Arena,
+        overlay_stack: &OverlayStack,
+        provisional: Option<&str>,
         string_handler: &mut TextRenderer,
         image_handler: &mut ImageRenderer,
         circle_handler: &mut CircleRenderer,
+        rectangle_handler: &mut RectangleRenderer,
         screen_width: f32,
         screen_height: f32,
     ) {
-        self.root = rebuild_tree(
+        let (roots, top_root) = rebuild_layers(
             &mut self.forest,
-            Some(self.root),
-            slot_map,
-            start,
+            &self.roots,
+            arena,
+            overlay_stack,
+            provisional,
             string_handler,
             image_handler,
             circle_handler,
+            rectangle_handler,
             screen_width,
             screen_height,
         );
+        self.roots = roots;
+        self.top_root = top_root;
     }
 
     /// Generates a new spatial tree.
     ///
     /// See the documentation of [`rebuild_tree`] for more information.
     pub fn new(
-        slot_map: &SlotMap<ArenaKey, Value>,
-        start: ArenaKey,
+        arena: &Arena,
+        overlay_stack: &OverlayStack,
+        provisional: Option<&str>,
         string_handler: &mut TextRenderer,
         image_handler: &mut ImageRenderer,
         circle_handler: &mut CircleRenderer,
+        rectangle_handler: &mut RectangleRenderer,
         screen_width: f32,
         screen_height: f32,
     ) -> Self {
         let mut forest: Forest<SpatialTreeKey, SpatialTreeData> = Forest::new();
-        let root = rebuild_tree(
+        let (roots, top_root) = rebuild_layers(
             &mut forest,
-            None,
-            slot_map,
-            start,
+            &[],
+            arena,
+            overlay_stack,
+            provisional,
             string_handler,
             image_handler,
             circle_handler,
+            rectangle_handler,
             screen_width,
             screen_height,
         );
-        SpatialTree { forest, root }
+        SpatialTree {
+            forest,
+            roots,
+            top_root,
+        }
+    }
+
+    /// Returns every object in the tree, breadth-first from the root of the
+    /// base layer to the roots of the layers above it, so that every object
+    /// comes after the object it is inside of. See [`crate::geometry`].
+    pub fn nodes(&self) -> Vec<LayoutNode> {
+        let mut nodes = Vec::new();
+        for (root_index, &root) in self.roots.iter().enumerate() {
+            let mut todo: VecDeque<(SpatialTreeKey, Option<usize>, usize)> =
+                vec![(root, None, 0)].into_iter().collect();
+            while let Some((node, parent, depth)) = todo.pop_front() {
+                let index = nodes.len();
+                nodes.push(LayoutNode {
+                    data: *self.forest.get(node).unwrap(),
+                    parent,
+                    depth,
+                    root: root_index,
+                });
+                for &child in self.forest.children(node).unwrap() {
+                    todo.push_back((child, Some(index), depth + 1));
+                }
+            }
+        }
+        nodes
+    }
+
+    /// Returns the number of objects directly inside of the root node of the
+    /// topmost [`Layer`](crate::overlay::Layer)'s focus. These are the objects
+    /// that can be clicked on or selected.
+    pub fn selectable_count(&self) -> usize {
+        self.forest.children(self.top_root).unwrap().len()
+    }
+
+    /// Returns the `index`th object directly inside of the root node of the
+    /// topmost [`Layer`](crate::overlay::Layer)'s focus, in the order they
+    /// were laid out.
+    pub fn selectable(&self, index: usize) -> Option<SpatialTreeData> {
+        let child = *self.forest.children(self.top_root)?.get(index)?;
+        self.forest.get(child).copied()
+    }
+
+    /// Returns the position of the object displaying `key` among the objects
+    /// that can be selected (see [`SpatialTree::selectable`]), if one of them
+    /// displays it.
+    pub fn selectable_index(&self, key: ArenaKey) -> Option<usize> {
+        self.forest
+            .children(self.top_root)?
+            .iter()
+            .position(|&child| self.forest.get(child).map(|data| data.key) == Some(key))
+    }
+
+    /// Returns the bounds of every object inside of the topmost [`Layer`]'s
+    /// focus that is visible on screen and displays one of `highlights`, in
+    /// the order they were laid out. See [`HighlightRenderer`].
+    ///
+    /// [`Layer`]: crate::overlay::Layer
+    /// [`HighlightRenderer`]: crate::render::highlight::HighlightRenderer
+    pub fn highlighted(
+        &self,
+        highlights: &HashSet<ArenaKey>,
+        screen_width: f32,
+        screen_height: f32,
+    ) -> Vec<SpatialBound> {
+        let mut bounds = Vec::new();
+        let mut todo: VecDeque<SpatialTreeKey> = vec![self.top_root].into_iter().collect();
+        while let Some(node) = todo.pop_front() {
+            let data = *self.forest.get(node).unwrap();
+            if !data.bounds.is_visible(screen_width, screen_height) {
+                continue;
+            }
+            if highlights.contains(&data.key) {
+                bounds.push(data.bounds);
+            }
+            todo.extend(self.forest.children(node).unwrap().iter().copied());
+        }
+        bounds
+    }
+
+    /// Groups the objects inside of the topmost [`Layer`]'s focus that are
+    /// visible on screen by the value they display, returning the bounds of
+    /// each value that is displayed more than once, in the order they were
+    /// laid out. See [`SharingRenderer`].
+    ///
+    /// [`Layer`]: crate::overlay::Layer
+    /// [`SharingRenderer`]: crate::render::sharing::SharingRenderer
+    pub fn shared(&self, screen_width: f32, screen_height: f32) -> Vec<Vec<SpatialBound>> {
+        let mut groups: HashMap<ArenaKey, Vec<SpatialBound>> = HashMap::new();
+        let mut order = Vec::new();
+        let mut todo: VecDeque<SpatialTreeKey> = self
+            .forest
+            .children(self.top_root)
+            .unwrap()
+            .iter()
+            .copied()
+            .collect();
+        while let Some(node) = todo.pop_front() {
+            let SpatialTreeData { key, bounds } = *self.forest.get(node).unwrap();
+            if !bounds.is_visible(screen_width, screen_height) {
+                continue;
+            }
+            let group = groups.entry(key).or_insert_with(|| {
+                order.push(key);
+                Vec::new()
+            });
+            group.push(bounds);
+            todo.extend(self.forest.children(node).unwrap().iter().copied());
+        }
+        order
+            .into_iter()
+            .filter_map(|key| groups.remove(&key))
+            .filter(|group| group.len() > 1)
+            .collect()
     }
 
     /// Returns the object at coordinates (`mouse_x`, `mouse_y`) on screen, if
     /// there is such an object.
     ///
-    /// Only children of the root node are considered. A click on a
-    /// child-of-child of the root node returns the the child, not the
-    /// child-of-child.
+    /// Only children of the root node of the topmost
+    /// [`Layer`](crate::overlay::Layer)'s focus are
+    /// considered. A click on a child-of-child of the root node returns the
+    /// the child, not the child-of-child.
     pub fn click(
         &self,
         screen_width: f32,
@@ -220,22 +799,38 @@ impl SpatialTree {
         mouse_x: f32,
         mouse_y: f32,
     ) -> Option<ArenaKey> {
+        let index = self.click_index(screen_width, screen_height, mouse_x, mouse_y)?;
+        self.selectable(index).map(|selection| selection.key)
+    }
+
+    /// Like [`SpatialTree::click`], but returns the position of the object
+    /// among the objects that can be selected (see
+    /// [`SpatialTree::selectable`]). Lists can display the same value more
+    /// than once, so this tells which of them was clicked on.
+    pub fn click_index(
+        &self,
+        screen_width: f32,
+        screen_height: f32,
+        mouse_x: f32,
+        mouse_y: f32,
+    ) -> Option<usize> {
         let (mouse_x, mouse_y) =
             screen_to_view_coordinates(mouse_x, mouse_y, screen_width, screen_height);
         self.forest
-            .children(self.root)
+            .children(self.top_root)
             .unwrap()
             .iter()
             .copied()
-            .find_map(|child| {
-                let SpatialTreeData { key, bounds } = self.forest.get(child).unwrap();
+            .enumerate()
+            .find_map(|(index, child)| {
+                let SpatialTreeData { bounds, .. } = self.forest.get(child).unwrap();
                 match bounds {
                     SpatialBound::Sphere(sphere) => {
                         let dx = sphere.center.x - mouse_x;
                         let dy = sphere.center.y - mouse_y;
                         let inside_rad = (dx * dx + dy * dy).sqrt() <= sphere.radius;
                         if inside_rad {
-                            Some(*key)
+                            Some(index)
                         } else {
                             None
                         }
@@ -244,8 +839,8 @@ impl SpatialTree {
                         let dx = cuboid.center.x - mouse_x;
                         let dy = cuboid.center.y - mouse_y;
                         let (width, height) = cuboid.dimensions_2d();
-                        if dx < width && dy < height {
-                            Some(*key)
+                        if dx.abs() <= width * 0.5 && dy.abs() <= height * 0.5 {
+                            Some(index)
                         } else {
                             None
                         }
@@ -284,12 +879,116 @@ fn screen_to_view_coordinates(
     }
 }
 
+/// Labels the ring around a container with the names of the registers bound
+/// to it (see [`Arena::register_names`]).
+///
+/// Labels are drawn above everything else in their layer so that they aren't
+/// hidden by the container's own contents.
+fn handle_label(
+    text_renderer: &mut TextRenderer,
+    arena: &Arena,
+    spatial_tree_data: SpatialTreeData,
+    order: DrawOrder,
+) {
+    let names = arena.register_names(spatial_tree_data.key);
+    if !names.is_empty() {
+        text_renderer.with_label(
+            SpatialBound::sphere_inside_bound(&spatial_tree_data.bounds),
+            DrawOrder {
+                z: i64::MAX,
+                ..order
+            },
+            names.join(", "),
+        );
+    }
+}
+
+/// Draws a small badge on the edge of a starred value, up and to the right of
+/// its center. See [`Arena::toggle_star`].
+fn handle_star(
+    circle_handler: &mut CircleRenderer,
+    spatial_tree_data: SpatialTreeData,
+    depth: i64,
+) {
+    let sphere = SpatialBound::sphere_inside_bound(&spatial_tree_data.bounds);
+    let offset = sphere.radius * STAR_DISTANCE * std::f32::consts::FRAC_1_SQRT_2;
+    circle_handler.with_instance(
+        Sphere {
+            center: sphere.center + cgmath::vec3(offset, offset, 0.0),
+            radius: sphere.radius * STAR_RADIUS,
+        },
+        depth as usize,
+    );
+}
+
+/// Draws a badge on the edge of a tagged value, down and to the right of its
+/// center, with the names of its tags written inside of it. See
+/// [`Arena::tag`].
+fn handle_tags(
+    circle_handler: &mut CircleRenderer,
+    text_handler: &mut TextRenderer,
+    arena: &Arena,
+    spatial_tree_data: SpatialTreeData,
+    order: DrawOrder,
+    depth: i64,
+) {
+    let tags = arena.tags(spatial_tree_data.key);
+    if tags.is_empty() {
+        return;
+    }
+    let sphere = SpatialBound::sphere_inside_bound(&spatial_tree_data.bounds);
+    let offset = sphere.radius * STAR_DISTANCE * std::f32::consts::FRAC_1_SQRT_2;
+    let badge = Sphere {
+        center: sphere.center + cgmath::vec3(offset, -offset, 0.0),
+        radius: sphere.radius * TAG_RADIUS,
+    };
+    circle_handler.with_instance(badge, depth as usize);
+    let names = tags
+        .iter()
+        .map(|tag| format!("#{}", tag))
+        .collect::<Vec<_>>();
+    text_handler.with_provisional(
+        SpatialBound::Sphere(badge),
+        DrawOrder {
+            z: i64::MAX,
+            ..order
+        },
+        names.join(" "),
+    );
+}
+
 /// Lays out a string.
 fn handle_string(
     string_handler: &mut TextRenderer,
     spatial_tree_data: SpatialTreeData,
+    order: DrawOrder,
+    class: StringClass,
+    depth: i64,
+) -> Vec<SpatialTreeData> {
+    string_handler.with_instance(spatial_tree_data, order, class, depth as usize);
+    vec![]
+}
+
+/// Lays out a number (see [`crate::number`]) as the text it is written as.
+fn handle_number(
+    string_handler: &mut TextRenderer,
+    spatial_tree_data: SpatialTreeData,
+    order: DrawOrder,
+    number: f64,
+) -> Vec<SpatialTreeData> {
+    string_handler.with_provisional(spatial_tree_data.bounds, order, number::format(number));
+    vec![]
+}
+
+/// Lays out a blob (see [`crate::blob`]) as an icon naming its type, above
+/// its file name.
+fn handle_blob(
+    string_handler: &mut TextRenderer,
+    spatial_tree_data: SpatialTreeData,
+    order: DrawOrder,
+    blob: &Blob,
 ) -> Vec<SpatialTreeData> {
-    string_handler.with_instance(spatial_tree_data);
+    string_handler.with_provisional(spatial_tree_data.bounds, order, blob.label());
     vec![]
 }
 
@@ -297,23 +996,47 @@ fn handle_string(
 fn handle_image(
     image_handler: &mut ImageRenderer,
     spatial_tree_data: SpatialTreeData,
+    order: DrawOrder,
 ) -> Vec<SpatialTreeData> {
-    image_handler.with_image(spatial_tree_data);
+    image_handler.with_image(spatial_tree_data, order);
     vec![]
 }
 
-fn handle_command(
+/// Lays out a custom leaf (see [`crate::leaf`]) as its thumbnail, or as its
+/// description if it doesn't have one.
+fn handle_custom(
+    image_handler: &mut ImageRenderer,
+    string_handler: &mut TextRenderer,
+    spatial_tree_data: SpatialTreeData,
+    order: DrawOrder,
+    custom: &CustomValue,
+) -> Vec<SpatialTreeData> {
+    match custom.thumbnail {
+        Some(_) => image_handler.with_image(spatial_tree_data, order),
+        None => {
+            string_handler.with_provisional(spatial_tree_data.bounds, order, custom.leaf.describe())
+        }
+    }
+    vec![]
+}
+
+/// Lays out the `elements` of a list (or command) in order, from top to
+/// bottom, each in a strip of equal height.
+///
+/// The return value is a vector containing the layout information for the
+/// elements of the list.
+fn handle_list(
     spatial_tree_data: SpatialTreeData,
-    command: &Vec<ArenaKey>,
+    elements: &Vec<ArenaKey>,
 ) -> Vec<SpatialTreeData> {
-    if command.len() > 0 {
+    if elements.len() > 0 {
         let cuboid = SpatialBound::cuboid_inside_bound(
             &spatial_tree_data.bounds,
-            1.0 / command.len() as f32,
+            1.0 / elements.len() as f32,
         );
-        SquareCuboid::split_vertically(cuboid, command.len())
+        SquareCuboid::split_vertically(cuboid, elements.len())
             .into_iter()
-            .zip(command.into_iter())
+            .zip(elements.into_iter())
             .map(|(c, k)| SpatialTreeData {
                 key: *k,
                 bounds: SpatialBound::SquareCuboid(c),
@@ -324,25 +1047,62 @@ fn handle_command(
     }
 }
 
-/// Lays out a set.
+/// Lays out a list like [`handle_list`], inside of a rectangle so that it can
+/// be told apart from a set, and seen even when it is empty.
+fn handle_framed_list(
+    rectangle_handler: &mut RectangleRenderer,
+    spatial_tree_data: SpatialTreeData,
+    elements: &Vec<ArenaKey>,
+    order: DrawOrder,
+) -> Vec<SpatialTreeData> {
+    let aspect_ratio = 1.0 / elements.len().max(1) as f32;
+    let frame = SpatialBound::cuboid_inside_bound(&spatial_tree_data.bounds, aspect_ratio);
+    rectangle_handler.with_instance(frame, order);
+    let (width, height) = frame.dimensions_2d();
+    let inside = SpatialTreeData {
+        bounds: SpatialBound::SquareCuboid(frame.shrink(THICKNESS * width.min(height))),
+        ..spatial_tree_data
+    };
+    handle_list(inside, elements)
+}
+
+/// Lays out a set with the given `members`, in the order they are arranged in
+/// (see [`Arena::arrange`]).
 ///
-/// A single circle is registered to enclose the set. Each element of the set is
-/// positioned along the inside circumference of the circle. The math for laying
+/// A single circle is registered to enclose the set, `depth` levels deep (which
+/// decides how dark its shadow is). Each element of the set is positioned
+/// along the inside circumference of the circle. The math for laying
 /// out the elements of the set is handled by a [`CirclePositioner`].
 ///
+/// If `weighted_by` is given, each element's radius is proportional to its
+/// [`Arena::leaf_count`] in that arena and the math is handled by
+/// [`fit_weighted_circles`] instead, as long as there are no more than
+/// [`RING_THRESHOLD`] elements; it takes time quadratic in the number of
+/// elements, and larger sets are arranged in rings by the
+/// [`CirclePositioner`] anyway. If `carousel` is given, the element at that
+/// position (see [`Arena::carousel_position`]) is enlarged by
+/// [`carousel_circles`].
+///
+/// Every element is turned `rotation` radians counterclockwise about the
+/// center of the set (see [`Arena::rotation`]).
+///
 /// The return value is a vector containing the layout information for the
 /// elements of the set.
 fn handle_set(
     circle_handler: &mut CircleRenderer,
     spatial_tree_data: SpatialTreeData,
-    set: &HashSet<ArenaKey>,
+    depth: i64,
+    members: &[ArenaKey],
+    weighted_by: Option<&Arena>,
+    carousel: Option<f32>,
+    rotation: f64,
 ) -> Vec<SpatialTreeData> {
     let sphere = SpatialBound::sphere_inside_bound(&spatial_tree_data.bounds);
 
     // The circle that encloses the set
-    circle_handler.with_instance(sphere);
+    circle_handler.with_instance(sphere, depth as usize);
 
-    let sphere = if set.len() == 1 {
+    let sphere = if members.len() == 1 {
         // In the case where our set only contains one element, it is confusing
         // if that element were to be displayed the same size as the enclosing
         // circle. For instance, if that element was a set, we would not be able
@@ -356,19 +1116,46 @@ fn handle_set(
     } else {
         sphere
     };
-    let circle_positioner = CirclePositioner::new(
-        (sphere.radius * MIN_RADIUS) as f64,
-        set.len() as u64,
-        0.0,
-        Point {
-            x: sphere.center.x as f64,
-            y: sphere.center.y as f64,
-        },
-        0.0,
-    );
-    circle_positioner
+    let enclosing_radius = (sphere.radius * MIN_RADIUS) as f64;
+    let center = Point {
+        x: sphere.center.x as f64,
+        y: sphere.center.y as f64,
+    };
+    let circles: Vec<Circle> = match (weighted_by, carousel) {
+        (Some(arena), _) if members.len() as u64 <= RING_THRESHOLD => {
+            // A set inside of itself stands in for a string being entered
+            // (see `rebuild_tree`), which is a single leaf.
+            let weights: Vec<f64> = members
+                .iter()
+                .map(|&k| {
+                    if k == spatial_tree_data.key {
+                        1.0
+                    } else {
+                        arena.leaf_count(k) as f64
+                    }
+                })
+                .collect();
+            fit_weighted_circles(enclosing_radius, center, &weights)
+                .into_iter()
+                .map(|circle| rotate_about(circle, center, rotation))
+                .collect()
+        }
+        (_, Some(position)) => {
+            carousel_circles(enclosing_radius, center, members.len(), position, rotation)
+        }
+        (_, None) => CirclePositioner::new(
+            enclosing_radius,
+            members.len() as u64,
+            0.0,
+            center,
+            rotation,
+        )
+        .into_iter()
+        .collect(),
+    };
+    circles
         .into_iter()
-        .zip(set.iter())
+        .zip(members.iter())
         .map(|(circle, key)| {
             let Circle { center, radius } = circle;
             let Point { x, y } = center;
@@ -385,9 +1172,62 @@ fn handle_set(
         .collect()
 }
 
+/// Returns the circles `count` elements are placed in when the element at
+/// `position` (see [`Arena::carousel_position`]) is enlarged, in the order of
+/// the elements, turned `rotation` radians counterclockwise.
+///
+/// The other elements are placed around the enlarged one in order,
+/// counterclockwise, so that moving from one position to the next turns every
+/// element into its neighbour's place. Positions between two indices are drawn
+/// part of the way through that turn.
+fn carousel_circles(
+    enclosing_radius: f64,
+    center: Point,
+    count: usize,
+    position: f32,
+    rotation: f64,
+) -> Vec<Circle> {
+    if count < 2 {
+        return CirclePositioner::new(enclosing_radius, count as u64, 0.0, center, rotation)
+            .collect();
+    }
+    // The places the elements can be in, starting with the enlarged one and
+    // going counterclockwise from there.
+    let mut places: Vec<Circle> =
+        CirclePositioner::new(enclosing_radius, count as u64, ZOOM, center, rotation).collect();
+    let enlarged_angle = (places[0].center.y - center.y).atan2(places[0].center.x - center.x);
+    let turned = |circle: &Circle| {
+        let angle = (circle.center.y - center.y).atan2(circle.center.x - center.x);
+        (angle - enlarged_angle).rem_euclid(2.0 * std::f64::consts::PI)
+    };
+    places[1..].sort_by(|a, b| turned(a).partial_cmp(&turned(b)).unwrap());
+    // Where each element is when the element at `enlarged` is enlarged.
+    let arrangement = |enlarged: usize| {
+        let mut circles = places.clone();
+        circles.rotate_right(enlarged);
+        circles
+    };
+    let position = (position as f64).rem_euclid(count as f64);
+    let t = position.fract();
+    let from = arrangement(position.floor() as usize % count);
+    let to = arrangement((position.floor() as usize + 1) % count);
+    from.iter()
+        .zip(to.iter())
+        .map(|(from, to)| Circle {
+            center: Point {
+                x: from.center.x + t * (to.center.x - from.center.x),
+                y: from.center.y + t * (to.center.y - from.center.y),
+            },
+            radius: from.radius + t * (to.radius - from.radius),
+        })
+        .collect()
+}
+
 /// Lays out a map.
 ///
-/// A single circle is registered to enclose the map. The key-value pairs are
+/// A single circle is registered to enclose the map, `depth` levels deep, like
+/// in [`handle_set`], and turned `rotation` radians counterclockwise like the
+/// elements of a set. The key-value pairs are
 /// rendered as if the map was a set containing one two-element-set for each
 /// key-value pair.
 ///
@@ -396,10 +1236,12 @@ fn handle_set(
 fn handle_map(
     circle_handler: &mut CircleRenderer,
     spatial_tree_data: SpatialTreeData,
+    depth: i64,
     map: &HashMap<ArenaKey, ArenaKey>,
+    rotation: f64,
 ) -> Vec<SpatialTreeData> {
     let bound_sphere = SpatialBound::sphere_inside_bound(&spatial_tree_data.bounds);
-    circle_handler.with_instance(bound_sphere);
+    circle_handler.with_instance(bound_sphere, depth as usize);
     let sphere = if map.len() == 1 {
         Sphere {
             center: bound_sphere.center,
@@ -416,7 +1258,7 @@ fn handle_map(
             x: sphere.center.x as f64,
             y: sphere.center.y as f64,
         },
-        0.0,
+        rotation,
     );
     circle_positioner
         .into_iter()
@@ -429,7 +1271,7 @@ fn handle_map(
                 center: cgmath::vec3(x as f32, y as f32, 0.0),
                 radius,
             };
-            circle_handler.with_instance(other_sphere);
+            circle_handler.with_instance(other_sphere, depth as usize + 1);
             let sub_circle_positioner =
                 CirclePositioner::new(circle.radius, 2, 0.0, circle.center, 0.0);
             sub_circle_positioner
@@ -453,3 +1295,154 @@ fn handle_map(
         .flatten()
         .collect()
 }
+
+/// Returns the rectangle that a container is drawn as when using
+/// [`LayoutStrategy::Treemap`], and the region inside of it that its elements
+/// are divided between.
+fn treemap_container(
+    rectangle_handler: &mut RectangleRenderer,
+    spatial_tree_data: SpatialTreeData,
+    order: DrawOrder,
+) -> SquareCuboid {
+    let cuboid = match spatial_tree_data.bounds {
+        SpatialBound::SquareCuboid(cuboid) => cuboid,
+        SpatialBound::Sphere(sphere) => SpatialBound::cuboid_inside_sphere(&sphere, 1.0),
+    };
+    rectangle_handler.with_instance(cuboid, order);
+    let (width, height) = cuboid.dimensions_2d();
+    cuboid.shrink(THICKNESS * width.min(height))
+}
+
+/// Divides `inside` between `keys` with equal weights. Each element is given a
+/// little less than its share so that neighbouring rectangles don't touch.
+fn treemap_elements<'a, I: Iterator<Item = &'a ArenaKey>>(
+    inside: SquareCuboid,
+    n: usize,
+    keys: I,
+) -> Vec<SpatialTreeData> {
+    SquareCuboid::squarify(inside, &vec![1.0; n])
+        .into_iter()
+        .zip(keys)
+        .map(|(cell, &key)| {
+            let (width, height) = cell.dimensions_2d();
+            SpatialTreeData {
+                key,
+                bounds: SpatialBound::SquareCuboid(cell.shrink(THICKNESS * width.min(height))),
+            }
+        })
+        .collect()
+}
+
+/// Lays out a set as a treemap.
+///
+/// A single rectangle is registered to enclose the set. The inside of the
+/// rectangle is divided up between the elements of the set.
+fn handle_set_treemap(
+    rectangle_handler: &mut RectangleRenderer,
+    spatial_tree_data: SpatialTreeData,
+    members: &[ArenaKey],
+    order: DrawOrder,
+) -> Vec<SpatialTreeData> {
+    let inside = treemap_container(rectangle_handler, spatial_tree_data, order);
+    treemap_elements(inside, members.len(), members.iter())
+}
+
+/// Lays out a string that is still being entered (see
+/// [`TextRenderer::with_provisional`]) in `place`, the place it will be in once
+/// it is inserted. It is drawn inside of a ring (or a rectangle, in treemaps)
+/// so that it stands out from the values that are actually there.
+fn handle_provisional(
+    circle_handler: &mut CircleRenderer,
+    rectangle_handler: &mut RectangleRenderer,
+    string_handler: &mut TextRenderer,
+    place: SpatialTreeData,
+    depth: i64,
+    order: DrawOrder,
+    text: &str,
+) {
+    let inside = match place.bounds {
+        SpatialBound::Sphere(sphere) => {
+            circle_handler.with_instance(sphere, depth as usize);
+            SpatialBound::Sphere(Sphere {
+                center: sphere.center,
+                radius: sphere.radius * MIN_RADIUS,
+            })
+        }
+        SpatialBound::SquareCuboid(_) => {
+            SpatialBound::SquareCuboid(treemap_container(rectangle_handler, place, order))
+        }
+    };
+    if !text.is_empty() {
+        string_handler.with_provisional(inside, order, text.to_string());
+    }
+}
+
+/// Lays out a map as a treemap.
+///
+/// Like [`handle_set_treemap`], except that each key-value pair gets its own
+/// rectangle, which is divided in half between the key and the value.
+fn handle_map_treemap(
+    rectangle_handler: &mut RectangleRenderer,
+    spatial_tree_data: SpatialTreeData,
+    map: &HashMap<ArenaKey, ArenaKey>,
+    order: DrawOrder,
+) -> Vec<SpatialTreeData> {
+    let inside = treemap_container(rectangle_handler, spatial_tree_data, order);
+    SquareCuboid::squarify(inside, &vec![1.0; map.len()])
+        .into_iter()
+        .zip(map.iter())
+        .flat_map(|(cell, (key, value))| {
+            let (width, height) = cell.dimensions_2d();
+            let pair = SpatialTreeData {
+                key: *key,
+                bounds: SpatialBound::SquareCuboid(cell.shrink(THICKNESS * width.min(height))),
+            };
+            let inside = treemap_container(rectangle_handler, pair, order);
+            treemap_elements(inside, 2, vec![key, value].into_iter())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn text_heavy_sets_are_treemaps() {
+        let mut arena = Arena::new();
+        arena.bind_register_to_empty_set("a");
+        for string in &["one", "two", "three"] {
+            arena.set_insert_string("a", *string).unwrap();
+        }
+        let set = arena.register("a").unwrap();
+        let layout = |arena: &Arena| container_layout(arena, set, LayoutStrategy::Circles);
+        assert_eq!(LayoutStrategy::Treemap, layout(&arena));
+
+        arena.bind_register_to_empty_set("b");
+        arena.set_insert("a", "b").unwrap();
+        arena.bind_register_to_empty_set("c");
+        arena.set_insert("a", "c").unwrap();
+        assert_eq!(LayoutStrategy::Circles, layout(&arena));
+
+        arena.set_layout_override(set, Some(LayoutStrategy::WeightedCircles));
+        assert_eq!(LayoutStrategy::WeightedCircles, layout(&arena));
+    }
+
+    #[test]
+    fn carousels_turn_into_place() {
+        let center = Point { x: 0.0, y: 0.0 };
+        let at = |position: f32| carousel_circles(1.0, center, 5, position, 0.0);
+        let largest = |circles: &[Circle]| {
+            (0..circles.len())
+                .max_by(|&a, &b| circles[a].radius.partial_cmp(&circles[b].radius).unwrap())
+                .unwrap()
+        };
+        assert_eq!(0, largest(&at(0.0)));
+        assert_eq!(2, largest(&at(2.0)));
+        assert_eq!(4, largest(&at(-1.0)));
+        // Halfway through a turn, the element being enlarged is growing into
+        // the place of the enlarged element.
+        let radius = |position: f32| at(position)[1].radius;
+        assert!(radius(0.0) < radius(0.5) && radius(0.5) < radius(1.0));
+    }
+}
@@ -107,7 +107,150 @@ impl SquareCuboid {
         width / height
     }
 
+    /// Returns a cuboid with the same center whose width and height are each
+    /// `2 * amount` smaller (but never negative).
+    pub fn shrink(&self, amount: f32) -> SquareCuboid {
+        let (width, height) = self.dimensions_2d();
+        Self::from_dimensions(
+            (width - 2.0 * amount).max(0.0),
+            (height - 2.0 * amount).max(0.0),
+            self.center,
+        )
+    }
+
+    /// Divides a cuboid into one cuboid per element of `weights`, such that
+    /// the area of each is proportional to its weight. The returned list is in
+    /// the same order as `weights`. If every weight is zero, the cuboids are
+    /// all the same size.
+    ///
+    /// This uses the [squarified treemap] algorithm, which tries to keep the
+    /// cuboids as close to square as possible, so that things placed inside of
+    /// them don't end up long and thin.
+    ///
+    /// [squarified treemap]: https://www.win.tue.nl/~vanwijk/stm.pdf
+    pub fn squarify(cuboid: SquareCuboid, weights: &[f32]) -> Vec<SquareCuboid> {
+        let (width, height) = cuboid.dimensions_2d();
+        let total: f32 = weights.iter().map(|w| w.max(0.0)).sum();
+        let areas: Vec<f32> = if total > 0.0 {
+            weights
+                .iter()
+                .map(|w| w.max(0.0) / total * width * height)
+                .collect()
+        } else {
+            vec![width * height / weights.len() as f32; weights.len()]
+        };
+
+        // The algorithm works best when placing the largest areas first.
+        let mut order: Vec<usize> = (0..areas.len()).collect();
+        order.sort_by(|&a, &b| areas[b].partial_cmp(&areas[a]).unwrap());
+
+        // The aspect ratio of the least square cuboid in a row of `row_area`
+        // total area laid along a side of length `side`, where the biggest
+        // and smallest areas in the row are `max` and `min`.
+        let worst = |row_area: f32, min: f32, max: f32, side: f32| -> f32 {
+            let side = side * side;
+            let row_area = row_area * row_area;
+            (side * max / row_area).max(row_area / (side * min))
+        };
+
+        let mut result = vec![cuboid; areas.len()];
+        // The part of `cuboid` that hasn't been filled yet, as its left edge,
+        // bottom edge, width, and height.
+        let (mut x, y) = (
+            cuboid.center.x - width * 0.5,
+            cuboid.center.y - height * 0.5,
+        );
+        let (mut w, mut h) = (width, height);
+        let mut start = 0;
+        while start < order.len() {
+            // Rows are laid along the shorter side of the unfilled part.
+            let side = w.min(h);
+            let mut end = start + 1;
+            let mut row_area = areas[order[start]];
+            while end < order.len() {
+                let area = areas[order[end]];
+                let min = areas[order[end - 1]];
+                let max = areas[order[start]];
+                if worst(row_area + area, area, max, side) > worst(row_area, min, max, side) {
+                    break;
+                }
+                row_area += area;
+                end += 1;
+            }
+
+            // Lay out the row, then shrink the unfilled part to exclude it.
+            if w >= h {
+                let row_width = if h > 0.0 { row_area / h } else { 0.0 };
+                let mut top = y + h;
+                for &i in &order[start..end] {
+                    let cell_height = if row_area > 0.0 {
+                        h * areas[i] / row_area
+                    } else {
+                        0.0
+                    };
+                    result[i] = Self::from_dimensions(
+                        row_width,
+                        cell_height,
+                        (x + row_width * 0.5, top - cell_height * 0.5, 0.0).into(),
+                    );
+                    top -= cell_height;
+                }
+                x += row_width;
+                w -= row_width;
+            } else {
+                let row_height = if w > 0.0 { row_area / w } else { 0.0 };
+                let mut left = x;
+                for &i in &order[start..end] {
+                    let cell_width = if row_area > 0.0 {
+                        w * areas[i] / row_area
+                    } else {
+                        0.0
+                    };
+                    result[i] = Self::from_dimensions(
+                        cell_width,
+                        row_height,
+                        (left + cell_width * 0.5, y + h - row_height * 0.5, 0.0).into(),
+                    );
+                    left += cell_width;
+                }
+                h -= row_height;
+            }
+            start = end;
+        }
+
+        result
+    }
+
     // pub const fn is_horizontal(&self) -> bool {
     //     self.orientation == Orientation::Horizontal
     // }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn squarify() {
+        let cuboid = SquareCuboid::from_dimensions(2.0, 1.0, (1.0, 0.5, 0.0).into());
+        let weights = [6.0, 6.0, 4.0, 3.0, 2.0, 2.0, 1.0];
+        let total: f32 = weights.iter().sum();
+        let cells = SquareCuboid::squarify(cuboid, &weights);
+        assert_eq!(weights.len(), cells.len());
+        for (cell, weight) in cells.iter().zip(weights.iter()) {
+            let (width, height) = cell.dimensions_2d();
+            assert!(approx_eq!(
+                f32,
+                width * height,
+                2.0 * weight / total,
+                epsilon = 0.0001
+            ));
+            // Every cell is inside of the cuboid we divided up.
+            assert!(cell.center.x - width * 0.5 >= -0.0001);
+            assert!(cell.center.x + width * 0.5 <= 2.0001);
+            assert!(cell.center.y - height * 0.5 >= -0.0001);
+            assert!(cell.center.y + height * 0.5 <= 1.0001);
+        }
+    }
+}
@@ -1,16 +1,60 @@
+use crate::render::capture::CaptureRequest;
 use crate::render::renderer::Renderer;
+use crate::window::{Options, WindowRequest};
+use std::io;
+use std::path::Path;
+use std::time::Instant;
 use winit::window::Window;
 
+/// How many times in a row the swap chain can be recreated without a frame
+/// being drawn before the device is assumed to be lost. wgpu can't tell us
+/// when that happens, but a lost device keeps losing every swap chain created
+/// on it.
+const MAX_SWAP_CHAIN_RECREATIONS: u32 = 3;
+
 pub struct State {
+    instance: wgpu::Instance,
     surface: wgpu::Surface,
     device: wgpu::Device,
     queue: wgpu::Queue,
     sc_desc: wgpu::SwapChainDescriptor,
     swap_chain: wgpu::SwapChain,
     size: winit::dpi::PhysicalSize<u32>,
+    /// How many times the swap chain has been recreated since the last frame
+    /// was drawn. See [`State::recover`].
+    recreations: u32,
     renderer: Renderer,
 }
 
+/// Finds an adapter that can draw to `surface`, and opens a device on it.
+/// Returns the device alongside the texture format the adapter prefers for
+/// `surface`.
+async fn request_device(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface,
+) -> (wgpu::Device, wgpu::Queue, wgpu::TextureFormat) {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: Some(surface),
+        })
+        .await
+        .unwrap();
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    let texture_format = adapter.get_swap_chain_preferred_format(surface);
+    (device, queue, texture_format)
+}
+
 #[derive(Debug)]
 struct Instance {
     position: cgmath::Vector3<f32>,
@@ -24,31 +68,12 @@ struct InstanceRaw {
 }
 
 impl State {
-    pub async fn new(window: &Window) -> Self {
+    pub async fn new(window: &Window, options: Options) -> Self {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
         let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                },
-                None,
-            )
-            .await
-            .unwrap();
-
-        let texture_format = adapter.get_swap_chain_preferred_format(&surface);
+        let (device, queue, texture_format) = request_device(&instance, &surface).await;
 
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
@@ -63,25 +88,63 @@ impl State {
         let renderer = Renderer::new(
             &device, // &mut queue,
             &sc_desc,
+            window.scale_factor() as f32,
+            options,
         );
 
         Self {
+            instance,
             surface,
             device,
             queue,
             sc_desc,
             swap_chain,
             size,
+            recreations: 0,
             renderer,
         }
     }
 
-    pub fn recreate_swap_chain(&mut self) {
-        self.resize(self.size);
+    /// Recovers from the swap chain being lost or outdated, which happens
+    /// when the window is resized quickly or switched in and out of
+    /// fullscreen, by recreating it. If that keeps failing, the device itself
+    /// is assumed to be lost (after a GPU reset, say), so a new one is opened
+    /// and every GPU resource is recreated on it. Nothing in the arena is
+    /// lost either way.
+    pub fn recover(&mut self) {
+        self.recreations += 1;
+        if self.recreations > MAX_SWAP_CHAIN_RECREATIONS {
+            log::warn!("the swap chain keeps getting lost; recreating the device");
+            self.recreate_device();
+            self.recreations = 0;
+        } else {
+            self.resize(self.size);
+        }
+    }
+
+    fn recreate_device(&mut self) {
+        let (device, queue, texture_format) =
+            futures::executor::block_on(request_device(&self.instance, &self.surface));
+        self.device = device;
+        self.queue = queue;
+        self.sc_desc.format = texture_format;
+        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        self.renderer.recreate(&self.device, &self.sc_desc);
+    }
+
+    /// Tells the renderer how many physical pixels make up a logical pixel on
+    /// the display the window is now on.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.renderer.set_scale_factor(scale_factor as f32);
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         self.size = new_size;
+        // Minimized windows have no size, and swap chains can't be empty.
+        // Nothing is drawn until the window has a size again.
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
         self.sc_desc.width = new_size.width;
         self.sc_desc.height = new_size.height;
         self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
@@ -93,14 +156,83 @@ impl State {
     }
 
     pub fn input(&mut self, event: &winit::event::WindowEvent) -> bool {
-        self.renderer.input(
+        let should_redraw = self.renderer.input(
             // &self.device, &mut
             //  self.queue,
             event,
-        )
+        );
+        if let Some(request) = self.renderer.take_capture_request() {
+            self.capture(request);
+        }
+        should_redraw
+    }
+
+    /// Starts capturing a string into the inbox, returning true if the window
+    /// should be redrawn. See [`crate::background`].
+    pub fn begin_capture(&mut self) -> bool {
+        self.renderer.begin_capture()
+    }
+
+    /// Takes the change to the window asked for by the last input, if there
+    /// was one. Only the event loop can change the window.
+    pub fn take_window_request(&mut self) -> Option<WindowRequest> {
+        self.renderer.take_window_request()
+    }
+
+    /// Draws a capture and saves it in the working directory. See
+    /// [`crate::render::capture`].
+    fn capture(&mut self, request: CaptureRequest) {
+        let captured =
+            self.renderer
+                .capture(&self.device, &mut self.queue, self.sc_desc.format, request);
+        match captured {
+            Some((uuid, image)) => {
+                let file_name = request.file_name(uuid);
+                match image.save(&file_name) {
+                    Ok(()) => log::info!("saved capture to {}", file_name),
+                    Err(e) => log::warn!("couldn't save capture to {}: {}", file_name, e),
+                }
+            }
+            None => log::warn!("couldn't read back capture"),
+        }
+    }
+
+    /// Returns true if the window should keep redrawing, even without input.
+    pub fn is_animating(&self) -> bool {
+        self.renderer.is_animating()
+    }
+
+    /// Returns when the window should next be redrawn even without input, if
+    /// ever.
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        self.renderer.next_wakeup()
+    }
+
+    /// Returns true if animations should wait for [`State::next_wakeup`] to
+    /// be redrawn. See [`crate::power`].
+    pub fn frame_rate_capped(&self) -> bool {
+        self.renderer.frame_rate_capped()
+    }
+
+    /// See [`Renderer::export_layout`].
+    pub fn export_layout<P: AsRef<Path>>(&mut self, path: P) -> io::Result<usize> {
+        self.renderer.export_layout(path)
+    }
+
+    /// See [`Renderer::rescue`].
+    pub fn rescue(&mut self, dir: &Path) -> io::Result<()> {
+        self.renderer.rescue(dir)
+    }
+
+    /// See [`Renderer::title`].
+    pub fn title(&self) -> String {
+        self.renderer.title()
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
+        if self.size.width == 0 || self.size.height == 0 {
+            return Ok(());
+        }
         let frame = self.swap_chain.get_current_frame()?.output;
 
         let mut encoder = self
@@ -120,6 +252,7 @@ impl State {
         self.queue.submit(std::iter::once(encoder.finish()));
 
         self.renderer.post_render();
+        self.recreations = 0;
 
         Ok(())
     }
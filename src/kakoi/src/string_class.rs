@@ -0,0 +1,227 @@
+//! # Special kinds of strings
+//!
+//! Some strings are more than just text. Each string is given a
+//! [`StringClass`] by [`classify`], which the text renderer uses to style it:
+//! links are drawn underlined in a different color, dates are written out in
+//! words, and code is drawn in a monospace font with its whitespace intact.
+//!
+//! Strings never change once they are in the [`Arena`], so their classes are
+//! only computed once. See [`Arena::string_class`].
+//!
+//! [`Arena`]: crate::arena::Arena
+//! [`Arena::string_class`]: crate::arena::Arena::string_class
+
+use std::borrow::Cow;
+
+/// Marks the beginning and end of a block of code, like in Markdown.
+const CODE_FENCE: &str = "```";
+
+/// Tabs in code are replaced by this many spaces, since fonts don't agree on
+/// how wide a tab is.
+const TAB_WIDTH: usize = 4;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// What kind of content a string holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringClass {
+    /// Ordinary text.
+    Plain,
+    /// A web address starting with `http://` or `https://`, which can be
+    /// opened in a browser, or a deep link to a value (see [`crate::link`]),
+    /// which can be followed.
+    Url,
+    /// An ISO 8601 calendar date, like `2021-03-04`.
+    Date { year: u16, month: u8, day: u8 },
+    /// A block of code surrounded by a pair of `` ``` `` fences. If the block
+    /// spans several lines, anything after the opening fence on the first
+    /// line (usually the name of a language) isn't displayed.
+    Code,
+}
+
+impl StringClass {
+    /// Returns the text to display in place of `string`, which must have this
+    /// class.
+    pub fn display<'a>(&self, string: &'a str) -> Cow<'a, str> {
+        match *self {
+            StringClass::Plain | StringClass::Url => Cow::Borrowed(string),
+            StringClass::Date { year, month, day } => {
+                Cow::Owned(format!("{} {}, {}", MONTHS[month as usize - 1], day, year))
+            }
+            StringClass::Code => {
+                let inner = &string[CODE_FENCE.len()..string.len() - CODE_FENCE.len()];
+                // Skip the rest of the line the opening fence is on.
+                let inner = match inner.find('\n') {
+                    Some(newline) => &inner[newline + 1..],
+                    None => inner,
+                };
+                let inner = inner.strip_suffix('\n').unwrap_or(inner);
+                Cow::Owned(inner.replace('\t', &" ".repeat(TAB_WIDTH)))
+            }
+        }
+    }
+}
+
+/// Decides what kind of content `string` holds.
+pub fn classify(string: &str) -> StringClass {
+    if is_url(string) {
+        StringClass::Url
+    } else if let Some((year, month, day)) = parse_date(string) {
+        StringClass::Date { year, month, day }
+    } else if string.len() >= 2 * CODE_FENCE.len()
+        && string.starts_with(CODE_FENCE)
+        && string.ends_with(CODE_FENCE)
+    {
+        StringClass::Code
+    } else {
+        StringClass::Plain
+    }
+}
+
+fn is_url(string: &str) -> bool {
+    let rest = string
+        .strip_prefix("https://")
+        .or_else(|| string.strip_prefix("http://"))
+        .or_else(|| string.strip_prefix(crate::link::SCHEME));
+    match rest {
+        Some(rest) => !rest.is_empty() && !rest.chars().any(char::is_whitespace),
+        None => false,
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date, making sure that the day exists.
+fn parse_date(string: &str) -> Option<(u16, u8, u8)> {
+    let bytes = string.as_bytes();
+    let digits = |range: std::ops::Range<usize>| -> Option<u16> {
+        let part = string.get(range)?;
+        if part.bytes().all(|b| b.is_ascii_digit()) {
+            part.parse().ok()
+        } else {
+            None
+        }
+    };
+    if bytes.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return None;
+    }
+    let (year, month, day) = (digits(0..4)?, digits(5..7)?, digits(8..10)?);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if (1..=days_in_month).contains(&day) {
+        Some((year, month as u8, day as u8))
+    } else {
+        None
+    }
+}
+
+/// Opens `url` with the operating system's default handler (usually a web
+/// browser), without waiting for it to finish. The url is handed straight to
+/// the handler rather than through a shell, so that characters like `&` in it
+/// aren't run as commands.
+pub fn open_url(url: &str) -> std::io::Result<()> {
+    use std::process::Command;
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+    command.arg(url).spawn().map(|_| ())
+}
+
+/// Puts `text` on the operating system's clipboard by handing it to the
+/// platform's clipboard tool (`clip` on Windows, `pbcopy` on macOS, and
+/// `xclip` elsewhere), waiting for it to finish.
+pub fn copy_to_clipboard(text: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    let mut command = if cfg!(target_os = "windows") {
+        Command::new("clip")
+    } else if cfg!(target_os = "macos") {
+        Command::new("pbcopy")
+    } else {
+        let mut command = Command::new("xclip");
+        command.args(&["-selection", "clipboard"]);
+        command
+    };
+    let mut child = command.stdin(Stdio::piped()).spawn()?;
+    child.stdin.take().unwrap().write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("clipboard tool exited with {}", status),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        assert_eq!(StringClass::Url, classify("https://example.com/a?b=c"));
+        assert_eq!(StringClass::Url, classify("http://example.com"));
+        assert_eq!(
+            StringClass::Url,
+            classify("kakoi:///67e55044-10b1-426f-9247-bb680e5fe0c8")
+        );
+        assert_eq!(StringClass::Plain, classify("https://"));
+        assert_eq!(StringClass::Plain, classify("see https://example.com"));
+        assert_eq!(StringClass::Plain, classify("https://example.com and more"));
+    }
+
+    #[test]
+    fn dates() {
+        let date = classify("2020-02-29");
+        assert_eq!(
+            StringClass::Date {
+                year: 2020,
+                month: 2,
+                day: 29
+            },
+            date
+        );
+        assert_eq!("February 29, 2020", date.display("2020-02-29"));
+        assert_eq!(StringClass::Plain, classify("2021-02-29"));
+        assert_eq!(StringClass::Plain, classify("2021-13-01"));
+        assert_eq!(StringClass::Plain, classify("2021-1-01"));
+        assert_eq!(StringClass::Plain, classify("+021-01-01"));
+    }
+
+    #[test]
+    fn code() {
+        let code = "```rust\nfn main() {\n\tloop {}\n}\n```";
+        assert_eq!(StringClass::Code, classify(code));
+        assert_eq!(
+            "fn main() {\n    loop {}\n}",
+            StringClass::Code.display(code)
+        );
+        assert_eq!("x", StringClass::Code.display("```x```"));
+        assert_eq!(StringClass::Plain, classify("```"));
+    }
+}
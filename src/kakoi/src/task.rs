@@ -0,0 +1,176 @@
+//! # Background tasks
+//!
+//! Some operations (like decoding a large image) take long enough that doing
+//! them while handling input would freeze the window. A [`Task`] runs such an
+//! operation on a worker thread instead. The operation reports how far along it
+//! is through a [`Progress`], which is also how it finds out that it has been
+//! cancelled.
+//!
+//! Tasks never touch the [`Arena`](crate::arena::Arena) themselves. They
+//! produce a value which is handed back to the main thread by [`Task::poll`]
+//! once they finish, and only then is the arena modified, all at once. A
+//! cancelled task has no effect at all.
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    mpsc::{self, Receiver, TryRecvError},
+    Arc,
+};
+
+/// State shared between a [`Task`] and its worker thread.
+#[derive(Debug, Default)]
+struct Shared {
+    /// The bits of an `f32` between zero and one.
+    progress: AtomicU32,
+    cancelled: AtomicBool,
+}
+
+/// Given to the operation run by a [`Task`], so that it can report its
+/// progress and check whether it should stop early.
+#[derive(Debug)]
+pub struct Progress {
+    shared: Arc<Shared>,
+}
+
+impl Progress {
+    /// Progress that nothing is watching, for running an operation meant to be
+    /// run by a [`Task`] directly instead, like when importing a file from the
+    /// command line. It is never cancelled.
+    pub fn unobserved() -> Self {
+        Self {
+            shared: Arc::default(),
+        }
+    }
+
+    /// Records that the operation is `fraction` of the way done. Values outside
+    /// of `0.0..=1.0` are clamped.
+    pub fn report(&self, fraction: f32) {
+        self.shared
+            .progress
+            .store(fraction.max(0.0).min(1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Returns true if the task was cancelled. Operations should check this
+    /// regularly and return [`None`] as soon as it is true, since their result
+    /// would be thrown away anyway.
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// What a [`Task`] is up to. See [`Task::poll`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum TaskStatus<T> {
+    Running,
+    /// The operation finished, producing a value.
+    Finished(T),
+    /// The task was cancelled, or its operation gave up (by returning [`None`]
+    /// or panicking).
+    Cancelled,
+}
+
+/// An operation running on a worker thread.
+///
+/// See [the module-level documentation](crate::task) for more information.
+#[derive(Debug)]
+pub struct Task<T> {
+    name: String,
+    shared: Arc<Shared>,
+    receiver: Receiver<Option<T>>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    /// Starts running `operation` on a new thread. `name` describes the task
+    /// to the user.
+    pub fn spawn<S, F>(name: S, operation: F) -> Self
+    where
+        S: Into<String>,
+        F: FnOnce(&Progress) -> Option<T> + Send + 'static,
+    {
+        let shared = Arc::new(Shared::default());
+        let progress = Progress {
+            shared: shared.clone(),
+        };
+        let (sender, receiver) = mpsc::channel();
+        let name = name.into();
+        std::thread::Builder::new()
+            .name(format!("task: {}", name))
+            .spawn(move || {
+                // The task may have been dropped already, in which case nobody
+                // is interested in the result.
+                let _ = sender.send(operation(&progress));
+            })
+            .unwrap();
+        Self {
+            name,
+            shared,
+            receiver,
+        }
+    }
+}
+
+impl<T> Task<T> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// How far along the operation is, from zero to one.
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.shared.progress.load(Ordering::Relaxed))
+    }
+
+    /// Asks the operation to stop. Its result is discarded even if it finishes
+    /// anyway.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Checks whether the operation has finished, without waiting for it.
+    /// Once this returns something other than [`TaskStatus::Running`], the
+    /// task should be dropped.
+    pub fn poll(&self) -> TaskStatus<T> {
+        match self.receiver.try_recv() {
+            Err(TryRecvError::Empty) => TaskStatus::Running,
+            Ok(Some(value)) if !self.shared.cancelled.load(Ordering::Relaxed) => {
+                TaskStatus::Finished(value)
+            }
+            Ok(_) | Err(TryRecvError::Disconnected) => TaskStatus::Cancelled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wait<T>(task: &Task<T>) -> TaskStatus<T> {
+        loop {
+            match task.poll() {
+                TaskStatus::Running => std::thread::yield_now(),
+                status => return status,
+            }
+        }
+    }
+
+    #[test]
+    fn finish() {
+        let task = Task::spawn("add", |progress| {
+            progress.report(2.0);
+            Some(1 + 1)
+        });
+        assert_eq!(TaskStatus::Finished(2), wait(&task));
+        assert_eq!(1.0, task.progress());
+    }
+
+    #[test]
+    fn cancel() {
+        let task = Task::spawn("spin", |progress| {
+            while !progress.is_cancelled() {
+                std::thread::yield_now();
+            }
+            Some(())
+        });
+        task.cancel();
+        assert_eq!(TaskStatus::Cancelled, wait(&task));
+    }
+}
@@ -0,0 +1,482 @@
+//! # Importing text
+//!
+//! Plain text and Markdown files are imported as a [`Structure::List`] of
+//! sections, each of which is a list of paragraphs (strings). Paragraphs are
+//! separated by blank lines, and the lines inside of a paragraph are joined
+//! with spaces, since text files are usually wrapped by hand. Fenced code
+//! blocks are kept as they are, as a single paragraph.
+//!
+//! Files are decoded according to their [byte order mark], if they have one.
+//! Otherwise, they are decoded as UTF-8 if they can be, as UTF-16 if they look
+//! like it (every other byte of ASCII text stored as UTF-16 is zero), and as
+//! Latin-1 if all else fails, since every byte is a Latin-1 character. Line
+//! endings are normalized to `\n` either way.
+//!
+//! Unless [`ImportPolicy::split_text`] is turned off, a new section starts at
+//! each heading. Headings are Markdown headings (`# Title`, or a line
+//! underlined with `=` or `-`), or lines like `Chapter 12` standing on their
+//! own. Only the outermost level of headings present starts sections, so that
+//! a document with chapters and subsections is split into chapters; deeper
+//! headings are kept as paragraphs. Each section starts with its heading.
+//!
+//! Importing goes through the same preview as images (see [`crate::import`]):
+//! the list is created right away, but only inserted into `.` once the import
+//! is confirmed.
+//!
+//! [`Structure::List`]: crate::arena::Structure::List
+//! [byte order mark]: https://en.wikipedia.org/wiki/Byte_order_mark
+
+use crate::arena::{Arena, ArenaKey};
+use crate::import::{format_byte_size, read_file, ImportPolicy};
+use crate::task::Progress;
+use std::fmt;
+use std::path::Path;
+
+/// Files with these extensions are imported as text.
+const EXTENSIONS: [&str; 4] = ["txt", "text", "md", "markdown"];
+
+/// Words that start a heading when they begin a line standing on its own,
+/// like `Chapter 12`.
+const CHAPTER_WORDS: [&str; 3] = ["chapter", "part", "book"];
+
+/// Lines longer than this are never taken for chapter headings.
+const MAX_CHAPTER_LENGTH: usize = 60;
+
+/// Returns true if the file at `path` should be imported as text rather than
+/// as an image.
+pub fn is_text_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| {
+            EXTENSIONS.contains(&extension.to_lowercase().as_str())
+        })
+}
+
+/// The encodings text files can be decoded from. See [the module-level
+/// documentation](crate::text_import).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Utf8 => "UTF-8",
+            Self::Utf16Le => "UTF-16LE",
+            Self::Utf16Be => "UTF-16BE",
+            Self::Latin1 => "Latin-1",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Decodes `bytes`, guessing their encoding, and normalizes their line endings
+/// to `\n`.
+pub fn decode(bytes: &[u8]) -> (String, Encoding) {
+    let (text, encoding) = if bytes.starts_with(&[0xef, 0xbb, 0xbf]) {
+        (
+            String::from_utf8_lossy(&bytes[3..]).into_owned(),
+            Encoding::Utf8,
+        )
+    } else if bytes.starts_with(&[0xff, 0xfe]) {
+        (decode_utf16(&bytes[2..], false), Encoding::Utf16Le)
+    } else if bytes.starts_with(&[0xfe, 0xff]) {
+        (decode_utf16(&bytes[2..], true), Encoding::Utf16Be)
+    } else if let Some(big_endian) = looks_like_utf16(bytes) {
+        let encoding = if big_endian {
+            Encoding::Utf16Be
+        } else {
+            Encoding::Utf16Le
+        };
+        (decode_utf16(bytes, big_endian), encoding)
+    } else {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => (text.to_owned(), Encoding::Utf8),
+            Err(_) => (
+                bytes.iter().map(|&byte| byte as char).collect(),
+                Encoding::Latin1,
+            ),
+        }
+    };
+    (text.replace("\r\n", "\n").replace('\r', "\n"), encoding)
+}
+
+fn decode_utf16(bytes: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            if big_endian {
+                u16::from_be_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_le_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Returns whether `bytes` look like UTF-16 without a byte order mark, and if
+/// so, whether it is big endian. Text files hardly ever contain zero bytes
+/// otherwise, but most characters stored as UTF-16 have one.
+fn looks_like_utf16(bytes: &[u8]) -> Option<bool> {
+    let pairs = bytes.len() / 2;
+    if pairs == 0 || bytes.len() % 2 != 0 {
+        return None;
+    }
+    let zeros_at = |parity: usize| {
+        bytes
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|&&byte| byte == 0)
+            .count()
+    };
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    if odd * 2 > pairs && even == 0 {
+        Some(false)
+    } else if even * 2 > pairs && odd == 0 {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// A heading or a paragraph of text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Block {
+    /// A heading of the given level, where 1 is the outermost. Chapter
+    /// headings are level 1.
+    Heading(usize, String),
+    Paragraph(String),
+}
+
+/// Splits `text` (whose line endings are already normalized) into headings
+/// and paragraphs.
+fn blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut fence: Option<Vec<&str>> = None;
+    let finish = |paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            let text = paragraph.join(" ");
+            let lone_line = paragraph.len() == 1;
+            paragraph.clear();
+            blocks.push(if lone_line && is_chapter_heading(&text) {
+                Block::Heading(1, text)
+            } else {
+                Block::Paragraph(text)
+            });
+        }
+    };
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some(code) = &mut fence {
+            if trimmed.starts_with("```") {
+                blocks.push(Block::Paragraph(code.join("\n")));
+                fence = None;
+            } else {
+                code.push(line);
+            }
+        } else if trimmed.starts_with("```") {
+            finish(&mut paragraph, &mut blocks);
+            fence = Some(Vec::new());
+        } else if trimmed.is_empty() {
+            finish(&mut paragraph, &mut blocks);
+        } else if let Some((level, heading)) = atx_heading(trimmed) {
+            finish(&mut paragraph, &mut blocks);
+            blocks.push(Block::Heading(level, heading.to_owned()));
+        } else if let Some(level) = setext_underline(trimmed) {
+            match paragraph.len() {
+                1 => {
+                    let title = paragraph.pop().unwrap();
+                    blocks.push(Block::Heading(level, title.to_owned()));
+                }
+                // A line of dashes on its own is a horizontal rule.
+                0 => {}
+                _ => paragraph.push(trimmed),
+            }
+        } else {
+            paragraph.push(trimmed);
+        }
+    }
+    // An unclosed fence runs to the end of the file.
+    if let Some(code) = fence {
+        blocks.push(Block::Paragraph(code.join("\n")));
+    }
+    finish(&mut paragraph, &mut blocks);
+    blocks
+}
+
+/// Parses a heading like `## Title`, returning its level and its text.
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')) {
+        Some((level, rest.trim().trim_end_matches('#').trim_end()))
+    } else {
+        None
+    }
+}
+
+/// Returns the level of the heading underlined by `line`, if it is a row of
+/// `=` (level 1) or `-` (level 2).
+fn setext_underline(line: &str) -> Option<usize> {
+    if line.len() < 2 {
+        None
+    } else if line.chars().all(|c| c == '=') {
+        Some(1)
+    } else if line.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Returns true if `line` looks like a chapter heading: one of
+/// [`CHAPTER_WORDS`] followed by a number, like `Chapter 12` or `PART IV: The
+/// Return`.
+fn is_chapter_heading(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    let (first, number) = match (words.next(), words.next()) {
+        (Some(first), Some(number)) => (first.to_lowercase(), number),
+        _ => return false,
+    };
+    let number = number.trim_end_matches(|c: char| c.is_ascii_punctuation());
+    let is_number = !number.is_empty()
+        && (number.chars().all(|c| c.is_ascii_digit())
+            || number.chars().all(|c| "IVXLCDMivxlcdm".contains(c)));
+    line.len() <= MAX_CHAPTER_LENGTH && CHAPTER_WORDS.contains(&first.as_str()) && is_number
+}
+
+/// A run of paragraphs, starting with a heading if there is one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub heading: Option<String>,
+    pub paragraphs: Vec<String>,
+}
+
+/// Groups `blocks` into sections. If `split` is true, a section starts at
+/// each heading of the outermost level present; otherwise, everything ends up
+/// in a single section.
+fn sections(blocks: Vec<Block>, split: bool) -> Vec<Section> {
+    let outermost = blocks
+        .iter()
+        .filter_map(|block| match block {
+            Block::Heading(level, _) => Some(*level),
+            Block::Paragraph(_) => None,
+        })
+        .min()
+        .filter(|_| split);
+    let mut sections = vec![Section {
+        heading: None,
+        paragraphs: Vec::new(),
+    }];
+    for block in blocks {
+        match block {
+            Block::Heading(level, heading) if Some(level) == outermost => {
+                sections.push(Section {
+                    heading: Some(heading),
+                    paragraphs: Vec::new(),
+                });
+            }
+            Block::Heading(_, text) | Block::Paragraph(text) => {
+                sections.last_mut().unwrap().paragraphs.push(text);
+            }
+        }
+    }
+    // Text before the first heading only gets a section if there is any.
+    if sections.len() > 1 && sections[0].paragraphs.is_empty() {
+        sections.remove(0);
+    }
+    sections
+}
+
+/// A text file that has been read and split up, but not yet inserted into an
+/// arena.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedText {
+    pub sections: Vec<Section>,
+    pub encoding: Encoding,
+    /// The size of the file the text was read from, in bytes.
+    pub byte_size: u64,
+}
+
+impl ImportedText {
+    /// Splits `bytes`, read from a text file, into sections of paragraphs.
+    pub fn parse(bytes: &[u8], split: bool) -> Self {
+        let (text, encoding) = decode(bytes);
+        Self {
+            sections: sections(blocks(&text), split),
+            encoding,
+            byte_size: bytes.len() as u64,
+        }
+    }
+
+    /// Reads the text file at `path` and splits it up according to `policy`.
+    /// Returns [`None`] if the file can't be read or `progress` is cancelled.
+    ///
+    /// Meant to be run as a [`Task`](crate::task::Task), like
+    /// [`ImportedImage::load`](crate::import::ImportedImage::load).
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        policy: ImportPolicy,
+        progress: &Progress,
+    ) -> Option<Self> {
+        let bytes = read_file(path, 0.5, progress)?;
+        let imported = Self::parse(&bytes, policy.split_text);
+        progress.report(1.0);
+        Some(imported)
+    }
+
+    /// Creates a list of the sections, each a list of its heading (if it has
+    /// one) followed by its paragraphs, and returns it.
+    pub fn insert(&self, arena: &mut Arena) -> ArenaKey {
+        let sections: Vec<ArenaKey> = self
+            .sections
+            .iter()
+            .map(|section| {
+                let paragraphs: Vec<ArenaKey> = section
+                    .heading
+                    .iter()
+                    .chain(&section.paragraphs)
+                    .map(|paragraph| arena.string(paragraph))
+                    .collect();
+                arena.list(paragraphs)
+            })
+            .collect();
+        arena.list(sections)
+    }
+
+    /// A short description of the text to display alongside its preview, like
+    /// `"12 sections, 340 paragraphs, UTF-8, 24.1 KB"`.
+    pub fn describe(&self) -> String {
+        let paragraphs: usize = self
+            .sections
+            .iter()
+            .map(|section| section.paragraphs.len())
+            .sum();
+        format!(
+            "{} sections, {} paragraphs, {}, {}",
+            self.sections.len(),
+            paragraphs,
+            self.encoding,
+            format_byte_size(self.byte_size)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::Structure;
+
+    #[test]
+    fn encodings() {
+        let utf16 = |big_endian: bool| {
+            "é\r\nb"
+                .encode_utf16()
+                .flat_map(|unit| {
+                    if big_endian {
+                        unit.to_be_bytes()
+                    } else {
+                        unit.to_le_bytes()
+                    }
+                })
+                .collect::<Vec<u8>>()
+        };
+        let expected = "é\nb".to_owned();
+        assert_eq!(
+            (expected.clone(), Encoding::Utf8),
+            decode("é\r\nb".as_bytes())
+        );
+        assert_eq!(
+            (expected.clone(), Encoding::Utf8),
+            decode(&[&[0xef, 0xbb, 0xbf], "é\rb".as_bytes()].concat())
+        );
+        assert_eq!(
+            (expected.clone(), Encoding::Utf16Le),
+            decode(&[vec![0xff, 0xfe], utf16(false)].concat())
+        );
+        assert_eq!((expected.clone(), Encoding::Utf16Le), decode(&utf16(false)));
+        assert_eq!((expected.clone(), Encoding::Utf16Be), decode(&utf16(true)));
+        assert_eq!(
+            (expected, Encoding::Latin1),
+            decode(&[0xe9, b'\r', b'\n', b'b'])
+        );
+    }
+
+    #[test]
+    fn chunking() {
+        let text = "Preface text.\n\n\
+                    # One\n\n\
+                    First\nparagraph.\n\n\
+                    ## Detail\n\n\
+                    ```\n# not a heading\n\n  indented\n```\n\n\
+                    Two\n===\n\n\
+                    Last.\n";
+        let imported = ImportedText::parse(text.as_bytes(), true);
+        let section = |heading: &str, paragraphs: &[&str]| Section {
+            heading: Some(heading.into()),
+            paragraphs: paragraphs.iter().map(|&p| p.into()).collect(),
+        };
+        assert_eq!(
+            vec![
+                Section {
+                    heading: None,
+                    paragraphs: vec!["Preface text.".into()],
+                },
+                section(
+                    "One",
+                    &[
+                        "First paragraph.",
+                        "Detail",
+                        "# not a heading\n\n  indented"
+                    ]
+                ),
+                section("Two", &["Last."]),
+            ],
+            imported.sections
+        );
+        assert_eq!(
+            1,
+            ImportedText::parse(text.as_bytes(), false).sections.len()
+        );
+
+        let novel = "CHAPTER I\n\nIt was a dark night.\n\nChapter 2\n\nIt got darker.";
+        let imported = ImportedText::parse(novel.as_bytes(), true);
+        assert_eq!(
+            vec![
+                section("CHAPTER I", &["It was a dark night."]),
+                section("Chapter 2", &["It got darker."]),
+            ],
+            imported.sections
+        );
+
+        let mut arena = Arena::new();
+        let list = imported.insert(&mut arena);
+        let expected = vec![arena.string("Chapter 2"), arena.string("It got darker.")];
+        match &arena.slot_map[list].structure {
+            Structure::List(sections) => {
+                assert_eq!(2, sections.len());
+                match &arena.slot_map[sections[1]].structure {
+                    Structure::List(paragraphs) => assert_eq!(expected, paragraphs.to_vec()),
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn text_files() {
+        assert!(is_text_file("notes.MD"));
+        assert!(is_text_file("/a/b.txt"));
+        assert!(!is_text_file("photo.png"));
+        assert!(!is_text_file("README"));
+    }
+}
@@ -0,0 +1,186 @@
+//! # In-app tutorial
+//!
+//! New users are greeted by a tutorial that is built out of ordinary values,
+//! so that learning how to use Kakoi is done by using Kakoi. Each step of the
+//! tutorial is a set of strings explaining a few key bindings or concepts.
+//! Each step also contains the set for the following step, so the user moves
+//! through the tutorial by clicking on the innermost set.
+//!
+//! The first step is bound to the [`TUTORIAL_REGISTER`], and the tutorial is
+//! displayed when Kakoi starts without a saved arena. Kakoi can't open an
+//! arena written out as JSON at startup yet (see [`crate::json`]), so this is
+//! currently every time Kakoi starts. The tutorial can be
+//! rebuilt from scratch with
+//! [`CompleteAction::RestartTutorial`](crate::input_manager::CompleteAction::RestartTutorial),
+//! in case the user modified it while following along.
+//!
+//! Keys are named in the tutorial as they are bound. When they are bound by
+//! their position (see [`BindingMode::Scancode`]), those names are of the keys
+//! in the same place on a US QWERTY keyboard, which may not be what the
+//! user's keyboard says. The tutorial is built before any keys have been
+//! pressed, so it can't name them the way
+//! [`InputManager::key_label`](crate::input_manager::InputManager::key_label)
+//! does; it says so instead.
+
+use crate::arena::{Arena, ArenaKey};
+use crate::input_manager::BindingMode;
+
+/// The register the first step of the tutorial is bound to. Like the
+/// [`INBOX_REGISTER`](crate::arena::INBOX_REGISTER), it isn't named after a
+/// key, so building the tutorial never rebinds a register of the user's.
+pub const TUTORIAL_REGISTER: &str = "tutorial";
+
+/// Added to the first step of the tutorial when keys are bound by their
+/// position.
+const SCANCODE_NOTE: &str =
+    "Keys are named after the key in the same place on a US QWERTY keyboard, whatever yours says on it";
+
+/// The text of each step of the tutorial, in order.
+const STEPS: &[&[&str]] = &[
+    &[
+        "Welcome to Kakoi!",
+        "Everything here is a value: a string, an image, a set, or a map",
+        "Sets are drawn as circles around the values they contain",
+        "Click on the empty circle to continue",
+    ],
+    &[
+        "Moving around",
+        "Click on a value to look at it",
+        "tab selects the next value inside, and d looks at the selected value",
+        "p goes back to what you were looking at before",
+        "v shows every register and the value bound to it",
+        "s followed by a register's key looks at that register",
+        "tab instead of a key types a register's name out, tab again completes it, and up goes back through past entries",
+        "The value you are looking at is bound to the . register",
+    ],
+    &[
+        "Changing sets",
+        "e replaces what you are looking at with an empty set",
+        "t starts typing a string, which appears as you type; shift+enter inserts it",
+        "c starts typing a string that goes into the inbox, wherever you are",
+        "= starts typing a number instead; control+a adds, subtracts, multiplies or divides the numbers bound to two registers",
+        "with kakoi --background running, kakoi --summon capture (bound to a desktop shortcut) jumps straight to c",
+        "i followed by a register inserts that register's value",
+        "r followed by a register removes that register's value",
+        "f3 followed by a register inserts that register's value, or removes it if it is already there",
+        "control+o makes an empty list instead; control+i adds a register's value to its end, and control+x removes its last element",
+        "Type a position first, like 2, to edit the middle of a list: control+u inserts a register's value there, control+shift+x removes, control+w swaps with the next, and control+m moves it",
+        "b followed by a register binds it to what you are looking at",
+        "Registers starting with a comma, like ,a, belong to what you are looking at",
+        "Drop an image file onto the window to preview it; enter inserts it",
+        "Other files, like PDFs, are kept as they are and drawn as their names",
+        "Huge images are shrunk as they are imported; see --max-image-size and --memory-budget",
+        "With --link-images, images are only read from their files while they are on screen",
+        "x cancels imports that are still loading",
+        "u opens the selected link in your web browser",
+        "control+l copies a kakoi:// link to the selected value, which u follows back to it",
+        "w followed by a size like 4096x4096 saves what you are looking at as a PNG",
+        "h locks the selected value so nothing inside of it can change; f2 locks everything",
+    ],
+    &[
+        "Views",
+        "o followed by a register opens a panel showing that register",
+        "escape closes the panel",
+        "l switches between circles, circles sized by their contents, rectangles, and circles with one enlarged",
+        "scrolling turns a set with one enlarged circle, enlarging the next one",
+        "control+k picks how the selected value is always arranged; arrows and enter or a number key choose",
+        "] turns the selected set clockwise and [ turns it back; three presses of ] put its first member at the top",
+        "g suggests groups of similar values; enter accepts them, escape discards them",
+        "f flattens the sets inside of a set into it, and z undoes that or an accepted grouping",
+        "z also puts back values inserted into or removed from a set by mistake",
+        "control+y redoes what z undid; z and control+y go back and forth through the last 100 changes",
+        "control+c names this point, like control+c before cleanup; control+j goes back to it, control+shift+c lists them",
+        "control+r followed by a pattern and a replacement finds and replaces text in the strings here",
+        "control+d finds strings that differ by a typo and identical images; enter merges them",
+        "y marks the selected value for moving; enter moves it into the set you are looking at",
+        "dragging a value onto a set moves it there, or copies it if you hold control",
+        "sets of mostly text are drawn as rectangles; k changes how the selected value is drawn",
+        "q finds values, like q contains(\"idea\") and type(set); j reruns a selected query",
+        "control+f followed by a register and a query binds that register to the matches, like control+f a starts_with(\"todo\")",
+        "control+q highlights matches where they are; control+n and control+p step through them",
+        "m shows only a random sample of a large set, and n shows a different sample",
+        "a draws lines between values that appear in more than one place",
+        "control+h rings everything in blue through red by how much it costs to draw",
+        "control+shift+1 pins what you are looking at to slot 1, and control+1 shows it again",
+        "control+s stars the selected value, and control+shift+s shows everything starred",
+        "control+t tags the selected value, control+shift+t untags it, and control+g lists a tag; control+f a tag(\"todo\") binds a set of everything tagged todo to a, and author(\"name\") finds what someone made while running with --author",
+        "f4 inspects the selected image; arrows pan, = and - zoom, and 1 shows actual size",
+        "control+e writes the selected value out to a Markdown or org-mode file, like control+e notes.md, or to a web page, like control+e notes.html; control+e arena.json writes out everything as JSON",
+        "control+shift+e writes where everything on screen is drawn out to a JSON file",
+        "control+shift+i a arena.json merges an arena written out with control+e into this one, binding its registers to a map in register a",
+        "control+shift+d a arena.json binds a map of what was added, removed and changed since arena.json was written out to register a",
+        "control+v shows the earlier versions of the selected string or set, and control+shift+v restores the selected one",
+        "control+b saves power by drawing less often and with less detail",
+        "control+= and control+- change how large text must be before it is drawn",
+    ],
+    &[
+        "That's everything!",
+        "f1 restarts this tutorial from the beginning",
+        "f12 checks for and repairs problems in the arena",
+        "f5 removes values that nothing leads to any more and releases memory that has built up over a long session",
+        "with --prune-orphans, values are removed as soon as they are removed from everything containing them",
+        "with --track-usage, f6 puts recently or often visited values first, and f7 shows recent ones",
+        "control+f7 shows the sets you changed in the last 15 minutes (see --changed-window)",
+        "f10 wanders from value to value every few seconds, until pressed again",
+        "f11 switches fullscreen on and off, f9 minimizes, and f8 followed by a size like 1280x720 resizes",
+    ],
+];
+
+/// Builds a fresh copy of the tutorial inside of `arena`, binds it to the
+/// [`TUTORIAL_REGISTER`], and returns the key of its first step. Keys are
+/// bound according to `binding_mode`.
+pub fn build(arena: &mut Arena, binding_mode: BindingMode) -> ArenaKey {
+    // Steps are built from last to first, since each step contains the next.
+    // The tutorial register is used as scratch space while building each step.
+    let mut next = None;
+    for (index, step) in STEPS.iter().enumerate().rev() {
+        arena.bind_register_to_empty_set(TUTORIAL_REGISTER);
+        for &line in step.iter() {
+            arena.set_insert_string(TUTORIAL_REGISTER, line).unwrap();
+        }
+        if index == 0 && binding_mode == BindingMode::Scancode {
+            arena
+                .set_insert_string(TUTORIAL_REGISTER, SCANCODE_NOTE)
+                .unwrap();
+        }
+        if let Some(next) = next {
+            arena.set_insert_value(TUTORIAL_REGISTER, next).unwrap();
+        }
+        next = arena.register(TUTORIAL_REGISTER);
+    }
+    next.unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::Structure;
+
+    #[test]
+    fn steps_are_linked() {
+        let mut arena = Arena::new();
+        let first = build(&mut arena, BindingMode::Scancode);
+        assert_eq!(Some(first), arena.register(TUTORIAL_REGISTER));
+        assert_eq!(None, arena.register("h"));
+        assert!(arena.lookup_string(SCANCODE_NOTE).is_some());
+
+        // Follow the chain of nested sets, making sure there is one per step.
+        let mut step = Some(first);
+        let mut steps = 0;
+        while let Some(key) = step {
+            steps += 1;
+            step = match &arena.slot_map[key].structure {
+                Structure::Set(set) => {
+                    set.iter()
+                        .copied()
+                        .find(|&k| match &arena.slot_map[k].structure {
+                            Structure::Set(_) => true,
+                            _ => false,
+                        })
+                }
+                _ => panic!(),
+            };
+        }
+        assert_eq!(STEPS.len(), steps);
+    }
+}
@@ -1,49 +1,403 @@
+use crate::arena::{ChangeWindow, Pruning};
+use crate::background::{self, Summons};
+use crate::crash;
+use crate::import::ImportPolicy;
+use crate::input_manager::BindingMode;
+use crate::profiling::Profiler;
+use crate::render::text::Truncation;
+use crate::replay::Recorder;
 use crate::state::State;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use uuid::Uuid;
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
+    window::{Fullscreen, Icon, Window},
 };
 
-pub fn create_window() {
+/// The width and height of the window's icon, in pixels.
+const ICON_SIZE: u32 = 64;
+
+/// The smallest and largest [`Options::ui_scale`]s, which
+/// [`CompleteAction::ScaleUi`] can't go beyond either.
+///
+/// [`CompleteAction::ScaleUi`]: crate::input_manager::CompleteAction::ScaleUi
+pub const UI_SCALE_RANGE: (f32, f32) = (0.25, 8.0);
+
+/// Parses a UI scale (see [`Options::ui_scale`]), clamping it to
+/// [`UI_SCALE_RANGE`]. Returns [`None`] unless `text` is a positive number.
+pub fn parse_ui_scale(text: &str) -> Option<f32> {
+    let (min, max) = UI_SCALE_RANGE;
+    match text.trim().parse::<f32>() {
+        Ok(factor) if factor.is_finite() && factor > 0.0 => Some(factor.max(min).min(max)),
+        _ => None,
+    }
+}
+
+/// Settings that change how Kakoi behaves, usually chosen on the command line.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Check the arena for inconsistencies after every action, logging and
+    /// repairing any that are found. See [`Arena::validate`].
+    ///
+    /// [`Arena::validate`]: crate::arena::Arena::validate
+    pub validate: bool,
+    /// How key presses are matched against key bindings.
+    pub binding_mode: BindingMode,
+    /// Start in read-only mode, in which nothing can be modified. See
+    /// [`CompleteAction::ToggleReadOnly`].
+    ///
+    /// [`CompleteAction::ToggleReadOnly`]: crate::input_manager::CompleteAction::ToggleReadOnly
+    pub read_only: bool,
+    /// Draw rings flat, without the shadows that make nesting easier to
+    /// perceive. See [`CircleRenderer::set_depth_cues`].
+    ///
+    /// [`CircleRenderer::set_depth_cues`]: crate::render::circle::CircleRenderer::set_depth_cues
+    pub flat_rings: bool,
+    /// Draw rings thinner the more deeply they are nested. See
+    /// [`RingThickness::TAPERED`].
+    ///
+    /// [`RingThickness::TAPERED`]: crate::render::circle::RingThickness::TAPERED
+    pub tapered_rings: bool,
+    /// Fade rings and strings nested far below the focused value into the
+    /// background. See [`CircleRenderer::set_depth_fading`].
+    ///
+    /// [`CircleRenderer::set_depth_fading`]: crate::render::circle::CircleRenderer::set_depth_fading
+    pub depth_of_field: bool,
+    /// Start in power saving mode, even when not running on battery. See
+    /// [`crate::power`].
+    pub power_saving: bool,
+    /// How many times larger than usual text and other details must be drawn
+    /// before they are considered readable, on top of the scale factor of the
+    /// display. Defaults to 1. See [`CompleteAction::ScaleUi`].
+    ///
+    /// [`CompleteAction::ScaleUi`]: crate::input_manager::CompleteAction::ScaleUi
+    pub ui_scale: Option<f32>,
+    /// How many words of a long string are drawn when it isn't the focus of
+    /// its layer, or [`None`] to always draw strings whole. See
+    /// [`TextRenderer::set_preview_words`].
+    ///
+    /// [`TextRenderer::set_preview_words`]: crate::render::text::TextRenderer::set_preview_words
+    pub preview_words: Option<usize>,
+    /// Shorten strings too small to read by removing graphemes from their
+    /// middle rather than their end, keeping both ends. See
+    /// [`TextRenderer::set_truncation`].
+    ///
+    /// [`TextRenderer::set_truncation`]: crate::render::text::TextRenderer::set_truncation
+    pub truncate_middle: bool,
+    /// The inner size of the window when it opens, in pixels. Defaults to
+    /// 1920x1080.
+    pub window_size: Option<(u32, u32)>,
+    /// Open the window fullscreen.
+    pub fullscreen: bool,
+    /// The value to display first, instead of the tutorial. See
+    /// [`crate::link`].
+    pub focus: Option<Uuid>,
+    /// Track how often and how recently values are visited. See
+    /// [`Arena::set_usage_tracking`].
+    ///
+    /// [`Arena::set_usage_tracking`]: crate::arena::Arena::set_usage_tracking
+    pub track_usage: bool,
+    /// How long changed values stay in the set bound to the
+    /// [`CHANGED_REGISTER`]. See [`Arena::record_change`].
+    ///
+    /// [`CHANGED_REGISTER`]: crate::arena::CHANGED_REGISTER
+    /// [`Arena::record_change`]: crate::arena::Arena::record_change
+    pub change_window: ChangeWindow,
+    /// How large imported images may be, and how much memory Kakoi may take
+    /// up before importing more comes with a warning.
+    pub import_policy: ImportPolicy,
+    /// A file to import once the window opens, as if it were dropped onto
+    /// it. See [`crate::import`].
+    pub import: Option<PathBuf>,
+    /// A file to write the layout out to as JSON once the first frame has
+    /// been drawn, closing the window afterwards. See [`crate::geometry`].
+    pub dump_layout: Option<PathBuf>,
+    /// Start with the window hidden, hide it instead of exiting when it is
+    /// closed, and listen for summons to show it again. See
+    /// [`crate::background`].
+    pub background: bool,
+    /// A file to write every key pressed and character typed out to as a
+    /// test replaying them, once the window closes. See [`crate::replay`].
+    pub record_session: Option<PathBuf>,
+    /// Remove values that can't be reached any more every so often, rather
+    /// than only when compacting, releasing the textures of images that are
+    /// no longer drawn too. See [`Arena::collect_garbage`].
+    ///
+    /// [`Arena::collect_garbage`]: crate::arena::Arena::collect_garbage
+    pub collect_garbage: bool,
+    /// Remove values as soon as they have been removed from everything
+    /// containing them, if set. See [`Arena::prune_orphans_keeping`].
+    ///
+    /// [`Arena::prune_orphans_keeping`]: crate::arena::Arena::prune_orphans_keeping
+    pub prune_orphans: Option<Pruning>,
+    /// Who to record as the author of every value created, along with when
+    /// it was created, if set. See [`Arena::record_metadata`].
+    ///
+    /// [`Arena::record_metadata`]: crate::arena::Arena::record_metadata
+    pub author: Option<String>,
+}
+
+impl Options {
+    /// Where strings too small to read are shortened. See
+    /// [`Options::truncate_middle`].
+    pub fn truncation(&self) -> Truncation {
+        if self.truncate_middle {
+            Truncation::Middle
+        } else {
+            Truncation::End
+        }
+    }
+}
+
+/// A change to the window asked for by the user. The window belongs to the
+/// event loop, so these are handed to it by the
+/// [`Renderer`](crate::render::renderer::Renderer) after handling input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowRequest {
+    /// Switches between borderless fullscreen and an ordinary window.
+    ToggleFullscreen,
+    Minimize,
+    /// Leaves fullscreen and resizes the window to the given width and
+    /// height, in pixels.
+    Resize(u32, u32),
+}
+
+fn apply_window_request(window: &Window, request: WindowRequest) {
+    match request {
+        WindowRequest::ToggleFullscreen => {
+            let fullscreen = match window.fullscreen() {
+                Some(_) => None,
+                None => Some(Fullscreen::Borderless(window.current_monitor())),
+            };
+            window.set_fullscreen(fullscreen);
+        }
+        WindowRequest::Minimize => window.set_minimized(true),
+        WindowRequest::Resize(width, height) => {
+            window.set_fullscreen(None);
+            window.set_inner_size(winit::dpi::PhysicalSize::new(width, height));
+        }
+    }
+}
+
+/// Writes out the session recorded for `--record-session`, if one is being
+/// recorded. See [`crate::replay`].
+fn save_recording(recording: &mut Option<(PathBuf, Recorder)>) {
+    if let Some((path, recorder)) = recording.take() {
+        match recorder.save(&path) {
+            Ok(()) => println!("wrote the recorded session to {}", path.display()),
+            Err(e) => eprintln!(
+                "couldn't write the recorded session to {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// Draws Kakoi's icon: a ring enclosing three smaller rings, like a set
+/// containing three values. It is drawn rather than loaded from an image so
+/// that there is nothing to ship alongside the executable. Not every platform
+/// shows it; macOS takes the icon from the application bundle instead.
+fn icon() -> Option<Icon> {
+    let size = ICON_SIZE as f32;
+    // The center and radius of each ring, as fractions of the icon's size.
+    let rings = [
+        (0.5, 0.5, 0.44),
+        (0.5, 0.33, 0.13),
+        (0.35, 0.6, 0.13),
+        (0.65, 0.6, 0.13),
+    ];
+    let thickness = 0.05;
+    let (background, foreground) = (64.0, 230.0);
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let (px, py) = ((x as f32 + 0.5) / size, (y as f32 + 0.5) / size);
+            let distance = |(cx, cy): (f32, f32)| ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+            // How much of this pixel a shape covers, given how far the
+            // pixel's center is outside of it, smoothing edges over a pixel.
+            let coverage = |outside: f32| (0.5 - outside * size).max(0.0).min(1.0);
+            let disc = coverage(distance((0.5, 0.5)) - 0.5 + 1.0 / size);
+            let ring = rings
+                .iter()
+                .map(|&(cx, cy, radius)| {
+                    coverage((distance((cx, cy)) - radius).abs() - thickness / 2.0)
+                })
+                .fold(0.0, f32::max);
+            let shade = (background + (foreground - background) * ring) as u8;
+            rgba.extend_from_slice(&[shade, shade, shade, (disc.max(ring) * 255.0) as u8]);
+        }
+    }
+    Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE).ok()
+}
+
+pub fn create_window(profiler: Profiler, options: Options) {
     env_logger::init();
-    let event_loop = EventLoop::new();
+    crash::install(&options);
+    let event_loop = EventLoop::with_user_event();
+    let background = options.background;
+    if background {
+        let proxy = event_loop.create_proxy();
+        match background::listen(move |summons| proxy.send_event(summons).is_ok()) {
+            Ok(()) => println!("kakoi is running in the background; kakoi --summon show shows it"),
+            Err(e) => {
+                eprintln!(
+                    "can't listen for summons at {}, is kakoi already running in the background? ({})",
+                    background::ADDRESS,
+                    e
+                );
+                return;
+            }
+        }
+    }
 
     let min_size: winit::dpi::PhysicalSize<u32> = (200, 200).into();
-    let start_size: winit::dpi::PhysicalSize<u32> = (1920, 1080).into();
+    let start_size: winit::dpi::PhysicalSize<u32> =
+        options.window_size.unwrap_or((1920, 1080)).into();
     let window = winit::window::WindowBuilder::new()
         .with_title("kakoi")
+        .with_window_icon(icon())
         .with_min_inner_size(min_size)
         .with_inner_size(start_size)
         .with_resizable(true)
+        .with_visible(!background)
         .build(&event_loop)
         .unwrap();
+    if options.fullscreen {
+        apply_window_request(&window, WindowRequest::ToggleFullscreen);
+    }
+
+    let mut dump_layout = options.dump_layout.clone();
+    let mut recording = options
+        .record_session
+        .clone()
+        .map(|path| (path, Recorder::new()));
+    let mut state = futures::executor::block_on(State::new(&window, options));
 
-    let mut state = futures::executor::block_on(State::new(&window));
+    // Moved into the event loop so that it lives until the window is closed.
+    let mut profiler = Some(profiler);
+    // The window's title as of the last time it was set. See
+    // `Renderer::title`.
+    let mut title = String::new();
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
-        match event {
-            Event::WindowEvent { ref event, .. } => match event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                WindowEvent::Resized(physical_size) => {
-                    state.resize(*physical_size);
+        // A panic would otherwise take everything in the arena with it. See
+        // `crate::crash`.
+        let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+            *control_flow = match state.next_wakeup() {
+                Some(deadline) => ControlFlow::WaitUntil(deadline),
+                None => ControlFlow::Wait,
+            };
+            match event {
+                Event::WindowEvent { ref event, .. } => match event {
+                    // Hidden windows keep everything in the arena until they
+                    // are summoned again. See `crate::background`.
+                    WindowEvent::CloseRequested if background => window.set_visible(false),
+                    WindowEvent::CloseRequested => {
+                        // Dropping the profiler writes out its trace, if it has one.
+                        save_recording(&mut recording);
+                        profiler.take();
+                        *control_flow = ControlFlow::Exit
+                    }
+                    WindowEvent::Resized(physical_size) => {
+                        state.resize(*physical_size);
+                    }
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
+                    } => {
+                        state.set_scale_factor(*scale_factor);
+                        state.resize(**new_inner_size);
+                    }
+                    // get keyboard input, etc. here
+                    _ => {
+                        if let Some((_, recorder)) = &mut recording {
+                            match event {
+                                WindowEvent::KeyboardInput { input, .. } => recorder.key(input),
+                                WindowEvent::ReceivedCharacter(c) => recorder.character(*c),
+                                _ => {}
+                            }
+                        }
+                        if state.input(&event) {
+                            window.request_redraw();
+                        }
+                        if let Some(request) = state.take_window_request() {
+                            apply_window_request(&window, request);
+                        }
+                    }
+                },
+                Event::UserEvent(Summons::Quit) => {
+                    save_recording(&mut recording);
+                    profiler.take();
+                    *control_flow = ControlFlow::Exit
                 }
-                // get keyboard input, etc. here
-                _ => {
-                    if state.input(&event) {
-                        window.request_redraw();
+                Event::UserEvent(summons) => {
+                    window.set_visible(true);
+                    window.set_minimized(false);
+                    if summons == Summons::Capture {
+                        state.begin_capture();
                     }
+                    window.request_redraw();
                 }
-            },
-            Event::RedrawRequested(_) => {
-                match state.render() {
+                Event::NewEvents(StartCause::ResumeTimeReached { .. }) => window.request_redraw(),
+                Event::RedrawRequested(_) => match state.render() {
+                    Ok(_) if dump_layout.is_some() => {
+                        let path = dump_layout.take().unwrap();
+                        match state.export_layout(&path) {
+                            Ok(count) => println!("exported {} nodes to {}", count, path.display()),
+                            Err(e) => eprintln!("couldn't export to {}: {}", path.display(), e),
+                        }
+                        save_recording(&mut recording);
+                        profiler.take();
+                        *control_flow = ControlFlow::Exit
+                    }
+                    // While the frame rate is capped, the next frame is drawn once
+                    // `next_wakeup` is reached instead.
+                    Ok(_) if state.is_animating() && !state.frame_rate_capped() => {
+                        window.request_redraw()
+                    }
                     Ok(_) => {}
-                    Err(wgpu::SwapChainError::Lost) => state.recreate_swap_chain(),
+                    Err(wgpu::SwapChainError::Lost) | Err(wgpu::SwapChainError::Outdated) => {
+                        state.recover();
+                        window.request_redraw();
+                    }
+                    // The frame took too long to become available. Skip it and
+                    // try again.
+                    Err(wgpu::SwapChainError::Timeout) => {
+                        log::warn!("timed out waiting for a frame");
+                        window.request_redraw();
+                    }
                     Err(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    Err(e) => eprintln!("{:?}", e),
+                },
+                // Every event that could have changed the title has been handled
+                // by now.
+                Event::MainEventsCleared => {
+                    let new_title = state.title();
+                    if new_title != title {
+                        window.set_title(&new_title);
+                        title = new_title;
+                    }
+                }
+                _ => {}
+            }
+        }));
+        if handled.is_err() {
+            if let Some(dir) = crash::bundle() {
+                match panic::catch_unwind(AssertUnwindSafe(|| state.rescue(&dir))) {
+                    Ok(Ok(())) => eprintln!(
+                        "wrote the arena to {}, and an outline of what was open to {}",
+                        dir.join(crash::ARENA).display(),
+                        dir.join(crash::OUTLINE).display()
+                    ),
+                    Ok(Err(e)) => eprintln!("couldn't write the arena and an outline: {}", e),
+                    Err(_) => {}
                 }
             }
-            _ => {}
+            save_recording(&mut recording);
+            profiler.take();
+            *control_flow = ControlFlow::Exit
         }
     })
 }